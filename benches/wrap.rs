@@ -0,0 +1,108 @@
+//! Per-frame cost of wrapping the ask dialog's input
+//!
+//! Compares the range-based layout (wrapped once per frame into a reused
+//! buffer) with the previous approach, which wrapped into owned strings three
+//! times per frame (draw, cursor position, scroll update).
+//!
+//! Run with `cargo bench --bench wrap`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+// The module's unit tests are compiled out of context here
+#[allow(dead_code, unused_imports)]
+#[path = "../src/tui/wrap.rs"]
+mod wrap;
+
+const WIDTH: usize = 76;
+const PREFIX: usize = 2;
+const TAB_WIDTH: usize = 4;
+
+/// About 5 KB of prompt text with paragraphs, long lines and blank lines
+fn input() -> String {
+    let paragraph = "Refactor the session handling so that reconnects keep the queued prompts, \
+                     and explain why the retry loop in the event stream can spin when the server \
+                     restarts. Include the relevant file paths.";
+    let mut text = String::new();
+    while text.len() < 5 * 1024 {
+        text.push_str(paragraph);
+        text.push_str("\n- check @this\n- and @diff\n\n");
+    }
+    text
+}
+
+/// The previous layout: a visual line owns a copy of its text
+struct OwnedLine {
+    text: String,
+    start_pos: usize,
+}
+
+fn wrap_owned(text: &str, width: usize, prefix_width: usize) -> Vec<OwnedLine> {
+    let mut wrapped = Vec::new();
+    let mut byte_offset = 0;
+    let effective_width = width.saturating_sub(prefix_width);
+    for line in text.split('\n') {
+        let mut remaining = line;
+        let mut line_byte_offset = byte_offset;
+        if remaining.is_empty() {
+            wrapped.push(OwnedLine {
+                text: String::new(),
+                start_pos: byte_offset,
+            });
+        }
+        while !remaining.is_empty() {
+            let break_at = if remaining.chars().count() <= effective_width {
+                remaining.len()
+            } else {
+                remaining
+                    .char_indices()
+                    .nth(effective_width)
+                    .map(|(i, _)| i)
+                    .unwrap_or(remaining.len())
+            };
+            let (chunk, rest) = remaining.split_at(break_at);
+            wrapped.push(OwnedLine {
+                text: chunk.to_string(),
+                start_pos: line_byte_offset,
+            });
+            line_byte_offset += chunk.len();
+            remaining = rest;
+        }
+        byte_offset += line.len() + 1;
+    }
+    wrapped
+}
+
+fn cursor_row_owned(text: &str, cursor_pos: usize) -> usize {
+    wrap_owned(text, WIDTH, PREFIX)
+        .iter()
+        .position(|l| cursor_pos >= l.start_pos && cursor_pos <= l.start_pos + l.text.len())
+        .unwrap_or(0)
+}
+
+fn bench_frame(c: &mut Criterion) {
+    let text = input();
+    let cursor = text.len() / 2;
+
+    let mut group = c.benchmark_group("ask_frame_5kb");
+    group.bench_function("owned_strings_3x", |b| {
+        b.iter(|| {
+            let lines = wrap_owned(black_box(&text), WIDTH, PREFIX);
+            let row = cursor_row_owned(&text, cursor);
+            let scroll_row = cursor_row_owned(&text, cursor);
+            black_box((lines.len(), row, scroll_row))
+        })
+    });
+
+    let mut layout = Vec::new();
+    group.bench_function("ranges_once", |b| {
+        b.iter(|| {
+            wrap::wrap_into(black_box(&text), WIDTH, PREFIX, TAB_WIDTH, &mut layout);
+            let (row, col) = wrap::cursor_to_visual_pos(&layout, &text, cursor, TAB_WIDTH);
+            black_box((layout.len(), row, col))
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_frame);
+criterion_main!(benches);
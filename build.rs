@@ -0,0 +1,76 @@
+//! Build details for `--version --json`: the git commit, the build date and
+//! the target triple, passed to the crate as `OPENCODE_HELIX_*` variables
+//!
+//! Outside a git checkout (a release tarball) there is no commit. The date
+//! honours `SOURCE_DATE_EPOCH`, so reproducible builds stay reproducible.
+
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    let dir = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string());
+
+    if let Some(hash) = git(&dir, &["rev-parse", "--short=12", "HEAD"]) {
+        println!("cargo:rustc-env=OPENCODE_HELIX_GIT_HASH={}", hash);
+    }
+    // A new commit moves HEAD or the branch it points to
+    if let Some(git_dir) = git(&dir, &["rev-parse", "--absolute-git-dir"]) {
+        let git_dir = Path::new(&git_dir);
+        let mut watched = vec![git_dir.join("HEAD"), git_dir.join("packed-refs")];
+        if let Some(branch) = git(&dir, &["symbolic-ref", "-q", "HEAD"]) {
+            watched.push(git_dir.join(branch));
+        }
+        // A missing path would rerun the script on every build
+        for path in watched.iter().filter(|path| path.exists()) {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    let seconds = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.trim().parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs())
+        });
+    println!(
+        "cargo:rustc-env=OPENCODE_HELIX_BUILD_DATE={}",
+        date(seconds)
+    );
+
+    let target = std::env::var("TARGET").unwrap_or_else(|_| "unknown".to_string());
+    println!("cargo:rustc-env=OPENCODE_HELIX_TARGET={}", target);
+}
+
+/// Trimmed output of a successful `git` command run in `dir`
+fn git(dir: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .ok()?;
+    let text = String::from_utf8(output.stdout).ok()?;
+    let text = text.trim();
+    (output.status.success() && !text.is_empty()).then(|| text.to_string())
+}
+
+/// `YYYY-MM-DD` (UTC) of a unix time
+fn date(seconds: u64) -> String {
+    // Days to a civil date, after Howard Hinnant's `civil_from_days`
+    let days = (seconds / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
@@ -0,0 +1,143 @@
+//! `--agent`: hand every prompt to a named opencode agent
+//!
+//! opencode routes a prompt starting with `@name` to that agent, so the
+//! flag prepends the mention to each step before it is expanded, unless the
+//! text already mentions the agent.
+//!
+//! The agent and the model (`--model`, for session messages) can also come
+//! from the project config's `default_agent` and `default_model`, or be
+//! chosen in the ask dialog for one run; see [`Route::resolve`].
+
+use anyhow::{bail, Result};
+
+use crate::server::client::Agent;
+use crate::server::Model;
+
+/// The agent and model prompts go to
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Route {
+    /// Agent name, without the `@`
+    pub agent: Option<String>,
+    pub model: Option<Model>,
+}
+
+impl Route {
+    /// The agent and the model each from the first of `flags` (--agent and
+    /// --model), `dialog` (chosen in the ask dialog) and `project` (the
+    /// project config) that sets it
+    pub fn resolve(flags: &Self, dialog: &Self, project: &Self) -> Self {
+        let layers = [flags, dialog, project];
+        Self {
+            agent: layers.iter().find_map(|route| route.agent.clone()),
+            model: layers.iter().find_map(|route| route.model.clone()),
+        }
+    }
+}
+
+/// `text` with `@name ` in front, unless it already mentions `@name`
+pub fn prefix(text: &str, name: &str) -> String {
+    if mentions(text, name) {
+        text.to_string()
+    } else {
+        format!("@{} {}", name, text)
+    }
+}
+
+/// Whether `text` mentions `@name` as a word of its own
+pub fn mentions(text: &str, name: &str) -> bool {
+    let mention = format!("@{}", name);
+    text.match_indices(&mention).any(|(i, _)| {
+        let before = text[..i].chars().next_back();
+        let after = text[i + mention.len()..].chars().next();
+        before.is_none_or(char::is_whitespace)
+            && !after.is_some_and(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    })
+}
+
+/// Fail unless the server has an agent called `name`, listing the ones it has
+pub fn check(name: &str, agents: &[Agent]) -> Result<()> {
+    if agents.iter().any(|agent| agent.name == name) {
+        return Ok(());
+    }
+    let names: Vec<&str> = agents.iter().map(|agent| agent.name.as_str()).collect();
+    if names.is_empty() {
+        bail!("Unknown agent \"{}\": the server lists no agents", name);
+    }
+    bail!(
+        "Unknown agent \"{}\" (available: {}); pass --no-validate-agent to send anyway",
+        name,
+        names.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn agent(name: &str) -> Agent {
+        Agent {
+            name: name.to_string(),
+            #[cfg(feature = "tui")]
+            description: String::new(),
+            #[cfg(feature = "tui")]
+            mode: "subagent".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_prefix() {
+        assert_eq!(prefix("review @this", "reviewer"), "@reviewer review @this");
+        // Already mentioned, anywhere in the text
+        assert_eq!(prefix("@reviewer review", "reviewer"), "@reviewer review");
+        assert_eq!(
+            prefix("ask\n@reviewer: go", "reviewer"),
+            "ask\n@reviewer: go"
+        );
+        // A longer name or an email address isn't a mention
+        assert_eq!(
+            prefix("@reviewer-2 go", "reviewer"),
+            "@reviewer @reviewer-2 go"
+        );
+        assert_eq!(
+            prefix("mail me@reviewer", "reviewer"),
+            "@reviewer mail me@reviewer"
+        );
+    }
+
+    #[test]
+    fn test_resolve() {
+        let route = |agent: Option<&str>, model: Option<&str>| Route {
+            agent: agent.map(str::to_string),
+            model: model.map(|model| model.parse().unwrap()),
+        };
+        let none = Route::default();
+        let project = route(Some("architect"), Some("anthropic/claude-sonnet-4"));
+        let dialog = route(Some("build"), None);
+        let flags = route(None, Some("openai/gpt-5"));
+
+        assert_eq!(Route::resolve(&none, &none, &none), none);
+        assert_eq!(Route::resolve(&none, &none, &project), project);
+        // Field by field: the dialog's agent, the project's model
+        assert_eq!(
+            Route::resolve(&none, &dialog, &project),
+            route(Some("build"), Some("anthropic/claude-sonnet-4"))
+        );
+        // Flags over both
+        assert_eq!(
+            Route::resolve(&flags, &dialog, &project),
+            route(Some("build"), Some("openai/gpt-5"))
+        );
+        assert_eq!(Route::resolve(&project, &dialog, &flags), project);
+    }
+
+    #[test]
+    fn test_check() {
+        let agents = [agent("build"), agent("reviewer")];
+        assert!(check("reviewer", &agents).is_ok());
+        let error = check("review", &agents).unwrap_err().to_string();
+        assert!(error.contains("\"review\""), "{}", error);
+        assert!(error.contains("available: build, reviewer"), "{}", error);
+        let error = check("review", &[]).unwrap_err().to_string();
+        assert!(error.contains("lists no agents"), "{}", error);
+    }
+}
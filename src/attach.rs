@@ -0,0 +1,265 @@
+//! Image paths pasted into the ask input, sent as attachments
+//!
+//! With `attach_pasted_images = true` in the config, a path to an image in
+//! the ask input is picked up when it names an existing file of at most
+//! [`MAX_IMAGE_BYTES`]: as typed, spaces and all, in quotes, with `\ `
+//! escapes, or as a `file://` URL. The dialog lists it above the buttons
+//! (Ctrl+X drops it). A `--session` message carries the image as a file
+//! part; the prompt box only takes text, so there the path becomes an
+//! `@path` reference.
+
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::server::MessagePart;
+use crate::util::size_label;
+
+/// Largest image attached
+pub const MAX_IMAGE_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Image extensions (lowercase) and their MIME types
+const IMAGE_TYPES: [(&str, &str); 6] = [
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("bmp", "image/bmp"),
+];
+
+/// An image file pasted into the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pasted {
+    /// Where the path is in the input, with its quotes
+    pub range: Range<usize>,
+    /// The file, absolute
+    pub path: PathBuf,
+    /// Size in bytes
+    pub size: u64,
+}
+
+impl Pasted {
+    /// File name shown for the attachment
+    pub fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned())
+    }
+
+    /// The dialog's chip, e.g. `📎 screenshot.png (240.0 KB)`
+    pub fn label(&self) -> String {
+        format!("📎 {} ({})", self.file_name(), size_label(self.size))
+    }
+
+    /// The image as a session message part
+    pub fn part(&self) -> Option<MessagePart> {
+        let url = reqwest::Url::from_file_path(&self.path).ok()?;
+        Some(MessagePart::File {
+            mime: image_type(&self.path)?.to_string(),
+            filename: self.file_name(),
+            url: url.to_string(),
+        })
+    }
+}
+
+/// MIME type of an image path, by its extension
+fn image_type(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    IMAGE_TYPES
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, mime)| *mime)
+}
+
+/// The images pasted into `text`, relative paths taken from `cwd`
+///
+/// A path ends in an image extension and starts a word; of the words
+/// before the extension on its line, the first that starts an existing
+/// file's path wins, so a path with spaces is found whole.
+pub fn find(text: &str, cwd: &Path) -> Vec<Pasted> {
+    let mut pasted = Vec::new();
+    // Paths don't overlap: the next starts after the last
+    let mut from = 0;
+    for end in extension_ends(text) {
+        if end <= from {
+            continue;
+        }
+        let line_start = text[..end].rfind('\n').map_or(0, |i| i + 1).max(from);
+        let found =
+            word_starts(text, line_start, end).find_map(|start| existing(text, start..end, cwd));
+        if let Some(image) = found {
+            from = image.range.end;
+            pasted.push(image);
+        }
+    }
+    pasted
+}
+
+/// `text` with each of `pasted` (as found by [`find`]) replaced by an
+/// `@path` reference
+pub fn to_references(text: &str, pasted: &[Pasted]) -> String {
+    let mut result = text.to_string();
+    for image in pasted.iter().rev() {
+        result.replace_range(image.range.clone(), &format!("@{}", image.path.display()));
+    }
+    result
+}
+
+/// Offsets just past each image extension in `text` that ends a word
+fn extension_ends(text: &str) -> impl Iterator<Item = usize> + '_ {
+    text.match_indices('.').filter_map(|(dot, _)| {
+        let after = &text[dot + 1..];
+        let len = after
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(after.len());
+        let extension = after[..len].to_ascii_lowercase();
+        let ends_word = !after[len..].starts_with(['/', '_', '-']);
+        (ends_word && IMAGE_TYPES.iter().any(|(ext, _)| *ext == extension)).then_some(dot + 1 + len)
+    })
+}
+
+/// Offsets in `start..end` of `text` where a word starts, first to last
+fn word_starts(text: &str, start: usize, end: usize) -> impl Iterator<Item = usize> + '_ {
+    text[start..end].char_indices().filter_map(move |(i, c)| {
+        let at = start + i;
+        let before = text[..at].chars().next_back();
+        let starts_word = before.is_none_or(|b| b.is_whitespace() || b == '(');
+        (starts_word && !c.is_whitespace()).then_some(at)
+    })
+}
+
+/// The image at `range` of `text`, if it names one that exists and isn't too large
+fn existing(text: &str, range: Range<usize>, cwd: &Path) -> Option<Pasted> {
+    let mut range = range;
+    let mut raw = &text[range.clone()];
+    // Quoted: the closing quote follows the extension
+    if let Some(quote) = raw.chars().next().filter(|c| matches!(c, '\'' | '"')) {
+        if !text[range.end..].starts_with(quote) {
+            return None;
+        }
+        raw = &raw[1..];
+        range.end += 1;
+    }
+    let path = if raw.starts_with("file://") {
+        reqwest::Url::parse(raw).ok()?.to_file_path().ok()?
+    } else {
+        let unescaped = raw.replace("\\ ", " ");
+        match unescaped.strip_prefix("~/") {
+            Some(rest) => PathBuf::from(std::env::var_os("HOME")?).join(rest),
+            None => cwd.join(unescaped),
+        }
+    };
+    let metadata = std::fs::metadata(&path).ok()?;
+    if !metadata.is_file() || metadata.len() > MAX_IMAGE_BYTES {
+        return None;
+    }
+    Some(Pasted {
+        range,
+        path,
+        size: metadata.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory with `files` (name, size in bytes) in it
+    fn files(files: &[(&str, usize)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        for (name, size) in files {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, vec![0u8; *size]).unwrap();
+        }
+        dir
+    }
+
+    /// The found paths as they were typed
+    fn found<'a>(text: &'a str, cwd: &Path) -> Vec<&'a str> {
+        find(text, cwd)
+            .into_iter()
+            .map(|image| &text[image.range])
+            .collect()
+    }
+
+    #[test]
+    fn test_find() {
+        let dir = files(&[
+            ("shot.png", 10),
+            ("Screen Shot 2024.PNG", 20),
+            ("my dir/a b.jpeg", 30),
+            ("notes.txt", 5),
+            ("big.gif", MAX_IMAGE_BYTES as usize + 1),
+        ]);
+        let abs = dir.join("shot.png").display().to_string();
+
+        // Relative and absolute, with the size
+        let text = format!("why is shot.png off? compare {}.", abs);
+        let pasted = find(&text, &dir);
+        assert_eq!(pasted.len(), 2);
+        assert_eq!(&text[pasted[0].range.clone()], "shot.png");
+        assert_eq!(pasted[0].path, dir.join("shot.png"));
+        assert_eq!(pasted[0].size, 10);
+        assert_eq!(&text[pasted[1].range.clone()], abs);
+
+        // Spaces: as typed, escaped, quoted, and in a file:// URL
+        assert_eq!(
+            found("see Screen Shot 2024.PNG here", &dir),
+            ["Screen Shot 2024.PNG"]
+        );
+        assert_eq!(
+            found("see my\\ dir/a\\ b.jpeg", &dir),
+            ["my\\ dir/a\\ b.jpeg"]
+        );
+        assert_eq!(found("see 'my dir/a b.jpeg'", &dir), ["'my dir/a b.jpeg'"]);
+        let url = reqwest::Url::from_file_path(dir.join("my dir/a b.jpeg")).unwrap();
+        let text = format!("see {} now", url);
+        assert_eq!(found(&text, &dir), [url.as_str()]);
+
+        // Not images, missing, too large, not a word of its own, or a directory
+        for text in [
+            "notes.txt",
+            "missing.png",
+            "big.gif",
+            "ashot.png",
+            "shot.png_old",
+            "shot.pngx",
+            "'shot.png",
+            "my dir",
+        ] {
+            assert_eq!(found(text, &dir), Vec::<&str>::new(), "{}", text);
+        }
+        std::fs::create_dir_all(dir.join("folder.png")).unwrap();
+        assert!(find("folder.png", &dir).is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_references_and_parts() {
+        let dir = files(&[("a b.png", 2048)]);
+        let text = "fix \"a b.png\" please";
+        let pasted = find(text, &dir);
+        assert_eq!(
+            to_references(text, &pasted),
+            format!("fix @{} please", dir.join("a b.png").display())
+        );
+        assert_eq!(pasted[0].label(), "📎 a b.png (2.0 KB)");
+        let Some(MessagePart::File {
+            mime,
+            filename,
+            url,
+        }) = pasted[0].part()
+        else {
+            panic!("no part");
+        };
+        assert_eq!(mime, "image/png");
+        assert_eq!(filename, "a b.png");
+        assert!(
+            url.starts_with("file:///") && url.ends_with("a%20b.png"),
+            "{}",
+            url
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
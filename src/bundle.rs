@@ -0,0 +1,398 @@
+//! Sanitized bug report bundles (`report`)
+//!
+//! The bundle is a plain directory the user can read before attaching it to
+//! an issue: `report.txt` with versions and the terminal, and `debug.log`
+//! with the last `--debug` log run through [`sanitize_log`].
+//!
+//! What leaves the machine is decided by the pure functions below:
+//! - file paths (absolute paths, and quoted strings that look like paths)
+//!   are replaced by a short hash, so the same path can still be followed
+//!   through the log without revealing it
+//! - every other quoted string (selections, prompts, session ids) is
+//!   replaced by its length
+//! - messages that print user text unquoted ([`REDACTED_PAYLOADS`]) lose
+//!   their payload, and lines continuing a message are dropped
+//! - the invocation keeps flag and subcommand names but not their values
+//!
+//! Everything else (message names, counts, timings, error kinds) is kept.
+
+use std::fmt::Write as _;
+
+/// Log messages that print user text unquoted: (message prefix, text kept
+/// after the prefix up to and including this marker)
+const REDACTED_PAYLOADS: [(&str, &str); 3] = [
+    // Values included; the "Invocation:" line has the shape instead
+    ("CLI args:", ""),
+    // Keystrokes in the dialogs
+    ("Input bytes:", ""),
+    // The expanded prompt
+    ("send_steps: step", " = "),
+];
+
+/// Stand-in for a path: the same path always gives the same stand-in
+pub fn hash_path(path: &str) -> String {
+    // FNV-1a: stable across runs and platforms, unlike std's hasher
+    let hash = path.bytes().fold(0x811c_9dc5u32, |hash, b| {
+        (hash ^ b as u32).wrapping_mul(0x0100_0193)
+    });
+    format!("<path:{:08x}>", hash)
+}
+
+/// Shape of a command line: flags and the subcommand, with values redacted
+///
+/// `--file src/main.rs ask` becomes `--file <value> ask`, and
+/// `--port=4096` becomes `--port=<value>`.
+pub fn invocation_shape(args: &[String], subcommands: &[&str]) -> String {
+    let mut subcommand_seen = false;
+    let mut after_separator = false;
+    args.iter()
+        .map(|arg| {
+            if after_separator {
+                "<value>".to_string()
+            } else if arg == "--" {
+                after_separator = true;
+                arg.clone()
+            } else if arg.starts_with('-') && arg.len() > 1 {
+                match arg.split_once('=') {
+                    Some((flag, _)) => format!("{}=<value>", flag),
+                    None => arg.clone(),
+                }
+            } else if !subcommand_seen && subcommands.contains(&arg.as_str()) {
+                subcommand_seen = true;
+                arg.clone()
+            } else {
+                "<value>".to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Sanitize a debug log (see the module docs for the rules)
+pub fn sanitize_log(log: &str) -> String {
+    let mut out = String::new();
+    let mut continuation = 0;
+    for line in log.lines() {
+        // Entries are "[HH:MM:SS.mmm] message"; anything else continues a
+        // multi-line message
+        let entry = line
+            .strip_prefix('[')
+            .and_then(|rest| rest.split_once("] "));
+        let Some((timestamp, message)) = entry else {
+            continuation += 1;
+            continue;
+        };
+        flush_continuation(&mut out, &mut continuation);
+        let _ = writeln!(out, "[{}] {}", timestamp, sanitize_message(message));
+    }
+    flush_continuation(&mut out, &mut continuation);
+    out
+}
+
+/// Note dropped continuation lines
+fn flush_continuation(out: &mut String, count: &mut usize) {
+    match *count {
+        0 => {}
+        1 => out.push_str("  <1 line redacted>\n"),
+        n => {
+            let _ = writeln!(out, "  <{} lines redacted>", n);
+        }
+    }
+    *count = 0;
+}
+
+/// Sanitize one log message
+pub fn sanitize_message(message: &str) -> String {
+    for (prefix, marker) in REDACTED_PAYLOADS {
+        let Some(rest) = message.strip_prefix(prefix) else {
+            continue;
+        };
+        let kept = rest.find(marker).map_or(0, |i| i + marker.len());
+        let (kept, payload) = rest.split_at(kept);
+        return format!(
+            "{}{} <redacted {} bytes>",
+            prefix,
+            kept.trim_end(),
+            payload.trim_start().len()
+        );
+    }
+    hash_bare_paths(&redact_quoted(message))
+}
+
+/// Replace `"..."` strings (as printed by `{:?}`) with a path hash or their length
+fn redact_quoted(message: &str) -> String {
+    let mut out = String::with_capacity(message.len());
+    let mut chars = message.chars();
+    while let Some(c) = chars.next() {
+        if c != '"' {
+            out.push(c);
+            continue;
+        }
+        let mut content = String::new();
+        let mut escaped = false;
+        let mut closed = false;
+        for c in chars.by_ref() {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                closed = true;
+                break;
+            }
+            content.push(c);
+        }
+        // An unterminated string is redacted to the end of the message
+        out.push('"');
+        if looks_like_path(&content) {
+            out.push_str(&hash_path(&content));
+        } else if !content.is_empty() {
+            let _ = write!(out, "<{} chars>", content.chars().count());
+        }
+        if closed {
+            out.push('"');
+        }
+    }
+    out
+}
+
+/// Whether a quoted string is a file path rather than text
+fn looks_like_path(s: &str) -> bool {
+    !s.is_empty() && !s.contains(char::is_whitespace) && (s.contains('/') || s.starts_with('~'))
+}
+
+/// Replace unquoted absolute (or `~/`) paths with their hash
+///
+/// A path starts a word, so the `/` in URLs and fractions is left alone.
+fn hash_bare_paths(message: &str) -> String {
+    let mut out = String::with_capacity(message.len());
+    let mut rest = message;
+    let mut prev: Option<char> = None;
+    while let Some(c) = rest.chars().next() {
+        let starts_word = prev.is_none_or(|p| p.is_whitespace() || "([='".contains(p));
+        let is_path = rest.starts_with('/') || rest.starts_with("~/");
+        if starts_word && is_path {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || "\"'),;]:".contains(c))
+                .unwrap_or(rest.len());
+            let (path, tail) = rest.split_at(end);
+            if path.len() > 1 {
+                out.push_str(&hash_path(path));
+                prev = path.chars().last();
+                rest = tail;
+                continue;
+            }
+        }
+        out.push(c);
+        prev = Some(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+/// Facts about the machine and the last run, for `report.txt`
+#[derive(Debug, Default)]
+pub struct Environment {
+    pub version: String,
+    pub os: String,
+    /// TERM, TERM_PROGRAM and friends, as (name, value)
+    pub terminal: Vec<(String, String)>,
+    /// opencode version, or why it isn't known
+    pub server: String,
+    /// Shape of the logged invocation, if there was a debug log
+    pub invocation: Option<String>,
+}
+
+/// Contents of `report.txt`
+pub fn summary(env: &Environment) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "opencode-helix: {}", env.version);
+    let _ = writeln!(out, "OS:             {}", env.os);
+    let _ = writeln!(out, "opencode:       {}", env.server);
+    let _ = writeln!(
+        out,
+        "Invocation:     {}",
+        env.invocation
+            .as_deref()
+            .unwrap_or("unknown (no debug log; rerun with --debug)")
+    );
+    out.push_str("Terminal:\n");
+    if env.terminal.is_empty() {
+        out.push_str("  unknown\n");
+    }
+    for (name, value) in &env.terminal {
+        let _ = writeln!(out, "  {}={}", name, value);
+    }
+    out
+}
+
+/// The last "Invocation:" line of a sanitized log
+pub fn last_invocation(log: &str) -> Option<String> {
+    log.lines()
+        .filter_map(|line| line.split_once("] Invocation: "))
+        .map(|(_, shape)| shape.to_string())
+        .next_back()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split(' ').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_hash_path() {
+        // Stable and distinct, with nothing of the path left
+        assert_eq!(hash_path("/home/me/src"), hash_path("/home/me/src"));
+        assert_ne!(hash_path("/home/me/src"), hash_path("/home/me/src2"));
+        assert_eq!(hash_path(""), "<path:811c9dc5>");
+        assert!(!hash_path("/home/me").contains("home"));
+    }
+
+    #[test]
+    fn test_invocation_shape() {
+        let subcommands = ["ask", "prompt", "status"];
+        assert_eq!(
+            invocation_shape(
+                &args("--debug --file src/main.rs --line=12 prompt review"),
+                &subcommands
+            ),
+            "--debug --file <value> --line=<value> prompt <value>"
+        );
+        // Only the first subcommand name counts; later ones are values
+        assert_eq!(
+            invocation_shape(&args("-p 4096 ask status"), &subcommands),
+            "-p <value> ask <value>"
+        );
+        // Everything after "--" is a value, even if it looks like a flag
+        assert_eq!(
+            invocation_shape(&args("prompt -- --secret -"), &subcommands),
+            "prompt -- <value> <value>"
+        );
+        assert_eq!(invocation_shape(&[], &subcommands), "");
+    }
+
+    #[test]
+    fn test_sanitize_message_paths() {
+        let cwd = hash_path("/home/me/project");
+        // Quoted paths, absolute or relative, are hashed
+        assert_eq!(
+            sanitize_message(r#"CWD: "/home/me/project""#),
+            format!(r#"CWD: "{}""#, cwd)
+        );
+        assert_eq!(
+            sanitize_message(r#"file: Some("src/main.rs")"#),
+            format!(r#"file: Some("{}")"#, hash_path("src/main.rs"))
+        );
+        // Bare absolute paths are hashed up to the end of the word
+        assert_eq!(
+            sanitize_message("Failed to read /home/me/project: No such file"),
+            format!("Failed to read {}: No such file", cwd)
+        );
+        assert_eq!(
+            sanitize_message("cwd=(/home/me/project)"),
+            format!("cwd=({})", cwd)
+        );
+        assert_eq!(
+            sanitize_message("config in ~/.config/x.toml"),
+            format!("config in {}", hash_path("~/.config/x.toml"))
+        );
+        // URLs, fractions and a lone slash are kept
+        assert_eq!(
+            sanitize_message("POST http://127.0.0.1:4096/tui/publish, 1/2 /"),
+            "POST http://127.0.0.1:4096/tui/publish, 1/2 /"
+        );
+    }
+
+    #[test]
+    fn test_sanitize_message_text() {
+        // Other quoted strings keep only their length as printed, escapes included
+        assert_eq!(
+            sanitize_message(r#"selection: Some("fn main() {\n    \"hi\"\n}"), line: Some(3)"#),
+            r#"selection: Some("<26 chars>"), line: Some(3)"#
+        );
+        assert_eq!(
+            sanitize_message(r#"run_ask: TUI result = Submit("explain this")"#),
+            r#"run_ask: TUI result = Submit("<12 chars>")"#
+        );
+        assert_eq!(sanitize_message(r#"prompt: """#), r#"prompt: """#);
+        // An unterminated string is redacted to the end
+        assert_eq!(sanitize_message(r#"x: "abc /d"#), r#"x: "<6 chars>"#);
+        // Unquoted user text is dropped by message
+        assert_eq!(
+            sanitize_message("send_steps: step 1 (review) = Review @this please"),
+            "send_steps: step 1 (review) = <redacted 19 bytes>"
+        );
+        assert_eq!(
+            sanitize_message(r#"CLI args: Cli { prompt: Some("x"), port: Some(4096) }"#),
+            "CLI args: <redacted 43 bytes>"
+        );
+        assert_eq!(
+            sanitize_message("Input bytes: [68, 69]"),
+            "Input bytes: <redacted 8 bytes>"
+        );
+        // `@cmd:` commands are logged quoted
+        assert_eq!(
+            sanitize_message(r#"run_commands: "cat secrets.env" not run: skipped"#),
+            r#"run_commands: "<15 chars>" not run: skipped"#
+        );
+        assert_eq!(
+            sanitize_message(r#"run_commands: "curl -H 'k: v' x" printed 12 bytes"#),
+            r#"run_commands: "<16 chars>" printed 12 bytes"#
+        );
+        // Messages without user content are kept as is
+        assert_eq!(
+            sanitize_message("send: idle after 3s"),
+            "send: idle after 3s"
+        );
+        assert_eq!(
+            sanitize_message("Invocation: --debug prompt <value>"),
+            "Invocation: --debug prompt <value>"
+        );
+    }
+
+    #[test]
+    fn test_sanitize_log() {
+        let log = "[10:00:00.000] Invocation: prompt <value>\n\
+                   [10:00:00.001] send_steps: step 1 (text) = first line\n\
+                   second line\n\
+                   third line\n\
+                   [10:00:00.002] send: delivered as publish\n\
+                   trailing\n";
+        assert_eq!(
+            sanitize_log(log),
+            "[10:00:00.000] Invocation: prompt <value>\n\
+             [10:00:00.001] send_steps: step 1 (text) = <redacted 10 bytes>\n\
+             \x20 <2 lines redacted>\n\
+             [10:00:00.002] send: delivered as publish\n\
+             \x20 <1 line redacted>\n"
+        );
+        assert_eq!(
+            last_invocation(&sanitize_log(log)).as_deref(),
+            Some("prompt <value>")
+        );
+        assert_eq!(last_invocation(""), None);
+    }
+
+    #[test]
+    fn test_summary() {
+        let env = Environment {
+            version: "1.2.3".to_string(),
+            os: "linux x86_64".to_string(),
+            terminal: vec![("TERM".to_string(), "xterm-kitty".to_string())],
+            server: "0.15.0".to_string(),
+            invocation: None,
+        };
+        assert_eq!(
+            summary(&env),
+            "opencode-helix: 1.2.3\n\
+             OS:             linux x86_64\n\
+             opencode:       0.15.0\n\
+             Invocation:     unknown (no debug log; rerun with --debug)\n\
+             Terminal:\n\
+             \x20 TERM=xterm-kitty\n"
+        );
+    }
+}
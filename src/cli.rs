@@ -1,12 +1,17 @@
 //! CLI argument parsing for opencode-helix
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::register::RegisterArg;
+use crate::server::{Model, Token};
 
 /// External TUI for integrating opencode AI assistant with Helix editor
 #[derive(Parser, Debug)]
 #[command(name = "opencode-helix")]
-#[command(author, version, about, long_about = None)]
+// --version is ours, so it can take --json
+#[command(author, version, about, long_about = None, disable_version_flag = true)]
 pub struct Cli {
     /// Connect to a specific port (skips server discovery)
     #[arg(short, long, global = true)]
@@ -24,10 +29,22 @@ pub struct Cli {
     #[arg(short, long, global = true)]
     pub column: Option<u32>,
 
-    /// Path to file containing selection text (file is deleted after reading)
+    /// Path to file containing selection text (file is deleted after reading;
+    /// `-` reads it from stdin)
     #[arg(long, global = true)]
     pub selection_file: Option<PathBuf>,
 
+    /// Selection text from --context-file (not a flag)
+    #[arg(skip)]
+    pub selection_text: Option<String>,
+
+    /// Leave --selection-file for [`KeptSelectionFile`] to delete, as
+    /// `ask --loop` does (not a flag)
+    ///
+    /// [`KeptSelectionFile`]: crate::context::KeptSelectionFile
+    #[arg(skip)]
+    pub keep_selection_file: bool,
+
     /// Selection start line (1-based)
     #[arg(long, global = true)]
     pub selection_start: Option<u32>,
@@ -36,28 +53,184 @@ pub struct Cli {
     #[arg(long, global = true)]
     pub selection_end: Option<u32>,
 
-    /// Working directory (for server discovery, defaults to current dir)
+    /// Another file to refer to, e.g. the buffer looked at last (for @alt and
+    /// @alt:inline context)
+    #[arg(long, global = true, value_name = "PATH")]
+    pub alt_file: Option<PathBuf>,
+
+    /// Line of interest in --alt-file (1-based)
+    #[arg(long, global = true, value_name = "N", requires = "alt_file")]
+    pub alt_line: Option<u32>,
+
+    /// Helix register as an `@reg:NAME` placeholder (`NAME=CONTENT`, repeatable)
+    #[arg(long = "register", global = true, value_name = "NAME=CONTENT")]
+    pub registers: Vec<RegisterArg>,
+
+    /// Register read from a file, for long contents (`NAME=PATH`, repeatable;
+    /// file is deleted after reading)
+    #[arg(long = "register-file", global = true, value_name = "NAME=PATH")]
+    pub register_files: Vec<RegisterArg>,
+
+    /// JSON file with the editor context (`{"v": 1, "file": ..., "selection": ...}`);
+    /// flags given as well override its fields (file is deleted after reading)
+    #[arg(long, global = true)]
+    pub context_file: Option<PathBuf>,
+
+    /// Modification time (unix seconds) of the file as the buffer last saw it;
+    /// a different time on disk means placeholders may read stale content
+    #[arg(long, global = true, value_name = "SECONDS")]
+    pub buffer_mtime: Option<u64>,
+
+    /// The buffer has unsaved changes, so the file on disk is out of date
+    #[arg(long, global = true)]
+    pub modified: bool,
+
+    /// Token for a server that requires authentication (sent as a Bearer token)
+    #[arg(
+        long,
+        global = true,
+        env = "OPENCODE_HELIX_TOKEN",
+        hide_env_values = true
+    )]
+    pub auth_token: Option<Token>,
+
+    /// Hand every prompt to this opencode agent (prepends `@NAME` unless the
+    /// prompt mentions it already)
+    #[arg(long, global = true, value_name = "NAME")]
+    pub agent: Option<String>,
+
+    /// Send to the --agent without checking that the server has it
+    #[arg(long, global = true)]
+    pub no_validate_agent: bool,
+
+    /// Model the --session message asks for (`PROVIDER/MODEL`, e.g.
+    /// anthropic/claude-sonnet-4); over the project's default_model
+    #[arg(
+        long,
+        global = true,
+        value_name = "PROVIDER/MODEL",
+        requires = "session"
+    )]
+    pub model: Option<Model>,
+
+    /// Send to this opencode session id instead of the focused TUI session
+    #[arg(long, global = true)]
+    pub session: Option<String>,
+
+    /// Title for the --session, set once the prompt is sent
+    #[arg(long, global = true, value_name = "TEXT", requires = "session")]
+    pub title: Option<String>,
+
+    /// Ignore the config's [policy] for this run (asks to type "yes" first)
+    #[arg(long, global = true)]
+    pub override_policy: bool,
+
+    /// Leave an untitled --session untitled instead of naming it after the prompt
+    #[arg(long, global = true)]
+    pub no_auto_title: bool,
+
+    /// Working directory (for server discovery; defaults to the project
+    /// around --file, else the current dir)
     #[arg(long, global = true)]
     pub cwd: Option<PathBuf>,
 
+    /// Project directory found above --file when --cwd isn't given (not a
+    /// flag; see [`workspace`](crate::workspace))
+    #[arg(skip)]
+    pub workspace: Option<PathBuf>,
+
+    /// Seconds server discovery may take before giving up
+    #[arg(
+        long,
+        global = true,
+        value_name = "SECONDS",
+        default_value = "3",
+        value_parser = parse_seconds
+    )]
+    pub discovery_timeout: Duration,
+
     /// File language (e.g., "rust", "python")
     #[arg(long, global = true)]
     pub language: Option<String>,
 
-    /// Enable debug mode (writes debug info to /tmp/opencode-helix-debug.log)
+    /// Config file to use instead of ~/.config/opencode-helix/config.toml
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Enable debug mode (writes debug info to debug.log in the state directory)
     #[arg(long, global = true)]
     pub debug: bool,
 
-    /// UI theme: minimal, hacker (default), matrix, crt
+    /// UI theme: minimal, hacker (default), matrix, crt, high-contrast
     #[arg(long, global = true, default_value = "hacker")]
     pub theme: String,
 
-    /// Disable animations (blinking cursor, typing effect, scanline)
+    /// Disable animations (dialogs growing as they open, button flash)
     #[arg(long, global = true)]
     pub no_anim: bool,
 
+    /// Leave the terminal title and cursor shape alone in the dialogs
+    #[arg(long, global = true)]
+    pub no_term_integration: bool,
+
+    /// Draw the dialogs in the bottom rows of the screen instead of the alternate screen
+    #[arg(long, global = true)]
+    pub inline: bool,
+
+    /// Clear the opencode prompt box before sending (asks first if it may hold text)
+    #[arg(long, global = true)]
+    pub clear_prompt: bool,
+
+    /// Clear the opencode prompt box without checking it (implies --clear-prompt)
+    #[arg(long, global = true)]
+    pub force_clear: bool,
+
+    /// Wait for the session to finish after sending
+    #[arg(long, global = true)]
+    pub wait: bool,
+
+    /// Ring the bell and show a desktop notification when a --wait send finishes
+    #[arg(long, global = true)]
+    pub notify: bool,
+
+    /// If the session is busy, queue the prompt and send it once the session is idle
+    #[arg(long, global = true)]
+    pub queue: bool,
+
+    /// Send only the first step of a chained prompt
+    #[arg(long, global = true)]
+    pub no_chain: bool,
+
+    /// Run `@cmd:` placeholders without asking (needs `allow_cmd_placeholder`
+    /// in the config)
+    #[arg(long, global = true)]
+    pub allow_cmd: bool,
+
+    /// Don't send a prompt with placeholders left unexpanded: fail (exit 5),
+    /// or ask in the dialogs
+    #[arg(long, global = true)]
+    pub strict_placeholders: bool,
+
+    /// Suppress non-error output on stderr ("Sent: ...", "Cancelled")
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+
+    /// What to write to stdout after a prompt is sent (prompt, ask, select)
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+
+    /// Print version
+    #[arg(short = 'V', long)]
+    pub version: bool,
+
+    /// With --version: print it as JSON with the commit, build date, cargo
+    /// features and target
+    #[arg(long, requires = "version")]
+    pub json: bool,
+
+    /// None (no subcommand) runs the first-run setup
     #[command(subcommand)]
-    pub command: Command,
+    pub command: Option<Command>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -67,51 +240,302 @@ pub enum Command {
         /// Initial text to populate the input with
         #[arg(default_value = "")]
         initial: String,
+
+        /// After sending, reopen the dialog with the same text and context
+        /// (Esc ends the loop)
+        #[arg(long = "loop")]
+        repeat: bool,
     },
 
     /// Open menu to select from prompts/commands
-    Select,
+    Select {
+        /// Initial filter text (keep typing to refine it)
+        filter: Option<String>,
+
+        /// Only list one section of the menu
+        #[arg(long, value_enum)]
+        category: Option<SelectCategory>,
+
+        /// Act on the only matching item without opening the menu
+        #[arg(long)]
+        auto: bool,
+
+        /// Fetch the agents and commands instead of opening on the cached lists
+        #[arg(long)]
+        refresh: bool,
+    },
 
     /// Send a prompt directly (non-interactive)
     Prompt {
         /// Prompt name (e.g., "explain", "review") or raw text
-        text: String,
+        name_or_text: String,
+
+        /// Extra text appended on a new line after a named prompt
+        extra: Vec<String>,
+
+        /// Treat the first argument as a prompt name (error if unknown)
+        #[arg(long)]
+        name: bool,
 
         /// Submit the prompt immediately (don't just append)
         #[arg(short, long, default_value = "true")]
         submit: bool,
+
+        /// Wait for the reply and print it to stdout (implies --wait)
+        #[arg(long)]
+        print: bool,
+
+        /// Stop waiting after this many seconds, with exit code 9 (implies --wait)
+        #[arg(long, value_name = "SECONDS", value_parser = parse_seconds)]
+        max_wait: Option<Duration>,
+
+        /// With --print: the reply exactly as sent, not trimmed
+        #[arg(long, requires = "print", conflicts_with = "first_code_block")]
+        raw: bool,
+
+        /// With --print: only the contents of the reply's first fenced code block
+        #[arg(long, requires = "print")]
+        first_code_block: bool,
+    },
+
+    /// Print a prompt with its placeholders expanded from the editor
+    /// context, without sending it (`@cmd:` placeholders aren't run)
+    Context {
+        /// Prompt name (e.g., "explain", "review") or raw text
+        name_or_text: String,
+
+        /// Extra text appended on a new line after a named prompt
+        extra: Vec<String>,
+
+        /// Print where each placeholder is and what became of it instead
+        #[arg(long)]
+        explain: bool,
     },
 
     /// Show current opencode status
-    Status,
+    Status {
+        /// Print status as JSON
+        #[arg(long)]
+        json: bool,
+
+        /// Print `export OPENCODE_PORT=... OPENCODE_CWD=...` for `eval`, so
+        /// tools started from opencode reach the same server without discovery
+        #[arg(long, conflicts_with = "json")]
+        print_server_env: bool,
+    },
+
+    /// Send prompts left in the --queue queue
+    Flush,
+
+    /// Print what opencode is doing as it happens (messages, tool calls)
+    /// until Ctrl+C; --session limits it to one session
+    Watch {
+        /// Print the server events as JSON lines instead
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Send the last prompt from this project again, expanded with the current context
+    Resend {
+        /// Open the ask dialog with the prompt to change it first
+        #[arg(long)]
+        edit: bool,
+    },
+
+    /// List available prompts and where they are defined
+    List {
+        /// What to list
+        #[arg(value_enum)]
+        what: ListTarget,
+
+        /// Print the list as JSON (prompts include their per-language variants)
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Pick a recent session and switch the opencode TUI to it
+    Resume {
+        /// Maximum number of sessions to list
+        #[arg(long, default_value = "20")]
+        limit: usize,
+    },
+
+    /// Summarize the usage stats recorded with `stats = true` in the config
+    Stats {
+        /// Delete the recorded stats
+        #[arg(long)]
+        clear: bool,
+    },
+
+    /// Manage what is remembered between runs (history, collapsed categories, stats, queue)
+    State {
+        /// What to do with it
+        #[arg(value_enum)]
+        action: StateAction,
+    },
+
+    /// Collect versions and the sanitized last --debug log for a bug report
+    Report {
+        /// Directory to write the report to [default: ./opencode-helix-report-<time>]
+        #[arg(long)]
+        out_dir: Option<PathBuf>,
+    },
+
+    /// Print editor keybindings that run opencode-helix, ready to paste into its config
+    Setup {
+        /// Editor to print the keybindings for
+        #[arg(value_enum, default_value_t = Editor::Helix)]
+        editor: Editor,
+
+        /// Key the bindings are grouped under
+        #[arg(long, default_value = ";")]
+        leader: String,
+    },
+
+    /// Generate man pages or markdown docs from the CLI definition
+    #[command(hide = true)]
+    GenDocs {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = DocsFormat::Man)]
+        format: DocsFormat,
+
+        /// Directory to write the files to
+        #[arg(long, default_value = ".")]
+        out_dir: PathBuf,
+    },
+}
+
+/// Section of the select menu
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectCategory {
+    /// Built-in and user prompts (including chains)
+    Prompts,
+    /// Server commands
+    Commands,
+    /// Subagents
+    Agents,
+}
+
+/// Stdout format for sent prompts
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Nothing on stdout; the status message goes to stderr
+    Text,
+    /// One JSON object per sent prompt (see `report::SentPrompt`)
+    Json,
+}
+
+/// Editor `setup` prints keybindings for
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Editor {
+    /// `[keys.normal]` and `[keys.select]` tables for ~/.config/helix/config.toml
+    Helix,
+}
+
+/// What `gen-docs` writes
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocsFormat {
+    /// opencode-helix.1 plus a page per subcommand
+    Man,
+    /// opencode-helix.md with the options, built-in prompts and placeholders
+    Markdown,
+}
+
+/// What `state` does
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateAction {
+    /// Delete all of it, and the encryption key in the system keyring
+    Clear,
+}
+
+/// What `list` shows
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListTarget {
+    /// Built-in, user and project prompts
+    Prompts,
+}
+
+impl Command {
+    /// Subcommand name as typed
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Ask { .. } => "ask",
+            Self::Select { .. } => "select",
+            Self::Prompt { .. } => "prompt",
+            Self::Context { .. } => "context",
+            Self::Status { .. } => "status",
+            Self::Flush => "flush",
+            Self::Watch { .. } => "watch",
+            Self::Resend { .. } => "resend",
+            Self::List { .. } => "list",
+            Self::Resume { .. } => "resume",
+            Self::Stats { .. } => "stats",
+            Self::State { .. } => "state",
+            Self::Report { .. } => "report",
+            Self::Setup { .. } => "setup",
+            Self::GenDocs { .. } => "gen-docs",
+        }
+    }
+
+    /// Check if the subcommand sends a prompt
+    pub fn sends(&self) -> bool {
+        matches!(
+            self,
+            Self::Ask { .. } | Self::Select { .. } | Self::Prompt { .. } | Self::Resend { .. }
+        )
+    }
+
+    /// Check if the subcommand opens an interactive dialog
+    pub fn needs_tui(&self) -> bool {
+        matches!(
+            self,
+            Self::Ask { .. }
+                | Self::Select { .. }
+                | Self::Resume { .. }
+                | Self::Resend { edit: true }
+        )
+    }
+}
+
+/// A positive number of seconds, fractions allowed
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    match s.parse::<f64>() {
+        Ok(secs) if secs > 0.0 && secs.is_finite() => Ok(Duration::from_secs_f64(secs)),
+        _ => Err(format!(
+            "expected a positive number of seconds, got {:?}",
+            s
+        )),
+    }
 }
 
 impl Cli {
-    /// Parse CLI arguments
-    pub fn parse_args() -> Self {
-        Cli::parse()
+    /// Parse CLI arguments, returning clap's error instead of exiting
+    pub fn try_parse_args() -> Result<Self, clap::Error> {
+        Cli::try_parse()
     }
 
-    /// Get the working directory (from --cwd or current directory)
+    /// Get the working directory (from --cwd, the project around --file, or
+    /// the current directory)
     pub fn working_directory(&self) -> PathBuf {
         self.cwd
             .clone()
+            .or_else(|| self.workspace.clone())
             .unwrap_or_else(|| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")))
     }
 
-    /// Check if we have selection context
-    pub fn has_selection(&self) -> bool {
-        self.selection_file.is_some() || self.selection_start.is_some()
-    }
-
-    /// Check if we have cursor context
-    pub fn has_cursor(&self) -> bool {
-        self.line.is_some()
-    }
-
-    /// Check if we have any file context
-    pub fn has_file(&self) -> bool {
-        self.file.is_some()
+    /// Find the project around --file when --cwd isn't given, looking for
+    /// `markers` (see [`workspace::root_of`](crate::workspace::root_of))
+    pub fn find_workspace(&mut self, markers: &[String]) {
+        if self.cwd.is_some() {
+            return;
+        }
+        let Some(file) = self.file.as_ref() else {
+            return;
+        };
+        let file = std::env::current_dir()
+            .map(|dir| dir.join(file))
+            .unwrap_or_else(|_| file.clone());
+        self.workspace = crate::workspace::root_of(&file, markers);
     }
 }
 
@@ -122,7 +546,16 @@ mod tests {
     #[test]
     fn test_parse_ask() {
         let cli = Cli::parse_from(["opencode-helix", "ask"]);
-        assert!(matches!(cli.command, Command::Ask { initial } if initial.is_empty()));
+        assert!(matches!(
+            cli.command.unwrap(),
+            Command::Ask { initial, repeat: false } if initial.is_empty()
+        ));
+
+        let cli = Cli::parse_from(["opencode-helix", "ask", "--loop", "fix @this"]);
+        assert!(matches!(
+            cli.command.unwrap(),
+            Command::Ask { initial, repeat: true } if initial == "fix @this"
+        ));
     }
 
     #[test]
@@ -145,14 +578,245 @@ mod tests {
     #[test]
     fn test_parse_prompt() {
         let cli = Cli::parse_from(["opencode-helix", "prompt", "explain"]);
+        assert!(matches!(
+            cli.command.unwrap(),
+            Command::Prompt { name_or_text, extra, name, submit, print, max_wait, raw, first_code_block }
+                if name_or_text == "explain" && extra.is_empty() && !name && submit
+                    && !print && max_wait.is_none() && !raw && !first_code_block
+        ));
+    }
+
+    #[test]
+    fn test_parse_prompt_with_extra() {
+        let cli = Cli::parse_from([
+            "opencode-helix",
+            "prompt",
+            "--name",
+            "explain",
+            "focus on",
+            "errors",
+        ]);
+        assert!(matches!(
+            cli.command.unwrap(),
+            Command::Prompt { name_or_text, extra, name, .. }
+                if name_or_text == "explain" && extra == ["focus on", "errors"] && name
+        ));
+    }
+
+    #[test]
+    fn test_parse_select() {
+        let cli = Cli::parse_from(["opencode-helix", "select"]);
+        assert!(matches!(
+            cli.command.unwrap(),
+            Command::Select {
+                filter: None,
+                category: None,
+                auto: false,
+                refresh: false
+            }
+        ));
+
+        let cli = Cli::parse_from([
+            "opencode-helix",
+            "select",
+            "rev",
+            "--category",
+            "agents",
+            "--auto",
+            "--refresh",
+        ]);
+        assert!(matches!(
+            cli.command.unwrap(),
+            Command::Select { filter: Some(f), category: Some(SelectCategory::Agents), auto: true, refresh: true }
+                if f == "rev"
+        ));
+        assert!(Cli::try_parse_from(["opencode-helix", "select", "--category", "x"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_resend() {
+        let cli = Cli::parse_from(["opencode-helix", "resend"]);
+        assert!(matches!(cli.command, Some(Command::Resend { edit: false })));
+        assert!(!cli.command.unwrap().needs_tui());
+
+        let cli = Cli::parse_from(["opencode-helix", "resend", "--edit"]);
+        assert!(matches!(cli.command, Some(Command::Resend { edit: true })));
+        assert!(cli.command.unwrap().needs_tui());
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let cli = Cli::parse_from(["opencode-helix", "list", "prompts"]);
+        assert!(matches!(
+            cli.command.unwrap(),
+            Command::List {
+                what: ListTarget::Prompts,
+                json: false
+            }
+        ));
+        let cli = Cli::parse_from(["opencode-helix", "list", "prompts", "--json"]);
+        assert!(matches!(
+            cli.command.unwrap(),
+            Command::List { json: true, .. }
+        ));
+        assert!(Cli::try_parse_from(["opencode-helix", "list"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_output() {
+        let cli = Cli::parse_from(["opencode-helix", "prompt", "explain"]);
+        assert_eq!(cli.output, OutputFormat::Text);
+
+        let cli = Cli::parse_from(["opencode-helix", "--output", "json", "ask"]);
+        assert_eq!(cli.output, OutputFormat::Json);
+        assert!(Cli::try_parse_from(["opencode-helix", "--output", "yaml", "ask"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_resume() {
+        let cli = Cli::parse_from(["opencode-helix", "resume"]);
+        assert!(matches!(
+            cli.command.unwrap(),
+            Command::Resume { limit: 20 }
+        ));
+
+        let cli = Cli::parse_from(["opencode-helix", "resume", "--limit", "5"]);
+        assert!(matches!(cli.command.unwrap(), Command::Resume { limit: 5 }));
+    }
+
+    #[test]
+    fn test_parse_quiet() {
+        let cli = Cli::parse_from(["opencode-helix", "--quiet", "ask"]);
+        assert!(cli.quiet);
+
+        let cli = Cli::parse_from(["opencode-helix", "prompt", "explain", "-q"]);
+        assert!(cli.quiet);
+    }
+
+    #[test]
+    fn test_parse_version() {
+        let cli = Cli::parse_from(["opencode-helix", "--version", "--json"]);
+        assert!(cli.version && cli.json);
+        assert!(!Cli::parse_from(["opencode-helix", "-V"]).json);
+
+        // --json alone belongs to the subcommands that take it
+        assert!(Cli::try_parse_from(["opencode-helix", "--json"]).is_err());
+        let cli = Cli::parse_from(["opencode-helix", "status", "--json"]);
+        assert!(!cli.json);
+        assert!(matches!(
+            cli.command.unwrap(),
+            Command::Status { json: true, .. }
+        ));
+    }
+
+    #[test]
+    fn test_parse_session() {
+        let cli = Cli::parse_from([
+            "opencode-helix",
+            "--session",
+            "ses_123",
+            "prompt",
+            "explain",
+        ]);
+        assert_eq!(cli.session, Some("ses_123".to_string()));
+        assert!(!cli.no_auto_title);
+
+        let cli = Cli::parse_from([
+            "opencode-helix",
+            "--session",
+            "ses_123",
+            "--title",
+            "Parser fixes",
+            "prompt",
+            "explain",
+        ]);
+        assert_eq!(cli.title.as_deref(), Some("Parser fixes"));
+        // A title needs a session to go to
+        assert!(Cli::try_parse_from(["opencode-helix", "--title", "x", "ask"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_auth_token() {
+        let cli = Cli::parse_from(["opencode-helix", "--auth-token", "s3cret", "status"]);
+        assert_eq!(cli.auth_token, Some("s3cret".parse().unwrap()));
+        // Never in the CLI dump written to the --debug log
+        assert!(!format!("{:?}", cli).contains("s3cret"));
+    }
+
+    #[test]
+    fn test_parse_discovery_timeout() {
+        let cli = Cli::parse_from(["opencode-helix", "status"]);
+        assert_eq!(cli.discovery_timeout, Duration::from_secs(3));
+        let cli = Cli::parse_from(["opencode-helix", "--discovery-timeout", "0.5", "status"]);
+        assert_eq!(cli.discovery_timeout, Duration::from_millis(500));
+        for bad in ["0", "-1", "soon"] {
+            assert!(
+                Cli::try_parse_from(["opencode-helix", "--discovery-timeout", bad, "status"])
+                    .is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_wait_notify() {
+        let cli = Cli::parse_from(["opencode-helix", "prompt", "explain", "--wait", "--notify"]);
+        assert!(cli.wait);
+        assert!(cli.notify);
+    }
+
+    #[test]
+    fn test_parse_status_json() {
+        let cli = Cli::parse_from(["opencode-helix", "status", "--json"]);
+        assert!(matches!(
+            cli.command.unwrap(),
+            Command::Status { json: true, .. }
+        ));
+    }
+
+    #[test]
+    fn test_parse_queue_and_flush() {
+        let cli = Cli::parse_from(["opencode-helix", "--queue", "prompt", "review"]);
+        assert!(cli.queue);
+        let cli = Cli::parse_from(["opencode-helix", "flush"]);
+        assert!(matches!(cli.command, Some(Command::Flush)));
+        assert!(!cli.command.unwrap().needs_tui());
+    }
+
+    #[test]
+    fn test_parse_no_chain() {
+        let cli = Cli::parse_from(["opencode-helix", "prompt", "review-fix", "--no-chain"]);
+        assert!(cli.no_chain);
+        let cli = Cli::parse_from(["opencode-helix", "select"]);
+        assert!(!cli.no_chain);
+    }
+
+    #[test]
+    fn test_parse_setup() {
+        let cli = Cli::parse_from(["opencode-helix", "setup"]);
+        assert!(matches!(
+            cli.command.unwrap(),
+            Command::Setup { editor: Editor::Helix, ref leader } if leader == ";"
+        ));
+        let cli = Cli::parse_from(["opencode-helix", "setup", "helix", "--leader", "space"]);
         assert!(
-            matches!(cli.command, Command::Prompt { text, submit } if text == "explain" && submit)
+            matches!(cli.command.unwrap(), Command::Setup { ref leader, .. } if leader == "space")
         );
+        assert!(Cli::try_parse_from(["opencode-helix", "setup", "vim"]).is_err());
     }
 
     #[test]
-    fn test_parse_select() {
+    fn test_needs_tui() {
         let cli = Cli::parse_from(["opencode-helix", "select"]);
-        assert!(matches!(cli.command, Command::Select));
+        assert!(cli.command.unwrap().needs_tui());
+        let cli = Cli::parse_from(["opencode-helix", "status"]);
+        assert!(!cli.command.unwrap().needs_tui());
+    }
+
+    #[test]
+    fn test_parse_no_subcommand() {
+        let cli = Cli::parse_from(["opencode-helix"]);
+        assert!(cli.command.is_none());
+        let cli = Cli::parse_from(["opencode-helix", "--debug"]);
+        assert!(cli.command.is_none());
     }
 }
@@ -0,0 +1,340 @@
+//! `@cmd:<command>` placeholders: a shell command's output in the prompt
+//!
+//! `explain this failure: @cmd:cargo build 2>&1 | tail -n 50` runs the
+//! command through `sh -c` in the project directory and sends what it
+//! printed, stdout and stderr together, in a code fence under a `$ <command>`
+//! line. The command is the rest of the line, spaces and colons included.
+//!
+//! This runs whatever the prompt says, so it is off unless the config has
+//! `allow_cmd_placeholder = true`, and then each command is confirmed in the
+//! dialogs, shown as it will run, or allowed with `--allow-cmd` without them.
+//! A command is stopped after [`TIMEOUT`] and its output cut at
+//! [`MAX_OUTPUT`] bytes. One that doesn't run is sent as typed.
+//!
+//! Outputs are spliced in around the other placeholders' expansion, so
+//! placeholders in a command or its output stay literal.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+
+use crate::context::{Expansion, Resolution};
+use crate::server::{MessageBuilder, MessagePart};
+use crate::util::size_label;
+
+/// What a command placeholder starts with
+pub const PREFIX: &str = "@cmd:";
+
+/// How long a command may run
+pub const TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Most output sent of a command, in bytes
+pub const MAX_OUTPUT: usize = 64 * 1024;
+
+/// Fenced output of each command that ran, by command
+pub type Outputs = BTreeMap<String, String>;
+
+/// A stretch of a prompt: text, or a command placeholder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Piece<'a> {
+    Text(&'a str),
+    /// The command of a placeholder (the token is [`PREFIX`] and the command)
+    Command(&'a str),
+}
+
+/// `text` split at its `@cmd:` placeholders
+///
+/// A placeholder starts a word and runs to the end of its line, trailing
+/// blanks left out; `@cmd:` with nothing after it is text.
+pub fn split(text: &str) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut from = 0;
+    while let Some(i) = text[from..].find(PREFIX).map(|i| from + i) {
+        let after = i + PREFIX.len();
+        let line_end = text[after..].find('\n').map_or(text.len(), |n| after + n);
+        let command = text[after..line_end].trim_end();
+        let starts_word = !text[..i]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_');
+        if starts_word && !command.trim().is_empty() {
+            if start < i {
+                pieces.push(Piece::Text(&text[start..i]));
+            }
+            pieces.push(Piece::Command(command));
+            start = after + command.len();
+        }
+        from = after;
+    }
+    if start < text.len() {
+        pieces.push(Piece::Text(&text[start..]));
+    }
+    pieces
+}
+
+/// The commands of `text`'s placeholders, each once, in order
+pub fn commands(text: &str) -> Vec<&str> {
+    let mut commands = Vec::new();
+    for piece in split(text) {
+        if let Piece::Command(command) = piece {
+            if !commands.contains(&command) {
+                commands.push(command);
+            }
+        }
+    }
+    commands
+}
+
+/// `text` expanded by `expand` around its command placeholders, each
+/// replaced by its output in `outputs` (as typed without one)
+pub fn expand(text: &str, outputs: &Outputs, expand: impl Fn(&str) -> Expansion) -> Expansion {
+    let mut result = Expansion::default();
+    for piece in split(text) {
+        match piece {
+            Piece::Text(piece) => {
+                let expansion = expand(piece);
+                result.text.push_str(&expansion.text);
+                result.substituted.extend(expansion.substituted);
+                // Pieces are slices of `text`
+                let offset = piece.as_ptr() as usize - text.as_ptr() as usize;
+                result.report.merge(expansion.report, offset);
+            }
+            Piece::Command(command) => {
+                let token = format!("{}{}", PREFIX, command);
+                let position = command.as_ptr() as usize - text.as_ptr() as usize - PREFIX.len();
+                match outputs.get(command) {
+                    Some(output) => {
+                        result.text.push_str(output);
+                        let delta = output.len() as isize - token.len() as isize;
+                        result
+                            .report
+                            .note(&token, position, Resolution::Expanded, delta);
+                        result.substituted.insert(token, output.len());
+                    }
+                    None => {
+                        result.text.push_str(&token);
+                        result
+                            .report
+                            .note(&token, position, Resolution::Disabled, 0);
+                    }
+                }
+            }
+        }
+    }
+    result
+}
+
+/// [`expand`] for a session message: `parts` makes the parts of the text
+/// around the placeholders, and the outputs are text parts
+pub fn parts(
+    text: &str,
+    outputs: &Outputs,
+    parts: impl Fn(&str) -> Vec<MessagePart>,
+) -> Vec<MessagePart> {
+    let mut message = MessageBuilder::default();
+    for piece in split(text) {
+        message = match piece {
+            Piece::Text(text) => parts(text).into_iter().fold(message, MessageBuilder::part),
+            Piece::Command(command) => match outputs.get(command) {
+                Some(output) => message.text(output),
+                None => message.text(&format!("{}{}", PREFIX, command)),
+            },
+        };
+    }
+    message.build()
+}
+
+/// Run `command` with `sh -c` in `cwd` and fence what it printed
+///
+/// Fails if it can't be started or runs longer than `timeout`. Past `max`
+/// bytes of output the command is stopped and the output cut, with a note;
+/// a failed command's exit status is noted too.
+pub fn run(command: &str, cwd: &Path, timeout: Duration, max: usize) -> Result<String> {
+    let deadline = Instant::now() + timeout;
+    let mut child = Command::new("sh")
+        .arg("-c")
+        // stderr joins stdout, so the two interleave as in a terminal
+        .arg(format!("exec 2>&1\n{}", command))
+        .current_dir(cwd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        // Its own process group, so stopping it stops what it started
+        .process_group(0)
+        .spawn()
+        .context("Failed to start sh")?;
+
+    let mut stdout = child.stdout.take().context("No output pipe")?;
+    let (send, read) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut output = Vec::new();
+        let result = (&mut stdout).take(max as u64 + 1).read_to_end(&mut output);
+        let _ = send.send(result.map(|_| output));
+    });
+    let read = read.recv_timeout(timeout);
+    let status = match read {
+        Ok(Ok(ref output)) if output.len() <= max => wait_until(&mut child, deadline),
+        _ => None,
+    };
+    // Still running: over its time, or writing past the cap
+    if status.is_none() {
+        stop(&mut child);
+    }
+
+    let Ok(output) = read else {
+        bail!("timed out after {:?}", timeout);
+    };
+    let output = output.context("Failed to read the output")?;
+    let cut = output.len() > max;
+    let mut text = String::from_utf8_lossy(&output[..output.len().min(max)]).into_owned();
+    while text.ends_with('\n') {
+        text.pop();
+    }
+    let note = match status {
+        _ if cut => format!("[... output cut at {} ...]", size_label(max as u64)),
+        Some(status) => match status.code() {
+            Some(0) => String::new(),
+            Some(code) => format!("[exit status {}]", code),
+            None => "[killed by a signal]".to_string(),
+        },
+        None => bail!("timed out after {:?}", timeout),
+    };
+    let mut block = format!("```console\n$ {}\n", command);
+    for line in [text, note] {
+        if !line.is_empty() {
+            block.push_str(&line);
+            block.push('\n');
+        }
+    }
+    block.push_str("```");
+    Ok(block)
+}
+
+/// `child`'s exit status, if it exits by `deadline`
+fn wait_until(child: &mut Child, deadline: Instant) -> Option<ExitStatus> {
+    loop {
+        if let Ok(Some(status)) = child.try_wait() {
+            return Some(status);
+        }
+        if Instant::now() >= deadline {
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Kill `child`'s process group and reap it
+fn stop(child: &mut Child) {
+    // SAFETY: `child` was spawned with `process_group(0)`, so its pid is the
+    // group id, and it is still unreaped, so that id cannot have been reused
+    unsafe { libc::killpg(child.id() as libc::pid_t, libc::SIGKILL) };
+    let _ = child.wait();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split() {
+        let text = "explain this failure: @cmd:cargo build 2>&1 | tail -n 50";
+        assert_eq!(
+            split(text),
+            [
+                Piece::Text("explain this failure: "),
+                Piece::Command("cargo build 2>&1 | tail -n 50"),
+            ]
+        );
+
+        // To the end of the line, colons and all
+        let text = "@cmd:grep -n 'a: b' src/x.rs  \nthen\n(@cmd:date)\n";
+        assert_eq!(
+            split(text),
+            [
+                Piece::Command("grep -n 'a: b' src/x.rs"),
+                Piece::Text("  \nthen\n("),
+                Piece::Command("date)"),
+                Piece::Text("\n"),
+            ]
+        );
+        assert_eq!(commands("@cmd:ls\n@cmd:ls\n@cmd:pwd"), ["ls", "pwd"]);
+
+        // Not a placeholder: inside a word, or no command
+        for text in ["mail@cmd:ls", "@cmd:", "@cmd:  \nls", ""] {
+            assert!(commands(text).is_empty(), "{:?}", text);
+        }
+        assert_eq!(split("mail@cmd:ls"), [Piece::Text("mail@cmd:ls")]);
+    }
+
+    #[test]
+    fn test_expand() {
+        let outputs = Outputs::from([("ls".to_string(), "```\nREADME\n```".to_string())]);
+        let upper = |text: &str| Expansion {
+            text: text.to_uppercase(),
+            ..Default::default()
+        };
+        let expansion = expand("see @cmd:ls\nand @cmd:pwd\n", &outputs, upper);
+        assert_eq!(expansion.text, "SEE ```\nREADME\n```\nAND @cmd:pwd\n");
+        assert_eq!(expansion.substituted.get("@cmd:ls"), Some(&14));
+
+        let sent = parts("see @cmd:ls", &outputs, |text| {
+            vec![MessagePart::Text {
+                text: text.to_string(),
+            }]
+        });
+        assert_eq!(
+            sent,
+            [MessagePart::Text {
+                text: "see ```\nREADME\n```".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let cwd = std::env::temp_dir();
+        let run = |command: &str| run(command, &cwd, Duration::from_secs(10), 1000);
+
+        let block = run("echo out; echo err >&2").unwrap();
+        assert_eq!(block, "```console\n$ echo out; echo err >&2\nout\nerr\n```");
+        let block = run("echo failed; exit 3").unwrap();
+        assert!(block.ends_with("failed\n[exit status 3]\n```"), "{}", block);
+        assert_eq!(run("true").unwrap(), "```console\n$ true\n```");
+    }
+
+    #[test]
+    fn test_run_cap_and_timeout() {
+        let cwd = std::env::temp_dir();
+        // Stopped at the cap, however much more it would print
+        let started = Instant::now();
+        let block = run("yes", &cwd, Duration::from_secs(10), 1000).unwrap();
+        assert!(
+            block.ends_with("y\n[... output cut at 1000 B ...]\n```"),
+            "{}",
+            block
+        );
+        assert!(block.len() < 1100, "{}", block.len());
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // Stopped at the deadline, with what it started
+        let started = Instant::now();
+        let e = run("sleep 30", &cwd, Duration::from_millis(300), 1000).unwrap_err();
+        assert_eq!(e.to_string(), "timed out after 300ms");
+        let e = run(
+            "echo early; sleep 30",
+            &cwd,
+            Duration::from_millis(300),
+            1000,
+        )
+        .unwrap_err();
+        assert_eq!(e.to_string(), "timed out after 300ms");
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
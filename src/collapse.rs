@@ -0,0 +1,85 @@
+//! Collapsed select menu categories, remembered per project
+//!
+//! Stored like the `resend` history: one entry per canonical project
+//! directory, under `$XDG_STATE_HOME/opencode-helix/collapsed/`, through the
+//! [`StateStore`].
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::history::{canonical, entry_path};
+use crate::paths::state_dir;
+use crate::state::StateStore;
+
+/// A project's collapsed categories
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    /// Canonical project directory (guards against hash collisions)
+    project: PathBuf,
+    categories: BTreeSet<String>,
+}
+
+/// Collapse state directory (`collapsed/` in the state directory)
+pub fn collapse_dir() -> PathBuf {
+    state_dir().join("collapsed")
+}
+
+/// Categories collapsed in `project`'s select menu (none if never saved)
+pub fn load(store: &dyn StateStore, dir: &Path, project: &Path) -> Result<BTreeSet<String>> {
+    let project = canonical(project);
+    let path = entry_path(dir, &project);
+    let Some(text) = store.read(&path)? else {
+        return Ok(BTreeSet::new());
+    };
+    let entry: Entry = serde_json::from_str(&text)
+        .with_context(|| format!("Invalid collapse entry {}", path.display()))?;
+    Ok(if entry.project == project {
+        entry.categories
+    } else {
+        BTreeSet::new()
+    })
+}
+
+/// Remember the categories collapsed in `project`'s select menu
+pub fn save(
+    store: &dyn StateStore,
+    dir: &Path,
+    project: &Path,
+    categories: &BTreeSet<String>,
+) -> Result<()> {
+    let entry = Entry {
+        project: canonical(project),
+        categories: categories.clone(),
+    };
+    store.write(
+        &entry_path(dir, &entry.project),
+        &serde_json::to_string(&entry)?,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Plain;
+
+    #[test]
+    fn test_collapsed_per_project() {
+        let root = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        let dir = root.join("collapsed");
+        let (one, two) = (root.join("one"), root.join("two"));
+        std::fs::create_dir_all(&one).unwrap();
+        std::fs::create_dir_all(&two).unwrap();
+        assert!(load(&Plain, &dir, &one).unwrap().is_empty());
+
+        let commands = BTreeSet::from(["COMMANDS".to_string()]);
+        save(&Plain, &dir, &one, &commands).unwrap();
+        let loaded = load(&Plain, &dir, &root.join("two/../one")).unwrap();
+        let other = load(&Plain, &dir, &two).unwrap();
+        let _ = std::fs::remove_dir_all(&root);
+        assert_eq!(loaded, commands);
+        assert!(other.is_empty());
+    }
+}
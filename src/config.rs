@@ -1,6 +1,17 @@
 //! Configuration and default prompts
 
-use crate::tui::app::SelectItem;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "tui")]
+use crate::menu::SelectItem;
+use crate::paths;
+use crate::server::events::Keepalive;
+use crate::server::{Model, Token};
 
 /// A predefined prompt template
 #[derive(Debug, Clone)]
@@ -8,6 +19,8 @@ pub struct Prompt {
     pub name: &'static str,
     pub prompt: &'static str,
     pub description: &'static str,
+    /// Prompt text for particular languages (Helix language names)
+    pub by_language: &'static [(&'static str, &'static str)],
 }
 
 /// Default prompts matching opencode.nvim
@@ -16,67 +29,791 @@ pub const DEFAULT_PROMPTS: &[Prompt] = &[
         name: "explain",
         prompt: "Explain how this code works: @this",
         description: "Explain the selected code",
+        by_language: &[],
     },
     Prompt {
         name: "review",
         prompt: "Review this code and suggest improvements: @this",
         description: "Code review",
+        by_language: &[],
     },
     Prompt {
         name: "fix",
         prompt: "Fix the issue in this code: @this",
         description: "Fix code issues",
+        by_language: &[],
     },
     Prompt {
         name: "implement",
         prompt: "Implement based on the context: @this",
         description: "Implement code",
+        by_language: &[],
     },
     Prompt {
         name: "tests",
         prompt: "Write tests for this code: @this",
         description: "Generate tests",
+        by_language: &[
+            (
+                "rust",
+                "Write tests for this code using #[test] functions: @this",
+            ),
+            ("python", "Write tests for this code using pytest: @this"),
+            (
+                "go",
+                "Write tests for this code using the testing package: @this",
+            ),
+        ],
     },
     Prompt {
         name: "docs",
         prompt: "Add documentation to this code: @this",
         description: "Add documentation",
+        by_language: &[],
     },
     Prompt {
         name: "refactor",
         prompt: "Refactor this code to be cleaner and more maintainable: @this",
         description: "Refactor code",
+        by_language: &[],
     },
     Prompt {
         name: "optimize",
         prompt: "Optimize this code for better performance: @this",
         description: "Optimize performance",
+        by_language: &[],
     },
 ];
 
-/// Built-in commands
-pub const BUILTIN_COMMANDS: &[(&str, &str)] = &[
-    ("prompt.clear", "Clear the prompt input"),
-    ("prompt.submit", "Submit the current prompt"),
-    ("session.new", "Start a new session"),
-    ("session.list", "List all sessions"),
-    ("model.list", "List available models"),
-];
+/// User configuration loaded from `config.toml` (see [`paths::config_file`])
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct UserConfig {
+    /// Notify on completion when a --wait send takes longer than this many seconds
+    pub notify_after_secs: Option<u64>,
+    /// Allow Ctrl+G to open Helix as the prompt editor even when running inside Helix
+    pub allow_nested_editor: bool,
+    /// Run `@cmd:` placeholders (see `cmd`), each confirmed or allowed with --allow-cmd
+    pub allow_cmd_placeholder: bool,
+    /// Act as if --strict-placeholders were always given
+    pub strict_placeholders: bool,
+    /// Attach image files whose paths are pasted into the ask input (see `attach`)
+    pub attach_pasted_images: bool,
+    /// User-defined prompts (`[prompts.<name>]`), overriding built-ins of the same name
+    pub prompts: BTreeMap<String, UserPrompt>,
+    /// Built-in prompts to hide
+    pub disabled_prompts: Vec<String>,
+    /// Ask dialog quick actions (`[quick_actions]`): key name to template
+    pub quick_actions: BTreeMap<String, String>,
+    /// Longest selection `@selection` expands in full (default 400 lines)
+    pub max_selection_lines: Option<usize>,
+    /// Largest file read into a prompt or the selection preview, in bytes
+    /// (default 1 MiB)
+    pub max_file_bytes: Option<u64>,
+    /// How the dialogs show the text cursor
+    pub cursor_style: CursorStyle,
+    /// Terminal cursor shape in the dialogs (default: the theme's)
+    pub cursor_shape: Option<CursorShape>,
+    /// Columns between the tab stops tabs are drawn to in the dialogs (default 4)
+    pub tab_width: Option<usize>,
+    /// When the dialogs animate opening and flash focused buttons
+    pub dialog_effects: DialogEffects,
+    /// Draw the dialogs in the bottom rows of the screen (as with --inline)
+    pub inline: bool,
+    /// Record local usage stats for `opencode-helix stats`
+    pub stats: bool,
+    /// Encrypt the history, collapsed categories, stats and queue at rest
+    /// (see `state`)
+    pub encrypt_state: bool,
+    /// What --clear-prompt does when the server can't report the prompt box text
+    pub clear_behavior: ClearBehavior,
+    /// Estimated tokens over which the ask dialog's counter warns (default 8,000)
+    pub token_budget: Option<usize>,
+    /// Estimated tokens over which the ask dialog asks before sending (default 32,000)
+    pub token_limit: Option<usize>,
+    /// Longer prompts go to the TUI in several append events of at most this
+    /// many bytes (default 65,536)
+    pub chunk_size: Option<usize>,
+    /// Seconds between server probes while waiting on events (default 15, 0 = off)
+    pub keepalive_secs: Option<u64>,
+    /// Failed probes in a row after which the server counts as gone (default 2)
+    pub keepalive_failures: Option<u32>,
+    /// Hours after which temp files left with a selection in them are
+    /// removed (default 24, 0 = never; see `temp_files`)
+    pub temp_file_max_age_hours: Option<u64>,
+    /// Translations of the dialogs' text (`[strings]`, see `tui::strings`)
+    pub strings: toml::Table,
+    /// Limits on what a send may do (`[policy]`, see `policy`)
+    pub policy: Policy,
+    /// Files or directories marking a project's root, looked for above
+    /// --file when --cwd isn't given (see `workspace`)
+    pub root_markers: Option<Vec<String>>,
+}
+
+/// Limits on what a send may do, whatever the flags say
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Policy {
+    /// Append every prompt to the prompt box instead of submitting it
+    pub never_submit: bool,
+    /// Ask before submitting a prompt of more than this many bytes
+    pub confirm_submit_over_bytes: Option<usize>,
+    /// Agents a prompt may be handed to; empty allows any
+    pub allowed_agents: Vec<String>,
+}
+
+/// Default `tab_width`
+#[cfg(feature = "tui")]
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
+/// Default `chunk_size`: well below the request body limits servers use
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Smallest `chunk_size` accepted, so a typo can't turn a prompt into thousands of requests
+const MIN_CHUNK_SIZE: usize = 1024;
+
+/// Token estimates at which the ask dialog warns, and asks before sending
+#[cfg(feature = "tui")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenBudget {
+    pub warn: usize,
+    pub limit: usize,
+}
+
+#[cfg(feature = "tui")]
+impl Default for TokenBudget {
+    fn default() -> Self {
+        Self {
+            warn: 8_000,
+            limit: 32_000,
+        }
+    }
+}
+
+/// What to do with text that may be in the opencode prompt box before --clear-prompt
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClearBehavior {
+    /// Ask in the dialogs; require --force-clear otherwise
+    #[default]
+    Ask,
+    /// Keep the text and append to it
+    Append,
+    /// Clear it
+    Overwrite,
+}
+
+/// How the dialogs show the text cursor
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CursorStyle {
+    /// A blinking "█" after the text
+    #[default]
+    Block,
+    /// The character under the cursor in reverse video
+    Reversed,
+    /// Only the terminal's own cursor (followed by screen magnifiers)
+    Terminal,
+}
+
+/// Terminal cursor shape while a dialog is open (DECSCUSR, steady)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CursorShape {
+    Block,
+    Underline,
+    Bar,
+}
+
+#[cfg(feature = "tui")]
+impl CursorShape {
+    /// DECSCUSR parameter (`CSI n SP q`)
+    pub fn decscusr(self) -> u8 {
+        match self {
+            Self::Block => 2,
+            Self::Underline => 4,
+            Self::Bar => 6,
+        }
+    }
+}
+
+/// When the dialogs animate opening and flash focused buttons (never with --no-anim)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DialogEffects {
+    /// Unless the terminal looks slow (over SSH, or a dumb terminal)
+    #[default]
+    Auto,
+    On,
+    Off,
+}
+
+impl DialogEffects {
+    /// Whether to show the effects on a terminal that is `slow` or not
+    #[cfg(feature = "tui")]
+    pub fn enabled(self, slow: bool) -> bool {
+        match self {
+            Self::Auto => !slow,
+            Self::On => true,
+            Self::Off => false,
+        }
+    }
+}
+
+/// A prompt defined in the user config
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct UserPrompt {
+    /// Prompt text (optional when the prompt only chains others)
+    pub prompt: Option<String>,
+    /// Description shown in the select menu
+    pub description: Option<String>,
+    /// Prompts sent after this one, each once the session has gone idle
+    pub chain: Vec<String>,
+    /// Prompt text used instead of `prompt` for these languages
+    /// (`[prompts.<name>.by_language]`, keyed by Helix language name)
+    pub by_language: BTreeMap<String, String>,
+}
+
+impl UserPrompt {
+    /// Prompt text for `language`, falling back to `prompt`
+    pub fn text(&self, language: Option<&str>) -> Option<&str> {
+        language
+            .and_then(|language| self.by_language.get(language))
+            .or(self.prompt.as_ref())
+            .map(String::as_str)
+    }
+}
+
+/// Key that triggers a quick action
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ActionKey {
+    /// Function key F1-F12
+    F(u8),
+    /// Alt + digit
+    Alt(char),
+}
+
+impl ActionKey {
+    /// Parse a key name: "F1" to "F12" or "Alt+0" to "Alt+9" (case-insensitive)
+    pub fn parse(name: &str) -> Option<Self> {
+        let lower = name.trim().to_ascii_lowercase();
+        if let Some(digit) = lower.strip_prefix("alt+") {
+            let mut chars = digit.chars();
+            return match (chars.next(), chars.next()) {
+                (Some(c @ '0'..='9'), None) => Some(Self::Alt(c)),
+                _ => None,
+            };
+        }
+        match lower.strip_prefix('f')?.parse() {
+            Ok(n @ 1..=12) => Some(Self::F(n)),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ActionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::F(n) => write!(f, "F{}", n),
+            Self::Alt(c) => write!(f, "Alt+{}", c),
+        }
+    }
+}
+
+/// A quick action: pressing `key` in the ask dialog submits the input through `template`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuickAction {
+    pub key: ActionKey,
+    /// Prompt template; `{input}` is replaced with the dialog's input
+    pub template: String,
+}
 
-/// Get prompt by name
-pub fn get_prompt(name: &str) -> Option<&'static Prompt> {
-    DEFAULT_PROMPTS.iter().find(|p| p.name == name)
+impl QuickAction {
+    /// Parse and validate a `[quick_actions]` entry
+    fn parse(key: &str, template: &str) -> Result<Self> {
+        let Some(action_key) = ActionKey::parse(key) else {
+            bail!(
+                "Quick action '{}': unknown key (expected F1-F12 or Alt+0-9)",
+                key
+            );
+        };
+        if template.trim().is_empty() {
+            bail!("Quick action {}: empty template", action_key);
+        }
+        // `{input}` is the only placeholder; other braces are likely typos
+        let rest = template.replace("{input}", "");
+        if let Some(pos) = rest.find(['{', '}']) {
+            let snippet: String = rest[pos..].chars().take(12).collect();
+            bail!(
+                "Quick action {}: unexpected brace at '{}' (only {{input}} is supported)",
+                action_key,
+                snippet
+            );
+        }
+        Ok(Self {
+            key: action_key,
+            template: template.to_string(),
+        })
+    }
+
+    /// Apply the template to the dialog's input
+    #[cfg(feature = "tui")]
+    pub fn apply(&self, input: &str) -> String {
+        self.template.replace("{input}", input)
+    }
+
+    /// Short description for the help line (the template without `{input}`)
+    #[cfg(feature = "tui")]
+    pub fn label(&self) -> String {
+        let text = self.template.replace("{input}", " ");
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let label = words.join(" ");
+        if label.is_empty() {
+            "send".to_string()
+        } else {
+            crate::util::display_truncate(&label, 16)
+        }
+    }
+}
+
+/// One prompt to send; chained prompts resolve to several steps
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PromptStep {
+    /// Prompt name (shown in chain progress)
+    pub name: String,
+    /// Prompt text before placeholder expansion
+    pub text: String,
+    /// From the project config, which comes with the repository: its
+    /// `@cmd:` placeholders are always confirmed (see `cmd`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub from_project: bool,
 }
 
-/// Convert prompts to select items
-pub fn prompts_to_select_items() -> Vec<SelectItem> {
-    DEFAULT_PROMPTS
+impl UserConfig {
+    /// Load the user config, returning defaults if the file doesn't exist
+    ///
+    /// `flag` is `--config`; a file named there or in `$OPENCODE_HELIX_CONFIG`
+    /// must exist (see [`paths::config_file`]).
+    pub fn load(flag: Option<&Path>) -> Result<Self> {
+        let explicit = paths::explicit_config(flag).is_some();
+        match paths::config_file(flag) {
+            Some(path) if explicit || path.exists() => {
+                let text = std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                Self::parse(&text).with_context(|| format!("Invalid config {}", path.display()))
+            }
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Parse config from TOML text, rejecting unknown or cyclic prompt chains
+    pub fn parse(text: &str) -> Result<Self> {
+        let config: Self = toml::from_str(text)?;
+        if config.max_selection_lines == Some(0) {
+            bail!("max_selection_lines must be at least 1");
+        }
+        if config.max_file_bytes == Some(0) {
+            bail!("max_file_bytes must be at least 1");
+        }
+        if config.chunk_size.is_some_and(|size| size < MIN_CHUNK_SIZE) {
+            bail!("chunk_size must be at least {}", MIN_CHUNK_SIZE);
+        }
+        if config.keepalive_failures == Some(0) {
+            bail!("keepalive_failures must be at least 1");
+        }
+        if config.tab_width == Some(0) {
+            bail!("tab_width must be at least 1");
+        }
+        #[cfg(feature = "tui")]
+        crate::tui::strings::Strings::layered(&[&config.strings]).context("Invalid [strings]")?;
+        PromptRegistry::new(&config, None)?;
+        let mut seen = Vec::new();
+        for (key, template) in &config.quick_actions {
+            let action = QuickAction::parse(key, template)?;
+            if seen.contains(&action.key) {
+                bail!("Quick action {} is defined twice", action.key);
+            }
+            seen.push(action.key);
+        }
+        Ok(config)
+    }
+
+    /// Quick actions of the ask dialog, in key order
+    #[cfg(feature = "tui")]
+    pub fn quick_actions(&self) -> Vec<QuickAction> {
+        // Validated when the config was parsed
+        let mut actions: Vec<QuickAction> = self
+            .quick_actions
+            .iter()
+            .filter_map(|(key, template)| QuickAction::parse(key, template).ok())
+            .collect();
+        actions.sort_by_key(|a| a.key);
+        actions
+    }
+
+    /// Longest selection `@selection` expands in full
+    pub fn max_selection_lines(&self) -> usize {
+        self.max_selection_lines
+            .unwrap_or(crate::context::DEFAULT_MAX_SELECTION_LINES)
+    }
+
+    /// Largest file read into a prompt or the selection preview, in bytes
+    pub fn max_file_bytes(&self) -> u64 {
+        self.max_file_bytes
+            .unwrap_or(crate::file_content::DEFAULT_MAX_BYTES)
+    }
+
+    /// Longest piece of a prompt sent in one append event, in bytes
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE)
+    }
+
+    /// Files or directories marking a project's root
+    pub fn root_markers(&self) -> Vec<String> {
+        self.root_markers.clone().unwrap_or_else(|| {
+            crate::workspace::DEFAULT_MARKERS
+                .iter()
+                .map(|marker| marker.to_string())
+                .collect()
+        })
+    }
+
+    /// Columns between tab stops in the dialogs
+    #[cfg(feature = "tui")]
+    pub fn tab_width(&self) -> usize {
+        self.tab_width.unwrap_or(DEFAULT_TAB_WIDTH)
+    }
+
+    /// Server probes while waiting on events, None when turned off
+    pub fn keepalive(&self) -> Option<Keepalive> {
+        let default = Keepalive::default();
+        let interval = match self.keepalive_secs {
+            Some(0) => return None,
+            Some(secs) => Duration::from_secs(secs),
+            None => default.interval,
+        };
+        Some(Keepalive {
+            interval,
+            failures: self.keepalive_failures.unwrap_or(default.failures),
+        })
+    }
+
+    /// Age at which left-behind temp files are removed, None when turned off
+    pub fn temp_file_max_age(&self) -> Option<Duration> {
+        match self.temp_file_max_age_hours {
+            Some(0) => None,
+            Some(hours) => Some(Duration::from_secs(hours.saturating_mul(60 * 60))),
+            None => Some(crate::temp_files::DEFAULT_MAX_AGE),
+        }
+    }
+
+    /// Token budget of the ask dialog
+    #[cfg(feature = "tui")]
+    pub fn token_budget(&self) -> TokenBudget {
+        let default = TokenBudget::default();
+        TokenBudget {
+            warn: self.token_budget.unwrap_or(default.warn),
+            limit: self.token_limit.unwrap_or(default.limit),
+        }
+    }
+
+    /// Whether a wait of `elapsed_secs` should trigger a notification
+    pub fn should_notify(&self, notify_flag: bool, elapsed_secs: u64) -> bool {
+        notify_flag || self.notify_after_secs.is_some_and(|n| elapsed_secs >= n)
+    }
+}
+
+/// Name of the per-project config file, looked up from the working directory upwards
+pub const PROJECT_CONFIG_FILE: &str = ".opencode-helix.toml";
+
+/// Project configuration (`.opencode-helix.toml`), shared by a team in the repo
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ProjectConfig {
+    /// Project prompts (`[[prompts]]`), overriding user and built-in prompts
+    pub prompts: Vec<ProjectPrompt>,
+    /// Built-in prompts to hide
+    pub disabled_prompts: Vec<String>,
+    /// Token for a server that requires authentication (--auth-token takes precedence)
+    pub auth_token: Option<Token>,
+    /// Header the token is sent in, as is; by default `Authorization: Bearer <token>`
+    pub auth_header: Option<String>,
+    /// Agent prompts are handed to unless --agent or the ask dialog says otherwise
+    pub default_agent: Option<String>,
+    /// Model (`provider/model`) session messages ask for unless --model or
+    /// the ask dialog says otherwise
+    pub default_model: Option<Model>,
+}
+
+/// A prompt defined in the project config
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProjectPrompt {
+    pub name: String,
+    #[serde(flatten)]
+    pub prompt: UserPrompt,
+}
+
+impl ProjectConfig {
+    /// Find the nearest project config at or above `dir`
+    pub fn find(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors()
+            .map(|d| d.join(PROJECT_CONFIG_FILE))
+            .find(|path| path.is_file())
+    }
+
+    /// Load the nearest project config, if there is one
+    pub fn load(dir: &Path) -> Result<Option<Self>> {
+        let Some(path) = Self::find(dir) else {
+            return Ok(None);
+        };
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let config = toml::from_str(&text)
+            .with_context(|| format!("Invalid project config {}", path.display()))?;
+        Ok(Some(config))
+    }
+}
+
+/// Where a prompt comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptSource {
+    Builtin,
+    User,
+    Project,
+}
+
+impl std::fmt::Display for PromptSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Builtin => write!(f, "builtin"),
+            Self::User => write!(f, "user"),
+            Self::Project => write!(f, "project"),
+        }
+    }
+}
+
+/// A prompt in the registry
+#[derive(Debug, Clone)]
+pub struct RegistryEntry {
+    pub name: String,
+    pub prompt: UserPrompt,
+    pub source: PromptSource,
+}
+
+/// The available prompts: built-ins, overridden by the user config, overridden
+/// by the project config (project > user > built-in)
+#[derive(Debug, Clone)]
+pub struct PromptRegistry {
+    /// Built-ins first in their usual order, then user and project additions
+    entries: Vec<RegistryEntry>,
+}
+
+impl Default for PromptRegistry {
+    fn default() -> Self {
+        Self::new(&UserConfig::default(), None).expect("built-in prompts are valid")
+    }
+}
+
+impl PromptRegistry {
+    /// Merge the prompt layers, rejecting unknown or cyclic chains and
+    /// disabling names that aren't built-ins
+    pub fn new(user: &UserConfig, project: Option<&ProjectConfig>) -> Result<Self> {
+        let disabled: Vec<&String> = user
+            .disabled_prompts
+            .iter()
+            .chain(project.iter().flat_map(|p| &p.disabled_prompts))
+            .collect();
+        if let Some(name) = disabled
+            .iter()
+            .find(|name| !DEFAULT_PROMPTS.iter().any(|p| p.name == name.as_str()))
+        {
+            bail!("disabled_prompts: '{}' is not a built-in prompt", name);
+        }
+
+        let mut registry = Self {
+            entries: DEFAULT_PROMPTS
+                .iter()
+                .filter(|p| !disabled.iter().any(|d| d.as_str() == p.name))
+                .map(|p| RegistryEntry {
+                    name: p.name.to_string(),
+                    prompt: UserPrompt {
+                        prompt: Some(p.prompt.to_string()),
+                        description: Some(p.description.to_string()),
+                        chain: Vec::new(),
+                        by_language: p
+                            .by_language
+                            .iter()
+                            .map(|(language, text)| (language.to_string(), text.to_string()))
+                            .collect(),
+                    },
+                    source: PromptSource::Builtin,
+                })
+                .collect(),
+        };
+        for (name, prompt) in &user.prompts {
+            registry.set(name, prompt, PromptSource::User);
+        }
+        for p in project.iter().flat_map(|p| &p.prompts) {
+            registry.set(&p.name, &p.prompt, PromptSource::Project);
+        }
+
+        for entry in &registry.entries {
+            registry.collect_steps(&entry.name, None, &mut Vec::new(), &mut Vec::new())?;
+        }
+        Ok(registry)
+    }
+
+    /// Add a prompt, replacing one of the same name in place
+    fn set(&mut self, name: &str, prompt: &UserPrompt, source: PromptSource) {
+        let entry = RegistryEntry {
+            name: name.to_string(),
+            prompt: prompt.clone(),
+            source,
+        };
+        match self.entries.iter_mut().find(|e| e.name == name) {
+            Some(existing) => *existing = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    /// All prompts, in menu order
+    pub fn entries(&self) -> &[RegistryEntry] {
+        &self.entries
+    }
+
+    /// Look up a prompt by name
+    pub fn get(&self, name: &str) -> Option<&RegistryEntry> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+
+    /// Steps of the named prompt, following chains; None if there is no such prompt
+    ///
+    /// Each step uses its prompt's variant for `language` when it has one.
+    pub fn prompt_steps(&self, name: &str, language: Option<&str>) -> Option<Vec<PromptStep>> {
+        self.get(name)?;
+        let mut steps = Vec::new();
+        // Chains were validated when the registry was built
+        self.collect_steps(name, language, &mut Vec::new(), &mut steps)
+            .ok()?;
+        Some(steps)
+    }
+
+    /// Append the steps of `name` to `steps`; `stack` holds the chain being followed
+    fn collect_steps(
+        &self,
+        name: &str,
+        language: Option<&str>,
+        stack: &mut Vec<String>,
+        steps: &mut Vec<PromptStep>,
+    ) -> Result<()> {
+        if stack.iter().any(|s| s == name) {
+            bail!("Prompt chain cycle: {} -> {}", stack.join(" -> "), name);
+        }
+
+        let Some(entry) = self.get(name) else {
+            if stack.is_empty() {
+                bail!("Unknown prompt name: {}", name);
+            }
+            bail!(
+                "Prompt '{}' chains unknown prompt '{}'",
+                stack.join(" -> "),
+                name
+            );
+        };
+
+        let prompt = &entry.prompt;
+        if prompt.prompt.is_none() && prompt.chain.is_empty() {
+            bail!("Prompt '{}' needs a `prompt` or a `chain`", name);
+        }
+        if prompt.prompt.is_none() && !prompt.by_language.is_empty() {
+            bail!(
+                "Prompt '{}' has `by_language` variants but no `prompt` to fall back to",
+                name
+            );
+        }
+        if let Some(text) = prompt.text(language) {
+            steps.push(PromptStep {
+                name: name.to_string(),
+                text: text.to_string(),
+                from_project: entry.source == PromptSource::Project,
+            });
+        }
+
+        stack.push(name.to_string());
+        for next in &prompt.chain {
+            self.collect_steps(next, language, stack, steps)?;
+        }
+        stack.pop();
+        Ok(())
+    }
+}
+
+/// Resolve the `prompt` subcommand arguments to prompt steps (before expansion)
+///
+/// A named prompt is used only when `name_or_text` is exactly a prompt name
+/// (or `force_name` is set); extra text is appended on a new line to the first
+/// step. Anything else is raw text, so "explain why this fails" is not
+/// hijacked by "explain". Named prompts use their `language` variant if any.
+pub fn resolve_prompt(
+    registry: &PromptRegistry,
+    name_or_text: &str,
+    extra: &[String],
+    force_name: bool,
+    language: Option<&str>,
+) -> Result<Vec<PromptStep>> {
+    match registry.prompt_steps(name_or_text, language) {
+        Some(mut steps) => {
+            if let (Some(first), false) = (steps.first_mut(), extra.is_empty()) {
+                first.text = format!("{}\n{}", first.text, extra.join(" "));
+            }
+            Ok(steps)
+        }
+        None if force_name => bail!("Unknown prompt name: {}", name_or_text),
+        None => {
+            let mut parts = vec![name_or_text.to_string()];
+            parts.extend(extra.iter().cloned());
+            Ok(vec![PromptStep {
+                name: "text".to_string(),
+                text: parts.join(" "),
+                from_project: false,
+            }])
+        }
+    }
+}
+
+/// Convert the registry's prompts to select items, with their `language` variant
+/// Chained prompts go in the CHAINS category with their name as the value (not editable)
+#[cfg(feature = "tui")]
+pub fn prompts_to_select_items(
+    registry: &PromptRegistry,
+    language: Option<&str>,
+) -> Vec<SelectItem> {
+    registry
+        .entries()
         .iter()
-        .map(|p| SelectItem::new(p.name, p.description, p.prompt, "PROMPTS"))
+        .map(|entry| {
+            let prompt = &entry.prompt;
+            if prompt.chain.is_empty() {
+                SelectItem::new(
+                    &entry.name,
+                    prompt.description.as_deref().unwrap_or_default(),
+                    prompt.text(language).unwrap_or_default(),
+                    "PROMPTS",
+                )
+            } else {
+                let description = prompt
+                    .description
+                    .clone()
+                    .unwrap_or_else(|| format!("Chain: {}", prompt.chain.join(" → ")));
+                SelectItem::new(&entry.name, &description, &entry.name, "CHAINS").fixed()
+            }
+        })
         .collect()
 }
 
 /// Convert commands to select items
+#[cfg(feature = "tui")]
 pub fn commands_to_select_items(commands: &[crate::server::client::Command]) -> Vec<SelectItem> {
     commands
         .iter()
@@ -91,7 +828,52 @@ pub fn commands_to_select_items(commands: &[crate::server::client::Command]) ->
         .collect()
 }
 
+/// Format a millisecond timestamp as a relative age (e.g. "5m ago")
+#[cfg(feature = "tui")]
+pub fn format_relative_age(timestamp_ms: i64, now_ms: i64) -> String {
+    let secs = (now_ms - timestamp_ms).max(0) / 1000;
+    if secs < 60 {
+        "just now".to_string()
+    } else if secs < 3600 {
+        format!("{}m ago", secs / 60)
+    } else if secs < 86400 {
+        format!("{}h ago", secs / 3600)
+    } else {
+        format!("{}d ago", secs / 86400)
+    }
+}
+
+/// Convert sessions to select items, most recently active first
+#[cfg(feature = "tui")]
+pub fn sessions_to_select_items(
+    sessions: &[crate::server::client::Session],
+    limit: usize,
+    now_ms: i64,
+) -> Vec<SelectItem> {
+    let mut sorted: Vec<_> = sessions.iter().collect();
+    sorted.sort_by_key(|s| std::cmp::Reverse(s.last_activity()));
+
+    sorted
+        .into_iter()
+        .take(limit)
+        .map(|s| {
+            let title = if s.title.is_empty() {
+                s.id.as_str()
+            } else {
+                s.title.as_str()
+            };
+            let age = format_relative_age(s.last_activity(), now_ms);
+            let description = match s.message_count {
+                Some(count) => format!("{} · {} messages", age, count),
+                None => age,
+            };
+            SelectItem::new(title, &description, &s.id, "SESSIONS")
+        })
+        .collect()
+}
+
 /// Convert agents to select items
+#[cfg(feature = "tui")]
 pub fn agents_to_select_items(agents: &[crate::server::client::Agent]) -> Vec<SelectItem> {
     agents
         .iter()
@@ -106,3 +888,505 @@ pub fn agents_to_select_items(agents: &[crate::server::client::Agent]) -> Vec<Se
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "tui")]
+    use crate::server::client::{Session, SessionTime};
+
+    #[cfg(feature = "tui")]
+    fn session(id: &str, title: &str, updated: i64) -> Session {
+        Session {
+            id: id.to_string(),
+            title: title.to_string(),
+            time: SessionTime {
+                created: 0,
+                updated,
+            },
+            message_count: None,
+        }
+    }
+
+    #[test]
+    fn test_user_config_parse() {
+        let config = UserConfig::parse("notify_after_secs = 30").unwrap();
+        assert_eq!(config.notify_after_secs, Some(30));
+        assert!(!config.should_notify(false, 10));
+        assert!(config.should_notify(false, 30));
+        assert!(config.should_notify(true, 0));
+
+        let config = UserConfig::parse("").unwrap();
+        assert!(!config.should_notify(false, 1000));
+        assert!(UserConfig::parse("notify_after_secs = \"x\"").is_err());
+
+        assert_eq!(config.max_selection_lines(), 400);
+        let config = UserConfig::parse("max_selection_lines = 50").unwrap();
+        assert_eq!(config.max_selection_lines(), 50);
+        assert!(UserConfig::parse("max_selection_lines = 0").is_err());
+        assert_eq!(config.max_file_bytes(), 1024 * 1024);
+        let config = UserConfig::parse("max_file_bytes = 4096").unwrap();
+        assert_eq!(config.max_file_bytes(), 4096);
+        assert!(UserConfig::parse("max_file_bytes = 0").is_err());
+
+        assert_eq!(config.cursor_style, CursorStyle::Block);
+        let config = UserConfig::parse("cursor_style = \"reversed\"").unwrap();
+        assert_eq!(config.cursor_style, CursorStyle::Reversed);
+        assert!(UserConfig::parse("cursor_style = \"bar\"").is_err());
+        assert_eq!(config.cursor_shape, None);
+        let config = UserConfig::parse("cursor_shape = \"underline\"").unwrap();
+        assert_eq!(config.cursor_shape, Some(CursorShape::Underline));
+        assert!(UserConfig::parse("cursor_shape = \"reversed\"").is_err());
+
+        assert_eq!(config.dialog_effects, DialogEffects::Auto);
+        #[cfg(feature = "tui")]
+        {
+            assert!(config.dialog_effects.enabled(false));
+            assert!(!config.dialog_effects.enabled(true));
+            let config = UserConfig::parse("dialog_effects = \"on\"").unwrap();
+            assert!(config.dialog_effects.enabled(true));
+            let config = UserConfig::parse("dialog_effects = \"off\"").unwrap();
+            assert!(!config.dialog_effects.enabled(false));
+        }
+        assert!(UserConfig::parse("dialog_effects = true").is_err());
+
+        assert_eq!(config.chunk_size(), DEFAULT_CHUNK_SIZE);
+        let config = UserConfig::parse("chunk_size = 16384").unwrap();
+        assert_eq!(config.chunk_size(), 16384);
+        assert!(UserConfig::parse("chunk_size = 10").is_err());
+
+        #[cfg(feature = "tui")]
+        {
+            assert_eq!(config.tab_width(), DEFAULT_TAB_WIDTH);
+            assert_eq!(UserConfig::parse("tab_width = 8").unwrap().tab_width(), 8);
+        }
+        assert!(UserConfig::parse("tab_width = 0").is_err());
+
+        assert_eq!(config.keepalive(), Some(Keepalive::default()));
+        let config = UserConfig::parse("keepalive_secs = 5\nkeepalive_failures = 3").unwrap();
+        assert_eq!(
+            config.keepalive(),
+            Some(Keepalive {
+                interval: Duration::from_secs(5),
+                failures: 3
+            })
+        );
+        let config = UserConfig::parse("keepalive_secs = 0").unwrap();
+        assert_eq!(config.keepalive(), None);
+        assert!(UserConfig::parse("keepalive_failures = 0").is_err());
+
+        assert_eq!(
+            config.temp_file_max_age(),
+            Some(Duration::from_secs(24 * 60 * 60))
+        );
+        let config = UserConfig::parse("temp_file_max_age_hours = 2").unwrap();
+        assert_eq!(config.temp_file_max_age(), Some(Duration::from_secs(7200)));
+        let config = UserConfig::parse("temp_file_max_age_hours = 0").unwrap();
+        assert_eq!(config.temp_file_max_age(), None);
+
+        let config = UserConfig::parse("[strings]\nsend = \"送信\"").unwrap();
+        assert_eq!(config.strings["send"].as_str(), Some("送信"));
+        #[cfg(feature = "tui")]
+        assert!(UserConfig::parse("[strings]\nsned = \"送信\"").is_err());
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_quick_actions() {
+        let config = UserConfig::parse(
+            r#"
+            [quick_actions]
+            F3 = "@reviewer {input}"
+            F2 = "{input}\n\nContext: @diff"
+            "alt+1" = "Explain @this"
+            "#,
+        )
+        .unwrap();
+        let actions = config.quick_actions();
+        let keys: Vec<String> = actions.iter().map(|a| a.key.to_string()).collect();
+        assert_eq!(keys, ["F2", "F3", "Alt+1"]);
+        assert_eq!(actions[0].apply("why?"), "why?\n\nContext: @diff");
+        assert_eq!(actions[1].apply("check this"), "@reviewer check this");
+        assert_eq!(actions[0].label(), "Context: @diff");
+
+        for (text, offending) in [
+            ("[quick_actions]\nF13 = \"x\"", "F13"),
+            ("[quick_actions]\nF4 = \"{inptu} @diff\"", "F4"),
+            ("[quick_actions]\nF5 = \"  \"", "F5"),
+            ("[quick_actions]\nF6 = \"x\"\nf6 = \"y\"", "F6"),
+        ] {
+            let err = UserConfig::parse(text).unwrap_err().to_string();
+            assert!(err.contains(offending), "{}: {}", text, err);
+        }
+    }
+
+    /// Resolve with the default config and return the text of the single step
+    fn resolve_text(name_or_text: &str, extra: &[String], force_name: bool) -> Result<String> {
+        let steps = resolve_prompt(
+            &PromptRegistry::default(),
+            name_or_text,
+            extra,
+            force_name,
+            None,
+        )?;
+        assert_eq!(steps.len(), 1);
+        Ok(steps[0].text.clone())
+    }
+
+    #[test]
+    fn test_resolve_prompt_named() {
+        assert_eq!(
+            resolve_text("explain", &[], false).unwrap(),
+            "Explain how this code works: @this"
+        );
+        let extra = vec!["focus on".to_string(), "errors".to_string()];
+        assert_eq!(
+            resolve_text("explain", &extra, false).unwrap(),
+            "Explain how this code works: @this\nfocus on errors"
+        );
+        assert_eq!(
+            resolve_text("explain", &extra, true).unwrap(),
+            "Explain how this code works: @this\nfocus on errors"
+        );
+    }
+
+    #[test]
+    fn test_resolve_prompt_raw() {
+        // First word matches a prompt name, but the argument is not a single token
+        assert_eq!(
+            resolve_text("explain why this fails", &[], false).unwrap(),
+            "explain why this fails"
+        );
+        let extra = vec!["bug".to_string()];
+        assert_eq!(
+            resolve_text("Fix the", &extra, false).unwrap(),
+            "Fix the bug"
+        );
+        assert!(resolve_text("not-a-prompt", &[], true).is_err());
+    }
+
+    #[test]
+    fn test_prompt_chain_steps() {
+        let config = UserConfig::parse(
+            r#"
+            [prompts.review-fix]
+            chain = ["review", "fix"]
+
+            [prompts.full]
+            prompt = "Look at @this"
+            chain = ["review-fix", "tests"]
+            "#,
+        )
+        .unwrap();
+        let registry = PromptRegistry::new(&config, None).unwrap();
+
+        let names = |name: &str| -> Vec<String> {
+            registry
+                .prompt_steps(name, None)
+                .unwrap()
+                .into_iter()
+                .map(|s| s.name)
+                .collect()
+        };
+        assert_eq!(names("review-fix"), ["review", "fix"]);
+        assert_eq!(names("full"), ["full", "review", "fix", "tests"]);
+        assert_eq!(names("explain"), ["explain"]);
+        assert!(registry.prompt_steps("nope", None).is_none());
+
+        // Extra text goes on the first step only
+        let steps = resolve_prompt(
+            &registry,
+            "review-fix",
+            &["quickly".to_string()],
+            false,
+            None,
+        )
+        .unwrap();
+        assert!(steps[0].text.ends_with("\nquickly"));
+        assert!(!steps[1].text.contains("quickly"));
+
+        #[cfg(feature = "tui")]
+        {
+            let items = prompts_to_select_items(&registry, None);
+            let chain = items.iter().find(|i| i.name == "review-fix").unwrap();
+            assert_eq!(chain.category, "CHAINS");
+            assert_eq!(chain.value, "review-fix");
+            assert_eq!(chain.description, "Chain: review → fix");
+            assert!(!chain.editable);
+            assert!(items.iter().find(|i| i.name == "review").unwrap().editable);
+        }
+    }
+
+    #[test]
+    fn test_prompt_chain_validation() {
+        let err = UserConfig::parse(
+            r#"
+            [prompts.a]
+            chain = ["b"]
+            [prompts.b]
+            chain = ["explain", "a"]
+            "#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("cycle"), "{}", err);
+
+        // A user prompt overriding a built-in can't chain to itself
+        assert!(UserConfig::parse("[prompts.review]\nchain = [\"review\"]").is_err());
+        assert!(UserConfig::parse("[prompts.a]\nchain = [\"missing\"]").is_err());
+        assert!(UserConfig::parse("[prompts.a]\ndescription = \"empty\"").is_err());
+    }
+
+    #[test]
+    fn test_prompt_language_variants() {
+        let config = UserConfig::parse(
+            r#"
+            [prompts.bench]
+            prompt = "Benchmark @this"
+            [prompts.bench.by_language]
+            rust = "Benchmark @this with criterion"
+
+            [prompts.check]
+            chain = ["bench", "tests"]
+            "#,
+        )
+        .unwrap();
+        let registry = PromptRegistry::new(&config, None).unwrap();
+        let text = |name: &str, language: Option<&str>| {
+            resolve_prompt(&registry, name, &[], false, language).unwrap()[0]
+                .text
+                .clone()
+        };
+
+        // Exact match, else the base prompt
+        assert_eq!(
+            text("bench", Some("rust")),
+            "Benchmark @this with criterion"
+        );
+        assert_eq!(text("bench", Some("python")), "Benchmark @this");
+        assert_eq!(text("bench", Some("Rust")), "Benchmark @this");
+        assert_eq!(text("bench", None), "Benchmark @this");
+        // Built-in variants
+        assert_eq!(
+            text("tests", Some("python")),
+            "Write tests for this code using pytest: @this"
+        );
+        assert_eq!(
+            text("tests", Some("lua")),
+            "Write tests for this code: @this"
+        );
+
+        // Every step of a chain picks its own variant
+        let steps: Vec<String> = registry
+            .prompt_steps("check", Some("rust"))
+            .unwrap()
+            .into_iter()
+            .map(|s| s.text)
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                "Benchmark @this with criterion",
+                "Write tests for this code using #[test] functions: @this"
+            ]
+        );
+
+        #[cfg(feature = "tui")]
+        {
+            let items = prompts_to_select_items(&registry, Some("rust"));
+            let bench = items.iter().find(|i| i.name == "bench").unwrap();
+            assert_eq!(bench.value, "Benchmark @this with criterion");
+        }
+
+        // Project prompts take variants too
+        let docs = project(
+            r#"
+            [[prompts]]
+            name = "docs"
+            prompt = "Document @this"
+            by_language = { python = "Add docstrings to @this" }
+            "#,
+        );
+        let registry = PromptRegistry::new(&UserConfig::default(), Some(&docs)).unwrap();
+        let steps = registry.prompt_steps("docs", Some("python")).unwrap();
+        assert_eq!(steps[0].text, "Add docstrings to @this");
+
+        // Variants need a base prompt to fall back to
+        let err =
+            UserConfig::parse("[prompts.a]\nchain = [\"fix\"]\nby_language = { rust = \"x\" }")
+                .unwrap_err();
+        assert!(err.to_string().contains("no `prompt`"), "{}", err);
+    }
+
+    fn project(text: &str) -> ProjectConfig {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn test_registry_precedence() {
+        let user = UserConfig::parse(
+            r#"
+            [prompts.review]
+            prompt = "User review: @this"
+            [prompts.security]
+            prompt = "Security review: @this"
+            [prompts.mine]
+            prompt = "Mine"
+            "#,
+        )
+        .unwrap();
+        let project = project(
+            r#"
+            [[prompts]]
+            name = "review"
+            prompt = "Review against CONTRIBUTING.md: @this"
+            description = "Team review"
+
+            [[prompts]]
+            name = "security"
+            chain = ["review", "fix"]
+
+            [[prompts]]
+            name = "changelog"
+            prompt = "Write a changelog entry for @diff"
+            "#,
+        );
+        let registry = PromptRegistry::new(&user, Some(&project)).unwrap();
+
+        let source = |name: &str| registry.get(name).map(|e| e.source);
+        assert_eq!(source("explain"), Some(PromptSource::Builtin));
+        assert_eq!(source("mine"), Some(PromptSource::User));
+        assert_eq!(source("review"), Some(PromptSource::Project));
+        assert_eq!(source("changelog"), Some(PromptSource::Project));
+        assert_eq!(
+            registry.prompt_steps("review", None).unwrap()[0].text,
+            "Review against CONTRIBUTING.md: @this"
+        );
+        // A project chain replaces a user prompt and resolves to the project "review"
+        let steps = registry.prompt_steps("security", None).unwrap();
+        assert_eq!(steps[0].text, "Review against CONTRIBUTING.md: @this");
+        assert_eq!(steps.len(), 2);
+        // Each step says whether the repository wrote it
+        assert!(steps[0].from_project);
+        assert!(!steps[1].from_project);
+
+        // Overrides keep their place; additions come after the built-ins
+        let names: Vec<&str> = registry.entries().iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names[1], "review");
+        assert_eq!(
+            names[DEFAULT_PROMPTS.len()..],
+            ["mine", "security", "changelog"]
+        );
+
+        // Without the project layer, the user prompt applies
+        let registry = PromptRegistry::new(&user, None).unwrap();
+        assert_eq!(registry.get("review").unwrap().source, PromptSource::User);
+    }
+
+    #[test]
+    fn test_registry_disabled_prompts() {
+        let user = UserConfig::parse("disabled_prompts = [\"optimize\"]").unwrap();
+        let docs = project(
+            r#"
+            disabled_prompts = ["implement"]
+            [[prompts]]
+            name = "docs"
+            prompt = "Project docs: @this"
+            "#,
+        );
+        let registry = PromptRegistry::new(&user, Some(&docs)).unwrap();
+        assert!(registry.get("implement").is_none());
+        assert!(registry.get("optimize").is_none());
+        assert!(registry.prompt_steps("implement", None).is_none());
+        #[cfg(feature = "tui")]
+        {
+            let items = prompts_to_select_items(&registry, None);
+            assert!(!items
+                .iter()
+                .any(|i| i.name == "implement" || i.name == "optimize"));
+            assert_eq!(items.len(), DEFAULT_PROMPTS.len() - 2);
+        }
+
+        // Raw text is no longer hijacked by a disabled name
+        let steps = resolve_prompt(&registry, "implement", &[], false, None).unwrap();
+        assert_eq!(steps[0].name, "text");
+
+        // Disabling a non-built-in, or chaining to a disabled prompt, is an error
+        assert!(UserConfig::parse("disabled_prompts = [\"nope\"]").is_err());
+        let chains_disabled = project(
+            r#"
+            disabled_prompts = ["fix"]
+            [[prompts]]
+            name = "review-fix"
+            chain = ["review", "fix"]
+            "#,
+        );
+        let err = PromptRegistry::new(&UserConfig::default(), Some(&chains_disabled)).unwrap_err();
+        assert!(err.to_string().contains("unknown prompt 'fix'"), "{}", err);
+    }
+
+    #[test]
+    fn test_find_project_config() {
+        let root = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        let nested = root.join("src/deep");
+        std::fs::create_dir_all(&nested).unwrap();
+        assert!(ProjectConfig::load(&nested).unwrap().is_none());
+
+        std::fs::write(
+            root.join(PROJECT_CONFIG_FILE),
+            "disabled_prompts = [\"tests\"]",
+        )
+        .unwrap();
+        let found = ProjectConfig::load(&nested).unwrap();
+        let _ = std::fs::remove_dir_all(&root);
+        assert_eq!(found.unwrap().disabled_prompts, ["tests"]);
+    }
+
+    #[test]
+    fn test_project_defaults() {
+        let config = project(
+            r#"
+            default_agent = "architect"
+            default_model = "anthropic/claude-sonnet-4"
+            "#,
+        );
+        assert_eq!(config.default_agent.as_deref(), Some("architect"));
+        let model = config.default_model.unwrap();
+        assert_eq!(
+            (model.provider.as_str(), model.id.as_str()),
+            ("anthropic", "claude-sonnet-4")
+        );
+
+        let err = toml::from_str::<ProjectConfig>(r#"default_model = "claude""#).unwrap_err();
+        assert!(
+            err.to_string().contains("expected PROVIDER/MODEL"),
+            "{}",
+            err
+        );
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_format_relative_age() {
+        assert_eq!(format_relative_age(0, 30_000), "just now");
+        assert_eq!(format_relative_age(0, 5 * 60_000), "5m ago");
+        assert_eq!(format_relative_age(0, 3 * 3_600_000), "3h ago");
+        assert_eq!(format_relative_age(0, 2 * 86_400_000), "2d ago");
+        assert_eq!(format_relative_age(10, 0), "just now");
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_sessions_to_select_items() {
+        let sessions = vec![
+            session("a", "Old", 1_000),
+            session("b", "", 3_000),
+            session("c", "Middle", 2_000),
+        ];
+        let items = sessions_to_select_items(&sessions, 2, 3_000);
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].value, "b");
+        assert_eq!(items[0].name, "b"); // falls back to id without a title
+        assert_eq!(items[1].name, "Middle");
+        assert_eq!(items[1].category, "SESSIONS");
+    }
+}
@@ -3,7 +3,17 @@
 //! Maps Helix editor context to opencode format.
 
 use crate::cli::Cli;
+use crate::file_content;
+use crate::language;
+use crate::register::Registers;
+use crate::server::{MessageBuilder, MessagePart};
+use crate::workspace;
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::UNIX_EPOCH;
 
 /// Get clipboard text content
 fn get_clipboard_text() -> Option<String> {
@@ -13,8 +23,569 @@ fn get_clipboard_text() -> Option<String> {
         .filter(|s| !s.is_empty())
 }
 
+/// A source line with its 1-based line number
+#[cfg(feature = "tui")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumberedLine {
+    /// Line number in the file
+    pub number: u32,
+    /// Line text (without newline)
+    pub text: String,
+    /// Whether the line is inside the selection range
+    pub selected: bool,
+}
+
+/// A prompt with its placeholders expanded
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Expansion {
+    /// The expanded text
+    pub text: String,
+    /// Length in bytes of the value substituted for each placeholder
+    pub substituted: BTreeMap<String, usize>,
+    /// What became of each placeholder, for `--debug` and `context --explain`
+    pub report: ExpansionReport,
+}
+
+impl Expansion {
+    /// Replace every occurrence of `token` with `value`
+    fn replace(&mut self, token: &str, value: &str) {
+        self.replace_with(token, value, |text| text.replace(token, value));
+    }
+
+    /// Apply `replace`, recording `token` if it changed the text
+    fn replace_with(&mut self, token: &str, value: &str, replace: impl FnOnce(&str) -> String) {
+        let replaced = replace(&self.text);
+        if replaced != self.text {
+            self.substituted.insert(token.to_string(), value.len());
+            self.text = replaced;
+        }
+    }
+}
+
+/// What expansion did with a placeholder of a prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Replaced with its value
+    Expanded,
+    /// Left as typed: the context has no value for it
+    Unavailable,
+    /// Left as typed: a command placeholder that wasn't run
+    Disabled,
+    /// Left as typed: not a placeholder
+    Unknown,
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Expanded => "expanded",
+            Self::Unavailable => "left as typed (unavailable)",
+            Self::Disabled => "left as typed (disabled)",
+            Self::Unknown => "left as typed (unknown)",
+        })
+    }
+}
+
+/// One placeholder of an [`ExpansionReport`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportEntry {
+    /// The token as typed, e.g. `@diff:50`
+    pub token: String,
+    /// Byte offsets of its occurrences in the prompt
+    pub positions: Vec<usize>,
+    pub resolution: Resolution,
+    /// Bytes its expansion added to the prompt (negative if it got shorter)
+    pub delta: isize,
+}
+
+/// What expanding a prompt did with each of its placeholders, in the
+/// order they first appear, each once
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExpansionReport {
+    pub entries: Vec<ReportEntry>,
+}
+
+impl ExpansionReport {
+    /// Record `token` at `position`, adding `delta` to its size change
+    pub fn note(&mut self, token: &str, position: usize, resolution: Resolution, delta: isize) {
+        match self.entries.iter_mut().find(|entry| entry.token == token) {
+            Some(entry) => {
+                entry.positions.push(position);
+                entry.delta += delta;
+            }
+            None => self.entries.push(ReportEntry {
+                token: token.to_string(),
+                positions: vec![position],
+                resolution,
+                delta,
+            }),
+        }
+    }
+
+    /// The placeholders left as typed that are placeholders all the same:
+    /// those without a value and commands that weren't run
+    pub fn unexpanded(&self) -> impl Iterator<Item = &ReportEntry> {
+        self.entries.iter().filter(|entry| {
+            matches!(
+                entry.resolution,
+                Resolution::Unavailable | Resolution::Disabled
+            )
+        })
+    }
+
+    /// Add `other`, the report of the part of the prompt at byte `offset`
+    pub fn merge(&mut self, other: ExpansionReport, offset: usize) {
+        for entry in other.entries {
+            for (i, position) in entry.positions.into_iter().enumerate() {
+                let delta = if i == 0 { entry.delta } else { 0 };
+                self.note(&entry.token, offset + position, entry.resolution, delta);
+            }
+        }
+    }
+}
+
+impl fmt::Display for ExpansionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.entries.is_empty() {
+            return f.write_str("no placeholders");
+        }
+        for (i, entry) in self.entries.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            let positions: Vec<String> = entry.positions.iter().map(usize::to_string).collect();
+            write!(
+                f,
+                "{} at {}: {}",
+                entry.token,
+                positions.join(", "),
+                entry.resolution
+            )?;
+            if entry.resolution == Resolution::Expanded {
+                write!(f, ", {:+} bytes", entry.delta)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The `@word` that starts at byte `i` of `text`, if it could be a placeholder
+///
+/// The `@` starts a word and a lowercase letter follows, as in every
+/// placeholder, so `a@b.com` and `@Override` aren't words; nor is a file
+/// reference like `@src/main.rs`, which opencode resolves itself.
+pub fn mention_at(text: &str, i: usize) -> Option<&str> {
+    let before = text[..i].chars().next_back();
+    let starts_word =
+        before.is_none_or(|b| b.is_whitespace() || matches!(b, '(' | '[' | '{' | '"' | '\''));
+    let rest = text[i..].strip_prefix('@')?;
+    if !starts_word || !rest.starts_with(|n: char| n.is_ascii_lowercase()) {
+        return None;
+    }
+    let len = rest
+        .find(|n: char| !(n.is_alphanumeric() || matches!(n, '_' | '-' | ':' | '.' | '/')))
+        .unwrap_or(rest.len());
+    let word = rest[..len].trim_end_matches(['.', '-']);
+    if word.contains(['/', '.']) {
+        return None;
+    }
+    Some(&text[i..i + 1 + word.len()])
+}
+
+/// The placeholder token of [`PlaceholderKind::ALL`] at the start of
+/// `text`, matched as expansion matches it
+fn token_at(text: &str) -> Option<&str> {
+    if let Some(after) = text.strip_prefix("@diff:") {
+        let digits = after
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(after.len());
+        if after[..digits].parse::<usize>().is_ok() {
+            return Some(&text[.."@diff:".len() + digits]);
+        }
+    }
+    PlaceholderKind::ALL.into_iter().find_map(|kind| {
+        let after = text.strip_prefix(kind.name())?;
+        match kind {
+            PlaceholderKind::DiffLimited => None,
+            // Not part of a longer word, as in `replace_bare_token`
+            PlaceholderKind::Alt | PlaceholderKind::Diff
+                if after.starts_with(|c: char| c.is_alphanumeric() || c == '_' || c == ':') =>
+            {
+                None
+            }
+            _ => Some(kind.name()),
+        }
+    })
+}
+
+/// A placeholder prompts can reference, expanded from the editor context
+///
+/// Expansion, the placeholders panel and autocomplete all go through
+/// [`PlaceholderKind::ALL`], so a new placeholder is registered here only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PlaceholderKind {
+    This,
+    Buffer,
+    Path,
+    Project,
+    Branch,
+    Blame,
+    SelectionFull,
+    Selection,
+    AltInline,
+    Alt,
+    Diffstat,
+    DiffLimited,
+    Diff,
+    Clipboard,
+}
+
+impl PlaceholderKind {
+    /// Every placeholder, in expansion order
+    /// A token comes before any token it is a prefix of (`@selection:full`
+    /// before `@selection`, `@alt:inline` before `@alt`, `@diffstat` and
+    /// `@diff:N` before `@diff`).
+    pub const ALL: [Self; 14] = [
+        Self::This,
+        Self::Buffer,
+        Self::Path,
+        Self::Project,
+        Self::Branch,
+        Self::Blame,
+        Self::SelectionFull,
+        Self::Selection,
+        Self::AltInline,
+        Self::Alt,
+        Self::Diffstat,
+        Self::DiffLimited,
+        Self::Diff,
+        Self::Clipboard,
+    ];
+
+    /// Token as typed in a prompt (`@diff:N` is shown with an example limit)
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::This => "@this",
+            Self::Buffer => "@buffer",
+            Self::Path => "@path",
+            Self::Project => "@project",
+            Self::Branch => "@branch",
+            Self::Blame => "@blame",
+            Self::SelectionFull => "@selection:full",
+            Self::Selection => "@selection",
+            Self::AltInline => "@alt:inline",
+            Self::Alt => "@alt",
+            Self::Diffstat => "@diffstat",
+            Self::DiffLimited => "@diff:200",
+            Self::Diff => "@diff",
+            Self::Clipboard => "@clipboard",
+        }
+    }
+
+    /// What the placeholder expands to
+    pub fn describe(self) -> &'static str {
+        match self {
+            Self::This => "current file with the cursor or selection position",
+            Self::Buffer => "current file",
+            Self::Path => "absolute path of the current file",
+            Self::Project => "name of the git repository",
+            Self::Branch => "current git branch (the commit when detached)",
+            Self::Blame => "commits that last changed the selected lines (git blame)",
+            Self::SelectionFull => "selected text, never shortened",
+            Self::Selection => "selected text with its location",
+            Self::AltInline => "lines around --alt-line of the alternate file",
+            Self::Alt => "alternate file (--alt-file) with the --alt-line position",
+            Self::Diffstat => "summary of uncommitted changes",
+            Self::DiffLimited => "git diff, at most N lines",
+            Self::Diff => "uncommitted changes (git diff)",
+            Self::Clipboard => "system clipboard",
+        }
+    }
+
+    /// Whether the context has a value for the placeholder
+    /// `@selection:full` only counts when `@selection` would be shortened.
+    #[cfg(feature = "tui")]
+    pub fn available(self, ctx: &Context) -> bool {
+        match self {
+            Self::This | Self::Buffer => ctx.file.is_some(),
+            Self::Path => ctx.file_absolute.is_some(),
+            Self::Selection => ctx.selection.is_some() && ctx.file.is_some(),
+            Self::SelectionFull => ctx.selection_omitted_lines().is_some() && ctx.file.is_some(),
+            Self::Alt => ctx.alt_file.is_some(),
+            Self::AltInline
+            | Self::Project
+            | Self::Branch
+            | Self::Blame
+            | Self::Diffstat
+            | Self::DiffLimited
+            | Self::Diff
+            | Self::Clipboard => self.expand(ctx).is_some(),
+        }
+    }
+
+    /// Value substituted for [`name`](Self::name), or None if unavailable
+    pub fn expand(self, ctx: &Context) -> Option<String> {
+        match self {
+            Self::This => ctx.format_this().map(|v| ctx.mark_stale(v)),
+            Self::Buffer => ctx.format_buffer().map(|v| ctx.mark_stale(v)),
+            Self::Path => ctx.file_absolute.clone(),
+            Self::Project => ctx.format_project(),
+            Self::Branch => ctx.format_branch(),
+            Self::Blame => ctx.format_blame().map(|v| ctx.mark_stale(v)),
+            Self::SelectionFull => ctx.format_selection_full(),
+            Self::Selection => ctx.format_selection(),
+            Self::AltInline => ctx.format_alt_inline(),
+            Self::Alt => ctx.format_alt(),
+            Self::Diffstat => ctx.format_diffstat(),
+            Self::DiffLimited => ctx.format_diff_limited(DEFAULT_DIFF_LIMIT),
+            Self::Diff => ctx.format_diff(),
+            Self::Clipboard => get_clipboard_text(),
+        }
+    }
+
+    /// The placeholders panel's preview of the placeholder, and its value
+    #[cfg(feature = "tui")]
+    pub fn panel_entry(self, ctx: &Context) -> (String, Option<String>) {
+        let value = self.expand(ctx);
+        (self.preview(ctx, value.as_deref()), value)
+    }
+
+    /// Replace the placeholder in `expansion`; values are only computed when
+    /// it appears, and not at all when `known`
+    fn substitute(self, ctx: &Context, known: &Known, expansion: &mut Expansion) {
+        let value = || match known.get(&self) {
+            Some(value) => value.clone(),
+            None => self.expand(ctx),
+        };
+        match self {
+            // Any limit, not just the one in the name
+            Self::DiffLimited => {
+                if expansion.text.contains("@diff:") {
+                    let mut limited = Vec::new();
+                    expansion.text = replace_diff_limits(&expansion.text, |max| {
+                        let diff = ctx.format_diff_limited(max)?;
+                        limited.push((format!("@diff:{}", max), diff.len()));
+                        Some(diff)
+                    });
+                    expansion.substituted.extend(limited);
+                }
+            }
+            // Leaves @alt:inline, and words like @alternative, alone
+            Self::Alt => {
+                if expansion.text.contains("@alt") {
+                    if let Some(alt) = value() {
+                        expansion.replace_with("@alt", &alt, |text| {
+                            replace_bare_token(text, "@alt", &alt)
+                        });
+                    }
+                }
+            }
+            // Leaves unexpanded @diffstat / @diff:N alone
+            Self::Diff => {
+                if expansion.text.contains("@diff") {
+                    if let Some(diff) = value() {
+                        expansion.replace_with("@diff", &diff, |text| {
+                            replace_bare_token(text, "@diff", &diff)
+                        });
+                    }
+                }
+            }
+            _ => {
+                if expansion.text.contains(self.name()) {
+                    if let Some(value) = value() {
+                        expansion.replace(self.name(), &value);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Short summary of `value` for the placeholders panel
+    #[cfg(feature = "tui")]
+    fn preview(self, ctx: &Context, value: Option<&str>) -> String {
+        let Some(value) = value else {
+            return match self {
+                Self::This | Self::Buffer | Self::Path => "(no file context)",
+                Self::Project | Self::Branch => "(not a git repository)",
+                Self::Blame if ctx.file_absolute.is_none() => "(no file context)",
+                Self::Blame => "(no committed lines here)",
+                Self::Selection | Self::SelectionFull => "(no selection)",
+                Self::AltInline if ctx.alt_file.is_some() => "(can't read the file)",
+                Self::Alt | Self::AltInline => "(no alternate file)",
+                Self::Diffstat | Self::DiffLimited | Self::Diff => {
+                    return format!("({})", ctx.diff_status().reason().unwrap_or("no git diff"));
+                }
+                Self::Clipboard => "(empty)",
+            }
+            .to_string();
+        };
+        match self {
+            Self::This | Self::Buffer | Self::Path | Self::Project | Self::Branch | Self::Alt => {
+                value.to_string()
+            }
+            // The location line
+            Self::AltInline => preview(value),
+            Self::Selection => {
+                let sel = ctx.selection.as_deref().unwrap_or_default();
+                let truncated = if ctx.selection_omitted_lines().is_some() {
+                    " (truncated)"
+                } else {
+                    ""
+                };
+                format!(
+                    "{} lines{}: {}",
+                    sel.lines().count(),
+                    truncated,
+                    preview(sel)
+                )
+            }
+            Self::SelectionFull => {
+                let lines = ctx.selection.as_deref().unwrap_or_default().lines().count();
+                format!("all {} lines", lines)
+            }
+            Self::Diffstat => value
+                .lines()
+                .last()
+                .map(|l| l.trim().to_string())
+                .unwrap_or_default(),
+            Self::Blame => format!(
+                "{} commits: {}",
+                value.lines().filter(|l| l.starts_with("- ")).count(),
+                preview(value)
+            ),
+            Self::DiffLimited => self.describe().to_string(),
+            Self::Diff => format!("{} lines of changes", value.lines().count()),
+            Self::Clipboard => format!("{} lines: {}", value.lines().count(), preview(value)),
+        }
+    }
+}
+
+/// Placeholder values computed already, e.g. for the placeholders panel
+pub type Known = BTreeMap<PlaceholderKind, Option<String>>;
+
+/// Placeholders sent as file parts of a session message, longest name first
+/// so `@selection:full` isn't taken for `@selection`
+const ATTACHMENTS: [PlaceholderKind; 3] = [
+    PlaceholderKind::SelectionFull,
+    PlaceholderKind::Selection,
+    PlaceholderKind::Buffer,
+];
+
+/// Most commits `@blame` lists, the ones behind the most lines
+const MAX_BLAME_COMMITS: usize = 10;
+
+/// Line limit of the `@diff:N` example offered in the placeholders panel
+const DEFAULT_DIFF_LIMIT: usize = 200;
+
+/// Lines `@alt:inline` shows on each side of `--alt-line`
+const ALT_RADIUS: u32 = 20;
+
+/// Longest selection `@selection` expands in full, unless configured
+pub const DEFAULT_MAX_SELECTION_LINES: usize = 400;
+
+/// `--selection-file` that reads the selection from stdin
+pub const STDIN: &str = "-";
+
+/// Appended to placeholders that make opencode read the file, when the buffer may differ
+pub const STALE_MARKER: &str = "[note: file may be stale]";
+
+/// Outcome of asking git for a diff, saying why there is none
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffResult {
+    Diff(String),
+    /// No `git` on the PATH
+    GitMissing,
+    /// Neither the file nor the project is in a git repository
+    NotARepo,
+    /// A repository with nothing uncommitted
+    NoChanges,
+}
+
+impl DiffResult {
+    pub fn into_diff(self) -> Option<String> {
+        match self {
+            Self::Diff(diff) => Some(diff),
+            _ => None,
+        }
+    }
+
+    /// Why there is no diff, or None if there is one
+    pub fn reason(&self) -> Option<&'static str> {
+        match self {
+            Self::Diff(_) => None,
+            Self::GitMissing => Some("git is not installed"),
+            Self::NotARepo => Some("not a git repository"),
+            Self::NoChanges => Some("no uncommitted changes"),
+        }
+    }
+}
+
+/// A problem with the editor context given on the command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContextWarning {
+    /// Selection text (or one end of the range) without both range flags;
+    /// `inferred` is the range assumed from the selection's line count
+    IncompleteRange { inferred: Option<(u32, u32)> },
+    /// The selection range ends before it starts, so it is ignored
+    ReversedRange { start: u32, end: u32 },
+    /// The buffer has unsaved changes (or the file changed on disk since
+    /// Helix read it), so opencode sees different content than the editor
+    StaleFile,
+    /// A --register-file couldn't be read, so `@reg:NAME` stays as typed
+    UnreadableRegister {
+        name: String,
+        path: PathBuf,
+        error: String,
+    },
+    /// A file read for the context couldn't be deleted, so its contents stay
+    /// on disk
+    Undeleted { path: PathBuf, error: String },
+}
+
+impl fmt::Display for ContextWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IncompleteRange { inferred } => {
+                write!(
+                    f,
+                    "the selection has no line range; add --selection-start \
+                     %{{selection_line_start}} --selection-end %{{selection_line_end}} \
+                     to the keybinding"
+                )?;
+                match inferred {
+                    Some((start, end)) => write!(
+                        f,
+                        " (assuming L{}-L{} from the selection's line count)",
+                        start, end
+                    ),
+                    None => write!(f, " (@this falls back to the cursor or the whole file)"),
+                }
+            }
+            Self::ReversedRange { start, end } => write!(
+                f,
+                "ignoring selection range L{}-L{}: it ends before it starts",
+                start, end
+            ),
+            Self::StaleFile => write!(f, "buffer has unsaved changes — disk content will be used"),
+            Self::UnreadableRegister { name, path, error } => write!(
+                f,
+                "can't read {} for @reg:{} ({}); it is sent as typed",
+                path.display(),
+                name,
+                error
+            ),
+            Self::Undeleted { path, error } => write!(
+                f,
+                "can't delete {} after reading it ({}); remove it yourself",
+                path.display(),
+                error
+            ),
+        }
+    }
+}
+
 /// Editor context captured from Helix
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct Context {
     /// Current file path (relative)
     pub file: Option<String>,
@@ -37,22 +608,106 @@ pub struct Context {
     /// Selection end line (1-based)
     pub selection_end: Option<u32>,
 
-    /// File language
+    /// Alternate file for `@alt`, e.g. the buffer looked at last (relative)
+    pub alt_file: Option<String>,
+
+    /// Alternate file path (absolute)
+    pub alt_file_absolute: Option<String>,
+
+    /// Line of interest in the alternate file (1-based)
+    pub alt_line: Option<u32>,
+
+    /// File language (from --language)
     pub language: Option<String>,
+
+    /// Helix registers (`@reg:NAME`), from --register and --register-file
+    pub registers: Registers,
+
+    /// Language guessed from the file when --language isn't given
+    detected_language: Option<&'static str>,
+
+    /// Project working directory (git commands run here)
+    pub cwd: Option<PathBuf>,
+
+    /// The file on disk may not match the buffer (--modified or --buffer-mtime)
+    stale: bool,
+
+    /// Longer selections are shortened to their first and last lines by `@selection`
+    pub max_selection_lines: usize,
+
+    /// Larger files aren't read for the selection preview
+    max_file_bytes: u64,
+
+    /// Git toplevel of the current file (or the project), looked up once
+    git_toplevel: OnceLock<Option<PathBuf>>,
+
+    /// `@branch` value, looked up once
+    branch: OnceLock<Option<String>>,
+
+    /// `@blame` value, looked up once
+    blame: OnceLock<Option<String>>,
+
+    /// Problems found (and worked around) when reading the command line
+    warnings: Vec<ContextWarning>,
+
+    /// Parts sent after the prompt's in a session message (images pasted
+    /// into the ask dialog)
+    pub attached: Vec<MessagePart>,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self {
+            file: None,
+            file_absolute: None,
+            line: None,
+            column: None,
+            selection: None,
+            selection_start: None,
+            selection_end: None,
+            alt_file: None,
+            alt_file_absolute: None,
+            alt_line: None,
+            language: None,
+            registers: Registers::default(),
+            detected_language: None,
+            cwd: None,
+            stale: false,
+            max_selection_lines: DEFAULT_MAX_SELECTION_LINES,
+            max_file_bytes: file_content::DEFAULT_MAX_BYTES,
+            git_toplevel: OnceLock::new(),
+            branch: OnceLock::new(),
+            blame: OnceLock::new(),
+            warnings: Vec::new(),
+            attached: Vec::new(),
+        }
+    }
 }
 
 impl Context {
     /// Create context from CLI arguments
-    pub fn from_cli(cli: &Cli) -> Self {
-        let selection = cli.selection_file.as_ref().and_then(|path| {
-            let content = fs::read_to_string(path).ok();
-            // Clean up the temp file after reading
-            let _ = fs::remove_file(path);
-            content
-        });
+    ///
+    /// Register files over `max_file_bytes`, or binary, are replaced by a
+    /// marker (see [`file_content`]).
+    pub fn from_cli(cli: &Cli, max_file_bytes: u64) -> Self {
+        let mut warnings = Vec::new();
+        let selection = match cli.selection_file {
+            Some(ref path) if path.as_os_str() == STDIN => {
+                std::io::read_to_string(std::io::stdin()).ok()
+            }
+            Some(ref path) => {
+                let content = fs::read_to_string(path).ok();
+                // Clean up the temp file after reading, unless a loop keeps it
+                if !cli.keep_selection_file {
+                    remove_read_file(path, &mut warnings);
+                }
+                content
+            }
+            None => cli.selection_text.clone(),
+        };
 
-        // Get absolute path from relative file path
-        let file_absolute = cli.file.as_ref().and_then(|p| {
+        // Get absolute paths from relative file paths
+        let absolute = |p: &PathBuf| {
             if p.is_absolute() {
                 Some(p.display().to_string())
             } else {
@@ -64,17 +719,141 @@ impl Context {
                     .or_else(|| std::env::current_dir().ok());
                 base.map(|b| b.join(p).display().to_string())
             }
+        };
+        let file_absolute = cli.file.as_ref().and_then(absolute);
+        let alt_file_absolute = cli.alt_file.as_ref().and_then(absolute);
+        // Relative to the project found above --file, as opencode runs there
+        let shown = |p: &PathBuf, absolute: Option<&String>| {
+            let relative = cli
+                .workspace
+                .as_deref()
+                .zip(absolute)
+                .and_then(|(root, abs)| workspace::relative(Path::new(abs), root));
+            relative.as_deref().unwrap_or(p).display().to_string()
+        };
+
+        let detected_language = match cli.language {
+            Some(_) => None,
+            None => file_absolute
+                .as_deref()
+                .and_then(|path| language::detect(Path::new(path))),
+        };
+
+        let stale = file_absolute.as_deref().is_some_and(|path| {
+            is_stale(file_mtime(Path::new(path)), cli.buffer_mtime, cli.modified)
         });
 
-        Self {
-            file: cli.file.as_ref().map(|p| p.display().to_string()),
+        let mut registers = Registers::default();
+        for arg in &cli.registers {
+            registers.insert(&arg.name, arg.value.clone());
+        }
+        for arg in &cli.register_files {
+            let path = Path::new(&arg.value);
+            match file_content::read_text_guarded(path, max_file_bytes) {
+                Ok(content) => registers.insert(&arg.name, content.into_prompt_text(path)),
+                Err(e) => warnings.push(ContextWarning::UnreadableRegister {
+                    name: arg.name.clone(),
+                    path: path.to_path_buf(),
+                    error: e.to_string(),
+                }),
+            }
+            remove_read_file(path, &mut warnings);
+        }
+
+        let mut ctx = Self {
+            file: cli.file.as_ref().map(|p| shown(p, file_absolute.as_ref())),
             file_absolute,
             line: cli.line,
             column: cli.column,
             selection,
             selection_start: cli.selection_start,
             selection_end: cli.selection_end,
+            alt_file: cli
+                .alt_file
+                .as_ref()
+                .map(|p| shown(p, alt_file_absolute.as_ref())),
+            alt_file_absolute,
+            alt_line: cli.alt_line,
             language: cli.language.clone(),
+            registers,
+            detected_language,
+            cwd: Some(cli.working_directory()),
+            stale,
+            max_selection_lines: DEFAULT_MAX_SELECTION_LINES,
+            max_file_bytes,
+            git_toplevel: OnceLock::new(),
+            branch: OnceLock::new(),
+            blame: OnceLock::new(),
+            warnings,
+            attached: Vec::new(),
+        };
+        ctx.normalize_range();
+        if stale {
+            ctx.warnings.push(ContextWarning::StaleFile);
+        }
+        ctx
+    }
+
+    /// Whether the file on disk may not match what the buffer shows
+    #[cfg(feature = "tui")]
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    /// `value` with [`STALE_MARKER`] after it when the file may be stale
+    fn mark_stale(&self, value: String) -> String {
+        if self.stale {
+            format!("{} {}", value, STALE_MARKER)
+        } else {
+            value
+        }
+    }
+
+    /// File language: --language if given, else the one guessed from the file
+    pub fn language_or_detected(&self) -> Option<&str> {
+        self.language.as_deref().or(self.detected_language)
+    }
+
+    /// Problems with the command line context, and how they were worked around
+    pub fn validate(&self) -> Vec<ContextWarning> {
+        self.warnings.clone()
+    }
+
+    /// Complete a partial selection range, then drop it if it is reversed
+    ///
+    /// A missing end is inferred from the selection text's line count,
+    /// anchored at the given end of the range or else at `--line`. Without
+    /// either, `@this` falls back to the cursor position or the file.
+    fn normalize_range(&mut self) {
+        let lines = self
+            .selection
+            .as_deref()
+            .map(|sel| sel.lines().count().max(1) as u32);
+        match (self.selection_start, self.selection_end) {
+            (Some(_), Some(_)) => {}
+            (None, None) if lines.is_none() => {}
+            (start, end) => {
+                let inferred = lines.and_then(|lines| match (start, end) {
+                    (Some(start), _) => Some((start, start + lines - 1)),
+                    (None, Some(end)) => Some((end.saturating_sub(lines - 1).max(1), end)),
+                    (None, None) => self.line.map(|line| (line, line + lines - 1)),
+                });
+                if let Some((start, end)) = inferred {
+                    self.selection_start = Some(start);
+                    self.selection_end = Some(end);
+                }
+                self.warnings
+                    .push(ContextWarning::IncompleteRange { inferred });
+            }
+        }
+
+        if let (Some(start), Some(end)) = (self.selection_start, self.selection_end) {
+            if start > end {
+                self.selection_start = None;
+                self.selection_end = None;
+                self.warnings
+                    .push(ContextWarning::ReversedRange { start, end });
+            }
         }
     }
 
@@ -116,158 +895,707 @@ impl Context {
     }
 
     /// Format @selection context - includes the selection text
+    /// Selections over `max_selection_lines` keep only their first and last lines.
     /// Returns None if no selection is available
     pub fn format_selection(&self) -> Option<String> {
-        // Only return something if we have actual selection content
+        let sel = self.selection.as_ref()?;
+        self.format_selection_text(&head_tail_lines(sel, self.max_selection_lines))
+    }
+
+    /// Format @selection:full context - the selection text however long it is
+    pub fn format_selection_full(&self) -> Option<String> {
+        self.format_selection_text(self.selection.as_ref()?)
+    }
+
+    /// Number of selection lines `@selection` leaves out, if it shortens the selection
+    #[cfg(feature = "tui")]
+    pub fn selection_omitted_lines(&self) -> Option<usize> {
+        let total = self.selection.as_ref()?.lines().count();
+        (total > self.max_selection_lines).then(|| total - self.max_selection_lines)
+    }
+
+    /// Selection text in a fenced block after its location
+    /// Returns None without a location, so @selection is left as-is
+    fn format_selection_text(&self, text: &str) -> Option<String> {
+        let location = self.format_location()?;
+        Some(format!("{}\n```\n{}\n```", location, text))
+    }
+
+    /// Format @alt - the alternate file with its line, e.g. `@src/lib.rs L80`
+    pub fn format_alt(&self) -> Option<String> {
+        let file = self.alt_file.as_ref()?;
+        Some(match self.alt_line {
+            Some(line) => format!("@{} L{}", file, line),
+            None => format!("@{}", file),
+        })
+    }
+
+    /// Format @alt:inline - the lines around `--alt-line` (the start of the
+    /// file without one) in a fenced block after their location
+    ///
+    /// The window is shortened like a selection past `max_selection_lines`;
+    /// a binary or too large file gives its marker instead of lines.
+    pub fn format_alt_inline(&self) -> Option<String> {
+        let file = self.alt_file.as_ref()?;
+        let path = Path::new(self.alt_file_absolute.as_ref()?);
+        let content = match file_content::read_text_guarded(path, self.max_file_bytes).ok()? {
+            file_content::FileContent::Text { text, .. } => text,
+            other => return Some(format!("@{}\n{}", file, other.into_prompt_text(path))),
+        };
+        let lines: Vec<&str> = content.lines().collect();
+        let Some((start, end)) = alt_window(self.alt_line, lines.len(), ALT_RADIUS) else {
+            return Some(format!("@{} (empty file)", file));
+        };
+        let window = lines[start as usize - 1..end as usize].join("\n");
+        Some(format!(
+            "@{} L{}-L{}\n```\n{}\n```",
+            file,
+            start,
+            end,
+            head_tail_lines(&window, self.max_selection_lines)
+        ))
+    }
+
+    /// Numbered lines of the selection, at most `max_lines`
+    ///
+    /// Uses the captured selection text when present; otherwise reads the
+    /// selection range from the file, with one line of context on each side
+    /// (None for a binary or too large file).
+    #[cfg(feature = "tui")]
+    pub fn selection_lines(&self, max_lines: usize) -> Option<Vec<NumberedLine>> {
         if let Some(ref sel) = self.selection {
-            let location = self.format_location()?;
-            Some(format!("{}\n```\n{}\n```", location, sel))
-        } else {
-            // No selection available - return None so @selection is not replaced
-            None
+            let first = self.selection_start.or(self.line).unwrap_or(1);
+            let lines = sel
+                .lines()
+                .take(max_lines)
+                .zip(first..)
+                .map(|(text, number)| NumberedLine {
+                    number,
+                    text: text.to_string(),
+                    selected: true,
+                })
+                .collect();
+            return Some(lines);
         }
+
+        let (start, end) = (self.selection_start?, self.selection_end?);
+        let path = Path::new(self.file_absolute.as_ref()?);
+        let file_content::FileContent::Text { text: content, .. } =
+            file_content::read_text_guarded(path, self.max_file_bytes).ok()?
+        else {
+            return None;
+        };
+        let first = start.saturating_sub(1).max(1);
+        let lines: Vec<NumberedLine> = content
+            .lines()
+            .zip(1u32..)
+            .skip(first as usize - 1)
+            .take_while(|(_, number)| *number <= end + 1)
+            .take(max_lines)
+            .map(|(text, number)| NumberedLine {
+                number,
+                text: text.to_string(),
+                selected: (start..=end).contains(&number),
+            })
+            .collect();
+        (!lines.is_empty()).then_some(lines)
     }
 
-    /// Get git diff output
-    pub fn format_diff(&self) -> Option<String> {
-        std::process::Command::new("git")
-            .args(["--no-pager", "diff"])
-            .output()
-            .ok()
-            .and_then(|output| {
-                if output.status.success() {
-                    let diff = String::from_utf8_lossy(&output.stdout).to_string();
-                    if diff.is_empty() {
-                        None
-                    } else {
-                        Some(diff)
-                    }
-                } else {
-                    None
-                }
+    /// Toplevel of the git repository containing the current file, or else the project cwd
+    fn git_toplevel(&self) -> Option<&Path> {
+        self.git_toplevel
+            .get_or_init(|| {
+                let file_dir = self
+                    .file_absolute
+                    .as_deref()
+                    .and_then(|f| Path::new(f).parent())
+                    .filter(|dir| dir.is_dir());
+                file_dir
+                    .and_then(|dir| run_git(Some(dir), &["rev-parse", "--show-toplevel"]))
+                    .or_else(|| run_git(self.cwd.as_deref(), &["rev-parse", "--show-toplevel"]))
+                    .map(|top| PathBuf::from(top.trim_end()))
             })
+            .as_deref()
     }
 
-    /// Expand context placeholders in a prompt
-    pub fn expand(&self, prompt: &str) -> String {
-        let mut result = prompt.to_string();
+    /// Directory git commands run in
+    ///
+    /// The toplevel of the repository containing the current file, so a
+    /// worktree or submodule is diffed rather than whatever checkout the
+    /// project cwd points at; falls back to the project cwd.
+    fn git_dir(&self) -> Option<PathBuf> {
+        self.git_toplevel()
+            .map(Path::to_path_buf)
+            .or_else(|| self.cwd.clone())
+    }
 
-        // Replace @this
-        if let Some(this) = self.format_this() {
-            result = result.replace("@this", &this);
-        }
+    /// Run a git command in the repository of the current file (or the project directory)
+    /// Returns None if git fails or produces no output
+    fn git_command(&self, args: &[&str]) -> Option<String> {
+        run_git(self.git_dir().as_deref(), args)
+    }
 
-        // Replace @buffer
-        if let Some(buffer) = self.format_buffer() {
-            result = result.replace("@buffer", &buffer);
+    /// `git diff` with `args`, or why it shows nothing
+    ///
+    /// The cause is only looked into when git gives no output.
+    fn git_diff(&self, args: &[&str]) -> DiffResult {
+        if let Some(diff) = self.git_command(args) {
+            DiffResult::Diff(diff)
+        } else if !git_installed() {
+            DiffResult::GitMissing
+        } else if self.git_toplevel().is_none() {
+            DiffResult::NotARepo
+        } else {
+            DiffResult::NoChanges
         }
+    }
 
-        // Replace @path (absolute file path)
-        if let Some(ref path) = self.file_absolute {
-            result = result.replace("@path", path);
-        }
+    /// Whether `@diff` has something to expand to, and why not
+    pub fn diff_status(&self) -> DiffResult {
+        self.git_diff(&["diff"])
+    }
 
-        // Replace @selection
-        if let Some(selection) = self.format_selection() {
-            result = result.replace("@selection", &selection);
-        }
+    /// Why the diff placeholders in `prompt` are left as typed, if they are
+    /// e.g. `@diff not expanded: not a git repository`
+    pub fn unexpanded_diff(&self, prompt: &str) -> Option<String> {
+        let token = diff_token(prompt)?;
+        let reason = self.diff_status().reason()?;
+        Some(format!("{} not expanded: {}", token, reason))
+    }
 
-        // Replace @diff
-        if result.contains("@diff") {
-            if let Some(diff) = self.format_diff() {
-                result = result.replace("@diff", &diff);
-            }
+    /// Why `entry`, one of [`ExpansionReport::unexpanded`], was left as
+    /// typed, e.g. `no selection`
+    pub fn why_unexpanded(&self, entry: &ReportEntry) -> &'static str {
+        if entry.resolution == Resolution::Disabled {
+            return "command not run";
         }
-
-        // Replace @clipboard
-        if result.contains("@clipboard") {
-            if let Some(clipboard) = get_clipboard_text() {
-                result = result.replace("@clipboard", &clipboard);
+        if entry.token.starts_with(crate::register::PREFIX) {
+            return "register not given";
+        }
+        let kind = if entry.token.starts_with("@diff:") {
+            Some(PlaceholderKind::DiffLimited)
+        } else {
+            PlaceholderKind::ALL
+                .into_iter()
+                .find(|kind| kind.name() == entry.token)
+        };
+        let Some(kind) = kind else {
+            return "no value";
+        };
+        match kind {
+            PlaceholderKind::This | PlaceholderKind::Buffer | PlaceholderKind::Path => {
+                "no --file given"
+            }
+            PlaceholderKind::Selection | PlaceholderKind::SelectionFull if self.file.is_none() => {
+                "no --file given"
+            }
+            PlaceholderKind::Selection | PlaceholderKind::SelectionFull => "no selection given",
+            PlaceholderKind::Alt | PlaceholderKind::AltInline if self.alt_file.is_none() => {
+                "no --alt-file given"
             }
+            PlaceholderKind::Alt | PlaceholderKind::AltInline => "alternate file unreadable",
+            PlaceholderKind::Diffstat | PlaceholderKind::DiffLimited | PlaceholderKind::Diff => {
+                self.diff_status().reason().unwrap_or("no diff")
+            }
+            PlaceholderKind::Project | PlaceholderKind::Branch | PlaceholderKind::Blame
+                if self.git_toplevel().is_none() =>
+            {
+                "not a git repository"
+            }
+            PlaceholderKind::Project | PlaceholderKind::Branch => "no commits yet",
+            PlaceholderKind::Blame => "no committed lines to blame",
+            PlaceholderKind::Clipboard => "clipboard empty or unreadable",
         }
+    }
 
-        result
+    /// Get git diff output
+    pub fn format_diff(&self) -> Option<String> {
+        self.diff_status().into_diff()
+    }
+
+    /// Get git diff output limited to `max_lines` lines
+    pub fn format_diff_limited(&self, max_lines: usize) -> Option<String> {
+        self.format_diff()
+            .map(|diff| truncate_lines(&diff, max_lines))
     }
 
-    /// Check if context has any file information
-    pub fn has_file(&self) -> bool {
-        self.file.is_some()
+    /// Format @project - the name of the git repository
+    pub fn format_project(&self) -> Option<String> {
+        let name = self.git_toplevel()?.file_name()?;
+        Some(name.to_string_lossy().into_owned())
     }
 
-    /// Check if context has selection
-    pub fn has_selection(&self) -> bool {
-        self.selection.is_some() || self.selection_start.is_some()
+    /// Format @branch - the current branch, or `abc1234 (detached)` on a detached HEAD
+    pub fn format_branch(&self) -> Option<String> {
+        self.branch
+            .get_or_init(|| {
+                let branch = self.git_command(&["rev-parse", "--abbrev-ref", "HEAD"])?;
+                let branch = branch.trim_end();
+                if branch != "HEAD" {
+                    return Some(branch.to_string());
+                }
+                let commit = self.git_command(&["rev-parse", "--short", "HEAD"])?;
+                Some(format!("{} (detached)", commit.trim_end()))
+            })
+            .clone()
     }
 
-    /// Get all placeholders with their current values
-    /// Returns a list of (placeholder, value) pairs
-    pub fn list_placeholders(&self) -> Vec<(&'static str, String)> {
-        let mut placeholders = Vec::new();
+    /// Format @blame - the commits that last changed the selection range (or
+    /// the cursor line), most lines first, e.g.
+    /// `- abc1234 Fix the parser (Ada, 2024-05-01, 3 lines)`
+    ///
+    /// Lines not committed yet are left out; None when no line is committed.
+    pub fn format_blame(&self) -> Option<String> {
+        self.blame
+            .get_or_init(|| {
+                let path = self.file_absolute.as_deref()?;
+                let (start, end) = match (self.selection_start, self.selection_end) {
+                    (Some(start), Some(end)) => (start, end),
+                    _ => (self.line?, self.line?),
+                };
+                let range = format!("{},{}", start, end);
+                let porcelain =
+                    self.git_command(&["blame", "--porcelain", "-L", &range, "--", path])?;
+                let commits: Vec<String> = blame_commits(&porcelain, MAX_BLAME_COMMITS)
+                    .into_iter()
+                    .filter_map(|(hash, lines)| {
+                        let log = self.git_command(&[
+                            "log",
+                            "-1",
+                            "--format=%h%x00%s%x00%an%x00%as",
+                            hash,
+                        ])?;
+                        let mut fields = log.trim_end().splitn(4, '\0');
+                        let (short, subject, author, date) = (
+                            fields.next()?,
+                            fields.next()?,
+                            fields.next()?,
+                            fields.next()?,
+                        );
+                        let lines = match lines {
+                            1 => "1 line".to_string(),
+                            n => format!("{} lines", n),
+                        };
+                        Some(format!(
+                            "- {} {} ({}, {}, {})",
+                            short, subject, author, date, lines
+                        ))
+                    })
+                    .collect();
+                (!commits.is_empty()).then(|| commits.join("\n"))
+            })
+            .clone()
+    }
 
-        // @this - current file + cursor/selection position
-        let this_value = self
-            .format_this()
-            .unwrap_or_else(|| "(no file context)".to_string());
-        placeholders.push(("@this", this_value));
+    /// Get git diff --stat output
+    pub fn format_diffstat(&self) -> Option<String> {
+        self.git_diff(&["diff", "--stat"]).into_diff()
+    }
 
-        // @buffer - current file reference
-        let buffer_value = self
-            .format_buffer()
-            .unwrap_or_else(|| "(no file context)".to_string());
-        placeholders.push(("@buffer", buffer_value));
+    /// Expand context placeholders in a prompt
+    pub fn expand(&self, prompt: &str) -> String {
+        self.expansion(prompt).text
+    }
 
-        // @path - absolute file path
-        let path_value = self
-            .file_absolute
-            .clone()
-            .unwrap_or_else(|| "(no file context)".to_string());
-        placeholders.push(("@path", path_value));
-
-        // @selection - selection with content
-        let selection_value = if self.selection.is_some() {
-            let sel = self.selection.as_ref().unwrap();
-            let preview = if sel.len() > 50 {
-                format!("{}...", &sel[..50])
-            } else {
-                sel.clone()
-            };
-            let lines = sel.lines().count();
-            format!("{} lines: {}", lines, preview.replace('\n', "\\n"))
-        } else {
-            "(no selection)".to_string()
-        };
-        placeholders.push(("@selection", selection_value));
+    /// Expand context placeholders, recording which ones were substituted
+    pub fn expansion(&self, prompt: &str) -> Expansion {
+        self.expansion_with(prompt, &Known::new())
+    }
 
-        // @diff - git diff output
-        let diff_value = if let Some(diff) = self.format_diff() {
-            let lines = diff.lines().count();
-            format!("{} lines of changes", lines)
-        } else {
-            "(no git diff)".to_string()
+    /// [`expansion`](Self::expansion), with the values in `known` instead
+    /// of computing them again
+    pub fn expansion_with(&self, prompt: &str, known: &Known) -> Expansion {
+        let mut result = Expansion {
+            text: prompt.to_string(),
+            ..Default::default()
         };
-        placeholders.push(("@diff", diff_value));
+        for kind in PlaceholderKind::ALL {
+            kind.substitute(self, known, &mut result);
+        }
+        // Last, so placeholders in register contents stay literal
+        let (text, substituted) = self.registers.expand(&result.text);
+        result.text = text;
+        result.substituted.extend(substituted);
+        result.report = self.report(prompt, &result.substituted);
+        result
+    }
 
-        // @clipboard - system clipboard content
-        let clipboard_value = if let Some(clip) = get_clipboard_text() {
-            let preview = if clip.len() > 50 {
-                format!("{}...", &clip[..50])
-            } else {
-                clip.clone()
+    /// What expanding `prompt` did with each `@` token in it, given the
+    /// value length `substituted` for each expanded one
+    fn report(&self, prompt: &str, substituted: &BTreeMap<String, usize>) -> ExpansionReport {
+        let mut report = ExpansionReport::default();
+        for (i, _) in prompt.match_indices('@') {
+            let rest = &prompt[i..];
+            let register = rest
+                .strip_prefix(crate::register::PREFIX)
+                .and_then(|after| self.registers.named_at(after))
+                .map(|name| &rest[..crate::register::PREFIX.len() + name.len()]);
+            let (token, resolution) = match token_at(rest).or(register) {
+                Some(token) if substituted.contains_key(token) => (token, Resolution::Expanded),
+                Some(token) => (token, Resolution::Unavailable),
+                None => match mention_at(prompt, i) {
+                    // A register that wasn't given
+                    Some(word) if word.starts_with(crate::register::PREFIX) => {
+                        (word, Resolution::Unavailable)
+                    }
+                    Some(word) => (word, Resolution::Unknown),
+                    None => continue,
+                },
             };
-            let lines = clip.lines().count();
-            format!("{} lines: {}", lines, preview.replace('\n', "\\n"))
-        } else {
-            "(empty)".to_string()
-        };
-        placeholders.push(("@clipboard", clipboard_value));
+            let delta = match substituted.get(token) {
+                Some(&len) if resolution == Resolution::Expanded => {
+                    len as isize - token.len() as isize
+                }
+                _ => 0,
+            };
+            report.note(token, i, resolution, delta);
+        }
+        report
+    }
 
-        placeholders
+    /// Expand a prompt into session message parts
+    ///
+    /// `@buffer` and `@selection` (and `@selection:full`) become file parts
+    /// the server reads itself, the selection with its line range, instead
+    /// of text inlined into the prompt. The server reads the file from disk,
+    /// so it doesn't see unsaved changes. Everything else, and attachments
+    /// without an absolute path or range, is expanded as by [`expand`](Self::expand).
+    pub fn to_parts(&self, prompt: &str) -> Vec<MessagePart> {
+        let mut message = MessageBuilder::default();
+        let mut rest = prompt;
+        while let Some((start, kind)) = ATTACHMENTS
+            .into_iter()
+            .filter_map(|kind| rest.find(kind.name()).map(|i| (i, kind)))
+            .min_by_key(|(i, _)| *i)
+        {
+            message = message.text(&self.expand(&rest[..start]));
+            message = match self.attachment(kind) {
+                // The server reads the file from disk
+                Some(part) if self.stale => message.part(part).text(&format!(" {}", STALE_MARKER)),
+                Some(part) => message.part(part),
+                None => message.text(&self.expand(kind.name())),
+            };
+            rest = &rest[start + kind.name().len()..];
+        }
+        message.text(&self.expand(rest)).build()
     }
-}
+
+    /// File part for an attachment placeholder, if it can be attached
+    fn attachment(&self, kind: PlaceholderKind) -> Option<MessagePart> {
+        let file = self.file.as_deref()?;
+        let path = Path::new(self.file_absolute.as_deref()?);
+        match kind {
+            PlaceholderKind::Buffer => MessagePart::file(path, file, None),
+            PlaceholderKind::Selection | PlaceholderKind::SelectionFull => {
+                self.selection.as_ref()?;
+                let range = (self.selection_start?, self.selection_end?);
+                MessagePart::file(path, file, Some(range))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether every placeholder `prompt` uses has a value
+    ///
+    /// `@selection:full` needs what `@selection` needs, each `@diff`
+    /// variant needs a diff, and `@reg:NAME` a register given this time.
+    #[cfg(feature = "tui")]
+    pub fn placeholders_available(&self, prompt: &str) -> bool {
+        self.registers.available(prompt)
+            && PlaceholderKind::ALL.into_iter().all(|kind| {
+                let token = match kind {
+                    PlaceholderKind::SelectionFull => return true,
+                    PlaceholderKind::DiffLimited => "@diff:",
+                    _ => kind.name(),
+                };
+                !prompt.contains(token) || kind.available(self)
+            })
+    }
+
+    /// Placeholders the panel lists, found without computing any value
+    ///
+    /// `@alt` and `@alt:inline` are only listed with an --alt-file.
+    #[cfg(feature = "tui")]
+    pub fn panel_kinds(&self) -> Vec<PlaceholderKind> {
+        PlaceholderKind::ALL
+            .into_iter()
+            .filter(|kind| match kind {
+                PlaceholderKind::SelectionFull => kind.available(self),
+                PlaceholderKind::Alt | PlaceholderKind::AltInline => self.alt_file.is_some(),
+                _ => true,
+            })
+            .collect()
+    }
+
+    /// The registers given, as (token, preview)
+    #[cfg(feature = "tui")]
+    pub fn register_previews(&self) -> Vec<(String, String)> {
+        self.registers
+            .iter()
+            .map(|(token, content)| {
+                let preview = format!("{} lines: {}", content.lines().count(), preview(content));
+                (token, preview)
+            })
+            .collect()
+    }
+}
+
+/// The `--selection-file` a run kept ([`Cli::keep_selection_file`]),
+/// deleted when this is dropped
+///
+/// `ask --loop` reads the selection once and goes on expanding it round
+/// after round, so the file outlives the context read from it, until the
+/// loop is over however it ends.
+pub struct KeptSelectionFile(Option<PathBuf>);
+
+impl KeptSelectionFile {
+    /// Take over `cli`'s selection file if it was kept
+    pub fn new(cli: &Cli) -> Self {
+        Self(
+            cli.selection_file
+                .clone()
+                .filter(|path| cli.keep_selection_file && path.as_os_str() != STDIN),
+        )
+    }
+}
+
+impl Drop for KeptSelectionFile {
+    fn drop(&mut self) {
+        if let Some(path) = &self.0 {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Delete a file the context was read from; one that stays is a warning
+fn remove_read_file(path: &Path, warnings: &mut Vec<ContextWarning>) {
+    match fs::remove_file(path) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => warnings.push(ContextWarning::Undeleted {
+            path: path.to_path_buf(),
+            error: e.to_string(),
+        }),
+    }
+}
+
+/// Modification time of `path` in unix seconds
+fn file_mtime(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs())
+}
+
+/// Whether the file on disk may differ from the buffer
+///
+/// True when the buffer is `modified`, or when `buffer_mtime` is given and
+/// the file's mtime differs from it (including a file missing from disk).
+fn is_stale(disk_mtime: Option<u64>, buffer_mtime: Option<u64>, modified: bool) -> bool {
+    modified || buffer_mtime.is_some_and(|buffer| disk_mtime != Some(buffer))
+}
+
+/// Run git in `dir` (or the process cwd)
+/// Returns None if git fails or produces no output
+fn run_git(dir: Option<&Path>, args: &[&str]) -> Option<String> {
+    let mut cmd = std::process::Command::new("git");
+    cmd.arg("--no-pager").args(args);
+    if let Some(dir) = dir {
+        cmd.current_dir(dir);
+    }
+
+    let output = cmd.output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    if stdout.is_empty() {
+        None
+    } else {
+        Some(stdout)
+    }
+}
+
+/// Commits in `git blame --porcelain` output with the number of lines each
+/// last changed, at most `max`, most lines first (ties in order of appearance)
+///
+/// Lines not committed yet (the all-zero hash) aren't counted.
+fn blame_commits(porcelain: &str, max: usize) -> Vec<(&str, usize)> {
+    let mut commits: Vec<(&str, usize)> = Vec::new();
+    // Each line's header starts with its commit; content lines start with a tab
+    for line in porcelain.lines().filter(|line| !line.starts_with('\t')) {
+        let Some(hash) = line
+            .split(' ')
+            .next()
+            .filter(|hash| hash.len() >= 40 && hash.bytes().all(|b| b.is_ascii_hexdigit()))
+        else {
+            continue;
+        };
+        if hash.bytes().all(|b| b == b'0') {
+            continue;
+        }
+        match commits.iter_mut().find(|(seen, _)| *seen == hash) {
+            Some((_, lines)) => *lines += 1,
+            None => commits.push((hash, 1)),
+        }
+    }
+    commits.sort_by_key(|(_, lines)| std::cmp::Reverse(*lines));
+    commits.truncate(max);
+    commits
+}
+
+/// Whether `git` can be run at all
+fn git_installed() -> bool {
+    std::process::Command::new("git")
+        .arg("--version")
+        .output()
+        .is_ok()
+}
+
+/// First `@diff`, `@diffstat` or `@diff:N` placeholder in `text`
+fn diff_token(text: &str) -> Option<&str> {
+    const TOKEN: &str = "@diff";
+    text.match_indices(TOKEN).find_map(|(idx, _)| {
+        let after = &text[idx + TOKEN.len()..];
+        let len = if after.starts_with("stat") {
+            "stat".len()
+        } else if let Some(limit) = after.strip_prefix(':') {
+            match limit
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(limit.len())
+            {
+                0 => return None,
+                digits => 1 + digits,
+            }
+        } else if after.starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+            return None;
+        } else {
+            0
+        };
+        Some(&text[idx..idx + TOKEN.len() + len])
+    })
+}
+
+/// First and last line (1-based, inclusive) of the window `@alt:inline`
+/// shows of a file of `total` lines: `radius` lines on each side of `line`,
+/// cut at the ends of the file, or the first `2 * radius + 1` lines without a
+/// line; None for an empty file
+///
+/// A line past the end is taken as the last one.
+fn alt_window(line: Option<u32>, total: usize, radius: u32) -> Option<(u32, u32)> {
+    let total = u32::try_from(total).unwrap_or(u32::MAX);
+    if total == 0 {
+        return None;
+    }
+    let (start, end) = match line {
+        Some(line) => {
+            let line = line.clamp(1, total);
+            (
+                line.saturating_sub(radius).max(1),
+                line.saturating_add(radius),
+            )
+        }
+        None => (1, radius.saturating_mul(2).saturating_add(1)),
+    };
+    Some((start, end.min(total)))
+}
+
+/// First 50 columns of `text` on one line
+#[cfg(feature = "tui")]
+fn preview(text: &str) -> String {
+    crate::util::display_truncate(text, 50)
+}
+
+/// Keep the first and last `max_lines / 2` lines of `text`, with a marker for the rest
+/// Whole lines are kept, so multi-byte characters are never split.
+fn head_tail_lines(text: &str, max_lines: usize) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    if lines.len() <= max_lines {
+        return text.to_string();
+    }
+
+    let head = max_lines / 2;
+    let tail = max_lines - head;
+    let marker = format!(
+        "[... {} lines omitted ...]",
+        crate::util::thousands(lines.len() - max_lines)
+    );
+    let mut kept = lines[..head].to_vec();
+    kept.push(&marker);
+    kept.extend_from_slice(&lines[lines.len() - tail..]);
+    kept.join("\n")
+}
+
+/// Keep at most `max_lines` lines of `text`, appending a marker with the omitted count
+fn truncate_lines(text: &str, max_lines: usize) -> String {
+    let total = text.lines().count();
+    if total <= max_lines {
+        return text.to_string();
+    }
+
+    let mut kept: Vec<&str> = text.lines().take(max_lines).collect();
+    let marker = format!("... ({} more lines omitted)", total - max_lines);
+    kept.push(&marker);
+    kept.join("\n")
+}
+
+/// Replace `token` only where it is not followed by a word character or `:`
+fn replace_bare_token(text: &str, token: &str, value: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(idx) = rest.find(token) {
+        let after = &rest[idx + token.len()..];
+        result.push_str(&rest[..idx]);
+        match after.chars().next() {
+            Some(c) if c.is_alphanumeric() || c == '_' || c == ':' => result.push_str(token),
+            _ => result.push_str(value),
+        }
+        rest = after;
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Replace every `@diff:N` occurrence using `diff_for` to produce the limited diff
+/// Occurrences without a valid number, or where `diff_for` returns None, are left as-is
+fn replace_diff_limits<F>(text: &str, mut diff_for: F) -> String
+where
+    F: FnMut(usize) -> Option<String>,
+{
+    const TOKEN: &str = "@diff:";
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(idx) = rest.find(TOKEN) {
+        result.push_str(&rest[..idx]);
+        let after = &rest[idx + TOKEN.len()..];
+        let digits_len = after
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(after.len());
+
+        let replacement = after[..digits_len]
+            .parse::<usize>()
+            .ok()
+            .and_then(&mut diff_for);
+        match replacement {
+            Some(diff) => {
+                result.push_str(&diff);
+                rest = &after[digits_len..];
+            }
+            None => {
+                result.push_str(TOKEN);
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::file_content::DEFAULT_MAX_BYTES;
 
     #[test]
     fn test_format_location_cursor() {
@@ -308,6 +1636,260 @@ mod tests {
         assert_eq!(result, "Explain @src/main.rs L42");
     }
 
+    #[test]
+    fn test_expansion_report() {
+        let mut ctx = Context {
+            file: Some("src/main.rs".to_string()),
+            file_absolute: Some("/p/src/main.rs".to_string()),
+            line: Some(42),
+            ..Default::default()
+        };
+        ctx.registers.insert("a", "hello".to_string());
+        let prompt = "@path, @buffr and @path; @selection, @reg:a @reg:z @alternative mail@x.com";
+        let report = ctx.expansion(prompt).report;
+        let entries: Vec<(&str, &[usize], Resolution, isize)> = report
+            .entries
+            .iter()
+            .map(|e| {
+                (
+                    e.token.as_str(),
+                    e.positions.as_slice(),
+                    e.resolution,
+                    e.delta,
+                )
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                // Repeated: once, in the order first seen, with both sizes
+                ("@path", &[0, 18][..], Resolution::Expanded, 18),
+                ("@buffr", &[7], Resolution::Unknown, 0),
+                ("@selection", &[25], Resolution::Unavailable, 0),
+                ("@reg:a", &[37], Resolution::Expanded, -1),
+                ("@reg:z", &[44], Resolution::Unavailable, 0),
+                ("@alternative", &[51], Resolution::Unknown, 0),
+            ]
+        );
+        assert_eq!(
+            report.to_string(),
+            "@path at 0, 18: expanded, +18 bytes\n\
+             @buffr at 7: left as typed (unknown)\n\
+             @selection at 25: left as typed (unavailable)\n\
+             @reg:a at 37: expanded, -1 bytes\n\
+             @reg:z at 44: left as typed (unavailable)\n\
+             @alternative at 51: left as typed (unknown)"
+        );
+        assert_eq!(ctx.expansion("plain").report.to_string(), "no placeholders");
+    }
+
+    #[test]
+    fn test_unexpanded() {
+        let mut ctx = Context {
+            file: Some("src/main.rs".to_string()),
+            file_absolute: Some("/p/src/main.rs".to_string()),
+            line: Some(42),
+            ..Default::default()
+        };
+        ctx.registers.insert("a", "hello".to_string());
+        let why = |prompt: &str| -> Vec<String> {
+            let report = ctx.expansion(prompt).report;
+            report
+                .unexpanded()
+                .map(|entry| format!("{} ({})", entry.token, ctx.why_unexpanded(entry)))
+                .collect()
+        };
+        // Expanded and unknown tokens pass; those without a value don't
+        assert_eq!(why("@this @reg:a @buffr @Override"), Vec::<String>::new());
+        assert_eq!(
+            why("@this and @selection, @buffr, @reg:z @alt:inline @selection"),
+            [
+                "@selection (no selection given)",
+                "@reg:z (register not given)",
+                "@alt:inline (no --alt-file given)",
+            ]
+        );
+        let no_file = Context::default();
+        let report = no_file.expansion("@buffer @selection:full").report;
+        let reasons: Vec<&str> = report
+            .unexpanded()
+            .map(|entry| no_file.why_unexpanded(entry))
+            .collect();
+        assert_eq!(reasons, ["no --file given", "no --file given"]);
+    }
+
+    #[test]
+    fn test_expansion_report_around_commands() {
+        let ctx = Context {
+            file_absolute: Some("/p/src/main.rs".to_string()),
+            ..Default::default()
+        };
+        let outputs = crate::cmd::Outputs::from([("pwd".to_string(), "/p".to_string())]);
+        let prompt = "@path\n@cmd:ls -l\nin @cmd:pwd\nsee @path";
+        let report = crate::cmd::expand(prompt, &outputs, |text| ctx.expansion(text)).report;
+        assert_eq!(
+            report.to_string(),
+            "@path at 0, 33: expanded, +18 bytes\n\
+             @cmd:ls -l at 6: left as typed (disabled)\n\
+             @cmd:pwd at 20: expanded, -6 bytes"
+        );
+    }
+
+    #[test]
+    fn test_expansion_records_substitutions() {
+        let ctx = Context {
+            file: Some("src/main.rs".to_string()),
+            file_absolute: Some("/p/src/main.rs".to_string()),
+            line: Some(42),
+            ..Default::default()
+        };
+        let expansion = ctx.expansion("Explain @this (@path), not @this again or @selection");
+        assert_eq!(
+            expansion.text,
+            "Explain @src/main.rs L42 (/p/src/main.rs), not @src/main.rs L42 again or @selection"
+        );
+        let substituted: Vec<(&str, usize)> = expansion
+            .substituted
+            .iter()
+            .map(|(name, len)| (name.as_str(), *len))
+            .collect();
+        assert_eq!(substituted, [("@path", 14), ("@this", 16)]);
+
+        assert!(ctx.expansion("plain text").substituted.is_empty());
+    }
+
+    #[test]
+    fn test_expansion_with_known() {
+        let ctx = Context {
+            file: Some("src/main.rs".to_string()),
+            line: Some(42),
+            ..Default::default()
+        };
+        // Values computed earlier win over the context's, even missing ones
+        let known = Known::from([
+            (PlaceholderKind::Diff, Some("+ the diff".to_string())),
+            (PlaceholderKind::This, None),
+        ]);
+        let expansion = ctx.expansion_with("@this: @diff", &known);
+        assert_eq!(expansion.text, "@this: + the diff");
+        assert_eq!(ctx.expand("@this"), "@src/main.rs L42");
+    }
+
+    #[test]
+    fn test_to_parts() {
+        let text = |t: &str| MessagePart::Text {
+            text: t.to_string(),
+        };
+        let file = |url: &str| MessagePart::File {
+            mime: "text/plain".to_string(),
+            filename: "src/my file.rs".to_string(),
+            url: url.to_string(),
+        };
+        let ctx = Context {
+            file: Some("src/my file.rs".to_string()),
+            file_absolute: Some("/p/src/my file.rs".to_string()),
+            selection: Some("fn a() {}\nfn b() {}".to_string()),
+            selection_start: Some(10),
+            selection_end: Some(11),
+            ..Default::default()
+        };
+        assert_eq!(
+            ctx.to_parts("Explain @selection (@this) using @buffer and @selection:full"),
+            [
+                text("Explain "),
+                file("file:///p/src/my%20file.rs?start=10&end=11"),
+                text(" (@src/my file.rs L10-L11) using "),
+                file("file:///p/src/my%20file.rs"),
+                text(" and "),
+                file("file:///p/src/my%20file.rs?start=10&end=11"),
+            ]
+        );
+        // Without attachments, the parts are the expanded text
+        assert_eq!(
+            ctx.to_parts("Explain @this"),
+            [text(&ctx.expand("Explain @this"))]
+        );
+
+        // Attachments that can't be made fall back to the text expansion
+        let relative = Context {
+            file_absolute: Some("src/my file.rs".to_string()),
+            ..ctx.clone()
+        };
+        assert_eq!(
+            relative.to_parts("Explain @selection"),
+            [text(&ctx.expand("Explain @selection"))]
+        );
+        let no_file = Context {
+            selection: Some("x".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            no_file.to_parts("Explain @selection and @buffer"),
+            [text("Explain @selection and @buffer")]
+        );
+    }
+
+    #[test]
+    fn test_large_selection_is_shortened() {
+        let selection: Vec<String> = (1..=8000).map(|i| format!("é line {}", i)).collect();
+        let ctx = Context {
+            file: Some("big.rs".to_string()),
+            selection: Some(selection.join("\n")),
+            selection_start: Some(1),
+            selection_end: Some(8000),
+            ..Default::default()
+        };
+        #[cfg(feature = "tui")]
+        assert_eq!(ctx.selection_omitted_lines(), Some(7600));
+
+        let expanded = ctx.expand("Check @selection");
+        let body: Vec<&str> = expanded.lines().skip(2).collect();
+        assert_eq!(body.len(), 400 + 1 + 1); // kept lines, marker, closing fence
+        assert_eq!(body[0], "é line 1");
+        assert_eq!(body[199], "é line 200");
+        assert_eq!(body[200], "[... 7,600 lines omitted ...]");
+        assert_eq!(body[201], "é line 7801");
+        assert_eq!(body[400], "é line 8000");
+
+        let full = ctx.expand("Check @selection:full");
+        assert_eq!(full.lines().count(), 8000 + 3);
+        assert!(!full.contains("omitted"));
+
+        #[cfg(feature = "tui")]
+        {
+            let (preview, _) = PlaceholderKind::Selection.panel_entry(&ctx);
+            assert!(
+                preview.starts_with("8000 lines (truncated): "),
+                "{}",
+                preview
+            );
+            assert!(ctx.panel_kinds().contains(&PlaceholderKind::SelectionFull));
+        }
+
+        // Small selections are left alone
+        let small = Context {
+            max_selection_lines: 8000,
+            ..ctx
+        };
+        assert!(!small.expand("@selection").contains("omitted"));
+        #[cfg(feature = "tui")]
+        {
+            assert_eq!(small.selection_omitted_lines(), None);
+            assert!(!small
+                .panel_kinds()
+                .contains(&PlaceholderKind::SelectionFull));
+        }
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_preview() {
+        let long = "é".repeat(60);
+        assert_eq!(preview(&long), format!("{}…", "é".repeat(49)));
+        assert_eq!(preview("a\nb"), "a↵b");
+        assert_eq!(preview("short"), "short");
+    }
+
     #[test]
     fn test_expand_no_context() {
         let ctx = Context::default();
@@ -315,4 +1897,785 @@ mod tests {
         // @this should remain as-is if no context
         assert_eq!(result, "Hello @this world");
     }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_placeholders_available() {
+        let file = Context {
+            file: Some("src/main.rs".to_string()),
+            line: Some(42),
+            ..Default::default()
+        };
+        let selected = Context {
+            selection: Some("fn main() {}".to_string()),
+            ..file.clone()
+        };
+        assert!(file.placeholders_available("Explain @this"));
+        assert!(file.placeholders_available("No placeholders"));
+        assert!(!file.placeholders_available("Review @selection"));
+        assert!(!file.placeholders_available("Review @selection:full"));
+        assert!(selected.placeholders_available("Review @selection:full and @buffer"));
+        assert!(!Context::default().placeholders_available("Explain @this"));
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_selection_lines_from_text() {
+        let ctx = Context {
+            selection: Some("let a = 1;\nlet b = 2;\nlet c = 3;".to_string()),
+            selection_start: Some(10),
+            selection_end: Some(12),
+            ..Default::default()
+        };
+        let lines = ctx.selection_lines(2).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].number, 10);
+        assert_eq!(lines[1].text, "let b = 2;");
+        assert!(lines.iter().all(|l| l.selected));
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_selection_lines_from_file() {
+        let path =
+            std::env::temp_dir().join(format!("opencode-helix-{}.txt", uuid::Uuid::new_v4()));
+        fs::write(&path, "one\ntwo\nthree\nfour\nfive\nsix\n").unwrap();
+        let ctx = Context {
+            file_absolute: Some(path.display().to_string()),
+            selection_start: Some(3),
+            selection_end: Some(4),
+            ..Default::default()
+        };
+        let lines = ctx.selection_lines(8).unwrap();
+
+        let numbers: Vec<u32> = lines.iter().map(|l| l.number).collect();
+        assert_eq!(numbers, [2, 3, 4, 5]);
+        let selected: Vec<bool> = lines.iter().map(|l| l.selected).collect();
+        assert_eq!(selected, [false, true, true, false]);
+
+        // No preview of a file too large, or binary
+        let small = Context {
+            max_file_bytes: 10,
+            ..ctx
+        };
+        assert!(small.selection_lines(8).is_none());
+        fs::write(&path, "one\0\ntwo\nthree\nfour\n").unwrap();
+        let binary = Context {
+            max_file_bytes: DEFAULT_MAX_BYTES,
+            ..small
+        };
+        assert!(binary.selection_lines(8).is_none());
+        let _ = fs::remove_file(&path);
+
+        assert!(Context::default().selection_lines(8).is_none());
+    }
+
+    /// Run git in `dir`, panicking on failure
+    fn git(dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    #[test]
+    fn test_diff_uses_worktree_of_file() {
+        let root = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        let main = root.join("main");
+        let tree = root.join("feature");
+        fs::create_dir_all(&main).unwrap();
+        git(&main, &["init", "-q"]);
+        fs::write(main.join("lib.rs"), "fn a() {}\n").unwrap();
+        git(&main, &["add", "."]);
+        git(&main, &["commit", "-qm", "init"]);
+        git(&main, &["worktree", "add", "-q", tree.to_str().unwrap()]);
+        fs::write(tree.join("lib.rs"), "fn worktree_change() {}\n").unwrap();
+
+        // Project cwd is the main checkout, but the file lives in the worktree
+        let ctx = Context {
+            file_absolute: Some(tree.join("lib.rs").display().to_string()),
+            cwd: Some(main.clone()),
+            ..Default::default()
+        };
+        let diff = ctx.format_diff();
+        let _ = fs::remove_dir_all(&root);
+
+        assert!(diff.unwrap().contains("worktree_change"));
+    }
+
+    #[test]
+    fn test_every_placeholder_round_trips() {
+        // Registering a variant means adding it here and to `ALL`
+        for (i, kind) in PlaceholderKind::ALL.into_iter().enumerate() {
+            let index = match kind {
+                PlaceholderKind::This => 0,
+                PlaceholderKind::Buffer => 1,
+                PlaceholderKind::Path => 2,
+                PlaceholderKind::Project => 3,
+                PlaceholderKind::Branch => 4,
+                PlaceholderKind::Blame => 5,
+                PlaceholderKind::SelectionFull => 6,
+                PlaceholderKind::Selection => 7,
+                PlaceholderKind::AltInline => 8,
+                PlaceholderKind::Alt => 9,
+                PlaceholderKind::Diffstat => 10,
+                PlaceholderKind::DiffLimited => 11,
+                PlaceholderKind::Diff => 12,
+                PlaceholderKind::Clipboard => 13,
+            };
+            assert_eq!(i, index, "{:?} is out of place in ALL", kind);
+        }
+
+        let root = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        git(&root, &["init", "-q"]);
+        fs::write(root.join("lib.rs"), "fn a() {}\n").unwrap();
+        git(&root, &["add", "."]);
+        git(&root, &["commit", "-qm", "init"]);
+        fs::write(root.join("lib.rs"), "fn a() {}\nfn b() {}\n").unwrap();
+
+        let ctx = Context {
+            file: Some("lib.rs".to_string()),
+            file_absolute: Some(root.join("lib.rs").display().to_string()),
+            selection: Some("fn b() {}\nfn c() {}".to_string()),
+            selection_start: Some(1),
+            selection_end: Some(2),
+            alt_file: Some("lib.rs".to_string()),
+            alt_file_absolute: Some(root.join("lib.rs").display().to_string()),
+            alt_line: Some(2),
+            cwd: Some(root.clone()),
+            max_selection_lines: 1,
+            ..Default::default()
+        };
+        for kind in PlaceholderKind::ALL {
+            let expansion = ctx.expansion(kind.name());
+            match kind.expand(&ctx) {
+                Some(value) => {
+                    assert_eq!(expansion.text, value, "{:?}", kind);
+                    assert_eq!(expansion.substituted.len(), 1, "{:?}", kind);
+                }
+                // Only the clipboard depends on the environment
+                None => {
+                    assert_eq!(kind, PlaceholderKind::Clipboard);
+                    assert_eq!(expansion.text, kind.name());
+                }
+            }
+            #[cfg(feature = "tui")]
+            assert_eq!(
+                kind.panel_entry(&ctx).1.is_some(),
+                kind.available(&ctx),
+                "{:?}",
+                kind
+            );
+        }
+        let _ = fs::remove_dir_all(&root);
+
+        // Without editor context the file and selection placeholders are
+        // unavailable (git and the clipboard don't depend on it), and
+        // @selection:full, @alt and @alt:inline are unlisted
+        #[cfg(feature = "tui")]
+        {
+            let empty = Context::default();
+            let kinds = empty.panel_kinds();
+            assert_eq!(kinds.len(), PlaceholderKind::ALL.len() - 3);
+            let names: Vec<&str> = kinds
+                .into_iter()
+                .filter(|kind| kind.panel_entry(&empty).1.is_none())
+                .map(PlaceholderKind::name)
+                .take(5)
+                .collect();
+            assert_eq!(names, ["@this", "@buffer", "@path", "@blame", "@selection"]);
+        }
+    }
+
+    #[test]
+    fn test_alt_window() {
+        // 20 lines each side, cut at the ends of the file
+        assert_eq!(alt_window(Some(50), 100, 20), Some((30, 70)));
+        assert_eq!(alt_window(Some(1), 100, 20), Some((1, 21)));
+        assert_eq!(alt_window(Some(21), 100, 20), Some((1, 41)));
+        assert_eq!(alt_window(Some(5), 100, 20), Some((1, 25)));
+        assert_eq!(alt_window(Some(95), 100, 20), Some((75, 100)));
+        assert_eq!(alt_window(Some(3), 4, 20), Some((1, 4)));
+        // Past the end, or 0: the nearest line
+        assert_eq!(alt_window(Some(500), 100, 20), Some((80, 100)));
+        assert_eq!(alt_window(Some(0), 100, 20), Some((1, 21)));
+        // Without a line, the start of the file
+        assert_eq!(alt_window(None, 100, 20), Some((1, 41)));
+        assert_eq!(alt_window(None, 7, 20), Some((1, 7)));
+        assert_eq!(alt_window(Some(1), 0, 20), None);
+        assert_eq!(alt_window(Some(u32::MAX), 10, u32::MAX), Some((1, 10)));
+    }
+
+    #[test]
+    fn test_alt_from_cli() {
+        use clap::Parser;
+
+        let dir = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(dir.join("src")).unwrap();
+        let text: String = (1..=100).map(|n| format!("line {}\n", n)).collect();
+        fs::write(dir.join("src/other.rs"), text).unwrap();
+        let cwd = dir.display().to_string();
+        let from = |args: &[&str]| {
+            let mut argv = vec!["opencode-helix", "--cwd", cwd.as_str(), "-f", "src/main.rs"];
+            argv.extend(args);
+            argv.push("status");
+            Context::from_cli(&Cli::parse_from(argv), DEFAULT_MAX_BYTES)
+        };
+
+        // Relative like --file, against --cwd
+        let ctx = from(&["--alt-file", "src/other.rs", "--alt-line", "60"]);
+        assert_eq!(
+            ctx.alt_file_absolute.as_deref(),
+            Some(dir.join("src/other.rs").to_str().unwrap())
+        );
+        assert_eq!(
+            ctx.expand("compare @this to @alt, then @alternatives"),
+            "compare @src/main.rs to @src/other.rs L60, then @alternatives"
+        );
+        let inline = ctx.expand("@alt:inline");
+        assert!(
+            inline.starts_with("@src/other.rs L40-L80\n```\nline 40\n"),
+            "{}",
+            inline
+        );
+        assert!(inline.ends_with("line 80\n```"), "{}", inline);
+        assert_eq!(inline.lines().count(), 41 + 3);
+        #[cfg(feature = "tui")]
+        {
+            let names: Vec<String> = ctx.panel_kinds().iter().map(|k| k.name().into()).collect();
+            assert!(names.contains(&"@alt".to_string()));
+            assert!(names.contains(&"@alt:inline".to_string()));
+        }
+
+        // The window is shortened past max_selection_lines
+        let short = Context {
+            max_selection_lines: 10,
+            ..from(&["--alt-file", "src/other.rs", "--alt-line", "100"])
+        };
+        let inline = short.expand("@alt:inline");
+        assert!(inline.starts_with("@src/other.rs L80-L100\n"), "{}", inline);
+        assert!(inline.contains("line 84\n[... 11 lines omitted ...]\nline 96"));
+
+        // A file over max_file_bytes isn't read, and a missing one stays as typed
+        let large = Context {
+            max_file_bytes: 100,
+            ..from(&["--alt-file", "src/other.rs"])
+        };
+        let inline = large.expand("@alt:inline");
+        assert!(
+            inline.starts_with("@src/other.rs\n[file too large, skipped: other.rs,"),
+            "{}",
+            inline
+        );
+        assert_eq!(large.expand("@alt"), "@src/other.rs");
+        let missing = from(&["--alt-file", "gone.rs"]);
+        assert_eq!(missing.expand("@alt:inline"), "@alt:inline");
+        #[cfg(feature = "tui")]
+        {
+            assert!(!missing.placeholders_available("@alt:inline"));
+            assert!(missing.placeholders_available("@alt"));
+        }
+        let _ = fs::remove_dir_all(&dir);
+
+        // Unlisted and left as typed without --alt-file
+        let none = from(&[]);
+        assert_eq!(none.expand("@alt @alt:inline"), "@alt @alt:inline");
+        #[cfg(feature = "tui")]
+        assert!(!none
+            .panel_kinds()
+            .iter()
+            .any(|kind| matches!(kind, PlaceholderKind::Alt | PlaceholderKind::AltInline)));
+        assert!(Cli::try_parse_from(["opencode-helix", "--alt-line", "3", "status"]).is_err());
+    }
+
+    #[test]
+    fn test_selection_file_kept() {
+        use clap::Parser;
+
+        let dir = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("selection");
+        let read = |keep: bool| {
+            fs::write(&path, "fn main() {}").unwrap();
+            let mut cli = Cli::parse_from([
+                "opencode-helix",
+                "--selection-file",
+                path.to_str().unwrap(),
+                "status",
+            ]);
+            cli.keep_selection_file = keep;
+            let ctx = Context::from_cli(&cli, DEFAULT_MAX_BYTES);
+            assert_eq!(ctx.selection.as_deref(), Some("fn main() {}"));
+            cli
+        };
+
+        // Deleted once read
+        let cli = read(false);
+        assert!(!path.exists());
+        drop(KeptSelectionFile::new(&cli));
+
+        // Kept until the loop is over
+        let cli = read(true);
+        let kept = KeptSelectionFile::new(&cli);
+        assert!(path.exists());
+        drop(kept);
+        assert!(!path.exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_language_detected() {
+        use clap::Parser;
+
+        let dir = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("run"), "#!/usr/bin/env python3\n").unwrap();
+        let cwd = dir.display().to_string();
+        let from = |args: &[&str]| {
+            let mut argv = vec!["opencode-helix", "--cwd", cwd.as_str()];
+            argv.extend(args);
+            argv.push("status");
+            Context::from_cli(&Cli::parse_from(argv), DEFAULT_MAX_BYTES)
+        };
+
+        let by_extension = from(&["-f", "src/main.rs"]);
+        let by_shebang = from(&["-f", "run"]);
+        let explicit = from(&["-f", "run", "--language", "bash"]);
+        let no_file = from(&[]);
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(by_extension.language_or_detected(), Some("rust"));
+        assert_eq!(by_extension.language, None);
+        assert_eq!(by_shebang.language_or_detected(), Some("python"));
+        // --language is authoritative
+        assert_eq!(explicit.language_or_detected(), Some("bash"));
+        assert_eq!(no_file.language_or_detected(), None);
+    }
+
+    #[test]
+    fn test_selection_range_normalized() {
+        use clap::Parser;
+
+        let dir = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let from = |args: &[&str], selection: Option<&str>| {
+            let mut argv = vec!["opencode-helix", "-f", "src/lib.rs"];
+            argv.extend(args);
+            let path = dir.join("selection.tmp");
+            let path_arg = path.display().to_string();
+            if let Some(text) = selection {
+                fs::write(&path, text).unwrap();
+                argv.extend(["--selection-file", path_arg.as_str()]);
+            }
+            argv.push("status");
+            Context::from_cli(&Cli::parse_from(argv), DEFAULT_MAX_BYTES)
+        };
+
+        // Selection text anchored at the cursor line, or at the given end
+        let ctx = from(&["-l", "10"], Some("a\nb\nc\n"));
+        assert_eq!(ctx.format_this().unwrap(), "@src/lib.rs L10-L12");
+        assert_eq!(
+            ctx.validate(),
+            [ContextWarning::IncompleteRange {
+                inferred: Some((10, 12))
+            }]
+        );
+        let ctx = from(&["--selection-end", "4"], Some("a\nb\nc"));
+        assert_eq!(ctx.format_this().unwrap(), "@src/lib.rs L2-L4");
+
+        // Nothing to anchor to: @this falls back, with a warning saying so
+        let ctx = from(&[], Some("a"));
+        assert_eq!(ctx.format_this().unwrap(), "@src/lib.rs");
+        let warning = ctx.validate()[0].to_string();
+        assert!(warning.contains("--selection-start"), "{}", warning);
+        let ctx = from(&["--selection-start", "5", "-l", "7"], None);
+        assert_eq!(ctx.format_this().unwrap(), "@src/lib.rs L7");
+        assert_eq!(ctx.validate().len(), 1);
+
+        // Reversed ranges are rejected after the range is completed
+        let ctx = from(&["--selection-start", "9", "--selection-end", "3"], None);
+        assert_eq!(ctx.format_this().unwrap(), "@src/lib.rs");
+        assert_eq!(
+            ctx.validate(),
+            [ContextWarning::ReversedRange { start: 9, end: 3 }]
+        );
+
+        assert!(from(
+            &["--selection-start", "3", "--selection-end", "9"],
+            Some("x")
+        )
+        .validate()
+        .is_empty());
+        assert!(from(&["-l", "3"], None).validate().is_empty());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_project_and_branch() {
+        let root = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        let repo = root.join("myproj");
+        fs::create_dir_all(repo.join("src")).unwrap();
+        git(&repo, &["init", "-q"]);
+        fs::write(repo.join("src/lib.rs"), "fn a() {}\n").unwrap();
+        git(&repo, &["add", "."]);
+        git(&repo, &["commit", "-qm", "init"]);
+        git(&repo, &["checkout", "-qb", "feature/parser"]);
+
+        let in_repo = || Context {
+            file_absolute: Some(repo.join("src/lib.rs").display().to_string()),
+            cwd: Some(root.clone()),
+            ..Default::default()
+        };
+        let ctx = in_repo();
+        assert_eq!(
+            ctx.expand("Follow @project conventions on @branch"),
+            "Follow myproj conventions on feature/parser"
+        );
+        // Looked up once per context
+        git(&repo, &["checkout", "-q", "--detach"]);
+        assert_eq!(ctx.expand("@branch"), "feature/parser");
+
+        let commit = run_git(Some(&repo), &["rev-parse", "--short", "HEAD"]).unwrap();
+        assert_eq!(
+            in_repo().expand("@branch"),
+            format!("{} (detached)", commit.trim_end())
+        );
+
+        // Outside a repository both are left as-is
+        let outside = Context {
+            cwd: Some(root.clone()),
+            ..Default::default()
+        };
+        let text = outside.expand("@project on @branch");
+        let _ = fs::remove_dir_all(&root);
+        assert_eq!(text, "@project on @branch");
+    }
+
+    #[test]
+    fn test_blame_commits() {
+        let a = "a".repeat(40);
+        let b = "b".repeat(40);
+        let zero = "0".repeat(40);
+        let porcelain = format!(
+            "{a} 1 1 1\nauthor Ada\nsummary First\nfilename lib.rs\n\tfn a() {{}}\n\
+             {b} 2 2 2\nauthor Bob\nprevious {a} lib.rs\nfilename lib.rs\n\t{a}\n\
+             {b} 3 3\n\tfn c() {{}}\n\
+             {zero} 4 4 1\nfilename lib.rs\n\tfn d() {{}}\n\
+             {a} 5 5 1\n\tfn e() {{}}\n"
+        );
+        // A tie keeps the first seen first; a hash in the content isn't a line
+        assert_eq!(
+            blame_commits(&porcelain, 10),
+            [(a.as_str(), 2), (b.as_str(), 2)]
+        );
+        assert_eq!(blame_commits(&porcelain, 1), [(a.as_str(), 2)]);
+        assert!(blame_commits("", 10).is_empty());
+    }
+
+    #[test]
+    fn test_blame() {
+        let root = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&root).unwrap();
+        git(&root, &["init", "-q"]);
+        let commit = |author: &str, message: &str| {
+            git(&root, &["add", "."]);
+            git(&root, &["commit", "-qm", message, "--author", author]);
+        };
+        fs::write(root.join("lib.rs"), "fn a() {}\nfn b() {}\nfn c() {}\n").unwrap();
+        commit("Ada <ada@example.com>", "Add the parser");
+        fs::write(root.join("lib.rs"), "fn a() {}\nfn b2() {}\nfn c() {}\n").unwrap();
+        commit("Bob <bob@example.com>", "Rename b");
+        // Not committed yet: left out
+        fs::write(
+            root.join("lib.rs"),
+            "fn a() {}\nfn b2() {}\nfn c() {}\nfn d() {}\n",
+        )
+        .unwrap();
+
+        let at = |range: Option<(u32, u32)>, line: Option<u32>| Context {
+            file: Some("lib.rs".to_string()),
+            file_absolute: Some(root.join("lib.rs").display().to_string()),
+            selection_start: range.map(|(start, _)| start),
+            selection_end: range.map(|(_, end)| end),
+            line,
+            cwd: Some(std::env::temp_dir()),
+            ..Default::default()
+        };
+        let selection = at(Some((1, 4)), Some(4)).expand("Why? @blame");
+        let cursor = at(None, Some(2)).expand("@blame");
+        let uncommitted = at(None, Some(4)).expand("@blame");
+        let no_line = at(None, None).expand("@blame");
+        let _ = fs::remove_dir_all(&root);
+
+        // Most lines first
+        let lines: Vec<&str> = selection.lines().collect();
+        assert_eq!(lines.len(), 2, "{}", selection);
+        assert!(lines[0].starts_with("Why? - "), "{}", selection);
+        assert!(lines[0].contains(" Add the parser (Ada, "), "{}", selection);
+        assert!(lines[0].ends_with(", 2 lines)"), "{}", selection);
+        assert!(lines[1].starts_with("- "), "{}", selection);
+        assert!(lines[1].contains(" Rename b (Bob, "), "{}", selection);
+        assert!(lines[1].ends_with(", 1 line)"), "{}", selection);
+        // The cursor line without a selection
+        assert!(cursor.contains("Rename b (Bob, "), "{}", cursor);
+        assert!(!cursor.contains("Ada"), "{}", cursor);
+        assert_eq!(uncommitted, "@blame");
+        assert_eq!(no_line, "@blame");
+    }
+
+    #[test]
+    fn test_diff_status() {
+        let root = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        let repo = root.join("repo");
+        let plain = root.join("plain");
+        fs::create_dir_all(&repo).unwrap();
+        fs::create_dir_all(&plain).unwrap();
+        git(&repo, &["init", "-q"]);
+        fs::write(repo.join("lib.rs"), "fn a() {}\n").unwrap();
+        git(&repo, &["add", "."]);
+        git(&repo, &["commit", "-qm", "init"]);
+        let at = |dir: &Path| Context {
+            cwd: Some(dir.to_path_buf()),
+            ..Default::default()
+        };
+
+        let outside = at(&plain);
+        let clean = at(&repo);
+        let statuses = [outside.diff_status(), clean.diff_status()];
+        let warnings = [
+            outside.unexpanded_diff("Review @diff:50"),
+            clean.unexpanded_diff("Summarize @diffstat"),
+            clean.unexpanded_diff("No placeholder here"),
+        ];
+        #[cfg(feature = "tui")]
+        let panel = PlaceholderKind::Diff.panel_entry(&outside);
+        fs::write(repo.join("lib.rs"), "fn b() {}\n").unwrap();
+        let changed = at(&repo);
+        let changed_status = changed.diff_status();
+        let changed_warning = changed.unexpanded_diff("Review @diff");
+        let _ = fs::remove_dir_all(&root);
+
+        assert_eq!(statuses, [DiffResult::NotARepo, DiffResult::NoChanges]);
+        assert_eq!(
+            warnings,
+            [
+                Some("@diff:50 not expanded: not a git repository".to_string()),
+                Some("@diffstat not expanded: no uncommitted changes".to_string()),
+                None,
+            ]
+        );
+        #[cfg(feature = "tui")]
+        {
+            let (preview, value) = panel;
+            assert_eq!((preview.as_str(), value), ("(not a git repository)", None));
+        }
+
+        assert!(changed_status
+            .clone()
+            .into_diff()
+            .unwrap()
+            .contains("+fn b()"));
+        assert_eq!(changed_status.reason(), None);
+        assert_eq!(changed_warning, None);
+    }
+
+    #[test]
+    fn test_diff_token() {
+        assert_eq!(diff_token("Review @diff please"), Some("@diff"));
+        assert_eq!(diff_token("@diff"), Some("@diff"));
+        assert_eq!(diff_token("see @diffstat"), Some("@diffstat"));
+        assert_eq!(diff_token("last @diff:20 lines"), Some("@diff:20"));
+        // Not placeholders
+        assert_eq!(diff_token("@diff: x"), None);
+        assert_eq!(diff_token("@differ @diff_a"), None);
+        assert_eq!(diff_token("no diff"), None);
+        assert_eq!(diff_token("@diff: then @diff"), Some("@diff"));
+    }
+
+    #[test]
+    fn test_is_stale() {
+        assert!(!is_stale(Some(100), None, false));
+        assert!(!is_stale(Some(100), Some(100), false));
+        assert!(is_stale(Some(101), Some(100), false));
+        // Not on disk yet
+        assert!(is_stale(None, Some(100), false));
+        assert!(is_stale(Some(100), Some(100), true));
+        assert!(is_stale(None, None, true));
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_stale_from_cli() {
+        use clap::Parser;
+
+        let dir = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("lib.rs");
+        fs::write(&file, "fn main() {}\n").unwrap();
+        let mtime = file_mtime(&file).unwrap().to_string();
+        let older = (file_mtime(&file).unwrap() - 60).to_string();
+        let path = file.display().to_string();
+        let from = |args: &[&str]| {
+            let mut argv = vec!["opencode-helix", "-f", path.as_str()];
+            argv.extend(args);
+            argv.push("status");
+            Context::from_cli(&Cli::parse_from(argv), DEFAULT_MAX_BYTES)
+        };
+
+        let contexts = [
+            from(&[]),
+            from(&["--buffer-mtime", &mtime]),
+            from(&["--buffer-mtime", &older]),
+            from(&["--modified"]),
+        ];
+        let _ = fs::remove_dir_all(&dir);
+        let stale: Vec<bool> = contexts.iter().map(Context::is_stale).collect();
+        assert_eq!(stale, [false, false, true, true]);
+        assert_eq!(contexts[3].validate(), [ContextWarning::StaleFile]);
+        assert!(contexts[1].validate().is_empty());
+
+        // No file, nothing to be stale
+        let ctx = Context::from_cli(
+            &Cli::parse_from(["opencode-helix", "--modified", "status"]),
+            DEFAULT_MAX_BYTES,
+        );
+        assert!(!ctx.is_stale());
+    }
+
+    #[test]
+    fn test_registers_from_cli() {
+        use clap::Parser;
+
+        let dir = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let long = dir.join("reg_b");
+        fs::write(&long, "line 1\nline 2\n").unwrap();
+        let missing = dir.join("missing");
+        let image = dir.join("reg.png");
+        fs::write(&image, b"\x89PNG\0\0").unwrap();
+        let ctx = Context::from_cli(
+            &Cli::parse_from([
+                "opencode-helix",
+                "--register",
+                "a=alpha",
+                "--register-file",
+                &format!("b={}", long.display()),
+                "--register-file",
+                &format!("c={}", missing.display()),
+                "--register-file",
+                &format!("d={}", image.display()),
+                "status",
+            ]),
+            DEFAULT_MAX_BYTES,
+        );
+        let deleted = !long.exists();
+        let _ = fs::remove_dir_all(&dir);
+        assert!(deleted);
+
+        let expansion = ctx.expansion("@reg:a then @reg:b, not @reg:c");
+        assert_eq!(
+            expansion.text,
+            "alpha then ```\nline 1\nline 2\n```, not @reg:c"
+        );
+        assert_eq!(expansion.substituted.get("@reg:a"), Some(&5));
+        assert_eq!(ctx.expand("@reg:d"), "[binary file skipped: reg.png, 6 B]");
+        #[cfg(feature = "tui")]
+        assert!(!ctx.placeholders_available("@reg:c"));
+        assert!(matches!(
+            ctx.validate().as_slice(),
+            [ContextWarning::UnreadableRegister { name, .. }] if name == "c"
+        ));
+
+        #[cfg(feature = "tui")]
+        {
+            let listed: Vec<String> = ctx
+                .register_previews()
+                .into_iter()
+                .map(|(token, _)| token)
+                .collect();
+            assert_eq!(listed, ["@reg:a", "@reg:b", "@reg:d"]);
+        }
+
+        // Contents stay out of debug output
+        let debug = format!("{:?}", ctx);
+        assert!(
+            !debug.contains("alpha") && !debug.contains("line 1"),
+            "{}",
+            debug
+        );
+    }
+
+    #[test]
+    fn test_stale_marker() {
+        let ctx = Context {
+            file: Some("src/lib.rs".to_string()),
+            file_absolute: Some("/p/src/lib.rs".to_string()),
+            line: Some(3),
+            selection: Some("fn a() {}".to_string()),
+            selection_start: Some(3),
+            selection_end: Some(3),
+            stale: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            ctx.expand("Fix @this in @buffer"),
+            "Fix @src/lib.rs L3-L3 [note: file may be stale] in @src/lib.rs [note: file may be stale]"
+        );
+        // The selection text comes from the buffer, not the disk
+        assert!(!ctx.expand("@selection").contains(STALE_MARKER));
+
+        let parts = ctx.to_parts("Review @buffer now");
+        assert_eq!(
+            parts[2],
+            MessagePart::Text {
+                text: format!(" {} now", STALE_MARKER)
+            }
+        );
+
+        let fresh = Context {
+            stale: false,
+            ..ctx.clone()
+        };
+        assert_eq!(fresh.expand("@buffer"), "@src/lib.rs");
+        assert_eq!(fresh.to_parts("@buffer").len(), 1);
+    }
+
+    #[test]
+    fn test_truncate_lines() {
+        assert_eq!(truncate_lines("a\nb\nc", 5), "a\nb\nc");
+        assert_eq!(
+            truncate_lines("a\nb\nc\nd", 2),
+            "a\nb\n... (2 more lines omitted)"
+        );
+    }
+
+    #[test]
+    fn test_replace_bare_token() {
+        assert_eq!(
+            replace_bare_token("@diff @diffstat @diff:5 @diff", "@diff", "D"),
+            "D @diffstat @diff:5 D"
+        );
+    }
+
+    #[test]
+    fn test_replace_diff_limits() {
+        let diff_for = |max: usize| Some(format!("<diff {}>", max));
+        assert_eq!(
+            replace_diff_limits("Review @diff:200 please", diff_for),
+            "Review <diff 200> please"
+        );
+        assert_eq!(
+            replace_diff_limits("@diff:1 and @diff:2", diff_for),
+            "<diff 1> and <diff 2>"
+        );
+        // No number - left untouched
+        assert_eq!(replace_diff_limits("@diff: x", diff_for), "@diff: x");
+        // No diff available - left untouched
+        assert_eq!(replace_diff_limits("@diff:10", |_| None), "@diff:10");
+    }
 }
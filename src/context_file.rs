@@ -0,0 +1,209 @@
+//! `--context-file`: the whole editor context in one JSON document
+//!
+//! A keybinding writes one file instead of getting five separate flags
+//! right. Flags given alongside it win over the fields it sets.
+//!
+//! ```json
+//! {"v": 1, "file": "src/main.rs", "line": 12, "selection": "...",
+//!  "selection_start": 10, "selection_end": 20, "language": "rust"}
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::cli::Cli;
+
+/// Schema version this build reads (the `v` key)
+pub const VERSION: u32 = 1;
+
+/// Editor context from a `--context-file`
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ContextFile {
+    /// Schema version, required
+    pub v: u32,
+    pub file: Option<PathBuf>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    /// Selection text
+    pub selection: Option<String>,
+    pub selection_start: Option<u32>,
+    pub selection_end: Option<u32>,
+    pub language: Option<String>,
+    pub cwd: Option<PathBuf>,
+}
+
+impl ContextFile {
+    /// Read and delete the file at `path`, then parse it
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read context file {}", path.display()));
+        // Deleted like --selection-file, even when it doesn't parse
+        let _ = fs::remove_file(path);
+        Self::parse(&text?).with_context(|| format!("Invalid context file {}", path.display()))
+    }
+
+    /// Parse a context document, naming the field at fault when it is invalid
+    pub fn parse(text: &str) -> Result<Self> {
+        let doc: Map<String, Value> =
+            serde_json::from_str(text).context("expected a JSON object")?;
+        let context: Self =
+            serde_json::from_value(Value::Object(doc.clone())).map_err(|e| field_error(&doc, e))?;
+        if context.v != VERSION {
+            bail!(
+                "unsupported version {} (this build reads version {})",
+                context.v,
+                VERSION
+            );
+        }
+        Ok(context)
+    }
+
+    /// Fill the context flags `cli` doesn't set from the file
+    pub fn apply(self, cli: &mut Cli) {
+        fill(&mut cli.file, self.file);
+        fill(&mut cli.line, self.line);
+        fill(&mut cli.column, self.column);
+        fill(&mut cli.selection_start, self.selection_start);
+        fill(&mut cli.selection_end, self.selection_end);
+        fill(&mut cli.language, self.language);
+        fill(&mut cli.cwd, self.cwd);
+        // --selection-file wins over the text in the file
+        if cli.selection_file.is_none() {
+            fill(&mut cli.selection_text, self.selection);
+        }
+    }
+}
+
+fn fill<T>(flag: &mut Option<T>, value: Option<T>) {
+    if flag.is_none() {
+        *flag = value;
+    }
+}
+
+/// `error` with the name of the field it is about
+///
+/// serde_json names missing and unknown fields, but not one with the wrong
+/// type, so each field is checked on its own to find it.
+fn field_error(doc: &Map<String, Value>, error: serde_json::Error) -> anyhow::Error {
+    let culprit = doc.iter().find(|(key, value)| {
+        let mut single = Map::new();
+        single.insert("v".to_string(), VERSION.into());
+        single.insert((*key).clone(), (*value).clone());
+        serde_json::from_value::<ContextFile>(Value::Object(single)).is_err()
+    });
+    match culprit {
+        Some((key, _)) => anyhow!("\"{}\": {}", key, error),
+        None => anyhow!("{}", error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_parse_partial() {
+        let context = ContextFile::parse(r#"{"v": 1, "file": "src/main.rs", "line": 12}"#).unwrap();
+        assert_eq!(
+            context,
+            ContextFile {
+                v: 1,
+                file: Some("src/main.rs".into()),
+                line: Some(12),
+                ..Default::default()
+            }
+        );
+
+        let context = ContextFile::parse(
+            r#"{"v": 1, "selection": "fn a() {}\n", "selection_start": 10,
+                "selection_end": 20, "language": "rust", "column": null}"#,
+        )
+        .unwrap();
+        assert_eq!(context.selection.as_deref(), Some("fn a() {}\n"));
+        assert_eq!(
+            (context.selection_start, context.selection_end),
+            (Some(10), Some(20))
+        );
+        assert_eq!(context.column, None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |text: &str| format!("{:#}", ContextFile::parse(text).unwrap_err());
+
+        assert!(error(r#"{"file": "a.rs"}"#).contains("missing field `v`"));
+        let message = error(r#"{"v": 1, "file": "a.rs", "line": "12"}"#);
+        assert!(message.starts_with("\"line\": invalid type"), "{}", message);
+        let message = error(r#"{"v": 1, "selection_end": -3}"#);
+        assert!(message.starts_with("\"selection_end\": "), "{}", message);
+        let message = error(r#"{"v": 1, "lang": "rust"}"#);
+        assert!(message.contains("unknown field `lang`"), "{}", message);
+        assert!(error(r#"{"v": "1"}"#).starts_with("\"v\": "));
+        assert!(error(r#"{"v": 2}"#).contains("unsupported version 2"));
+        assert!(error("[1, 2]").contains("expected a JSON object"));
+        assert!(error("{\"v\": 1,").contains("expected a JSON object"));
+    }
+
+    #[test]
+    fn test_flags_override_file() {
+        let context = ContextFile {
+            v: 1,
+            file: Some("from_file.rs".into()),
+            line: Some(12),
+            selection: Some("text".to_string()),
+            selection_start: Some(10),
+            selection_end: Some(11),
+            language: Some("rust".to_string()),
+            ..Default::default()
+        };
+
+        let mut cli = Cli::parse_from(["opencode-helix", "-l", "3", "--language", "go", "status"]);
+        context.clone().apply(&mut cli);
+        assert_eq!(cli.file, Some("from_file.rs".into()));
+        assert_eq!(cli.line, Some(3));
+        assert_eq!(cli.language.as_deref(), Some("go"));
+        assert_eq!(
+            (cli.selection_start, cli.selection_end),
+            (Some(10), Some(11))
+        );
+        assert_eq!(cli.selection_text.as_deref(), Some("text"));
+        assert_eq!(cli.cwd, None);
+
+        let mut cli = Cli::parse_from([
+            "opencode-helix",
+            "--selection-file",
+            "/tmp/sel.tmp",
+            "-f",
+            "flag.rs",
+            "status",
+        ]);
+        context.apply(&mut cli);
+        assert_eq!(cli.file, Some("flag.rs".into()));
+        assert_eq!(cli.selection_text, None);
+    }
+
+    #[test]
+    fn test_read_deletes_file() {
+        let dir = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let good = dir.join("context.json");
+        fs::write(&good, r#"{"v": 1, "line": 4}"#).unwrap();
+        let bad = dir.join("bad.json");
+        fs::write(&bad, r#"{"v": 1, "line": true}"#).unwrap();
+
+        let read = ContextFile::read(&good).unwrap();
+        let error = format!("{:#}", ContextFile::read(&bad).unwrap_err());
+        let left = (good.exists(), bad.exists());
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(read.line, Some(4));
+        assert!(error.contains("\"line\""), "{}", error);
+        assert_eq!(left, (false, false));
+    }
+}
@@ -0,0 +1,241 @@
+//! Man pages and markdown docs generated from the clap definitions
+//!
+//! `gen-docs` writes these so packaged man pages and the Helix-side docs
+//! can't drift from the actual flags. The markdown also lists the built-in
+//! prompts and the placeholders from their registries. Nothing here needs a
+//! server or a terminal.
+
+use std::fmt::Write as _;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use clap::{Arg, Command};
+
+use crate::config::DEFAULT_PROMPTS;
+use crate::context::PlaceholderKind;
+
+/// Subcommands that get their own page and section (hidden ones and `help` don't)
+fn documented(cmd: &Command) -> impl Iterator<Item = &Command> {
+    cmd.get_subcommands()
+        .filter(|sub| !sub.is_hide_set() && sub.get_name() != "help")
+}
+
+/// Man pages as (file name, contents): the main page, then one per subcommand
+pub fn man_pages(cmd: &Command) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut cmd = cmd.clone();
+    cmd.build();
+    let name = cmd.get_name().to_string();
+
+    let mut pages = vec![(format!("{}.1", name), render_man(cmd.clone())?)];
+    for sub in documented(&cmd) {
+        // `build` names subcommands after their parent (opencode-helix-ask)
+        let page = sub.get_display_name().unwrap_or(sub.get_name());
+        pages.push((format!("{}.1", page), render_man(sub.clone())?));
+    }
+    Ok(pages)
+}
+
+fn render_man(cmd: Command) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    clap_mangen::Man::new(cmd)
+        .render(&mut buf)
+        .context("Failed to render man page")?;
+    Ok(buf)
+}
+
+/// Markdown reference: global options, subcommands, built-in prompts and placeholders
+pub fn markdown(cmd: &Command) -> String {
+    let mut cmd = cmd.clone();
+    cmd.build();
+    let mut out = String::new();
+
+    let _ = writeln!(out, "# {}\n", cmd.get_name());
+    if let Some(about) = cmd.get_about() {
+        let _ = writeln!(out, "{}\n", about);
+    }
+    out.push_str(
+        "<!-- Generated by `opencode-helix gen-docs --format markdown`; do not edit -->\n\n",
+    );
+
+    out.push_str("## Global options\n\n");
+    options_table(&mut out, cmd.get_arguments().filter(|a| a.is_global_set()));
+
+    out.push_str("## Commands\n\n");
+    for sub in documented(&cmd) {
+        let _ = writeln!(out, "### {}\n", sub.get_name());
+        if let Some(about) = sub.get_about() {
+            let _ = writeln!(out, "{}\n", about);
+        }
+        options_table(&mut out, sub.get_arguments().filter(|a| !a.is_global_set()));
+    }
+
+    out.push_str(
+        "## Built-in prompts\n\n| Name | Description | Prompt |\n|------|-------------|--------|\n",
+    );
+    for prompt in DEFAULT_PROMPTS {
+        let _ = writeln!(
+            out,
+            "| `{}` | {} | `{}` |",
+            prompt.name,
+            escape(prompt.description),
+            escape(prompt.prompt)
+        );
+        for (language, text) in prompt.by_language {
+            let _ = writeln!(out, "| | ({}) | `{}` |", language, escape(text));
+        }
+    }
+
+    out.push_str(
+        "\n## Placeholders\n\n| Placeholder | Expands to |\n|-------------|------------|\n",
+    );
+    for kind in PlaceholderKind::ALL {
+        // The panel offers `@diff:200` as an example; document the general form
+        let name = match kind {
+            PlaceholderKind::DiffLimited => "@diff:N",
+            kind => kind.name(),
+        };
+        let _ = writeln!(out, "| `{}` | {} |", name, escape(kind.describe()));
+    }
+    out.push_str("| `@reg:NAME` | Helix register given with --register or --register-file |\n");
+    out
+}
+
+/// A table of the visible arguments (nothing for a command without any)
+fn options_table<'a>(out: &mut String, args: impl Iterator<Item = &'a Arg>) {
+    let rows: Vec<String> = args
+        // Every command has --help; only the top level has --version
+        .filter(|arg| !arg.is_hide_set() && !["help", "version"].contains(&arg.get_id().as_str()))
+        .map(|arg| format!("| `{}` | {} |", usage(arg), escape(&help(arg))))
+        .collect();
+    if rows.is_empty() {
+        return;
+    }
+    out.push_str("| Option | Description |\n|--------|-------------|\n");
+    for row in rows {
+        out.push_str(&row);
+        out.push('\n');
+    }
+    out.push('\n');
+}
+
+/// How the argument is written: `-p, --port <PORT>` or `<NAME>`
+fn usage(arg: &Arg) -> String {
+    let value = arg
+        .get_value_names()
+        .and_then(|names| names.first())
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| arg.get_id().as_str().to_uppercase());
+    if arg.is_positional() {
+        return format!("<{}>", value);
+    }
+
+    let mut usage = match (arg.get_short(), arg.get_long()) {
+        (Some(short), Some(long)) => format!("-{}, --{}", short, long),
+        (Some(short), None) => format!("-{}", short),
+        (None, long) => format!("--{}", long.unwrap_or_default()),
+    };
+    if arg.get_action().takes_values() {
+        let _ = write!(usage, " <{}>", value);
+    }
+    usage
+}
+
+/// Help text with the possible values and the default
+fn help(arg: &Arg) -> String {
+    let mut help = arg.get_help().map(|h| h.to_string()).unwrap_or_default();
+    let values: Vec<String> = arg
+        .get_possible_values()
+        .iter()
+        .filter(|v| !v.is_hide_set())
+        .map(|v| v.get_name().to_string())
+        .collect();
+    if arg.get_action().takes_values() && !values.is_empty() {
+        let _ = write!(help, " (one of: {})", values.join(", "));
+    }
+    let defaults: Vec<String> = arg
+        .get_default_values()
+        .iter()
+        .map(|v| v.to_string_lossy().into_owned())
+        .collect();
+    if arg.get_action().takes_values() && !defaults.is_empty() && defaults != [""] {
+        let _ = write!(help, " [default: {}]", defaults.join(", "));
+    }
+    help
+}
+
+/// Keep table cells on one line and their pipes literal
+fn escape(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+/// Write a file by renaming a temporary sibling over it, so a reader never
+/// sees it half written
+pub fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = dir.join(format!(".{}.{}.tmp", file_name, std::process::id()));
+    std::fs::write(&tmp, contents)
+        .and_then(|()| std::fs::rename(&tmp, path))
+        .with_context(|| format!("Failed to write {}", path.display()))
+        .inspect_err(|_| {
+            let _ = std::fs::remove_file(&tmp);
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Cli;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_man_pages() {
+        let pages = man_pages(&Cli::command()).unwrap();
+        let names: Vec<&str> = pages.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names[0], "opencode-helix.1");
+        assert!(names.contains(&"opencode-helix-ask.1"));
+        assert!(names.contains(&"opencode-helix-prompt.1"));
+        assert!(!names
+            .iter()
+            .any(|n| n.contains("gen-docs") || n.contains("help")));
+
+        let main = String::from_utf8_lossy(&pages[0].1);
+        assert!(main.starts_with(".ie"), "{}", &main[..40]);
+        assert!(main.contains("opencode\\-helix"));
+    }
+
+    #[test]
+    fn test_markdown() {
+        let md = markdown(&Cli::command());
+        assert!(md.contains("| `-p, --port <PORT>` | Connect to a specific port"));
+        assert!(md.contains("| `--output <OUTPUT>` |"));
+        assert!(md.contains("(one of: text, json) [default: text]"));
+        assert!(md.contains("\n### prompt\n"));
+        assert!(md.contains("| `<NAME_OR_TEXT>` |"));
+        assert!(!md.contains("gen-docs\n"));
+        for prompt in DEFAULT_PROMPTS {
+            assert!(md.contains(&format!("| `{}` |", prompt.name)));
+        }
+        for kind in PlaceholderKind::ALL {
+            if kind != PlaceholderKind::DiffLimited {
+                assert!(md.contains(&format!("| `{}` |", kind.name())));
+            }
+        }
+        assert!(md.contains("| `@diff:N` | git diff, at most N lines |"));
+        assert!(md.contains("| `@reg:NAME` |"));
+    }
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("man/opencode-helix.1");
+        write_atomic(&path, b"old").unwrap();
+        write_atomic(&path, b"new").unwrap();
+        let contents = std::fs::read(&path).unwrap();
+        let entries = std::fs::read_dir(dir.join("man")).unwrap().count();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(contents, b"new");
+        assert_eq!(entries, 1);
+    }
+}
@@ -0,0 +1,257 @@
+//! Exit codes and outcome mapping
+//!
+//! Documented exit codes let Helix keybindings and scripts tell a cancel
+//! apart from a failure:
+//!
+//! | Code | Meaning |
+//! |------|---------|
+//! | 0 | Success (prompt sent) |
+//! | 1 | Unexpected error (e.g. terminal setup) |
+//! | 2 | User cancelled |
+//! | 3 | No opencode server found |
+//! | 4 | opencode server error |
+//! | 5 | Bad usage |
+//! | 6 | opencode is running without `--port` |
+//! | 7 | No opencode server answered |
+//! | 8 | Servers answered, but none for this directory |
+//! | 9 | Sent, but no reply to print (`prompt --print`) |
+//!
+//! Code 3 means no opencode process at all, so a wrapper can start one.
+
+use std::fmt;
+
+use anyhow::Result;
+
+use crate::server::discovery::DiscoveryError;
+
+/// Success (prompt sent)
+pub const EXIT_OK: i32 = 0;
+/// Unexpected error
+pub const EXIT_ERROR: i32 = 1;
+/// User cancelled
+pub const EXIT_CANCELLED: i32 = 2;
+/// No opencode server found
+pub const EXIT_NO_SERVER: i32 = 3;
+/// opencode server error
+pub const EXIT_SERVER_ERROR: i32 = 4;
+/// Bad usage
+pub const EXIT_USAGE: i32 = 5;
+/// opencode is running without --port
+pub const EXIT_NO_PORT: i32 = 6;
+/// No opencode server answered
+pub const EXIT_UNREACHABLE: i32 = 7;
+/// No opencode server for this directory
+pub const EXIT_CWD_MISMATCH: i32 = 8;
+/// Sent, but no reply came to print
+pub const EXIT_NO_REPLY: i32 = 9;
+
+/// Result of a command that did not fail
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// Completed, with an optional message shown unless --quiet
+    Done(Option<String>),
+    /// User cancelled
+    Cancelled,
+}
+
+/// Error category attached to errors as anyhow context
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// Server discovery failed
+    NoServer,
+    /// A request to the server failed
+    Server,
+    /// Invalid arguments not caught by clap
+    Usage,
+    /// The prompt was sent, but `--print` got no reply: none within
+    /// `--max-wait`, or none it could print
+    NoReply,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoServer => write!(f, "No opencode server found"),
+            Self::Server => write!(f, "opencode server request failed"),
+            Self::Usage => write!(f, "Invalid usage"),
+            Self::NoReply => write!(f, "No reply to print"),
+        }
+    }
+}
+
+/// Map a command result to a process exit code
+pub fn exit_code(result: &Result<Outcome>) -> i32 {
+    match result {
+        Ok(Outcome::Done(_)) => EXIT_OK,
+        Ok(Outcome::Cancelled) => EXIT_CANCELLED,
+        Err(e) => error_exit_code(e),
+    }
+}
+
+/// Exit code for an error, from its discovery cause or [`ErrorKind`]
+pub fn error_exit_code(err: &anyhow::Error) -> i32 {
+    if let Some(discovery) = err.downcast_ref::<DiscoveryError>() {
+        return discovery.exit_code();
+    }
+    match err.downcast_ref::<ErrorKind>() {
+        Some(ErrorKind::NoServer) => EXIT_NO_SERVER,
+        Some(ErrorKind::Server) => EXIT_SERVER_ERROR,
+        Some(ErrorKind::Usage) => EXIT_USAGE,
+        Some(ErrorKind::NoReply) => EXIT_NO_REPLY,
+        None => EXIT_ERROR,
+    }
+}
+
+/// Machine-readable cause of an error, the `reason` in JSON output
+pub fn error_reason(err: &anyhow::Error) -> &'static str {
+    if let Some(discovery) = err.downcast_ref::<DiscoveryError>() {
+        return discovery.reason();
+    }
+    match err.downcast_ref::<ErrorKind>() {
+        Some(ErrorKind::NoServer) => "no_server",
+        Some(ErrorKind::Server) => "server",
+        Some(ErrorKind::Usage) => "usage",
+        Some(ErrorKind::NoReply) => "no_reply",
+        None => "error",
+    }
+}
+
+/// Map a clap parse error to a process exit code
+/// (--help and --version are not errors)
+pub fn usage_exit_code(err: &clap::Error) -> i32 {
+    if err.use_stderr() {
+        EXIT_USAGE
+    } else {
+        EXIT_OK
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+    use clap::Parser;
+
+    use crate::cli::Cli;
+
+    #[test]
+    fn test_exit_code_outcomes() {
+        assert_eq!(exit_code(&Ok(Outcome::Done(None))), EXIT_OK);
+        assert_eq!(
+            exit_code(&Ok(Outcome::Done(Some("Sent".to_string())))),
+            EXIT_OK
+        );
+        assert_eq!(exit_code(&Ok(Outcome::Cancelled)), EXIT_CANCELLED);
+    }
+
+    #[test]
+    fn test_exit_code_errors() {
+        let err: Result<Outcome> = Err(anyhow!("connection refused")).context(ErrorKind::NoServer);
+        assert_eq!(exit_code(&err), EXIT_NO_SERVER);
+
+        // Kind is found even under further context
+        let err: Result<Outcome> = Err(anyhow!("timeout"))
+            .context(ErrorKind::Server)
+            .context("Failed to send prompt");
+        assert_eq!(exit_code(&err), EXIT_SERVER_ERROR);
+
+        let err: Result<Outcome> = Err(anyhow!("bad name")).context(ErrorKind::Usage);
+        assert_eq!(exit_code(&err), EXIT_USAGE);
+
+        let err: Result<Outcome> = Err(anyhow!("no reply within 5s")).context(ErrorKind::NoReply);
+        assert_eq!(exit_code(&err), EXIT_NO_REPLY);
+        assert_eq!(error_reason(err.as_ref().unwrap_err()), "no_reply");
+
+        let err: Result<Outcome> = Err(anyhow!("no tty"));
+        assert_eq!(exit_code(&err), EXIT_ERROR);
+    }
+
+    #[test]
+    fn test_discovery_errors() {
+        use crate::server::discovery::Probe;
+        use crate::server::Server;
+
+        let probe = |pid| Probe {
+            pid,
+            port: 4096,
+            error: "connection refused".to_string(),
+        };
+        let server = Server {
+            pid: 7,
+            port: 4097,
+            cwd: "/home/u/other".into(),
+        };
+        let cases = [
+            (
+                DiscoveryError::NoProcesses,
+                EXIT_NO_SERVER,
+                "no_processes",
+                "Start opencode first",
+            ),
+            (
+                DiscoveryError::NoPortInCmdline { pids: vec![12, 34] },
+                EXIT_NO_PORT,
+                "no_port_in_cmdline",
+                "(pid 12, 34) but without --port",
+            ),
+            (
+                DiscoveryError::AllProbesFailed {
+                    attempts: vec![probe(Some(12)), probe(Some(34))],
+                },
+                EXIT_UNREACHABLE,
+                "all_probes_failed",
+                "port 4096 (pid 34): connection refused",
+            ),
+            (
+                DiscoveryError::AllProbesFailed {
+                    attempts: vec![probe(None)],
+                },
+                EXIT_UNREACHABLE,
+                "all_probes_failed",
+                "No opencode server responding on port 4096: connection refused",
+            ),
+            (
+                DiscoveryError::CwdMismatch {
+                    cwd: "/home/u/project".into(),
+                    candidates: vec![server],
+                },
+                EXIT_CWD_MISMATCH,
+                "cwd_mismatch",
+                "port 4097 (pid 7): /home/u/other",
+            ),
+            (
+                DiscoveryError::TimedOut {
+                    after_ms: 3000,
+                    steps: Vec::new(),
+                },
+                EXIT_UNREACHABLE,
+                "timed_out",
+                "No opencode server found within 3.0s",
+            ),
+        ];
+
+        for (error, code, reason, message) in cases {
+            let json = serde_json::to_value(&error).unwrap();
+            assert_eq!(json["reason"], reason);
+            assert!(error.to_string().contains(message), "{}", error);
+
+            // Found under the context main.rs adds
+            let err: Result<Outcome> = Err(error).context(ErrorKind::NoServer);
+            assert_eq!(exit_code(&err), code);
+            assert_eq!(error_reason(err.as_ref().unwrap_err()), reason);
+        }
+
+        let err = anyhow!("bad name").context(ErrorKind::Usage);
+        assert_eq!(error_reason(&err), "usage");
+        assert_eq!(error_reason(&anyhow!("no tty")), "error");
+    }
+
+    #[test]
+    fn test_usage_exit_code() {
+        let err = Cli::try_parse_from(["opencode-helix", "bogus"]).unwrap_err();
+        assert_eq!(usage_exit_code(&err), EXIT_USAGE);
+
+        let err = Cli::try_parse_from(["opencode-helix", "--help"]).unwrap_err();
+        assert_eq!(usage_exit_code(&err), EXIT_OK);
+    }
+}
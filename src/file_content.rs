@@ -0,0 +1,180 @@
+//! Reading files into a prompt without binary garbage or huge payloads
+//!
+//! Contents read from disk and inlined into a prompt (`--register-file`,
+//! the selection preview) go through [`read_text_guarded`]. A file over the
+//! size limit isn't read at all, and one that looks binary (a NUL byte near
+//! the start, or much of it not UTF-8) is skipped; either way the prompt gets
+//! a short marker instead, e.g. `[binary file skipped: logo.png, 2.1 MB]`.
+//! A mostly-text file with a few bad bytes (say Latin-1) is kept, with the
+//! bytes replaced and a note saying so.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::util::size_label;
+
+/// Largest file read into a prompt unless `max_file_bytes` says otherwise
+pub const DEFAULT_MAX_BYTES: u64 = 1024 * 1024;
+
+/// How much of the start of a file is searched for a NUL byte
+const SNIFF_LEN: usize = 8 * 1024;
+
+/// Above this percentage of bytes that aren't UTF-8, a file counts as binary
+const MAX_INVALID_PERCENT: usize = 5;
+
+/// What a guarded read found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileContent {
+    /// Text; `lossy` when bytes that weren't UTF-8 were replaced with U+FFFD
+    Text { text: String, lossy: bool },
+    /// Likely binary: not read into the prompt
+    Binary { size: u64 },
+    /// Over the limit: not read at all
+    TooLarge { size: u64, limit: u64 },
+}
+
+impl FileContent {
+    /// What goes into the prompt for `path`: the text, or a marker saying
+    /// why there is none
+    pub fn into_prompt_text(self, path: &Path) -> String {
+        let name = path.file_name().map_or_else(
+            || path.display().to_string(),
+            |name| name.to_string_lossy().into(),
+        );
+        match self {
+            Self::Text { text, lossy: false } => text,
+            Self::Text { text, lossy: true } => {
+                format!(
+                    "[{}: bytes that aren't UTF-8 were replaced]\n{}",
+                    name, text
+                )
+            }
+            Self::Binary { size } => {
+                format!("[binary file skipped: {}, {}]", name, size_label(size))
+            }
+            Self::TooLarge { size, limit } => format!(
+                "[file too large, skipped: {}, {} (limit {})]",
+                name,
+                size_label(size),
+                size_label(limit)
+            ),
+        }
+    }
+}
+
+/// Read `path` as text if it is at most `max_bytes` long and looks like text
+pub fn read_text_guarded(path: &Path, max_bytes: u64) -> io::Result<FileContent> {
+    let size = fs::metadata(path)?.len();
+    if size > max_bytes {
+        return Ok(FileContent::TooLarge {
+            size,
+            limit: max_bytes,
+        });
+    }
+    Ok(classify(fs::read(path)?))
+}
+
+/// Text, lossy text or binary, by the rules above
+fn classify(bytes: Vec<u8>) -> FileContent {
+    let size = bytes.len() as u64;
+    if bytes[..bytes.len().min(SNIFF_LEN)].contains(&0) {
+        return FileContent::Binary { size };
+    }
+    match String::from_utf8(bytes) {
+        Ok(text) => FileContent::Text { text, lossy: false },
+        Err(e) => {
+            let bytes = e.into_bytes();
+            let invalid: usize = bytes.utf8_chunks().map(|chunk| chunk.invalid().len()).sum();
+            if invalid * 100 > bytes.len() * MAX_INVALID_PERCENT {
+                FileContent::Binary { size }
+            } else {
+                FileContent::Text {
+                    text: String::from_utf8_lossy(&bytes).into_owned(),
+                    lossy: true,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/files")
+            .join(name)
+    }
+
+    #[test]
+    fn test_read_text_guarded() {
+        let png = fixture("pixel.png");
+        assert_eq!(
+            read_text_guarded(&png, DEFAULT_MAX_BYTES).unwrap(),
+            FileContent::Binary { size: 68 }
+        );
+        // Mostly invalid UTF-8, without a NUL byte
+        assert!(matches!(
+            read_text_guarded(&fixture("noise.bin"), DEFAULT_MAX_BYTES).unwrap(),
+            FileContent::Binary { .. }
+        ));
+
+        let latin1 = fixture("latin1.txt");
+        let FileContent::Text { text, lossy } = read_text_guarded(&latin1, 1000).unwrap() else {
+            panic!("latin1.txt isn't text");
+        };
+        assert!(lossy);
+        assert!(text.starts_with("Caf\u{fffd} menu\nCrème"), "{}", text);
+
+        assert_eq!(
+            read_text_guarded(&latin1, 40).unwrap(),
+            FileContent::TooLarge {
+                size: 41,
+                limit: 40
+            }
+        );
+        assert!(read_text_guarded(&fixture("missing.txt"), 40).is_err());
+    }
+
+    #[test]
+    fn test_classify() {
+        let text = |s: &str| FileContent::Text {
+            text: s.to_string(),
+            lossy: false,
+        };
+        assert_eq!(classify(b"fn main() {}\n".to_vec()), text("fn main() {}\n"));
+        assert_eq!(classify(Vec::new()), text(""));
+        // A NUL past the sniffed start doesn't count
+        let mut late = vec![b'a'; SNIFF_LEN];
+        late.push(0);
+        assert!(matches!(classify(late), FileContent::Text { .. }));
+        assert_eq!(classify(b"a\0b".to_vec()), FileContent::Binary { size: 3 });
+    }
+
+    #[test]
+    fn test_into_prompt_text() {
+        let path = Path::new("assets/logo.png");
+        assert_eq!(
+            FileContent::Binary { size: 2_202_009 }.into_prompt_text(path),
+            "[binary file skipped: logo.png, 2.1 MB]"
+        );
+        assert_eq!(
+            FileContent::TooLarge {
+                size: 3 * 1024 * 1024,
+                limit: DEFAULT_MAX_BYTES
+            }
+            .into_prompt_text(Path::new("dist/bundle.js")),
+            "[file too large, skipped: bundle.js, 3.0 MB (limit 1.0 MB)]"
+        );
+        assert_eq!(
+            FileContent::Text {
+                text: "Caf\u{fffd}".to_string(),
+                lossy: true
+            }
+            .into_prompt_text(Path::new("menu.txt")),
+            "[menu.txt: bytes that aren't UTF-8 were replaced]\nCaf\u{fffd}"
+        );
+    }
+}
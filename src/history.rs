@@ -0,0 +1,167 @@
+//! The last prompt sent from each project, for `resend`
+//!
+//! Prompts are stored before placeholder expansion, so resending picks up the
+//! current editor context. Each project has its own entry, keyed by the
+//! canonical working directory, under `$XDG_STATE_HOME/opencode-helix/last/`.
+//! The ask dialog also offers it as a suggestion while its input is empty.
+//! Entries go through the [`StateStore`], so they may be encrypted.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::PromptStep;
+use crate::state::StateStore;
+
+/// The last prompt sent from a project
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LastPrompt {
+    /// Canonical project directory (guards against hash collisions)
+    pub project: PathBuf,
+    /// Steps as sent (several for a chain), before expansion
+    pub steps: Vec<PromptStep>,
+    /// Submit after appending
+    pub submit: bool,
+}
+
+/// History directory (`last/` in the state directory)
+pub fn history_dir() -> PathBuf {
+    crate::paths::state_dir().join("last")
+}
+
+/// Canonical form of a project directory, so `.` and a symlink share an entry
+pub fn canonical(project: &Path) -> PathBuf {
+    project
+        .canonicalize()
+        .unwrap_or_else(|_| project.to_path_buf())
+}
+
+/// Entry file for a project: an FNV-1a hash of its canonical path
+pub fn entry_path(dir: &Path, project: &Path) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+
+    let hash = project
+        .as_os_str()
+        .as_bytes()
+        .iter()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    dir.join(format!("{:016x}.json", hash))
+}
+
+/// Remember `steps` as the last prompt sent from `project`
+pub fn save(
+    store: &dyn StateStore,
+    dir: &Path,
+    project: &Path,
+    steps: &[PromptStep],
+    submit: bool,
+) -> Result<()> {
+    let project = canonical(project);
+    let last = LastPrompt {
+        project: project.clone(),
+        steps: steps.to_vec(),
+        submit,
+    };
+    store.write(&entry_path(dir, &project), &serde_json::to_string(&last)?)
+}
+
+/// The last prompt sent from `project`, if any
+pub fn load(store: &dyn StateStore, dir: &Path, project: &Path) -> Result<Option<LastPrompt>> {
+    let project = canonical(project);
+    let path = entry_path(dir, &project);
+    let Some(text) = store.read(&path).context("History unavailable")? else {
+        return Ok(None);
+    };
+    let last: LastPrompt = serde_json::from_str(&text)
+        .with_context(|| format!("Invalid history entry {}", path.display()))?;
+    Ok((last.project == project).then_some(last))
+}
+
+/// Text the ask dialog suggests while its input is empty
+///
+/// The most recent step of `last` (the final one of a chain) that is not
+/// empty and whose placeholders are all `usable` now, so a prompt using
+/// `@selection` isn't offered without a selection.
+#[cfg(feature = "tui")]
+pub fn suggestion(last: Option<&LastPrompt>, usable: impl Fn(&str) -> bool) -> Option<&str> {
+    last?
+        .steps
+        .iter()
+        .rev()
+        .map(|step| step.text.as_str())
+        .find(|text| !text.trim().is_empty() && usable(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Plain;
+
+    fn step(text: &str) -> PromptStep {
+        PromptStep {
+            name: "text".to_string(),
+            text: text.to_string(),
+            from_project: false,
+        }
+    }
+
+    #[test]
+    fn test_last_prompt_per_project() {
+        let root = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        let dir = root.join("last");
+        let (one, two) = (root.join("one"), root.join("two"));
+        std::fs::create_dir_all(&one).unwrap();
+        std::fs::create_dir_all(&two).unwrap();
+        assert_eq!(load(&Plain, &dir, &one).unwrap(), None);
+
+        save(&Plain, &dir, &one, &[step("review @this")], true).unwrap();
+        save(&Plain, &dir, &two, &[step("explain @this")], false).unwrap();
+        // The same project through a non-canonical path
+        let last = load(&Plain, &dir, &root.join("two/../one"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(last.steps, [step("review @this")]);
+        assert!(last.submit);
+
+        save(&Plain, &dir, &one, &[step("fix @diff")], true).unwrap();
+        let last = load(&Plain, &dir, &one).unwrap().unwrap();
+        let other = load(&Plain, &dir, &two).unwrap().unwrap();
+        let _ = std::fs::remove_dir_all(&root);
+        assert_eq!(last.steps, [step("fix @diff")]);
+        assert_eq!(other.steps, [step("explain @this")]);
+        assert!(!other.submit);
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_suggestion() {
+        let last = |steps: &[&str]| LastPrompt {
+            project: PathBuf::from("/project"),
+            steps: steps.iter().map(|text| step(text)).collect(),
+            submit: true,
+        };
+        let no_selection = |text: &str| !text.contains("@selection");
+
+        assert_eq!(suggestion(None, |_| true), None);
+        let single = last(&["review @this"]);
+        assert_eq!(
+            suggestion(Some(&single), no_selection),
+            Some("review @this")
+        );
+        let selection = last(&["explain @selection"]);
+        assert_eq!(suggestion(Some(&selection), no_selection), None);
+        assert_eq!(
+            suggestion(Some(&selection), |_| true),
+            Some("explain @selection")
+        );
+
+        // The latest usable step of a chain
+        let chain = last(&["review @this", "fix @selection", "  "]);
+        assert_eq!(suggestion(Some(&chain), no_selection), Some("review @this"));
+        assert_eq!(suggestion(Some(&chain), |_| true), Some("fix @selection"));
+        assert_eq!(suggestion(Some(&last(&[])), |_| true), None);
+    }
+}
@@ -0,0 +1,176 @@
+//! File language guessed from the path, for when Helix doesn't pass --language
+//!
+//! Names follow Helix's `%{language}` values, so a detected language and
+//! one passed on the command line look the same to the rest of the code.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// File extension (lowercase) and language
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("rs", "rust"),
+    ("py", "python"),
+    ("pyi", "python"),
+    ("ts", "typescript"),
+    ("mts", "typescript"),
+    ("cts", "typescript"),
+    ("tsx", "tsx"),
+    ("js", "javascript"),
+    ("mjs", "javascript"),
+    ("cjs", "javascript"),
+    ("jsx", "jsx"),
+    ("go", "go"),
+    ("c", "c"),
+    ("h", "c"),
+    ("cpp", "cpp"),
+    ("cc", "cpp"),
+    ("cxx", "cpp"),
+    ("hpp", "cpp"),
+    ("hh", "cpp"),
+    ("java", "java"),
+    ("kt", "kotlin"),
+    ("rb", "ruby"),
+    ("sh", "bash"),
+    ("bash", "bash"),
+    ("zsh", "bash"),
+    ("lua", "lua"),
+    ("zig", "zig"),
+    ("nix", "nix"),
+    ("html", "html"),
+    ("css", "css"),
+    ("json", "json"),
+    ("toml", "toml"),
+    ("yaml", "yaml"),
+    ("yml", "yaml"),
+    ("md", "markdown"),
+];
+
+/// Shebang interpreter (without a version suffix) and language
+const INTERPRETERS: &[(&str, &str)] = &[
+    ("sh", "bash"),
+    ("bash", "bash"),
+    ("zsh", "bash"),
+    ("dash", "bash"),
+    ("python", "python"),
+    ("node", "javascript"),
+    ("deno", "typescript"),
+    ("ruby", "ruby"),
+    ("perl", "perl"),
+    ("lua", "lua"),
+];
+
+/// Longest first line read when looking for a shebang
+const SHEBANG_MAX: u64 = 256;
+
+/// Language of the file at `path`, from its extension or else its shebang line
+///
+/// The file is only opened when the extension is missing or unknown.
+pub fn detect(path: &Path) -> Option<&'static str> {
+    from_extension(path).or_else(|| {
+        let mut head = Vec::new();
+        File::open(path)
+            .ok()?
+            .take(SHEBANG_MAX)
+            .read_to_end(&mut head)
+            .ok()?;
+        from_shebang(&String::from_utf8_lossy(&head))
+    })
+}
+
+/// Language for the extension of `path`
+pub fn from_extension(path: &Path) -> Option<&'static str> {
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    EXTENSIONS
+        .iter()
+        .find(|(known, _)| *known == ext)
+        .map(|(_, language)| *language)
+}
+
+/// Language for a `#!` line at the start of `head`
+///
+/// Handles both `#!/bin/bash` and `#!/usr/bin/env -S python3 -u`, and
+/// ignores version suffixes (`python3.12`).
+pub fn from_shebang(head: &str) -> Option<&'static str> {
+    let line = head.strip_prefix("#!")?.lines().next()?;
+    let mut words = line.split_whitespace();
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-'))?;
+    }
+    let name = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    INTERPRETERS
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(_, language)| *language)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_from_extension() {
+        let cases = [
+            ("src/main.rs", Some("rust")),
+            ("app.py", Some("python")),
+            ("index.ts", Some("typescript")),
+            ("App.tsx", Some("tsx")),
+            ("main.go", Some("go")),
+            ("lib.c", Some("c")),
+            ("lib.cpp", Some("cpp")),
+            ("Main.java", Some("java")),
+            ("app.rb", Some("ruby")),
+            ("build.sh", Some("bash")),
+            ("Cargo.toml", Some("toml")),
+            ("ci.yml", Some("yaml")),
+            ("README.MD", Some("markdown")),
+            ("notes.txt", None),
+            ("Makefile", None),
+            (".bashrc", None),
+        ];
+        for (path, language) in cases {
+            assert_eq!(from_extension(Path::new(path)), language, "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_from_shebang() {
+        assert_eq!(from_shebang("#!/bin/bash\necho hi\n"), Some("bash"));
+        assert_eq!(from_shebang("#!/bin/sh"), Some("bash"));
+        assert_eq!(from_shebang("#!/usr/bin/env python3\n"), Some("python"));
+        assert_eq!(from_shebang("#!/usr/bin/python3.12 -u\n"), Some("python"));
+        assert_eq!(
+            from_shebang("#!/usr/bin/env -S node --harmony\n"),
+            Some("javascript")
+        );
+        assert_eq!(from_shebang("#! /usr/bin/ruby\n"), Some("ruby"));
+        assert_eq!(from_shebang("#!/usr/bin/env\n"), None);
+        assert_eq!(from_shebang("#!/usr/bin/awk -f\n"), None);
+        assert_eq!(from_shebang("echo '#!/bin/bash'\n"), None);
+        assert_eq!(from_shebang(""), None);
+    }
+
+    #[test]
+    fn test_detect_fixtures() {
+        let dir = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("deploy");
+        fs::write(&script, "#!/usr/bin/env bash\nset -e\n").unwrap();
+        let plain = dir.join("LICENSE");
+        fs::write(&plain, "MIT License\n").unwrap();
+        // The extension wins over the contents
+        let module = dir.join("tool.py");
+        fs::write(&module, "#!/bin/sh\n").unwrap();
+
+        let detected = [
+            detect(&script),
+            detect(&plain),
+            detect(&module),
+            detect(&dir.join("missing")),
+        ];
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(detected, [Some("bash"), None, Some("python"), None]);
+    }
+}
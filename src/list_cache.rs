@@ -0,0 +1,238 @@
+//! The select menu's agent and command lists, cached between runs
+//!
+//! Both lists rarely change, yet fetching them held up every menu. The last
+//! responses are kept in `$XDG_CACHE_HOME/opencode-helix/lists/<port>.json`,
+//! with when they were fetched and their `ETag`/`Last-Modified`. The menu
+//! opens on them and is updated in place once the server has been asked
+//! again, the headers letting it answer "not modified" instead of resending
+//! the lists.
+//!
+//! An entry only serves the server it came from: one on the same port,
+//! serving the same directory. A server restarted in another directory on
+//! the port starts over. `select --refresh` skips the cache.
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::paths;
+use crate::server::client::{Lenient, Listed, Validators};
+use crate::server::Client;
+use crate::state::{Plain, StateStore};
+
+/// A list the menu shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Agents,
+    Commands,
+}
+
+impl Kind {
+    /// Endpoint listing it
+    fn path(self) -> &'static str {
+        match self {
+            Self::Agents => "/agent",
+            Self::Commands => "/command",
+        }
+    }
+}
+
+/// A list as the server sent it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedList {
+    /// The response's elements, unparsed so entries of a newer server survive
+    pub values: Vec<serde_json::Value>,
+    /// When the server last sent or confirmed it, in seconds since the epoch
+    pub fetched: u64,
+    /// Headers to ask the server whether it changed
+    #[serde(default)]
+    pub validators: Validators,
+}
+
+/// The lists cached for a server
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Directory the server was serving
+    pub cwd: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agents: Option<CachedList>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commands: Option<CachedList>,
+}
+
+impl Entry {
+    /// An empty entry for the server serving `cwd`
+    pub fn new(cwd: &Path) -> Self {
+        Self {
+            cwd: cwd.to_path_buf(),
+            ..Default::default()
+        }
+    }
+
+    /// The cached `kind` list, if it was fetched
+    pub fn list(&self, kind: Kind) -> Option<&CachedList> {
+        match kind {
+            Kind::Agents => self.agents.as_ref(),
+            Kind::Commands => self.commands.as_ref(),
+        }
+    }
+
+    fn list_mut(&mut self, kind: Kind) -> &mut Option<CachedList> {
+        match kind {
+            Kind::Agents => &mut self.agents,
+            Kind::Commands => &mut self.commands,
+        }
+    }
+
+    /// The `kind` list parsed as `T`, element by element; empty if it wasn't fetched
+    pub fn parsed<T: DeserializeOwned>(&self, kind: Kind) -> Lenient<T> {
+        self.list(kind)
+            .map(|list| Lenient::from_values(list.values.clone()))
+            .unwrap_or_default()
+    }
+
+    /// Seconds since the oldest of the `kinds` lists was fetched
+    pub fn age(&self, kinds: &[Kind], now: u64) -> u64 {
+        kinds
+            .iter()
+            .filter_map(|&kind| self.list(kind))
+            .map(|list| now.saturating_sub(list.fetched))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Ask the server for the `kind` list again, with the cached one's
+    /// headers; returns whether it changed
+    pub async fn refresh(&mut self, client: &Client, kind: Kind) -> Result<bool> {
+        let since = self
+            .list(kind)
+            .map(|list| list.validators.clone())
+            .unwrap_or_default();
+        let listed = client.get_list(kind.path(), &since).await?;
+        let cached = self.list_mut(kind);
+        match (listed, cached) {
+            (Listed::NotModified, Some(list)) => {
+                list.fetched = now();
+                Ok(false)
+            }
+            (Listed::NotModified, None) => {
+                bail!("{} answered 304 without a cached list", kind.path())
+            }
+            (Listed::Fresh(values, validators), cached) => {
+                let changed = cached.as_ref().is_none_or(|list| list.values != values);
+                *cached = Some(CachedList {
+                    values,
+                    fetched: now(),
+                    validators,
+                });
+                Ok(changed)
+            }
+        }
+    }
+}
+
+/// Cache directory of the lists (`lists/` in the cache directory)
+pub fn cache_dir() -> PathBuf {
+    paths::cache_dir().join("lists")
+}
+
+/// The lists cached for the server on `port` serving `cwd`
+///
+/// None when there are none, they were for a server serving another
+/// directory, or the file can't be read.
+pub fn load(dir: &Path, port: u16, cwd: &Path) -> Option<Entry> {
+    let text = Plain.read(&entry_path(dir, port)).ok()??;
+    let entry: Entry = serde_json::from_str(&text).ok()?;
+    (entry.cwd == cwd).then_some(entry)
+}
+
+/// Cache the lists of the server on `port`, replacing what it had
+pub fn save(dir: &Path, port: u16, entry: &Entry) -> Result<()> {
+    let text = serde_json::to_string(entry).context("Failed to serialize the cached lists")?;
+    Plain.write(&entry_path(dir, port), &text)
+}
+
+/// Seconds since the epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Entry file for the server on `port`
+fn entry_path(dir: &Path, port: u16) -> PathBuf {
+    dir.join(format!("{}.json", port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::client::{Agent, Command};
+
+    fn list(values: serde_json::Value) -> CachedList {
+        CachedList {
+            values: serde_json::from_value(values).unwrap(),
+            fetched: 1_700_000_000,
+            validators: Validators {
+                etag: Some("\"v1\"".to_string()),
+                last_modified: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_entry_round_trip() {
+        let entry = Entry {
+            agents: Some(list(
+                serde_json::json!([{"name": "build", "mode": "primary"}]),
+            )),
+            commands: Some(list(
+                serde_json::json!([{"name": "test", "template": "Run the tests"}]),
+            )),
+            ..Entry::new(Path::new("/home/u/project"))
+        };
+        let json = serde_json::to_string(&entry).unwrap();
+        assert!(json.contains(r#""etag":"\"v1\"""#), "{}", json);
+        assert!(!json.contains("last_modified"), "{}", json);
+        assert_eq!(serde_json::from_str::<Entry>(&json).unwrap(), entry);
+
+        let agents: Lenient<Agent> = entry.parsed(Kind::Agents);
+        assert_eq!(agents.items[0].name, "build");
+        let commands: Lenient<Command> = entry.parsed(Kind::Commands);
+        assert_eq!(commands.items[0].template, "Run the tests");
+        assert_eq!(entry.age(&[Kind::Agents], 1_700_000_060), 60);
+
+        // An entry written before a list was cached
+        let old: Entry = serde_json::from_str(r#"{"cwd":"/p","agents":null}"#).unwrap();
+        assert_eq!(old, Entry::new(Path::new("/p")));
+        assert!(old.parsed::<Agent>(Kind::Agents).items.is_empty());
+    }
+
+    #[test]
+    fn test_load_per_server() {
+        let dir = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        let project = Path::new("/home/u/project");
+        assert_eq!(load(&dir, 4096, project), None);
+
+        let entry = Entry {
+            agents: Some(list(serde_json::json!([{"name": "build"}]))),
+            ..Entry::new(project)
+        };
+        save(&dir, 4096, &entry).unwrap();
+        assert_eq!(load(&dir, 4096, project), Some(entry.clone()));
+        assert_eq!(load(&dir, 4097, project), None);
+
+        // The server was restarted in another directory on the same port
+        assert_eq!(load(&dir, 4096, Path::new("/home/u/other")), None);
+        save(&dir, 4096, &Entry::new(Path::new("/home/u/other"))).unwrap();
+        assert_eq!(load(&dir, 4096, project), None);
+
+        // A damaged file is a miss
+        std::fs::write(entry_path(&dir, 4096), "{").unwrap();
+        assert_eq!(load(&dir, 4096, Path::new("/home/u/other")), None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
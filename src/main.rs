@@ -1,27 +1,77 @@
 //! opencode-helix: External TUI for integrating opencode AI with Helix editor
 
+#[macro_use]
+mod output;
+
+mod agent;
+#[cfg(feature = "tui")]
+mod attach;
+mod bundle;
 mod cli;
+mod cmd;
+#[cfg(feature = "tui")]
+mod collapse;
 mod config;
 mod context;
+mod context_file;
+mod docs;
+mod exit;
+mod file_content;
+mod history;
+mod language;
+#[cfg(feature = "tui")]
+mod list_cache;
+#[cfg(feature = "tui")]
+mod menu;
+mod notify;
+#[cfg(feature = "tui")]
+mod onboarding;
+mod paths;
+mod policy;
+mod publish_cache;
+mod queue;
+mod register;
+mod reply;
+mod report;
 mod server;
+mod setup;
+mod state;
+mod stats;
+mod temp_files;
+mod title;
+#[cfg(feature = "tui")]
 mod tui;
+mod util;
+mod version;
+mod watch;
+mod workspace;
 
 use anyhow::{Context, Result};
-use cli::{Cli, Command};
+use clap::CommandFactory;
+#[cfg(feature = "tui")]
+use cli::SelectCategory;
+use cli::{Cli, Command, OutputFormat};
 use context::Context as EditorContext;
-use tui::app::{App, AppResult, SelectItem};
+use exit::{ErrorKind, Outcome};
+#[cfg(feature = "tui")]
+use menu::SelectItem;
+use policy::Ask;
+#[cfg(feature = "tui")]
+use tui::app::{App, AppResult};
+#[cfg(feature = "tui")]
 use tui::theme::ThemeKind;
 
-const DEBUG_LOG_PATH: &str = "/tmp/opencode-helix-debug.log";
-
 /// Write debug info to log file if debug mode is enabled
 fn debug_log(debug: bool, msg: &str) {
     if debug {
         use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+        // The log holds selections and prompts: owner only
         if let Ok(mut file) = std::fs::OpenOptions::new()
             .create(true)
             .append(true)
-            .open(DEBUG_LOG_PATH)
+            .mode(0o600)
+            .open(paths::debug_log())
         {
             let timestamp = chrono::Local::now().format("%H:%M:%S%.3f");
             let _ = writeln!(file, "[{}] {}", timestamp, msg);
@@ -30,204 +80,2382 @@ fn debug_log(debug: bool, msg: &str) {
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let cli = Cli::parse_args();
-    let debug = cli.debug;
-    let cwd = cli.working_directory();
-    let ctx = EditorContext::from_cli(&cli);
-    let theme = ThemeKind::from_str(&cli.theme);
-    let animations = !cli.no_anim;
+async fn main() {
+    output::ignore_sigpipe();
+
+    let cli = match Cli::try_parse_args() {
+        Ok(cli) => cli,
+        Err(e) => {
+            let _ = e.print();
+            std::process::exit(exit::usage_exit_code(&e));
+        }
+    };
+    let quiet = cli.quiet;
+    let json = cli.output == OutputFormat::Json;
+
+    let result = run(cli).await;
+    match &result {
+        Ok(Outcome::Done(Some(msg))) if !quiet => errln!("{}", msg),
+        Ok(Outcome::Cancelled) if !quiet => errln!("Cancelled"),
+        Err(e) if json => errln!("{}", report::error_json(e)),
+        Err(e) => errln!("Error: {:?}", e),
+        _ => {}
+    }
+
+    std::process::exit(exit::exit_code(&result));
+}
+
+/// Load the configuration, run the subcommand and record its stats
+async fn run(mut cli: Cli) -> Result<Outcome> {
+    if cli.version {
+        return run_version(cli.json);
+    }
+    if let Some(path) = cli.context_file.clone() {
+        context_file::ContextFile::read(&path)
+            .context(ErrorKind::Usage)?
+            .apply(&mut cli);
+    }
+    // No subcommand: the first-run setup
+    let Some(command) = cli.command.take() else {
+        return run_onboarding(&cli).await;
+    };
+    // `ask --loop` goes on expanding the selection after it was read
+    cli.keep_selection_file = matches!(command, Command::Ask { repeat: true, .. });
+    // `report` reads the last run's log, so it must not replace it
+    let debug = cli.debug && !matches!(command, Command::Report { .. });
 
     if debug {
-        // Clear previous debug log
-        let _ = std::fs::write(DEBUG_LOG_PATH, "");
+        clear_debug_log();
+        let cmd = Cli::command();
+        let subcommands: Vec<&str> = cmd.get_subcommands().map(|sub| sub.get_name()).collect();
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        debug_log(
+            debug,
+            &format!(
+                "Invocation: {}",
+                bundle::invocation_shape(&args, &subcommands)
+            ),
+        );
         debug_log(debug, &format!("CLI args: {:?}", cli));
-        debug_log(debug, &format!("Context: {:?}", ctx));
-        debug_log(debug, &format!("CWD: {:?}", cwd));
-        debug_log(debug, &format!("Theme: {:?}", theme));
-        debug_log(debug, &format!("Animations: {}", animations));
     }
 
+    let config = match config::UserConfig::load(cli.config.as_deref()) {
+        Ok(config) => config,
+        // A config named with --config or OPENCODE_HELIX_CONFIG must load
+        Err(e) if paths::explicit_config(cli.config.as_deref()).is_some() => {
+            return Err(e).context(ErrorKind::Usage);
+        }
+        Err(e) => {
+            debug_log(debug, &format!("Ignoring user config: {:#}", e));
+            config::UserConfig::default()
+        }
+    };
+    cli.find_workspace(&config.root_markers());
+    let cwd = cli.working_directory();
+    if let Some(max_age) = config.temp_file_max_age() {
+        clean_temp_files(max_age, cli.quiet, debug);
+    }
+    // Register files are read (and deleted) here, within the configured size
+    let mut ctx = EditorContext::from_cli(&cli, config.max_file_bytes());
+    let _selection_file = context::KeptSelectionFile::new(&cli);
+    debug_log(debug, &format!("Context: {:?}", ctx));
+    debug_log(debug, &format!("CWD: {:?}", cwd));
+    for warning in ctx.validate() {
+        debug_log(debug, &format!("Context warning: {:?}", warning));
+        if !cli.quiet {
+            errln!("Warning: {}", warning);
+        }
+    }
+
+    // Interactive commands need the TUI; fail before discovery
+    #[cfg(not(feature = "tui"))]
+    if command.needs_tui() {
+        return Err(anyhow::anyhow!("built without TUI support")).context(ErrorKind::Usage);
+    }
+
+    let project = load_project(&cwd, cli.quiet, debug);
+    let prompts = load_prompts(&config, project.as_ref(), cli.quiet);
+    ctx.max_selection_lines = config.max_selection_lines();
+
+    // Listing and stats don't need a server
+    match command {
+        Command::List { what, json } => return run_list(what, json, &prompts),
+        Command::Context {
+            ref name_or_text,
+            ref extra,
+            explain,
+        } => return run_context(&prompts, &ctx, name_or_text, extra, explain),
+        Command::Stats { clear } => return run_stats(clear, &*state::store(config.encrypt_state)),
+        Command::State { action } => return run_state(action, &config),
+        Command::Report { ref out_dir } => {
+            let auth = server_auth(&cli, project.as_ref()).unwrap_or_default();
+            return run_report(
+                out_dir.as_deref(),
+                &cwd,
+                cli.port,
+                cli.discovery_timeout,
+                auth,
+            )
+            .await;
+        }
+        Command::Setup { editor, ref leader } => return Ok(run_setup(editor, leader)),
+        Command::GenDocs {
+            format,
+            ref out_dir,
+        } => return run_gen_docs(format, out_dir),
+        _ => {}
+    }
+
+    let auth = server_auth(&cli, project.as_ref()).context(ErrorKind::Usage)?;
+    // Confirmed before the dialogs take the terminal
+    if cli.override_policy && !policy::confirm_override()? {
+        return Ok(Outcome::Cancelled);
+    }
+
+    let (print, max_wait) = match command {
+        Command::Prompt {
+            print,
+            max_wait,
+            raw,
+            first_code_block,
+            ..
+        } => {
+            let format = match (raw, first_code_block) {
+                (true, _) => reply::ReplyFormat::Raw,
+                (_, true) => reply::ReplyFormat::FirstCodeBlock,
+                _ => reply::ReplyFormat::Text,
+            };
+            (print.then_some(format), max_wait)
+        }
+        _ => (None, None),
+    };
+    if print.is_some() && cli.output == OutputFormat::Json {
+        return Err(anyhow::anyhow!("--print can't be used with --output json"))
+            .context(ErrorKind::Usage);
+    }
+    if print.is_some() && matches!(command, Command::Prompt { submit: false, .. }) {
+        return Err(anyhow::anyhow!("--print needs the prompt submitted"))
+            .context(ErrorKind::Usage);
+    }
+
+    let send_opts = SendOptions {
+        auth,
+        wait: cli.wait || print.is_some() || max_wait.is_some(),
+        print,
+        max_wait,
+        notify: cli.notify,
+        chain: !cli.no_chain,
+        queue: cli.queue,
+        clear: cli.clear_prompt || cli.force_clear,
+        force_clear: cli.force_clear,
+        allow_cmd: cli.allow_cmd,
+        strict: cli.strict_placeholders || config.strict_placeholders,
+        quiet: cli.quiet,
+        output: cli.output,
+        flag_route: agent::Route {
+            agent: cli
+                .agent
+                .as_deref()
+                .map(|name| name.trim_start_matches('@').to_string()),
+            model: cli.model.clone(),
+        },
+        project_route: agent::Route {
+            agent: project
+                .as_ref()
+                .and_then(|p| p.default_agent.as_deref())
+                .map(|name| name.trim_start_matches('@').to_string()),
+            model: project.as_ref().and_then(|p| p.default_model.clone()),
+        },
+        title: cli.title.clone(),
+        auto_title: !cli.no_auto_title,
+        override_policy: cli.override_policy,
+        project: cwd,
+        store: state::store(config.encrypt_state),
+        config,
+        prompts,
+        usage: stats::Usage::default(),
+    };
+
+    let name = command.name();
+    let started = std::time::Instant::now();
+    let result = run_command(cli, command, &ctx, &send_opts, debug).await;
+
+    if send_opts.config.stats {
+        let status = match result {
+            Ok(Outcome::Done(_)) => stats::Status::Ok,
+            Ok(Outcome::Cancelled) => stats::Status::Cancelled,
+            Err(_) => stats::Status::Failed,
+        };
+        let record = send_opts
+            .usage
+            .record(name, &send_opts.project, status, started.elapsed());
+        if let Err(e) = stats::append(&*send_opts.store, &stats::stats_path(), &record) {
+            debug_log(debug, &format!("Stats not recorded: {:#}", e));
+        }
+    }
+    result
+}
+
+/// Await `discovery`, with a status line on stderr if it takes a moment
+/// (not with --quiet, nor when stderr isn't a terminal)
+async fn discover<T>(cli: &Cli, discovery: impl std::future::Future<Output = T>) -> T {
+    use std::io::IsTerminal;
+    if cli.quiet || !std::io::stderr().is_terminal() {
+        return discovery.await;
+    }
+    output::with_status(
+        std::io::stderr(),
+        output::STATUS_DELAY,
+        "discovering opencode server…",
+        discovery,
+    )
+    .await
+}
+
+/// Discover the server and dispatch the subcommand
+async fn run_command(
+    cli: Cli,
+    command: Command,
+    ctx: &EditorContext,
+    send_opts: &SendOptions,
+    debug: bool,
+) -> Result<Outcome> {
+    #[cfg(feature = "tui")]
+    let theme = ThemeKind::from_str(&cli.theme);
+    #[cfg(feature = "tui")]
+    let animations = !cli.no_anim;
+    #[cfg(feature = "tui")]
+    let effects = animations
+        && send_opts
+            .config
+            .dialog_effects
+            .enabled(tui::effects::slow_terminal(|var| std::env::var(var).ok()));
+    #[cfg(feature = "tui")]
+    debug_log(
+        debug,
+        &format!(
+            "Theme: {:?}, animations: {}, effects: {}",
+            theme, animations, effects
+        ),
+    );
+
     // Discover the opencode server
-    let server = server::discover_server(&cwd, cli.port)
-        .await
-        .context("Failed to find opencode server")?;
+    let started = std::time::Instant::now();
+    let server = discover(
+        &cli,
+        server::discover_server(
+            &send_opts.project,
+            cli.port,
+            send_opts.auth.as_ref(),
+            cli.discovery_timeout,
+        ),
+    )
+    .await
+    .context(ErrorKind::NoServer)?;
+    send_opts.usage.discovered(started.elapsed());
 
     debug_log(
         debug,
         &format!("Server found: port={}, cwd={:?}", server.port, server.cwd),
     );
 
-    let client = server::Client::new(server.port);
+    let publish_dir = publish_cache::cache_dir();
+    let batch_publish = publish_cache::load(&publish_dir, server.port, &server.cwd);
+    let client = server::Client::new(server.port)
+        .with_auth(send_opts.auth.clone())
+        .with_session(cli.session.clone())
+        .with_batch_publish(batch_publish);
+    let client = routed(&client, &send_opts.route(&agent::Route::default()), debug);
+
+    // Fail early with a version error rather than a raw HTTP failure
+    if cli.session.is_some() {
+        client
+            .require(server::Feature::Sessions)
+            .await
+            .context(ErrorKind::Server)?;
+    }
+    if cli.wait || matches!(command, Command::Watch { .. }) {
+        client
+            .require(server::Feature::Events)
+            .await
+            .context(ErrorKind::Server)?;
+    }
+    if let Some(name) = client.agent() {
+        if command.sends() && !cli.no_validate_agent {
+            let agents = client
+                .get_agents()
+                .await
+                .context("Could not check the agent (pass --no-validate-agent to skip the check)")
+                .context(ErrorKind::Server)?;
+            agent::check(name, &agents.items).context(ErrorKind::Usage)?;
+        }
+    }
+
+    #[cfg(feature = "tui")]
+    let ui = UiOptions {
+        theme,
+        animations,
+        effects,
+        term_integration: !cli.no_term_integration,
+        cursor_shape: send_opts.config.cursor_shape,
+        inline: cli.inline || send_opts.config.inline,
+        strings: tui::strings::Strings::load(
+            &send_opts.config.strings,
+            |var| std::env::var(var).ok(),
+            debug,
+        )
+        .context(ErrorKind::Usage)?,
+        target: if command.needs_tui() {
+            Some(dialog_target(&server, &client, ctx, debug).await)
+        } else {
+            None
+        },
+        agents: if command.needs_tui() {
+            dialog_agents(&server, &client, debug).await
+        } else {
+            Vec::new()
+        },
+        models: if command.needs_tui() && client.session().is_some() {
+            dialog_models(&client, debug).await
+        } else {
+            Vec::new()
+        },
+    };
+
+    let outcome = match command {
+        #[cfg(feature = "tui")]
+        Command::Ask { initial, repeat } => {
+            run_ask(&client, ctx, &initial, repeat, send_opts, &ui, debug).await
+        }
+        #[cfg(feature = "tui")]
+        Command::Select {
+            filter,
+            category,
+            auto,
+            refresh,
+        } => {
+            let select_opts = SelectOptions {
+                filter: filter.as_deref().unwrap_or_default(),
+                category,
+                auto,
+                refresh,
+                server_cwd: &server.cwd,
+            };
+            run_select(&client, ctx, &select_opts, send_opts, &ui, debug).await
+        }
+        Command::Prompt {
+            name_or_text,
+            extra,
+            name,
+            submit,
+            ..
+        } => {
+            let steps = config::resolve_prompt(
+                &send_opts.prompts,
+                &name_or_text,
+                &extra,
+                name,
+                ctx.language_or_detected(),
+            )
+            .context(ErrorKind::Usage)?;
+            run_prompt(&client, ctx, &steps, submit, send_opts, debug).await
+        }
+        Command::Status {
+            print_server_env: true,
+            ..
+        } => {
+            outln!("{}", server::server_env(&server).trim_end());
+            Ok(Outcome::Done(None))
+        }
+        Command::Status { json, .. } => run_status(&server, &client, json, cli.quiet).await,
+        Command::Flush => run_flush(&client, send_opts, debug).await,
+        Command::Watch { json } => run_watch(&client, json, send_opts, debug).await,
+        #[cfg(feature = "tui")]
+        Command::Resend { edit: true } => {
+            let last = last_prompt(send_opts)?;
+            run_ask(
+                &client,
+                ctx,
+                &last.steps[0].text,
+                false,
+                send_opts,
+                &ui,
+                debug,
+            )
+            .await
+        }
+        Command::Resend { .. } => {
+            let last = last_prompt(send_opts)?;
+            send_steps(
+                &client,
+                ctx,
+                &last.steps,
+                last.submit,
+                send_opts,
+                None,
+                debug,
+            )
+            .await
+        }
+        Command::List { .. }
+        | Command::Context { .. }
+        | Command::Stats { .. }
+        | Command::State { .. }
+        | Command::Report { .. }
+        | Command::Setup { .. }
+        | Command::GenDocs { .. } => {
+            unreachable!(
+                "list, context, stats, state, report, setup and gen-docs are handled before discovery"
+            )
+        }
+        #[cfg(feature = "tui")]
+        Command::Resume { limit } => run_resume(&client, limit, &ui, debug).await,
+        #[cfg(not(feature = "tui"))]
+        Command::Ask { .. } | Command::Select { .. } | Command::Resume { .. } => {
+            unreachable!("interactive commands are rejected before discovery")
+        }
+    };
+
+    // Later runs send the way this one found the server takes events
+    if let Some(batch) = client.batch_publish().filter(|&b| Some(b) != batch_publish) {
+        debug_log(debug, &format!("publish mode: batch={}", batch));
+        if let Err(e) = publish_cache::save(&publish_dir, server.port, &server.cwd, batch) {
+            debug_log(debug, &format!("publish mode not cached: {:#}", e));
+        }
+    }
+    outcome
+}
+
+/// Options controlling how prompts are sent
+struct SendOptions {
+    /// Credentials for a server that requires them
+    auth: Option<server::Auth>,
+    /// Wait for the session to go idle after sending
+    wait: bool,
+    /// Print the reply to stdout once idle (`prompt --print`)
+    print: Option<reply::ReplyFormat>,
+    /// Give up waiting after this long (`prompt --max-wait`)
+    max_wait: Option<std::time::Duration>,
+    /// Notify on completion of a wait
+    notify: bool,
+    /// Send every step of a chained prompt (false with --no-chain)
+    chain: bool,
+    /// Hold prompts for a busy session and send them once it is idle
+    queue: bool,
+    /// Clear the opencode prompt box before sending
+    clear: bool,
+    /// Clear without checking what the prompt box holds
+    force_clear: bool,
+    /// Run `@cmd:` placeholders without asking (--allow-cmd)
+    allow_cmd: bool,
+    /// Refuse to send placeholders left as typed (--strict-placeholders)
+    strict: bool,
+    /// Suppress chain and queue progress on stderr
+    quiet: bool,
+    /// Report each sent prompt on stdout
+    output: OutputFormat,
+    /// Agent every prompt is handed to (--agent, without the `@`) and the
+    /// model of --session messages (--model)
+    flag_route: agent::Route,
+    /// The project config's `default_agent` and `default_model`, under the flags
+    project_route: agent::Route,
+    /// Title for the --session (--title)
+    title: Option<String>,
+    /// Name an untitled --session after its first prompt
+    auto_title: bool,
+    /// Ignore the config's `[policy]` (--override-policy, confirmed)
+    override_policy: bool,
+    /// Project directory the last sent prompt is remembered for
+    project: std::path::PathBuf,
+    /// Where history, collapsed categories, stats and the queue are kept
+    store: Box<dyn state::StateStore>,
+    /// User configuration
+    config: config::UserConfig,
+    /// Built-in, user and project prompts
+    prompts: config::PromptRegistry,
+    /// What was sent, for the usage stats
+    usage: stats::Usage,
+}
+
+impl SendOptions {
+    /// The agent and model prompts go to, with `dialog` the ones picked in the ask dialog
+    fn route(&self, dialog: &agent::Route) -> agent::Route {
+        agent::Route::resolve(&self.flag_route, dialog, &self.project_route)
+    }
+}
+
+/// `client` sending to `route`'s agent and model
+/// The model only applies to --session messages, so without a session it is dropped.
+fn routed(client: &server::Client, route: &agent::Route, debug: bool) -> server::Client {
+    let model = match (&route.model, client.session()) {
+        (Some(model), None) => {
+            debug_log(
+                debug,
+                &format!("Model {} ignored: it applies to --session messages", model),
+            );
+            None
+        }
+        (model, _) => model.clone(),
+    };
+    client
+        .clone()
+        .with_agent(route.agent.clone())
+        .with_model(model)
+}
+
+/// The nearest project config
+/// A broken one is reported and skipped rather than failing the command.
+fn load_project(cwd: &std::path::Path, quiet: bool, debug: bool) -> Option<config::ProjectConfig> {
+    let project = config::ProjectConfig::load(cwd).unwrap_or_else(|e| {
+        if !quiet {
+            errln!("Warning: ignoring project config: {:#}", e);
+        }
+        None
+    });
+    debug_log(debug, &format!("Project config: {:?}", project));
+    project
+}
+
+/// Auth header from --auth-token (or its environment variable), else the project config
+fn server_auth(cli: &Cli, project: Option<&config::ProjectConfig>) -> Result<Option<server::Auth>> {
+    let token = cli
+        .auth_token
+        .as_ref()
+        .or(project.and_then(|p| p.auth_token.as_ref()));
+    let header = project.and_then(|p| p.auth_header.as_deref());
+    token
+        .map(|token| server::Auth::new(token, header))
+        .transpose()
+}
+
+/// Merge built-in, user and project prompts
+/// Invalid project prompts are reported and skipped rather than failing the command.
+fn load_prompts(
+    user: &config::UserConfig,
+    project: Option<&config::ProjectConfig>,
+    quiet: bool,
+) -> config::PromptRegistry {
+    let registry = config::PromptRegistry::new(user, project).context("Invalid project prompts");
+    registry.unwrap_or_else(|e| {
+        if !quiet {
+            errln!("Warning: ignoring project config: {:#}", e);
+        }
+        config::PromptRegistry::new(user, None).unwrap_or_default()
+    })
+}
+
+/// Options for the interactive dialogs
+#[cfg(feature = "tui")]
+struct UiOptions {
+    theme: ThemeKind,
+    animations: bool,
+    /// Open animation and focus flash
+    effects: bool,
+    /// Set the terminal title and cursor shape
+    term_integration: bool,
+    /// Cursor shape instead of the theme's
+    cursor_shape: Option<config::CursorShape>,
+    /// Draw in the bottom rows of the screen instead of the alternate screen
+    inline: bool,
+    /// Text of the dialogs, maybe translated
+    strings: tui::strings::Strings,
+    /// Server (and session) shown in the dialog titles
+    target: Option<tui::target::Target>,
+    /// Agents the server has, so the ask dialog doesn't take mentions of them for typos
+    agents: Vec<String>,
+    /// Models (`provider/model`) Alt+A offers for a --session message
+    models: Vec<String>,
+}
+
+/// What the select menu lists and how it starts
+#[cfg(feature = "tui")]
+struct SelectOptions<'a> {
+    /// Initial filter text
+    filter: &'a str,
+    /// Only list this section
+    category: Option<SelectCategory>,
+    /// Act on a single match without opening the menu
+    auto: bool,
+    /// Fetch the agents and commands rather than open on the cached lists
+    refresh: bool,
+    /// Directory the server serves, which the cached lists must match
+    server_cwd: &'a std::path::Path,
+}
+
+/// Names of the server's agents, from the select menu's cache or else asked for
+///
+/// None are known if the server can't list them; the dialog then flags
+/// every agent mention, and sending one takes a confirmation.
+#[cfg(feature = "tui")]
+async fn dialog_agents(
+    server: &server::Server,
+    client: &server::Client,
+    debug: bool,
+) -> Vec<String> {
+    let kinds = [list_cache::Kind::Agents];
+    let dir = list_cache::cache_dir();
+    let entry = match list_cache::load(&dir, client.port(), &server.cwd) {
+        Some(entry) if entry.list(list_cache::Kind::Agents).is_some() => entry,
+        cached => {
+            let mut entry = cached.unwrap_or_else(|| list_cache::Entry::new(&server.cwd));
+            if let Err(e) = entry.refresh(client, list_cache::Kind::Agents).await {
+                debug_log(debug, &format!("dialog_agents: not listed: {:#}", e));
+            }
+            save_lists(client.port(), &entry, &kinds, debug);
+            entry
+        }
+    };
+    let agents: server::client::Lenient<server::client::Agent> =
+        entry.parsed(list_cache::Kind::Agents);
+    agents.items.into_iter().map(|agent| agent.name).collect()
+}
+
+/// Models the server offers, or none if it can't list them
+#[cfg(feature = "tui")]
+async fn dialog_models(client: &server::Client, debug: bool) -> Vec<String> {
+    match client.get_models().await {
+        Ok(models) => models.iter().map(ToString::to_string).collect(),
+        Err(e) => {
+            debug_log(debug, &format!("dialog_models: not listed: {:#}", e));
+            Vec::new()
+        }
+    }
+}
+
+/// Describe the server the dialogs send to, with the --session title if it can be found
+#[cfg(feature = "tui")]
+async fn dialog_target(
+    server: &server::Server,
+    client: &server::Client,
+    ctx: &EditorContext,
+    debug: bool,
+) -> tui::target::Target {
+    let session = match client.session() {
+        Some(id) => {
+            let title = match client.list_sessions().await {
+                Ok(sessions) => sessions
+                    .into_iter()
+                    .find(|s| s.id == id && !s.title.is_empty())
+                    .map(|s| s.title),
+                Err(e) => {
+                    debug_log(debug, &format!("dialog_target: no session list: {:#}", e));
+                    None
+                }
+            };
+            Some(title.unwrap_or_else(|| id.to_string()))
+        }
+        None => None,
+    };
+    let file = ctx.file_absolute.as_deref().map(std::path::Path::new);
+    tui::target::Target::new(server.port, &server.cwd, session, file)
+}
+
+/// Send a prompt, optionally waiting for the session to finish
+///
+/// Returns the reply if it waited and saw one.
+async fn send(
+    gate: &policy::SendGate<'_>,
+    text: &str,
+    parts: Option<&[server::MessagePart]>,
+    clear: bool,
+    submit: bool,
+    opts: &SendOptions,
+    debug: bool,
+) -> Result<Option<String>> {
+    if !opts.wait {
+        let (mode, _) = deliver(gate, text, parts, clear, submit, opts).await?;
+        delivered(mode, opts, debug);
+        return Ok(None);
+    }
+
+    let started = std::time::Instant::now();
+    let reply = send_and_wait(gate, text, parts, clear, submit, opts, debug).await?;
+    let elapsed = started.elapsed().as_secs();
+    debug_log(debug, &format!("send: idle after {}s", elapsed));
+
+    if opts.config.should_notify(opts.notify, elapsed) {
+        notify::notify_done(reply.as_deref());
+    }
+
+    Ok(reply)
+}
+
+/// Send a prompt and wait for the session to go idle, returning the reply if seen
+async fn send_and_wait(
+    gate: &policy::SendGate<'_>,
+    text: &str,
+    parts: Option<&[server::MessagePart]>,
+    clear: bool,
+    submit: bool,
+    opts: &SendOptions,
+    debug: bool,
+) -> Result<Option<String>> {
+    // Subscribe before sending so the idle event can't be missed
+    let client = gate.client();
+    let mut events = client.subscribe_events().await.context(ErrorKind::Server)?;
+
+    let (mode, submitted) = deliver(gate, text, parts, clear, submit, opts).await?;
+    delivered(mode, opts, debug);
+    // An appended prompt leaves the session idle; there is nothing to wait for
+    if !submitted {
+        debug_log(debug, "send: appended, not waiting");
+        return Ok(None);
+    }
+
+    debug_log(debug, "send: waiting for session to go idle");
+    let session = client.session().map(str::to_string);
+    let keepalive = opts.config.keepalive();
+    let idle =
+        server::events::wait_for_idle(&mut events, session, keepalive, || ping(client, debug));
+    let Some(max_wait) = opts.max_wait else {
+        return idle.await.context(ErrorKind::Server);
+    };
+    match tokio::time::timeout(max_wait, idle).await {
+        Ok(reply) => reply.context(ErrorKind::Server),
+        Err(_) => Err(anyhow::anyhow!(
+            "The session was still busy after {}s (--max-wait)",
+            max_wait.as_secs()
+        ))
+        .context(ErrorKind::NoReply),
+    }
+}
+
+/// Keepalive probe while waiting on events; only failures are logged
+async fn ping(client: &server::Client, debug: bool) -> Result<()> {
+    client.get_path().await.map(drop).inspect_err(|e| {
+        debug_log(debug, &format!("keepalive: probe failed: {:#}", e));
+    })
+}
+
+/// Send a prompt through `gate`, reporting the progress of a chunked send
+///
+/// Returns how it went and whether it was submitted.
+async fn deliver(
+    gate: &policy::SendGate<'_>,
+    text: &str,
+    parts: Option<&[server::MessagePart]>,
+    clear: bool,
+    submit: bool,
+    opts: &SendOptions,
+) -> Result<(server::PublishMode, bool)> {
+    gate.send(text, parts, clear, submit, |n, total| {
+        if !opts.quiet {
+            errln!("sending {}/{}…", n, total);
+        }
+    })
+    .await
+}
+
+/// The gate every prompt is sent through, with `ask` to confirm in
+fn send_gate<'a>(
+    client: &'a server::Client,
+    opts: &'a SendOptions,
+    ask: Ask<'a>,
+) -> policy::SendGate<'a> {
+    policy::SendGate::new(client, &opts.config.policy)
+        .with_agent(client.agent())
+        .with_override(opts.override_policy)
+        .with_ask(ask)
+        .with_chunk_size(opts.config.chunk_size())
+        .with_quiet(opts.quiet)
+}
+
+/// Whether to resume a chunked send that broke off, asked in the TUI modes
+fn retry_chunks(partial: &server::PartialSend, ask: Ask<'_>) -> Result<bool> {
+    let Some(ask) = ask else {
+        return Ok(false);
+    };
+    let question = format!(
+        "{} ({:#}). The opencode prompt box holds the chunks sent so far. Retry from chunk {}?",
+        partial,
+        partial.error,
+        partial.sent + 1
+    );
+    Ok(ask(&question, &["Retry", "Cancel"])? == Some(0))
+}
+
+/// Log how a prompt was delivered, warning if its file parts were dropped
+fn delivered(mode: server::PublishMode, opts: &SendOptions, debug: bool) {
+    debug_log(debug, &format!("send: delivered as {}", mode));
+    if mode == server::PublishMode::SessionText && !opts.quiet {
+        errln!("Warning: the server rejected the file attachments; sent the prompt as text");
+    }
+}
+
+/// Decide whether a --clear-prompt send may clear the opencode prompt box
+///
+/// Returns Some(true) to clear, Some(false) to append instead, or None if the
+/// user cancelled. Text the server reports in the box is never cleared without
+/// asking (or --force-clear); when the server can't report it, the
+/// `clear_behavior` config decides.
+async fn check_clear(
+    client: &server::Client,
+    opts: &SendOptions,
+    ask: Ask<'_>,
+    debug: bool,
+) -> Result<Option<bool>> {
+    if opts.force_clear {
+        debug_log(debug, "check_clear: --force-clear, overwriting");
+        return Ok(Some(true));
+    }
+
+    let current = client.tui_prompt_text().await.unwrap_or_else(|e| {
+        debug_log(
+            debug,
+            &format!("check_clear: prompt text unavailable: {:#}", e),
+        );
+        None
+    });
+    debug_log(
+        debug,
+        &format!("check_clear: prompt box holds {:?}", current),
+    );
+    let behavior = match current {
+        Some(text) if text.trim().is_empty() => config::ClearBehavior::Overwrite,
+        Some(_) => config::ClearBehavior::Ask,
+        None => opts.config.clear_behavior,
+    };
+
+    let decision = match behavior {
+        config::ClearBehavior::Overwrite => Some(true),
+        config::ClearBehavior::Append => Some(false),
+        config::ClearBehavior::Ask => {
+            let Some(ask) = ask else {
+                return Err(anyhow::anyhow!(
+                    "The opencode prompt box may not be empty; pass --force-clear to overwrite it"
+                ))
+                .context(ErrorKind::Usage);
+            };
+            let question = "The opencode prompt box is not empty (or can't be checked). \
+                            Overwrite it, append to it, or cancel?";
+            match ask(question, &["Overwrite", "Append"])? {
+                Some(0) => Some(true),
+                Some(1) => Some(false),
+                _ => None,
+            }
+        }
+    };
+    debug_log(
+        debug,
+        &format!("check_clear: {:?} -> {:?}", behavior, decision),
+    );
+    Ok(decision)
+}
+
+/// With --strict-placeholders: whether to send a prompt whose expansion
+/// is `report`, the user asked in TUI modes if placeholders were left as typed
+///
+/// Without a dialog to ask in, that fails instead, listing them and why.
+fn check_strict(
+    ctx: &EditorContext,
+    report: &context::ExpansionReport,
+    ask: Ask<'_>,
+    debug: bool,
+) -> Result<bool> {
+    let unexpanded: Vec<String> = report
+        .unexpanded()
+        .map(|entry| format!("{} ({})", entry.token, ctx.why_unexpanded(entry)))
+        .collect();
+    if unexpanded.is_empty() {
+        return Ok(true);
+    }
+    let listed = unexpanded.join(", ");
+    debug_log(debug, &format!("check_strict: unexpanded {}", listed));
+    let Some(ask) = ask else {
+        return Err(anyhow::anyhow!(
+            "Not sent: placeholders left as typed: {}",
+            listed
+        ))
+        .context(ErrorKind::Usage);
+    };
+    let question = format!(
+        "These placeholders have no value and would be sent as typed: {}. Send anyway?",
+        listed
+    );
+    Ok(ask(&question, &["Send anyway"])? == Some(0))
+}
+
+/// What to do after checking whether the session is busy
+enum BusyCheck {
+    /// Send now
+    Send,
+    /// The session went idle while the prompt waited in the queue; send, then
+    /// remove the queue entry
+    Queued(std::path::PathBuf),
+    /// Don't send
+    Stop(Outcome),
+}
+
+/// Check the target session before sending `text`
+///
+/// With --queue, a busy session makes the prompt wait (on disk, so `flush` can
+/// send it later) until the session goes idle. Otherwise the user confirms in
+/// TUI modes, or gets a warning.
+async fn check_busy(
+    client: &server::Client,
+    text: &str,
+    submit: bool,
+    opts: &SendOptions,
+    ask: Ask<'_>,
+    debug: bool,
+) -> Result<BusyCheck> {
+    let busy = client.busy_session().await.unwrap_or_else(|e| {
+        debug_log(debug, &format!("check_busy: status unavailable: {:#}", e));
+        None
+    });
+    let Some(busy_id) = busy else {
+        return Ok(BusyCheck::Send);
+    };
+    debug_log(debug, &format!("check_busy: session {} is busy", busy_id));
+
+    if !opts.queue {
+        if let Some(ask) = ask {
+            let question = format!(
+                "Session {} is still working. Send anyway? (--queue waits for it instead)",
+                busy_id
+            );
+            if ask(&question, &["Yes", "No"])? != Some(0) {
+                return Ok(BusyCheck::Stop(Outcome::Cancelled));
+            }
+        } else if !opts.quiet {
+            errln!(
+                "Warning: session {} is busy; sending anyway (use --queue to wait)",
+                busy_id
+            );
+        }
+        return Ok(BusyCheck::Send);
+    }
+
+    let entry = queue::push(
+        &*opts.store,
+        &queue::queue_dir(),
+        &queue::QueuedPrompt {
+            session: client.session().map(str::to_string),
+            text: text.to_string(),
+            submit,
+            created: chrono::Utc::now().timestamp_millis(),
+        },
+    )?;
+    if !opts.quiet {
+        errln!("Session busy, prompt queued; waiting for it to finish (Ctrl+C to stop waiting)");
+    }
+
+    let mut events = client.subscribe_events().await.context(ErrorKind::Server)?;
+    // It may have gone idle before the subscription started
+    if client.busy_session().await.ok().flatten().is_some() {
+        tokio::select! {
+            result = server::events::wait_for_idle(
+                &mut events,
+                Some(busy_id),
+                opts.config.keepalive(),
+                || ping(client, debug),
+            ) => {
+                result.context(ErrorKind::Server)?;
+            }
+            _ = tokio::signal::ctrl_c() => {
+                if !opts.quiet {
+                    errln!("Prompt left in the queue; run `opencode-helix flush` to send it");
+                }
+                return Ok(BusyCheck::Stop(Outcome::Cancelled));
+            }
+        }
+    }
+    Ok(BusyCheck::Queued(entry))
+}
+
+/// Run the `@cmd:` placeholders of `text` that may run (see `cmd`)
+///
+/// Each command is confirmed in TUI modes; otherwise --allow-cmd runs them,
+/// except in a prompt `from_project`, which anyone committing to the
+/// repository can write. One that doesn't run is reported on stderr and
+/// sent as typed. Returns None if the user cancelled.
+async fn run_commands(
+    text: &str,
+    from_project: bool,
+    opts: &SendOptions,
+    ask: Ask<'_>,
+    debug: bool,
+) -> Result<Option<cmd::Outputs>> {
+    let mut outputs = cmd::Outputs::new();
+    for command in cmd::commands(text) {
+        let refused = if !opts.config.allow_cmd_placeholder {
+            Some("not allowed (set allow_cmd_placeholder = true in the config)".to_string())
+        } else if let Some(ask) = ask {
+            let question = if from_project {
+                format!(
+                    "Run this command from {}?\n\n$ {}",
+                    config::PROJECT_CONFIG_FILE,
+                    command
+                )
+            } else {
+                format!("Run this command for the prompt?\n\n$ {}", command)
+            };
+            match ask(&question, &["Run", "Skip"])? {
+                Some(0) => None,
+                Some(_) => Some("skipped".to_string()),
+                None => return Ok(None),
+            }
+        } else if from_project {
+            Some(format!(
+                "it comes from {}; pick the prompt in the dialogs to confirm it",
+                config::PROJECT_CONFIG_FILE
+            ))
+        } else if !opts.allow_cmd {
+            Some("pass --allow-cmd to run it".to_string())
+        } else {
+            None
+        };
+        let result = match refused {
+            Some(reason) => Err(reason),
+            None => {
+                let (command, cwd) = (command.to_string(), opts.project.clone());
+                tokio::task::spawn_blocking(move || {
+                    cmd::run(&command, &cwd, cmd::TIMEOUT, cmd::MAX_OUTPUT)
+                })
+                .await
+                .context("Command task failed")?
+                .map_err(|e| format!("{:#}", e))
+            }
+        };
+        match result {
+            Ok(output) => {
+                debug_log(
+                    debug,
+                    &format!("run_commands: {:?} printed {} bytes", command, output.len()),
+                );
+                outputs.insert(command.to_string(), output);
+            }
+            Err(reason) => {
+                let warning = format!("{}{} not run: {}", cmd::PREFIX, command, reason);
+                // Quoted, so a report bundle keeps only its length
+                debug_log(
+                    debug,
+                    &format!("run_commands: {:?} not run: {}", command, reason),
+                );
+                if !opts.quiet {
+                    errln!("Warning: {}", warning);
+                }
+            }
+        }
+    }
+    Ok(Some(outputs))
+}
+
+/// Expand and send prompt steps, waiting for the session to go idle between steps
+async fn send_steps(
+    client: &server::Client,
+    ctx: &EditorContext,
+    steps: &[config::PromptStep],
+    submit: bool,
+    opts: &SendOptions,
+    ask: Ask<'_>,
+    debug: bool,
+) -> Result<Outcome> {
+    let steps = if opts.chain {
+        steps
+    } else {
+        &steps[..steps.len().min(1)]
+    };
+    if steps.len() > 1 && !submit {
+        return Err(anyhow::anyhow!("Chained prompts must be submitted")).context(ErrorKind::Usage);
+    }
+    if opts.wait && !submit {
+        return Err(anyhow::anyhow!("--wait needs the prompt submitted")).context(ErrorKind::Usage);
+    }
+    if steps.len() > 1 {
+        client
+            .require(server::Feature::Events)
+            .await
+            .context(ErrorKind::Server)?;
+    }
+
+    // A server that restarted on another port is found again for the send
+    let mut redial = server::Redial::new(client.clone(), &opts.project, || {
+        server::discover_server(
+            &opts.project,
+            None,
+            opts.auth.as_ref(),
+            server::redial::REDIAL_TIMEOUT,
+        )
+    });
+    let mut expanded = String::new();
+    let mut reply = None;
+    for (i, step) in steps.iter().enumerate() {
+        let input = match redial.client().agent() {
+            Some(name) => agent::prefix(&step.text, name),
+            None => step.text.clone(),
+        };
+        let Some(outputs) = run_commands(&input, step.from_project, opts, ask, debug).await? else {
+            return Ok(Outcome::Cancelled);
+        };
+        let expansion = cmd::expand(&input, &outputs, |text| ctx.expansion(text));
+        expanded = expansion.text;
+        // Register contents never reach the log, so it shows the text as typed
+        let logged = if expansion
+            .substituted
+            .keys()
+            .any(|token| token.starts_with(register::PREFIX))
+        {
+            format!("{} (registers not logged)", step.text)
+        } else {
+            expanded.clone()
+        };
+        debug_log(
+            debug,
+            &format!("send_steps: step {} ({}) = {}", i + 1, step.name, logged),
+        );
+        // Where in the input each placeholder was, and what became of it
+        debug_log(
+            debug,
+            &format!(
+                "send_steps: step {} placeholders:\n{}",
+                i + 1,
+                expansion.report
+            ),
+        );
+        if opts.strict && !check_strict(ctx, &expansion.report, ask, debug)? {
+            return Ok(Outcome::Cancelled);
+        }
+        if steps.len() > 1 && !opts.quiet {
+            errln!("step {}/{}: {}", i + 1, steps.len(), step.name);
+        }
+        // Otherwise the first sign is opencode asking what "@diff" means
+        if let Some(warning) = ctx.unexpanded_diff(&step.text) {
+            debug_log(debug, &format!("send_steps: {}", warning));
+            if !opts.quiet {
+                errln!("Warning: {}", warning);
+            }
+        }
+
+        // Only the first step can find the session busy; later ones follow an idle
+        let mut queued = None;
+        let mut clear = false;
+        let mut title = None;
+        if i == 0 {
+            // A session message never touches the prompt box
+            if opts.clear && redial.client().session().is_none() {
+                match check_clear(redial.client(), opts, ask, debug).await? {
+                    Some(decision) => clear = decision,
+                    None => return Ok(Outcome::Cancelled),
+                }
+            }
+            match check_busy(redial.client(), &expanded, submit, opts, ask, debug).await? {
+                BusyCheck::Send => {}
+                BusyCheck::Queued(entry) => queued = Some(entry),
+                BusyCheck::Stop(outcome) => return Ok(outcome),
+            }
+            title = session_title(redial.client(), &step.text, opts, debug).await;
+        }
 
-    match cli.command {
-        Command::Ask { initial } => {
-            run_ask(&client, &ctx, &initial, debug, theme, animations).await?;
+        // The TUI prompt box only takes text
+        let parts = redial.client().session().map(|_| {
+            let mut parts = cmd::parts(&input, &outputs, |text| ctx.to_parts(text));
+            parts.extend(ctx.attached.iter().cloned());
+            parts
+        });
+        // What is left to send; a retried chunked send resumes where it broke off
+        let mut text = expanded.as_str();
+        loop {
+            let gate = send_gate(redial.client(), opts, ask);
+            let result = if i + 1 == steps.len() {
+                send(&gate, text, parts.as_deref(), clear, submit, opts, debug).await
+            } else {
+                tokio::select! {
+                    result = send_and_wait(&gate, text, parts.as_deref(), clear, submit, opts, debug) => {
+                        result.map(|_| None)
+                    }
+                    _ = tokio::signal::ctrl_c() => {
+                        debug_log(debug, "send_steps: interrupted between steps");
+                        return Ok(Outcome::Cancelled);
+                    }
+                }
+            };
+            let e = match result {
+                Ok(last) => {
+                    reply = last;
+                    break;
+                }
+                Err(e) => e,
+            };
+            if e.downcast_ref::<policy::Declined>().is_some() {
+                return Ok(Outcome::Cancelled);
+            }
+            let from = redial.client().port();
+            if let Some(port) = redial.recover(&e).await {
+                debug_log(
+                    debug,
+                    &format!("send_steps: server moved from port {} to {}", from, port),
+                );
+                // The new server's prompt box holds none of it
+                text = expanded.as_str();
+                continue;
+            }
+            let Some(partial) = e.downcast_ref::<server::PartialSend>() else {
+                return Err(e);
+            };
+            debug_log(debug, &format!("send_steps: {:#}", e));
+            let sent_bytes = partial.sent_bytes;
+            if !retry_chunks(partial, ask)? {
+                return Err(e);
+            }
+            text = &text[sent_bytes..];
+            clear = false;
         }
-        Command::Select => {
-            run_select(&client, &ctx, debug, theme, animations).await?;
+        if let Some(entry) = queued {
+            queue::remove(&entry);
         }
-        Command::Prompt { text, submit } => {
-            run_prompt(&client, &ctx, &text, submit, debug).await?;
+        if let Some(title) = title {
+            rename_session(redial.client(), &title, opts, debug).await;
         }
-        Command::Status => {
-            run_status(&server).await?;
+        opts.usage.sent(&step.name, expanded.len());
+
+        if opts.output == OutputFormat::Json {
+            let sent = report::SentPrompt {
+                version: report::VERSION,
+                timestamp: report::timestamp(),
+                port: redial.client().port(),
+                session: redial.client().session(),
+                step: &step.name,
+                input: &step.text,
+                expanded: &expanded,
+                placeholders: &expansion.substituted,
+            };
+            outln!("{}", sent.to_json());
         }
     }
 
+    if let Err(e) = history::save(
+        &*opts.store,
+        &history::history_dir(),
+        &opts.project,
+        steps,
+        submit,
+    ) {
+        debug_log(debug, &format!("send_steps: not remembered: {:#}", e));
+    }
+
+    if let Some(format) = opts.print {
+        print_reply(reply.as_deref(), format)?;
+    }
+
+    Ok(Outcome::Done(Some(format!(
+        "Sent: {}",
+        util::display_truncate(&expanded, 50)
+    ))))
+}
+
+/// Write the reply to stdout for `prompt --print`
+fn print_reply(reply: Option<&str>, format: reply::ReplyFormat) -> Result<()> {
+    let Some(reply) = reply else {
+        return Err(anyhow::anyhow!(
+            "The session went idle without a text reply"
+        ))
+        .context(ErrorKind::NoReply);
+    };
+    let Some(text) = format.render(reply) else {
+        let reason = match format {
+            reply::ReplyFormat::FirstCodeBlock => "The reply has no code block",
+            _ => "The reply is empty",
+        };
+        return Err(anyhow::anyhow!(reason)).context(ErrorKind::NoReply);
+    };
+    // Like outln!, but --raw output gets no newline added
+    let mut stdout = std::io::stdout().lock();
+    let _ = std::io::Write::write_all(&mut stdout, text.as_bytes())
+        .and_then(|()| std::io::Write::flush(&mut stdout));
     Ok(())
 }
 
+/// Title to give the --session once `prompt` is sent: --title, or one from
+/// the prompt while the session is untitled
+async fn session_title(
+    client: &server::Client,
+    prompt: &str,
+    opts: &SendOptions,
+    debug: bool,
+) -> Option<String> {
+    let id = client.session()?;
+    if opts.title.is_some() || !opts.auto_title {
+        return opts.title.clone();
+    }
+    match client.list_sessions().await {
+        Ok(sessions) => sessions
+            .iter()
+            .any(|session| session.id == id && session.is_untitled())
+            .then(|| title::from_prompt(prompt))
+            .flatten(),
+        Err(e) => {
+            debug_log(debug, &format!("session_title: no session list: {:#}", e));
+            None
+        }
+    }
+}
+
+/// Set the --session's title; the prompt is sent already, so a failure
+/// is only a warning
+async fn rename_session(client: &server::Client, title: &str, opts: &SendOptions, debug: bool) {
+    let Some(id) = client.session() else {
+        return;
+    };
+    match client.rename_session(id, title).await {
+        Ok(()) => debug_log(debug, &format!("rename_session: {} is {:?}", id, title)),
+        Err(e) => {
+            debug_log(debug, &format!("rename_session: {:#}", e));
+            if !opts.quiet {
+                errln!("Warning: session not renamed: {:#}", e);
+            }
+        }
+    }
+}
+
+/// The last prompt sent from this project, for `resend`
+fn last_prompt(opts: &SendOptions) -> Result<history::LastPrompt> {
+    history::load(&*opts.store, &history::history_dir(), &opts.project)?
+        .filter(|last| !last.steps.is_empty())
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Nothing has been sent from {} yet; send a prompt first",
+                opts.project.display()
+            )
+        })
+        .context(ErrorKind::Usage)
+}
+
+/// The last prompt from this project, suggested in the empty ask input
+#[cfg(feature = "tui")]
+fn suggestion(ctx: &EditorContext, opts: &SendOptions, debug: bool) -> Option<String> {
+    let last = history::load(&*opts.store, &history::history_dir(), &opts.project)
+        .map_err(|e| debug_log(debug, &format!("suggestion: history unreadable: {:#}", e)))
+        .ok()
+        .flatten();
+    history::suggestion(last.as_ref(), |text| ctx.placeholders_available(text)).map(str::to_string)
+}
+
 /// Run the ask (input) mode
+///
+/// With `repeat` (`ask --loop`), each successful send reopens the dialog
+/// with the text just sent, on the same server and context, until Esc.
+#[cfg(feature = "tui")]
 async fn run_ask(
     client: &server::Client,
     ctx: &EditorContext,
     initial: &str,
+    repeat: bool,
+    send_opts: &SendOptions,
+    ui: &UiOptions,
     debug: bool,
-    theme: ThemeKind,
-    animations: bool,
-) -> Result<()> {
+) -> Result<Outcome> {
     debug_log(debug, "run_ask: starting");
-    let mut app = App::with_theme(debug, theme)?;
-
     // Build context hint
     let context_hint = ctx.format_this();
+    let mut text = initial.to_string();
+    let mut last = Outcome::Cancelled;
+    // Picked with Alt+A, kept for the rounds of a loop
+    let mut dialog = agent::Route::default();
 
-    // Run the TUI with context for placeholder display
-    let result = app.run_ask(initial, context_hint.as_deref(), Some(ctx), animations)?;
-    debug_log(debug, &format!("run_ask: TUI result = {:?}", result));
+    for round in 1.. {
+        let client = routed(client, &send_opts.route(&dialog), debug);
+        let app = open_app(debug, ui)?
+            .with_nested_editor(send_opts.config.allow_nested_editor)
+            .with_quick_actions(send_opts.config.quick_actions())
+            .with_cursor_style(send_opts.config.cursor_style)
+            .with_tab_width(send_opts.config.tab_width())
+            .with_token_budget(send_opts.config.token_budget())
+            .with_target(ui.target.clone())
+            .with_round(repeat.then_some(round))
+            .with_attachments(
+                send_opts
+                    .config
+                    .attach_pasted_images
+                    .then(|| send_opts.project.clone()),
+            );
+        let mut app = with_route(app, &client, send_opts, ui);
+        // The suggestion is for an empty input, which a loop doesn't reopen with
+        if round == 1 {
+            app = app.with_suggestion(suggestion(ctx, send_opts, debug));
+        }
 
-    // Clean up terminal before any async operations
-    app.restore()?;
-    drop(app);
+        // Run the TUI with context for placeholder display
+        let result = app.run_ask(&text, context_hint.as_deref(), Some(ctx), ui.animations)?;
+        debug_log(
+            debug,
+            &format!("run_ask: round {} TUI result = {:?}", round, result),
+        );
 
-    match result {
-        AppResult::Submit(input) => {
-            // Expand context placeholders
-            let expanded = ctx.expand(&input);
-            debug_log(debug, &format!("run_ask: expanded = {}", expanded));
+        // Clean up terminal before any async operations
+        app.restore()?;
+        merge_chosen(&mut dialog, app.chosen(), debug);
+        let pasted = app.pasted().to_vec();
+        drop(app);
 
-            // Send to opencode
-            client.send_prompt(&expanded, false, true).await?;
+        let AppResult::Submit(input) = result else {
+            debug_log(debug, "run_ask: cancelled");
+            break;
+        };
+        let client = routed(&client, &send_opts.route(&dialog), debug);
+        let (sent, images) = attach_pasted(&input, &pasted, &client, send_opts.quiet, debug);
+        let steps = [config::PromptStep {
+            name: "ask".to_string(),
+            text: sent,
+            from_project: false,
+        }];
+        let ask = |question: &str, choices: &[&str]| run_choice(question, choices, debug, ui);
 
-            // Print confirmation (will be captured by Helix but that's ok)
-            eprintln!("Sent: {}", truncate(&expanded, 50));
+        // The context carries the images to the session message
+        let with_images = (!images.is_empty()).then(|| {
+            let mut ctx = ctx.clone();
+            ctx.attached = images;
+            ctx
+        });
+        let ctx = with_images.as_ref().unwrap_or(ctx);
+
+        // Confirmation is printed to stderr (captured by Helix but that's ok)
+        let outcome = send_steps(&client, ctx, &steps, true, send_opts, Some(&ask), debug).await?;
+        if !repeat {
+            return Ok(outcome);
         }
-        AppResult::Cancel => {
-            debug_log(debug, "run_ask: cancelled");
-            eprintln!("Cancelled");
+        // Each round's confirmation, as the dialog is about to take the screen again
+        if let Outcome::Done(Some(ref msg)) = outcome {
+            if !send_opts.quiet {
+                errln!("{}", msg);
+            }
         }
+        text = input;
+        last = outcome;
     }
+    // Esc ends a loop without cancelling what it sent
+    Ok(match last {
+        Outcome::Done(_) => Outcome::Done(None),
+        Outcome::Cancelled => Outcome::Cancelled,
+    })
+}
 
-    Ok(())
+/// The ask dialog's text to send and the session message parts of the
+/// images pasted into it
+///
+/// The prompt box only takes text, so without a session the paths become
+/// `@path` references for opencode to read instead, with a note saying so.
+#[cfg(feature = "tui")]
+fn attach_pasted(
+    input: &str,
+    pasted: &[attach::Pasted],
+    client: &server::Client,
+    quiet: bool,
+    debug: bool,
+) -> (String, Vec<server::MessagePart>) {
+    for image in pasted {
+        debug_log(
+            debug,
+            &format!(
+                "run_ask: attaching {} ({})",
+                image.path.display(),
+                util::size_label(image.size)
+            ),
+        );
+    }
+    if pasted.is_empty() || client.session().is_some() {
+        let parts = pasted.iter().filter_map(attach::Pasted::part).collect();
+        return (input.to_string(), parts);
+    }
+    if !quiet {
+        let names: Vec<String> = pasted.iter().map(attach::Pasted::file_name).collect();
+        errln!(
+            "Note: {} sent as @path references; images are attached only to --session messages",
+            names.join(", ")
+        );
+    }
+    (attach::to_references(input, pasted), Vec::new())
 }
 
 /// Run the select (menu) mode
+#[cfg(feature = "tui")]
 async fn run_select(
     client: &server::Client,
     ctx: &EditorContext,
+    select_opts: &SelectOptions<'_>,
+    send_opts: &SendOptions,
+    ui: &UiOptions,
     debug: bool,
-    theme: ThemeKind,
-    animations: bool,
-) -> Result<()> {
+) -> Result<Outcome> {
     debug_log(debug, "run_select: starting");
+    let wants = |category| {
+        select_opts
+            .category
+            .is_none_or(|only: SelectCategory| only == category)
+    };
 
-    // Fetch agents and commands from server
-    let agents = client.get_agents().await.unwrap_or_default();
-    let commands = client.get_commands().await.unwrap_or_default();
-    debug_log(
-        debug,
-        &format!(
-            "run_select: {} agents, {} commands",
-            agents.len(),
-            commands.len()
-        ),
-    );
-
-    // Build menu items
-    let mut items: Vec<SelectItem> = Vec::new();
-
-    // Add prompts
-    items.extend(config::prompts_to_select_items());
+    // Build menu items, fetching agents and commands only when listed
+    let mut prompts: Vec<SelectItem> = Vec::new();
+    if wants(SelectCategory::Prompts) {
+        prompts.extend(config::prompts_to_select_items(
+            &send_opts.prompts,
+            ctx.language_or_detected(),
+        ));
+    }
+    let kinds: Vec<list_cache::Kind> = [
+        (SelectCategory::Commands, list_cache::Kind::Commands),
+        (SelectCategory::Agents, list_cache::Kind::Agents),
+    ]
+    .into_iter()
+    .filter(|&(category, _)| wants(category))
+    .map(|(_, kind)| kind)
+    .collect();
 
-    // Add server commands
-    items.extend(config::commands_to_select_items(&commands));
+    // Cached lists open the menu at once and are refreshed behind it
+    let dir = list_cache::cache_dir();
+    let cached = list_cache::load(&dir, client.port(), select_opts.server_cwd).filter(|entry| {
+        !select_opts.refresh
+            && !kinds.is_empty()
+            && kinds.iter().all(|&kind| entry.list(kind).is_some())
+    });
+    let (mut items, refresh) = match cached {
+        Some(entry) => {
+            debug_log(
+                debug,
+                &format!(
+                    "run_select: lists cached {}s ago",
+                    entry.age(&kinds, list_cache::now())
+                ),
+            );
+            let items = menu_items(&prompts, &entry, &kinds, send_opts.quiet, debug);
+            let (send, refresh) = std::sync::mpsc::channel();
+            tokio::spawn(refresh_menu(
+                client.clone(),
+                entry,
+                kinds,
+                prompts,
+                send,
+                debug,
+            ));
+            (items, Some(refresh))
+        }
+        None => {
+            let mut entry = list_cache::Entry::new(select_opts.server_cwd);
+            for &kind in &kinds {
+                if let Err(e) = entry.refresh(client, kind).await {
+                    debug_log(debug, &format!("Failed to fetch {:?}: {:#}", kind, e));
+                }
+            }
+            save_lists(client.port(), &entry, &kinds, debug);
+            (
+                menu_items(&prompts, &entry, &kinds, send_opts.quiet, debug),
+                None,
+            )
+        }
+    };
+    debug_log(debug, &format!("run_select: {} items", items.len()));
 
-    // Add agents
-    items.extend(config::agents_to_select_items(&agents));
+    // With --auto, a single match is used without opening the menu
+    let mut matching = items
+        .iter()
+        .filter(|item| tui::select::SelectState::matches(item, select_opts.filter));
+    let auto_pick = match (select_opts.auto, matching.next(), matching.next()) {
+        (true, Some(item), None) => Some(item.value.clone()),
+        _ => None,
+    };
 
-    let mut app = App::with_theme(debug, theme)?;
-    let result = app.run_select(&items, animations)?;
-    debug_log(debug, &format!("run_select: TUI result = {:?}", result));
+    let mut dialog = agent::Route::default();
+    let result = match auto_pick {
+        Some(value) => {
+            debug_log(debug, &format!("run_select: auto-picked {:?}", value));
+            AppResult::Submit(value)
+        }
+        None => {
+            // The ask dialog's settings too, for editing an item before sending
+            let app = open_app(debug, ui)?
+                .with_refresh(refresh)
+                .with_nested_editor(send_opts.config.allow_nested_editor)
+                .with_quick_actions(send_opts.config.quick_actions())
+                .with_cursor_style(send_opts.config.cursor_style)
+                .with_tab_width(send_opts.config.tab_width())
+                .with_token_budget(send_opts.config.token_budget())
+                .with_target(ui.target.clone());
+            let mut app = with_route(app, client, send_opts, ui);
+            let dir = collapse::collapse_dir();
+            let saved =
+                collapse::load(&*send_opts.store, &dir, &send_opts.project).unwrap_or_else(|e| {
+                    debug_log(
+                        debug,
+                        &format!("run_select: collapse state ignored: {:#}", e),
+                    );
+                    Default::default()
+                });
+            let mut collapsed = saved.clone();
+            let result = app.run_select(
+                &mut items,
+                select_opts.filter,
+                &mut collapsed,
+                Some(ctx),
+                ui.animations,
+            )?;
+            debug_log(debug, &format!("run_select: TUI result = {:?}", result));
 
-    // Clean up terminal
-    app.restore()?;
-    drop(app);
+            // Clean up terminal
+            app.restore()?;
+            merge_chosen(&mut dialog, app.chosen(), debug);
+            if collapsed != saved {
+                if let Err(e) =
+                    collapse::save(&*send_opts.store, &dir, &send_opts.project, &collapsed)
+                {
+                    debug_log(
+                        debug,
+                        &format!("run_select: collapse state not saved: {:#}", e),
+                    );
+                }
+            }
+            result
+        }
+    };
 
     match result {
         AppResult::Submit(value) => {
-            // Expand context placeholders
-            let expanded = ctx.expand(&value);
-            debug_log(debug, &format!("run_select: expanded = {}", expanded));
-
-            // Send to opencode
-            client.send_prompt(&expanded, false, true).await?;
+            let picked = items.iter().find(|item| item.value == value);
+            if let Some(item) = picked {
+                send_opts.usage.prompt(&item.name);
+            }
+            // Chained prompts carry their name as the value
+            let is_chain = items
+                .iter()
+                .any(|item| item.category == "CHAINS" && item.value == value);
+            let steps = match send_opts
+                .prompts
+                .prompt_steps(&value, ctx.language_or_detected())
+            {
+                Some(steps) if is_chain => steps,
+                _ => vec![config::PromptStep {
+                    name: "select".to_string(),
+                    text: value,
+                    from_project: picked.is_some_and(|item| {
+                        item.category == "PROMPTS"
+                            && send_opts
+                                .prompts
+                                .get(&item.name)
+                                .is_some_and(|entry| entry.source == config::PromptSource::Project)
+                    }),
+                }],
+            };
 
-            eprintln!("Sent: {}", truncate(&expanded, 50));
+            let client = routed(client, &send_opts.route(&dialog), debug);
+            let ask = |question: &str, choices: &[&str]| run_choice(question, choices, debug, ui);
+            send_steps(&client, ctx, &steps, true, send_opts, Some(&ask), debug).await
         }
         AppResult::Cancel => {
             debug_log(debug, "run_select: cancelled");
-            eprintln!("Cancelled");
+            Ok(Outcome::Cancelled)
         }
     }
+}
 
-    Ok(())
+/// The select menu's items: `prompts`, then the `kinds` lists of `entry`
+/// A list that wasn't fetched is left out, so the menu still opens
+#[cfg(feature = "tui")]
+fn menu_items(
+    prompts: &[SelectItem],
+    entry: &list_cache::Entry,
+    kinds: &[list_cache::Kind],
+    quiet: bool,
+    debug: bool,
+) -> Vec<SelectItem> {
+    let mut items = prompts.to_vec();
+    for &kind in kinds {
+        match kind {
+            list_cache::Kind::Commands => {
+                let commands = list_or_warn(entry.parsed(kind), "commands", quiet, debug);
+                items.extend(config::commands_to_select_items(&commands));
+            }
+            list_cache::Kind::Agents => {
+                let agents = list_or_warn(entry.parsed(kind), "agents", quiet, debug);
+                items.extend(config::agents_to_select_items(&agents));
+            }
+        }
+    }
+    items
+}
+
+/// Ask the server for the cached `entry`'s lists again while the menu is
+/// open, sending it the new items if they changed
+///
+/// The menu has the terminal, so nothing is written to stderr. A failed
+/// refresh sends nothing and the menu stays tagged as cached.
+#[cfg(feature = "tui")]
+async fn refresh_menu(
+    client: server::Client,
+    mut entry: list_cache::Entry,
+    kinds: Vec<list_cache::Kind>,
+    prompts: Vec<SelectItem>,
+    send: std::sync::mpsc::Sender<menu::Refresh>,
+    debug: bool,
+) {
+    let mut changed = false;
+    for &kind in &kinds {
+        match entry.refresh(&client, kind).await {
+            Ok(list_changed) => changed |= list_changed,
+            Err(e) => {
+                debug_log(debug, &format!("Failed to refresh {:?}: {:#}", kind, e));
+                return;
+            }
+        }
+    }
+    debug_log(
+        debug,
+        &format!("run_select: refreshed, changed = {}", changed),
+    );
+    save_lists(client.port(), &entry, &kinds, debug);
+    let news = if changed {
+        menu::Refresh::Items(menu_items(&prompts, &entry, &kinds, true, debug))
+    } else {
+        menu::Refresh::Unchanged
+    };
+    // The menu may have closed already
+    let _ = send.send(news);
+}
+
+/// Cache the lists of the server on `port`, if all of `kinds` were fetched
+#[cfg(feature = "tui")]
+fn save_lists(port: u16, entry: &list_cache::Entry, kinds: &[list_cache::Kind], debug: bool) {
+    if kinds.is_empty() || !kinds.iter().all(|&kind| entry.list(kind).is_some()) {
+        return;
+    }
+    if let Err(e) = list_cache::save(&list_cache::cache_dir(), port, entry) {
+        debug_log(debug, &format!("Lists not cached: {:#}", e));
+    }
+}
+
+/// Unwrap a leniently parsed list, warning about skipped entries
+#[cfg(feature = "tui")]
+fn list_or_warn<T>(
+    parsed: server::client::Lenient<T>,
+    what: &str,
+    quiet: bool,
+    debug: bool,
+) -> Vec<T> {
+    for skipped in &parsed.skipped {
+        debug_log(debug, &format!("Skipped {} entry: {}", what, skipped));
+    }
+    if !parsed.skipped.is_empty() && !quiet {
+        errln!(
+            "Warning: skipped {} {} the server sent in an unexpected format",
+            parsed.skipped.len(),
+            what
+        );
+    }
+    parsed.items
 }
 
 /// Run the prompt command (non-interactive)
 async fn run_prompt(
     client: &server::Client,
     ctx: &EditorContext,
-    text: &str,
+    steps: &[config::PromptStep],
     submit: bool,
+    send_opts: &SendOptions,
     debug: bool,
-) -> Result<()> {
+) -> Result<Outcome> {
     debug_log(
         debug,
-        &format!("run_prompt: text='{}', submit={}", text, submit),
+        &format!("run_prompt: steps={:?}, submit={}", steps, submit),
     );
 
-    // Check if text is a prompt name
-    let prompt_text = config::get_prompt(text).map(|p| p.prompt).unwrap_or(text);
+    send_steps(client, ctx, steps, submit, send_opts, None, debug).await
+}
 
-    // Expand context
-    let expanded = ctx.expand(prompt_text);
-    debug_log(debug, &format!("run_prompt: expanded = {}", expanded));
+/// Open the dialogs on the alternate screen, or inline
+#[cfg(feature = "tui")]
+fn open_app(debug: bool, ui: &UiOptions) -> Result<App> {
+    let app = if ui.inline {
+        App::inline(debug, ui.theme)?
+    } else {
+        App::with_theme(debug, ui.theme)?
+    };
+    Ok(app
+        .with_strings(ui.strings.clone())
+        .with_term_integration(ui.term_integration)
+        .with_cursor_shape(ui.cursor_shape)
+        .with_effects(ui.effects))
+}
 
-    // Send to opencode
-    client.send_prompt(&expanded, false, submit).await?;
+/// Show `client`'s agent and model in the ask dialog, and offer the ones
+/// Alt+A can pick: not an agent or a model a flag already fixes
+#[cfg(feature = "tui")]
+fn with_route(app: App, client: &server::Client, send_opts: &SendOptions, ui: &UiOptions) -> App {
+    let agents = match send_opts.flag_route.agent {
+        Some(_) => Vec::new(),
+        None => ui.agents.clone(),
+    };
+    let models = match send_opts.flag_route.model {
+        Some(_) => Vec::new(),
+        None => ui.models.clone(),
+    };
+    app.with_agent(client.agent().map(str::to_string))
+        .with_model(client.model().map(ToString::to_string))
+        .with_agents(ui.agents.clone())
+        .with_choices(agents, models)
+}
 
-    eprintln!("Sent: {}", truncate(&expanded, 50));
+/// Add what was picked with Alt+A in a dialog to `dialog`
+#[cfg(feature = "tui")]
+fn merge_chosen(dialog: &mut agent::Route, chosen: &tui::app::Chosen, debug: bool) {
+    if let Some(name) = &chosen.agent {
+        dialog.agent = Some(name.clone());
+    }
+    if let Some(model) = &chosen.model {
+        match model.parse() {
+            Ok(model) => dialog.model = Some(model),
+            Err(e) => debug_log(debug, &format!("merge_chosen: {:#}", e)),
+        }
+    }
+}
 
-    Ok(())
+/// Ask a question with a few answers in a dialog
+#[cfg(feature = "tui")]
+fn run_choice(
+    question: &str,
+    choices: &[&str],
+    debug: bool,
+    ui: &UiOptions,
+) -> Result<Option<usize>> {
+    let mut app = open_app(debug, ui)?;
+    let picked = app.run_choice(question, choices)?;
+    app.restore()?;
+    Ok(picked)
 }
 
-/// Show server status
-async fn run_status(server: &server::Server) -> Result<()> {
-    println!("opencode server:");
-    println!("  Port: {}", server.port);
-    println!("  CWD:  {}", server.cwd.display());
-    if server.pid > 0 {
-        println!("  PID:  {}", server.pid);
+/// Send prompts left in the queue, oldest first
+async fn run_flush(
+    client: &server::Client,
+    send_opts: &SendOptions,
+    debug: bool,
+) -> Result<Outcome> {
+    let entries = queue::list(&*send_opts.store, &queue::queue_dir())?;
+    debug_log(
+        debug,
+        &format!("run_flush: {} queued prompts", entries.len()),
+    );
+    if entries.is_empty() {
+        return Ok(Outcome::Done(Some("Queue is empty".to_string())));
     }
-    Ok(())
+
+    let total = entries.len();
+    for (i, (path, prompt)) in entries.into_iter().enumerate() {
+        let client = client.clone().with_session(prompt.session.clone());
+        if !send_opts.quiet {
+            errln!(
+                "flush {}/{}: {}",
+                i + 1,
+                total,
+                util::display_truncate(&prompt.text, 50)
+            );
+        }
+
+        // Each prompt waits for the previous one (or other work) to finish
+        if let Some(busy_id) = client.busy_session().await.ok().flatten() {
+            let mut events = client.subscribe_events().await.context(ErrorKind::Server)?;
+            if client.busy_session().await.ok().flatten().is_some() {
+                tokio::select! {
+                    result = server::events::wait_for_idle(
+                        &mut events,
+                        Some(busy_id),
+                        send_opts.config.keepalive(),
+                        || ping(&client, debug),
+                    ) => {
+                        result.context(ErrorKind::Server)?;
+                    }
+                    _ = tokio::signal::ctrl_c() => return Ok(Outcome::Cancelled),
+                }
+            }
+        }
+
+        let gate = send_gate(&client, send_opts, None);
+        let (mode, _) = deliver(&gate, &prompt.text, None, false, prompt.submit, send_opts).await?;
+        debug_log(debug, &format!("run_flush: delivered as {}", mode));
+        queue::remove(&path);
+    }
+
+    Ok(Outcome::Done(Some(format!(
+        "Flushed {} queued prompts",
+        total
+    ))))
+}
+
+/// Run the resume (session picker) mode
+#[cfg(feature = "tui")]
+async fn run_resume(
+    client: &server::Client,
+    limit: usize,
+    ui: &UiOptions,
+    debug: bool,
+) -> Result<Outcome> {
+    debug_log(debug, "run_resume: starting");
+
+    client
+        .require(server::Feature::Sessions)
+        .await
+        .context(ErrorKind::Server)?;
+
+    let sessions = client.list_sessions().await.context(ErrorKind::Server)?;
+    debug_log(debug, &format!("run_resume: {} sessions", sessions.len()));
+
+    let now = chrono::Utc::now().timestamp_millis();
+    let mut items = config::sessions_to_select_items(&sessions, limit, now);
+    if items.is_empty() {
+        return Ok(Outcome::Done(Some("No sessions found".to_string())));
+    }
+
+    let mut app = open_app(debug, ui)?.with_target(ui.target.clone());
+    // Sessions aren't grouped, so there is nothing to collapse
+    let result = app.run_select(&mut items, "", &mut Default::default(), None, ui.animations)?;
+    debug_log(debug, &format!("run_resume: TUI result = {:?}", result));
+
+    // Clean up terminal
+    app.restore()?;
+    drop(app);
+
+    match result {
+        AppResult::Submit(session_id) => {
+            client
+                .tui_select_session(&session_id)
+                .await
+                .context(ErrorKind::Server)?;
+            Ok(Outcome::Done(Some(format!(
+                "Switched to session {}",
+                session_id
+            ))))
+        }
+        AppResult::Cancel => {
+            debug_log(debug, "run_resume: cancelled");
+            Ok(Outcome::Cancelled)
+        }
+    }
+}
+
+/// Print a prompt expanded from the editor context, or with --explain what
+/// became of each of its placeholders
+fn run_context(
+    prompts: &config::PromptRegistry,
+    ctx: &EditorContext,
+    name_or_text: &str,
+    extra: &[String],
+    explain: bool,
+) -> Result<Outcome> {
+    let steps = config::resolve_prompt(
+        prompts,
+        name_or_text,
+        extra,
+        false,
+        ctx.language_or_detected(),
+    )
+    .context(ErrorKind::Usage)?;
+    for (i, step) in steps.iter().enumerate() {
+        if steps.len() > 1 {
+            outln!("# step {}: {}", i + 1, step.name);
+        }
+        // Commands only run for a send
+        let expansion = cmd::expand(&step.text, &cmd::Outputs::new(), |text| ctx.expansion(text));
+        if explain {
+            outln!("{}", expansion.report);
+        } else {
+            outln!("{}", expansion.text);
+        }
+    }
+    Ok(Outcome::Done(None))
+}
+
+/// List prompts with where each one comes from
+fn run_list(
+    what: cli::ListTarget,
+    json: bool,
+    prompts: &config::PromptRegistry,
+) -> Result<Outcome> {
+    match what {
+        cli::ListTarget::Prompts if json => {
+            let entries: Vec<serde_json::Value> = prompts
+                .entries()
+                .iter()
+                .map(|entry| {
+                    serde_json::json!({
+                        "name": entry.name,
+                        "source": entry.source.to_string(),
+                        "description": entry.prompt.description,
+                        "prompt": entry.prompt.prompt,
+                        "chain": entry.prompt.chain,
+                        "by_language": entry.prompt.by_language,
+                    })
+                })
+                .collect();
+            outln!("{}", serde_json::to_string_pretty(&entries)?);
+        }
+        cli::ListTarget::Prompts => {
+            let width = prompts
+                .entries()
+                .iter()
+                .map(|e| e.name.chars().count())
+                .max()
+                .unwrap_or(0);
+            for entry in prompts.entries() {
+                let description = match (&entry.prompt.description, entry.prompt.chain.is_empty()) {
+                    (Some(description), _) => description.clone(),
+                    (None, false) => format!("Chain: {}", entry.prompt.chain.join(" → ")),
+                    (None, true) => String::new(),
+                };
+                outln!(
+                    "{:<width$}  {:<7}  {}",
+                    entry.name,
+                    entry.source,
+                    description,
+                    width = width
+                );
+            }
+        }
+    }
+    Ok(Outcome::Done(None))
+}
+
+/// Summarize the recorded usage stats, or clear them
+fn run_stats(clear: bool, store: &dyn state::StateStore) -> Result<Outcome> {
+    let path = stats::stats_path();
+    if clear {
+        stats::clear(&path)?;
+        return Ok(Outcome::Done(Some("Stats cleared".to_string())));
+    }
+    outln!("{}", stats::report(&stats::read(store, &path)?));
+    Ok(Outcome::Done(None))
+}
+
+/// Delete the remembered state, listing what was removed
+fn run_state(action: cli::StateAction, config: &config::UserConfig) -> Result<Outcome> {
+    match action {
+        cli::StateAction::Clear => {
+            for dir in state::clear(config.encrypt_state)? {
+                outln!("Removed {}", dir.display());
+            }
+            Ok(Outcome::Done(Some("State cleared".to_string())))
+        }
+    }
+}
+
+/// Write a sanitized bug report bundle to `out_dir`
+async fn run_report(
+    out_dir: Option<&std::path::Path>,
+    cwd: &std::path::Path,
+    port: Option<u16>,
+    deadline: std::time::Duration,
+    auth: Option<server::Auth>,
+) -> Result<Outcome> {
+    let out_dir = out_dir
+        .map(std::path::Path::to_path_buf)
+        .unwrap_or_else(|| {
+            let time = chrono::Local::now().format("%Y%m%d-%H%M%S");
+            format!("opencode-helix-report-{}", time).into()
+        });
+
+    let log_path = paths::debug_log();
+    let log = match std::fs::read_to_string(log_path) {
+        Ok(log) => Some(bundle::sanitize_log(&log)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", log_path.display())),
+    };
+
+    // Best-effort: a report is most needed when the server can't be reached
+    let server = match server::discover_server(cwd, port, auth.as_ref(), deadline).await {
+        Ok(server) => match server::Client::new(server.port)
+            .with_auth(auth)
+            .capabilities()
+            .await
+        {
+            Ok(caps) => {
+                let version = caps
+                    .server_version
+                    .clone()
+                    .unwrap_or_else(|| "unknown version".to_string());
+                match caps.compat_warning() {
+                    Some(warning) => format!("{} (warning: {})", version, warning),
+                    None => version,
+                }
+            }
+            Err(e) => format!(
+                "capabilities unavailable ({})",
+                bundle::sanitize_message(&format!("{:#}", e))
+            ),
+        },
+        Err(_) => "no server found".to_string(),
+    };
+
+    let env = bundle::Environment {
+        version: version::BuildInfo::current().line(),
+        os: format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+        terminal: ["TERM", "TERM_PROGRAM", "TERM_PROGRAM_VERSION", "COLORTERM"]
+            .into_iter()
+            .filter_map(|name| Some((name.to_string(), std::env::var(name).ok()?)))
+            .collect(),
+        server,
+        invocation: log.as_deref().and_then(bundle::last_invocation),
+    };
+
+    docs::write_atomic(
+        &out_dir.join("report.txt"),
+        bundle::summary(&env).as_bytes(),
+    )?;
+    if let Some(log) = &log {
+        docs::write_atomic(&out_dir.join("debug.log"), log.as_bytes())?;
+    }
+    Ok(Outcome::Done(Some(format!(
+        "Wrote {}; check it before sharing",
+        out_dir.display()
+    ))))
 }
 
-/// Truncate a string for display
-fn truncate(s: &str, max_len: usize) -> String {
-    if s.len() <= max_len {
-        s.to_string()
+/// Print the version, or with `json` the build details as JSON
+fn run_version(json: bool) -> Result<Outcome> {
+    let info = version::BuildInfo::current();
+    if json {
+        outln!("{}", serde_json::to_string_pretty(&info)?);
     } else {
-        format!("{}...", &s[..max_len])
+        outln!("opencode-helix {}", info.line());
+    }
+    Ok(Outcome::Done(None))
+}
+
+/// Remove temp files left behind with a selection in them (see `temp_files`)
+///
+/// Those that can't be deleted are reported in one warning.
+fn clean_temp_files(max_age: std::time::Duration, quiet: bool, debug: bool) {
+    let cleanup = temp_files::clean_all(max_age);
+    for path in &cleanup.removed {
+        debug_log(debug, &format!("Removed left-behind temp file {:?}", path));
+    }
+    for (path, e) in &cleanup.failed {
+        debug_log(debug, &format!("Can't remove temp file {:?}: {}", path, e));
+    }
+    if let (Some((path, e)), false) = (cleanup.failed.first(), quiet) {
+        let more = match cleanup.failed.len() {
+            1 => String::new(),
+            n => format!(" and {} more", n - 1),
+        };
+        errln!(
+            "Warning: can't remove left-behind temp file {}{} ({})",
+            path.display(),
+            more,
+            e
+        );
+    }
+}
+
+/// Empty the debug log, making it private if an older run left it readable
+///
+/// The state directory is created first; if that fails, so does logging,
+/// which is reported once here.
+fn clear_debug_log() {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    let path = paths::debug_log();
+    if let Some(dir) = path.parent() {
+        if let Err(e) = paths::ensure_dir(dir) {
+            errln!("Warning: no debug log: {:#}", e);
+            return;
+        }
+    }
+    if let Ok(file) = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+    {
+        let _ = file.set_permissions(std::fs::Permissions::from_mode(0o600));
+    }
+}
+
+/// Walk a new user through finding the server, the keybindings and a config
+#[cfg(feature = "tui")]
+async fn run_onboarding(cli: &Cli) -> Result<Outcome> {
+    let cwd = cli.working_directory();
+    let project = load_project(&cwd, cli.quiet, cli.debug);
+    let auth = server_auth(cli, project.as_ref()).context(ErrorKind::Usage)?;
+    let server = discover(
+        cli,
+        server::discover_server(&cwd, cli.port, auth.as_ref(), cli.discovery_timeout),
+    )
+    .await
+    .map(|server| format!("on port {} in {}", server.port, server.cwd.display()))
+    .map_err(|e| e.to_string());
+    let onboarding = onboarding::Onboarding {
+        server,
+        keybindings: setup::helix(&Cli::command(), ";"),
+        cwd,
+        config: paths::config_file(cli.config.as_deref()),
+    };
+    let mut app = App::with_theme(cli.debug, ThemeKind::from_str(&cli.theme))?;
+    let done = onboarding.run(&mut app);
+    app.restore()?;
+    let Some(done) = done? else {
+        return Ok(Outcome::Cancelled);
+    };
+    for line in done {
+        outln!("{}", line);
+    }
+    Ok(Outcome::Done(Some(
+        "Set up. Run `opencode-helix --help` for the subcommands".to_string(),
+    )))
+}
+
+/// Without the dialogs there is no first-run setup to fall back on
+#[cfg(not(feature = "tui"))]
+async fn run_onboarding(_cli: &Cli) -> Result<Outcome> {
+    Err(anyhow::anyhow!(
+        "no subcommand given (see --help); the first-run setup needs TUI support"
+    ))
+    .context(ErrorKind::Usage)
+}
+
+/// Print keybindings for `editor` to paste into its config
+fn run_setup(editor: cli::Editor, leader: &str) -> Outcome {
+    let snippet = match editor {
+        cli::Editor::Helix => setup::helix(&Cli::command(), leader),
+    };
+    outln!("{}", snippet.trim_end());
+    Outcome::Done(None)
+}
+
+/// Write the generated docs to `out_dir`
+fn run_gen_docs(format: cli::DocsFormat, out_dir: &std::path::Path) -> Result<Outcome> {
+    let files = match format {
+        cli::DocsFormat::Man => docs::man_pages(&Cli::command())?,
+        cli::DocsFormat::Markdown => vec![(
+            "opencode-helix.md".to_string(),
+            docs::markdown(&Cli::command()).into_bytes(),
+        )],
+    };
+    for (name, contents) in &files {
+        docs::write_atomic(&out_dir.join(name), contents)?;
+    }
+    let count = match files.len() {
+        1 => "1 file".to_string(),
+        n => format!("{} files", n),
+    };
+    Ok(Outcome::Done(Some(format!(
+        "Wrote {} to {}",
+        count,
+        out_dir.display()
+    ))))
+}
+
+/// Print the session activity until Ctrl+C, reconnecting when the stream drops
+async fn run_watch(
+    client: &server::Client,
+    json: bool,
+    opts: &SendOptions,
+    debug: bool,
+) -> Result<Outcome> {
+    use std::io::IsTerminal;
+
+    let color = watch::use_color(std::io::stdout().is_terminal(), |var| {
+        std::env::var(var).ok()
+    });
+    let session = client.session().map(str::to_string);
+    let mut log = watch::Log::new(session.clone(), color);
+    let mut backoff = watch::Backoff::default();
+    if !opts.quiet {
+        errln!(
+            "Watching opencode on port {} (Ctrl+C to stop)",
+            client.port()
+        );
+    }
+
+    let follow = async {
+        loop {
+            let error = match client.subscribe_events().await {
+                Ok(mut events) => {
+                    backoff.reset();
+                    let followed = server::events::follow(
+                        &mut events,
+                        opts.config.keepalive(),
+                        || ping(client, debug),
+                        |value| {
+                            let event = server::events::Event::parse(&value);
+                            let wanted = session.as_deref().is_none_or(|session| {
+                                watch::session_of(&value, &event) == Some(session)
+                            });
+                            if json && wanted {
+                                outln!("{}", value);
+                            } else if !json {
+                                for line in log.lines(&event) {
+                                    outln!("{}", line);
+                                }
+                            }
+                            false
+                        },
+                    )
+                    .await;
+                    match followed {
+                        Ok(_) => "the event stream closed".to_string(),
+                        Err(e) => format!("{:#}", e),
+                    }
+                }
+                Err(e) => format!("{:#}", e),
+            };
+            let delay = backoff.next_delay();
+            debug_log(debug, &format!("watch: {}; retrying in {:?}", error, delay));
+            if !opts.quiet {
+                errln!(
+                    "Disconnected ({}); reconnecting in {}s",
+                    error,
+                    delay.as_secs()
+                );
+            }
+            tokio::time::sleep(delay).await;
+        }
+    };
+    tokio::select! {
+        () = follow => unreachable!("watch only stops on Ctrl+C"),
+        _ = tokio::signal::ctrl_c() => Ok(Outcome::Done(None)),
+    }
+}
+
+/// Show server status
+async fn run_status(
+    server: &server::Server,
+    client: &server::Client,
+    json: bool,
+    quiet: bool,
+) -> Result<Outcome> {
+    let caps = client.capabilities().await.context(ErrorKind::Server)?;
+    // On stderr, so it doesn't get in the way of --json
+    if let Some(warning) = caps.compat_warning() {
+        if !quiet {
+            errln!("Warning: {}", warning);
+        }
+    }
+
+    if json {
+        let status = serde_json::json!({
+            "port": server.port,
+            "cwd": server.cwd,
+            "pid": (server.pid > 0).then_some(server.pid),
+            "auth": client.has_auth(),
+            "capabilities": caps,
+            "compat": caps.compat(),
+        });
+        outln!("{}", serde_json::to_string_pretty(&status)?);
+        return Ok(Outcome::Done(None));
+    }
+
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
+    outln!("opencode server:");
+    outln!("  Port: {}", server.port);
+    outln!("  CWD:  {}", server.cwd.display());
+    if server.pid > 0 {
+        outln!("  PID:  {}", server.pid);
+    }
+    outln!(
+        "  Version:  {}",
+        caps.server_version.as_deref().unwrap_or("unknown")
+    );
+    outln!("  Sessions: {}", yes_no(caps.has_sessions));
+    outln!("  Events:   {}", yes_no(caps.has_events));
+    outln!("  Models:   {}", yes_no(caps.has_models));
+    outln!("  Prompt:   {}", yes_no(caps.has_prompt_text));
+    // The probes above would have failed on a rejected token
+    if client.has_auth() {
+        outln!("  Auth:     token accepted");
     }
+    Ok(Outcome::Done(None))
 }
@@ -0,0 +1,46 @@
+//! Menu items shared by the select UI and prompt/command conversion
+//!
+//! Kept outside the `tui` module so headless builds can still build menus.
+
+/// An item in the select menu
+#[derive(Debug, Clone)]
+pub struct SelectItem {
+    /// Display name
+    pub name: String,
+    /// Description
+    pub description: String,
+    /// Value to return when selected
+    pub value: String,
+    /// Category for grouping
+    pub category: String,
+    /// Whether the value can be edited in the ask dialog before it is sent
+    pub editable: bool,
+}
+
+impl SelectItem {
+    /// Create a new menu item
+    pub fn new(name: &str, description: &str, value: &str, category: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            value: value.to_string(),
+            category: category.to_string(),
+            editable: true,
+        }
+    }
+
+    /// Mark the value as not editable (e.g. a chain's name)
+    pub fn fixed(mut self) -> Self {
+        self.editable = false;
+        self
+    }
+}
+
+/// What the background refresh of a menu opened on cached items found
+#[derive(Debug)]
+pub enum Refresh {
+    /// The server's lists are the cached ones
+    Unchanged,
+    /// They changed: the menu's items now
+    Items(Vec<SelectItem>),
+}
@@ -0,0 +1,71 @@
+//! Completion notifications for --wait sends
+//!
+//! Always rings the terminal bell; a desktop notification is attempted on top
+//! (notify-rust with the `notifications` feature, `notify-send` otherwise) and
+//! silently skipped when no notification service is available.
+
+use std::io::Write;
+
+/// Notification title
+const NOTIFY_TITLE: &str = "opencode";
+
+/// Ring the terminal bell and show a desktop notification with the reply's first line
+pub fn notify_done(reply: Option<&str>) {
+    ring_bell();
+
+    let body = summary_line(reply);
+    if !desktop_notify(&body) {
+        let _ = std::process::Command::new("notify-send")
+            .args([NOTIFY_TITLE, &body])
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status();
+    }
+}
+
+/// Write BEL to the terminal
+fn ring_bell() {
+    if let Ok(mut tty) = std::fs::OpenOptions::new().write(true).open("/dev/tty") {
+        let _ = tty.write_all(b"\x07");
+        let _ = tty.flush();
+    }
+}
+
+/// First non-empty line of the reply, or a generic message
+fn summary_line(reply: Option<&str>) -> String {
+    reply
+        .and_then(|r| r.lines().map(str::trim).find(|l| !l.is_empty()))
+        .map(str::to_string)
+        .unwrap_or_else(|| "Session finished".to_string())
+}
+
+/// Show a desktop notification; returns false if it could not be shown
+#[cfg(feature = "notifications")]
+fn desktop_notify(body: &str) -> bool {
+    notify_rust::Notification::new()
+        .summary(NOTIFY_TITLE)
+        .body(body)
+        .show()
+        .is_ok()
+}
+
+/// Show a desktop notification; returns false if it could not be shown
+#[cfg(not(feature = "notifications"))]
+fn desktop_notify(_body: &str) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_line() {
+        assert_eq!(
+            summary_line(Some("\n  Done: fixed it\nmore")),
+            "Done: fixed it"
+        );
+        assert_eq!(summary_line(Some("")), "Session finished");
+        assert_eq!(summary_line(None), "Session finished");
+    }
+}
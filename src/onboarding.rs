@@ -0,0 +1,255 @@
+//! First run: what `opencode-helix` does without a subcommand
+//!
+//! Run by hand, the other subcommands stop at "No opencode processes found",
+//! which tells a new user little. Without a subcommand the binary walks
+//! through getting set up instead, in the usual dialogs, each step
+//! skippable:
+//!
+//! 1. whether an opencode server can be found from here
+//! 2. the Helix keybindings `setup` prints, written to a file the user
+//!    names (their Helix config is never edited)
+//! 3. a starter config with the settings commented out, unless there is one
+//!
+//! What each step did is returned for a summary on stdout.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::paths;
+use crate::tui::app::{App, AppResult};
+use crate::tui::backend::Backend;
+
+/// Config written by the last step: every setting, commented out
+pub const STARTER_CONFIG: &str = r#"# opencode-helix configuration. Every setting is optional; uncomment to change.
+
+# Notify when a --wait send takes at least this many seconds (even without --notify)
+# notify_after_secs = 30
+
+# Longer selections are shortened by @selection (use @selection:full for all of it)
+# max_selection_lines = 400
+
+# Files read into a prompt (--register-file) or the selection preview over this
+# many bytes are skipped, as are binary files
+# max_file_bytes = 1048576
+
+# Text cursor in the dialogs: "block", "reversed" or "terminal"
+# cursor_style = "block"
+
+# Terminal cursor shape in the dialogs: "block", "underline" or "bar"
+# cursor_shape = "bar"
+
+# Draw the dialogs below the cursor instead of on the alternate screen (as with --inline)
+# inline = false
+
+# Record each run locally for `opencode-helix stats`
+# stats = false
+
+# Estimated tokens over which the ask dialog warns, and asks before sending
+# token_budget = 8000
+# token_limit = 32000
+
+# Hide built-in prompts from `prompt`, the select menu and `list prompts`
+# disabled_prompts = ["optimize"]
+
+# Custom prompts (a name matching a built-in overrides it)
+# [prompts.security]
+# prompt = "Check this code for security issues: @this"
+# description = "Security review"
+
+# Ask dialog quick actions: the key submits the input through the template
+# [quick_actions]
+# F2 = "{input}\n\nContext: @diff"
+
+# Limits on every send, whatever the flags ask (--override-policy lifts them for a run)
+# [policy]
+# never_submit = false
+# confirm_submit_over_bytes = 16384
+# allowed_agents = ["build", "plan"]
+"#;
+
+/// What the steps work with, found before the dialogs open
+pub struct Onboarding {
+    /// The server discovery found, described, or why there is none
+    pub server: std::result::Result<String, String>,
+    /// Helix keybindings, as `setup` prints them
+    pub keybindings: String,
+    /// Directory a relative keybindings file name is taken from
+    pub cwd: PathBuf,
+    /// The config file, None without a home directory
+    pub config: Option<PathBuf>,
+}
+
+/// File name first offered for the keybindings
+const KEYS_FILE: &str = "opencode-helix-keys.toml";
+
+impl Onboarding {
+    /// Run the steps in `app`, returning what each did; None when the user quit
+    pub fn run<B: Backend>(&self, app: &mut App<B>) -> Result<Option<Vec<String>>> {
+        let mut done = Vec::new();
+
+        let question = match &self.server {
+            Ok(server) => format!(
+                "Found opencode {}. Prompts from Helix will go there.",
+                server
+            ),
+            Err(e) => format!(
+                "No opencode server found from here ({}). Start opencode in your project; \
+                 opencode-helix sends to it.",
+                e
+            ),
+        };
+        if app.run_choice(&question, &["Continue", "Quit"])? != Some(0) {
+            return Ok(None);
+        }
+        done.push(match &self.server {
+            Ok(server) => format!("Server: {}", server),
+            Err(_) => "Server: none running yet".to_string(),
+        });
+
+        let question = "Write the Helix keybindings to a file? Paste them into \
+                        ~/.config/helix/config.toml yourself; it is never edited.";
+        match app.run_choice(question, &["Write", "Skip", "Quit"])? {
+            Some(0) => match app.run_ask(KEYS_FILE, None, None, false)? {
+                AppResult::Submit(name) => {
+                    let path = resolve(&self.cwd, name.trim());
+                    done.push(match write_new(&path, &self.keybindings) {
+                        Ok(()) => format!("Keybindings: written to {}", path.display()),
+                        Err(e) => format!("Keybindings: not written: {:#}", e),
+                    });
+                }
+                AppResult::Cancel => done.push("Keybindings: skipped".to_string()),
+            },
+            Some(1) => {
+                done.push("Keybindings: skipped (`opencode-helix setup` prints them)".into())
+            }
+            _ => return Ok(None),
+        }
+
+        match &self.config {
+            Some(path) if path.exists() => {
+                done.push(format!("Config: {} already exists", path.display()));
+            }
+            Some(path) => {
+                let question = format!(
+                    "Create a starter config at {}? Its settings are commented out.",
+                    path.display()
+                );
+                match app.run_choice(&question, &["Create", "Skip", "Quit"])? {
+                    Some(0) => done.push(match write_new(path, STARTER_CONFIG) {
+                        Ok(()) => format!("Config: created {}", path.display()),
+                        Err(e) => format!("Config: not created: {:#}", e),
+                    }),
+                    Some(1) => done.push("Config: skipped".to_string()),
+                    _ => return Ok(None),
+                }
+            }
+            None => done.push("Config: no home directory to put one in".to_string()),
+        }
+        Ok(Some(done))
+    }
+}
+
+/// `name` as a path: `~/` is the home directory, relative to `cwd` otherwise
+fn resolve(cwd: &Path, name: &str) -> PathBuf {
+    match (name.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => cwd.join(name),
+    }
+}
+
+/// Write `text` to a new file at `path`; an existing file is left alone
+fn write_new(path: &Path, text: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        paths::ensure_dir(dir)?;
+    }
+    std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(text.as_bytes()))
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::UserConfig;
+    use crate::tui::backend::Headless;
+    use crate::tui::theme::ThemeKind;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    fn press(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_starter_config_parses() {
+        let config = UserConfig::parse(STARTER_CONFIG).unwrap();
+        assert_eq!(config.token_limit, None);
+        assert!(config.prompts.is_empty());
+        // And still does with everything uncommented
+        let uncommented: String = STARTER_CONFIG
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.strip_prefix("# "))
+            .filter(|line| line.contains('=') || line.starts_with('['))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        let config = UserConfig::parse(&uncommented).unwrap();
+        assert_eq!(config.token_limit, Some(32000));
+        assert!(config.prompts.contains_key("security"));
+        assert_eq!(config.policy.confirm_submit_over_bytes, Some(16384));
+    }
+
+    #[test]
+    fn test_onboarding_steps() {
+        let dir = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        let onboarding = Onboarding {
+            server: Err("no opencode processes".to_string()),
+            keybindings: "[keys.normal]\n".to_string(),
+            cwd: dir.clone(),
+            config: Some(dir.join("config/config.toml")),
+        };
+        // Continue, Write (to the offered name), Create
+        let keys = [
+            press(KeyCode::Enter),
+            press(KeyCode::Enter),
+            press(KeyCode::Enter),
+            press(KeyCode::Enter),
+        ];
+        let mut app = App::with_backend(Headless::new(100, 48, keys), false, ThemeKind::default());
+        let done = onboarding.run(&mut app).unwrap().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join(KEYS_FILE)).unwrap(),
+            "[keys.normal]\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("config/config.toml")).unwrap(),
+            STARTER_CONFIG
+        );
+        assert_eq!(done[0], "Server: none running yet");
+        assert!(done[2].starts_with("Config: created"), "{:?}", done);
+
+        // Nothing is overwritten; Esc quits
+        let keys = [
+            press(KeyCode::Enter),
+            press(KeyCode::Enter),
+            press(KeyCode::Enter),
+            press(KeyCode::Esc),
+        ];
+        let mut app = App::with_backend(Headless::new(100, 48, keys), false, ThemeKind::default());
+        let onboarding = Onboarding {
+            keybindings: "[keys.select]\n".to_string(),
+            config: Some(dir.join("missing/config.toml")),
+            ..onboarding
+        };
+        assert_eq!(onboarding.run(&mut app).unwrap(), None);
+        assert_eq!(
+            std::fs::read_to_string(dir.join(KEYS_FILE)).unwrap(),
+            "[keys.normal]\n"
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,117 @@
+//! Status output that tolerates closed pipes
+//!
+//! Helix may close the `:insert-output` pipe early (e.g. when the user
+//! cancels). `println!`/`eprintln!` panic on a write error, which would
+//! replace the real exit code with a panic after the work is already done,
+//! so messages go through these helpers instead. SIGPIPE is ignored at
+//! startup so writes to a closed pipe return `EPIPE` rather than killing us.
+
+use std::fmt;
+use std::future::Future;
+use std::io::{self, Write};
+use std::time::Duration;
+
+/// Silence after which [`with_status`] says what is being waited for
+pub const STATUS_DELAY: Duration = Duration::from_millis(300);
+
+/// Print a line to stdout; write errors are dropped (there is nowhere to report them)
+macro_rules! outln {
+    ($($arg:tt)*) => {{
+        let _ = $crate::output::write_line(&mut std::io::stdout().lock(), format_args!($($arg)*));
+    }};
+}
+
+/// Print a line to stderr; write errors are dropped (there is nowhere to report them)
+macro_rules! errln {
+    ($($arg:tt)*) => {{
+        let _ = $crate::output::write_line(&mut std::io::stderr().lock(), format_args!($($arg)*));
+    }};
+}
+
+/// Ignore SIGPIPE so a closed pipe surfaces as an `EPIPE` write error
+pub fn ignore_sigpipe() {
+    // SAFETY: called at startup; SIG_IGN installs no handler code
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_IGN);
+    }
+}
+
+/// Write a line and flush; a broken pipe (the reader went away) is not an error
+pub fn write_line(out: &mut impl Write, args: fmt::Arguments) -> io::Result<()> {
+    match out
+        .write_fmt(args)
+        .and_then(|()| out.write_all(b"\n"))
+        .and_then(|()| out.flush())
+    {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
+
+/// Run `work`, writing `status` to `out` if it takes longer than `delay`
+///
+/// The line is cleared again once `work` is done, so a dialog or the next
+/// message starts on a clean row.
+pub async fn with_status<T>(
+    mut out: impl Write,
+    delay: Duration,
+    status: &str,
+    work: impl Future<Output = T>,
+) -> T {
+    tokio::pin!(work);
+    tokio::select! {
+        result = &mut work => return result,
+        () = tokio::time::sleep(delay) => {}
+    }
+    let _ = write!(out, "{}", status).and_then(|()| out.flush());
+    let result = work.await;
+    let _ = write!(out, "\r\x1b[2K").and_then(|()| out.flush());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A writer whose reader has gone away
+    struct ClosedPipe;
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(io::ErrorKind::BrokenPipe))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::Error::from(io::ErrorKind::BrokenPipe))
+        }
+    }
+
+    #[test]
+    fn test_write_line() {
+        let mut buf = Vec::new();
+        write_line(&mut buf, format_args!("Sent: {}", 42)).unwrap();
+        assert_eq!(buf, b"Sent: 42\n");
+
+        assert!(write_line(&mut ClosedPipe, format_args!("Cancelled")).is_ok());
+        let mut full: &mut [u8] = &mut [];
+        assert!(write_line(&mut full, format_args!("x")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_with_status() {
+        let mut out = Vec::new();
+        let delay = Duration::from_millis(50);
+        assert_eq!(
+            with_status(&mut out, delay, "waiting…", async { 1 }).await,
+            1
+        );
+        assert!(out.is_empty());
+
+        let slow = async {
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            2
+        };
+        assert_eq!(with_status(&mut out, delay, "waiting…", slow).await, 2);
+        assert_eq!(String::from_utf8(out).unwrap(), "waiting…\r\x1b[2K");
+    }
+}
@@ -0,0 +1,272 @@
+//! Where opencode-helix keeps its files
+//!
+//! Everything follows the XDG base directories, so every file location
+//! goes through here:
+//!
+//! - config: `--config`, `$OPENCODE_HELIX_CONFIG`, or
+//!   `$XDG_CONFIG_HOME/opencode-helix/config.toml` (default `~/.config`)
+//! - translations of the dialogs: `$XDG_CONFIG_HOME/opencode-helix/strings/`
+//! - state (history, stats, collapsed categories, the debug log):
+//!   `$OPENCODE_HELIX_STATE_DIR`, or `$XDG_STATE_HOME/opencode-helix`
+//!   (default `~/.local/state`)
+//! - cache: `$XDG_CACHE_HOME/opencode-helix` (default `~/.cache`)
+//! - runtime (the `--queue` queue): `$XDG_RUNTIME_DIR/opencode-helix`, or
+//!   `opencode-helix-<uid>` in the temp dir, kept private
+//!   ([`ensure_private_dir`])
+//!
+//! Nothing is created here; writers call [`ensure_dir`] when they first need
+//! a directory.
+
+use std::ffi::OsString;
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::{bail, Context, Result};
+
+/// Environment variable naming the config file (like `--config`)
+pub const CONFIG_ENV: &str = "OPENCODE_HELIX_CONFIG";
+
+/// Environment variable overriding the state directory
+pub const STATE_DIR_ENV: &str = "OPENCODE_HELIX_STATE_DIR";
+
+/// Directory name under each base directory
+const APP: &str = "opencode-helix";
+
+/// A config file named with `--config` or `$OPENCODE_HELIX_CONFIG`; it must exist
+pub fn explicit_config(flag: Option<&Path>) -> Option<PathBuf> {
+    flag.map(Path::to_path_buf)
+        .or_else(|| env_path(&process_env, CONFIG_ENV))
+}
+
+/// Config file: the explicit one if given, else the XDG location
+pub fn config_file(flag: Option<&Path>) -> Option<PathBuf> {
+    explicit_config(flag).or_else(|| default_config(&process_env))
+}
+
+/// Directory of the dialogs' locale files (`<locale>.toml`)
+#[cfg(feature = "tui")]
+pub fn strings_dir() -> Option<PathBuf> {
+    config_home(&process_env).map(|base| base.join(APP).join("strings"))
+}
+
+/// State directory
+pub fn state_dir() -> PathBuf {
+    state_dir_in(&process_env)
+}
+
+/// Cache directory
+pub fn cache_dir() -> PathBuf {
+    base_dir(&process_env, "XDG_CACHE_HOME", ".cache").join(APP)
+}
+
+/// Runtime directory, for files that needn't outlive the session
+pub fn runtime_dir() -> PathBuf {
+    runtime_dir_in(&process_env)
+}
+
+/// Debug log written with `--debug` (`debug.log` in the state directory)
+pub fn debug_log() -> &'static Path {
+    static PATH: OnceLock<PathBuf> = OnceLock::new();
+    PATH.get_or_init(|| state_dir().join("debug.log"))
+}
+
+/// Create `dir` (and its parents) if missing
+///
+/// A permission error says which variable moves the directory elsewhere.
+pub fn ensure_dir(dir: &Path) -> Result<()> {
+    match std::fs::create_dir_all(dir) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Err(e).with_context(|| {
+            format!(
+                "No permission to create {} (set {} or the XDG base directory variables \
+                 to a writable location)",
+                dir.display(),
+                STATE_DIR_ENV
+            )
+        }),
+        Err(e) => Err(e).with_context(|| format!("Failed to create {}", dir.display())),
+    }
+}
+
+/// Create `dir` if missing, readable only by us, and check that it is
+///
+/// In a shared place such as the temp dir another user could have made
+/// it first, to read what we write there or plant files for us to read,
+/// so a directory of someone else's is refused. Ours, if open to others,
+/// is closed.
+pub fn ensure_private_dir(dir: &Path) -> Result<()> {
+    if let Some(parent) = dir.parent() {
+        ensure_dir(parent)?;
+    }
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e).with_context(|| format!("Failed to create {}", dir.display())),
+    }
+    // Not following a symlink someone put in its place
+    let metadata = std::fs::symlink_metadata(dir)
+        .with_context(|| format!("Failed to read {}", dir.display()))?;
+    if !metadata.is_dir() || metadata.uid() != unsafe { libc::geteuid() } {
+        bail!(
+            "{} is not a directory of this user; refusing to use it",
+            dir.display()
+        );
+    }
+    if metadata.mode() & 0o077 != 0 {
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))
+            .with_context(|| format!("Failed to make {} private", dir.display()))?;
+    }
+    Ok(())
+}
+
+/// Environment lookup, replaceable in tests
+type Env<'a> = &'a dyn Fn(&str) -> Option<OsString>;
+
+fn process_env(var: &str) -> Option<OsString> {
+    std::env::var_os(var)
+}
+
+/// Path in `var`, ignoring it when empty (as the XDG spec asks)
+fn env_path(env: Env, var: &str) -> Option<PathBuf> {
+    env(var)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+/// `$var`, else `~/fallback`, else the temp dir
+fn base_dir(env: Env, var: &str, fallback: &str) -> PathBuf {
+    env_path(env, var)
+        .or_else(|| env_path(env, "HOME").map(|home| home.join(fallback)))
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+/// `$XDG_CONFIG_HOME`, else `~/.config`
+fn config_home(env: Env) -> Option<PathBuf> {
+    env_path(env, "XDG_CONFIG_HOME")
+        .or_else(|| env_path(env, "HOME").map(|home| home.join(".config")))
+}
+
+fn default_config(env: Env) -> Option<PathBuf> {
+    Some(config_home(env)?.join(APP).join("config.toml"))
+}
+
+fn state_dir_in(env: Env) -> PathBuf {
+    env_path(env, STATE_DIR_ENV)
+        .unwrap_or_else(|| base_dir(env, "XDG_STATE_HOME", ".local/state").join(APP))
+}
+
+/// `$XDG_RUNTIME_DIR` is private to the user; the temp dir is shared, so
+/// the directory there is named after the user
+fn runtime_dir_in(env: Env) -> PathBuf {
+    match env_path(env, "XDG_RUNTIME_DIR") {
+        Some(dir) => dir.join(APP),
+        None => std::env::temp_dir().join(format!("{}-{}", APP, unsafe { libc::geteuid() })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<OsString> {
+        let vars: HashMap<String, OsString> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), OsString::from(value)))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_xdg_defaults() {
+        let home = env(&[("HOME", "/home/u")]);
+        assert_eq!(
+            default_config(&home),
+            Some("/home/u/.config/opencode-helix/config.toml".into())
+        );
+        assert_eq!(
+            state_dir_in(&home),
+            PathBuf::from("/home/u/.local/state/opencode-helix")
+        );
+        assert_eq!(
+            base_dir(&home, "XDG_CACHE_HOME", ".cache"),
+            PathBuf::from("/home/u/.cache")
+        );
+        assert_eq!(
+            runtime_dir_in(&home),
+            std::env::temp_dir().join(format!("opencode-helix-{}", unsafe { libc::geteuid() }))
+        );
+        assert_eq!(default_config(&env(&[])), None);
+    }
+
+    #[test]
+    fn test_xdg_overrides() {
+        let xdg = env(&[
+            ("HOME", "/home/u"),
+            ("XDG_CONFIG_HOME", "/cfg"),
+            ("XDG_STATE_HOME", "/state"),
+            ("XDG_RUNTIME_DIR", "/run/user/1"),
+            // Empty means unset
+            ("XDG_CACHE_HOME", ""),
+        ]);
+        assert_eq!(
+            default_config(&xdg),
+            Some("/cfg/opencode-helix/config.toml".into())
+        );
+        assert_eq!(state_dir_in(&xdg), PathBuf::from("/state/opencode-helix"));
+        assert_eq!(
+            runtime_dir_in(&xdg),
+            PathBuf::from("/run/user/1/opencode-helix")
+        );
+        assert_eq!(
+            base_dir(&xdg, "XDG_CACHE_HOME", ".cache"),
+            PathBuf::from("/home/u/.cache")
+        );
+
+        // The app's own variable wins, used as is
+        let own = env(&[("XDG_STATE_HOME", "/state"), (STATE_DIR_ENV, "/mine")]);
+        assert_eq!(state_dir_in(&own), PathBuf::from("/mine"));
+    }
+
+    #[test]
+    fn test_ensure_dir() {
+        let dir = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        let nested = dir.join("a/b");
+        ensure_dir(&nested).unwrap();
+        ensure_dir(&nested).unwrap();
+        assert!(nested.is_dir());
+
+        // A file in the way is reported with the path
+        let blocker = dir.join("file");
+        std::fs::write(&blocker, "").unwrap();
+        let error = format!("{:#}", ensure_dir(&blocker.join("sub")).unwrap_err());
+        let _ = std::fs::remove_dir_all(&dir);
+        assert!(error.contains("Failed to create"), "{}", error);
+        assert!(error.contains("file/sub"), "{}", error);
+    }
+
+    #[test]
+    fn test_ensure_private_dir() {
+        let dir = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        let private = dir.join("queue");
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().mode() & 0o777;
+        ensure_private_dir(&private).unwrap();
+        assert_eq!(mode(&private), 0o700);
+
+        // One of ours left open is closed
+        std::fs::set_permissions(&private, std::fs::Permissions::from_mode(0o755)).unwrap();
+        ensure_private_dir(&private).unwrap();
+        assert_eq!(mode(&private), 0o700);
+
+        // A symlink or a file in its place is refused
+        let link = dir.join("link");
+        std::os::unix::fs::symlink(&private, &link).unwrap();
+        let error = format!("{:#}", ensure_private_dir(&link).unwrap_err());
+        assert!(error.contains("refusing"), "{}", error);
+        std::fs::write(dir.join("file"), "").unwrap();
+        assert!(ensure_private_dir(&dir.join("file")).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,360 @@
+//! `[policy]`: limits on what a send may do, whatever the keybinding asks
+//!
+//! A prompt submitted half-written to an expensive model can't be taken
+//! back, so the config can hold a team's rules (see [`Policy`]):
+//!
+//! - `never_submit`: prompts are appended to the prompt box, never submitted
+//! - `confirm_submit_over_bytes`: a longer prompt is only submitted once the
+//!   dialogs confirm it; without them it is appended
+//! - `allowed_agents`: a prompt for any other agent (`--agent`, or an `@name`
+//!   mention of an agent the server has) isn't sent
+//!
+//! Every send goes through a [`SendGate`], so no command gets around the
+//! rules. `--override-policy` lifts them for one run, once "yes" is typed at
+//! the terminal ([`confirm_override`]).
+
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::agent;
+use crate::config::Policy;
+use crate::exit::ErrorKind;
+use crate::server::{Client, PublishMode};
+use crate::util::{self, size_label};
+
+/// Asks the user a question in a dialog, returning the picked answer (None if cancelled)
+pub type Ask<'a> = Option<&'a dyn Fn(&str, &[&str]) -> Result<Option<usize>>>;
+
+/// What the policy makes of a send
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// Send it as asked
+    Allow,
+    /// Append it without submitting, for the reason given
+    Append(String),
+    /// Ask this before submitting it
+    Confirm(String),
+    /// Don't send it, for the reason given
+    Reject(String),
+}
+
+/// Judge sending `text`, submitted if `submit`, to `agents`
+pub fn judge(policy: &Policy, text: &str, submit: bool, agents: &[String]) -> Verdict {
+    if !policy.allowed_agents.is_empty() {
+        if let Some(agent) = agents
+            .iter()
+            .find(|agent| !policy.allowed_agents.contains(agent))
+        {
+            return Verdict::Reject(format!(
+                "the policy doesn't allow agent \"{}\" (allowed: {})",
+                agent,
+                policy.allowed_agents.join(", ")
+            ));
+        }
+    }
+    if !submit {
+        return Verdict::Allow;
+    }
+    if policy.never_submit {
+        return Verdict::Append("the policy sets never_submit".to_string());
+    }
+    match policy.confirm_submit_over_bytes {
+        Some(limit) if text.len() > limit => Verdict::Confirm(format!(
+            "This prompt is {}, over the {} the policy asks about. Submit it?",
+            size_label(text.len() as u64),
+            size_label(limit as u64)
+        )),
+        _ => Verdict::Allow,
+    }
+}
+
+/// The user declined a send the policy asked about
+#[derive(Debug)]
+pub struct Declined;
+
+impl fmt::Display for Declined {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Not sent: declined")
+    }
+}
+
+impl std::error::Error for Declined {}
+
+/// The one way prompts reach the server, within the policy
+///
+/// Errors carry their [`ErrorKind`]: a rejected send is a usage error, a
+/// failed request a server error. A declined confirmation is [`Declined`].
+pub struct SendGate<'a> {
+    client: &'a Client,
+    policy: &'a Policy,
+    /// `--agent`, without the `@`
+    agent: Option<&'a str>,
+    /// `--override-policy`, confirmed
+    overridden: bool,
+    /// Dialogs to confirm in, None outside them
+    ask: Ask<'a>,
+    /// Longest piece sent to the prompt box in one request
+    chunk_size: usize,
+    /// Leave out the notices on stderr
+    quiet: bool,
+}
+
+impl<'a> SendGate<'a> {
+    pub fn new(client: &'a Client, policy: &'a Policy) -> Self {
+        Self {
+            client,
+            policy,
+            agent: None,
+            overridden: false,
+            ask: None,
+            chunk_size: crate::config::DEFAULT_CHUNK_SIZE,
+            quiet: false,
+        }
+    }
+
+    /// The agent `--agent` hands prompts to
+    pub fn with_agent(mut self, agent: Option<&'a str>) -> Self {
+        self.agent = agent;
+        self
+    }
+
+    /// Lift the policy (`--override-policy`, once confirmed)
+    pub fn with_override(mut self, overridden: bool) -> Self {
+        self.overridden = overridden;
+        self
+    }
+
+    /// Confirm submits in these dialogs
+    pub fn with_ask(mut self, ask: Ask<'a>) -> Self {
+        self.ask = ask;
+        self
+    }
+
+    /// Split longer prompts for the prompt box into pieces of `chunk_size` bytes
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Leave out the notices on stderr (--quiet)
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// The client prompts are sent with
+    pub fn client(&self) -> &'a Client {
+        self.client
+    }
+
+    /// Send a prompt, in chunks if it is too long for one append event
+    ///
+    /// A session message can't be split, so only TUI sends are chunked;
+    /// `progress` gets the chunk number and count before each. Returns how
+    /// it went, and whether it was submitted: the policy may only append it.
+    pub async fn send(
+        &self,
+        text: &str,
+        parts: Option<&[crate::server::MessagePart]>,
+        clear: bool,
+        submit: bool,
+        progress: impl Fn(usize, usize),
+    ) -> Result<(PublishMode, bool)> {
+        let submit = self.admit(text, submit).await?;
+        let chunks = util::split_chunks(text, self.chunk_size);
+        let sent = if self.client.session().is_some() || chunks.len() == 1 {
+            self.client.send_prompt(text, parts, clear, submit).await
+        } else {
+            self.client
+                .send_chunks(&chunks, clear, submit, progress)
+                .await
+        };
+        let mode = sent.context(ErrorKind::Server)?;
+        Ok((mode, submit))
+    }
+
+    /// Whether `text` may be sent and submitted (Ok(false): only appended)
+    async fn admit(&self, text: &str, submit: bool) -> Result<bool> {
+        if self.overridden {
+            return Ok(submit);
+        }
+        let agents = self.agents(text).await;
+        match judge(self.policy, text, submit, &agents) {
+            Verdict::Allow => Ok(submit),
+            Verdict::Append(reason) => self.append(&reason),
+            Verdict::Confirm(question) => {
+                let Some(ask) = self.ask else {
+                    return self
+                        .append("a submit over confirm_submit_over_bytes needs the dialogs");
+                };
+                let session = self.client.session().is_some();
+                let choices: &[&str] = if session {
+                    &["Submit", "Cancel"]
+                } else {
+                    &["Submit", "Append", "Cancel"]
+                };
+                match ask(&question, choices)? {
+                    Some(0) => Ok(true),
+                    Some(1) if !session => Ok(false),
+                    _ => Err(Declined.into()),
+                }
+            }
+            Verdict::Reject(reason) => {
+                Err(anyhow!("Not sent: {}", reason)).context(ErrorKind::Usage)
+            }
+        }
+    }
+
+    /// Append instead of submitting, for `reason`; a session message can't be
+    fn append(&self, reason: &str) -> Result<bool> {
+        if self.client.session().is_some() {
+            return Err(anyhow!(
+                "Not sent: {}, and a --session message is always submitted",
+                reason
+            ))
+            .context(ErrorKind::Usage);
+        }
+        if !self.quiet {
+            errln!("Policy: {}; appended without submitting", reason);
+        }
+        Ok(false)
+    }
+
+    /// Agents `text` is handed to: the --agent and the server's agents it
+    /// mentions; only looked up when the policy limits them
+    async fn agents(&self, text: &str) -> Vec<String> {
+        if self.policy.allowed_agents.is_empty() {
+            return Vec::new();
+        }
+        let mut names: Vec<String> = self.agent.map(str::to_string).into_iter().collect();
+        // Without the list only the --agent can be checked
+        if let Ok(agents) = self.client.get_agents().await {
+            names.extend(agents.items.into_iter().map(|agent| agent.name));
+        }
+        names.sort();
+        names.dedup();
+        names.retain(|name| agent::mentions(text, name));
+        names
+    }
+}
+
+/// Ask at the terminal whether to lift the policy for this run; only "yes" does
+pub fn confirm_override() -> Result<bool> {
+    let tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .context("--override-policy needs a terminal to confirm on")
+        .context(ErrorKind::Usage)?;
+    let input = BufReader::new(tty.try_clone()?);
+    Ok(confirm_on(input, tty)?)
+}
+
+/// [`confirm_override`] on `input` and `output`
+fn confirm_on(mut input: impl BufRead, mut output: impl Write) -> io::Result<bool> {
+    write!(
+        output,
+        "Ignore the send policy for this run? Type \"yes\" to confirm: "
+    )?;
+    output.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(answer.trim() == "yes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_judge_never_submit() {
+        let policy = Policy {
+            never_submit: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            judge(&policy, "fix it", true, &[]),
+            Verdict::Append(_)
+        ));
+        // Appending was all that was asked anyway
+        assert_eq!(judge(&policy, "fix it", false, &[]), Verdict::Allow);
+        assert_eq!(
+            judge(&Policy::default(), "fix it", true, &[]),
+            Verdict::Allow
+        );
+    }
+
+    #[test]
+    fn test_judge_confirm_over_bytes() {
+        let policy = Policy {
+            confirm_submit_over_bytes: Some(2048),
+            ..Default::default()
+        };
+        assert_eq!(judge(&policy, &"x".repeat(2048), true, &[]), Verdict::Allow);
+        assert_eq!(
+            judge(&policy, &"x".repeat(3000), true, &[]),
+            Verdict::Confirm(
+                "This prompt is 2.9 KB, over the 2.0 KB the policy asks about. Submit it?"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            judge(&policy, &"x".repeat(3000), false, &[]),
+            Verdict::Allow
+        );
+        // never_submit wins: there is nothing to confirm
+        let policy = Policy {
+            never_submit: true,
+            ..policy
+        };
+        assert!(matches!(
+            judge(&policy, &"x".repeat(3000), true, &[]),
+            Verdict::Append(_)
+        ));
+    }
+
+    #[test]
+    fn test_judge_allowed_agents() {
+        let policy = Policy {
+            allowed_agents: names(&["plan", "reviewer"]),
+            ..Default::default()
+        };
+        assert_eq!(
+            judge(&policy, "@plan go", true, &names(&["plan"])),
+            Verdict::Allow
+        );
+        assert_eq!(judge(&policy, "go", true, &[]), Verdict::Allow);
+        assert_eq!(
+            judge(&policy, "@build go", false, &names(&["build"])),
+            Verdict::Reject(
+                "the policy doesn't allow agent \"build\" (allowed: plan, reviewer)".to_string()
+            )
+        );
+        // Any agent, when none are listed
+        assert_eq!(
+            judge(&Policy::default(), "@build go", true, &names(&["build"])),
+            Verdict::Allow
+        );
+    }
+
+    #[test]
+    fn test_confirm_on() {
+        let confirm = |typed: &str| {
+            let mut output = Vec::new();
+            let confirmed = confirm_on(typed.as_bytes(), &mut output).unwrap();
+            assert!(String::from_utf8(output).unwrap().contains("Type \"yes\""));
+            confirmed
+        };
+        assert!(confirm("yes\n"));
+        assert!(confirm("  yes  \n"));
+        assert!(!confirm("y\n"));
+        assert!(!confirm("YES please\n"));
+        assert!(!confirm(""));
+    }
+}
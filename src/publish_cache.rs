@@ -0,0 +1,77 @@
+//! How each server takes TUI events, remembered between runs
+//!
+//! Servers that take a batch of events on `/tui/publish` get a whole send in
+//! one request; older ones reject the batch and get one event per request.
+//! Which it was is kept in `$XDG_CACHE_HOME/opencode-helix/publish/<port>.json`,
+//! next to the select menu's lists, so later runs send the right way at once.
+//! As for the lists, an entry only serves the server on the same port serving
+//! the same directory.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::paths;
+use crate::state::{Plain, StateStore};
+
+/// What a server's `/tui/publish` took
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Entry {
+    /// Directory the server was serving
+    cwd: PathBuf,
+    /// Whether it took a batch of events
+    batch: bool,
+}
+
+/// Cache directory of the modes (`publish/` in the cache directory)
+pub fn cache_dir() -> PathBuf {
+    paths::cache_dir().join("publish")
+}
+
+/// Whether the server on `port` serving `cwd` took a batch, if a run found out
+pub fn load(dir: &Path, port: u16, cwd: &Path) -> Option<bool> {
+    let text = Plain.read(&entry_path(dir, port)).ok()??;
+    let entry: Entry = serde_json::from_str(&text).ok()?;
+    (entry.cwd == cwd).then_some(entry.batch)
+}
+
+/// Remember whether the server on `port` serving `cwd` took a batch
+pub fn save(dir: &Path, port: u16, cwd: &Path, batch: bool) -> Result<()> {
+    let entry = Entry {
+        cwd: cwd.to_path_buf(),
+        batch,
+    };
+    let text = serde_json::to_string(&entry).context("Failed to serialize the publish mode")?;
+    Plain.write(&entry_path(dir, port), &text)
+}
+
+/// Entry file for the server on `port`
+fn entry_path(dir: &Path, port: u16) -> PathBuf {
+    dir.join(format!("{}.json", port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_per_server() {
+        let dir = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        let project = Path::new("/home/u/project");
+        assert_eq!(load(&dir, 4096, project), None);
+
+        save(&dir, 4096, project, false).unwrap();
+        assert_eq!(load(&dir, 4096, project), Some(false));
+        assert_eq!(load(&dir, 4097, project), None);
+        // The server was restarted in another directory on the same port
+        assert_eq!(load(&dir, 4096, Path::new("/home/u/other")), None);
+
+        save(&dir, 4096, project, true).unwrap();
+        assert_eq!(load(&dir, 4096, project), Some(true));
+        // A damaged file is a miss
+        std::fs::write(entry_path(&dir, 4096), "{").unwrap();
+        assert_eq!(load(&dir, 4096, project), None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
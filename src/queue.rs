@@ -0,0 +1,111 @@
+//! On-disk queue of prompts waiting for a busy session
+//!
+//! `--queue` stores the expanded prompt before waiting, so it survives the
+//! process exiting; `flush` sends whatever is left. Entries are JSON files in
+//! `$XDG_RUNTIME_DIR/opencode-helix/queue/` (written through the
+//! [`StateStore`]), sent in the order they were queued. The directory is
+//! private ([`paths::ensure_private_dir`]): entries hold source code, and
+//! `flush` sends whatever is in it.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::paths;
+use crate::state::StateStore;
+
+/// A prompt waiting to be sent
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueuedPrompt {
+    /// Target session id (None for the focused TUI session)
+    pub session: Option<String>,
+    /// Expanded prompt text
+    pub text: String,
+    /// Submit after appending
+    pub submit: bool,
+    /// When the prompt was queued (milliseconds since epoch)
+    pub created: i64,
+}
+
+/// Queue directory (`queue/` in the runtime directory)
+pub fn queue_dir() -> PathBuf {
+    paths::runtime_dir().join("queue")
+}
+
+/// Add a prompt to the queue, returning the entry's path
+pub fn push(store: &dyn StateStore, dir: &Path, prompt: &QueuedPrompt) -> Result<PathBuf> {
+    // Zero-padded timestamp first so entries sort in queue order
+    paths::ensure_private_dir(dir)?;
+    let path = dir.join(format!(
+        "{:015}-{}.json",
+        prompt.created,
+        uuid::Uuid::new_v4()
+    ));
+    store.write(&path, &serde_json::to_string(prompt)?)?;
+    Ok(path)
+}
+
+/// Queued prompts, oldest first; unreadable entries are skipped
+pub fn list(store: &dyn StateStore, dir: &Path) -> Result<Vec<(PathBuf, QueuedPrompt)>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    paths::ensure_private_dir(dir)?;
+
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read queue dir {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+
+    Ok(paths
+        .into_iter()
+        .filter_map(|path| {
+            let text = store.read(&path).ok()??;
+            let prompt = serde_json::from_str(&text).ok()?;
+            Some((path, prompt))
+        })
+        .collect())
+}
+
+/// Remove a sent entry from the queue
+pub fn remove(path: &Path) {
+    let _ = std::fs::remove_file(path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Plain;
+
+    fn prompt(text: &str, created: i64) -> QueuedPrompt {
+        QueuedPrompt {
+            session: None,
+            text: text.to_string(),
+            submit: true,
+            created,
+        }
+    }
+
+    #[test]
+    fn test_queue_order_and_remove() {
+        let dir = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        assert!(list(&Plain, &dir).unwrap().is_empty());
+
+        push(&Plain, &dir, &prompt("second", 2_000)).unwrap();
+        let first = push(&Plain, &dir, &prompt("first", 1_000)).unwrap();
+        std::fs::write(dir.join("broken.json"), "{").unwrap();
+
+        let entries = list(&Plain, &dir).unwrap();
+        let texts: Vec<&str> = entries.iter().map(|(_, p)| p.text.as_str()).collect();
+        assert_eq!(texts, ["first", "second"]);
+
+        remove(&first);
+        let remaining = list(&Plain, &dir).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].1, prompt("second", 2_000));
+    }
+}
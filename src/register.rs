@@ -0,0 +1,200 @@
+//! Helix registers passed on the command line, as `@reg:NAME` placeholders
+//!
+//! A keybinding expands a register into `--register NAME=CONTENT` (or, for
+//! long contents, writes it to a file named with `--register-file
+//! NAME=PATH`). `@reg:NAME` then expands to the content, in a code fence
+//! when it spans several lines; a name that wasn't given stays as typed.
+//!
+//! Register contents are whatever the user yanked, so they never appear in
+//! `Debug` output (and with it the debug log): only their sizes do.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// What a register placeholder starts with
+pub const PREFIX: &str = "@reg:";
+
+/// `NAME=VALUE` from `--register` (the content) or `--register-file` (a path)
+#[derive(Clone, PartialEq, Eq)]
+pub struct RegisterArg {
+    pub name: String,
+    pub value: String,
+}
+
+impl FromStr for RegisterArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once('=')
+            .ok_or_else(|| "expected NAME=VALUE".to_string())?;
+        if name.is_empty() || name.chars().any(char::is_whitespace) {
+            return Err(format!("invalid register name {:?}", name));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+impl fmt::Debug for RegisterArg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={:?}", self.name, Size(&self.value))
+    }
+}
+
+/// Stands in for a register's content in `Debug` output
+struct Size<'a>(&'a str);
+
+impl fmt::Debug for Size<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{} bytes>", self.0.len())
+    }
+}
+
+/// Register contents by name
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Registers(BTreeMap<String, String>);
+
+impl fmt::Debug for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.0.iter().map(|(name, content)| (name, Size(content))))
+            .finish()
+    }
+}
+
+impl Registers {
+    /// Set register `name`, replacing an earlier value
+    pub fn insert(&mut self, name: &str, content: String) {
+        self.0.insert(name.to_string(), content);
+    }
+
+    /// Placeholder tokens (`@reg:a`) with each register's raw content
+    #[cfg(feature = "tui")]
+    pub fn iter(&self) -> impl Iterator<Item = (String, &str)> {
+        self.0
+            .iter()
+            .map(|(name, content)| (format!("{}{}", PREFIX, name), content.as_str()))
+    }
+
+    /// Value `@reg:name` expands to: the content, fenced when multi-line
+    fn value(&self, name: &str) -> Option<String> {
+        let content = self.0.get(name)?;
+        let content = content.strip_suffix('\n').unwrap_or(content);
+        Some(if content.contains('\n') {
+            format!("```\n{}\n```", content)
+        } else {
+            content.to_string()
+        })
+    }
+
+    /// Register named at the start of `rest` (the text after [`PREFIX`])
+    ///
+    /// The longest name that isn't followed by more of a word, so `@reg:ab`
+    /// is register `ab` rather than `a`, and `@reg:a.` is register `a`.
+    pub fn named_at(&self, rest: &str) -> Option<&str> {
+        self.0
+            .keys()
+            .filter(|name| {
+                rest.strip_prefix(name.as_str()).is_some_and(|after| {
+                    !after
+                        .chars()
+                        .next()
+                        .is_some_and(|c| c.is_alphanumeric() || c == '_')
+                })
+            })
+            .max_by_key(|name| name.len())
+            .map(String::as_str)
+    }
+
+    /// Whether every `@reg:` in `text` names a known register
+    #[cfg(feature = "tui")]
+    pub fn available(&self, text: &str) -> bool {
+        text.match_indices(PREFIX)
+            .all(|(i, _)| self.named_at(&text[i + PREFIX.len()..]).is_some())
+    }
+
+    /// `text` with known registers expanded, and the token and value length
+    /// of each one substituted
+    pub fn expand(&self, text: &str) -> (String, Vec<(String, usize)>) {
+        let mut out = String::with_capacity(text.len());
+        let mut substituted = Vec::new();
+        let mut rest = text;
+        while let Some(i) = rest.find(PREFIX) {
+            out.push_str(&rest[..i]);
+            let after = &rest[i + PREFIX.len()..];
+            match self.named_at(after) {
+                Some(name) => {
+                    let value = self.value(name).unwrap_or_default();
+                    out.push_str(&value);
+                    substituted.push((format!("{}{}", PREFIX, name), value.len()));
+                    rest = &after[name.len()..];
+                }
+                None => {
+                    out.push_str(PREFIX);
+                    rest = after;
+                }
+            }
+        }
+        out.push_str(rest);
+        (out, substituted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registers(entries: &[(&str, &str)]) -> Registers {
+        let mut registers = Registers::default();
+        for (name, content) in entries {
+            registers.insert(name, content.to_string());
+        }
+        registers
+    }
+
+    #[test]
+    fn test_parse_arg() {
+        let arg: RegisterArg = "a=x = y".parse().unwrap();
+        assert_eq!((arg.name.as_str(), arg.value.as_str()), ("a", "x = y"));
+        let arg: RegisterArg = "+=".parse().unwrap();
+        assert_eq!((arg.name.as_str(), arg.value.as_str()), ("+", ""));
+        assert!("a".parse::<RegisterArg>().is_err());
+        assert!("=x".parse::<RegisterArg>().is_err());
+        assert!("a b=x".parse::<RegisterArg>().is_err());
+    }
+
+    #[test]
+    fn test_expand() {
+        let regs = registers(&[("a", "one line\n"), ("ab", "x\ny\n"), ("+", "clip")]);
+        let (text, substituted) = regs.expand("A @reg:a. AB @reg:ab + @reg:+ @reg:c @reg:abc");
+        assert_eq!(text, "A one line. AB ```\nx\ny\n``` + clip @reg:c @reg:abc");
+        assert_eq!(
+            substituted,
+            [
+                ("@reg:a".to_string(), 8),
+                ("@reg:ab".to_string(), 11),
+                ("@reg:+".to_string(), 4)
+            ]
+        );
+        assert_eq!(regs.expand("none").0, "none");
+
+        #[cfg(feature = "tui")]
+        {
+            assert!(regs.available("@reg:a and @reg:+"));
+            assert!(!regs.available("@reg:a and @reg:c"));
+            assert!(Registers::default().available("no registers"));
+        }
+    }
+
+    #[test]
+    fn test_debug_hides_contents() {
+        let regs = registers(&[("a", "secret")]);
+        assert_eq!(format!("{:?}", regs), r#"{"a": <6 bytes>}"#);
+        let arg: RegisterArg = "a=secret".parse().unwrap();
+        assert_eq!(format!("{:?}", arg), "a=<6 bytes>");
+    }
+}
@@ -0,0 +1,138 @@
+//! The reply printed by `prompt --print`
+//!
+//! `--print` waits for the session to go idle and prints the text of the
+//! last assistant message to stdout, so a script can use the answer:
+//!
+//! ```sh
+//! opencode-helix prompt --print --first-code-block \
+//!     "write a commit message for @diff in a code block" | git commit -F -
+//! ```
+
+/// What of the reply `--print` writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplyFormat {
+    /// The text, without leading and trailing blank space, ending in a newline
+    Text,
+    /// The text exactly as the server sent it (`--raw`)
+    Raw,
+    /// The contents of the first fenced code block, ending in a newline
+    /// (`--first-code-block`)
+    FirstCodeBlock,
+}
+
+impl ReplyFormat {
+    /// Output for `reply`, or None if it has nothing to print in this format
+    pub fn render(self, reply: &str) -> Option<String> {
+        match self {
+            Self::Raw => Some(reply.to_string()),
+            Self::Text => {
+                let text = reply.trim();
+                (!text.is_empty()).then(|| format!("{}\n", text))
+            }
+            Self::FirstCodeBlock => first_code_block(reply).map(|block| {
+                let mut block = block.to_string();
+                if !block.ends_with('\n') {
+                    block.push('\n');
+                }
+                block
+            }),
+        }
+    }
+}
+
+/// Contents of the first fenced code block in markdown `text`
+///
+/// A fence is three or more backticks or tildes, indented at most three
+/// spaces, and closes at a line of at least as many of the same character.
+/// An unclosed block runs to the end of the text.
+pub fn first_code_block(text: &str) -> Option<&str> {
+    let mut lines = line_spans(text).peekable();
+    let (fence_char, fence_len) = lines.by_ref().find_map(|(_, line)| {
+        let (c, len) = fence(line)?;
+        // A backtick fence's info string can't hold backticks
+        let info = &line.trim_start()[len..];
+        (c == '~' || !info.contains('`')).then_some((c, len))
+    })?;
+    let body_start = lines.peek().map_or(text.len(), |&(start, _)| start);
+    for (start, line) in lines {
+        if let Some((c, len)) = fence(line) {
+            let closing = line.trim_start()[len..].trim().is_empty();
+            if c == fence_char && len >= fence_len && closing {
+                return Some(&text[body_start..start]);
+            }
+        }
+    }
+    Some(&text[body_start..])
+}
+
+/// Each line of `text` with its byte offset, without the line break
+fn line_spans(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split('\n').scan(0, |offset, line| {
+        let start = *offset;
+        *offset += line.len() + 1;
+        Some((start, line.strip_suffix('\r').unwrap_or(line)))
+    })
+}
+
+/// The fence character and length a line opens or closes with, if any
+fn fence(line: &str) -> Option<(char, usize)> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() > 3 {
+        return None;
+    }
+    let c = trimmed.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let len = trimmed.chars().take_while(|&d| d == c).count();
+    (len >= 3).then_some((c, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_code_block() {
+        let reply =
+            "Here is the message:\n\n```text\nfix: parser\n\nLonger body\n```\n\n```\nsecond\n```";
+        assert_eq!(
+            first_code_block(reply),
+            Some("fix: parser\n\nLonger body\n")
+        );
+
+        // Tildes, a longer closing fence, and a shorter fence inside
+        let reply = "~~~\na\n~~~~\n";
+        assert_eq!(first_code_block(reply), Some("a\n"));
+        let reply = "````md\n```\ninner\n```\n````";
+        assert_eq!(first_code_block(reply), Some("```\ninner\n```\n"));
+
+        // Unclosed: to the end
+        assert_eq!(first_code_block("```\nfix: x"), Some("fix: x"));
+        assert_eq!(first_code_block("```"), Some(""));
+        // Not fences: inline backticks, two backticks, indented four spaces
+        assert_eq!(first_code_block("use ``` to fence"), None);
+        assert_eq!(first_code_block("``\nx\n``"), None);
+        assert_eq!(first_code_block("    ```\n    x\n    ```"), None);
+        assert_eq!(first_code_block("No code here."), None);
+    }
+
+    #[test]
+    fn test_render() {
+        let reply = "\n  Sure.\n\n```\nfix: parser\n```  \n";
+        assert_eq!(
+            ReplyFormat::Text.render(reply).as_deref(),
+            Some("Sure.\n\n```\nfix: parser\n```\n")
+        );
+        assert_eq!(ReplyFormat::Raw.render(reply).as_deref(), Some(reply));
+        assert_eq!(
+            ReplyFormat::FirstCodeBlock.render(reply).as_deref(),
+            Some("fix: parser\n")
+        );
+        assert_eq!(
+            ReplyFormat::FirstCodeBlock
+                .render("```\nno newline")
+                .as_deref(),
+            Some("no newline\n")
+        );
+        assert_eq!(ReplyFormat::FirstCodeBlock.render("Sure."), None);
+        assert_eq!(ReplyFormat::Text.render(" \n"), None);
+    }
+}
@@ -0,0 +1,129 @@
+//! Machine-readable record of sent prompts (`--output json`)
+//!
+//! Every prompt sent is written to stdout as one JSON object per line, so a
+//! wrapper can log or post-process what left the editor. `version` changes
+//! when a field is removed or changes meaning; new fields may appear without
+//! a version change.
+//!
+//! A failure is written to stderr as one JSON object instead of the usual
+//! message, with a machine-readable `reason` and the exit code.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::exit;
+use crate::server::discovery::DiscoveryError;
+
+/// Current schema version
+pub const VERSION: u32 = 1;
+
+/// A prompt that was sent to the server
+#[derive(Debug, Clone, Serialize)]
+pub struct SentPrompt<'a> {
+    /// Schema version ([`VERSION`])
+    pub version: u32,
+    /// When the prompt was sent (RFC 3339, UTC)
+    pub timestamp: String,
+    /// Port of the server it was sent to
+    pub port: u16,
+    /// Target session with --session, otherwise null (the focused TUI session)
+    pub session: Option<&'a str>,
+    /// The prompt's name, or "ask", "select" or "text" for typed text
+    pub step: &'a str,
+    /// Text before placeholder expansion
+    pub input: &'a str,
+    /// Text as sent
+    pub expanded: &'a str,
+    /// Substituted placeholders and the length in bytes of their values
+    pub placeholders: &'a BTreeMap<String, usize>,
+}
+
+impl SentPrompt<'_> {
+    /// Serialize as a single JSON line
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("SentPrompt always serializes")
+    }
+}
+
+/// An error as a single JSON line
+///
+/// `reason` names the cause (e.g. `cwd_mismatch`), and discovery errors
+/// carry their details (`pids`, `attempts` or `candidates`).
+pub fn error_json(err: &anyhow::Error) -> String {
+    let mut record = err
+        .downcast_ref::<DiscoveryError>()
+        .and_then(|discovery| serde_json::to_value(discovery).ok())
+        .unwrap_or_else(|| json!({ "reason": exit::error_reason(err) }));
+    record["version"] = VERSION.into();
+    record["exit_code"] = exit::error_exit_code(err).into();
+    record["message"] = format!("{:#}", err).into();
+    record.to_string()
+}
+
+/// Current time for [`SentPrompt::timestamp`]
+pub fn timestamp() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sent_prompt_json() {
+        let placeholders = BTreeMap::from([("@this".to_string(), 16)]);
+        let sent = SentPrompt {
+            version: VERSION,
+            timestamp: "2026-01-02T03:04:05.000Z".to_string(),
+            port: 39127,
+            session: None,
+            step: "ask",
+            input: "Explain @this",
+            expanded: "Explain @src/main.rs L42",
+            placeholders: &placeholders,
+        };
+        let json: serde_json::Value = serde_json::from_str(&sent.to_json()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "version": 1,
+                "timestamp": "2026-01-02T03:04:05.000Z",
+                "port": 39127,
+                "session": null,
+                "step": "ask",
+                "input": "Explain @this",
+                "expanded": "Explain @src/main.rs L42",
+                "placeholders": { "@this": 16 }
+            })
+        );
+        assert!(!sent.to_json().contains('\n'));
+        assert!(timestamp().ends_with('Z'));
+    }
+
+    #[test]
+    fn test_error_json() {
+        use anyhow::Context;
+
+        use crate::exit::ErrorKind;
+
+        let err = Err::<(), _>(DiscoveryError::NoPortInCmdline { pids: vec![42] })
+            .context(ErrorKind::NoServer)
+            .unwrap_err();
+        let json: serde_json::Value = serde_json::from_str(&error_json(&err)).unwrap();
+        assert_eq!(json["version"], 1);
+        assert_eq!(json["reason"], "no_port_in_cmdline");
+        assert_eq!(json["exit_code"], exit::EXIT_NO_PORT);
+        assert_eq!(json["pids"], serde_json::json!([42]));
+        let message = json["message"].as_str().unwrap();
+        assert!(message.starts_with("No opencode server found: opencode is running (pid 42)"));
+
+        let err = anyhow::anyhow!("bad name").context(ErrorKind::Usage);
+        let json: serde_json::Value = serde_json::from_str(&error_json(&err)).unwrap();
+        assert_eq!(json["reason"], "usage");
+        assert_eq!(json["exit_code"], exit::EXIT_USAGE);
+        assert_eq!(json["message"], "Invalid usage: bad name");
+        assert!(!error_json(&err).contains('\n'));
+    }
+}
@@ -0,0 +1,105 @@
+//! Credentials for opencode servers that require authentication
+//!
+//! The token comes from `--auth-token`, `OPENCODE_HELIX_TOKEN` or the
+//! project config. Its `Debug` output is redacted, so the CLI and config
+//! dumps in the --debug log never contain it.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Context, Result};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use serde::Deserialize;
+
+/// Environment variable `--auth-token` falls back to
+pub const TOKEN_ENV: &str = "OPENCODE_HELIX_TOKEN";
+
+/// A secret that stays out of debug output
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Token(String);
+
+impl Token {
+    /// The secret itself, for building the request header
+    fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<redacted>")
+    }
+}
+
+impl FromStr for Token {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.to_string()))
+    }
+}
+
+/// Header sent with every request to the server
+#[derive(Debug, Clone)]
+pub struct Auth {
+    name: HeaderName,
+    /// Marked sensitive, so it is redacted from `Debug` too
+    value: HeaderValue,
+}
+
+impl Auth {
+    /// `Authorization: Bearer <token>`, or `<header>: <token>` for a custom header name
+    pub fn new(token: &Token, header: Option<&str>) -> Result<Self> {
+        let (name, value) = match header {
+            None => (AUTHORIZATION, format!("Bearer {}", token.expose())),
+            Some(header) => (
+                HeaderName::from_bytes(header.as_bytes())
+                    .with_context(|| format!("Invalid auth header name: {:?}", header))?,
+                token.expose().to_string(),
+            ),
+        };
+        // The error would echo the value, so it gets no context of its own
+        let mut value = HeaderValue::from_str(&value)
+            .map_err(|_| anyhow::anyhow!("The auth token contains characters a header can't"))?;
+        value.set_sensitive(true);
+        Ok(Self { name, value })
+    }
+
+    /// Default headers for the HTTP client
+    pub fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(self.name.clone(), self.value.clone());
+        headers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auth_headers() {
+        let token: Token = "s3cret".parse().unwrap();
+        let headers = Auth::new(&token, None).unwrap().headers();
+        assert_eq!(headers[AUTHORIZATION], "Bearer s3cret");
+
+        let headers = Auth::new(&token, Some("X-Opencode-Token"))
+            .unwrap()
+            .headers();
+        assert_eq!(headers["x-opencode-token"], "s3cret");
+
+        assert!(Auth::new(&token, Some("bad header")).is_err());
+        let err = Auth::new(&"line\nbreak".parse().unwrap(), None).unwrap_err();
+        assert!(!format!("{:#}", err).contains("line"));
+    }
+
+    #[test]
+    fn test_token_redacted() {
+        let token: Token = "s3cret".parse().unwrap();
+        assert_eq!(format!("{:?}", token), "<redacted>");
+        let auth = Auth::new(&token, None).unwrap();
+        assert!(!format!("{:?}", auth).contains("s3cret"));
+        assert!(!format!("{:?}", auth.headers()).contains("s3cret"));
+    }
+}
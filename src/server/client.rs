@@ -2,14 +2,245 @@
 //!
 //! Communicates with the opencode server via HTTP/JSON.
 
-use anyhow::{Context, Result};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context, Result};
+use eventsource_stream::Eventsource;
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+
+use super::auth::{Auth, TOKEN_ENV};
+use super::events::EventStream;
 
 /// HTTP client for opencode server
 #[derive(Debug, Clone)]
 pub struct Client {
     port: u16,
     http: reqwest::Client,
+    /// Header sent with every request, for servers that require a token
+    auth: Option<Auth>,
+    /// Target session id (sends go to the session API instead of the TUI)
+    session: Option<String>,
+    /// Agent the send path hands prompts to (by an `@name` mention)
+    agent: Option<String>,
+    /// Model session messages ask for
+    model: Option<Model>,
+    /// Server capabilities, probed on first use
+    capabilities: Arc<OnceCell<Capabilities>>,
+    /// Whether `/tui/publish` takes batches, once a send found out (or a
+    /// previous run, see [`Client::with_batch_publish`])
+    publish_mode: Arc<Mutex<Option<PublishMode>>>,
+}
+
+/// How a prompt reached the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PublishMode {
+    /// All TUI events in one `/tui/publish` request
+    Batch,
+    /// One `/tui/publish` request per event (servers without batch support)
+    Sequential,
+    /// A message to the target session (`--session`)
+    Session,
+    /// A plain-text message to the target session, after it rejected the
+    /// file parts of the message
+    SessionText,
+    /// One `/tui/publish` request per piece of a prompt too long for one
+    Chunked(usize),
+}
+
+impl fmt::Display for PublishMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Batch => write!(f, "batched publish"),
+            Self::Sequential => write!(f, "sequential publish"),
+            Self::Session => write!(f, "session message"),
+            Self::SessionText => write!(f, "session message (as text)"),
+            Self::Chunked(chunks) => write!(f, "publish in {} chunks", chunks),
+        }
+    }
+}
+
+/// A chunked send that broke off: the first `sent` chunks are in the prompt box
+#[derive(Debug)]
+pub struct PartialSend {
+    /// Chunks the server accepted
+    pub sent: usize,
+    /// Chunks in the whole prompt
+    pub total: usize,
+    /// Length of the accepted chunks, where a retry resumes
+    pub sent_bytes: usize,
+    /// Why the next chunk failed
+    pub error: anyhow::Error,
+}
+
+impl fmt::Display for PartialSend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Sent {} of {} chunks of the prompt; chunk {} failed",
+            self.sent,
+            self.total,
+            self.sent + 1
+        )
+    }
+}
+
+impl std::error::Error for PartialSend {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
+
+/// Optional server features, detected by probing their endpoints
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Capabilities {
+    /// `/session` (session listing and direct session prompts)
+    pub has_sessions: bool,
+    /// `/event` (server event stream)
+    pub has_events: bool,
+    /// `/config/providers` (model listing)
+    pub has_models: bool,
+    /// `/tui/prompt` (text in the TUI's prompt box)
+    pub has_prompt_text: bool,
+    /// Version reported by `/global/health`, if any
+    pub server_version: Option<String>,
+}
+
+/// A server feature that older opencode versions lack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    Sessions,
+    Events,
+}
+
+impl Feature {
+    /// First opencode version known to provide the feature
+    pub fn min_version(self) -> &'static str {
+        match self {
+            Self::Sessions | Self::Events => "0.3",
+        }
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sessions => write!(f, "Session access"),
+            Self::Events => write!(f, "Waiting for the session"),
+        }
+    }
+}
+
+impl Capabilities {
+    /// Check if the server supports a feature
+    pub fn supports(&self, feature: Feature) -> bool {
+        match feature {
+            Feature::Sessions => self.has_sessions,
+            Feature::Events => self.has_events,
+        }
+    }
+
+    /// Fail with a consistent "requires opencode >= x.y" error if a feature is missing
+    pub fn require(&self, feature: Feature) -> Result<()> {
+        if self.supports(feature) {
+            return Ok(());
+        }
+        bail!(
+            "{} requires opencode >= {} (server reports {})",
+            feature,
+            feature.min_version(),
+            self.server_version
+                .as_deref()
+                .unwrap_or("an unknown version")
+        )
+    }
+}
+
+/// opencode versions this release is known to work with: from the first up
+/// to, but not including, the second
+pub const COMPATIBLE_VERSIONS: (&str, &str) = ("0.3.0", "2.0.0");
+
+/// Where a server's version falls against [`COMPATIBLE_VERSIONS`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Compat {
+    Compatible,
+    TooOld,
+    TooNew,
+    /// No version reported, or not a dotted version
+    Unknown,
+}
+
+impl Compat {
+    /// Compare `version` against [`COMPATIBLE_VERSIONS`]
+    pub fn of(version: Option<&str>) -> Self {
+        let (oldest, first_unknown) = COMPATIBLE_VERSIONS;
+        let Some(version) = version.and_then(parse_version) else {
+            return Self::Unknown;
+        };
+        let known = |bound: &str| parse_version(bound).expect("COMPATIBLE_VERSIONS parse");
+        if compare_versions(&version, &known(oldest)).is_lt() {
+            Self::TooOld
+        } else if compare_versions(&version, &known(first_unknown)).is_ge() {
+            Self::TooNew
+        } else {
+            Self::Compatible
+        }
+    }
+}
+
+/// A dotted version as numbers, ignoring a leading `v` and any pre-release
+/// or build suffix: `v1.2.3-beta+4` is `[1, 2, 3]`
+fn parse_version(version: &str) -> Option<Vec<u64>> {
+    let version = version.trim();
+    let version = version.strip_prefix('v').unwrap_or(version);
+    let core = version.split(['-', '+']).next()?;
+    core.split('.').map(|n| n.parse().ok()).collect()
+}
+
+/// Compare dotted versions, missing components counting as 0 (`1.2` is `1.2.0`)
+fn compare_versions(a: &[u64], b: &[u64]) -> std::cmp::Ordering {
+    let len = a.len().max(b.len());
+    let at = |v: &[u64], i: usize| v.get(i).copied().unwrap_or(0);
+    (0..len)
+        .map(|i| at(a, i).cmp(&at(b, i)))
+        .find(|order| order.is_ne())
+        .unwrap_or(std::cmp::Ordering::Equal)
+}
+
+impl Capabilities {
+    /// How the server's version compares with the versions known to work
+    pub fn compat(&self) -> Compat {
+        Compat::of(self.server_version.as_deref())
+    }
+
+    /// A warning if the server's version is outside [`COMPATIBLE_VERSIONS`]
+    pub fn compat_warning(&self) -> Option<String> {
+        let (oldest, first_unknown) = COMPATIBLE_VERSIONS;
+        let version = self.server_version.as_deref()?;
+        match self.compat() {
+            Compat::TooOld => Some(format!(
+                "opencode {} is older than {}, the oldest version this opencode-helix supports",
+                version, oldest
+            )),
+            Compat::TooNew => Some(format!(
+                "opencode {} is newer than this opencode-helix is known to work with (below {})",
+                version, first_unknown
+            )),
+            Compat::Compatible | Compat::Unknown => None,
+        }
+    }
+}
+
+/// Response from /global/health
+#[derive(Debug, Deserialize)]
+struct HealthResponse {
+    version: Option<String>,
 }
 
 /// Response from /path endpoint
@@ -19,21 +250,293 @@ pub struct PathResponse {
     pub worktree: Option<String>,
 }
 
+/// Deserialize a string that may be missing or null as an empty string
+#[cfg(feature = "tui")]
+fn string_or_empty<'de, D>(deserializer: D) -> std::result::Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(Option::<String>::deserialize(deserializer)?.unwrap_or_default())
+}
+
 /// Agent information
 #[derive(Debug, Clone, Deserialize)]
 pub struct Agent {
     pub name: String,
+    #[cfg(feature = "tui")]
+    #[serde(default, deserialize_with = "string_or_empty")]
     pub description: String,
+    #[cfg(feature = "tui")]
+    #[serde(default, deserialize_with = "string_or_empty")]
     pub mode: String, // "primary" or "subagent"
 }
 
+/// A model as opencode names it, `provider/model`
+/// (e.g. `anthropic/claude-sonnet-4`)
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Model {
+    pub provider: String,
+    pub id: String,
+}
+
+impl FromStr for Model {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.split_once('/') {
+            Some((provider, id)) if !provider.is_empty() && !id.is_empty() => Ok(Self {
+                provider: provider.to_string(),
+                id: id.to_string(),
+            }),
+            _ => Err(format!(
+                "\"{}\" is not a model: expected PROVIDER/MODEL, e.g. anthropic/claude-sonnet-4",
+                s
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for Model {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.provider, self.id)
+    }
+}
+
 /// Custom command from opencode
+#[cfg(feature = "tui")]
 #[derive(Debug, Clone, Deserialize)]
 pub struct Command {
     pub name: String,
+    #[serde(default, deserialize_with = "string_or_empty")]
     pub description: String,
+    #[serde(default, deserialize_with = "string_or_empty")]
     pub template: String,
-    pub agent: Option<String>,
+}
+
+/// A list response parsed element by element
+#[derive(Debug, Clone)]
+pub struct Lenient<T> {
+    /// Elements that parsed
+    pub items: Vec<T>,
+    /// Errors for elements that were skipped
+    pub skipped: Vec<String>,
+}
+
+impl<T> Default for Lenient<T> {
+    fn default() -> Self {
+        Self {
+            items: Vec::new(),
+            skipped: Vec::new(),
+        }
+    }
+}
+
+impl<T: DeserializeOwned> Lenient<T> {
+    /// Parse each array element on its own, skipping ones that don't match `T`
+    /// so one unexpected entry doesn't lose the whole list
+    pub fn from_values(values: Vec<serde_json::Value>) -> Self {
+        let mut parsed = Self::default();
+        for value in values {
+            match serde_json::from_value(value.clone()) {
+                Ok(item) => parsed.items.push(item),
+                Err(e) => parsed.skipped.push(format!("{}: {}", e, value)),
+            }
+        }
+        parsed
+    }
+}
+
+/// `ETag` and `Last-Modified` of a response, sent back to ask whether it changed
+#[cfg(feature = "tui")]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Validators {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+#[cfg(feature = "tui")]
+impl Validators {
+    /// Validators of `response`, if it has any
+    fn of(response: &reqwest::Response) -> Self {
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        Self {
+            etag: header(reqwest::header::ETAG),
+            last_modified: header(reqwest::header::LAST_MODIFIED),
+        }
+    }
+}
+
+/// Answer to a conditional GET of a list ([`Client::get_list`])
+#[cfg(feature = "tui")]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Listed {
+    /// The list the validators came with is still current (304)
+    NotModified,
+    /// The list's elements, and the validators to send next time
+    Fresh(Vec<serde_json::Value>, Validators),
+}
+
+/// Session timestamps (milliseconds since epoch)
+#[cfg(feature = "tui")]
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SessionTime {
+    #[serde(default)]
+    pub created: i64,
+    #[serde(default)]
+    pub updated: i64,
+}
+
+/// Session information from /session
+#[derive(Debug, Clone, Deserialize)]
+pub struct Session {
+    pub id: String,
+    #[serde(default)]
+    pub title: String,
+    #[cfg(feature = "tui")]
+    #[serde(default)]
+    pub time: SessionTime,
+    /// Number of messages, if the server reports it
+    #[cfg(feature = "tui")]
+    #[serde(default, alias = "messageCount")]
+    pub message_count: Option<u64>,
+}
+
+impl Session {
+    /// Timestamp of the last activity (falls back to creation time)
+    #[cfg(feature = "tui")]
+    pub fn last_activity(&self) -> i64 {
+        self.time.updated.max(self.time.created)
+    }
+
+    /// Whether the session still has no title of its own: none, or the
+    /// "New session - <time>" opencode gives a new one
+    pub fn is_untitled(&self) -> bool {
+        self.title.trim().is_empty() || self.title.starts_with("New session - ")
+    }
+}
+
+/// Response from /tui/prompt
+#[derive(Debug, Deserialize)]
+struct PromptTextResponse {
+    #[serde(default)]
+    text: String,
+}
+
+/// Entry of the /session/status map
+#[derive(Debug, Deserialize)]
+struct SessionStatus {
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+/// A part of a session message, in opencode's message schema
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum MessagePart {
+    /// Prose
+    Text { text: String },
+    /// A file the server reads itself; `url` is a `file://` URL with
+    /// `start`/`end` query parameters for a line range
+    File {
+        mime: String,
+        filename: String,
+        url: String,
+    },
+}
+
+impl MessagePart {
+    /// A text file attachment, optionally limited to lines `start..=end`
+    ///
+    /// Returns None if `path` isn't absolute.
+    pub fn file(path: &Path, filename: &str, lines: Option<(u32, u32)>) -> Option<Self> {
+        let mut url = reqwest::Url::from_file_path(path).ok()?;
+        if let Some((start, end)) = lines {
+            url.query_pairs_mut()
+                .append_pair("start", &start.to_string())
+                .append_pair("end", &end.to_string());
+        }
+        Some(Self::File {
+            mime: "text/plain".to_string(),
+            filename: filename.to_string(),
+            url: url.to_string(),
+        })
+    }
+
+    /// Check if the part is a file attachment
+    pub fn is_file(&self) -> bool {
+        matches!(self, Self::File { .. })
+    }
+}
+
+/// Builds the parts of a session message, merging adjacent text
+#[derive(Debug, Default)]
+pub struct MessageBuilder {
+    parts: Vec<MessagePart>,
+}
+
+impl MessageBuilder {
+    /// Append text, skipping empty text
+    pub fn text(mut self, text: &str) -> Self {
+        if text.is_empty() {
+            return self;
+        }
+        match self.parts.last_mut() {
+            Some(MessagePart::Text { text: last }) => last.push_str(text),
+            _ => self.parts.push(MessagePart::Text {
+                text: text.to_string(),
+            }),
+        }
+        self
+    }
+
+    /// Append a part
+    pub fn part(mut self, part: MessagePart) -> Self {
+        match part {
+            MessagePart::Text { text } => self.text(&text),
+            part => {
+                self.parts.push(part);
+                self
+            }
+        }
+    }
+
+    pub fn build(self) -> Vec<MessagePart> {
+        self.parts
+    }
+}
+
+/// Session prompt request
+#[derive(Debug, Serialize)]
+struct SessionPromptRequest<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<ModelRef<'a>>,
+    parts: &'a [MessagePart],
+}
+
+/// A model in a session prompt request
+#[derive(Debug, Serialize)]
+struct ModelRef<'a> {
+    #[serde(rename = "providerID")]
+    provider_id: &'a str,
+    #[serde(rename = "modelID")]
+    model_id: &'a str,
 }
 
 /// TUI publish request
@@ -47,12 +550,105 @@ struct TuiPublishRequest {
 impl Client {
     /// Create a new client for the given port
     pub fn new(port: u16) -> Self {
-        let http = reqwest::Client::builder()
+        Self {
+            port,
+            http: Self::http_client(None),
+            auth: None,
+            session: None,
+            agent: None,
+            model: None,
+            capabilities: Arc::new(OnceCell::new()),
+            publish_mode: Arc::default(),
+        }
+    }
+
+    /// This client for its server restarted on `port`: the same settings,
+    /// with the capabilities probed again
+    pub fn moved_to(&self, port: u16) -> Self {
+        Self {
+            port,
+            capabilities: Arc::new(OnceCell::new()),
+            publish_mode: Arc::default(),
+            ..self.clone()
+        }
+    }
+
+    /// Send to `/tui/publish` in batches, or not, as a previous run found
+    /// the server takes them; unknown, the first send tries a batch
+    pub fn with_batch_publish(mut self, batch: Option<bool>) -> Self {
+        let mode = batch.map(|batch| {
+            if batch {
+                PublishMode::Batch
+            } else {
+                PublishMode::Sequential
+            }
+        });
+        self.publish_mode = Arc::new(Mutex::new(mode));
+        self
+    }
+
+    /// Whether the server took a batch, if a send found out
+    pub fn batch_publish(&self) -> Option<bool> {
+        let mode = *self.publish_mode.lock().unwrap();
+        mode.map(|mode| mode == PublishMode::Batch)
+    }
+
+    /// Send `auth` with every request
+    pub fn with_auth(mut self, auth: Option<Auth>) -> Self {
+        self.http = Self::http_client(auth.as_ref());
+        self.auth = auth;
+        self
+    }
+
+    /// HTTP client with the short request timeout and the auth header, if any
+    fn http_client(auth: Option<&Auth>) -> reqwest::Client {
+        reqwest::Client::builder()
             .timeout(std::time::Duration::from_secs(5))
+            .default_headers(auth.map(Auth::headers).unwrap_or_default())
             .build()
-            .expect("Failed to create HTTP client");
+            .expect("Failed to create HTTP client")
+    }
 
-        Self { port, http }
+    /// Fail with an authentication error on 401 and 403
+    ///
+    /// Checked before reading the body, which would otherwise fail as a
+    /// confusing JSON parse error.
+    fn authorized(&self, response: reqwest::Response) -> Result<reqwest::Response> {
+        let status = response.status();
+        if status != reqwest::StatusCode::UNAUTHORIZED && status != reqwest::StatusCode::FORBIDDEN {
+            return Ok(response);
+        }
+        match self.auth {
+            Some(_) => bail!(
+                "Authentication failed ({}) - check {} or --auth-token",
+                status,
+                TOKEN_ENV
+            ),
+            None => bail!(
+                "Server requires authentication ({}) - set {} or --auth-token",
+                status,
+                TOKEN_ENV
+            ),
+        }
+    }
+
+    /// Target a specific session instead of the focused TUI session
+    pub fn with_session(mut self, session: Option<String>) -> Self {
+        self.session = session;
+        self
+    }
+
+    /// Hand prompts to `agent` (see [`crate::agent`])
+    pub fn with_agent(mut self, agent: Option<String>) -> Self {
+        self.agent = agent;
+        self
+    }
+
+    /// Ask for `model` in session messages; the TUI prompt box has no way
+    /// to choose one
+    pub fn with_model(mut self, model: Option<Model>) -> Self {
+        self.model = model;
+        self
     }
 
     /// Base URL for the server
@@ -60,6 +656,60 @@ impl Client {
         format!("http://localhost:{}", self.port)
     }
 
+    /// Server capabilities, probed once and cached
+    pub async fn capabilities(&self) -> Result<&Capabilities> {
+        self.capabilities
+            .get_or_try_init(|| self.probe_capabilities())
+            .await
+    }
+
+    /// Fail with a version error if the server lacks `feature`
+    pub async fn require(&self, feature: Feature) -> Result<()> {
+        self.capabilities().await?.require(feature)
+    }
+
+    /// Probe optional endpoints; a 404 means the endpoint is missing
+    async fn probe_capabilities(&self) -> Result<Capabilities> {
+        let (sessions, events, models, prompt_text, health) = tokio::join!(
+            self.endpoint_exists("/session"),
+            self.endpoint_exists("/event"),
+            self.endpoint_exists("/config/providers"),
+            self.endpoint_exists("/tui/prompt"),
+            self.http
+                .get(format!("{}/global/health", self.base_url()))
+                .send(),
+        );
+
+        let server_version = match health {
+            Ok(response) if response.status().is_success() => response
+                .json::<HealthResponse>()
+                .await
+                .ok()
+                .and_then(|h| h.version),
+            _ => None,
+        };
+
+        Ok(Capabilities {
+            has_sessions: sessions?,
+            has_events: events?,
+            has_models: models?,
+            has_prompt_text: prompt_text?,
+            server_version,
+        })
+    }
+
+    /// GET an endpoint and check it isn't a 404 (the body is not read)
+    async fn endpoint_exists(&self, path: &str) -> Result<bool> {
+        let response = self
+            .http
+            .get(format!("{}{}", self.base_url(), path))
+            .send()
+            .await
+            .with_context(|| format!("Failed to probe {}", path))?;
+        let response = self.authorized(response)?;
+        Ok(response.status() != reqwest::StatusCode::NOT_FOUND)
+    }
+
     /// GET /path - Get server working directory
     pub async fn get_path(&self) -> Result<PathResponse> {
         let url = format!("{}/path", self.base_url());
@@ -70,14 +720,14 @@ impl Client {
             .await
             .context("Failed to connect to opencode server")?;
 
-        response
+        self.authorized(response)?
             .json()
             .await
             .context("Failed to parse path response")
     }
 
-    /// GET /agent - List available agents
-    pub async fn get_agents(&self) -> Result<Vec<Agent>> {
+    /// GET /agent - List available agents, skipping entries that fail to parse
+    pub async fn get_agents(&self) -> Result<Lenient<Agent>> {
         let url = format!("{}/agent", self.base_url());
         let response = self
             .http
@@ -86,64 +736,286 @@ impl Client {
             .await
             .context("Failed to fetch agents")?;
 
-        response
+        let values = self
+            .authorized(response)?
             .json()
             .await
-            .context("Failed to parse agents response")
+            .context("Failed to parse agents response")?;
+        Ok(Lenient::from_values(values))
     }
 
-    /// GET /command - List custom commands
-    pub async fn get_commands(&self) -> Result<Vec<Command>> {
-        let url = format!("{}/command", self.base_url());
+    /// GET /config/providers - The models of the providers configured on
+    /// the server, in the order it lists them
+    #[cfg(feature = "tui")]
+    pub async fn get_models(&self) -> Result<Vec<Model>> {
+        let url = format!("{}/config/providers", self.base_url());
         let response = self
             .http
             .get(&url)
             .send()
             .await
-            .context("Failed to fetch commands")?;
+            .context("Failed to fetch models")?;
 
-        response
+        let value: serde_json::Value = self
+            .authorized(response)?
+            .error_for_status()?
             .json()
             .await
-            .context("Failed to parse commands response")
+            .context("Failed to parse providers response")?;
+        Ok(parse_models(&value))
     }
 
-    /// POST /tui/publish - Append text to prompt
-    pub async fn tui_append_prompt(&self, text: &str) -> Result<()> {
-        let url = format!("{}/tui/publish", self.base_url());
-        let request = TuiPublishRequest {
-            event_type: "tui.prompt.append".to_string(),
-            properties: serde_json::json!({ "text": text }),
-        };
+    /// GET a list endpoint (`/agent`, `/command`), unless it still matches `since`
+    ///
+    /// Servers that send neither header always answer with the list.
+    #[cfg(feature = "tui")]
+    pub async fn get_list(&self, path: &str, since: &Validators) -> Result<Listed> {
+        let mut request = self.http.get(format!("{}{}", self.base_url(), path));
+        if let Some(etag) = &since.etag {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+        }
+        if let Some(modified) = &since.last_modified {
+            request = request.header(reqwest::header::IF_MODIFIED_SINCE, modified);
+        }
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("Failed to fetch {}", path))?;
+        let response = self.authorized(response)?;
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            return Ok(Listed::NotModified);
+        }
+        let validators = Validators::of(&response);
+        let values = response
+            .error_for_status()?
+            .json()
+            .await
+            .with_context(|| format!("Failed to parse {} response", path))?;
+        Ok(Listed::Fresh(values, validators))
+    }
 
-        self.http
-            .post(&url)
-            .json(&request)
+    /// GET /session - List sessions
+    pub async fn list_sessions(&self) -> Result<Vec<Session>> {
+        let url = format!("{}/session", self.base_url());
+        let response = self
+            .http
+            .get(&url)
             .send()
             .await
-            .context("Failed to append prompt")?;
+            .context("Failed to fetch sessions")?;
+        let response = self.authorized(response)?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            bail!("Server does not support session listing (needs a newer opencode)");
+        }
+
+        response
+            .json()
+            .await
+            .context("Failed to parse sessions response")
+    }
 
+    /// PATCH /session/<id> - Set a session's title
+    pub async fn rename_session(&self, session_id: &str, title: &str) -> Result<()> {
+        let url = format!("{}/session/{}", self.base_url(), session_id);
+        let response = self
+            .http
+            .patch(&url)
+            .json(&serde_json::json!({ "title": title }))
+            .send()
+            .await
+            .context("Failed to rename session")?;
+        let status = self.authorized(response)?.status();
+        if !status.is_success() {
+            bail!(
+                "Server rejected the title for session {}: {}",
+                session_id,
+                status
+            );
+        }
         Ok(())
     }
 
-    /// POST /tui/publish - Execute a TUI command
-    pub async fn tui_execute_command(&self, command: &str) -> Result<()> {
-        let url = format!("{}/tui/publish", self.base_url());
+    /// GET /session/status - Id of a busy session, if any
+    ///
+    /// With a target session only that session counts; otherwise any busy
+    /// session does, since the focused TUI session isn't known. Servers
+    /// without the endpoint report nothing as busy.
+    pub async fn busy_session(&self) -> Result<Option<String>> {
+        let url = format!("{}/session/status", self.base_url());
+        let response = self
+            .http
+            .get(&url)
+            .send()
+            .await
+            .context("Failed to fetch session status")?;
+        let response = self.authorized(response)?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let statuses: std::collections::BTreeMap<String, SessionStatus> = response
+            .json()
+            .await
+            .context("Failed to parse session status")?;
+        Ok(statuses
+            .into_iter()
+            .filter(|(id, _)| self.session.as_ref().is_none_or(|s| s == id))
+            .find(|(_, status)| status.kind == "busy")
+            .map(|(id, _)| id))
+    }
+
+    /// GET /tui/prompt - Text in the TUI's prompt box
+    /// None when the server can't report it.
+    pub async fn tui_prompt_text(&self) -> Result<Option<String>> {
+        if !self.capabilities().await?.has_prompt_text {
+            return Ok(None);
+        }
+        let url = format!("{}/tui/prompt", self.base_url());
+        let response = self
+            .http
+            .get(&url)
+            .send()
+            .await
+            .context("Failed to fetch the prompt text")?;
+        let prompt: PromptTextResponse = self
+            .authorized(response)?
+            .json()
+            .await
+            .context("Failed to parse prompt text")?;
+        Ok(Some(prompt.text))
+    }
+
+    /// GET /event - Subscribe to the server event stream
+    pub async fn subscribe_events(&self) -> Result<EventStream> {
+        let url = format!("{}/event", self.base_url());
+        // The stream stays open, so it can't share the short request timeout
+        let http = reqwest::Client::builder()
+            .connect_timeout(std::time::Duration::from_secs(5))
+            .default_headers(self.auth.as_ref().map(Auth::headers).unwrap_or_default())
+            .build()
+            .context("Failed to create HTTP client")?;
+        let response = http
+            .get(&url)
+            .send()
+            .await
+            .context("Failed to subscribe to events")?;
+        let response = self.authorized(response)?;
+
+        let stream = response.bytes_stream().eventsource().map(|event| {
+            let event = event.context("Failed to read event stream")?;
+            serde_json::from_str(&event.data).context("Failed to parse event")
+        });
+        Ok(Box::pin(stream))
+    }
+
+    /// Port of the server this client talks to
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Whether requests carry an auth token
+    pub fn has_auth(&self) -> bool {
+        self.auth.is_some()
+    }
+
+    /// Session id targeted by this client, if any
+    pub fn session(&self) -> Option<&str> {
+        self.session.as_deref()
+    }
+
+    /// Agent prompts are handed to
+    pub fn agent(&self) -> Option<&str> {
+        self.agent.as_deref()
+    }
+
+    /// Model session messages ask for
+    #[cfg(feature = "tui")]
+    pub fn model(&self) -> Option<&Model> {
+        self.model.as_ref()
+    }
+
+    /// POST /tui/publish - Publish a TUI event
+    async fn tui_publish(&self, event_type: &str, properties: serde_json::Value) -> Result<()> {
         let request = TuiPublishRequest {
-            event_type: "tui.command.execute".to_string(),
-            properties: serde_json::json!({ "command": command }),
+            event_type: event_type.to_string(),
+            properties,
         };
+        self.post_publish(&request).await
+    }
 
-        self.http
+    /// POST /tui/publish - Publish several TUI events in one request
+    ///
+    /// Returns false when the server only takes single events: it rejects
+    /// the batch as a whole, so none of them ran.
+    async fn tui_publish_batch(&self, events: &[TuiPublishRequest]) -> Result<bool> {
+        let url = format!("{}/tui/publish", self.base_url());
+        let response = self
+            .http
             .post(&url)
-            .json(&request)
+            .json(events)
             .send()
             .await
-            .context("Failed to execute command")?;
+            .context("Failed to publish prompt")?;
+        let response = self.authorized(response)?;
 
+        let status = response.status();
+        if status == reqwest::StatusCode::BAD_REQUEST
+            || status == reqwest::StatusCode::UNPROCESSABLE_ENTITY
+        {
+            return Ok(false);
+        }
+        if !status.is_success() {
+            bail!(
+                "Failed to publish prompt: Server rejected event: {}",
+                status
+            );
+        }
+        Ok(true)
+    }
+
+    /// POST a publish body, failing unless the server accepted it
+    async fn post_publish(&self, body: &(impl Serialize + ?Sized)) -> Result<()> {
+        let url = format!("{}/tui/publish", self.base_url());
+        let response = self.http.post(&url).json(body).send().await?;
+        let response = self.authorized(response)?;
+
+        let status = response.status();
+        if !status.is_success() {
+            bail!("Server rejected event: {}", status);
+        }
         Ok(())
     }
 
+    /// POST /tui/publish - Append text to prompt
+    pub async fn tui_append_prompt(&self, text: &str) -> Result<()> {
+        self.tui_publish("tui.prompt.append", serde_json::json!({ "text": text }))
+            .await
+            .context("Failed to append prompt")
+    }
+
+    /// POST /tui/publish - Execute a TUI command
+    pub async fn tui_execute_command(&self, command: &str) -> Result<()> {
+        self.tui_publish(
+            "tui.command.execute",
+            serde_json::json!({ "command": command }),
+        )
+        .await
+        .context("Failed to execute command")
+    }
+
+    /// POST /tui/publish - Switch the TUI to a session
+    #[cfg(feature = "tui")]
+    pub async fn tui_select_session(&self, session_id: &str) -> Result<()> {
+        self.tui_publish(
+            "tui.session.select",
+            serde_json::json!({ "sessionID": session_id }),
+        )
+        .await
+        .context("Failed to switch session")
+    }
+
     /// Clear the prompt input
     pub async fn clear_prompt(&self) -> Result<()> {
         self.tui_execute_command("prompt.clear").await
@@ -154,8 +1026,131 @@ impl Client {
         self.tui_execute_command("prompt.submit").await
     }
 
+    /// POST /session/<id>/prompt_async - Send a message directly to a session
+    ///
+    /// Returns the response status; see [`check_session_status`](Self::check_session_status).
+    async fn session_message(
+        &self,
+        session_id: &str,
+        parts: &[MessagePart],
+    ) -> Result<reqwest::StatusCode> {
+        let url = format!("{}/session/{}/prompt_async", self.base_url(), session_id);
+        let response = self
+            .http
+            .post(&url)
+            .json(&SessionPromptRequest {
+                model: self.model.as_ref().map(|model| ModelRef {
+                    provider_id: &model.provider,
+                    model_id: &model.id,
+                }),
+                parts,
+            })
+            .send()
+            .await
+            .context("Failed to send message to session")?;
+        Ok(self.authorized(response)?.status())
+    }
+
+    /// Send `parts` to a session, or `text` alone if it rejects their schema
+    async fn session_prompt(
+        &self,
+        session_id: &str,
+        text: &str,
+        parts: Option<&[MessagePart]>,
+    ) -> Result<PublishMode> {
+        let text_only = [MessagePart::Text {
+            text: text.to_string(),
+        }];
+        // Without attachments the parts are the text anyway
+        let Some(parts) = parts.filter(|parts| parts.iter().any(MessagePart::is_file)) else {
+            let status = self.session_message(session_id, &text_only).await?;
+            Self::check_session_status(session_id, status)?;
+            return Ok(PublishMode::Session);
+        };
+
+        let status = self.session_message(session_id, parts).await?;
+        if status == reqwest::StatusCode::BAD_REQUEST
+            || status == reqwest::StatusCode::UNPROCESSABLE_ENTITY
+        {
+            let status = self.session_message(session_id, &text_only).await?;
+            Self::check_session_status(session_id, status)?;
+            return Ok(PublishMode::SessionText);
+        }
+        Self::check_session_status(session_id, status)?;
+        Ok(PublishMode::Session)
+    }
+
+    /// Turn a failed session message status into an error
+    fn check_session_status(session_id: &str, status: reqwest::StatusCode) -> Result<()> {
+        if status == reqwest::StatusCode::NOT_FOUND {
+            bail!(
+                "Server rejected message for session {}: session not found or endpoint not supported",
+                session_id
+            );
+        }
+        if !status.is_success() {
+            bail!(
+                "Server rejected message for session {}: {}",
+                session_id,
+                status
+            );
+        }
+
+        Ok(())
+    }
+
     /// Send a prompt: optionally clear, append text, optionally submit
-    pub async fn send_prompt(&self, text: &str, clear: bool, submit: bool) -> Result<()> {
+    ///
+    /// With a target session, the prompt is sent as a message to that session
+    /// (always submitted) instead of going through the TUI prompt, as `parts`
+    /// when given; a server that rejects them gets `text` instead. Otherwise
+    /// the events go in one request when the server takes batches, or one at
+    /// a time, stopping at the first the server rejects. Returns how the
+    /// prompt was delivered.
+    pub async fn send_prompt(
+        &self,
+        text: &str,
+        parts: Option<&[MessagePart]>,
+        clear: bool,
+        submit: bool,
+    ) -> Result<PublishMode> {
+        if let Some(ref session_id) = self.session {
+            if !submit {
+                bail!("Appending without submitting is not supported with --session");
+            }
+            return self.session_prompt(session_id, text, parts).await;
+        }
+
+        // A server not known to take batches is sent one to find out
+        let known = *self.publish_mode.lock().unwrap();
+        if known != Some(PublishMode::Sequential) {
+            let command = |command: &str| TuiPublishRequest {
+                event_type: "tui.command.execute".to_string(),
+                properties: serde_json::json!({ "command": command }),
+            };
+            let mut events = Vec::new();
+            if clear {
+                events.push(command("prompt.clear"));
+            }
+            events.push(TuiPublishRequest {
+                event_type: "tui.prompt.append".to_string(),
+                properties: serde_json::json!({ "text": text }),
+            });
+            if submit {
+                events.push(command("prompt.submit"));
+            }
+            let batched = self.tui_publish_batch(&events).await?;
+            let mode = if batched {
+                PublishMode::Batch
+            } else {
+                PublishMode::Sequential
+            };
+            *self.publish_mode.lock().unwrap() = Some(mode);
+            if batched {
+                return Ok(mode);
+            }
+        }
+
         if clear {
             self.clear_prompt().await?;
         }
@@ -166,8 +1161,64 @@ impl Client {
             self.submit_prompt().await?;
         }
 
-        Ok(())
+        Ok(PublishMode::Sequential)
     }
+
+    /// Append a long prompt to the TUI prompt box one chunk per request
+    ///
+    /// Servers cap the request body size, so a prompt too long for one event
+    /// goes in pieces, in order; `progress` is called with the 1-based chunk
+    /// number and the total before each. A failed chunk stops the send with a
+    /// [`PartialSend`], and the prompt is only submitted after the last one.
+    pub async fn send_chunks(
+        &self,
+        chunks: &[&str],
+        clear: bool,
+        submit: bool,
+        progress: impl Fn(usize, usize),
+    ) -> Result<PublishMode> {
+        if clear {
+            self.clear_prompt().await?;
+        }
+
+        let mut sent_bytes = 0;
+        for (i, chunk) in chunks.iter().enumerate() {
+            progress(i + 1, chunks.len());
+            if let Err(error) = self.tui_append_prompt(chunk).await {
+                return Err(PartialSend {
+                    sent: i,
+                    total: chunks.len(),
+                    sent_bytes,
+                    error,
+                }
+                .into());
+            }
+            sent_bytes += chunk.len();
+        }
+
+        if submit {
+            self.submit_prompt().await?;
+        }
+        Ok(PublishMode::Chunked(chunks.len()))
+    }
+}
+
+/// Models of a `/config/providers` response,
+/// `{"providers": [{"id": "anthropic", "models": {"claude-sonnet-4": {..}}}]}`;
+/// entries that don't fit are skipped
+#[cfg(feature = "tui")]
+fn parse_models(value: &serde_json::Value) -> Vec<Model> {
+    let providers = value["providers"].as_array().map_or(&[][..], Vec::as_slice);
+    providers
+        .iter()
+        .filter_map(|provider| Some((provider["id"].as_str()?, provider["models"].as_object()?)))
+        .flat_map(|(provider, models)| {
+            models.keys().map(move |id| Model {
+                provider: provider.to_string(),
+                id: id.clone(),
+            })
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -179,4 +1230,658 @@ mod tests {
         let client = Client::new(12345);
         assert_eq!(client.base_url(), "http://localhost:12345");
     }
+
+    #[test]
+    fn test_session_deserialize() {
+        let json = r#"{"id":"ses_1","title":"Fix bug","time":{"created":1,"updated":2}}"#;
+        let session: Session = serde_json::from_str(json).unwrap();
+        assert_eq!(session.id, "ses_1");
+        assert_eq!(session.title, "Fix bug");
+        #[cfg(feature = "tui")]
+        {
+            assert_eq!(session.time.updated, 2);
+            assert_eq!(session.message_count, None);
+        }
+
+        let json = r#"{"id":"ses_2","messageCount":7}"#;
+        let session: Session = serde_json::from_str(json).unwrap();
+        assert_eq!(session.title, "");
+        assert!(session.is_untitled());
+        #[cfg(feature = "tui")]
+        assert_eq!(session.message_count, Some(7));
+    }
+
+    #[test]
+    fn test_agents_old_shape() {
+        let json = r#"[
+            {"name": "build", "description": "Build things", "mode": "primary"},
+            {"name": "review", "description": "Review code", "mode": "subagent"}
+        ]"#;
+        let parsed: Lenient<Agent> = Lenient::from_values(serde_json::from_str(json).unwrap());
+        assert_eq!(parsed.items.len(), 2);
+        assert!(parsed.skipped.is_empty());
+        #[cfg(feature = "tui")]
+        assert_eq!(parsed.items[1].mode, "subagent");
+    }
+
+    #[test]
+    fn test_agents_new_shape() {
+        // Extra fields, missing description
+        let json = r#"[
+            {"name": "docs", "mode": "subagent", "builtIn": false, "permission": {"edit": "ask"}},
+            {"name": "plan", "description": null, "mode": "primary", "model": {"id": "x"}}
+        ]"#;
+        let parsed: Lenient<Agent> = Lenient::from_values(serde_json::from_str(json).unwrap());
+        assert_eq!(parsed.items.len(), 2);
+        assert!(parsed.skipped.is_empty());
+        assert_eq!(parsed.items[0].name, "docs");
+        #[cfg(feature = "tui")]
+        {
+            assert_eq!(parsed.items[0].description, "");
+            assert_eq!(parsed.items[1].description, "");
+        }
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_commands_partially_corrupt() {
+        let json = r#"[
+            {"name": "init", "description": "Create AGENTS.md", "template": "Init", "agent": "build"},
+            {"description": "no name"},
+            42,
+            {"name": "bare"}
+        ]"#;
+        let parsed: Lenient<Command> = Lenient::from_values(serde_json::from_str(json).unwrap());
+        let names: Vec<&str> = parsed.items.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["init", "bare"]);
+        assert_eq!(parsed.skipped.len(), 2);
+        assert_eq!(parsed.items[1].template, "");
+    }
+
+    /// Accept one HTTP request, reply with `status_line`, and return the raw request
+    async fn mock_server(status_line: &'static str) -> (u16, tokio::task::JoinHandle<String>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = Vec::new();
+            let mut chunk = [0u8; 1024];
+            loop {
+                let n = stream.read(&mut chunk).await.unwrap();
+                buf.extend_from_slice(&chunk[..n]);
+                let text = String::from_utf8_lossy(&buf).to_string();
+                if let Some(header_end) = text.find("\r\n\r\n") {
+                    let content_length = text[..header_end]
+                        .lines()
+                        .find_map(|l| {
+                            l.to_lowercase()
+                                .strip_prefix("content-length:")
+                                .map(|v| v.trim().parse::<usize>().unwrap())
+                        })
+                        .unwrap_or(0);
+                    if buf.len() >= header_end + 4 + content_length || n == 0 {
+                        break;
+                    }
+                }
+            }
+            let response = format!("{}\r\ncontent-length: 0\r\n\r\n", status_line);
+            stream.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8_lossy(&buf).to_string()
+        });
+        (port, handle)
+    }
+
+    /// Serve requests until dropped, replying with the status for each path
+    /// (unlisted paths get a 404) and an optional JSON body
+    async fn mock_routes(routes: &'static [(&'static str, &'static str, &'static str)]) -> u16 {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = Vec::new();
+                let mut chunk = [0u8; 1024];
+                while !String::from_utf8_lossy(&buf).contains("\r\n\r\n") {
+                    let n = stream.read(&mut chunk).await.unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    buf.extend_from_slice(&chunk[..n]);
+                }
+                let request = String::from_utf8_lossy(&buf).to_string();
+                let path = request.split_whitespace().nth(1).unwrap_or_default();
+                let (status, body) = routes
+                    .iter()
+                    .find(|(p, _, _)| *p == path)
+                    .map(|(_, status, body)| (*status, *body))
+                    .unwrap_or(("404 Not Found", ""));
+                let response = format!(
+                    "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        port
+    }
+
+    /// Serve `/tui/publish` until dropped, recording each request body and
+    /// replying 400 to the ones `reject` matches
+    async fn mock_publish(
+        reject: fn(&serde_json::Value) -> bool,
+    ) -> (u16, Arc<std::sync::Mutex<Vec<serde_json::Value>>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let bodies = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = bodies.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = Vec::new();
+                let mut chunk = [0u8; 1024];
+                let body = loop {
+                    let n = stream.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+                    let text = String::from_utf8_lossy(&buf).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length = head
+                            .lines()
+                            .find_map(|l| {
+                                l.to_lowercase()
+                                    .strip_prefix("content-length:")
+                                    .map(|v| v.trim().parse::<usize>().unwrap())
+                            })
+                            .unwrap_or(0);
+                        if body.len() >= length || n == 0 {
+                            break body.to_string();
+                        }
+                    }
+                };
+                let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+                let status = if reject(&json) {
+                    "400 Bad Request"
+                } else {
+                    "200 OK"
+                };
+                recorded.lock().unwrap().push(json);
+                let response = format!(
+                    "HTTP/1.1 {}\r\ncontent-length: 4\r\nconnection: close\r\n\r\ntrue",
+                    status
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        (port, bodies)
+    }
+
+    #[tokio::test]
+    async fn test_capabilities_probe() {
+        let port = mock_routes(&[
+            ("/session", "200 OK", "[]"),
+            (
+                "/global/health",
+                "200 OK",
+                r#"{"healthy":true,"version":"0.4.2"}"#,
+            ),
+        ])
+        .await;
+        let client = Client::new(port);
+        let caps = client.capabilities().await.unwrap();
+        assert_eq!(
+            caps,
+            &Capabilities {
+                has_sessions: true,
+                has_events: false,
+                has_models: false,
+                has_prompt_text: false,
+                server_version: Some("0.4.2".to_string()),
+            }
+        );
+        assert!(client.require(Feature::Sessions).await.is_ok());
+        let err = client.require(Feature::Events).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Waiting for the session requires opencode >= 0.3 (server reports 0.4.2)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_busy_session() {
+        let port = mock_routes(&[(
+            "/session/status",
+            "200 OK",
+            r#"{"ses_a":{"type":"idle"},"ses_b":{"type":"busy"}}"#,
+        )])
+        .await;
+        let client = Client::new(port);
+        assert_eq!(
+            client.busy_session().await.unwrap(),
+            Some("ses_b".to_string())
+        );
+        let client = Client::new(port).with_session(Some("ses_a".to_string()));
+        assert_eq!(client.busy_session().await.unwrap(), None);
+
+        // Older servers without the endpoint are never busy
+        let port = mock_routes(&[]).await;
+        assert_eq!(Client::new(port).busy_session().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_tui_prompt_text() {
+        let port = mock_routes(&[("/tui/prompt", "200 OK", r#"{"text":"half-typed"}"#)]).await;
+        assert_eq!(
+            Client::new(port)
+                .tui_prompt_text()
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("half-typed")
+        );
+
+        // Servers without the endpoint can't tell
+        let port = mock_routes(&[]).await;
+        assert_eq!(Client::new(port).tui_prompt_text().await.unwrap(), None);
+    }
+
+    #[test]
+    fn test_compat() {
+        assert_eq!(parse_version("v1.2.3-beta+4"), Some(vec![1, 2, 3]));
+        assert_eq!(parse_version("0.15"), Some(vec![0, 15]));
+        assert_eq!(parse_version("dev"), None);
+        assert_eq!(parse_version("1..2"), None);
+        assert!(compare_versions(&[1, 2], &[1, 2, 0]).is_eq());
+        assert!(compare_versions(&[0, 10], &[0, 9, 9]).is_gt());
+
+        let (oldest, first_unknown) = COMPATIBLE_VERSIONS;
+        assert_eq!(Compat::of(Some(oldest)), Compat::Compatible);
+        assert_eq!(Compat::of(Some("0.15.8")), Compat::Compatible);
+        assert_eq!(Compat::of(Some("0.2.9")), Compat::TooOld);
+        assert_eq!(Compat::of(Some(first_unknown)), Compat::TooNew);
+        assert_eq!(Compat::of(Some("local")), Compat::Unknown);
+        assert_eq!(Compat::of(None), Compat::Unknown);
+
+        let caps = |version: &str| Capabilities {
+            server_version: Some(version.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(caps("1.0.0").compat_warning(), None);
+        assert_eq!(
+            caps("0.2.1").compat_warning().unwrap(),
+            "opencode 0.2.1 is older than 0.3.0, the oldest version this opencode-helix supports"
+        );
+        assert_eq!(
+            caps("2.1.0").compat_warning().unwrap(),
+            "opencode 2.1.0 is newer than this opencode-helix is known to work with (below 2.0.0)"
+        );
+        assert_eq!(Capabilities::default().compat_warning(), None);
+    }
+
+    #[tokio::test]
+    async fn test_capabilities_old_server() {
+        let port = mock_routes(&[]).await;
+        let client = Client::new(port);
+        let err = client.require(Feature::Events).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Waiting for the session requires opencode >= 0.3 (server reports an unknown version)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_prompt_to_session() {
+        let (port, handle) = mock_server("HTTP/1.1 204 No Content").await;
+        let client = Client::new(port).with_session(Some("ses_123".to_string()));
+        client
+            .send_prompt("Explain @src/main.rs", None, false, true)
+            .await
+            .unwrap();
+
+        let request = handle.await.unwrap();
+        assert!(request.starts_with("POST /session/ses_123/prompt_async HTTP/1.1"));
+        let body = &request[request.find("\r\n\r\n").unwrap() + 4..];
+        let json: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "parts": [{ "type": "text", "text": "Explain @src/main.rs" }] })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_prompt_with_model() {
+        let (port, handle) = mock_server("HTTP/1.1 204 No Content").await;
+        let client = Client::new(port)
+            .with_session(Some("ses_123".to_string()))
+            .with_model(Some("anthropic/claude-sonnet-4".parse().unwrap()));
+        client.send_prompt("hi", None, false, true).await.unwrap();
+
+        let request = handle.await.unwrap();
+        let body = &request[request.find("\r\n\r\n").unwrap() + 4..];
+        let json: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(
+            json["model"],
+            serde_json::json!({ "providerID": "anthropic", "modelID": "claude-sonnet-4" })
+        );
+    }
+
+    #[test]
+    fn test_model_parse() {
+        let model: Model = "openrouter/meta-llama/llama-3".parse().unwrap();
+        assert_eq!(model.provider, "openrouter");
+        assert_eq!(model.id, "meta-llama/llama-3");
+        assert_eq!(model.to_string(), "openrouter/meta-llama/llama-3");
+        for text in ["claude-sonnet-4", "/claude", "anthropic/", ""] {
+            let e = text.parse::<Model>().unwrap_err();
+            assert!(e.to_string().contains("PROVIDER/MODEL"), "{}", e);
+        }
+    }
+
+    #[cfg(feature = "tui")]
+    #[tokio::test]
+    async fn test_get_models() {
+        let port = mock_routes(&[(
+            "/config/providers",
+            "200 OK",
+            r#"{"providers":[{"id":"anthropic","models":{"claude-sonnet-4":{},"claude-opus-4":{}}},{"id":"broken"}],"default":{}}"#,
+        )])
+        .await;
+        let models: Vec<String> = Client::new(port)
+            .get_models()
+            .await
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            models,
+            ["anthropic/claude-opus-4", "anthropic/claude-sonnet-4"]
+        );
+        assert!(parse_models(&serde_json::json!({})).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rename_session() {
+        let (port, handle) = mock_server("HTTP/1.1 200 OK").await;
+        let client = Client::new(port);
+        client
+            .rename_session("ses_123", "Fix the parser")
+            .await
+            .unwrap();
+
+        let request = handle.await.unwrap();
+        assert!(request.starts_with("PATCH /session/ses_123 HTTP/1.1"));
+        let body = &request[request.find("\r\n\r\n").unwrap() + 4..];
+        let json: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(json, serde_json::json!({ "title": "Fix the parser" }));
+
+        let (port, _handle) = mock_server("HTTP/1.1 400 Bad Request").await;
+        let err = Client::new(port)
+            .rename_session("ses_1", "x")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("ses_1"), "{}", err);
+    }
+
+    #[cfg(feature = "tui")]
+    #[tokio::test]
+    async fn test_get_list_not_modified() {
+        let (port, handle) = mock_server("HTTP/1.1 304 Not Modified").await;
+        let since = Validators {
+            etag: Some("\"abc\"".to_string()),
+            last_modified: Some("Wed, 01 Jan 2026 00:00:00 GMT".to_string()),
+        };
+        let listed = Client::new(port).get_list("/agent", &since).await.unwrap();
+        assert_eq!(listed, Listed::NotModified);
+
+        let request = handle.await.unwrap().to_lowercase();
+        assert!(request.starts_with("get /agent http/1.1"), "{}", request);
+        assert!(request.contains("if-none-match: \"abc\""), "{}", request);
+        assert!(
+            request.contains("if-modified-since: wed, 01 jan 2026"),
+            "{}",
+            request
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_prompt_to_session_rejected() {
+        let (port, _handle) = mock_server("HTTP/1.1 404 Not Found").await;
+        let client = Client::new(port).with_session(Some("ses_404".to_string()));
+        let err = client
+            .send_prompt("hi", None, false, true)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("ses_404"));
+    }
+
+    #[tokio::test]
+    async fn test_auth_rejected() {
+        let token: crate::server::Token = "s3cret".parse().unwrap();
+        let auth = Auth::new(&token, None).unwrap();
+        let (port, handle) = mock_server("HTTP/1.1 401 Unauthorized").await;
+        let client = Client::new(port).with_auth(Some(auth));
+        let err = client.get_agents().await.unwrap_err();
+
+        let request = handle.await.unwrap();
+        assert!(
+            request.contains("authorization: Bearer s3cret\r\n"),
+            "{}",
+            request
+        );
+        let message = format!("{:#}", err);
+        assert!(
+            message.contains("Authentication failed (401"),
+            "{}",
+            message
+        );
+        assert!(message.contains(TOKEN_ENV), "{}", message);
+        // Neither the error nor the client's debug output gives the token away
+        assert!(!message.contains("s3cret"));
+        assert!(!format!("{:?}", client).contains("s3cret"));
+
+        // Without a token, a 403 says one is needed rather than failing to parse
+        static ROUTES: &[(&str, &str, &str)] = &[("/session", "403 Forbidden", "")];
+        let client = Client::new(mock_routes(ROUTES).await);
+        let err = client.list_sessions().await.unwrap_err();
+        assert!(
+            err.to_string().contains("requires authentication"),
+            "{}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_parts_to_session() {
+        let parts = MessageBuilder::default()
+            .text("Explain ")
+            .part(MessagePart::file(Path::new("/p/a.rs"), "a.rs", Some((3, 4))).unwrap())
+            .text("")
+            .part(MessagePart::Text {
+                text: " please".to_string(),
+            })
+            .text("!")
+            .build();
+        assert_eq!(parts.len(), 3);
+        assert_eq!(MessagePart::file(Path::new("a.rs"), "a.rs", None), None);
+
+        let (port, bodies) = mock_publish(|_| false).await;
+        let client = Client::new(port).with_session(Some("ses_1".to_string()));
+        let mode = client
+            .send_prompt("Explain @a.rs please!", Some(&parts), false, true)
+            .await
+            .unwrap();
+        assert_eq!(mode, PublishMode::Session);
+        assert_eq!(
+            bodies.lock().unwrap()[0],
+            serde_json::json!({ "parts": [
+                { "type": "text", "text": "Explain " },
+                {
+                    "type": "file",
+                    "mime": "text/plain",
+                    "filename": "a.rs",
+                    "url": "file:///p/a.rs?start=3&end=4"
+                },
+                { "type": "text", "text": " please!" },
+            ] })
+        );
+
+        // A server that rejects file parts gets the text
+        let (port, bodies) = mock_publish(|json| json["parts"].as_array().unwrap().len() > 1).await;
+        let client = Client::new(port).with_session(Some("ses_1".to_string()));
+        let mode = client
+            .send_prompt("Explain @a.rs please!", Some(&parts), false, true)
+            .await
+            .unwrap();
+        assert_eq!(mode, PublishMode::SessionText);
+        let bodies = bodies.lock().unwrap();
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(
+            bodies[1],
+            serde_json::json!({ "parts": [{ "type": "text", "text": "Explain @a.rs please!" }] })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_chunks() {
+        let chunks = ["one\n", "two\n", "three"];
+        let (port, bodies) = mock_publish(|_| false).await;
+        let progress = std::sync::Mutex::new(Vec::new());
+        let mode = Client::new(port)
+            .send_chunks(&chunks, true, true, |n, total| {
+                progress.lock().unwrap().push((n, total))
+            })
+            .await
+            .unwrap();
+        assert_eq!(mode, PublishMode::Chunked(3));
+        assert_eq!(*progress.lock().unwrap(), [(1, 3), (2, 3), (3, 3)]);
+
+        // Clear first, the chunks in order, then submit
+        let events: Vec<String> = bodies
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|body| match body["type"].as_str().unwrap() {
+                "tui.prompt.append" => body["properties"]["text"].as_str().unwrap().to_string(),
+                _ => body["properties"]["command"].as_str().unwrap().to_string(),
+            })
+            .collect();
+        assert_eq!(
+            events,
+            ["prompt.clear", "one\n", "two\n", "three", "prompt.submit"]
+        );
+
+        // A rejected chunk stops the send before the submit
+        let (port, bodies) = mock_publish(|json| json["properties"]["text"] == "two\n").await;
+        let err = Client::new(port)
+            .send_chunks(&chunks, false, true, |_, _| {})
+            .await
+            .unwrap_err();
+        let partial = err.downcast_ref::<PartialSend>().unwrap();
+        assert_eq!((partial.sent, partial.total, partial.sent_bytes), (1, 3, 4));
+        assert_eq!(
+            err.to_string(),
+            "Sent 1 of 3 chunks of the prompt; chunk 2 failed"
+        );
+        assert_eq!(bodies.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_send_prompt_batched() {
+        let (port, bodies) = mock_publish(|_| false).await;
+        let client = Client::new(port);
+        assert_eq!(client.batch_publish(), None);
+        let mode = client
+            .send_prompt("Review @diff", None, true, true)
+            .await
+            .unwrap();
+        assert_eq!(mode, PublishMode::Batch);
+        assert_eq!(client.batch_publish(), Some(true));
+        client
+            .send_prompt("again", None, false, false)
+            .await
+            .unwrap();
+
+        // One request per send, and no probe
+        let bodies = bodies.lock().unwrap();
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(
+            bodies[0],
+            serde_json::json!([
+                { "type": "tui.command.execute", "properties": { "command": "prompt.clear" } },
+                { "type": "tui.prompt.append", "properties": { "text": "Review @diff" } },
+                { "type": "tui.command.execute", "properties": { "command": "prompt.submit" } },
+            ])
+        );
+        assert_eq!(
+            bodies[1],
+            serde_json::json!([{ "type": "tui.prompt.append", "properties": { "text": "again" } }])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_prompt_sequential() {
+        let (port, bodies) = mock_publish(|body| body.is_array()).await;
+        let client = Client::new(port);
+        let mode = client.send_prompt("hi", None, true, true).await.unwrap();
+        assert_eq!(mode, PublishMode::Sequential);
+        assert_eq!(client.batch_publish(), Some(false));
+        // Found out once: the next send doesn't try a batch
+        client
+            .send_prompt("again", None, false, false)
+            .await
+            .unwrap();
+
+        let bodies = bodies.lock().unwrap();
+        assert!(bodies[0].is_array());
+        let types: Vec<String> = bodies[1..]
+            .iter()
+            .map(|b| format!("{} {}", b["type"], b["properties"]))
+            .collect();
+        assert_eq!(
+            types,
+            [
+                r#""tui.command.execute" {"command":"prompt.clear"}"#,
+                r#""tui.prompt.append" {"text":"hi"}"#,
+                r#""tui.command.execute" {"command":"prompt.submit"}"#,
+                r#""tui.prompt.append" {"text":"again"}"#,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_prompt_known_sequential() {
+        let (port, bodies) = mock_publish(|body| body.is_array()).await;
+        let client = Client::new(port).with_batch_publish(Some(false));
+        let mode = client.send_prompt("hi", None, false, false).await.unwrap();
+        assert_eq!(mode, PublishMode::Sequential);
+        // A previous run found out, so not even one batch is tried
+        assert_eq!(
+            *bodies.lock().unwrap(),
+            [serde_json::json!({ "type": "tui.prompt.append", "properties": { "text": "hi" } })]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_prompt_sequential_stops_on_rejection() {
+        let (port, bodies) =
+            mock_publish(|body| body.is_array() || body["type"] == "tui.prompt.append").await;
+        let client = Client::new(port);
+        let err = client
+            .send_prompt("hi", None, true, true)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "Failed to append prompt: Server rejected event: 400 Bad Request"
+        );
+        // The submit after the rejected append is never sent
+        assert_eq!(bodies.lock().unwrap().len(), 3);
+    }
 }
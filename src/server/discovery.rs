@@ -1,13 +1,46 @@
 //! Server discovery for opencode processes
 //!
 //! Finds running opencode servers by scanning processes and validating via HTTP.
+//!
+//! Precedence, first match wins:
+//! 1. `--port`: used as given; a port that doesn't answer is an error
+//! 2. `$OPENCODE_PORT` (see [`PORT_ENV`]): used when it answers as an
+//!    opencode server, whatever its directory; otherwise ignored
+//! 3. Scanning the opencode processes for a `--port` whose server's
+//!    directory nests with ours
+//!
+//! The auth token goes to the ports of 1 and 2, and to processes of our
+//! own user; anyone on the machine can start a process that looks like
+//! opencode, so other users' servers are probed without it.
+//!
+//! The whole chain runs under one deadline (`--discovery-timeout`, 3s by
+//! default); each strategy notes how far it got as it goes, so a timed-out
+//! discovery can say where it was stuck.
+//!
+//! `status --print-server-env` prints the variables for `eval` (see
+//! [`server_env`]), so tools launched from opencode reach the same server
+//! without scanning again.
 
 use anyhow::{anyhow, Context, Result};
+use serde::Serialize;
+use std::fmt;
+use std::future::Future;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
 use sysinfo::System;
 
+use super::auth::Auth;
+use crate::exit;
+
+/// Port of a server to use before scanning processes
+pub const PORT_ENV: &str = "OPENCODE_PORT";
+
+/// Working directory of that server, printed alongside [`PORT_ENV`]
+pub const CWD_ENV: &str = "OPENCODE_CWD";
+
 /// A discovered opencode server
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct Server {
     /// Process ID
     pub pid: u32,
@@ -17,9 +50,203 @@ pub struct Server {
     pub cwd: PathBuf,
 }
 
-/// Find opencode processes listening on ports
-fn find_opencode_processes() -> Result<Vec<(u32, String)>> {
+/// A port that didn't answer as an opencode server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Probe {
+    /// Process the port was read from, None for `--port`
+    pub pid: Option<u32>,
+    pub port: u16,
+    /// Why the probe failed
+    pub error: String,
+}
+
+/// How far one discovery strategy got
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Step {
+    /// `--port`, `$OPENCODE_PORT` or `process scan`
+    pub strategy: String,
+    /// What it did last
+    pub progress: String,
+}
+
+/// The steps of a discovery run so far, shared with the deadline
+#[derive(Debug, Default)]
+struct Trail(Mutex<Vec<Step>>);
+
+impl Trail {
+    /// Record what `strategy` is doing, replacing its earlier note
+    fn note(&self, strategy: &str, progress: impl Into<String>) {
+        let mut steps = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let progress = progress.into();
+        match steps.last_mut() {
+            Some(last) if last.strategy == strategy => last.progress = progress,
+            _ => steps.push(Step {
+                strategy: strategy.to_string(),
+                progress,
+            }),
+        }
+    }
+
+    fn steps(&self) -> Vec<Step> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+const BY_FLAG: &str = "--port";
+const BY_ENV: &str = "$OPENCODE_PORT";
+const BY_SCAN: &str = "process scan";
+
+/// Why no server was found
+///
+/// Each cause has its own exit code, and with `--output json` it is
+/// reported as `reason` (the variant name in snake case) with its fields.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum DiscoveryError {
+    /// No opencode process is running
+    NoProcesses,
+    /// opencode is running, but without a `--port` to reach it on
+    NoPortInCmdline { pids: Vec<u32> },
+    /// No port answered as an opencode server
+    AllProbesFailed { attempts: Vec<Probe> },
+    /// Servers answered, but for other directories
+    CwdMismatch {
+        cwd: PathBuf,
+        candidates: Vec<Server>,
+    },
+    /// Discovery didn't finish within its deadline
+    TimedOut { after_ms: u64, steps: Vec<Step> },
+}
+
+impl DiscoveryError {
+    /// Exit code the process ends with
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::NoProcesses => exit::EXIT_NO_SERVER,
+            Self::NoPortInCmdline { .. } => exit::EXIT_NO_PORT,
+            Self::AllProbesFailed { .. } => exit::EXIT_UNREACHABLE,
+            Self::CwdMismatch { .. } => exit::EXIT_CWD_MISMATCH,
+            Self::TimedOut { .. } => exit::EXIT_UNREACHABLE,
+        }
+    }
+
+    /// The `reason` in JSON output
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::NoProcesses => "no_processes",
+            Self::NoPortInCmdline { .. } => "no_port_in_cmdline",
+            Self::AllProbesFailed { .. } => "all_probes_failed",
+            Self::CwdMismatch { .. } => "cwd_mismatch",
+            Self::TimedOut { .. } => "timed_out",
+        }
+    }
+}
+
+impl fmt::Display for DiscoveryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoProcesses => {
+                write!(
+                    f,
+                    "No opencode processes found. Start opencode first with: opencode"
+                )
+            }
+            Self::NoPortInCmdline { pids } => {
+                let pids: Vec<String> = pids.iter().map(u32::to_string).collect();
+                write!(
+                    f,
+                    "opencode is running (pid {}) but without --port, so its server can't be \
+                     found. Restart it with: opencode --port <PORT>, or pass --port if you \
+                     know the port",
+                    pids.join(", ")
+                )
+            }
+            Self::AllProbesFailed { attempts } => match attempts.as_slice() {
+                [Probe {
+                    pid: None,
+                    port,
+                    error,
+                }] => write!(
+                    f,
+                    "No opencode server responding on port {}: {}",
+                    port, error
+                ),
+                _ => {
+                    write!(f, "No opencode server answered:")?;
+                    for probe in attempts {
+                        match probe.pid {
+                            Some(pid) => write!(f, "\n  port {} (pid {})", probe.port, pid)?,
+                            None => write!(f, "\n  port {}", probe.port)?,
+                        }
+                        write!(f, ": {}", probe.error)?;
+                    }
+                    write!(
+                        f,
+                        "\nCheck that opencode is still starting up or not stuck; a server \
+                         that needs a password also needs --auth-token"
+                    )
+                }
+            },
+            Self::CwdMismatch { cwd, candidates } => {
+                write!(
+                    f,
+                    "No opencode server found for directory: {}",
+                    cwd.display()
+                )?;
+                write!(f, "\nRunning servers:")?;
+                for server in candidates {
+                    write!(
+                        f,
+                        "\n  port {} (pid {}): {}",
+                        server.port,
+                        server.pid,
+                        server.cwd.display()
+                    )?;
+                }
+                write!(
+                    f,
+                    "\nStart opencode in {}, run from one of these directories (or pass \
+                     --cwd), or pass --port to use one of them anyway",
+                    cwd.display()
+                )
+            }
+            Self::TimedOut { after_ms, steps } => {
+                write!(
+                    f,
+                    "No opencode server found within {:.1}s",
+                    *after_ms as f64 / 1000.0
+                )?;
+                if !steps.is_empty() {
+                    write!(f, ":")?;
+                }
+                for step in steps {
+                    write!(f, "\n  {}: {}", step.strategy, step.progress)?;
+                }
+                write!(
+                    f,
+                    "\nPass --port to skip discovery, or a longer --discovery-timeout"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for DiscoveryError {}
+
+/// An opencode process found by scanning
+#[derive(Debug, Clone)]
+struct Process {
+    pid: u32,
+    cmdline: String,
+    /// Run by our user, so its server may see the auth token
+    ours: bool,
+}
+
+/// Find opencode processes: those with a --port flag, and the opencode binary itself
+fn find_opencode_processes() -> Vec<Process> {
     let system = System::new_all();
+    let own_pid = std::process::id();
+    let uid = unsafe { libc::getuid() };
 
     let mut processes = Vec::new();
 
@@ -31,13 +258,21 @@ fn find_opencode_processes() -> Result<Vec<(u32, String)>> {
             .collect::<Vec<_>>()
             .join(" ");
 
-        // Look for opencode processes with --port flag
-        if cmd_str.contains("opencode") && cmd_str.contains("--port") {
-            processes.push((pid.as_u32(), cmd_str));
+        let is_binary = cmd
+            .first()
+            .and_then(|program| Path::new(program).file_name())
+            .is_some_and(|name| name == "opencode");
+        let with_port = cmd_str.contains("opencode") && cmd_str.contains("--port");
+        if pid.as_u32() != own_pid && (is_binary || with_port) {
+            processes.push(Process {
+                pid: pid.as_u32(),
+                cmdline: cmd_str,
+                ours: process.user_id().is_some_and(|owner| **owner == uid),
+            });
         }
     }
 
-    Ok(processes)
+    processes
 }
 
 /// Extract port number from command line arguments
@@ -62,9 +297,47 @@ fn extract_port_from_cmdline(cmdline: &str) -> Option<u16> {
     None
 }
 
+/// Normalize a directory for matching against a server cwd
+///
+/// Canonicalizes the path and maps a git worktree admin dir
+/// (`<repo>/.git/worktrees/<name>`) to the worktree checkout it belongs to.
+fn normalize_dir(path: &Path) -> PathBuf {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+
+    for admin in path.ancestors() {
+        let in_worktrees = admin
+            .parent()
+            .filter(|p| p.file_name().is_some_and(|n| n == "worktrees"))
+            .and_then(Path::parent)
+            .is_some_and(|p| p.file_name().is_some_and(|n| n == ".git"));
+        if !in_worktrees {
+            continue;
+        }
+        // `gitdir` holds the path of the worktree's `.git` file
+        if let Ok(gitdir) = std::fs::read_to_string(admin.join("gitdir")) {
+            if let Some(checkout) = Path::new(gitdir.trim()).parent() {
+                return checkout
+                    .canonicalize()
+                    .unwrap_or_else(|_| checkout.to_path_buf());
+            }
+        }
+    }
+    path
+}
+
+/// Check if our cwd and a server cwd belong together (one nests inside the other)
+///
+/// A worktree and its main repository are separate checkouts, so they only
+/// match when their paths actually nest.
+pub(super) fn dirs_related(ours: &Path, server: &Path) -> bool {
+    let ours = normalize_dir(ours);
+    let server = normalize_dir(server);
+    ours.starts_with(&server) || server.starts_with(&ours)
+}
+
 /// Validate a port is an opencode server and get its working directory
-async fn validate_server(port: u16) -> Result<Server> {
-    let client = super::client::Client::new(port);
+async fn validate_server(port: u16, auth: Option<&Auth>) -> Result<Server> {
+    let client = super::client::Client::new(port).with_auth(auth.cloned());
     let path_response = client
         .get_path()
         .await
@@ -82,57 +355,199 @@ async fn validate_server(port: u16) -> Result<Server> {
     })
 }
 
+/// `export` lines for `eval` that point nested invocations at `server`
+pub fn server_env(server: &Server) -> String {
+    let cwd = server.cwd.to_string_lossy();
+    format!(
+        "export {}={}\nexport {}='{}'\n",
+        PORT_ENV,
+        server.port,
+        CWD_ENV,
+        cwd.replace('\'', "'\\''")
+    )
+}
+
+/// Port in [`PORT_ENV`], if set to one
+fn env_port() -> Option<u16> {
+    std::env::var(PORT_ENV).ok()?.trim().parse().ok()
+}
+
 /// Discover an opencode server for the given working directory
 ///
-/// If `port` is specified, validates and uses that port directly.
-/// Otherwise, scans for opencode processes and finds one matching the cwd.
-pub async fn discover_server(cwd: &Path, port: Option<u16>) -> Result<Server> {
+/// If `port` is specified, validates and uses that port directly. Otherwise
+/// the port in [`PORT_ENV`] is used if it answers, and failing that the
+/// opencode processes are scanned for one matching the cwd (see the module
+/// docs). `auth` is sent to the servers the module docs say. Gives up after `deadline`
+/// with [`DiscoveryError::TimedOut`].
+pub async fn discover_server(
+    cwd: &Path,
+    port: Option<u16>,
+    auth: Option<&Auth>,
+    deadline: Duration,
+) -> Result<Server, DiscoveryError> {
+    let trail = Trail::default();
+    // The process table is read off the runtime, so the deadline can interrupt it
+    let processes = || async {
+        tokio::task::spawn_blocking(find_opencode_processes)
+            .await
+            .unwrap_or_default()
+    };
+    let discovery = discover_with(
+        cwd,
+        port,
+        env_port(),
+        processes,
+        |port, with_auth| validate_server(port, auth.filter(|_| with_auth)),
+        &trail,
+    );
+    within(deadline, &trail, discovery).await
+}
+
+/// `discovery`, or [`DiscoveryError::TimedOut`] with the `trail` so far once
+/// `deadline` passes
+async fn within(
+    deadline: Duration,
+    trail: &Trail,
+    discovery: impl Future<Output = Result<Server, DiscoveryError>>,
+) -> Result<Server, DiscoveryError> {
+    match tokio::time::timeout(deadline, discovery).await {
+        Ok(result) => result,
+        Err(_) => Err(DiscoveryError::TimedOut {
+            after_ms: deadline.as_millis() as u64,
+            steps: trail.steps(),
+        }),
+    }
+}
+
+/// The strategy chain, with the process list and the server check passed in
+///
+/// `validate` is told whether the port may see the auth token.
+async fn discover_with<P, PF, V, VF>(
+    cwd: &Path,
+    port: Option<u16>,
+    env_port: Option<u16>,
+    processes: P,
+    validate: V,
+    trail: &Trail,
+) -> Result<Server, DiscoveryError>
+where
+    P: FnOnce() -> PF,
+    PF: Future<Output = Vec<Process>>,
+    V: Fn(u16, bool) -> VF,
+    VF: Future<Output = Result<Server>>,
+{
     // If port is specified, use it directly
     if let Some(p) = port {
-        return validate_server(p)
+        trail.note(BY_FLAG, format!("probing port {}", p));
+        return validate(p, true)
             .await
-            .context(format!("No opencode server responding on port {}", p));
+            .map_err(|e| DiscoveryError::AllProbesFailed {
+                attempts: vec![Probe {
+                    pid: None,
+                    port: p,
+                    error: format!("{:#}", e),
+                }],
+            });
+    }
+
+    // Set by a parent invocation; stale once that server is gone
+    if let Some(p) = env_port {
+        trail.note(BY_ENV, format!("probing port {}", p));
+        match validate(p, true).await {
+            Ok(server) => return Ok(server),
+            Err(e) => trail.note(BY_ENV, format!("port {} didn't answer: {:#}", p, e)),
+        }
     }
 
     // Find all opencode processes
-    let processes = find_opencode_processes()?;
+    trail.note(BY_SCAN, "listing processes");
+    let processes = processes().await;
     if processes.is_empty() {
-        return Err(anyhow!(
-            "No opencode processes found. Start opencode first with: opencode"
-        ));
+        return Err(DiscoveryError::NoProcesses);
     }
 
     // Try each process to find one matching our cwd
-    let mut last_error = None;
-    for (pid, cmdline) in processes {
-        if let Some(port) = extract_port_from_cmdline(&cmdline) {
-            match validate_server(port).await {
-                Ok(mut server) => {
-                    server.pid = pid;
-
-                    // Check if server's cwd matches or contains our cwd
-                    let server_cwd = server.cwd.canonicalize().unwrap_or(server.cwd.clone());
-                    let our_cwd = cwd.canonicalize().unwrap_or(cwd.to_path_buf());
-
-                    if our_cwd.starts_with(&server_cwd) || server_cwd.starts_with(&our_cwd) {
-                        return Ok(server);
-                    }
-                }
-                Err(e) => {
-                    last_error = Some(e);
+    let total = processes.len();
+    let mut without_port = Vec::new();
+    let mut attempts = Vec::new();
+    let mut candidates = Vec::new();
+    for (i, process) in processes.into_iter().enumerate() {
+        let pid = process.pid;
+        let Some(port) = extract_port_from_cmdline(&process.cmdline) else {
+            without_port.push(pid);
+            continue;
+        };
+        trail.note(
+            BY_SCAN,
+            format!(
+                "probing port {} (pid {}), process {} of {}",
+                port,
+                pid,
+                i + 1,
+                total
+            ),
+        );
+        match validate(port, process.ours).await {
+            Ok(mut server) => {
+                server.pid = pid;
+
+                // Check if server's cwd matches or contains our cwd
+                if dirs_related(cwd, &server.cwd) {
+                    return Ok(server);
                 }
+                candidates.push(server);
             }
+            Err(e) => attempts.push(Probe {
+                pid: Some(pid),
+                port,
+                error: format!("{:#}", e),
+            }),
         }
     }
 
-    Err(last_error
-        .unwrap_or_else(|| anyhow!("No opencode server found for directory: {}", cwd.display())))
+    Err(failure(cwd, without_port, attempts, candidates))
+}
+
+/// The most useful error once no process matched
+///
+/// A server for another directory says the most about what to do, then a
+/// port that didn't answer, then an opencode without a port.
+fn failure(
+    cwd: &Path,
+    mut pids: Vec<u32>,
+    attempts: Vec<Probe>,
+    candidates: Vec<Server>,
+) -> DiscoveryError {
+    if !candidates.is_empty() {
+        DiscoveryError::CwdMismatch {
+            cwd: cwd.to_path_buf(),
+            candidates,
+        }
+    } else if !attempts.is_empty() {
+        DiscoveryError::AllProbesFailed { attempts }
+    } else {
+        pids.sort_unstable();
+        DiscoveryError::NoPortInCmdline { pids }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_server_env() {
+        let server = Server {
+            pid: 0,
+            port: 4096,
+            cwd: PathBuf::from("/home/u/it's here"),
+        };
+        assert_eq!(
+            server_env(&server),
+            "export OPENCODE_PORT=4096\nexport OPENCODE_CWD='/home/u/it'\\''s here'\n"
+        );
+    }
+
     #[test]
     fn test_extract_port() {
         assert_eq!(
@@ -149,4 +564,197 @@ mod tests {
         );
         assert_eq!(extract_port_from_cmdline("opencode --other"), None);
     }
+
+    /// Run git in `dir`, panicking on failure
+    fn git(dir: &Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    /// A process of ours running `cmdline`
+    fn process(pid: u32, cmdline: &str) -> Process {
+        Process {
+            pid,
+            cmdline: cmdline.to_string(),
+            ours: true,
+        }
+    }
+
+    /// Answers on `port` after `delay`; only port 4100 is a server
+    async fn slow_server(port: u16, delay: Duration) -> Result<Server> {
+        tokio::time::sleep(delay).await;
+        if port != 4100 {
+            anyhow::bail!("connection refused");
+        }
+        Ok(Server {
+            pid: 0,
+            port,
+            cwd: PathBuf::from("/"),
+        })
+    }
+
+    /// Discovery of `/` with `env_port` and processes on ports 4099 and
+    /// 4100, each probe taking `delay`
+    async fn discover_slowly(
+        env_port: Option<u16>,
+        delay: Duration,
+        deadline: Duration,
+    ) -> Result<Server, DiscoveryError> {
+        let trail = Trail::default();
+        let processes = || async {
+            vec![
+                process(11, "opencode --port 4099"),
+                process(12, "opencode"),
+                process(13, "opencode --port 4100"),
+            ]
+        };
+        let discovery = discover_with(
+            Path::new("/"),
+            None,
+            env_port,
+            processes,
+            |port, _| slow_server(port, delay),
+            &trail,
+        );
+        within(deadline, &trail, discovery).await
+    }
+
+    #[tokio::test]
+    async fn test_discovery_deadline() {
+        let probe = Duration::from_millis(80);
+        let server = discover_slowly(Some(4098), probe, Duration::from_secs(5))
+            .await
+            .unwrap();
+        assert_eq!((server.pid, server.port), (13, 4100));
+
+        // Stuck on the last process
+        let err = discover_slowly(Some(4098), probe, Duration::from_millis(200))
+            .await
+            .unwrap_err();
+        let step = |strategy: &str, progress: &str| Step {
+            strategy: strategy.to_string(),
+            progress: progress.to_string(),
+        };
+        assert_eq!(
+            err,
+            DiscoveryError::TimedOut {
+                after_ms: 200,
+                steps: vec![
+                    step(
+                        "$OPENCODE_PORT",
+                        "port 4098 didn't answer: connection refused"
+                    ),
+                    step("process scan", "probing port 4100 (pid 13), process 3 of 3"),
+                ],
+            }
+        );
+        assert_eq!(err.exit_code(), exit::EXIT_UNREACHABLE);
+        assert_eq!(
+            err.to_string(),
+            "No opencode server found within 0.2s:\n  \
+             $OPENCODE_PORT: port 4098 didn't answer: connection refused\n  \
+             process scan: probing port 4100 (pid 13), process 3 of 3\n\
+             Pass --port to skip discovery, or a longer --discovery-timeout"
+        );
+
+        let err = discover_slowly(None, Duration::from_secs(10), Duration::from_millis(20))
+            .await
+            .unwrap_err();
+        let DiscoveryError::TimedOut { steps, .. } = err else {
+            panic!("{:?}", err);
+        };
+        assert_eq!(
+            steps,
+            [step(
+                "process scan",
+                "probing port 4099 (pid 11), process 1 of 3"
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_auth_only_to_trusted_ports() {
+        // $OPENCODE_PORT is stale and the rest answer for another
+        // directory, so every port is probed
+        let probed = Mutex::new(Vec::new());
+        let validate = |port, with_auth| {
+            probed.lock().unwrap().push((port, with_auth));
+            async move {
+                if port == 4098 {
+                    anyhow::bail!("connection refused");
+                }
+                Ok(Server {
+                    pid: 0,
+                    port,
+                    cwd: PathBuf::from("/elsewhere"),
+                })
+            }
+        };
+        let processes = || async {
+            vec![
+                process(11, "opencode --port 4099"),
+                Process {
+                    ours: false,
+                    ..process(12, "opencode --port 4100")
+                },
+            ]
+        };
+        let trail = Trail::default();
+        let result = discover_with(
+            Path::new("/work"),
+            None,
+            Some(4098),
+            processes,
+            validate,
+            &trail,
+        )
+        .await;
+        assert!(matches!(result, Err(DiscoveryError::CwdMismatch { .. })));
+        // $OPENCODE_PORT and our own process get the token, another user's doesn't
+        assert_eq!(
+            *probed.lock().unwrap(),
+            [(4098, true), (4099, true), (4100, false)]
+        );
+
+        let result = discover_with(
+            Path::new("/work"),
+            Some(4097),
+            None,
+            || async { Vec::new() },
+            validate,
+            &trail,
+        )
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(probed.lock().unwrap().last(), Some(&(4097, true)));
+    }
+
+    #[test]
+    fn test_dirs_related_with_worktree() {
+        let root = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        let main = root.join("main");
+        let tree = root.join("feature");
+        std::fs::create_dir_all(main.join("src")).unwrap();
+        git(&main, &["init", "-q"]);
+        git(&main, &["commit", "-q", "--allow-empty", "-m", "init"]);
+        git(&main, &["worktree", "add", "-q", tree.to_str().unwrap()]);
+        let admin = main.join(".git/worktrees/feature");
+
+        let admin_maps_to_tree = normalize_dir(&admin) == tree.canonicalize().unwrap();
+        let admin_matches_tree = dirs_related(&admin, &tree);
+        let tree_matches_main = dirs_related(&tree, &main);
+        let subdir_matches_main = dirs_related(&main.join("src"), &main);
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert!(admin_maps_to_tree);
+        assert!(admin_matches_tree);
+        assert!(!tree_matches_main);
+        assert!(subdir_matches_main);
+    }
 }
@@ -0,0 +1,516 @@
+//! Server event stream handling
+//!
+//! Follows the opencode `/event` SSE stream to detect when a session goes idle
+//! after a prompt was sent, capturing the assistant's reply text on the way,
+//! and for `watch`. The events both need are decoded into [`Event`]; the
+//! rest are [`Event::Other`].
+//!
+//! A killed server can leave the stream half-open, with no more events and no
+//! error, so the server is probed alongside it ([`Keepalive`]).
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use futures::{Stream, StreamExt};
+use serde::Deserialize;
+
+/// Stream of decoded server events
+pub type EventStream = Pin<Box<dyn Stream<Item = Result<serde_json::Value>> + Send>>;
+
+/// A server event, as far as opencode-helix looks at it
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", content = "properties")]
+pub enum Event {
+    /// A message was created or changed (also while the reply streams)
+    #[serde(rename = "message.updated")]
+    MessageUpdated { info: MessageInfo },
+    /// A part of a message (text, tool call, ...) was created or changed
+    #[serde(rename = "message.part.updated")]
+    PartUpdated { part: Part },
+    /// A session finished working (older servers)
+    #[serde(rename = "session.idle")]
+    SessionIdle {
+        #[serde(rename = "sessionID")]
+        session_id: String,
+    },
+    /// A session's status changed
+    #[serde(rename = "session.status")]
+    SessionStatus {
+        #[serde(rename = "sessionID")]
+        session_id: String,
+        status: Status,
+    },
+    /// Any other event, or one missing fields
+    #[serde(other)]
+    Other,
+}
+
+impl Event {
+    /// Decode `value`; anything unexpected is [`Event::Other`]
+    pub fn parse(value: &serde_json::Value) -> Self {
+        Self::deserialize(value).unwrap_or(Self::Other)
+    }
+
+    /// Session the event belongs to, if it names one
+    pub fn session_id(&self) -> Option<&str> {
+        match self {
+            Self::MessageUpdated { info } => Some(&info.session_id),
+            Self::PartUpdated { part } => part.session_id.as_deref(),
+            Self::SessionIdle { session_id } | Self::SessionStatus { session_id, .. } => {
+                Some(session_id)
+            }
+            Self::Other => None,
+        }
+    }
+}
+
+/// The message of a `message.updated` event
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MessageInfo {
+    pub id: String,
+    #[serde(rename = "sessionID")]
+    pub session_id: String,
+    /// "user" or "assistant"
+    pub role: String,
+    #[serde(default)]
+    pub time: MessageTime,
+}
+
+/// When a message was created and completed (unix milliseconds)
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct MessageTime {
+    pub created: Option<f64>,
+    /// Set once the reply is complete
+    pub completed: Option<f64>,
+}
+
+/// The part of a `message.part.updated` event
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Part {
+    #[serde(default)]
+    pub id: String,
+    #[serde(rename = "messageID")]
+    pub message_id: String,
+    #[serde(rename = "sessionID")]
+    pub session_id: Option<String>,
+    /// "text", "tool", "reasoning", ...
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Text so far, for a text part
+    pub text: Option<String>,
+    /// Tool name, for a tool part
+    pub tool: Option<String>,
+    /// Progress of a tool call
+    pub state: Option<ToolState>,
+}
+
+/// Progress of a tool call
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ToolState {
+    /// "pending", "running", "completed" or "error"
+    pub status: String,
+    /// Arguments of the call (`filePath`, `command`, ...)
+    #[serde(default)]
+    pub input: serde_json::Value,
+}
+
+/// Status of a `session.status` event
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Status {
+    /// "idle", "busy", ...
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+/// Tracks events for one session until it goes idle
+#[derive(Debug, Default)]
+pub struct IdleWatcher {
+    /// Session being watched (locked onto the first assistant message if unset)
+    session: Option<String>,
+    /// Assistant message ids in the watched session, in the order they appeared
+    assistant_messages: Vec<String>,
+    /// Text parts of each assistant message, as (part id, latest text) in
+    /// the order they appeared
+    text_parts: HashMap<String, Vec<(String, String)>>,
+}
+
+impl IdleWatcher {
+    /// Watch `session`, or the first session that produces an assistant message
+    pub fn new(session: Option<String>) -> Self {
+        Self {
+            session,
+            ..Default::default()
+        }
+    }
+
+    /// Text of the last assistant message with any: its text parts joined
+    /// by blank lines
+    pub fn reply(&self) -> Option<String> {
+        self.assistant_messages
+            .iter()
+            .rev()
+            .find_map(|id| self.text_parts.get(id))
+            .map(|parts| {
+                let texts: Vec<&str> = parts.iter().map(|(_, text)| text.as_str()).collect();
+                texts.join("\n\n")
+            })
+    }
+
+    /// Feed an event; returns true once the watched session went idle
+    pub fn handle(&mut self, event: &Event) -> bool {
+        match event {
+            Event::MessageUpdated { info } => {
+                if info.role != "assistant" {
+                    return false;
+                }
+                if self.session.is_none() {
+                    self.session = Some(info.session_id.clone());
+                }
+                if self.session.as_deref() == Some(info.session_id.as_str())
+                    && !self.assistant_messages.contains(&info.id)
+                {
+                    self.assistant_messages.push(info.id.clone());
+                }
+                false
+            }
+            Event::PartUpdated { part } => {
+                let (true, Some(text)) = (
+                    self.assistant_messages.contains(&part.message_id) && part.kind == "text",
+                    &part.text,
+                ) else {
+                    return false;
+                };
+                // Text parts are sent whole as they grow
+                let parts = self.text_parts.entry(part.message_id.clone()).or_default();
+                match parts.iter_mut().find(|(id, _)| *id == part.id) {
+                    Some((_, latest)) => latest.clone_from(text),
+                    None => parts.push((part.id.clone(), text.clone())),
+                }
+                false
+            }
+            Event::SessionIdle { session_id } => self.is_watched(Some(session_id)),
+            Event::SessionStatus { session_id, status } if status.kind == "idle" => {
+                self.is_watched(Some(session_id))
+            }
+            _ => false,
+        }
+    }
+
+    /// Check if an event's session id is the watched session
+    fn is_watched(&self, session_id: Option<&str>) -> bool {
+        self.session.is_some() && self.session.as_deref() == session_id
+    }
+}
+
+/// How often the server is probed while waiting, and how many failed probes
+/// in a row mean it went away
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keepalive {
+    pub interval: Duration,
+    pub failures: u32,
+}
+
+impl Default for Keepalive {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(15),
+            failures: 2,
+        }
+    }
+}
+
+/// Wait until the session goes idle, returning the assistant's reply if seen
+///
+/// With a `keepalive`, `probe` runs every interval while events keep being
+/// read; after `failures` failed probes in a row the wait ends with a
+/// "server went away" error.
+pub async fn wait_for_idle<F, Fut>(
+    events: &mut EventStream,
+    session: Option<String>,
+    keepalive: Option<Keepalive>,
+    probe: F,
+) -> Result<Option<String>>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let mut watcher = IdleWatcher::new(session);
+    let idle = follow(events, keepalive, probe, |event| {
+        watcher.handle(&Event::parse(&event))
+    })
+    .await?;
+    if !idle {
+        bail!("Event stream closed before the session went idle");
+    }
+    Ok(watcher.reply())
+}
+
+/// Feed events to `handle` until it returns true (then true) or the stream
+/// ends (then false), probing the server as [`wait_for_idle`] does
+pub async fn follow<F, Fut>(
+    events: &mut EventStream,
+    keepalive: Option<Keepalive>,
+    mut probe: F,
+    mut handle: impl FnMut(serde_json::Value) -> bool,
+) -> Result<bool>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let mut ticker = keepalive.map(|k| {
+        let mut ticker =
+            tokio::time::interval_at(tokio::time::Instant::now() + k.interval, k.interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        ticker
+    });
+    // The probe in flight; events are still read while it runs
+    let mut probing: Option<Pin<Box<Fut>>> = None;
+    let mut failed = 0;
+
+    loop {
+        tokio::select! {
+            event = events.next() => match event {
+                Some(event) => {
+                    if handle(event?) {
+                        return Ok(true);
+                    }
+                }
+                None => return Ok(false),
+            },
+            _ = async { ticker.as_mut().expect("guarded by is_some").tick().await },
+                if ticker.is_some() && probing.is_none() =>
+            {
+                probing = Some(Box::pin(probe()));
+            }
+            result = async { probing.as_mut().expect("guarded by is_some").await },
+                if probing.is_some() =>
+            {
+                probing = None;
+                match result {
+                    Ok(()) => failed = 0,
+                    Err(e) => {
+                        failed += 1;
+                        let limit = keepalive.map_or(u32::MAX, |k| k.failures);
+                        if failed >= limit {
+                            bail!(
+                                "The opencode server went away: {} keepalive probes in a row failed (last: {:#})",
+                                failed,
+                                e
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use serde_json::json;
+
+    fn assistant_message(id: &str, session: &str) -> serde_json::Value {
+        json!({
+            "type": "message.updated",
+            "properties": { "info": { "id": id, "sessionID": session, "role": "assistant" } }
+        })
+    }
+
+    fn text_part(message: &str, text: &str) -> serde_json::Value {
+        json!({
+            "type": "message.part.updated",
+            "properties": { "part": { "messageID": message, "type": "text", "text": text } }
+        })
+    }
+
+    fn idle(session: &str) -> serde_json::Value {
+        json!({ "type": "session.idle", "properties": { "sessionID": session } })
+    }
+
+    #[test]
+    fn test_watcher_locks_onto_first_session() {
+        let mut watcher = IdleWatcher::new(None);
+        // Idle before any activity is ignored
+        assert!(!watcher.handle(&Event::parse(&idle("ses_a"))));
+        assert!(!watcher.handle(&Event::parse(&assistant_message("msg_1", "ses_a"))));
+        assert!(!watcher.handle(&Event::parse(&text_part("msg_1", "Hello"))));
+        assert!(!watcher.handle(&Event::parse(&text_part("msg_1", "Hello world"))));
+        assert!(!watcher.handle(&Event::parse(&idle("ses_b"))));
+        assert!(watcher.handle(&Event::parse(&idle("ses_a"))));
+        assert_eq!(watcher.reply().as_deref(), Some("Hello world"));
+    }
+
+    #[test]
+    fn test_watcher_joins_the_last_message() {
+        let part = |message: &str, id: &str, text: &str| {
+            json!({
+                "type": "message.part.updated",
+                "properties": { "part": {
+                    "id": id, "messageID": message, "type": "text", "text": text
+                } }
+            })
+        };
+        let mut watcher = IdleWatcher::new(Some("ses_a".to_string()));
+        for event in [
+            assistant_message("msg_1", "ses_a"),
+            part("msg_1", "prt_1", "Looking at the diff."),
+            assistant_message("msg_2", "ses_a"),
+            part("msg_2", "prt_2", "Fix"),
+            part("msg_2", "prt_3", "```"),
+            part("msg_2", "prt_2", "Fix the parser:"),
+            part("msg_2", "prt_3", "```\nfix: parser\n```"),
+            // An update to the first message doesn't make it the last
+            assistant_message("msg_1", "ses_a"),
+        ] {
+            assert!(!watcher.handle(&Event::parse(&event)));
+        }
+        assert_eq!(
+            watcher.reply().as_deref(),
+            Some("Fix the parser:\n\n```\nfix: parser\n```")
+        );
+    }
+
+    #[test]
+    fn test_watcher_filters_by_session() {
+        let mut watcher = IdleWatcher::new(Some("ses_b".to_string()));
+        assert!(!watcher.handle(&Event::parse(&assistant_message("msg_1", "ses_a"))));
+        assert!(!watcher.handle(&Event::parse(&text_part("msg_1", "other"))));
+        assert_eq!(watcher.reply(), None);
+
+        let status = json!({
+            "type": "session.status",
+            "properties": { "sessionID": "ses_b", "status": { "type": "idle" } }
+        });
+        assert!(watcher.handle(&Event::parse(&status)));
+    }
+
+    /// A stream that yields `events` and then stays open without ending
+    fn half_open(events: Vec<serde_json::Value>) -> EventStream {
+        Box::pin(
+            futures::stream::iter(events.into_iter().map(Ok)).chain(futures::stream::pending()),
+        )
+    }
+
+    const FAST: Keepalive = Keepalive {
+        interval: Duration::from_millis(10),
+        failures: 2,
+    };
+
+    #[tokio::test]
+    async fn test_wait_ends_when_server_goes_away() {
+        let mut events = half_open(vec![assistant_message("msg_1", "ses_a")]);
+        let probes = std::cell::Cell::new(0);
+        let probe = || {
+            probes.set(probes.get() + 1);
+            let n = probes.get();
+            async move {
+                // Answers twice, then is gone
+                if n <= 2 {
+                    Ok(())
+                } else {
+                    Err(anyhow!("connection refused"))
+                }
+            }
+        };
+        let error = wait_for_idle(&mut events, None, Some(FAST), probe)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("went away: 2 keepalive probes"), "{}", error);
+        assert!(error.contains("connection refused"), "{}", error);
+        assert_eq!(probes.get(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_wait_survives_a_single_failed_probe() {
+        let mut events: EventStream = Box::pin(
+            futures::stream::once(async {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                Ok(idle("ses_a"))
+            })
+            .boxed(),
+        );
+        let probes = std::cell::Cell::new(0);
+        // Every other probe fails, so two never fail in a row
+        let probe = || {
+            probes.set(probes.get() + 1);
+            let n = probes.get();
+            async move {
+                if n % 2 == 1 {
+                    Err(anyhow!("timeout"))
+                } else {
+                    Ok(())
+                }
+            }
+        };
+        let reply = wait_for_idle(&mut events, Some("ses_a".to_string()), Some(FAST), probe)
+            .await
+            .unwrap();
+        assert_eq!(reply, None);
+        assert!(probes.get() >= 3, "{}", probes.get());
+
+        // Events are read while a slow probe is still running
+        let mut events = half_open(vec![idle("ses_a")]);
+        let slow = || async {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Ok(())
+        };
+        let keepalive = Keepalive {
+            interval: Duration::from_millis(1),
+            failures: 1,
+        };
+        assert!(wait_for_idle(
+            &mut events,
+            Some("ses_a".to_string()),
+            Some(keepalive),
+            slow
+        )
+        .await
+        .is_ok());
+    }
+
+    #[test]
+    fn test_watcher_ignores_user_messages() {
+        let mut watcher = IdleWatcher::new(None);
+        let user = json!({
+            "type": "message.updated",
+            "properties": { "info": { "id": "msg_u", "sessionID": "ses_a", "role": "user" } }
+        });
+        assert!(!watcher.handle(&Event::parse(&user)));
+        assert!(!watcher.handle(&Event::parse(&text_part("msg_u", "prompt"))));
+        assert_eq!(watcher.reply(), None);
+    }
+
+    #[test]
+    fn test_parse_events() {
+        let tool = Event::parse(&json!({
+            "type": "message.part.updated",
+            "properties": { "part": {
+                "id": "prt_1", "messageID": "msg_1", "sessionID": "ses_a", "type": "tool",
+                "tool": "read",
+                "state": { "status": "running", "input": { "filePath": "src/main.rs" } }
+            } }
+        }));
+        let Event::PartUpdated { part } = &tool else {
+            panic!("{:?}", tool);
+        };
+        assert_eq!(part.tool.as_deref(), Some("read"));
+        assert_eq!(
+            part.state.as_ref().unwrap().input["filePath"],
+            "src/main.rs"
+        );
+        assert_eq!(tool.session_id(), Some("ses_a"));
+
+        // Unknown types, and known ones missing fields, are Other
+        let connected = json!({ "type": "server.connected", "properties": {} });
+        assert_eq!(Event::parse(&connected), Event::Other);
+        let broken = json!({ "type": "message.updated", "properties": { "info": {} } });
+        assert_eq!(Event::parse(&broken), Event::Other);
+        assert_eq!(Event::parse(&json!("text")), Event::Other);
+    }
+}
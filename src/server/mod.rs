@@ -1,7 +1,12 @@
 //! Server module for opencode communication
 
+pub mod auth;
 pub mod client;
 pub mod discovery;
+pub mod events;
+pub mod redial;
 
-pub use client::Client;
-pub use discovery::{discover_server, Server};
+pub use auth::{Auth, Token};
+pub use client::{Client, Feature, MessageBuilder, MessagePart, Model, PartialSend, PublishMode};
+pub use discovery::{discover_server, server_env, Server};
+pub use redial::Redial;
@@ -0,0 +1,174 @@
+//! Finding the server again after it restarted
+//!
+//! opencode listens on a new random port each time it starts, so a server
+//! that restarted while the dialog was open refuses the send. [`Redial`]
+//! then runs discovery once more, within [`REDIAL_TIMEOUT`], and points the
+//! client at the server it finds for the same project; the send is retried
+//! there. If none is found, the send fails as it would have.
+
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::client::Client;
+use super::discovery::{dirs_related, DiscoveryError, Server};
+
+/// How long discovery may take to find a restarted server
+pub const REDIAL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A client that follows the project's server to a new port, once
+pub struct Redial<F> {
+    client: Client,
+    /// Project directory the server was discovered for
+    cwd: PathBuf,
+    /// Discovery, run again when a send is refused
+    find: F,
+    /// Whether discovery was run again already
+    redialed: bool,
+}
+
+impl<F, FF> Redial<F>
+where
+    F: Fn() -> FF,
+    FF: Future<Output = Result<Server, DiscoveryError>>,
+{
+    pub fn new(client: Client, cwd: &Path, find: F) -> Self {
+        Self {
+            client,
+            cwd: cwd.to_path_buf(),
+            find,
+            redialed: false,
+        }
+    }
+
+    /// The client to send with, on the new port once the server moved
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// After a send failed with `error`: the port the server restarted on,
+    /// with the client moved to it, or None to give up with `error`
+    ///
+    /// Discovery runs again only the first time a connection is refused,
+    /// and only a server for the same project on another port counts.
+    pub async fn recover(&mut self, error: &anyhow::Error) -> Option<u16> {
+        if self.redialed || !refused(error) {
+            return None;
+        }
+        self.redialed = true;
+        let server = (self.find)().await.ok()?;
+        if server.port == self.client.port() || !dirs_related(&self.cwd, &server.cwd) {
+            return None;
+        }
+        self.client = self.client.moved_to(server.port);
+        Some(server.port)
+    }
+}
+
+/// Whether `error` comes from a connection the server didn't accept
+pub fn refused(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<reqwest::Error>()
+            .is_some_and(reqwest::Error::is_connect)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// A server that answers one request with 204 and then goes away,
+    /// handing back the request line
+    async fn one_shot() -> (u16, tokio::task::JoinHandle<String>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = Vec::new();
+            let mut chunk = [0u8; 1024];
+            // The body is short enough to arrive with the headers
+            while !String::from_utf8_lossy(&buf).contains("}]}") {
+                let n = stream.read(&mut chunk).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                buf.extend_from_slice(&chunk[..n]);
+            }
+            let response = "HTTP/1.1 204 No Content\r\ncontent-length: 0\r\n\r\n";
+            stream.write_all(response.as_bytes()).await.unwrap();
+            let request = String::from_utf8_lossy(&buf).to_string();
+            request.lines().next().unwrap_or_default().to_string()
+        });
+        (port, handle)
+    }
+
+    fn server(port: u16, cwd: &str) -> Server {
+        Server {
+            pid: 1,
+            port,
+            cwd: PathBuf::from(cwd),
+        }
+    }
+
+    async fn send(client: &Client) -> anyhow::Result<()> {
+        client.send_prompt("hi", None, false, true).await.map(drop)
+    }
+
+    #[tokio::test]
+    async fn test_follows_restarted_server() {
+        let (first, served) = one_shot().await;
+        let client = Client::new(first).with_session(Some("ses_1".to_string()));
+        let moved = std::sync::Mutex::new(None);
+        let mut redial = Redial::new(client, Path::new("/work/app"), || async {
+            moved
+                .lock()
+                .unwrap()
+                .map(|port| server(port, "/work/app"))
+                .ok_or(DiscoveryError::NoProcesses)
+        });
+        send(redial.client()).await.unwrap();
+        assert!(served.await.unwrap().starts_with("POST /session/ses_1/"));
+
+        // The first server is gone; the project's server is back on another port
+        let (second, served) = one_shot().await;
+        *moved.lock().unwrap() = Some(second);
+        let error = send(redial.client()).await.unwrap_err();
+        assert!(refused(&error), "{:#}", error);
+        assert_eq!(redial.recover(&error).await, Some(second));
+        assert_eq!(redial.client().port(), second);
+        assert_eq!(redial.client().session(), Some("ses_1"));
+        send(redial.client()).await.unwrap();
+        assert!(served.await.unwrap().starts_with("POST /session/ses_1/"));
+
+        // Only once
+        let error = send(redial.client()).await.unwrap_err();
+        assert_eq!(redial.recover(&error).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up() {
+        let (port, served) = one_shot().await;
+        served.abort();
+        let _ = served.await;
+        let client = Client::new(port).with_session(Some("ses_1".to_string()));
+        let error = send(&client).await.unwrap_err();
+
+        // Nothing found, or a server for another project
+        let none = || async { Err(DiscoveryError::NoProcesses) };
+        let mut redial = Redial::new(client.clone(), Path::new("/work/app"), none);
+        assert_eq!(redial.recover(&error).await, None);
+        let other = || async { Ok(server(port + 1, "/work/other")) };
+        let mut redial = Redial::new(client.clone(), Path::new("/work/app"), other);
+        assert_eq!(redial.recover(&error).await, None);
+
+        // An error other than a refused connection doesn't run discovery
+        let found = || async { Ok(server(port + 1, "/work/app")) };
+        let mut redial = Redial::new(client, Path::new("/work/app"), found);
+        let rejected = anyhow::anyhow!("Server rejected message for session ses_1: 500");
+        assert!(!refused(&rejected));
+        assert_eq!(redial.recover(&rejected).await, None);
+        assert_eq!(redial.recover(&error).await, Some(port + 1));
+    }
+}
@@ -0,0 +1,261 @@
+//! Editor keybinding snippets printed by `setup`
+//!
+//! The Helix bindings have to capture `%{buffer_name}` and friends before
+//! `:new` opens the scratch buffer, which makes them tedious to write by
+//! hand. The snippet is built from the clap definitions, so the flags it
+//! passes are the ones the binary actually accepts.
+
+use std::fmt::Write as _;
+
+use clap::Command;
+
+/// Where the bindings stash the editor state between the `:sh` and `:insert-output` steps
+pub const CACHE_DIR: &str = "~/.cache/helix";
+
+/// File the selection is piped to for `--selection-file`
+pub const SELECTION_FILE: &str = "opencode_selection.tmp";
+
+/// Helix variable, cache file and `Cli` field for the cursor context
+const CURSOR_CONTEXT: &[(&str, &str, &str)] = &[
+    ("buffer_name", "opencode_file", "file"),
+    ("cursor_line", "opencode_line", "line"),
+    ("cursor_column", "opencode_col", "column"),
+    ("workspace_directory", "opencode_cwd", "cwd"),
+    ("language", "opencode_lang", "language"),
+];
+
+/// Helix variable, cache file and `Cli` field for the selection context
+const SELECTION_CONTEXT: &[(&str, &str, &str)] = &[
+    ("buffer_name", "opencode_file", "file"),
+    (
+        "selection_line_start",
+        "opencode_sel_start",
+        "selection_start",
+    ),
+    ("selection_line_end", "opencode_sel_end", "selection_end"),
+    ("workspace_directory", "opencode_cwd", "cwd"),
+    ("language", "opencode_lang", "language"),
+];
+
+/// One key under the leader
+struct Binding {
+    key: char,
+    /// Comment line above the binding
+    description: &'static str,
+    /// Subcommand and its arguments
+    args: &'static [&'static str],
+}
+
+const NORMAL_BINDINGS: &[Binding] = &[
+    Binding {
+        key: 'i',
+        description: "Ask - Opens input prompt to type a custom question to AI",
+        args: &["ask"],
+    },
+    Binding {
+        key: 's',
+        description: "Select - Opens menu to choose from predefined prompts, commands, and agents",
+        args: &["select"],
+    },
+];
+
+const SELECT_BINDINGS: &[Binding] = &[
+    Binding {
+        key: 'e',
+        description: "Explain selection",
+        args: &["prompt", "explain"],
+    },
+    Binding {
+        key: 'r',
+        description: "Review selection",
+        args: &["prompt", "review"],
+    },
+];
+
+/// `config.toml` keybindings for Helix under `leader`
+///
+/// Normal mode gets ask and select with the cursor context; select mode
+/// pipes the selection to a temp file and sends explain or review with it.
+pub fn helix(cmd: &Command, leader: &str) -> String {
+    let mut cmd = cmd.clone();
+    cmd.build();
+    let leader_key = toml_string(leader);
+    let mut out = String::new();
+
+    let _ = writeln!(out, "# opencode-helix AI integration");
+    let _ = writeln!(
+        out,
+        "# Generated by `{} setup`; create the cache directory first: mkdir -p {}",
+        cmd.get_name(),
+        CACHE_DIR
+    );
+
+    let _ = writeln!(out, "\n[keys.normal.{}]", leader_key);
+    for binding in NORMAL_BINDINGS {
+        let steps = [
+            capture(CURSOR_CONTEXT),
+            ":new".to_string(),
+            insert_output(&cmd, binding.args, CURSOR_CONTEXT, false),
+        ];
+        write_binding(&mut out, leader, binding, &steps);
+    }
+
+    let _ = writeln!(
+        out,
+        "\n# Select mode: :pipe-to keeps quotes, newlines and special characters intact"
+    );
+    let _ = writeln!(out, "[keys.select.{}]", leader_key);
+    for binding in SELECT_BINDINGS {
+        let steps = [
+            capture(SELECTION_CONTEXT),
+            format!(":pipe-to cat > {}/{}", CACHE_DIR, SELECTION_FILE),
+            ":new".to_string(),
+            insert_output(&cmd, binding.args, SELECTION_CONTEXT, true),
+        ];
+        write_binding(&mut out, leader, binding, &steps);
+    }
+    out
+}
+
+fn write_binding(out: &mut String, leader: &str, binding: &Binding, steps: &[String]) {
+    let _ = writeln!(out, "# {}{} = {}", leader, binding.key, binding.description);
+    let _ = writeln!(out, "{} = [", binding.key);
+    for step in steps
+        .iter()
+        .map(String::as_str)
+        .chain([":buffer-close!", ":redraw"])
+    {
+        let _ = writeln!(out, "    {},", toml_string(step));
+    }
+    let _ = writeln!(out, "]");
+}
+
+/// `:sh` step that writes each Helix variable to its cache file
+fn capture(context: &[(&str, &str, &str)]) -> String {
+    let writes: Vec<String> = context
+        .iter()
+        .map(|(var, file, _)| format!("echo '%{{{}}}' > {}/{}", var, CACHE_DIR, file))
+        .collect();
+    format!(":sh {}", writes.join(" && "))
+}
+
+/// `:insert-output` step running the subcommand with the cached context
+fn insert_output(
+    cmd: &Command,
+    args: &[&str],
+    context: &[(&str, &str, &str)],
+    selection: bool,
+) -> String {
+    let mut line = format!(":insert-output {} {}", cmd.get_name(), args.join(" "));
+    for (_, file, field) in context {
+        if *field == "file" && selection {
+            let _ = write!(
+                line,
+                " {} {}/{}",
+                long_flag(cmd, "selection_file"),
+                CACHE_DIR,
+                SELECTION_FILE
+            );
+        }
+        let _ = write!(
+            line,
+            " {} %sh{{cat {}/{}}}",
+            long_flag(cmd, field),
+            CACHE_DIR,
+            file
+        );
+    }
+    line
+}
+
+/// `--name` of the `Cli` field `id`
+///
+/// Falls back to the field name in kebab case, which the snippet test then
+/// rejects, rather than failing at runtime.
+fn long_flag(cmd: &Command, id: &str) -> String {
+    let long = cmd
+        .get_arguments()
+        .find(|arg| arg.get_id() == id)
+        .and_then(|arg| arg.get_long())
+        .map(str::to_string);
+    format!("--{}", long.unwrap_or_else(|| id.replace('_', "-")))
+}
+
+/// TOML basic string
+fn toml_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Cli;
+    use clap::{CommandFactory, Parser};
+
+    /// argv of each `:insert-output` step, with `%sh{...}` expansions stubbed out
+    fn insert_output_argvs(snippet: &str) -> Vec<Vec<String>> {
+        snippet
+            .lines()
+            .filter_map(|line| {
+                let start = line.find(":insert-output ")? + ":insert-output ".len();
+                let end = line.rfind('"')?;
+                let mut command = line[start..end].to_string();
+                while let Some(open) = command.find("%sh{") {
+                    let close = open + command[open..].find('}')?;
+                    command.replace_range(open..=close, "1");
+                }
+                Some(command.split_whitespace().map(str::to_string).collect())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_helix_snippet_parses() {
+        let snippet = helix(&Cli::command(), ";");
+        let argvs = insert_output_argvs(&snippet);
+        assert_eq!(argvs.len(), NORMAL_BINDINGS.len() + SELECT_BINDINGS.len());
+
+        for argv in &argvs {
+            let cli =
+                Cli::try_parse_from(argv).unwrap_or_else(|e| panic!("{}\n{}", argv.join(" "), e));
+            assert_eq!(cli.file, Some("1".into()));
+            assert_eq!(cli.cwd, Some("1".into()));
+            assert_eq!(cli.language.as_deref(), Some("1"));
+        }
+
+        let cli = Cli::try_parse_from(&argvs[0]).unwrap();
+        assert!(matches!(cli.command, Some(crate::cli::Command::Ask { .. })));
+        assert_eq!((cli.line, cli.column), (Some(1), Some(1)));
+
+        let cli = Cli::try_parse_from(&argvs[2]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(crate::cli::Command::Prompt { ref name_or_text, .. }) if name_or_text == "explain"
+        ));
+        assert!(cli.selection_file.is_some());
+        assert_eq!((cli.selection_start, cli.selection_end), (Some(1), Some(1)));
+    }
+
+    #[test]
+    fn test_helix_snippet_leader() {
+        let snippet = helix(&Cli::command(), ";");
+        assert!(snippet.contains("[keys.normal.\";\"]\n"));
+        assert!(snippet.contains("[keys.select.\";\"]\n"));
+        assert!(snippet.contains("# ;e = Explain selection\ne = [\n"));
+        assert!(snippet.contains(":pipe-to cat > ~/.cache/helix/opencode_selection.tmp"));
+
+        // The leader is a TOML key, so quotes in it are escaped
+        let snippet = helix(&Cli::command(), "\"");
+        assert!(snippet.contains("[keys.normal.\"\\\"\"]\n"));
+    }
+}
@@ -0,0 +1,303 @@
+//! Reading and writing the state files, in plaintext or encrypted at rest
+//!
+//! Everything remembered between runs (the `resend` history, collapsed
+//! categories, usage stats and the `--queue` queue) goes through a
+//! [`StateStore`], picked once from the config by [`store`]:
+//!
+//! - [`Plain`] writes the files as they are (the default)
+//! - [`Encrypted`] (`encrypt_state = true`) seals each line with
+//!   XChaCha20-Poly1305, stored as base64 of the nonce and ciphertext. One
+//!   sealed record per line keeps appending a single write.
+//!
+//! The key is derived with Argon2id from a passphrase: `$OPENCODE_HELIX_STATE_KEY`
+//! for headless use, else one kept in the system keyring, created on first
+//! use. The salt is a random file in the state directory. Without the key
+//! (or with a different one) reading fails with "state unavailable" instead
+//! of returning the data, and nothing is written; `state clear` starts over.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::{anyhow, bail, Context, Result};
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+use crate::paths;
+
+/// Environment variable holding the passphrase, used instead of the keyring
+pub const KEY_ENV: &str = "OPENCODE_HELIX_STATE_KEY";
+
+/// Keyring entry of the passphrase
+const KEYRING_SERVICE: &str = "opencode-helix";
+const KEYRING_USER: &str = "state";
+
+/// Bytes of the random salt
+const SALT_LEN: usize = 16;
+/// Bytes of an XChaCha20-Poly1305 nonce, in front of each sealed line
+const NONCE_LEN: usize = 24;
+
+/// Reads and writes state files
+pub trait StateStore {
+    /// Text of `path`, None when there is no such file
+    fn read(&self, path: &Path) -> Result<Option<String>>;
+
+    /// Replace `path` with `text`, creating its directory
+    fn write(&self, path: &Path, text: &str) -> Result<()>;
+
+    /// Add `line` to the end of `path` in a single write, so concurrent
+    /// invocations don't interleave
+    fn append(&self, path: &Path, line: &str) -> Result<()>;
+}
+
+/// The store `encrypt_state` asks for
+pub fn store(encrypt: bool) -> Box<dyn StateStore> {
+    if encrypt {
+        Box::new(Encrypted::new(paths::state_dir().join("salt")))
+    } else {
+        Box::new(Plain)
+    }
+}
+
+/// Files as they are
+pub struct Plain;
+
+impl StateStore for Plain {
+    fn read(&self, path: &Path) -> Result<Option<String>> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Ok(Some(text)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        }
+    }
+
+    fn write(&self, path: &Path, text: &str) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            paths::ensure_dir(dir)?;
+        }
+        std::fs::write(path, text).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    fn append(&self, path: &Path, line: &str) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            paths::ensure_dir(dir)?;
+        }
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(format!("{}\n", line).as_bytes()))
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Files sealed line by line, with a key derived when first needed
+pub struct Encrypted {
+    /// Salt file for the key derivation
+    salt: PathBuf,
+    /// Passphrase, if not from the environment or keyring (tests)
+    passphrase: Option<String>,
+    /// The cipher, or why there is none
+    cipher: OnceLock<std::result::Result<XChaCha20Poly1305, String>>,
+}
+
+impl Encrypted {
+    /// Encrypt with the passphrase from the environment or the keyring
+    pub fn new(salt: PathBuf) -> Self {
+        Self {
+            salt,
+            passphrase: None,
+            cipher: OnceLock::new(),
+        }
+    }
+
+    /// Encrypt with `passphrase`
+    #[cfg(test)]
+    fn with_passphrase(salt: PathBuf, passphrase: &str) -> Self {
+        Self {
+            passphrase: Some(passphrase.to_string()),
+            ..Self::new(salt)
+        }
+    }
+
+    fn cipher(&self) -> Result<&XChaCha20Poly1305> {
+        self.cipher
+            .get_or_init(|| self.derive().map_err(|e| format!("{:#}", e)))
+            .as_ref()
+            .map_err(|e| anyhow!("state unavailable: {}", e))
+    }
+
+    fn derive(&self) -> Result<XChaCha20Poly1305> {
+        let passphrase = match &self.passphrase {
+            Some(passphrase) => passphrase.clone(),
+            None => passphrase()?,
+        };
+        let salt = salt(&self.salt)?;
+        let mut key = [0u8; 32];
+        argon2::Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|e| anyhow!("key derivation failed: {}", e))?;
+        Ok(XChaCha20Poly1305::new(&key.into()))
+    }
+
+    /// `line` sealed, as base64
+    fn seal(&self, line: &str) -> Result<String> {
+        let cipher = self.cipher()?;
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let sealed = cipher
+            .encrypt(&nonce, line.as_bytes())
+            .map_err(|_| anyhow!("encryption failed"))?;
+        let mut record = nonce.to_vec();
+        record.extend(sealed);
+        Ok(base64::engine::general_purpose::STANDARD.encode(record))
+    }
+
+    /// A line written by [`seal`](Self::seal), opened
+    fn open(cipher: &XChaCha20Poly1305, record: &str) -> Result<String> {
+        let record = base64::engine::general_purpose::STANDARD
+            .decode(record.trim())
+            .ok()
+            .filter(|record| record.len() > NONCE_LEN)
+            .context("not encrypted")?;
+        let (nonce, sealed) = record.split_at(NONCE_LEN);
+        let line = cipher
+            .decrypt(XNonce::from_slice(nonce), sealed)
+            .map_err(|_| anyhow!("wrong key"))?;
+        String::from_utf8(line).context("not text")
+    }
+}
+
+impl StateStore for Encrypted {
+    fn read(&self, path: &Path) -> Result<Option<String>> {
+        let Some(sealed) = Plain.read(path)? else {
+            return Ok(None);
+        };
+        let cipher = self.cipher()?;
+        let lines = sealed
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Self::open(cipher, line))
+            .collect::<Result<Vec<String>>>()
+            .with_context(|| format!("state unavailable: can't decrypt {}", path.display()))?;
+        Ok(Some(lines.join("\n")))
+    }
+
+    fn write(&self, path: &Path, text: &str) -> Result<()> {
+        let record = self.seal(text)?;
+        Plain.write(path, &format!("{}\n", record))
+    }
+
+    fn append(&self, path: &Path, line: &str) -> Result<()> {
+        let record = self.seal(line)?;
+        Plain.append(path, &record)
+    }
+}
+
+/// The passphrase: `$OPENCODE_HELIX_STATE_KEY`, else the keyring's, which is
+/// created the first time
+fn passphrase() -> Result<String> {
+    if let Some(key) = std::env::var(KEY_ENV).ok().filter(|key| !key.is_empty()) {
+        return Ok(key);
+    }
+    // On a thread of its own: the keyring backend runs an async runtime,
+    // which can't be started from inside ours
+    std::thread::spawn(keyring_passphrase)
+        .join()
+        .map_err(|_| anyhow!("the system keyring failed"))?
+}
+
+fn keyring_passphrase() -> Result<String> {
+    let entry = keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)
+        .context("no system keyring (set OPENCODE_HELIX_STATE_KEY instead)")?;
+    match entry.get_password() {
+        Ok(passphrase) => Ok(passphrase),
+        Err(keyring::Error::NoEntry) => {
+            let passphrase = base64::engine::general_purpose::STANDARD
+                .encode(XChaCha20Poly1305::generate_key(&mut OsRng));
+            entry
+                .set_password(&passphrase)
+                .context("can't store a key in the system keyring")?;
+            Ok(passphrase)
+        }
+        Err(e) => Err(e).context("no key in the system keyring (set OPENCODE_HELIX_STATE_KEY)"),
+    }
+}
+
+/// The salt in `path`, created if missing
+fn salt(path: &Path) -> Result<Vec<u8>> {
+    match std::fs::read(path) {
+        Ok(salt) if salt.len() == SALT_LEN => return Ok(salt),
+        Ok(_) => bail!("{} is damaged", path.display()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
+    let salt: Vec<u8> = XChaCha20Poly1305::generate_nonce(&mut OsRng)[..SALT_LEN].to_vec();
+    if let Some(dir) = path.parent() {
+        paths::ensure_dir(dir)?;
+    }
+    std::fs::write(path, &salt).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(salt)
+}
+
+/// Delete all state: the state directory, the queue and, with `keyring`,
+/// the keyring entry (left alone while `$OPENCODE_HELIX_STATE_KEY` is the key)
+///
+/// Returns the directories removed.
+pub fn clear(keyring: bool) -> Result<Vec<PathBuf>> {
+    let mut removed = Vec::new();
+    for dir in [paths::state_dir(), crate::queue::queue_dir()] {
+        match std::fs::remove_dir_all(&dir) {
+            Ok(()) => removed.push(dir),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("Failed to delete {}", dir.display())),
+        }
+    }
+    if keyring && std::env::var_os(KEY_ENV).is_none() {
+        std::thread::spawn(|| {
+            let entry = keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)?;
+            match entry.delete_credential() {
+                Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+                Err(e) => Err(e),
+            }
+        })
+        .join()
+        .map_err(|_| anyhow!("the system keyring failed"))?
+        .context("Failed to delete the key from the system keyring")?;
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypted_round_trip() {
+        let dir = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        let store = Encrypted::with_passphrase(dir.join("salt"), "correct horse");
+        let path = dir.join("last/entry.json");
+        assert_eq!(store.read(&path).unwrap(), None);
+
+        store.write(&path, "{\"text\":\"review @this\"}").unwrap();
+        let stats = dir.join("stats.jsonl");
+        store.append(&stats, "one").unwrap();
+        store.append(&stats, "two").unwrap();
+        let sealed = std::fs::read_to_string(&path).unwrap();
+        assert!(!sealed.contains("review"), "{}", sealed);
+        assert_eq!(std::fs::read_to_string(&stats).unwrap().lines().count(), 2);
+        assert_eq!(
+            store.read(&path).unwrap().as_deref(),
+            Some("{\"text\":\"review @this\"}")
+        );
+        assert_eq!(store.read(&stats).unwrap().as_deref(), Some("one\ntwo"));
+
+        // Another key, or a plaintext file, is unavailable rather than garbage
+        let wrong = Encrypted::with_passphrase(dir.join("salt"), "battery staple");
+        let error = format!("{:#}", wrong.read(&path).unwrap_err());
+        assert!(error.contains("state unavailable"), "{}", error);
+        Plain.write(&path, "{}").unwrap();
+        assert!(store.read(&path).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
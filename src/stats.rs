@@ -0,0 +1,336 @@
+//! Opt-in local usage stats (`stats = true` in the config)
+//!
+//! Each invocation appends one JSON line to
+//! `$XDG_STATE_HOME/opencode-helix/stats.jsonl` (through the [`StateStore`]),
+//! which `stats` summarizes.
+//! This module makes no network calls: the stats never leave the machine.
+//! Recording is best-effort, so a read-only or full disk never fails a send.
+
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::state::StateStore;
+
+/// Step names used for free text rather than a named prompt
+const UNNAMED_STEPS: [&str; 3] = ["text", "ask", "select"];
+
+/// Prompts listed in the report
+const TOP_PROMPTS: usize = 5;
+
+/// How an invocation ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Cancelled,
+    Failed,
+}
+
+/// One invocation, as a line of the stats file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    /// RFC 3339 UTC timestamp
+    pub timestamp: String,
+    /// Subcommand name
+    pub command: String,
+    /// Project (working) directory
+    pub project: PathBuf,
+    /// Named prompt that was sent, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    /// Bytes sent after placeholder expansion
+    pub expanded: usize,
+    pub status: Status,
+    pub duration_ms: u64,
+    /// Time taken to find the server, if it was looked for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discovery_ms: Option<u64>,
+}
+
+/// What an invocation did, filled in as it runs
+#[derive(Debug, Default)]
+pub struct Usage {
+    prompt: RefCell<Option<String>>,
+    expanded: Cell<usize>,
+    discovery_ms: Cell<Option<u64>>,
+}
+
+impl Usage {
+    /// Note the prompt picked by name (e.g. from the select menu)
+    pub fn prompt(&self, name: &str) {
+        self.prompt
+            .borrow_mut()
+            .get_or_insert_with(|| name.to_string());
+    }
+
+    /// Note a sent step and its expanded size
+    pub fn sent(&self, step: &str, expanded: usize) {
+        if !UNNAMED_STEPS.contains(&step) {
+            self.prompt(step);
+        }
+        self.expanded.set(self.expanded.get() + expanded);
+    }
+
+    /// Note how long server discovery took
+    pub fn discovered(&self, elapsed: Duration) {
+        self.discovery_ms.set(Some(elapsed.as_millis() as u64));
+    }
+
+    /// The stats line for this invocation
+    pub fn record(
+        &self,
+        command: &str,
+        project: &Path,
+        status: Status,
+        elapsed: Duration,
+    ) -> Record {
+        Record {
+            timestamp: crate::report::timestamp(),
+            command: command.to_string(),
+            project: project.to_path_buf(),
+            prompt: self.prompt.borrow().clone(),
+            expanded: self.expanded.get(),
+            status,
+            duration_ms: elapsed.as_millis() as u64,
+            discovery_ms: self.discovery_ms.get(),
+        }
+    }
+}
+
+/// Stats file (`$XDG_STATE_HOME/opencode-helix/stats.jsonl`)
+pub fn stats_path() -> PathBuf {
+    crate::paths::state_dir().join("stats.jsonl")
+}
+
+/// Append a record to the stats file
+pub fn append(store: &dyn StateStore, path: &Path, record: &Record) -> Result<()> {
+    store.append(path, &serde_json::to_string(record)?)
+}
+
+/// Read the stats file, skipping lines that don't parse (e.g. a torn write)
+pub fn read(store: &dyn StateStore, path: &Path) -> Result<Vec<Record>> {
+    let Some(text) = store.read(path)? else {
+        return Ok(Vec::new());
+    };
+    Ok(text
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Empty the stats file, if there is one
+pub fn clear(path: &Path) -> Result<()> {
+    match std::fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .open(path)
+    {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e).with_context(|| format!("Failed to clear {}", path.display())),
+    }
+}
+
+/// Totals over a set of records
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Summary {
+    runs: usize,
+    ok: usize,
+    failed: usize,
+    cancelled: usize,
+    discovery_ms: u64,
+    discoveries: u64,
+    prompts: BTreeMap<String, usize>,
+}
+
+impl Summary {
+    fn add(&mut self, record: &Record) {
+        self.runs += 1;
+        match record.status {
+            Status::Ok => self.ok += 1,
+            Status::Failed => self.failed += 1,
+            Status::Cancelled => self.cancelled += 1,
+        }
+        if let Some(ms) = record.discovery_ms {
+            self.discovery_ms += ms;
+            self.discoveries += 1;
+        }
+        if let Some(ref prompt) = record.prompt {
+            *self.prompts.entry(prompt.clone()).or_default() += 1;
+        }
+    }
+
+    /// "96% succeeded"; cancelled runs count as neither
+    fn success_rate(&self) -> String {
+        match self.ok + self.failed {
+            0 => "nothing sent".to_string(),
+            attempts => format!("{}% succeeded", self.ok * 100 / attempts),
+        }
+    }
+
+    /// Average discovery time, if the server was looked for
+    fn discovery(&self) -> Option<u64> {
+        (self.discoveries > 0).then(|| self.discovery_ms / self.discoveries)
+    }
+
+    /// Most used prompts, ties broken by name
+    fn top_prompts(&self) -> Vec<(&str, usize)> {
+        let mut prompts: Vec<(&str, usize)> = self
+            .prompts
+            .iter()
+            .map(|(name, count)| (name.as_str(), *count))
+            .collect();
+        prompts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        prompts.truncate(TOP_PROMPTS);
+        prompts
+    }
+}
+
+/// Summarize records into the `stats` report
+pub fn report(records: &[Record]) -> String {
+    let Some(first) = records.first() else {
+        return "No stats recorded yet (set `stats = true` in the config to record them)"
+            .to_string();
+    };
+
+    let mut overall = Summary::default();
+    let mut projects: BTreeMap<&Path, Summary> = BTreeMap::new();
+    for record in records {
+        overall.add(record);
+        projects.entry(&record.project).or_default().add(record);
+    }
+
+    let mut lines = vec![format!(
+        "{} runs since {}, {} ({} failed, {} cancelled)",
+        overall.runs,
+        first.timestamp.get(..10).unwrap_or(&first.timestamp),
+        overall.success_rate(),
+        overall.failed,
+        overall.cancelled
+    )];
+    if let Some(ms) = overall.discovery() {
+        lines.push(format!("Average discovery: {} ms", ms));
+    }
+
+    let top = overall.top_prompts();
+    if !top.is_empty() {
+        lines.push(String::new());
+        lines.push("Top prompts:".to_string());
+        let width = top.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        for (name, count) in top {
+            lines.push(format!("  {:width$}  {}", name, count));
+        }
+    }
+
+    lines.push(String::new());
+    lines.push("By project:".to_string());
+    for (project, summary) in &projects {
+        let mut line = format!(
+            "  {}: {} runs, {}",
+            project.display(),
+            summary.runs,
+            summary.success_rate()
+        );
+        if let Some(ms) = summary.discovery() {
+            line.push_str(&format!(", discovery {} ms", ms));
+        }
+        if let Some((name, _)) = summary.top_prompts().first() {
+            line.push_str(&format!(", mostly {}", name));
+        }
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Plain;
+
+    fn record(project: &str, prompt: Option<&str>, status: Status, discovery_ms: u64) -> Record {
+        Record {
+            timestamp: "2026-10-01T09:00:00.000Z".to_string(),
+            command: "prompt".to_string(),
+            project: PathBuf::from(project),
+            prompt: prompt.map(str::to_string),
+            expanded: 120,
+            status,
+            duration_ms: 300,
+            discovery_ms: Some(discovery_ms),
+        }
+    }
+
+    #[test]
+    fn test_usage_keeps_first_named_prompt() {
+        let usage = Usage::default();
+        usage.sent("text", 10);
+        usage.sent("review", 20);
+        usage.sent("tests", 5);
+        let record = usage.record("prompt", Path::new("/p"), Status::Ok, Duration::ZERO);
+        assert_eq!(record.prompt.as_deref(), Some("review"));
+        assert_eq!(record.expanded, 35);
+        assert_eq!(record.discovery_ms, None);
+
+        let picked = Usage::default();
+        picked.prompt("explain");
+        picked.sent("select", 10);
+        let record = picked.record("select", Path::new("/p"), Status::Ok, Duration::ZERO);
+        assert_eq!(record.prompt.as_deref(), Some("explain"));
+    }
+
+    #[test]
+    fn test_append_read_clear() {
+        let dir = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("stats.jsonl");
+        assert!(read(&Plain, &path).unwrap().is_empty());
+        assert!(clear(&path).is_ok());
+
+        let first = record("/a", Some("explain"), Status::Ok, 30);
+        append(&Plain, &path, &first).unwrap();
+        // A torn line is skipped
+        std::fs::write(
+            &path,
+            format!("{}\n{{\"timest\n", serde_json::to_string(&first).unwrap()),
+        )
+        .unwrap();
+        append(&Plain, &path, &record("/a", None, Status::Failed, 50)).unwrap();
+        let records = read(&Plain, &path).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0], first);
+
+        clear(&path).unwrap();
+        assert!(read(&Plain, &path).unwrap().is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_report() {
+        assert!(report(&[]).starts_with("No stats recorded yet"));
+
+        let records = [
+            record("/a", Some("explain"), Status::Ok, 30),
+            record("/a", Some("review"), Status::Ok, 50),
+            record("/a", Some("explain"), Status::Failed, 40),
+            record("/b", Some("review"), Status::Ok, 20),
+            record("/b", Some("review"), Status::Cancelled, 10),
+        ];
+        assert_eq!(
+            report(&records),
+            "5 runs since 2026-10-01, 75% succeeded (1 failed, 1 cancelled)\n\
+             Average discovery: 30 ms\n\
+             \n\
+             Top prompts:\n\
+             \x20 review   3\n\
+             \x20 explain  2\n\
+             \n\
+             By project:\n\
+             \x20 /a: 3 runs, 66% succeeded, discovery 40 ms, mostly explain\n\
+             \x20 /b: 2 runs, 100% succeeded, discovery 15 ms, mostly review"
+        );
+    }
+}
@@ -0,0 +1,160 @@
+//! Startup cleanup of temp files left behind with source code in them
+//!
+//! The `setup` keybindings pipe the selection to [`setup::SELECTION_FILE`]
+//! in Helix's cache directory, and Ctrl+G drafts the prompt in an
+//! `opencode-helix-<uuid>.md` file in the temp dir. Both are deleted once
+//! read, but a crash before then, or a mount that doesn't allow deleting,
+//! leaves them behind. Each run removes those older than
+//! `temp_file_max_age_hours` (default 24).
+//!
+//! Only regular files owned by the user running us, with exactly those
+//! names, are ever touched: not symlinks, directories, or anyone else's
+//! files that happen to look alike.
+
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::setup;
+
+/// Age past which a left-behind file is removed, unless the config says otherwise
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Start of the Ctrl+G draft files (`opencode-helix-<uuid>.md`)
+pub const DRAFT_PREFIX: &str = "opencode-helix-";
+
+/// End of the Ctrl+G draft files
+pub const DRAFT_SUFFIX: &str = ".md";
+
+/// What a cleanup pass did
+#[derive(Debug, Default)]
+pub struct Cleanup {
+    pub removed: Vec<PathBuf>,
+    /// Files that should have gone but couldn't be deleted
+    pub failed: Vec<(PathBuf, io::Error)>,
+}
+
+/// Whether `name` is one of the temp files we leave behind
+pub fn is_ours(name: &str) -> bool {
+    if name == setup::SELECTION_FILE {
+        return true;
+    }
+    name.strip_prefix(DRAFT_PREFIX)
+        .and_then(|rest| rest.strip_suffix(DRAFT_SUFFIX))
+        .is_some_and(|id| uuid::Uuid::parse_str(id).is_ok())
+}
+
+/// Directories the temp files are left in: the temp dir and, with a home
+/// directory, the keybindings' cache directory
+pub fn dirs() -> Vec<PathBuf> {
+    let mut dirs = vec![std::env::temp_dir()];
+    let cache = setup::CACHE_DIR.strip_prefix("~/");
+    if let (Some(cache), Some(home)) = (cache, std::env::var_os("HOME")) {
+        dirs.push(PathBuf::from(home).join(cache));
+    }
+    dirs
+}
+
+/// Remove our files in `dir` owned by `uid` and last modified more than
+/// `max_age` before `now`
+///
+/// A directory that can't be read is skipped; nothing here fails the run.
+pub fn clean(dir: &Path, max_age: Duration, now: SystemTime, uid: u32) -> Cleanup {
+    let mut cleanup = Cleanup::default();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return cleanup;
+    };
+    for entry in entries.flatten() {
+        if !entry.file_name().to_str().is_some_and(is_ours) {
+            continue;
+        }
+        // Not followed: a symlink is never ours
+        let Ok(metadata) = entry.path().symlink_metadata() else {
+            continue;
+        };
+        let old = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .is_some_and(|age| age > max_age);
+        if !metadata.is_file() || metadata.uid() != uid || !old {
+            continue;
+        }
+        match fs::remove_file(entry.path()) {
+            Ok(()) => cleanup.removed.push(entry.path()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => cleanup.failed.push((entry.path(), e)),
+        }
+    }
+    cleanup
+}
+
+/// [`clean`] every one of [`dirs`] of this user's files
+pub fn clean_all(max_age: Duration) -> Cleanup {
+    let uid = unsafe { libc::geteuid() };
+    let now = SystemTime::now();
+    let mut all = Cleanup::default();
+    for dir in dirs() {
+        let cleanup = clean(&dir, max_age, now, uid);
+        all.removed.extend(cleanup.removed);
+        all.failed.extend(cleanup.failed);
+    }
+    all
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_ours() {
+        let draft = format!("opencode-helix-{}.md", uuid::Uuid::new_v4());
+        assert!(is_ours(&draft));
+        assert!(is_ours("opencode_selection.tmp"));
+
+        for name in [
+            "opencode-helix-notes.md",
+            "opencode-helix-",
+            "opencode_selection.tmp.bak",
+            "opencode_file",
+            "my-opencode_selection.tmp",
+        ] {
+            assert!(!is_ours(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_clean() {
+        let dir = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let draft = format!("opencode-helix-{}.md", uuid::Uuid::new_v4());
+        for name in [draft.as_str(), "opencode_selection.tmp", "notes.md"] {
+            fs::write(dir.join(name), "fn secret() {}").unwrap();
+        }
+        // Named like ours, but a directory and a symlink
+        let other = format!("opencode-helix-{}.md", uuid::Uuid::new_v4());
+        fs::create_dir(dir.join(&other)).unwrap();
+        let link = format!("opencode-helix-{}.md", uuid::Uuid::new_v4());
+        std::os::unix::fs::symlink(dir.join("notes.md"), dir.join(&link)).unwrap();
+
+        let uid = fs::metadata(&dir).unwrap().uid();
+        let day = Duration::from_secs(24 * 60 * 60);
+        let now = SystemTime::now();
+        // Nothing is old enough yet, and someone else's files are never touched
+        assert!(clean(&dir, day, now, uid).removed.is_empty());
+        let later = now + 2 * day;
+        assert!(clean(&dir, day, later, uid + 1).removed.is_empty());
+
+        let mut removed = clean(&dir, day, later, uid).removed;
+        removed.sort();
+        let mut expected = vec![dir.join(&draft), dir.join("opencode_selection.tmp")];
+        expected.sort();
+        assert_eq!(removed, expected);
+        for name in ["notes.md", &other, &link] {
+            assert!(dir.join(name).symlink_metadata().is_ok(), "{}", name);
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
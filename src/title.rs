@@ -0,0 +1,53 @@
+//! Titles for sessions, from the prompt that starts them
+//!
+//! A session opencode hasn't titled yet shows as "New session - <time>" in
+//! its list. The first prompt sent to one with `--session` names it instead:
+//! its first words, without the placeholders (`@this`, `@reg:a`, an agent
+//! mention), cut to fit a list row. `--title` picks the title instead, and
+//! `--no-auto-title` leaves untitled sessions alone.
+
+use crate::util::display_truncate;
+
+/// Words of the prompt a title keeps
+const TITLE_WORDS: usize = 8;
+
+/// Widest title, in terminal columns
+const TITLE_COLS: usize = 60;
+
+/// Title for a session started with `prompt`; None when no words are left
+pub fn from_prompt(prompt: &str) -> Option<String> {
+    let words: Vec<&str> = prompt
+        .split_whitespace()
+        .filter(|word| !word.starts_with('@'))
+        .take(TITLE_WORDS)
+        .collect();
+    if words.is_empty() {
+        return None;
+    }
+    Some(display_truncate(&words.join(" "), TITLE_COLS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_prompt() {
+        assert_eq!(
+            from_prompt("Explain @this and\n\n  how it\tis used").as_deref(),
+            Some("Explain and how it is used")
+        );
+        assert_eq!(
+            from_prompt("@build fix the failing test in @reg:a, then run the whole suite again")
+                .as_deref(),
+            Some("fix the failing test in then run the")
+        );
+        assert_eq!(from_prompt("@this @selection:full"), None);
+        assert_eq!(from_prompt("  \n"), None);
+
+        // Cut to the width, wide chars included
+        let title = from_prompt(&"日本語のテキスト ".repeat(8)).unwrap();
+        assert!(title.ends_with('…'), "{}", title);
+        assert!(unicode_width::UnicodeWidthStr::width(title.as_str()) <= TITLE_COLS);
+    }
+}
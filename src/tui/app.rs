@@ -1,24 +1,54 @@
 //! Main TUI application
 
 use anyhow::Result;
-use crossterm::{
-    event::{KeyCode, KeyEvent, KeyModifiers},
-    terminal::{disable_raw_mode, enable_raw_mode},
-};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use ratatui::{
     prelude::*,
-    widgets::{Block, Borders, Clear, Paragraph},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
 };
-use std::fs::File;
-use std::io::Read;
-use std::os::unix::io::AsRawFd;
-use std::time::{Duration, Instant};
-
-use crate::context::Context;
-
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::mpsc::Receiver;
+use std::time::Instant;
+use unicode_width::UnicodeWidthStr;
+
+use crate::attach::{self, Pasted};
+use crate::config::{
+    ActionKey, CursorShape, CursorStyle, QuickAction, TokenBudget, DEFAULT_TAB_WIDTH,
+};
+use crate::context::{Context, Known, PlaceholderKind};
+use crate::menu::{Refresh, SelectItem};
+
+use crate::tui::backend::{Backend, TtyBackend};
+use crate::tui::clipboard::Toast;
+use crate::tui::counter::{Level, PromptCounter};
+use crate::tui::diff;
+use crate::tui::editor;
+use crate::tui::effects::{self, Flash, OpenAnimation};
+use crate::tui::help;
+use crate::tui::highlight;
+use crate::tui::keymap::{self, Command, When};
+use crate::tui::markdown;
+use crate::tui::previews::{Compute, Previews};
+use crate::tui::readline::{self, Case};
+use crate::tui::redraw::{Redraw, ACTIVE_POLL};
+use crate::tui::select::{Row as SelectRow, SelectState};
+use crate::tui::strings::{self, Strings};
+use crate::tui::target::Target;
 use crate::tui::theme::{Theme, ThemeKind};
+use crate::tui::typos;
+use crate::tui::wrap::{
+    char_to_visual_pos, columns, cursor_to_visual_pos, expand_tabs, scroll_to_row,
+    visual_pos_to_cursor, wrap_into, InputScroll, WrappedLine,
+};
+use crate::util::{display_truncate, size_label};
+
+/// Number of selection lines previewed in the placeholders panel
+const SELECTION_PREVIEW_LINES: usize = 3;
 
-const DEBUG_LOG_PATH: &str = "/tmp/opencode-helix-debug.log";
+/// Number of selection lines shown under the expanded context hint
+const HINT_PREVIEW_LINES: usize = 8;
 
 /// Find the @word being typed at cursor position
 /// Returns (start_position, partial_word) if cursor is within or right after an @word
@@ -46,6 +76,17 @@ fn filter_placeholders<'a>(partial: &str, placeholders: &[&'a str]) -> Vec<&'a s
         .collect()
 }
 
+/// Keys the select menu takes as filter text (or Backspace), which don't
+/// need its rows: not `j`/`k`, nor `h`/`l`/`e`/`?` on an empty filter
+fn is_filter_edit(key: &KeyEvent, filter_empty: bool) -> bool {
+    let command = keymap::lookup(keymap::SELECT_BINDINGS, key, |when| match when {
+        When::Always => true,
+        When::EmptyFilter => filter_empty,
+        _ => false,
+    });
+    matches!(command, Some(Command::Insert | Command::Backspace))
+}
+
 /// Multi-line input helper: convert flat cursor position to (line, column)
 fn cursor_to_line_col(text: &str, pos: usize) -> (usize, usize) {
     let mut line = 0;
@@ -72,9 +113,7 @@ fn line_col_to_cursor(text: &str, target_line: usize, target_col: usize) -> usiz
     for (i, c) in text.char_indices() {
         if current_line == target_line {
             // We're on the target line, find the column
-            let line_end = text[i..].find('\n').map(|p| i + p).unwrap_or(text.len());
-            let line_len = line_end - i;
-            return i + target_col.min(line_len);
+            return column_offset(text, i, target_col);
         }
         if c == '\n' {
             current_line += 1;
@@ -84,12 +123,19 @@ fn line_col_to_cursor(text: &str, target_line: usize, target_col: usize) -> usiz
 
     // If target_line is beyond the last line, return end of text
     if current_line == target_line {
-        let line_len = text.len() - line_start;
-        return line_start + target_col.min(line_len);
+        return column_offset(text, line_start, target_col);
     }
     text.len()
 }
 
+/// Byte offset of character `col` of the line starting at `line_start`,
+/// or of the line's end if it is shorter
+fn column_offset(text: &str, line_start: usize, col: usize) -> usize {
+    let line = &text[line_start..];
+    let line = &line[..line.find('\n').unwrap_or(line.len())];
+    line_start + line.char_indices().nth(col).map_or(line.len(), |(i, _)| i)
+}
+
 /// Get the length of a specific line (without newline)
 fn get_line_length(text: &str, line_idx: usize) -> usize {
     text.lines().nth(line_idx).map(|l| l.len()).unwrap_or(0)
@@ -104,147 +150,216 @@ fn count_lines(text: &str) -> usize {
     }
 }
 
-/// Represents a visual line after soft wrapping
-#[derive(Debug, Clone)]
-struct WrappedLine {
-    /// The text content of this visual line
-    text: String,
-    /// The logical line index this belongs to
-    logical_line: usize,
-    /// Whether this is the first visual line of the logical line
-    is_first: bool,
-    /// Start position in the original text (byte offset)
-    start_pos: usize,
+/// Right-aligned title naming the target server
+/// Dim normally, warning-colored when the file is outside the server's cwd
+fn target_title(
+    target: Option<&Target>,
+    theme: &Theme,
+    dialog_width: u16,
+    title_width: usize,
+) -> Line<'static> {
+    let Some(target) = target else {
+        return Line::default();
+    };
+    // Leave room for the borders, the main title and a gap
+    let room = (dialog_width as usize).saturating_sub(title_width + 6);
+    let color = if target.mismatch {
+        theme.warning
+    } else {
+        theme.dim
+    };
+    Line::from(Span::styled(
+        format!(" {} ", target.label(room.saturating_sub(2))),
+        Style::default().fg(color),
+    ))
+    .right_aligned()
 }
 
-/// Wrap text to fit within a given width, respecting logical line breaks
-fn wrap_text(text: &str, width: usize, prefix_width: usize) -> Vec<WrappedLine> {
-    let mut wrapped = Vec::new();
-    let mut byte_offset = 0;
-
-    for (logical_line, line) in text.split('\n').enumerate() {
-        if line.is_empty() {
-            // Empty line
-            wrapped.push(WrappedLine {
-                text: String::new(),
-                logical_line,
-                is_first: true,
-                start_pos: byte_offset,
-            });
-        } else {
-            // Calculate effective width (first line has prompt, others have indent)
-            let effective_width = width.saturating_sub(prefix_width);
-            if effective_width == 0 {
-                // Fallback if width is too small
-                wrapped.push(WrappedLine {
-                    text: line.to_string(),
-                    logical_line,
-                    is_first: true,
-                    start_pos: byte_offset,
-                });
-            } else {
-                let mut remaining = line;
-                let mut is_first = true;
-                let mut line_byte_offset = byte_offset;
-
-                while !remaining.is_empty() {
-                    // Find break point
-                    let break_at = if remaining.chars().count() <= effective_width {
-                        remaining.len()
-                    } else {
-                        // Find the byte position for the character at effective_width
-                        remaining
-                            .char_indices()
-                            .nth(effective_width)
-                            .map(|(i, _)| i)
-                            .unwrap_or(remaining.len())
-                    };
+/// Spans for a line of text with the cursor at byte `cursor` (None: not on this line)
+/// With `CursorStyle::Reversed` the cell under the cursor (a space at the end of
+/// the line) is drawn reversed; `Block` appends a glyph; `Terminal` draws nothing.
+/// Tabs are drawn as spaces to the next multiple of `tab_width`.
+fn cursor_line_spans<'a>(
+    text: &'a str,
+    style: Style,
+    cursor: Option<usize>,
+    cursor_style: CursorStyle,
+    visible: bool,
+    tab_width: usize,
+    theme: &Theme,
+) -> Vec<Span<'a>> {
+    let expanded = expand_tabs(text, 0, tab_width);
+    let Some(col) = cursor else {
+        return vec![Span::styled(expanded, style)];
+    };
+    match cursor_style {
+        CursorStyle::Block => {
+            let cursor_char = if visible { "█" } else { " " };
+            vec![
+                Span::styled(expanded, style),
+                Span::styled(cursor_char, Style::default().fg(theme.primary)),
+            ]
+        }
+        CursorStyle::Reversed if visible => {
+            let (before, rest) = text.split_at(col);
+            let under_len = rest.chars().next().map_or(0, char::len_utf8);
+            let (under, after) = rest.split_at(under_len);
+            let under_at = columns(before, tab_width);
+            let under = match under {
+                "" => Cow::Borrowed(" "),
+                under => expand_tabs(under, under_at, tab_width),
+            };
+            let after_at = under_at + under.width();
+            let reversed = style.add_modifier(Modifier::REVERSED);
+            vec![
+                Span::styled(expand_tabs(before, 0, tab_width), style),
+                Span::styled(under, reversed),
+                Span::styled(expand_tabs(after, after_at, tab_width), style),
+            ]
+        }
+        CursorStyle::Reversed | CursorStyle::Terminal => vec![Span::styled(expanded, style)],
+    }
+}
 
-                    let (chunk, rest) = remaining.split_at(break_at);
-                    wrapped.push(WrappedLine {
-                        text: chunk.to_string(),
-                        logical_line,
-                        is_first,
-                        start_pos: line_byte_offset,
-                    });
-                    line_byte_offset += chunk.len();
-                    remaining = rest;
-                    is_first = false;
-                }
+/// [`cursor_line_spans`] with the byte ranges `marks` of `text` (sorted,
+/// not overlapping) drawn in the `marked` style instead of `style`
+fn marked_line_spans<'a>(
+    text: &'a str,
+    (style, marked, marks): (Style, Style, &[std::ops::Range<usize>]),
+    cursor: Option<usize>,
+    cursor_style: CursorStyle,
+    visible: bool,
+    tab_width: usize,
+    theme: &Theme,
+) -> Vec<Span<'a>> {
+    // The cell under a reversed cursor is a piece of its own
+    let under = cursor
+        .filter(|_| cursor_style == CursorStyle::Reversed && visible)
+        .map(|col| col..col + text[col..].chars().next().map_or(0, char::len_utf8));
+    let mut cuts: Vec<usize> = marks
+        .iter()
+        .chain(&under)
+        .flat_map(|range| [range.start, range.end])
+        .chain([0, text.len()])
+        .collect();
+    cuts.sort_unstable();
+    cuts.dedup();
+
+    let mut spans: Vec<Span> = cuts
+        .windows(2)
+        .map(|cut| {
+            let (start, end) = (cut[0], cut[1]);
+            let mut piece_style = if marks.iter().any(|m| m.start <= start && end <= m.end) {
+                marked
+            } else {
+                style
+            };
+            if under.as_ref().is_some_and(|u| u.start == start) {
+                piece_style = piece_style.add_modifier(Modifier::REVERSED);
             }
+            let at = columns(&text[..start], tab_width);
+            Span::styled(expand_tabs(&text[start..end], at, tab_width), piece_style)
+        })
+        .collect();
+    match under {
+        // At the end of the line, a reversed space
+        Some(under) if under.is_empty() => {
+            spans.push(Span::styled(" ", style.add_modifier(Modifier::REVERSED)))
         }
-        byte_offset += line.len() + 1; // +1 for newline
+        _ => {}
     }
-
-    if wrapped.is_empty() {
-        wrapped.push(WrappedLine {
-            text: String::new(),
-            logical_line: 0,
-            is_first: true,
-            start_pos: 0,
-        });
+    if cursor.is_some() && cursor_style == CursorStyle::Block {
+        let cursor_char = if visible { "█" } else { " " };
+        spans.push(Span::styled(
+            cursor_char,
+            Style::default().fg(theme.primary),
+        ));
     }
-
-    wrapped
+    spans
 }
 
-/// Find the visual row and column for a cursor position in wrapped text
-fn cursor_to_visual_pos(
-    text: &str,
-    cursor_pos: usize,
-    width: usize,
-    prefix_width: usize,
-) -> (usize, usize) {
-    let wrapped = wrap_text(text, width, prefix_width);
-    let mut visual_row = 0;
-
-    for (i, wline) in wrapped.iter().enumerate() {
-        let line_end = wline.start_pos + wline.text.len();
-        // Check if cursor is in this wrapped line
-        if cursor_pos >= wline.start_pos && cursor_pos <= line_end {
-            let col_in_line = cursor_pos - wline.start_pos;
-            return (i, col_in_line);
-        }
-        visual_row = i;
+/// Widest the model's name gets in the route chip
+const MAX_MODEL_WIDTH: usize = 24;
+
+/// Chip drawn after the prompt character: the `@agent`, then the model's
+/// name (dim, without its provider), and a space, e.g. `@architect · claude-sonnet-4 `
+fn route_chip<'a>(agent: Option<&str>, model: Option<&str>, theme: &Theme) -> Vec<Span<'a>> {
+    let mut spans = Vec::new();
+    if let Some(agent) = agent {
+        spans.push(Span::styled(
+            format!("@{}", agent),
+            Style::default()
+                .fg(theme.accent)
+                .add_modifier(Modifier::REVERSED),
+        ));
+    }
+    if let Some(model) = model {
+        let name = model.split_once('/').map_or(model, |(_, name)| name);
+        let separator = if spans.is_empty() { "" } else { " · " };
+        spans.push(Span::styled(
+            format!("{}{}", separator, display_truncate(name, MAX_MODEL_WIDTH)),
+            Style::default().fg(theme.dim),
+        ));
     }
+    if !spans.is_empty() {
+        spans.push(Span::raw(" "));
+    }
+    spans
+}
 
-    // Cursor is at the end
-    (
-        visual_row,
-        wrapped.last().map(|l| l.text.len()).unwrap_or(0),
-    )
+/// Width of the ask dialog in a terminal `area_width` columns wide
+fn ask_dialog_width(area_width: u16, has_placeholders: bool) -> u16 {
+    if has_placeholders {
+        area_width.min(80)
+    } else {
+        area_width.min(70)
+    }
 }
 
-/// Count total visual lines after wrapping
-fn count_visual_lines(text: &str, width: usize, prefix_width: usize) -> usize {
-    wrap_text(text, width, prefix_width).len()
+/// The autocomplete popup, `width` x `height`, for an @-word drawn at `anchor`
+///
+/// It goes under the @, or above its line when there is no room below, and
+/// is kept within `bounds` (the dialog).
+fn autocomplete_area(anchor: Position, width: u16, height: u16, bounds: Rect) -> Rect {
+    let width = width.min(bounds.width);
+    let height = height.min(bounds.height);
+    let x = anchor
+        .x
+        .min(bounds.right().saturating_sub(width))
+        .max(bounds.x);
+    let y = if anchor.y + 1 + height <= bounds.bottom() {
+        anchor.y + 1
+    } else {
+        anchor.y.saturating_sub(height).max(bounds.y)
+    };
+    Rect {
+        x,
+        y,
+        width,
+        height,
+    }
 }
 
-/// Update scroll offset to keep cursor visible (using visual lines with wrapping)
-fn update_scroll_for_cursor(
-    text: &str,
-    cursor_pos: usize,
-    scroll_offset: &mut usize,
-    visible_lines: usize,
-    text_width: usize,
-    prefix_width: usize,
-) {
-    let (visual_row, _) = cursor_to_visual_pos(text, cursor_pos, text_width, prefix_width);
-    if visual_row < *scroll_offset {
-        *scroll_offset = visual_row;
-    } else if visual_row >= *scroll_offset + visible_lines {
-        *scroll_offset = visual_row - visible_lines + 1;
+/// A `width` x `height` rect centered in `area`
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    Rect {
+        x: area.x + area.width.saturating_sub(width) / 2,
+        y: area.y + area.height.saturating_sub(height) / 2,
+        width,
+        height,
     }
 }
 
 /// Write debug info to log file if debug mode is enabled
-fn debug_log(debug: bool, msg: &str) {
+pub fn debug_log(debug: bool, msg: &str) {
     if debug {
         use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
         if let Ok(mut file) = std::fs::OpenOptions::new()
             .create(true)
             .append(true)
-            .open(DEBUG_LOG_PATH)
+            .mode(0o600)
+            .open(crate::paths::debug_log())
         {
             let timestamp = chrono::Local::now().format("%H:%M:%S%.3f");
             let _ = writeln!(file, "[{}] {}", timestamp, msg);
@@ -252,6 +367,65 @@ fn debug_log(debug: bool, msg: &str) {
     }
 }
 
+/// Focus target in the ask dialog
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AskFocus {
+    /// Text input
+    Input,
+    /// Placeholders panel, with the highlighted row
+    Placeholders(usize),
+    /// Send button
+    Send,
+    /// Cancel button
+    Cancel,
+}
+
+impl AskFocus {
+    /// Next focus target (Tab); the panel is skipped when there are no placeholders
+    fn next(self, has_placeholders: bool) -> Self {
+        match self {
+            Self::Input if has_placeholders => Self::Placeholders(0),
+            Self::Input | Self::Placeholders(_) => Self::Send,
+            Self::Send => Self::Cancel,
+            Self::Cancel => Self::Input,
+        }
+    }
+
+    /// Previous focus target (Shift+Tab)
+    fn prev(self, has_placeholders: bool) -> Self {
+        match self {
+            Self::Input => Self::Cancel,
+            Self::Placeholders(_) => Self::Input,
+            Self::Send if has_placeholders => Self::Placeholders(0),
+            Self::Send => Self::Input,
+            Self::Cancel => Self::Send,
+        }
+    }
+}
+
+/// Quick action key for a key event (function key or Alt+digit)
+fn action_key(key: &KeyEvent) -> Option<ActionKey> {
+    match key.code {
+        KeyCode::F(n) if key.modifiers.is_empty() => Some(ActionKey::F(n)),
+        KeyCode::Char(c @ '0'..='9') if key.modifiers == KeyModifiers::ALT => {
+            Some(ActionKey::Alt(c))
+        }
+        _ => None,
+    }
+}
+
+/// Handle a key while the help overlay is open
+/// Arrows and PgUp/PgDn scroll; any other key closes the overlay (returns None)
+fn help_scroll_key(key: &KeyEvent, scroll: usize, max_scroll: usize) -> Option<usize> {
+    match key.code {
+        KeyCode::Up | KeyCode::Char('k') => Some(scroll.saturating_sub(1)),
+        KeyCode::Down | KeyCode::Char('j') => Some((scroll + 1).min(max_scroll)),
+        KeyCode::PageUp => Some(scroll.saturating_sub(10)),
+        KeyCode::PageDown => Some((scroll + 10).min(max_scroll)),
+        _ => None,
+    }
+}
+
 /// Result of running the TUI app
 #[derive(Debug)]
 pub enum AppResult {
@@ -261,193 +435,264 @@ pub enum AppResult {
     Cancel,
 }
 
+/// Agent and model picked in the ask dialog, for this run only
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Chosen {
+    pub agent: Option<String>,
+    /// `provider/model`
+    pub model: Option<String>,
+}
+
 /// TUI Application state
-pub struct App {
-    /// Terminal backend - uses /dev/tty to work when stdout is piped
-    terminal: Terminal<CrosstermBackend<File>>,
-    /// TTY file for reading input
-    tty_reader: File,
+pub struct App<B: Backend = TtyBackend> {
+    /// Where the dialogs are drawn and keys read
+    backend: B,
     /// Debug mode
     debug: bool,
     /// Visual theme
     theme: Theme,
+    /// Text of the dialogs, maybe translated
+    strings: Strings,
+    /// Allow composing in Helix while running inside Helix
+    allow_nested_editor: bool,
+    /// Server the dialogs send to, shown in the title
+    target: Option<Target>,
+    /// Ask dialog quick actions
+    quick_actions: Vec<QuickAction>,
+    /// How the text cursor is drawn
+    cursor_style: CursorStyle,
+    /// Animate opening dialogs and flash focused buttons
+    effects: bool,
+    /// Set the terminal title and cursor shape while a dialog is open
+    term_integration: bool,
+    /// Token estimates at which the ask dialog warns and asks before sending
+    token_budget: TokenBudget,
+    /// Prompt suggested while the ask input is empty
+    suggestion: Option<String>,
+    /// Agent the prompt goes to (--agent), shown next to the prompt character
+    agent: Option<String>,
+    /// Model the prompt goes to (`provider/model`), shown after the agent
+    model: Option<String>,
+    /// Agents the server has, whose `@name` mentions aren't flagged as typos
+    agents: Vec<String>,
+    /// Agents and models Alt+A offers in the ask dialog
+    choices: (Vec<String>, Vec<String>),
+    /// What was picked with Alt+A
+    chosen: Chosen,
+    /// Refresh of the select menu's cached items, while it is under way
+    refresh: Option<Receiver<Refresh>>,
+    /// How the placeholders panel's previews are computed
+    compute: Compute,
+    /// Round of `ask --loop`, shown in the ask dialog's title
+    round: Option<u32>,
+    /// Columns between tab stops in the input and selection previews
+    tab_width: usize,
+    /// Directory pasted image paths are relative to, when they are attached
+    attach_from: Option<PathBuf>,
+    /// Images attached to the submitted prompt
+    pasted: Vec<Pasted>,
 }
 
 impl App {
-    /// Create a new TUI application
-    /// Uses /dev/tty directly to support running via Helix's :insert-output
-    pub fn new(debug: bool) -> Result<Self> {
-        Self::with_theme(debug, ThemeKind::default())
-    }
-
     /// Create a new TUI application with a specific theme
     pub fn with_theme(debug: bool, theme_kind: ThemeKind) -> Result<Self> {
-        // Open /dev/tty directly - this works even when stdout is piped
-        let tty_write = File::options().read(true).write(true).open("/dev/tty")?;
-        let tty_reader = File::options().read(true).open("/dev/tty")?;
-
-        // Setup terminal
-        enable_raw_mode()?;
-
-        // Use a separate scope to handle the execute macro
-        let backend = {
-            let mut tty = tty_write;
-            // Write escape sequences directly
-            use std::io::Write;
-            write!(tty, "\x1b[?1049h")?; // Enter alternate screen
-            write!(tty, "\x1b[?1000h")?; // Enable mouse capture
-            tty.flush()?;
-            CrosstermBackend::new(tty)
-        };
-        let terminal = Terminal::new(backend)?;
+        Self::open(debug, theme_kind, false)
+    }
+
+    /// Create a TUI application drawn in the bottom rows of the current
+    /// screen, leaving the scrollback alone
+    pub fn inline(debug: bool, theme_kind: ThemeKind) -> Result<Self> {
+        Self::open(debug, theme_kind, true)
+    }
+
+    fn open(debug: bool, theme_kind: ThemeKind, inline: bool) -> Result<Self> {
+        Ok(Self::with_backend(
+            TtyBackend::open(debug, inline)?,
+            debug,
+            theme_kind,
+        ))
+    }
+}
 
-        Ok(Self {
-            terminal,
-            tty_reader,
+impl<B: Backend> App<B> {
+    /// Create a TUI application drawing on `backend`
+    pub fn with_backend(backend: B, debug: bool, theme_kind: ThemeKind) -> Self {
+        Self {
+            backend,
             debug,
             theme: theme_kind.config(),
-        })
+            strings: Strings::default(),
+            allow_nested_editor: false,
+            target: None,
+            quick_actions: Vec::new(),
+            cursor_style: CursorStyle::default(),
+            effects: false,
+            term_integration: true,
+            token_budget: TokenBudget::default(),
+            suggestion: None,
+            agent: None,
+            model: None,
+            agents: Vec::new(),
+            choices: (Vec::new(), Vec::new()),
+            chosen: Chosen::default(),
+            refresh: None,
+            compute: PlaceholderKind::panel_entry,
+            round: None,
+            tab_width: DEFAULT_TAB_WIDTH,
+            attach_from: None,
+            pasted: Vec::new(),
+        }
     }
 
-    /// Restore terminal to normal state
-    pub fn restore(&mut self) -> Result<()> {
-        disable_raw_mode()?;
-        // Write escape sequences directly
-        use std::io::Write;
-        let tty = self.terminal.backend_mut();
-        write!(tty, "\x1b[?1000l")?; // Disable mouse capture
-        write!(tty, "\x1b[?1049l")?; // Leave alternate screen
-        std::io::Write::flush(tty)?;
-        self.terminal.show_cursor()?;
-        Ok(())
+    /// Draw the dialogs with `strings` instead of the built-in English
+    pub fn with_strings(mut self, strings: Strings) -> Self {
+        self.strings = strings;
+        self
     }
 
-    /// Read a key event from /dev/tty with timeout
-    fn read_key(&mut self, timeout: Duration) -> Result<Option<KeyEvent>> {
-        let fd = self.tty_reader.as_raw_fd();
+    /// Allow Ctrl+G to launch Helix as the editor even when running inside Helix
+    pub fn with_nested_editor(mut self, allow: bool) -> Self {
+        self.allow_nested_editor = allow;
+        self
+    }
 
-        // Use poll to check if data is available
-        let mut pollfd = libc::pollfd {
-            fd,
-            events: libc::POLLIN,
-            revents: 0,
-        };
+    /// Show the server (and session) being sent to in the dialog titles
+    pub fn with_target(mut self, target: Option<Target>) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Bind quick actions (function keys / Alt+digit) in the ask dialog
+    pub fn with_quick_actions(mut self, actions: Vec<QuickAction>) -> Self {
+        self.quick_actions = actions;
+        self
+    }
 
-        let timeout_ms = timeout.as_millis() as i32;
-        let ret = unsafe { libc::poll(&mut pollfd, 1, timeout_ms) };
+    /// Choose how the text cursor is drawn
+    pub fn with_cursor_style(mut self, cursor_style: CursorStyle) -> Self {
+        self.cursor_style = cursor_style;
+        self
+    }
 
-        if ret <= 0 {
-            return Ok(None);
+    /// Set the terminal cursor shape instead of the theme's, if given
+    pub fn with_cursor_shape(mut self, shape: Option<CursorShape>) -> Self {
+        if shape.is_some() {
+            self.theme.cursor_shape = shape;
         }
+        self
+    }
 
-        // Read first byte
-        let mut buf = [0u8; 1];
-        let n = self.tty_reader.read(&mut buf)?;
-        if n == 0 {
-            return Ok(None);
-        }
+    /// Animate opening dialogs and flash focused buttons (see [`effects`])
+    pub fn with_effects(mut self, enabled: bool) -> Self {
+        self.effects = enabled;
+        self
+    }
 
-        let first_byte = buf[0];
+    /// Set the token estimates at which the ask dialog warns and asks before sending
+    pub fn with_token_budget(mut self, budget: TokenBudget) -> Self {
+        self.token_budget = budget;
+        self
+    }
 
-        // Debug log raw bytes
-        debug_log(self.debug, &format!("Key byte: 0x{:02x}", first_byte));
+    /// Suggest `text` as dim ghost text while the ask input is empty;
+    /// Right or End accepts it
+    pub fn with_suggestion(mut self, text: Option<String>) -> Self {
+        self.suggestion = text;
+        self
+    }
 
-        // If it's an escape byte, check if more bytes follow (escape sequence)
-        if first_byte == 0x1b {
-            // Poll briefly to see if more bytes are coming (escape sequence)
-            let mut pollfd2 = libc::pollfd {
-                fd,
-                events: libc::POLLIN,
-                revents: 0,
-            };
-            let ret2 = unsafe { libc::poll(&mut pollfd2, 1, 50) }; // 50ms timeout
+    /// Show the agent the prompt is handed to as a chip after the prompt character
+    pub fn with_agent(mut self, agent: Option<String>) -> Self {
+        self.agent = agent;
+        self
+    }
 
-            if ret2 > 0 {
-                // More bytes available - read the escape sequence
-                let mut seq_buf = [0u8; 16];
-                let seq_n = self.tty_reader.read(&mut seq_buf)?;
-                if seq_n > 0 {
-                    // Combine escape + sequence bytes
-                    let mut full_seq = vec![0x1b];
-                    full_seq.extend_from_slice(&seq_buf[..seq_n]);
+    /// Show the model (`provider/model`) the prompt goes to in the chip
+    pub fn with_model(mut self, model: Option<String>) -> Self {
+        self.model = model;
+        self
+    }
 
-                    // Debug log escape sequence
-                    debug_log(self.debug, &format!("Escape seq: {:02x?}", full_seq));
+    /// Agents the server has: the ask dialog doesn't flag mentions of them
+    pub fn with_agents(mut self, agents: Vec<String>) -> Self {
+        self.agents = agents;
+        self
+    }
 
-                    return Ok(self.parse_key(&full_seq));
-                }
-            }
-            // No more bytes - it's a bare Escape key
-            return Ok(Some(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)));
-        }
+    /// Let Alt+A in the ask dialog pick one of `agents` and `models` for this run
+    pub fn with_choices(mut self, agents: Vec<String>, models: Vec<String>) -> Self {
+        self.choices = (agents, models);
+        self
+    }
+
+    /// The agent and model picked with Alt+A, if any
+    pub fn chosen(&self) -> &Chosen {
+        &self.chosen
+    }
+
+    /// Attach images whose paths are pasted into the ask input, relative
+    /// ones taken from `cwd` (see [`attach`])
+    pub fn with_attachments(mut self, cwd: Option<PathBuf>) -> Self {
+        self.attach_from = cwd;
+        self
+    }
+
+    /// The images attached to the submitted prompt, with their places in it
+    pub fn pasted(&self) -> &[Pasted] {
+        &self.pasted
+    }
+
+    /// The select menu's items are from the cache: tag it as such until
+    /// `refresh` has news, replacing the items if they changed
+    pub fn with_refresh(mut self, refresh: Option<Receiver<Refresh>>) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Draw tabs to tab stops `tab_width` columns apart
+    pub fn with_tab_width(mut self, tab_width: usize) -> Self {
+        self.tab_width = tab_width;
+        self
+    }
+
+    /// Tag the ask dialog's title with the round of `ask --loop`
+    pub fn with_round(mut self, round: Option<u32>) -> Self {
+        self.round = round;
+        self
+    }
 
-        // Parse single byte
-        Ok(self.parse_key(&[first_byte]))
+    /// Leave the terminal title and cursor shape alone (for terminals that
+    /// mangle the sequences)
+    pub fn with_term_integration(mut self, enabled: bool) -> Self {
+        self.term_integration = enabled;
+        self
     }
 
-    /// Parse raw bytes into a KeyEvent
-    fn parse_key(&self, bytes: &[u8]) -> Option<KeyEvent> {
-        if bytes.is_empty() {
-            return None;
+    /// Title the terminal after the dialog being shown and set the cursor shape
+    fn integrate(&mut self, dialog: &'static str) -> Result<()> {
+        if self.term_integration {
+            let title = format!("{} — {}", self.theme.title_text, dialog);
+            self.backend.integrate(&title, self.theme.cursor_shape)?;
         }
+        Ok(())
+    }
 
-        let key = match bytes {
-            // Escape
-            [0x1b] => KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE),
-            // Enter
-            [0x0d] | [0x0a] => KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE),
-            // Backspace
-            [0x7f] | [0x08] => KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE),
-            // Tab
-            [0x09] => KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE),
-            // Ctrl+C
-            [0x03] => KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL),
-            // Ctrl+D
-            [0x04] => KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL),
-            // Explicit Ctrl+N and Ctrl+P
-            [0x0e] => KeyEvent::new(KeyCode::Char('n'), KeyModifiers::CONTROL),
-            [0x10] => KeyEvent::new(KeyCode::Char('p'), KeyModifiers::CONTROL),
-            // Generic Control characters (Ctrl+A to Ctrl+Z)
-            // 0x01 (A) to 0x1A (Z), excluding those handled above
-            [c] if *c >= 0x01 && *c <= 0x1A => {
-                let char_code = c + 0x60; // 1 -> 'a'
-                KeyEvent::new(KeyCode::Char(char_code as char), KeyModifiers::CONTROL)
-            }
-            // Arrow keys and other escape sequences
-            [0x1b, 0x5b, rest @ ..] => match rest {
-                [0x41] => KeyEvent::new(KeyCode::Up, KeyModifiers::NONE),
-                [0x42] => KeyEvent::new(KeyCode::Down, KeyModifiers::NONE),
-                [0x43] => KeyEvent::new(KeyCode::Right, KeyModifiers::NONE),
-                [0x44] => KeyEvent::new(KeyCode::Left, KeyModifiers::NONE),
-                [0x48] => KeyEvent::new(KeyCode::Home, KeyModifiers::NONE),
-                [0x46] => KeyEvent::new(KeyCode::End, KeyModifiers::NONE),
-                [0x33, 0x7e] => KeyEvent::new(KeyCode::Delete, KeyModifiers::NONE),
-                [0x5a] => KeyEvent::new(KeyCode::BackTab, KeyModifiers::SHIFT), // Shift+Tab
-                // Any other escape sequence - treat as Escape key
-                _ => KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE),
-            },
-            // Alt + Char
-            [0x1b, c] if *c >= 0x20 && *c < 0x7f => {
-                KeyEvent::new(KeyCode::Char(*c as char), KeyModifiers::ALT)
-            }
-            // Any other escape sequence - treat as Escape key
-            [0x1b, ..] => KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE),
-            // Regular ASCII character
-            [c] if *c >= 0x20 && *c < 0x7f => {
-                KeyEvent::new(KeyCode::Char(*c as char), KeyModifiers::NONE)
-            }
-            // UTF-8 character (2-4 bytes)
-            _ => {
-                if let Ok(s) = std::str::from_utf8(bytes) {
-                    if let Some(c) = s.chars().next() {
-                        return Some(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
-                    }
-                }
-                return None;
-            }
-        };
+    /// Restore terminal to normal state
+    pub fn restore(&mut self) -> Result<()> {
+        self.backend.restore()
+    }
+
+    /// Compute the placeholders panel's previews with `compute`
+    #[cfg(test)]
+    pub fn with_compute(mut self, compute: Compute) -> Self {
+        self.compute = compute;
+        self
+    }
 
-        Some(key)
+    /// The backend the dialogs run on
+    #[cfg(test)]
+    pub fn backend(&self) -> &B {
+        &self.backend
     }
 
     /// Run the ask (input) mode
@@ -458,244 +703,548 @@ impl App {
         context: Option<&Context>,
         animations: bool,
     ) -> Result<AppResult> {
+        self.integrate("ask")?;
+        self.pasted.clear();
         let mut input = initial.to_string();
         let mut cursor_pos = input.len();
-        // Focus: 0 = input, 1 = Send button, 2 = Cancel button
-        let mut focus: u8 = 0;
+        let mut focus = AskFocus::Input;
+        // Full-screen view of a placeholder expansion: (name, content, scroll)
+        let mut view: Option<(&str, String, usize)> = None;
 
         // Multi-line input state
         let input_visible_lines: u16 = 5; // Number of visible lines in input area
-        let mut scroll_offset: usize = 0; // First visible line
-        let mut last_text_width: usize = 60; // Track text width for scroll calculations
+        let mut scroll = InputScroll::default();
+        // Wrap layout of the input, recomputed (in place) once per frame
+        let mut wrapped_lines: Vec<WrappedLine> = Vec::new();
+        // The route chip widens the prompt, so wrapped lines indent past it too
+        let mut chip = route_chip(self.agent.as_deref(), self.model.as_deref(), &self.theme);
+        let mut prompt_len =
+            self.theme.prompt.chars().count() + chip.iter().map(Span::width).sum::<usize>();
 
         // Autocomplete state
         let mut autocomplete_active = false;
         let mut autocomplete_selected: usize = 0;
+        // Closed with Esc until the next key; also before the first key, so a
+        // pre-filled text ending in an @word can be sent or cancelled at once
+        let mut autocomplete_dismissed = true;
 
-        // Get placeholders if context is available
-        let placeholders = context
-            .map(|ctx| ctx.list_placeholders())
-            .unwrap_or_default();
+        // Placeholders if context is available, their previews computed behind the dialog
+        let mut previews = context.map(|ctx| Previews::start(ctx, self.compute));
+        let no_values = Known::new();
 
         // Available placeholder names for autocomplete
-        let placeholder_names: Vec<&str> = placeholders.iter().map(|(name, _)| *name).collect();
+        let names: Vec<String> = previews
+            .iter()
+            .flat_map(Previews::rows)
+            .map(|row| row.name.clone())
+            .collect();
+        let placeholder_names: Vec<&str> = names.iter().map(String::as_str).collect();
+        // What an @word may be; the rest are underlined and confirmed on sending
+        let agents: Vec<String> = self.agents.iter().chain(&self.agent).cloned().collect();
+        let is_known = |word: &str| {
+            typos::is_placeholder(word)
+                || placeholder_names.contains(&word)
+                // opencode takes `@build:` for the agent too
+                || agents.iter().any(|agent| {
+                    word.strip_prefix('@').map(|w| w.trim_end_matches(':')) == Some(agent)
+                })
+        };
 
-        // Clone theme for use in closure
+        // Highlighted preview of the selection, shown under @selection
+        let selection_preview = context
+            .and_then(|ctx| {
+                ctx.selection.as_deref().map(|sel| {
+                    highlight::highlight_preview(
+                        &expand_tabs(sel, 0, self.tab_width),
+                        ctx.language_or_detected(),
+                        SELECTION_PREVIEW_LINES,
+                        self.theme.dim,
+                    )
+                })
+            })
+            .unwrap_or_default();
+
+        // Numbered selection lines shown under the context hint (toggled with Ctrl+E)
+        let hint_preview = context
+            .and_then(|ctx| ctx.selection_lines(HINT_PREVIEW_LINES))
+            .unwrap_or_default();
+        let mut hint_expanded = false;
+
+        // Banner above the context hint when placeholders would read a stale file
+        let stale_banner = context
+            .is_some_and(Context::is_stale)
+            .then(|| format!("⚠ {}", self.strings.stale_file));
+        let banner_height = u16::from(stale_banner.is_some());
+
+        // Images pasted into the input, found again when it changes; Ctrl+X
+        // drops the last, for as long as the dialog is open
+        let attach_from = self.attach_from.clone();
+        let mut dismissed: Vec<PathBuf> = Vec::new();
+        let attachments = |text: &str, dismissed: &[PathBuf]| match attach_from.as_deref() {
+            Some(cwd) => {
+                let mut found = attach::find(text, cwd);
+                found.retain(|image| !dismissed.contains(&image.path));
+                found
+            }
+            None => Vec::new(),
+        };
+        let mut attached: Vec<Pasted> = Vec::new();
+        let mut scanned: Option<String> = None;
+
+        // One-off message shown in place of the help text (e.g. editor errors)
+        let mut notice: Option<String> = None;
+        // What Ctrl+Y copied, shown there for a moment
+        let mut toast = Toast::default();
+
+        // Help overlay scroll offset, while open
+        let mut help_scroll: Option<usize> = None;
+        let mut last_area_height: u16 = 24;
+        let number_width = hint_preview
+            .iter()
+            .map(|l| l.number.to_string().len())
+            .max()
+            .unwrap_or(0);
+
+        // Clone theme, strings and target for use in closure
         let theme = self.theme.clone();
+        let strings = self.strings.clone();
+        let target = self.target.clone();
+        let cursor_style = self.cursor_style;
+        let tab_width = self.tab_width;
+        let title = match self.round {
+            Some(round) => format!(
+                "{} {} ",
+                theme.title,
+                strings::fill(&strings.round, &[("round", &round.to_string())])
+            ),
+            None => theme.title.clone(),
+        };
 
         let mut cursor_visible = true;
-        let mut cursor_timer = Instant::now();
-
-        // Help text (static)
-        let help_text = "[Tab] Focus  [Enter] Send  [Esc] Abort";
+        let mut redraw = Redraw::new(Instant::now(), true);
+        let mut open = OpenAnimation::new(Instant::now(), self.effects);
+        // Send and Cancel flash when they get the focus
+        let mut flash = Flash::new(Instant::now(), self.effects);
+        let mut flashed_focus = focus;
+        // Ghost text in the empty input, until the first other key
+        let mut suggestion = self.suggestion.clone().filter(|_| input.is_empty());
+
+        // Characters and estimated tokens of the expanded input
+        let mut counter = PromptCounter::new(Instant::now());
+        let expand = |text: &str, known: &Known| match context {
+            Some(ctx) => ctx.expansion_with(text, known).text,
+            None => text.to_string(),
+        };
+        let token_budget = self.token_budget;
+
+        // Name column width for the placeholders panel
+        let name_column_width = placeholder_names
+            .iter()
+            .map(|name| name.width())
+            .max()
+            .unwrap_or(0)
+            + 2;
+
+        // Help text (depends on focus)
+        let input_help = if hint_preview.is_empty() {
+            &strings.ask_help
+        } else {
+            &strings.ask_help_context
+        };
+        // Configured quick actions are listed first so they aren't cut off
+        let actions_help: Vec<String> = self
+            .quick_actions
+            .iter()
+            .map(|a| format!("[{}] {}", a.key, a.label()))
+            .collect();
+        let input_help = if actions_help.is_empty() {
+            input_help.to_string()
+        } else {
+            format!("{}  {}", actions_help.join("  "), input_help)
+        };
 
         loop {
-            // Update cursor blink
-            if cursor_timer.elapsed() >= Duration::from_millis(530) {
+            if let Some(focused) = self.backend.focus_change() {
+                redraw.focus(focused, Instant::now());
+                // Solid while in the background, blinking again from shown
+                cursor_visible = true;
+                if !focused {
+                    open.finish();
+                    flash.finish();
+                }
+            }
+            if redraw.blink(Instant::now()) {
                 cursor_visible = !cursor_visible;
-                cursor_timer = Instant::now();
             }
 
-            // Draw UI
-            self.terminal.draw(|frame| {
-                let area = frame.area();
-
-                // Dialog size - always include space for placeholders if we have them
-                let has_placeholders = !placeholders.is_empty();
-                let dialog_width = if has_placeholders {
-                    area.width.min(80)
-                } else {
-                    area.width.min(70)
-                };
-                // Base height: hint(1) + input area(5) + gap(1) + buttons(1) + help(1) + borders(2) = 11
-                // With placeholders: add title(1) + placeholder lines + gap(1)
-                let dialog_height = if has_placeholders {
-                    13 + input_visible_lines + placeholders.len() as u16
-                } else {
-                    9 + input_visible_lines
-                };
-                let dialog_area = Rect {
-                    x: (area.width - dialog_width) / 2,
-                    y: (area.height - dialog_height) / 2,
-                    width: dialog_width,
-                    height: dialog_height,
-                };
+            if previews.as_mut().is_some_and(Previews::poll) {
+                redraw.mark();
+            }
+            let placeholders = previews.as_ref().map_or(&[][..], Previews::rows);
+            let known = previews.as_ref().map_or(&no_values, Previews::known);
+            if scanned.as_deref() != Some(input.as_str()) {
+                attached = attachments(&input, &dismissed);
+                scanned = Some(input.clone());
+            }
+            if counter.update(&input, Instant::now(), |text| expand(text, known)) {
+                redraw.mark();
+            }
+            if focus != flashed_focus {
+                if matches!(focus, AskFocus::Send | AskFocus::Cancel) {
+                    flash.start(Instant::now());
+                }
+                flashed_focus = focus;
+            }
+            if open.tick(Instant::now()) {
+                redraw.mark();
+            }
+            if flash.tick(Instant::now()) {
+                redraw.mark();
+            }
+            if toast.tick(Instant::now()) {
+                redraw.mark();
+            }
+            let counter_style = match counter.level(token_budget) {
+                Level::Normal => Style::default().fg(theme.dim),
+                Level::OverBudget => Style::default().fg(theme.warning),
+                Level::OverLimit => Style::default()
+                    .fg(theme.error)
+                    .add_modifier(Modifier::BOLD),
+            };
 
-                // Clear background
-                frame.render_widget(Clear, dialog_area);
+            // Wrap the input and keep the cursor in view before drawing
+            let has_placeholders = !placeholders.is_empty();
+            let size = self.backend.size()?;
+            redraw.resize(size);
+            // Minus borders and padding
+            let text_width =
+                ask_dialog_width(size.width, has_placeholders).saturating_sub(4) as usize;
+            wrap_into(
+                &input,
+                text_width,
+                prompt_len,
+                tab_width,
+                &mut wrapped_lines,
+            );
+            let (cursor_visual_row, cursor_visual_col) =
+                cursor_to_visual_pos(&wrapped_lines, &input, cursor_pos, tab_width);
+            // Not the word still being typed
+            let mut unknown = typos::unknown_words(&input, is_known);
+            unknown.retain(|word| focus != AskFocus::Input || word.end != cursor_pos);
+            scroll.update(
+                cursor_visual_row,
+                wrapped_lines.len(),
+                input_visible_lines as usize,
+            );
+            let cursor_in_view = scroll.shows(cursor_visual_row, input_visible_lines as usize);
+
+            // Dialog size - always include space for placeholders if we have them
+            // Base height: hint(1) + input area(5) + gap(1) + buttons(1) + help(1) + borders(2) = 11
+            // With placeholders: add title(1) + placeholder lines + gap(1)
+            let dialog_height = if has_placeholders {
+                13 + input_visible_lines
+                    + placeholders.len() as u16
+                    + selection_preview.len() as u16
+            } else {
+                9 + input_visible_lines
+            } + banner_height
+                + u16::from(!attached.is_empty());
+            let wanted_preview = if hint_expanded && context_hint.is_some() {
+                hint_preview.len() as u16
+            } else {
+                0
+            };
+            if self.backend.fit_viewport(dialog_height + wanted_preview)? {
+                redraw.mark();
+            }
+            // First line of the suggestion, drawn after the cursor
+            let ghost = suggestion
+                .as_deref()
+                .filter(|_| input.is_empty() && focus == AskFocus::Input)
+                .map(|text| match text.split_once('\n') {
+                    Some((first, _)) => format!("{} …", first),
+                    None => text.to_string(),
+                });
 
-                // Dialog box with themed styling
-                let block = Block::default()
-                    .title(theme.title.as_str())
-                    .title_style(
-                        Style::default()
-                            .fg(theme.primary)
-                            .add_modifier(Modifier::BOLD),
-                    )
-                    .borders(Borders::ALL)
-                    .border_type(theme.border_type())
-                    .border_style(Style::default().fg(theme.primary));
+            // Draw UI
+            if redraw.take() {
+                self.backend.draw(|frame| {
+                    let area = frame.area();
+                    last_area_height = area.height;
+                    let dialog_width = ask_dialog_width(area.width, has_placeholders);
+
+                    // Expanded hint preview, collapsed when the terminal is too short
+                    let preview_height = if hint_expanded
+                        && context_hint.is_some()
+                        && dialog_height + hint_preview.len() as u16 <= area.height
+                    {
+                        hint_preview.len() as u16
+                    } else {
+                        0
+                    };
+                    let dialog_height = dialog_height + preview_height;
+                    let dialog_area = centered(area, dialog_width, dialog_height);
+                    if open.running() {
+                        let rect = open.current_rect(dialog_area);
+                        effects::render_opening(frame, rect, &theme, theme.primary);
+                        return;
+                    }
 
-                let inner = block.inner(dialog_area);
-                frame.render_widget(block, dialog_area);
+                    // Clear background
+                    frame.render_widget(Clear, dialog_area);
 
-                // Context hint (if any)
-                let mut current_y = inner.y;
-                if let Some(hint) = context_hint {
-                    let hint_para = Paragraph::new(hint).style(Style::default().fg(theme.dim));
-                    frame.render_widget(
-                        hint_para,
-                        Rect {
-                            x: inner.x + 1, // Padding
-                            y: current_y,
-                            width: inner.width.saturating_sub(2),
-                            height: 1,
-                        },
-                    );
-                    current_y += 1;
-                }
+                    // Dialog box with themed styling
+                    let block = Block::default()
+                        .title(title.as_str())
+                        .title_style(
+                            Style::default()
+                                .fg(theme.primary)
+                                .add_modifier(Modifier::BOLD),
+                        )
+                        .title(target_title(
+                            target.as_ref(),
+                            &theme,
+                            dialog_width,
+                            title.width(),
+                        ))
+                        .borders(Borders::ALL)
+                        .border_type(theme.border_type())
+                        .border_style(Style::default().fg(theme.primary));
 
-                // Input field (multi-line with soft wrap)
-                let input_style = if focus == 0 {
-                    Style::default().fg(theme.input)
-                } else {
-                    Style::default().fg(theme.dim)
-                };
+                    let inner = block.inner(dialog_area);
+                    frame.render_widget(block, dialog_area);
 
-                // Calculate available width for text (minus padding and borders)
-                let prompt_len = theme.prompt.chars().count();
-                let text_width = inner.width.saturating_sub(2) as usize; // -2 for padding
-                last_text_width = text_width; // Save for scroll calculations in key handlers
-
-                // Get wrapped lines
-                let wrapped_lines = wrap_text(&input, text_width, prompt_len);
-                let total_visual_lines = wrapped_lines.len();
-
-                // Find cursor visual position
-                let (cursor_visual_row, cursor_visual_col) =
-                    cursor_to_visual_pos(&input, cursor_pos, text_width, prompt_len);
-
-                // Build display lines with scroll
-                let input_lines: Vec<Line> = wrapped_lines
-                    .iter()
-                    .enumerate()
-                    .skip(scroll_offset)
-                    .take(input_visible_lines as usize)
-                    .map(|(visual_idx, wline)| {
-                        let is_cursor_line = visual_idx == cursor_visual_row;
-                        let style = input_style;
-
-                        // Determine prefix: prompt for first line of first logical line,
-                        // continuation marker for wrapped lines, indent for other logical lines
-                        let (prefix, prefix_style) = if wline.logical_line == 0 && wline.is_first {
-                            // First line has prompt
-                            (
-                                theme.prompt.clone(),
+                    let mut current_y = inner.y;
+                    if let Some(ref banner) = stale_banner {
+                        frame.render_widget(
+                            Paragraph::new(banner.as_str()).style(
                                 Style::default()
-                                    .fg(theme.primary)
+                                    .fg(theme.warning)
                                     .add_modifier(Modifier::BOLD),
-                            )
-                        } else if !wline.is_first {
-                            // Continuation of wrapped line - use a subtle marker
-                            let cont = format!("{:>width$}", "↪ ", width = prompt_len);
-                            (cont, Style::default().fg(theme.dim))
-                        } else {
-                            // Other logical lines - indent to align
-                            let indent = " ".repeat(prompt_len);
-                            (indent, Style::default().fg(theme.dim))
-                        };
+                            ),
+                            Rect {
+                                x: inner.x + 1,
+                                y: current_y,
+                                width: inner.width.saturating_sub(2),
+                                height: 1,
+                            },
+                        );
+                        current_y += 1;
+                    }
 
-                        let prefix_span = Span::styled(prefix, prefix_style);
-                        let text_span = Span::styled(&wline.text, style);
+                    // Context hint (if any)
+                    if let Some(hint) = context_hint {
+                        // Expand marker when a selection preview is available
+                        let marker = match (hint_preview.is_empty(), preview_height > 0) {
+                            (true, _) => "",
+                            (false, true) => "▾ ",
+                            (false, false) => "▸ ",
+                        };
+                        let hint_para = Paragraph::new(format!("{}{}", marker, hint))
+                            .style(Style::default().fg(theme.dim));
+                        frame.render_widget(
+                            hint_para,
+                            Rect {
+                                x: inner.x + 1, // Padding
+                                y: current_y,
+                                width: inner.width.saturating_sub(2),
+                                height: 1,
+                            },
+                        );
+                        current_y += 1;
 
-                        if is_cursor_line && focus == 0 {
-                            let cursor_char = if cursor_visible { "█" } else { " " };
-                            let cursor_span =
-                                Span::styled(cursor_char, Style::default().fg(theme.primary));
-                            Line::from(vec![prefix_span, text_span, cursor_span])
-                        } else {
-                            Line::from(vec![prefix_span, text_span])
+                        for line in hint_preview.iter().take(preview_height as usize) {
+                            let (bar_style, text_style) = if line.selected {
+                                (
+                                    Style::default().fg(theme.primary),
+                                    Style::default().fg(theme.text),
+                                )
+                            } else {
+                                (
+                                    Style::default().fg(theme.dim),
+                                    Style::default().fg(theme.dim),
+                                )
+                            };
+                            let preview_line = Line::from(vec![
+                                Span::styled(
+                                    format!("{:>width$} ", line.number, width = number_width + 2),
+                                    Style::default().fg(theme.dim),
+                                ),
+                                Span::styled("│ ", bar_style),
+                                Span::styled(expand_tabs(&line.text, 0, tab_width), text_style),
+                            ]);
+                            frame.render_widget(
+                                Paragraph::new(preview_line),
+                                Rect {
+                                    x: inner.x + 1,
+                                    y: current_y,
+                                    width: inner.width.saturating_sub(2),
+                                    height: 1,
+                                },
+                            );
+                            current_y += 1;
                         }
-                    })
-                    .collect();
+                    }
 
-                // Show scroll indicator if needed
-                let scroll_indicator = if total_visual_lines > input_visible_lines as usize {
-                    format!(" [{}/{}]", cursor_visual_row + 1, total_visual_lines)
-                } else {
-                    String::new()
-                };
+                    // Input field (multi-line with soft wrap)
+                    let input_style = if focus == AskFocus::Input {
+                        Style::default().fg(theme.input)
+                    } else {
+                        Style::default().fg(theme.dim)
+                    };
 
-                let input_para = Paragraph::new(input_lines);
-                let input_y = current_y;
-                let input_area_height = input_visible_lines;
-                frame.render_widget(
-                    input_para,
-                    Rect {
-                        x: inner.x + 1, // Padding
-                        y: current_y,
-                        width: inner.width.saturating_sub(2),
-                        height: input_area_height,
-                    },
-                );
+                    let total_visual_lines = wrapped_lines.len();
+
+                    // Build display lines with scroll
+                    let input_lines: Vec<Line> = wrapped_lines
+                        .iter()
+                        .enumerate()
+                        .skip(scroll.offset)
+                        .take(input_visible_lines as usize)
+                        .map(|(visual_idx, wline)| {
+                            let is_cursor_line = visual_idx == cursor_visual_row;
+                            let style = input_style;
+
+                            // Determine prefix: prompt for first line of first logical line,
+                            // continuation marker for wrapped lines, indent for other logical lines
+                            let (prefix, prefix_style) =
+                                if wline.logical_line == 0 && wline.is_first {
+                                    // First line has prompt
+                                    (
+                                        theme.prompt.clone(),
+                                        Style::default()
+                                            .fg(theme.primary)
+                                            .add_modifier(Modifier::BOLD),
+                                    )
+                                } else if !wline.is_first {
+                                    // Continuation of wrapped line - use a subtle marker
+                                    let cont = format!("{:>width$}", "↪ ", width = prompt_len);
+                                    (cont, Style::default().fg(theme.dim))
+                                } else {
+                                    // Other logical lines - indent to align
+                                    let indent = " ".repeat(prompt_len);
+                                    (indent, Style::default().fg(theme.dim))
+                                };
+
+                            let cursor = (is_cursor_line && focus == AskFocus::Input)
+                                .then(|| cursor_pos.clamp(wline.range.start, wline.range.end))
+                                .map(|pos| pos - wline.range.start);
+                            let mut spans = vec![Span::styled(prefix, prefix_style)];
+                            if wline.logical_line == 0 && wline.is_first {
+                                spans.extend(chip.iter().cloned());
+                            }
+                            let marks: Vec<_> = unknown
+                                .iter()
+                                .filter(|word| {
+                                    word.start < wline.range.end && wline.range.start < word.end
+                                })
+                                .map(|word| {
+                                    word.start.max(wline.range.start) - wline.range.start
+                                        ..word.end.min(wline.range.end) - wline.range.start
+                                })
+                                .collect();
+                            if marks.is_empty() {
+                                spans.extend(cursor_line_spans(
+                                    wline.text(&input),
+                                    style,
+                                    cursor,
+                                    cursor_style,
+                                    cursor_visible,
+                                    tab_width,
+                                    &theme,
+                                ));
+                            } else {
+                                let marked =
+                                    style.fg(theme.warning).add_modifier(Modifier::UNDERLINED);
+                                spans.extend(marked_line_spans(
+                                    wline.text(&input),
+                                    (style, marked, &marks),
+                                    cursor,
+                                    cursor_style,
+                                    cursor_visible,
+                                    tab_width,
+                                    &theme,
+                                ));
+                            }
+                            if let Some(ghost) = ghost.as_deref() {
+                                spans.push(Span::styled(ghost, Style::default().fg(theme.dim)));
+                            }
+                            Line::from(spans)
+                        })
+                        .collect();
 
-                // Scroll indicator on the right side
-                if !scroll_indicator.is_empty() {
-                    let indicator = Paragraph::new(scroll_indicator)
-                        .style(Style::default().fg(theme.dim))
-                        .alignment(Alignment::Right);
+                    // Show scroll indicator if needed
+                    let scroll_indicator = if total_visual_lines > input_visible_lines as usize {
+                        format!(" [{}/{}]", cursor_visual_row + 1, total_visual_lines)
+                    } else {
+                        String::new()
+                    };
+
+                    let input_para = Paragraph::new(input_lines);
+                    let input_y = current_y;
+                    let input_area_height = input_visible_lines;
                     frame.render_widget(
-                        indicator,
+                        input_para,
                         Rect {
-                            x: inner.x + 1,
-                            y: current_y + input_area_height - 1,
+                            x: inner.x + 1, // Padding
+                            y: current_y,
                             width: inner.width.saturating_sub(2),
-                            height: 1,
+                            height: input_area_height,
                         },
                     );
-                }
 
-                current_y += input_area_height + 1;
+                    // Markers for rows hidden above and below the view
+                    let (more_above, more_below) =
+                        scroll.more(total_visual_lines, input_visible_lines as usize);
+                    if more_above {
+                        frame.render_widget(
+                            Paragraph::new(format!("▲ {}", strings.more))
+                                .style(Style::default().fg(theme.dim))
+                                .alignment(Alignment::Right),
+                            Rect {
+                                x: inner.x + 1,
+                                y: input_y,
+                                width: inner.width.saturating_sub(2),
+                                height: 1,
+                            },
+                        );
+                    }
 
-                // Placeholders panel (always show when we have placeholders)
-                if !placeholders.is_empty() {
-                    // Simple section header
-                    let title_para = Paragraph::new("Placeholders:").style(
-                        Style::default()
-                            .fg(theme.dim)
-                            .add_modifier(Modifier::ITALIC),
-                    );
+                    // Counter and scroll indicator on the right side
+                    let indicator = Line::from(vec![
+                        Span::styled(
+                            if more_below {
+                                format!("▼ {}  ", strings.more)
+                            } else {
+                                String::new()
+                            },
+                            Style::default().fg(theme.dim),
+                        ),
+                        Span::styled(counter.label(&strings.counter), counter_style),
+                        Span::styled(scroll_indicator, Style::default().fg(theme.dim)),
+                    ]);
                     frame.render_widget(
-                        title_para,
+                        Paragraph::new(indicator).alignment(Alignment::Right),
                         Rect {
                             x: inner.x + 1,
-                            y: current_y,
+                            y: current_y + input_area_height - 1,
                             width: inner.width.saturating_sub(2),
                             height: 1,
                         },
                     );
-                    current_y += 1;
-
-                    for (placeholder, value) in &placeholders {
-                        // Truncate value if too long
-                        let max_value_len = (inner.width as usize).saturating_sub(20);
-                        let display_value = if value.len() > max_value_len {
-                            format!("{}...", &value[..max_value_len.saturating_sub(3)])
-                        } else {
-                            value.clone()
-                        };
 
-                        let line = Line::from(vec![
-                            Span::styled(
-                                format!("  {:<12}", placeholder),
-                                Style::default().fg(theme.secondary),
-                            ),
-                            Span::styled(display_value, Style::default().fg(theme.dim)),
-                        ]);
+                    current_y += input_area_height + 1;
 
-                        let para = Paragraph::new(line);
+                    // Placeholders panel (always show when we have placeholders)
+                    if !placeholders.is_empty() {
+                        // Simple section header
+                        let title_para = Paragraph::new(strings.placeholders.as_str()).style(
+                            Style::default()
+                                .fg(theme.dim)
+                                .add_modifier(Modifier::ITALIC),
+                        );
                         frame.render_widget(
-                            para,
+                            title_para,
                             Rect {
                                 x: inner.x + 1,
                                 y: current_y,
@@ -704,152 +1253,307 @@ impl App {
                             },
                         );
                         current_y += 1;
-                    }
-                    current_y += 1;
-                }
 
-                // Buttons row
-                let button_y = current_y;
+                        for (idx, row) in placeholders.iter().enumerate() {
+                            let placeholder = &row.name;
+                            // Truncate value if too long; "…" until it is computed
+                            let max_value_len =
+                                (inner.width as usize).saturating_sub(name_column_width + 4);
+                            let display_value = display_truncate(
+                                row.preview.as_deref().unwrap_or("…"),
+                                max_value_len,
+                            );
 
-                // Send button (themed)
-                let send_style = if focus == 1 {
-                    Style::default()
-                        .fg(Color::Black)
-                        .bg(theme.primary)
-                        .add_modifier(Modifier::BOLD)
-                } else {
-                    Style::default().fg(theme.dim)
-                };
-                let send_btn = Paragraph::new(" SEND ")
-                    .style(send_style)
-                    .alignment(Alignment::Center);
-                frame.render_widget(
-                    send_btn,
-                    Rect {
+                            let name_style = if focus == AskFocus::Placeholders(idx) {
+                                Style::default()
+                                    .fg(Color::Black)
+                                    .bg(theme.primary)
+                                    .add_modifier(Modifier::BOLD)
+                            } else if row.available {
+                                Style::default().fg(theme.secondary)
+                            } else {
+                                Style::default().fg(theme.dim)
+                            };
+                            let padding = name_column_width.saturating_sub(placeholder.width());
+                            let line = Line::from(vec![
+                                Span::raw("  "),
+                                Span::styled(placeholder.as_str(), name_style),
+                                Span::raw(" ".repeat(padding)),
+                                Span::styled(display_value, Style::default().fg(theme.dim)),
+                            ]);
+
+                            let para = Paragraph::new(line);
+                            frame.render_widget(
+                                para,
+                                Rect {
+                                    x: inner.x + 1,
+                                    y: current_y,
+                                    width: inner.width.saturating_sub(2),
+                                    height: 1,
+                                },
+                            );
+                            current_y += 1;
+
+                            // Selection preview lines below @selection
+                            if placeholder == "@selection" {
+                                let preview_width =
+                                    (inner.width as usize).saturating_sub(name_column_width + 6);
+                                for preview_line in &selection_preview {
+                                    let mut spans =
+                                        vec![Span::raw(" ".repeat(name_column_width + 2))];
+                                    spans.extend(markdown::truncate_spans(
+                                        &preview_line.spans,
+                                        preview_width,
+                                    ));
+                                    frame.render_widget(
+                                        Paragraph::new(Line::from(spans)),
+                                        Rect {
+                                            x: inner.x + 1,
+                                            y: current_y,
+                                            width: inner.width.saturating_sub(2),
+                                            height: 1,
+                                        },
+                                    );
+                                    current_y += 1;
+                                }
+                            }
+                        }
+                        current_y += 1;
+                    }
+
+                    // Pasted images, above the buttons
+                    if !attached.is_empty() {
+                        let mut spans: Vec<Span> = Vec::new();
+                        for image in &attached {
+                            spans.push(Span::styled(
+                                image.label(),
+                                Style::default().fg(theme.accent),
+                            ));
+                            spans.push(Span::raw("  "));
+                        }
+                        spans.push(Span::styled(
+                            strings.attachments_help.as_str(),
+                            Style::default().fg(theme.dim),
+                        ));
+                        frame.render_widget(
+                            Paragraph::new(Line::from(spans)),
+                            Rect {
+                                x: inner.x + 1,
+                                y: current_y,
+                                width: inner.width.saturating_sub(2),
+                                height: 1,
+                            },
+                        );
+                        current_y += 1;
+                    }
+
+                    // Buttons row, each a space wider than its label on both
+                    // sides and kept inside the dialog
+                    let button_y = current_y;
+                    let send_label = format!(" {} ", strings.send);
+                    let send_area = Rect {
                         x: inner.x + 1,
                         y: button_y,
-                        width: 8,
+                        width: send_label.width() as u16 + 2,
                         height: 1,
-                    },
-                );
-
-                // Cancel button (themed)
-                let cancel_style = if focus == 2 {
-                    Style::default()
-                        .fg(Color::Black)
-                        .bg(theme.error)
-                        .add_modifier(Modifier::BOLD)
-                } else {
-                    Style::default().fg(theme.dim)
-                };
-                let cancel_btn = Paragraph::new(" CANCEL ")
-                    .style(cancel_style)
-                    .alignment(Alignment::Center);
-                frame.render_widget(
-                    cancel_btn,
-                    Rect {
-                        x: inner.x + 11,
+                    }
+                    .intersection(inner);
+                    let cancel_label = format!(" {} ", strings.cancel);
+                    let cancel_area = Rect {
+                        x: send_area.right() + 2,
                         y: button_y,
-                        width: 10,
+                        width: cancel_label.width() as u16 + 2,
                         height: 1,
-                    },
-                );
-
-                // Help text (themed)
-                let help_display = format!(" {} ", help_text);
-                let help_para = Paragraph::new(help_display)
-                    .style(Style::default().fg(theme.dim))
-                    .alignment(Alignment::Center);
-                frame.render_widget(
-                    help_para,
-                    Rect {
-                        x: inner.x,
-                        y: inner.y + inner.height - 1,
-                        width: inner.width,
-                        height: 1,
-                    },
-                );
+                    }
+                    .intersection(inner);
 
-                // Autocomplete popup (rendered last to appear on top)
-                let filtered_completions: Vec<&str> =
-                    if let Some((_, partial)) = find_at_word(&input, cursor_pos) {
-                        if autocomplete_active {
-                            filter_placeholders(partial, &placeholder_names)
-                        } else {
-                            vec![]
-                        }
+                    // Send button (themed)
+                    let send_style = if focus == AskFocus::Send {
+                        Style::default()
+                            .fg(Color::Black)
+                            .bg(if flash.active() {
+                                theme.accent
+                            } else {
+                                theme.primary
+                            })
+                            .add_modifier(Modifier::BOLD)
                     } else {
-                        vec![]
+                        Style::default().fg(theme.dim)
                     };
+                    let send_btn = Paragraph::new(send_label)
+                        .style(send_style)
+                        .alignment(Alignment::Center);
+                    frame.render_widget(send_btn, send_area);
 
-                if !filtered_completions.is_empty() {
-                    let popup_width = 16u16;
-                    let popup_height = (filtered_completions.len() as u16 + 2).min(8); // +2 for border
-                    let prompt_len = theme.prompt.chars().count() as u16;
-
-                    // Position popup below the @ symbol
-                    let at_pos = find_at_word(&input, cursor_pos)
-                        .map(|(p, _)| p)
-                        .unwrap_or(0);
-                    let popup_x = (inner.x + 1 + prompt_len + at_pos as u16)
-                        .min(area.width.saturating_sub(popup_width + 1));
-                    let popup_y = input_y + 1;
-
-                    let popup_area = Rect {
-                        x: popup_x,
-                        y: popup_y,
-                        width: popup_width,
-                        height: popup_height,
+                    // Cancel button (themed)
+                    let cancel_style = if focus == AskFocus::Cancel {
+                        Style::default()
+                            .fg(Color::Black)
+                            .bg(if flash.active() {
+                                theme.accent
+                            } else {
+                                theme.error
+                            })
+                            .add_modifier(Modifier::BOLD)
+                    } else {
+                        Style::default().fg(theme.dim)
+                    };
+                    let cancel_btn = Paragraph::new(cancel_label)
+                        .style(cancel_style)
+                        .alignment(Alignment::Center);
+                    frame.render_widget(cancel_btn, cancel_area);
+
+                    // Help text (themed), replaced by a pending notice or toast
+                    let help_text = match focus {
+                        AskFocus::Placeholders(_) => strings.placeholders_help.as_str(),
+                        _ => input_help.as_str(),
                     };
+                    let (help_display, help_color) = match (&notice, toast.text()) {
+                        (Some(msg), _) => (format!(" {} ", msg), theme.warning),
+                        (None, Some(msg)) => (format!(" {} ", msg), theme.accent),
+                        (None, None) => (format!(" {} ", help_text), theme.dim),
+                    };
+                    let help_para = Paragraph::new(help_display)
+                        .style(Style::default().fg(help_color))
+                        .alignment(Alignment::Center);
+                    frame.render_widget(
+                        help_para,
+                        Rect {
+                            x: inner.x,
+                            y: inner.y + inner.height - 1,
+                            width: inner.width,
+                            height: 1,
+                        },
+                    );
 
-                    // Clear and draw popup background
-                    frame.render_widget(Clear, popup_area);
-                    let popup_block = Block::default()
-                        .borders(Borders::ALL)
-                        .border_type(ratatui::widgets::BorderType::Rounded)
-                        .border_style(Style::default().fg(theme.secondary));
-                    let popup_inner = popup_block.inner(popup_area);
-                    frame.render_widget(popup_block, popup_area);
-
-                    // Draw completion items
-                    for (i, completion) in filtered_completions.iter().enumerate() {
-                        if i >= popup_inner.height as usize {
-                            break;
+                    // Autocomplete popup (rendered last to appear on top)
+                    let filtered_completions: Vec<&str> =
+                        if let Some((_, partial)) = find_at_word(&input, cursor_pos) {
+                            if autocomplete_active {
+                                filter_placeholders(partial, &placeholder_names)
+                            } else {
+                                vec![]
+                            }
+                        } else {
+                            vec![]
+                        };
+
+                    if !filtered_completions.is_empty() {
+                        let popup_width = 16u16;
+                        let popup_height = (filtered_completions.len() as u16 + 2).min(8); // +2 for border
+
+                        // Anchored at the @ where it is drawn: its wrapped row
+                        // within the view, past the prompt or indent
+                        let at_pos = find_at_word(&input, cursor_pos)
+                            .map(|(p, _)| p)
+                            .unwrap_or(0);
+                        let (at_row, at_x) =
+                            char_to_visual_pos(&wrapped_lines, &input, at_pos, tab_width);
+                        let visible_row = at_row
+                            .saturating_sub(scroll.offset)
+                            .min(input_visible_lines as usize - 1);
+                        let anchor = Position {
+                            x: inner.x + 1 + (prompt_len + at_x) as u16,
+                            y: input_y + visible_row as u16,
+                        };
+                        let popup_area =
+                            autocomplete_area(anchor, popup_width, popup_height, inner);
+
+                        // Clear and draw popup background
+                        frame.render_widget(Clear, popup_area);
+                        let popup_block = Block::default()
+                            .borders(Borders::ALL)
+                            .border_type(ratatui::widgets::BorderType::Rounded)
+                            .border_style(Style::default().fg(theme.secondary));
+                        let popup_inner = popup_block.inner(popup_area);
+                        frame.render_widget(popup_block, popup_area);
+
+                        // Draw completion items
+                        for (i, completion) in filtered_completions.iter().enumerate() {
+                            if i >= popup_inner.height as usize {
+                                break;
+                            }
+                            let style = if i == autocomplete_selected {
+                                Style::default()
+                                    .fg(Color::Black)
+                                    .bg(theme.primary)
+                                    .add_modifier(Modifier::BOLD)
+                            } else {
+                                Style::default().fg(theme.text)
+                            };
+                            let item = Paragraph::new(*completion).style(style);
+                            frame.render_widget(
+                                item,
+                                Rect {
+                                    x: popup_inner.x,
+                                    y: popup_inner.y + i as u16,
+                                    width: popup_inner.width,
+                                    height: 1,
+                                },
+                            );
                         }
-                        let style = if i == autocomplete_selected {
-                            Style::default()
-                                .fg(Color::Black)
-                                .bg(theme.primary)
-                                .add_modifier(Modifier::BOLD)
+                    }
+
+                    // Position the terminal cursor when the input is focused; the
+                    // reversed cell replaces it
+                    if focus == AskFocus::Input
+                        && cursor_in_view
+                        && cursor_style != CursorStyle::Reversed
+                    {
+                        let prompt_len = prompt_len as u16;
+                        let visible_cursor_row = cursor_visual_row - scroll.offset;
+                        let cursor_y_pos = input_y + visible_cursor_row as u16;
+                        // Column offset includes prefix width
+                        let col_offset = prompt_len + cursor_visual_col as u16;
+                        frame.set_cursor_position(Position {
+                            x: inner.x + 1 + col_offset,
+                            y: cursor_y_pos,
+                        });
+                    }
+
+                    // Full-screen placeholder expansion view (on top of everything)
+                    if let Some((name, content, scroll)) = &view {
+                        frame.render_widget(Clear, area);
+                        let block = Block::default()
+                            .title(format!(" {} ", name))
+                            .title_style(
+                                Style::default()
+                                    .fg(theme.primary)
+                                    .add_modifier(Modifier::BOLD),
+                            )
+                            .title_bottom(Line::from(format!(" {} ", strings.view_help)).centered())
+                            .borders(Borders::ALL)
+                            .border_type(theme.border_type())
+                            .border_style(Style::default().fg(theme.primary));
+                        // A diff in color, each line cut to the view
+                        let text = if diff::looks_like_diff(content) {
+                            let width = area.width.saturating_sub(2) as usize;
+                            Text::from(diff::render(content, width, &theme))
                         } else {
-                            Style::default().fg(theme.text)
+                            Text::raw(content.as_str())
                         };
-                        let item = Paragraph::new(*completion).style(style);
-                        frame.render_widget(
-                            item,
-                            Rect {
-                                x: popup_inner.x,
-                                y: popup_inner.y + i as u16,
-                                width: popup_inner.width,
-                                height: 1,
-                            },
+                        let para = Paragraph::new(text)
+                            .style(Style::default().fg(theme.text))
+                            .block(block)
+                            .scroll((*scroll as u16, 0));
+                        frame.render_widget(para, area);
+                    }
+
+                    // Keybinding help overlay (topmost)
+                    if let Some(scroll) = help_scroll {
+                        help::render_help(
+                            frame,
+                            area,
+                            &theme,
+                            &strings,
+                            keymap::ASK_BINDINGS,
+                            scroll,
                         );
                     }
-                }
 
-                // Position cursor only when input is focused (hidden, we use block cursor)
-                if focus == 0 {
-                    let prompt_len = theme.prompt.chars().count() as u16;
-                    let visible_cursor_row = cursor_visual_row.saturating_sub(scroll_offset);
-                    let cursor_y_pos = input_y + visible_cursor_row as u16;
-                    // Column offset includes prefix width
-                    let col_offset = prompt_len + cursor_visual_col as u16;
-                    frame.set_cursor_position(Position {
-                        x: inner.x + 1 + col_offset,
-                        y: cursor_y_pos,
-                    });
-                }
-            })?;
+                    theme.apply_emphasis(frame.buffer_mut());
+                })?;
+            }
 
             // Check if autocomplete should be shown
             let current_completions: Vec<&str> =
@@ -860,7 +1564,10 @@ impl App {
                 };
 
             // Update autocomplete state
-            if !current_completions.is_empty() && focus == 0 {
+            if !autocomplete_dismissed
+                && !current_completions.is_empty()
+                && focus == AskFocus::Input
+            {
                 autocomplete_active = true;
                 // Clamp selection to valid range
                 if autocomplete_selected >= current_completions.len() {
@@ -872,216 +1579,362 @@ impl App {
             }
 
             // Handle input from /dev/tty
-            if let Some(key) = self.read_key(Duration::from_millis(16))? {
-                // Handle autocomplete navigation first
-                if autocomplete_active && !current_completions.is_empty() {
-                    match key.code {
-                        KeyCode::Down | KeyCode::Char('n')
-                            if key.code == KeyCode::Down
-                                || key.modifiers.contains(KeyModifiers::CONTROL) =>
-                        {
-                            autocomplete_selected =
-                                (autocomplete_selected + 1) % current_completions.len();
-                            continue;
+            // Previews still coming are taken in as often as keys
+            let previews_due = previews
+                .as_ref()
+                .filter(|previews| previews.pending())
+                .map(|_| Instant::now() + ACTIVE_POLL);
+            let deadline = [
+                counter.settles_at(),
+                open.deadline(),
+                flash.deadline(),
+                toast.deadline(),
+                previews_due,
+            ]
+            .into_iter()
+            .flatten()
+            .min();
+            let timeout = redraw.poll_timeout(Instant::now(), deadline);
+            if let Some(key) = self.backend.read_key(timeout)? {
+                redraw.key(Instant::now());
+                open.finish();
+                notice = None;
+                autocomplete_dismissed = false;
+
+                // Right or End takes the suggestion; any other key drops it
+                let suggested = suggestion.take();
+
+                // The help overlay captures all keys while open
+                if let Some(scroll) = help_scroll {
+                    let max_scroll = help::max_scroll(keymap::ASK_BINDINGS, last_area_height);
+                    help_scroll = help_scroll_key(&key, scroll, max_scroll);
+                    continue;
+                }
+
+                let completing = autocomplete_active && !current_completions.is_empty();
+                // The expansion view captures all keys while open
+                let command = keymap::lookup(keymap::ASK_BINDINGS, &key, |when| match when {
+                    When::InView => view.is_some(),
+                    _ if view.is_some() => false,
+                    When::Always => true,
+                    When::EmptyText => input.is_empty(),
+                    When::Suggestion => ghost.is_some() && suggested.is_some(),
+                    When::WithSelection => !hint_preview.is_empty(),
+                    When::WithImage => !attached.is_empty(),
+                    When::Completing => completing,
+                    When::OnPlaceholders => matches!(focus, AskFocus::Placeholders(_)),
+                    When::EmptyFilter | When::OnHeader => false,
+                });
+
+                // Anything but scrolling the view brings it back to the cursor
+                if !matches!(command, Some(Command::ScrollUp | Command::ScrollDown)) {
+                    scroll.follow();
+                }
+
+                let Some(command) = command else {
+                    continue;
+                };
+                match command {
+                    Command::AcceptSuggestion => {
+                        if let Some(text) = suggested {
+                            input = text;
+                            cursor_pos = input.len();
+                            // Ready to send, even when it ends in a placeholder
+                            autocomplete_dismissed = true;
                         }
-                        KeyCode::Up | KeyCode::Char('p')
-                            if key.code == KeyCode::Up
-                                || key.modifiers.contains(KeyModifiers::CONTROL) =>
-                        {
-                            autocomplete_selected = if autocomplete_selected == 0 {
-                                current_completions.len() - 1
-                            } else {
-                                autocomplete_selected - 1
+                    }
+                    Command::Help => {
+                        help_scroll = Some(0);
+                    }
+                    Command::ViewUp
+                    | Command::ViewDown
+                    | Command::ViewPageUp
+                    | Command::ViewPageDown => {
+                        if let Some((_, content, scroll)) = view.as_mut() {
+                            let max_scroll = content.lines().count().saturating_sub(1);
+                            *scroll = match command {
+                                Command::ViewUp => scroll.saturating_sub(1),
+                                Command::ViewDown => (*scroll + 1).min(max_scroll),
+                                Command::ViewPageUp => scroll.saturating_sub(10),
+                                _ => (*scroll + 10).min(max_scroll),
                             };
-                            continue;
                         }
-                        KeyCode::Tab | KeyCode::Enter => {
-                            // Accept completion
-                            if let Some((at_pos, _)) = find_at_word(&input, cursor_pos) {
-                                let completion = current_completions[autocomplete_selected];
-                                // Replace the partial @word with the full completion
-                                input.replace_range(at_pos..cursor_pos, completion);
-                                cursor_pos = at_pos + completion.len();
-                                // Add a space after completion
-                                input.insert(cursor_pos, ' ');
-                                cursor_pos += 1;
-                                autocomplete_active = false;
-                                autocomplete_selected = 0;
-                            }
+                    }
+                    Command::CloseView => {
+                        view = None;
+                    }
+                    // Quick actions submit the input through their template
+                    Command::Quick => {
+                        let Some(action) = action_key(&key)
+                            .and_then(|k| self.quick_actions.iter().find(|a| a.key == k))
+                        else {
                             continue;
+                        };
+                        debug_log(self.debug, &format!("run_ask: quick action {}", action.key));
+                        let text = action.apply(&input);
+                        counter.refresh(&text, |text| expand(text, known));
+                        if self.confirm_unknown_words(&text, is_known)?
+                            && self.confirm_over_limit(&counter)?
+                        {
+                            self.pasted = attachments(&text, &dismissed);
+                            return Ok(AppResult::Submit(text));
                         }
-                        KeyCode::Esc => {
-                            // Cancel autocomplete but don't exit dialog
+                    }
+                    Command::CompletionDown => {
+                        autocomplete_selected =
+                            (autocomplete_selected + 1) % current_completions.len();
+                    }
+                    Command::CompletionUp => {
+                        autocomplete_selected = if autocomplete_selected == 0 {
+                            current_completions.len() - 1
+                        } else {
+                            autocomplete_selected - 1
+                        };
+                    }
+                    Command::Complete => {
+                        if let Some((at_pos, _)) = find_at_word(&input, cursor_pos) {
+                            let completion = current_completions[autocomplete_selected];
+                            // Replace the partial @word with the full completion
+                            input.replace_range(at_pos..cursor_pos, completion);
+                            cursor_pos = at_pos + completion.len();
+                            // Add a space after completion
+                            input.insert(cursor_pos, ' ');
+                            cursor_pos += 1;
                             autocomplete_active = false;
                             autocomplete_selected = 0;
-                            continue;
                         }
-                        _ => {}
                     }
-                }
-
-                match key.code {
-                    KeyCode::Tab if !autocomplete_active => {
-                        // Cycle focus: input -> Send -> Cancel -> input
-                        focus = (focus + 1) % 3;
+                    Command::CloseCompletion => {
+                        // Cancel autocomplete but don't exit dialog
+                        autocomplete_active = false;
+                        autocomplete_selected = 0;
+                        autocomplete_dismissed = true;
+                    }
+                    Command::FocusNext => {
+                        // Cycle focus: input -> placeholders -> Send -> Cancel -> input
+                        focus = focus.next(!placeholders.is_empty());
                     }
-                    KeyCode::BackTab => {
+                    Command::FocusPrev => {
                         // Reverse cycle
-                        focus = if focus == 0 { 2 } else { focus - 1 };
+                        focus = focus.prev(!placeholders.is_empty());
                     }
                     // Enter to submit (text auto-wraps visually, no manual newlines needed)
-                    KeyCode::Enter => {
+                    Command::Send => {
                         match focus {
-                            0 => {
-                                // Submit from input field
-                                if !input.is_empty() {
-                                    return Ok(AppResult::Submit(input));
-                                }
-                            }
-                            1 => {
-                                // Send button
-                                if !input.is_empty() {
+                            // Submit from input field or Send button
+                            AskFocus::Input | AskFocus::Send if !input.is_empty() => {
+                                counter.refresh(&input, |text| expand(text, known));
+                                if self.confirm_unknown_words(&input, is_known)?
+                                    && self.confirm_over_limit(&counter)?
+                                {
+                                    self.pasted = attached;
                                     return Ok(AppResult::Submit(input));
                                 }
                             }
-                            2 => {
+                            AskFocus::Cancel => {
                                 // Cancel button
                                 return Ok(AppResult::Cancel);
                             }
                             _ => {}
                         }
                     }
-                    KeyCode::Esc => {
-                        return Ok(AppResult::Cancel);
+                    Command::InsertPlaceholder => {
+                        if let AskFocus::Placeholders(idx) = focus {
+                            // Insert the highlighted placeholder at the cursor
+                            let name = placeholder_names[idx];
+                            input.insert_str(cursor_pos, name);
+                            cursor_pos += name.len();
+                            focus = AskFocus::Input;
+                        }
                     }
-                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                        return Ok(AppResult::Cancel);
+                    Command::PlaceholderUp => {
+                        if let AskFocus::Placeholders(idx) = focus {
+                            focus = AskFocus::Placeholders(idx.saturating_sub(1));
+                        }
                     }
-                    // Up arrow for multi-line navigation
-                    KeyCode::Up if focus == 0 && !autocomplete_active => {
-                        let (cursor_line, cursor_col) = cursor_to_line_col(&input, cursor_pos);
-                        if cursor_line > 0 {
-                            // Move to previous line, same column (or end of line if shorter)
-                            cursor_pos = line_col_to_cursor(&input, cursor_line - 1, cursor_col);
-                            // Update scroll using visual lines
-                            let prefix_len = theme.prompt.chars().count();
-                            update_scroll_for_cursor(
-                                &input,
-                                cursor_pos,
-                                &mut scroll_offset,
-                                input_visible_lines as usize,
-                                last_text_width,
-                                prefix_len,
-                            );
+                    Command::PlaceholderDown => {
+                        if let AskFocus::Placeholders(idx) = focus {
+                            focus = AskFocus::Placeholders((idx + 1).min(placeholders.len() - 1));
                         }
                     }
-                    // Down arrow for multi-line navigation
-                    KeyCode::Down if focus == 0 && !autocomplete_active => {
-                        let (cursor_line, cursor_col) = cursor_to_line_col(&input, cursor_pos);
-                        let total_lines = count_lines(&input);
-                        if cursor_line < total_lines - 1 {
-                            // Move to next line, same column (or end of line if shorter)
-                            cursor_pos = line_col_to_cursor(&input, cursor_line + 1, cursor_col);
-                            // Update scroll using visual lines
-                            let prefix_len = theme.prompt.chars().count();
-                            update_scroll_for_cursor(
-                                &input,
-                                cursor_pos,
-                                &mut scroll_offset,
-                                input_visible_lines as usize,
-                                last_text_width,
-                                prefix_len,
-                            );
+                    Command::ViewPlaceholder => {
+                        if let (AskFocus::Placeholders(idx), Some(ctx)) = (focus, context) {
+                            let name = placeholder_names[idx];
+                            let expanded = ctx.expansion_with(name, known).text;
+                            let content = if expanded == name {
+                                self.strings.not_available.clone()
+                            } else {
+                                expanded
+                            };
+                            view = Some((name, content, 0));
                         }
                     }
-                    // Only handle text input when input field is focused
-                    KeyCode::Char(c)
-                        if focus == 0
-                            && !key
-                                .modifiers
-                                .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
-                    {
-                        input.insert(cursor_pos, c);
-                        cursor_pos += 1;
-                        // Update scroll (line might wrap)
-                        let prefix_len = theme.prompt.chars().count();
-                        update_scroll_for_cursor(
-                            &input,
-                            cursor_pos,
-                            &mut scroll_offset,
-                            input_visible_lines as usize,
-                            last_text_width,
-                            prefix_len,
-                        );
+                    Command::Cancel => {
+                        return Ok(AppResult::Cancel);
                     }
-                    KeyCode::Backspace if focus == 0 => {
-                        if cursor_pos > 0 {
-                            input.remove(cursor_pos - 1);
-                            cursor_pos -= 1;
-                            // Update scroll using visual lines
-                            let prefix_len = theme.prompt.chars().count();
-                            update_scroll_for_cursor(
-                                &input,
-                                cursor_pos,
-                                &mut scroll_offset,
-                                input_visible_lines as usize,
-                                last_text_width,
-                                prefix_len,
+                    Command::DropImage => {
+                        if let Some(image) = attached.pop() {
+                            debug_log(
+                                self.debug,
+                                &format!("run_ask: not attaching {}", image.path.display()),
                             );
+                            dismissed.push(image.path);
                         }
                     }
-                    KeyCode::Delete if focus == 0 => {
-                        if cursor_pos < input.len() {
-                            input.remove(cursor_pos);
+                    Command::Copy => {
+                        let message = self.copy(&expand(&input, known));
+                        toast.show(message, Instant::now());
+                    }
+                    Command::Compose => {
+                        // Compose the prompt in $VISUAL / $EDITOR
+                        let editor_cmd = editor::editor_command();
+                        let inside_helix = editor::inside_helix();
+                        if let Some(reason) =
+                            editor::refusal(&editor_cmd, inside_helix, self.allow_nested_editor)
+                        {
+                            notice = Some(reason);
+                            continue;
+                        }
+                        debug_log(self.debug, &format!("run_ask: editing in {}", editor_cmd));
+                        match self
+                            .backend
+                            .suspend(|| editor::edit_text(&editor_cmd, &input))?
+                        {
+                            Ok(edited) => {
+                                input = edited;
+                                cursor_pos = input.len();
+                                focus = AskFocus::Input;
+                            }
+                            Err(e) => notice = Some(format!("{:#}", e)),
                         }
                     }
-                    KeyCode::Left if focus == 0 => {
-                        if cursor_pos > 0 {
-                            cursor_pos -= 1;
-                            // Update scroll using visual lines
-                            let prefix_len = theme.prompt.chars().count();
-                            update_scroll_for_cursor(
-                                &input,
-                                cursor_pos,
-                                &mut scroll_offset,
-                                input_visible_lines as usize,
-                                last_text_width,
-                                prefix_len,
-                            );
+                    Command::TogglePreview => {
+                        // Toggle the selection preview under the context hint
+                        hint_expanded = !hint_expanded;
+                    }
+                    Command::PickRoute => {
+                        if !self.pick_route(animations)? {
+                            notice = Some(self.strings.no_choices.clone());
+                            continue;
                         }
+                        chip = route_chip(self.agent.as_deref(), self.model.as_deref(), &theme);
+                        prompt_len = theme.prompt.chars().count()
+                            + chip.iter().map(Span::width).sum::<usize>();
+                        // Back to the dialog, which the menu drew over
+                        self.integrate("ask")?;
                     }
-                    KeyCode::Right if focus == 0 => {
-                        if cursor_pos < input.len() {
-                            cursor_pos += 1;
-                            // Update scroll using visual lines
-                            let prefix_len = theme.prompt.chars().count();
-                            update_scroll_for_cursor(
-                                &input,
-                                cursor_pos,
-                                &mut scroll_offset,
-                                input_visible_lines as usize,
-                                last_text_width,
-                                prefix_len,
-                            );
+                    // Left and Right also move between the buttons
+                    Command::Left => match focus {
+                        AskFocus::Input if cursor_pos > 0 => {
+                            cursor_pos = readline::prev_boundary(&input, cursor_pos);
+                        }
+                        AskFocus::Send => focus = AskFocus::Input,
+                        AskFocus::Cancel => focus = AskFocus::Send,
+                        _ => {}
+                    },
+                    Command::Right => match focus {
+                        AskFocus::Input if cursor_pos < input.len() => {
+                            cursor_pos = readline::next_boundary(&input, cursor_pos);
+                        }
+                        AskFocus::Send => focus = AskFocus::Cancel,
+                        _ => {}
+                    },
+                    // The rest edit the input, when it has the focus
+                    _ if focus != AskFocus::Input => {}
+                    Command::Insert => {
+                        if let KeyCode::Char(c) = key.code {
+                            input.insert(cursor_pos, c);
+                            cursor_pos += c.len_utf8();
                         }
                     }
-                    KeyCode::Home if focus == 0 => {
+                    Command::Backspace => {
+                        readline::backspace(&mut input, &mut cursor_pos);
+                    }
+                    Command::Delete => {
+                        readline::delete(&mut input, &mut cursor_pos);
+                    }
+                    Command::DeleteWord => {
+                        readline::delete_word(&mut input, &mut cursor_pos);
+                    }
+                    Command::Transpose => {
+                        readline::transpose(&mut input, &mut cursor_pos);
+                    }
+                    Command::Upcase => {
+                        readline::change_case(&mut input, &mut cursor_pos, Case::Upper);
+                    }
+                    Command::Downcase => {
+                        readline::change_case(&mut input, &mut cursor_pos, Case::Lower);
+                    }
+                    Command::Capitalize => {
+                        readline::change_case(&mut input, &mut cursor_pos, Case::Capitalize);
+                    }
+                    Command::Home => {
                         // Move to start of current line
                         let (cursor_line, _) = cursor_to_line_col(&input, cursor_pos);
                         cursor_pos = line_col_to_cursor(&input, cursor_line, 0);
                     }
-                    KeyCode::End if focus == 0 => {
+                    Command::End => {
                         // Move to end of current line
                         let (cursor_line, _) = cursor_to_line_col(&input, cursor_pos);
                         let line_len = get_line_length(&input, cursor_line);
                         cursor_pos = line_col_to_cursor(&input, cursor_line, line_len);
                     }
-                    // Arrow keys for button navigation
-                    KeyCode::Left if focus > 0 => {
-                        focus -= 1;
+                    // Ctrl+Up/Down scroll the view, leaving the cursor where it is
+                    Command::ScrollUp | Command::ScrollDown => {
+                        let rows = if command == Command::ScrollUp { -1 } else { 1 };
+                        scroll.scroll_by(
+                            rows,
+                            cursor_visual_row,
+                            wrapped_lines.len(),
+                            input_visible_lines as usize,
+                        );
+                    }
+                    // Moving by lines waits for the popup to close
+                    _ if autocomplete_active => {}
+                    // Alt+Up/Down jump to the first or last line
+                    Command::First | Command::Last => {
+                        let row = if command == Command::First {
+                            0
+                        } else {
+                            wrapped_lines.len().saturating_sub(1)
+                        };
+                        cursor_pos = visual_pos_to_cursor(
+                            &wrapped_lines,
+                            &input,
+                            row,
+                            cursor_visual_col,
+                            tab_width,
+                        );
                     }
-                    KeyCode::Right if focus > 0 && focus < 2 => {
-                        focus += 1;
+                    // Up arrow for multi-line navigation
+                    Command::Up => {
+                        let (cursor_line, cursor_col) = cursor_to_line_col(&input, cursor_pos);
+                        if cursor_line > 0 {
+                            // Move to previous line, same column (or end of line if shorter)
+                            cursor_pos = line_col_to_cursor(&input, cursor_line - 1, cursor_col);
+                        }
+                    }
+                    // Down arrow for multi-line navigation
+                    Command::Down => {
+                        let (cursor_line, cursor_col) = cursor_to_line_col(&input, cursor_pos);
+                        let total_lines = count_lines(&input);
+                        if cursor_line < total_lines - 1 {
+                            // Move to next line, same column (or end of line if shorter)
+                            cursor_pos = line_col_to_cursor(&input, cursor_line + 1, cursor_col);
+                        }
+                    }
+                    // Page through long input a screenful of visual rows at a time
+                    Command::PageUp | Command::PageDown => {
+                        let (row, col) =
+                            cursor_to_visual_pos(&wrapped_lines, &input, cursor_pos, tab_width);
+                        let page = input_visible_lines as usize;
+                        let row = if command == Command::PageUp {
+                            row.saturating_sub(page)
+                        } else {
+                            row + page
+                        };
+                        cursor_pos =
+                            visual_pos_to_cursor(&wrapped_lines, &input, row, col, tab_width);
                     }
                     _ => {}
                 }
@@ -1090,243 +1943,685 @@ impl App {
     }
 
     /// Run the select (menu) mode
-    pub fn run_select(&mut self, items: &[SelectItem], animations: bool) -> Result<AppResult> {
+    /// `filter` pre-fills the filter text. With a `context`, an editable item
+    /// can be opened in the ask dialog (Tab or `e`) and changed before it is
+    /// sent; Esc there comes back to the menu as it was left. A refresh (see
+    /// [`App::with_refresh`]) replaces `items` in place.
+    pub fn run_select(
+        &mut self,
+        items: &mut Vec<SelectItem>,
+        filter: &str,
+        collapsed: &mut BTreeSet<String>,
+        context: Option<&Context>,
+        animations: bool,
+    ) -> Result<AppResult> {
         if items.is_empty() {
             return Ok(AppResult::Cancel);
         }
+        self.integrate("select")?;
 
-        let mut selected = 0;
-        let mut filter = String::new();
+        let mut state = SelectState::with_filter(items, filter).with_collapsed(collapsed.clone());
+        // Items from the cache are tagged until the refresh lands
+        let refresh = self.refresh.take();
+        let mut cached = refresh.is_some();
 
-        // Clone theme for use in closure
+        // Clone theme, strings and target for use in closure
         let theme = self.theme.clone();
+        let strings = self.strings.clone();
+        let target = self.target.clone();
+        let chip = route_chip(self.agent.as_deref(), self.model.as_deref(), &self.theme);
+        let cursor_style = self.cursor_style;
+        let tab_width = self.tab_width;
 
         let mut cursor_visible = true;
-        let mut cursor_timer = Instant::now();
+        let mut redraw = Redraw::new(Instant::now(), true);
+        let mut open = OpenAnimation::new(Instant::now(), self.effects);
 
         // Help text (static)
-        let help_text = "[Tab] Navigate  [Enter] Execute  [Esc] Abort";
+        let help_text = if context.is_some() {
+            &strings.select_help_edit
+        } else {
+            &strings.select_help
+        };
+
+        // What Ctrl+Y copied, shown in place of the help text for a moment
+        let mut toast = Toast::default();
+
+        // Help overlay scroll offset, while open
+        let mut help_scroll: Option<usize> = None;
+        let mut last_area_height: u16 = 24;
+        // First visible item and the number of visible rows (a page for PgUp/PgDn)
+        let mut list_scroll: usize = 0;
+        let mut list_height: usize = 1;
 
         loop {
-            // Update cursor blink
-            if cursor_timer.elapsed() >= Duration::from_millis(530) {
+            if let Some(news) = refresh.as_ref().and_then(|refresh| refresh.try_recv().ok()) {
+                if let Refresh::Items(refreshed) = news {
+                    debug_log(
+                        self.debug,
+                        &format!("run_select: refreshed, {} items", refreshed.len()),
+                    );
+                    let filter = state.filter().to_string();
+                    let kept = state.collapsed().clone();
+                    let selected = state.selected_item().map(|item| item.value.clone());
+                    *items = refreshed;
+                    state = SelectState::with_filter(items, &filter).with_collapsed(kept);
+                    if let Some(value) = selected {
+                        state.select_value(&value);
+                    }
+                }
+                cached = false;
+                redraw.mark();
+            }
+            if let Some(focused) = self.backend.focus_change() {
+                redraw.focus(focused, Instant::now());
+                cursor_visible = true;
+                if !focused {
+                    open.finish();
+                }
+            }
+            if redraw.blink(Instant::now()) {
                 cursor_visible = !cursor_visible;
-                cursor_timer = Instant::now();
+            }
+            redraw.resize(self.backend.size()?);
+            if open.tick(Instant::now()) {
+                redraw.mark();
+            }
+            if toast.tick(Instant::now()) {
+                redraw.mark();
             }
 
-            // Filter items
-            let filtered: Vec<(usize, &SelectItem)> = items
-                .iter()
-                .enumerate()
-                .filter(|(_, item)| {
-                    if filter.is_empty() {
-                        true
-                    } else {
-                        item.name.to_lowercase().contains(&filter.to_lowercase())
-                            || item
-                                .description
-                                .to_lowercase()
-                                .contains(&filter.to_lowercase())
-                    }
-                })
-                .collect();
+            // Typed or pasted filter keys are taken together, refiltering once
+            if state.is_pending() && self.backend.key_waiting() {
+                redraw.mark();
+            } else {
+                state.refilter();
+            }
 
-            // Clamp selection
-            if selected >= filtered.len() {
-                selected = filtered.len().saturating_sub(1);
+            // Dialog size; the inline viewport is the dialog, without a margin
+            let dialog_height = state.row_capacity() as u16 + 6;
+            let margin = if self.backend.inline() { 0 } else { 4 };
+            if self.backend.fit_viewport(dialog_height)? {
+                redraw.mark();
             }
 
             // Draw UI
-            self.terminal.draw(|frame| {
-                let area = frame.area();
-
-                // Dialog size
-                let dialog_width = area.width.min(70);
-                let dialog_height = (items.len() as u16 + 6).min(area.height - 4);
-                let dialog_area = Rect {
-                    x: (area.width - dialog_width) / 2,
-                    y: (area.height - dialog_height) / 2,
-                    width: dialog_width,
-                    height: dialog_height,
-                };
+            if !state.is_pending() && redraw.take() {
+                self.backend.draw(|frame| {
+                    let area = frame.area();
+                    last_area_height = area.height;
+
+                    let dialog_width = area.width.min(70);
+                    let dialog_height = dialog_height.min(area.height.saturating_sub(margin));
+                    let dialog_area = centered(area, dialog_width, dialog_height);
+                    if open.running() {
+                        let rect = open.current_rect(dialog_area);
+                        effects::render_opening(frame, rect, &theme, theme.primary);
+                        return;
+                    }
 
-                // Clear background
-                frame.render_widget(Clear, dialog_area);
+                    // Clear background
+                    frame.render_widget(Clear, dialog_area);
 
-                // Dialog box with themed styling
-                let title = format!("{} SELECT ", theme.title);
-                let block = Block::default()
-                    .title(title)
-                    .title_style(
+                    // Dialog box with themed styling
+                    let title = format!("{} {} ", theme.title, strings.select_title);
+                    let mut block = Block::default()
+                        .title(title.as_str())
+                        .title_style(
+                            Style::default()
+                                .fg(theme.primary)
+                                .add_modifier(Modifier::BOLD),
+                        )
+                        .title(target_title(
+                            target.as_ref(),
+                            &theme,
+                            dialog_width,
+                            title.width(),
+                        ))
+                        .borders(Borders::ALL)
+                        .border_type(theme.border_type())
+                        .border_style(Style::default().fg(theme.primary));
+                    if cached {
+                        block = block.title_bottom(
+                            Line::styled(
+                                format!(" {} ", strings.cached),
+                                Style::default().fg(theme.dim),
+                            )
+                            .right_aligned(),
+                        );
+                    }
+
+                    let inner = block.inner(dialog_area);
+                    frame.render_widget(block, dialog_area);
+
+                    // Filter input (themed)
+                    let filter_prompt = Span::styled(
+                        theme.filter_prompt.as_str(),
                         Style::default()
-                            .fg(theme.primary)
+                            .fg(theme.warning)
                             .add_modifier(Modifier::BOLD),
-                    )
-                    .borders(Borders::ALL)
-                    .border_type(theme.border_type())
-                    .border_style(Style::default().fg(theme.primary));
-
-                let inner = block.inner(dialog_area);
-                frame.render_widget(block, dialog_area);
-
-                // Filter input (themed)
-                let filter_prompt = Span::styled(
-                    theme.filter_prompt.as_str(),
-                    Style::default()
-                        .fg(theme.warning)
-                        .add_modifier(Modifier::BOLD),
-                );
-                let filter_text = Span::styled(&filter, Style::default().fg(theme.input));
-                let cursor_char = if cursor_visible { "█" } else { " " };
-                let cursor_span = Span::styled(cursor_char, Style::default().fg(theme.primary));
-                let filter_line = Line::from(vec![filter_prompt, filter_text, cursor_span]);
-
-                let filter_para = Paragraph::new(filter_line);
-                frame.render_widget(
-                    filter_para,
-                    Rect {
+                    );
+                    let mut filter_spans = vec![filter_prompt];
+                    filter_spans.extend(chip.iter().cloned());
+                    let filter_prompt_width: usize = filter_spans.iter().map(Span::width).sum();
+                    // An active `>category` restriction is set apart in the accent color
+                    let restriction = state.category_restriction().unwrap_or_default();
+                    let filter_rest = &state.filter()[restriction.len()..];
+                    filter_spans.push(Span::styled(
+                        restriction,
+                        Style::default()
+                            .fg(theme.accent)
+                            .add_modifier(Modifier::BOLD),
+                    ));
+                    filter_spans.extend(cursor_line_spans(
+                        filter_rest,
+                        Style::default().fg(theme.input),
+                        Some(filter_rest.len()),
+                        cursor_style,
+                        cursor_visible,
+                        tab_width,
+                        &theme,
+                    ));
+                    let filter_line = Line::from(filter_spans);
+                    if cursor_style == CursorStyle::Terminal {
+                        frame.set_cursor_position(Position {
+                            x: inner.x + 1 + (filter_prompt_width + state.filter().width()) as u16,
+                            y: inner.y,
+                        });
+                    }
+
+                    let filter_para = Paragraph::new(filter_line);
+                    frame.render_widget(
+                        filter_para,
+                        Rect {
+                            x: inner.x + 1,
+                            y: inner.y,
+                            width: inner.width.saturating_sub(2),
+                            height: 1,
+                        },
+                    );
+
+                    // Items
+                    let items_area = Rect {
                         x: inner.x + 1,
-                        y: inner.y,
+                        y: inner.y + 2,
                         width: inner.width.saturating_sub(2),
-                        height: 1,
-                    },
-                );
-
-                // Items
-                let items_area = Rect {
-                    x: inner.x + 1,
-                    y: inner.y + 2,
-                    width: inner.width.saturating_sub(2),
-                    height: inner.height.saturating_sub(4),
-                };
+                        height: inner.height.saturating_sub(4),
+                    };
 
-                for (i, (_, item)) in filtered.iter().enumerate() {
-                    if i as u16 >= items_area.height {
-                        break;
-                    }
+                    list_height = (items_area.height as usize).max(1);
+                    scroll_to_row(state.selected(), &mut list_scroll, list_height);
 
-                    let (style, prefix) = if i == selected {
-                        (
-                            Style::default()
-                                .fg(Color::Black)
-                                .bg(theme.primary)
-                                .add_modifier(Modifier::BOLD),
-                            theme.selected_prefix.as_str(),
-                        )
-                    } else {
-                        (
-                            Style::default().fg(theme.text),
-                            theme.unselected_prefix.as_str(),
-                        )
-                    };
+                    for (i, row) in state
+                        .rows()
+                        .iter()
+                        .enumerate()
+                        .skip(list_scroll)
+                        .take(list_height)
+                    {
+                        let (style, prefix) = if i == state.selected() {
+                            (
+                                Style::default()
+                                    .fg(Color::Black)
+                                    .bg(theme.primary)
+                                    .add_modifier(Modifier::BOLD),
+                                theme.selected_prefix.as_str(),
+                            )
+                        } else if matches!(row, SelectRow::Header { .. }) {
+                            (
+                                Style::default()
+                                    .fg(theme.accent)
+                                    .add_modifier(Modifier::BOLD),
+                                theme.unselected_prefix.as_str(),
+                            )
+                        } else {
+                            (
+                                Style::default().fg(theme.text),
+                                theme.unselected_prefix.as_str(),
+                            )
+                        };
 
-                    let text = format!("{}{:<12} {}", prefix, item.name, item.description);
-                    let para = Paragraph::new(text).style(style);
+                        let line = match *row {
+                            SelectRow::Header {
+                                category,
+                                hidden: 0,
+                            } => Line::from(format!("{}▾ {}", prefix, category)),
+                            SelectRow::Header { category, hidden } => {
+                                let hidden = strings::fill(
+                                    &strings.hidden,
+                                    &[("count", &hidden.to_string())],
+                                );
+                                Line::from(format!("{}▸ {} ({})", prefix, category, hidden))
+                            }
+                            SelectRow::Item(index) => {
+                                let item = &state.items()[index];
+                                let name = format!("{}{:<12} ", prefix, item.name);
+                                // The description's markdown, cut to the room left
+                                let description = markdown::render_inline_md(
+                                    item.description.lines().next().unwrap_or(""),
+                                    &theme,
+                                );
+                                let room = (items_area.width as usize).saturating_sub(name.width());
+                                let mut spans = vec![Span::raw(name)];
+                                spans.extend(markdown::truncate_spans(&description, room));
+                                Line::from(spans)
+                            }
+                        };
+                        let para = Paragraph::new(line).style(style);
 
+                        frame.render_widget(
+                            para,
+                            Rect {
+                                x: items_area.x,
+                                y: items_area.y + (i - list_scroll) as u16,
+                                width: items_area.width,
+                                height: 1,
+                            },
+                        );
+                    }
+
+                    // Help text (themed), replaced by the toast
+                    let (help_display, help_color) = match toast.text() {
+                        Some(msg) => (format!(" {} ", msg), theme.accent),
+                        None => (format!(" {} ", help_text), theme.dim),
+                    };
+                    let help_para = Paragraph::new(help_display)
+                        .style(Style::default().fg(help_color))
+                        .alignment(Alignment::Center);
                     frame.render_widget(
-                        para,
+                        help_para,
                         Rect {
-                            x: items_area.x,
-                            y: items_area.y + i as u16,
-                            width: items_area.width,
+                            x: inner.x,
+                            y: inner.y + inner.height - 1,
+                            width: inner.width,
                             height: 1,
                         },
                     );
-                }
 
-                // Help text (themed)
-                let help_display = format!(" {} ", help_text);
-                let help_para = Paragraph::new(help_display)
-                    .style(Style::default().fg(theme.dim))
-                    .alignment(Alignment::Center);
-                frame.render_widget(
-                    help_para,
-                    Rect {
-                        x: inner.x,
-                        y: inner.y + inner.height - 1,
-                        width: inner.width,
-                        height: 1,
-                    },
-                );
-            })?;
+                    // Keybinding help overlay (topmost)
+                    if let Some(scroll) = help_scroll {
+                        help::render_help(
+                            frame,
+                            area,
+                            &theme,
+                            &strings,
+                            keymap::SELECT_BINDINGS,
+                            scroll,
+                        );
+                    }
+
+                    theme.apply_emphasis(frame.buffer_mut());
+                })?;
+            }
 
             // Handle input from /dev/tty
-            if let Some(key) = self.read_key(Duration::from_millis(16))? {
-                match key.code {
-                    KeyCode::Enter => {
-                        if let Some((_, item)) = filtered.get(selected) {
+            let deadline = open.deadline().into_iter().chain(toast.deadline()).min();
+            let timeout = redraw.poll_timeout(Instant::now(), deadline);
+            if let Some(key) = self.backend.read_key(timeout)? {
+                redraw.key(Instant::now());
+                open.finish();
+                // Anything but more filter text needs the rows of the filter typed so far
+                if !is_filter_edit(&key, state.filter().is_empty()) {
+                    state.refilter();
+                }
+                // The help overlay captures all keys while open
+                if let Some(scroll) = help_scroll {
+                    let max_scroll = help::max_scroll(keymap::SELECT_BINDINGS, last_area_height);
+                    help_scroll = help_scroll_key(&key, scroll, max_scroll);
+                    continue;
+                }
+                let command = keymap::lookup(keymap::SELECT_BINDINGS, &key, |when| match when {
+                    When::Always => true,
+                    When::EmptyFilter => state.filter().is_empty(),
+                    When::OnHeader => state.selected_item().is_none(),
+                    _ => false,
+                });
+                let Some(command) = command else {
+                    continue;
+                };
+
+                match command {
+                    Command::Help => {
+                        help_scroll = Some(0);
+                    }
+                    Command::Edit => {
+                        let Some(ctx) = context else {
+                            // Without a dialog to edit in, `e` is filter text
+                            if let KeyCode::Char(c) = key.code {
+                                state.edit_filter(|filter| filter.push(c));
+                            }
+                            continue;
+                        };
+                        let Some(item) = state.selected_item().filter(|item| item.editable) else {
+                            continue;
+                        };
+                        debug_log(self.debug, &format!("run_select: editing {:?}", item.name));
+                        let hint = ctx.format_this();
+                        match self.run_ask(&item.value, hint.as_deref(), Some(ctx), animations)? {
+                            AppResult::Submit(text) => return Ok(AppResult::Submit(text)),
+                            AppResult::Cancel => {
+                                // Back to the menu, which the ask dialog drew over
+                                self.integrate("select")?;
+                                redraw.mark();
+                            }
+                        }
+                        continue;
+                    }
+                    Command::Send => {
+                        if let Some(item) = state.selected_item() {
                             return Ok(AppResult::Submit(item.value.clone()));
                         }
                     }
-                    KeyCode::Esc => {
-                        return Ok(AppResult::Cancel);
+                    Command::Toggle => {
+                        state.toggle();
                     }
-                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    Command::Collapse => {
+                        state.collapse();
+                    }
+                    Command::Expand => {
+                        state.expand();
+                    }
+                    Command::Cancel => {
                         return Ok(AppResult::Cancel);
                     }
-                    KeyCode::Up | KeyCode::Char('k') => {
-                        if selected > 0 {
-                            selected -= 1;
+                    Command::Copy => {
+                        if let Some(item) = state.selected_item() {
+                            let text = match context {
+                                Some(ctx) => ctx.expansion(&item.value).text,
+                                None => item.value.clone(),
+                            };
+                            let message = self.copy(&text);
+                            toast.show(message, Instant::now());
                         }
                     }
-                    KeyCode::Char('p') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                        if selected > 0 {
-                            selected -= 1;
-                        }
+                    Command::Up => {
+                        state.move_up();
                     }
-                    KeyCode::Down | KeyCode::Char('j') => {
-                        if selected < filtered.len().saturating_sub(1) {
-                            selected += 1;
-                        }
+                    Command::Down => {
+                        state.move_down();
                     }
-                    KeyCode::Char('n') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                        if selected < filtered.len().saturating_sub(1) {
-                            selected += 1;
-                        }
+                    Command::PageUp => {
+                        state.move_up_by(list_height);
                     }
-                    KeyCode::Char(c)
-                        if !key
-                            .modifiers
-                            .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
-                    {
-                        filter.push(c);
+                    Command::PageDown => {
+                        state.move_down_by(list_height);
+                    }
+                    Command::Insert => {
+                        if let KeyCode::Char(c) = key.code {
+                            state.edit_filter(|filter| filter.push(c));
+                        }
                     }
-                    KeyCode::Backspace => {
-                        filter.pop();
+                    Command::Backspace => {
+                        state.edit_filter(|filter| {
+                            filter.pop();
+                        });
                     }
                     _ => {}
                 }
+                collapsed.clone_from(state.collapsed());
             }
         }
     }
-}
 
-impl Drop for App {
-    fn drop(&mut self) {
-        let _ = self.restore();
+    /// Put `text` on the clipboard, returning what the toast says
+    fn copy(&mut self, text: &str) -> String {
+        match self.backend.copy(text) {
+            Ok(()) => {
+                debug_log(self.debug, &format!("copied {} bytes", text.len()));
+                strings::fill(
+                    &self.strings.copied,
+                    &[("size", &size_label(text.len() as u64))],
+                )
+            }
+            Err(e) => format!("{:#}", e),
+        }
     }
-}
 
-/// An item in the select menu
-#[derive(Debug, Clone)]
-pub struct SelectItem {
-    /// Display name
-    pub name: String,
-    /// Description
-    pub description: String,
-    /// Value to return when selected
-    pub value: String,
-    /// Category for grouping
-    pub category: String,
-}
+    /// Alt+A in the ask dialog: pick the agent or the model for this run in
+    /// the select menu
+    ///
+    /// Returns false, without opening the menu, when there is nothing to pick.
+    fn pick_route(&mut self, animations: bool) -> Result<bool> {
+        let current = |name: &str, active: Option<&str>| {
+            if active == Some(name) {
+                self.strings.current.clone()
+            } else {
+                String::new()
+            }
+        };
+        let (agents, models) = &self.choices;
+        let mut items: Vec<SelectItem> = agents
+            .iter()
+            .map(|name| {
+                let description = current(name, self.agent.as_deref());
+                SelectItem::new(
+                    &format!("@{}", name),
+                    &description,
+                    &format!("agent:{}", name),
+                    "AGENTS",
+                )
+            })
+            .chain(models.iter().map(|model| {
+                let description = current(model, self.model.as_deref());
+                SelectItem::new(model, &description, &format!("model:{}", model), "MODELS")
+            }))
+            .collect();
+        if items.is_empty() {
+            return Ok(false);
+        }
+        let result = self.run_select(&mut items, "", &mut BTreeSet::new(), None, animations)?;
+        if let AppResult::Submit(value) = result {
+            debug_log(self.debug, &format!("pick_route: {}", value));
+            if let Some(agent) = value.strip_prefix("agent:") {
+                self.agent = Some(agent.to_string());
+                self.chosen.agent = self.agent.clone();
+            } else if let Some(model) = value.strip_prefix("model:") {
+                self.model = Some(model.to_string());
+                self.chosen.model = self.model.clone();
+            }
+        }
+        Ok(true)
+    }
 
-impl SelectItem {
-    pub fn new(name: &str, description: &str, value: &str, category: &str) -> Self {
-        Self {
-            name: name.to_string(),
-            description: description.to_string(),
-            value: value.to_string(),
-            category: category.to_string(),
+    /// Whether to send `text` with `@words` nothing will expand (see `typos`)
+    /// With any, the user is asked first and returned to the ask dialog.
+    fn confirm_unknown_words(
+        &mut self,
+        text: &str,
+        is_known: impl Fn(&str) -> bool,
+    ) -> Result<bool> {
+        let mut words: Vec<&str> = typos::unknown_words(text, is_known)
+            .into_iter()
+            .map(|range| &text[range])
+            .collect();
+        if words.is_empty() {
+            return Ok(true);
+        }
+        words.dedup();
+        let question = strings::fill(
+            &self.strings.unknown_words,
+            &[
+                ("count", &words.len().to_string()),
+                ("words", &words.join(", ")),
+            ],
+        );
+        let choices = [
+            self.strings.send_anyway.clone(),
+            self.strings.keep_editing.clone(),
+        ];
+        let choices = [choices[0].as_str(), choices[1].as_str()];
+        let send = self.run_choice(&question, &choices)? == Some(0);
+        debug_log(
+            self.debug,
+            &format!("run_ask: unknown @words {:?}, send = {}", words, send),
+        );
+        self.integrate("ask")?;
+        Ok(send)
+    }
+
+    /// Whether to send a prompt whose estimate may be over the hard limit
+    /// Over the limit, the user is asked first and returned to the ask dialog.
+    fn confirm_over_limit(&mut self, counter: &PromptCounter) -> Result<bool> {
+        if counter.level(self.token_budget) != Level::OverLimit {
+            return Ok(true);
+        }
+        let question = strings::fill(
+            &self.strings.over_limit,
+            &[
+                ("tokens", &crate::util::thousands(counter.tokens())),
+                ("limit", &crate::util::thousands(self.token_budget.limit)),
+            ],
+        );
+        let choices = [
+            self.strings.send_anyway.clone(),
+            self.strings.keep_editing.clone(),
+        ];
+        let choices = [choices[0].as_str(), choices[1].as_str()];
+        let send = self.run_choice(&question, &choices)? == Some(0);
+        debug_log(
+            self.debug,
+            &format!("run_ask: over the token limit, send = {}", send),
+        );
+        self.integrate("ask")?;
+        Ok(send)
+    }
+
+    /// Ask a question with a few answers, each picked by its first Latin
+    /// letter or digit, or by its number when it has none (as translations may)
+    /// Enter picks the first answer; Esc or Ctrl+C returns None.
+    pub fn run_choice(&mut self, message: &str, choices: &[&str]) -> Result<Option<usize>> {
+        self.integrate("confirm")?;
+        let theme = self.theme.clone();
+        let keys: Vec<char> = choices
+            .iter()
+            .enumerate()
+            .map(|(i, c)| match c.chars().find(char::is_ascii_alphanumeric) {
+                Some(key) => key.to_ascii_lowercase(),
+                None => char::from_digit(i as u32 + 1, 10).unwrap_or(' '),
+            })
+            .collect();
+        let help_text = choices
+            .iter()
+            .zip(&keys)
+            .enumerate()
+            .map(|(i, (choice, key))| {
+                if i == 0 {
+                    format!("[{}/Enter] {}", key, choice)
+                } else {
+                    format!("[{}] {}", key, choice)
+                }
+            })
+            .chain(std::iter::once(format!(
+                "[Esc] {}",
+                self.strings.choice_cancel
+            )))
+            .collect::<Vec<_>>()
+            .join("  ");
+
+        // A question quoting a diff shows it in color, wider and as tall as it needs
+        let is_diff = diff::looks_like_diff(message);
+        let (width, height) = if is_diff {
+            (100, (message.lines().count() as u16 + 3).max(6))
+        } else {
+            (60, 6)
+        };
+
+        let mut redraw = Redraw::new(Instant::now(), false);
+        let mut open = OpenAnimation::new(Instant::now(), self.effects);
+        loop {
+            if let Some(focused) = self.backend.focus_change() {
+                redraw.focus(focused, Instant::now());
+                if !focused {
+                    open.finish();
+                }
+            }
+            redraw.resize(self.backend.size()?);
+            if open.tick(Instant::now()) {
+                redraw.mark();
+            }
+            if self.backend.fit_viewport(height)? {
+                redraw.mark();
+            }
+            if redraw.take() {
+                self.backend.draw(|frame| {
+                    let area = frame.area();
+                    let dialog_width = area.width.min(width);
+                    let dialog_height = area.height.min(height);
+                    let dialog_area = centered(area, dialog_width, dialog_height);
+                    if open.running() {
+                        let rect = open.current_rect(dialog_area);
+                        effects::render_opening(frame, rect, &theme, theme.warning);
+                        return;
+                    }
+
+                    frame.render_widget(Clear, dialog_area);
+                    let block = Block::default()
+                        .title(theme.title.as_str())
+                        .title_style(
+                            Style::default()
+                                .fg(theme.primary)
+                                .add_modifier(Modifier::BOLD),
+                        )
+                        .borders(Borders::ALL)
+                        .border_type(theme.border_type())
+                        .border_style(Style::default().fg(theme.warning));
+                    let inner = block.inner(dialog_area);
+                    frame.render_widget(block, dialog_area);
+
+                    let message_para = if is_diff {
+                        let width = inner.width.saturating_sub(2) as usize;
+                        Paragraph::new(diff::render(message, width, &theme))
+                    } else {
+                        Paragraph::new(message)
+                            .style(Style::default().fg(theme.text))
+                            .wrap(Wrap { trim: true })
+                    };
+                    frame.render_widget(
+                        message_para,
+                        Rect {
+                            x: inner.x + 1,
+                            y: inner.y,
+                            width: inner.width.saturating_sub(2),
+                            height: inner.height.saturating_sub(1),
+                        },
+                    );
+
+                    let help_para = Paragraph::new(format!(" {} ", help_text))
+                        .style(Style::default().fg(theme.dim))
+                        .alignment(Alignment::Center);
+                    frame.render_widget(
+                        help_para,
+                        Rect {
+                            x: inner.x,
+                            y: inner.y + inner.height.saturating_sub(1),
+                            width: inner.width,
+                            height: 1,
+                        },
+                    );
+
+                    theme.apply_emphasis(frame.buffer_mut());
+                })?;
+            }
+
+            let timeout = redraw.poll_timeout(Instant::now(), open.deadline());
+            if let Some(key) = self.backend.read_key(timeout)? {
+                redraw.key(Instant::now());
+                open.finish();
+                match key.code {
+                    KeyCode::Enter => return Ok(Some(0)),
+                    KeyCode::Esc => return Ok(None),
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        return Ok(None);
+                    }
+                    KeyCode::Char(c) => {
+                        let c = c.to_ascii_lowercase();
+                        if let Some(i) = keys.iter().position(|k| *k == c) {
+                            return Ok(Some(i));
+                        }
+                    }
+                    _ => {}
+                }
+            }
         }
     }
 }
@@ -1378,6 +2673,12 @@ mod tests {
         assert_eq!(line_col_to_cursor(text, 2, 3), 15);
         // Clamped to line length
         assert_eq!(line_col_to_cursor(text, 1, 100), 11); // "world" is 5 chars, so max is position 11
+
+        // Columns are characters, not bytes
+        let text = "日本\néa";
+        assert_eq!(line_col_to_cursor(text, 0, 1), 3);
+        assert_eq!(line_col_to_cursor(text, 1, 1), 9);
+        assert_eq!(line_col_to_cursor(text, 1, 5), 10);
     }
 
     #[test]
@@ -1422,53 +2723,849 @@ mod tests {
     }
 
     #[test]
-    fn test_wrap_text_no_wrap_needed() {
-        let text = "hello";
-        let wrapped = wrap_text(text, 20, 2);
-        assert_eq!(wrapped.len(), 1);
-        assert_eq!(wrapped[0].text, "hello");
-        assert_eq!(wrapped[0].logical_line, 0);
-        assert!(wrapped[0].is_first);
+    fn test_cursor_line_spans() {
+        let theme = Theme::default();
+        let style = Style::default();
+        let reversed = style.add_modifier(Modifier::REVERSED);
+        let spans = |text, cursor, cursor_style, visible| {
+            cursor_line_spans(text, style, cursor, cursor_style, visible, 4, &theme)
+        };
+
+        // Reversed: mid-line (multi-byte), end of line and empty line
+        assert_eq!(
+            spans("aéb", Some(1), CursorStyle::Reversed, true),
+            [
+                Span::styled("a", style),
+                Span::styled("é", reversed),
+                Span::styled("b", style)
+            ]
+        );
+        assert_eq!(
+            spans("ab", Some(2), CursorStyle::Reversed, true)[1],
+            Span::styled(" ", reversed)
+        );
+        assert_eq!(
+            spans("", Some(0), CursorStyle::Reversed, true),
+            [
+                Span::styled("", style),
+                Span::styled(" ", reversed),
+                Span::styled("", style)
+            ]
+        );
+        // Blinked off, not on the cursor line, or left to the terminal: plain text
+        for (cursor, cursor_style, visible) in [
+            (Some(1), CursorStyle::Reversed, false),
+            (None, CursorStyle::Reversed, true),
+            (Some(1), CursorStyle::Terminal, true),
+        ] {
+            assert_eq!(
+                spans("ab", cursor, cursor_style, visible),
+                [Span::styled("ab", style)]
+            );
+        }
+
+        // Tabs as spaces, the one under the cursor reversed whole
+        assert_eq!(
+            spans("a\tb\tc", Some(3), CursorStyle::Reversed, true),
+            [
+                Span::styled("a   b", style),
+                Span::styled("   ", reversed),
+                Span::styled("c", style)
+            ]
+        );
+        assert_eq!(
+            spans("\tx", None, CursorStyle::Reversed, true),
+            [Span::styled("    x", style)]
+        );
+
+        let block = spans("ab", Some(0), CursorStyle::Block, true);
+        assert_eq!(block.len(), 2);
+        assert_eq!(block[1].content, "█");
+    }
+
+    #[test]
+    fn test_marked_line_spans() {
+        let theme = Theme::default();
+        let style = Style::default();
+        let marked = style.add_modifier(Modifier::UNDERLINED);
+        let spans = |text, mark: std::ops::Range<usize>, cursor| {
+            marked_line_spans(
+                text,
+                (style, marked, &[mark]),
+                cursor,
+                CursorStyle::Reversed,
+                true,
+                4,
+                &theme,
+            )
+        };
+
+        assert_eq!(
+            spans("a @x b", 2..4, None),
+            [
+                Span::styled("a ", style),
+                Span::styled("@x", marked),
+                Span::styled(" b", style)
+            ]
+        );
+        // The cursor inside a mark keeps its style, reversed; tabs after it
+        // still reach their tab stops
+        assert_eq!(
+            spans("@xy\tb", 0..3, Some(1)),
+            [
+                Span::styled("@", marked),
+                Span::styled("x", marked.add_modifier(Modifier::REVERSED)),
+                Span::styled("y", marked),
+                Span::styled(" b", style)
+            ]
+        );
+        assert_eq!(
+            spans("@x", 0..2, Some(2)),
+            [
+                Span::styled("@x", marked),
+                Span::styled(" ", style.add_modifier(Modifier::REVERSED))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_filter_edit_keys() {
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        assert!(is_filter_edit(&key(KeyCode::Char('r')), true));
+        assert!(is_filter_edit(&key(KeyCode::Backspace), false));
+        // Typed once there is a filter
+        assert!(is_filter_edit(&key(KeyCode::Char('e')), false));
+        assert!(is_filter_edit(&key(KeyCode::Char('l')), false));
+        // Moves, collapses, help and edit
+        for c in ['j', 'k'] {
+            assert!(!is_filter_edit(&key(KeyCode::Char(c)), false));
+        }
+        for c in ['h', 'l', 'e', '?'] {
+            assert!(!is_filter_edit(&key(KeyCode::Char(c)), true));
+        }
+        assert!(!is_filter_edit(&key(KeyCode::Enter), false));
+        assert!(!is_filter_edit(
+            &KeyEvent::new(KeyCode::Char('u'), KeyModifiers::CONTROL),
+            false
+        ));
+    }
+
+    #[test]
+    fn test_help_scroll_keys() {
+        let down = KeyEvent::new(KeyCode::Down, KeyModifiers::NONE);
+        let esc = KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE);
+        assert_eq!(help_scroll_key(&down, 0, 3), Some(1));
+        assert_eq!(help_scroll_key(&down, 3, 3), Some(3));
+        assert_eq!(help_scroll_key(&esc, 2, 3), None);
+        let page_down = KeyEvent::new(KeyCode::PageDown, KeyModifiers::NONE);
+        let page_up = KeyEvent::new(KeyCode::PageUp, KeyModifiers::NONE);
+        assert_eq!(help_scroll_key(&page_down, 0, 30), Some(10));
+        assert_eq!(help_scroll_key(&page_down, 25, 30), Some(30));
+        assert_eq!(help_scroll_key(&page_up, 5, 30), Some(0));
+    }
+
+    #[test]
+    fn test_ask_focus_cycle() {
+        let mut focus = AskFocus::Input;
+        focus = focus.next(true);
+        assert_eq!(focus, AskFocus::Placeholders(0));
+        focus = focus.next(true);
+        assert_eq!(focus, AskFocus::Send);
+        focus = focus.next(true);
+        assert_eq!(focus, AskFocus::Cancel);
+        focus = focus.next(true);
+        assert_eq!(focus, AskFocus::Input);
+
+        assert_eq!(AskFocus::Input.next(false), AskFocus::Send);
+        assert_eq!(AskFocus::Send.prev(false), AskFocus::Input);
+        assert_eq!(AskFocus::Send.prev(true), AskFocus::Placeholders(0));
+        assert_eq!(AskFocus::Placeholders(3).prev(true), AskFocus::Input);
+        assert_eq!(AskFocus::Input.prev(true), AskFocus::Cancel);
+    }
+
+    // Headless sessions: scripted keys against an in-memory screen
+
+    use crate::tui::backend::{rows, Headless};
+    use ratatui::buffer::Buffer;
+
+    fn press(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn typed(text: &str) -> impl Iterator<Item = KeyEvent> + '_ {
+        text.chars().map(|c| press(KeyCode::Char(c)))
+    }
+
+    fn headless(
+        width: u16,
+        height: u16,
+        keys: impl IntoIterator<Item = KeyEvent>,
+    ) -> App<Headless> {
+        App::with_backend(
+            Headless::new(width, height, keys),
+            false,
+            ThemeKind::default(),
+        )
+    }
+
+    fn context() -> Context {
+        let mut ctx = Context::default();
+        ctx.file = Some("src/main.rs".to_string());
+        ctx.line = Some(42);
+        ctx
+    }
+
+    /// Column and row of `needle` on the screen
+    fn locate(buffer: &Buffer, needle: &str) -> Option<(u16, u16)> {
+        rows(buffer).iter().enumerate().find_map(|(y, row)| {
+            let i = row.find(needle)?;
+            Some((row[..i].chars().count() as u16, y as u16))
+        })
+    }
+
+    #[test]
+    fn test_headless_ask_wraps_and_submits() {
+        let text = "Explain why the parser allocates a new buffer for every line it reads";
+        let mut app = headless(60, 24, typed(text).chain([press(KeyCode::Enter)]));
+        let result = app.run_ask("", None, None, false).unwrap();
+        assert!(
+            matches!(result, AppResult::Submit(ref t) if t == text),
+            "{:?}",
+            result
+        );
+
+        // The screen as Enter was pressed
+        let screen = rows(app.backend().shown(text.len()));
+        assert_eq!(
+            screen[5..19],
+            [
+                "┏ ░▒▓ OPENCODE ▓▒░ ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━┓",
+                "┃ λ Explain why the parser allocates a new buffer for ever ┃",
+                "┃ ↪ y line it reads█                                       ┃",
+                "┃                                                          ┃",
+                "┃                                                          ┃",
+                "┃                                     69 chars · ~0 tokens ┃",
+                "┃                                                          ┃",
+                "┃   SEND      CANCEL                                       ┃",
+                "┃                                                          ┃",
+                "┃                                                          ┃",
+                "┃                                                          ┃",
+                "┃                                                          ┃",
+                "┃ [Tab] Focus  [^G] Editor  [M-h] Keys  [Enter] Send  [Esc]┃",
+                "┗━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━┛",
+            ]
+        );
     }
 
     #[test]
-    fn test_wrap_text_single_wrap() {
-        let text = "hello world foo bar";
-        let wrapped = wrap_text(text, 12, 2); // effective width = 10
-        assert_eq!(wrapped.len(), 2);
-        assert_eq!(wrapped[0].text, "hello worl");
-        assert!(wrapped[0].is_first);
-        assert_eq!(wrapped[1].text, "d foo bar");
-        assert!(!wrapped[1].is_first);
+    fn test_headless_ask_autocomplete() {
+        let ctx = context();
+        // Down and back Up to the first completion
+        let keys = typed("explain @b")
+            .chain([
+                press(KeyCode::Down),
+                press(KeyCode::Up),
+                press(KeyCode::Tab),
+            ])
+            .chain(typed("please"))
+            .chain([press(KeyCode::Enter)]);
+        let mut app = headless(80, 48, keys);
+        let result = app.run_ask("", None, Some(&ctx), false).unwrap();
+        assert!(
+            matches!(result, AppResult::Submit(ref t) if t == "explain @buffer please"),
+            "{:?}",
+            result
+        );
+
+        let highlighted = |n: usize, name: &str| {
+            let buffer = app.backend().shown(n);
+            let (x, y) = locate(buffer, &format!("│{}", name)).expect(name);
+            buffer[(x + 1, y)].bg == app.theme.primary
+        };
+        // The menu as the first Down was pressed, and after it
+        let screen = rows(app.backend().shown(10));
+        assert!(screen.iter().any(|row| row.contains("λ explain @b█")));
+        assert!(highlighted(10, "@buffer") && !highlighted(10, "@branch"));
+        assert!(highlighted(11, "@branch") && !highlighted(11, "@buffer"));
+        assert!(highlighted(12, "@buffer"));
+
+        // Accepted, and the menu gone
+        let enter = app.backend().shown(19);
+        assert!(locate(enter, "λ explain @buffer please█").is_some());
+        assert!(locate(enter, "│@buffer").is_none());
+    }
+
+    /// Area of the autocomplete popup on the screen, from its corners
+    fn popup_rect(buffer: &Buffer) -> Rect {
+        let (x, y) = locate(buffer, "╭").expect("popup");
+        let (right, bottom) = locate(buffer, "╯").expect("popup");
+        Rect::new(x, y, right + 1 - x, bottom + 1 - y)
+    }
+
+    #[test]
+    fn test_autocomplete_area() {
+        let dialog = Rect::new(10, 5, 60, 20);
+        // Under the @
+        assert_eq!(
+            autocomplete_area(Position::new(20, 8), 16, 6, dialog),
+            Rect::new(20, 9, 16, 6)
+        );
+        // Kept inside on the right, and above the line without room below
+        assert_eq!(
+            autocomplete_area(Position::new(65, 8), 16, 6, dialog),
+            Rect::new(54, 9, 16, 6)
+        );
+        assert_eq!(
+            autocomplete_area(Position::new(20, 20), 16, 6, dialog),
+            Rect::new(20, 14, 16, 6)
+        );
+        // Never outside, even when it fits nowhere
+        assert_eq!(
+            autocomplete_area(Position::new(20, 8), 16, 6, Rect::new(10, 5, 12, 4)),
+            Rect::new(10, 5, 12, 4)
+        );
     }
 
     #[test]
-    fn test_wrap_text_with_newlines() {
-        let text = "hello\nworld";
-        let wrapped = wrap_text(text, 20, 2);
-        assert_eq!(wrapped.len(), 2);
-        assert_eq!(wrapped[0].text, "hello");
-        assert_eq!(wrapped[0].logical_line, 0);
-        assert_eq!(wrapped[1].text, "world");
-        assert_eq!(wrapped[1].logical_line, 1);
+    fn test_headless_autocomplete_follows_wrapped_input() {
+        let ctx = context();
+        // Wraps after 74 columns, so the @ starts the second row
+        let text = format!("{} @b", "x".repeat(73));
+        let keys = typed(&text).chain([press(KeyCode::Esc), press(KeyCode::Esc)]);
+        let mut app = headless(80, 48, keys);
+        app.run_ask("", None, Some(&ctx), false).unwrap();
+
+        let screen = app.backend().shown(text.len());
+        let (x, y) = locate(screen, "↪ @b█").expect("second row");
+        let popup = popup_rect(screen);
+        assert_eq!((popup.x, popup.y), (x + 2, y + 1));
+        assert_eq!(popup.width, 16);
+        assert!(locate(screen, "│@buffer").is_some());
     }
 
     #[test]
-    fn test_cursor_to_visual_pos_no_wrap() {
-        let text = "hello";
-        let (row, col) = cursor_to_visual_pos(text, 3, 20, 2);
-        assert_eq!(row, 0);
-        assert_eq!(col, 3);
+    fn test_headless_autocomplete_follows_scrolled_input() {
+        let ctx = context();
+        // Seven rows in a five-row view: the @ is on the last visible row
+        let keys = typed("@b").chain([press(KeyCode::Esc), press(KeyCode::Esc)]);
+        let mut app = headless(80, 48, keys);
+        app.run_ask("1\n2\n3\n4\n5\n6\n7 ", None, Some(&ctx), false)
+            .unwrap();
+
+        let screen = app.backend().shown(2);
+        let (x, y) = locate(screen, "7 @b█").expect("last row");
+        assert!(locate(screen, "λ 1").is_none());
+        let popup = popup_rect(screen);
+        assert_eq!((popup.x, popup.y), (x + 2, y + 1));
     }
 
     #[test]
-    fn test_cursor_to_visual_pos_with_wrap() {
-        let text = "hello world foo bar";
-        // With width=12, prefix=2, effective=10, wraps to:
-        // Line 0: "hello worl" (pos 0-10)
-        // Line 1: "d foo bar" (pos 10-19)
-        let (row, col) = cursor_to_visual_pos(text, 12, 12, 2);
-        assert_eq!(row, 1);
-        assert_eq!(col, 2); // "d " = 2 chars into the wrapped line
+    fn test_headless_ask_cancel() {
+        let ctx = context();
+        // The first Esc only closes the menu
+        let keys = typed("fix @th").chain([press(KeyCode::Esc), press(KeyCode::Esc)]);
+        let mut app = headless(80, 48, keys);
+        let result = app.run_ask("", None, Some(&ctx), false).unwrap();
+        assert!(matches!(result, AppResult::Cancel), "{:?}", result);
+        assert!(locate(app.backend().shown(7), "│@this").is_some());
+        let second = app.backend().shown(8);
+        assert!(locate(second, "λ fix @th█").is_some());
+        assert!(locate(second, "│@this").is_none());
+    }
+
+    #[test]
+    fn test_headless_ask_tabs() {
+        let keys = [press(KeyCode::Left), press(KeyCode::Esc)];
+        let mut app = headless(60, 16, keys).with_tab_width(4);
+        app.run_ask("\tif a\tb", None, None, false).unwrap();
+        // Drawn to the tab stops, the cursor where the text ends...
+        let frame = app.backend().shown(0);
+        let (x, y) = locate(frame, "λ     if a    b█").unwrap();
+        let end = x + "λ     if a    b".chars().count() as u16;
+        assert_eq!(app.backend().cursor(0), Position::new(end, y));
+        // ... and on the "b" after a tab, not a cell into it
+        assert_eq!(app.backend().cursor(1), Position::new(end - 1, y));
+    }
+
+    #[test]
+    fn test_headless_ask_round() {
+        let keys = [press(KeyCode::Esc)];
+        let mut app = headless(60, 16, keys).with_round(Some(3));
+        app.run_ask("fix it", None, None, false).unwrap();
+        let frame = app.backend().shown(0);
+        assert!(
+            locate(frame, "▓▒░  round 3 ━").is_some(),
+            "{:?}",
+            rows(frame)
+        );
+        assert!(locate(frame, "λ fix it█").is_some());
+    }
+
+    /// `@diff` takes two seconds; every other placeholder is "fast"
+    fn slow_diff(kind: PlaceholderKind, ctx: &Context) -> (String, Option<String>) {
+        if kind == PlaceholderKind::Diff {
+            std::thread::sleep(std::time::Duration::from_secs(2));
+        }
+        kind.panel_entry(ctx)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_headless_ask_previews_behind() {
+        let ctx = context();
+        let keys = [press(KeyCode::Esc)];
+        let mut app = headless(80, 48, keys).with_compute(slow_diff);
+        let started = Instant::now();
+        let result = app.run_ask("", None, Some(&ctx), false).unwrap();
+        assert!(matches!(result, AppResult::Cancel), "{:?}", result);
+        // Neither opening nor closing waited on @diff
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+        let first = app.backend().shown(0);
+        let (_, diff) = locate(first, "@diff ").unwrap();
+        assert!(
+            rows(first)[diff as usize].contains('…'),
+            "{:?}",
+            rows(first)
+        );
+    }
+
+    fn select_items() -> Vec<SelectItem> {
+        ["explain", "review", "refactor"]
+            .iter()
+            .map(|name| SelectItem::new(name, "", &format!("{} @this", name), "PROMPTS"))
+            .collect()
+    }
+
+    #[test]
+    fn test_headless_select_navigate() {
+        let mut items = select_items();
+        let keys = [
+            press(KeyCode::Down),
+            press(KeyCode::Down),
+            press(KeyCode::Enter),
+        ];
+        let mut app = headless(60, 24, keys);
+        let result = app
+            .run_select(&mut items, "", &mut BTreeSet::new(), None, false)
+            .unwrap();
+        assert!(
+            matches!(result, AppResult::Submit(ref t) if t == "refactor @this"),
+            "{:?}",
+            result
+        );
+
+        let menu = [
+            "┏ ░▒▓ OPENCODE ▓▒░  SELECT ━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━┓",
+            "┃ ⟫ █                                                      ┃",
+            "┃                                                          ┃",
+            "┃ ▸ explain                                                ┃",
+            "┃   review                                                 ┃",
+            "┃   refactor                                               ┃",
+            "┃                                                          ┃",
+            "┃  [↑↓] Navigate  [Enter] Execute  [M-h] Keys  [Esc] Abort ┃",
+            "┗━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━┛",
+        ];
+        assert_eq!(rows(app.backend().shown(0))[7..16], menu);
+        let enter = rows(app.backend().shown(2));
+        assert_eq!(
+            enter[10],
+            "┃   explain                                                ┃"
+        );
+        assert_eq!(
+            enter[12],
+            "┃ ▸ refactor                                               ┃"
+        );
+    }
+
+    #[test]
+    fn test_headless_select_filter_and_cancel() {
+        let mut items = select_items();
+        let keys = typed("re").chain([press(KeyCode::Down), press(KeyCode::Esc)]);
+        let mut app = headless(60, 24, keys);
+        let result = app
+            .run_select(&mut items, "", &mut BTreeSet::new(), None, false)
+            .unwrap();
+        assert!(matches!(result, AppResult::Cancel), "{:?}", result);
+
+        let screen = rows(app.backend().shown(3));
+        assert!(screen.iter().any(|row| row.contains("⟫ re█")));
+        assert!(screen.iter().any(|row| row.contains("▸ refactor")));
+        assert!(!screen.iter().any(|row| row.contains("explain")));
+    }
+
+    #[test]
+    fn test_headless_select_refresh() {
+        // Tagged until the refresh lands
+        let (_send, refresh) = std::sync::mpsc::channel();
+        let mut app = headless(60, 24, [press(KeyCode::Esc)]).with_refresh(Some(refresh));
+        app.run_select(&mut select_items(), "", &mut BTreeSet::new(), None, false)
+            .unwrap();
+        assert!(locate(app.backend().shown(0), " cached ┛").is_some());
+
+        // Changed items replace the menu's
+        let (send, refresh) = std::sync::mpsc::channel();
+        let mut refreshed = select_items();
+        refreshed.push(SelectItem::new("build", "", "@build", "AGENTS"));
+        send.send(Refresh::Items(refreshed)).unwrap();
+        let mut items = select_items();
+        let keys = typed("bu").chain([press(KeyCode::Enter)]);
+        let mut app = headless(60, 24, keys).with_refresh(Some(refresh));
+        let result = app
+            .run_select(&mut items, "", &mut BTreeSet::new(), None, false)
+            .unwrap();
+        assert!(
+            matches!(result, AppResult::Submit(ref t) if t == "@build"),
+            "{:?}",
+            result
+        );
+        assert_eq!(items.len(), 4);
+        let screen = app.backend().shown(0);
+        assert!(locate(screen, "build").is_some());
+        assert!(locate(screen, "cached").is_none());
+
+        // Unchanged ones only lose the tag
+        let (send, refresh) = std::sync::mpsc::channel();
+        send.send(Refresh::Unchanged).unwrap();
+        let mut app = headless(60, 24, [press(KeyCode::Esc)]).with_refresh(Some(refresh));
+        app.run_select(&mut select_items(), "", &mut BTreeSet::new(), None, false)
+            .unwrap();
+        assert!(locate(app.backend().shown(0), "cached").is_none());
+    }
+
+    fn ctrl(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+    }
+
+    #[test]
+    fn test_headless_ask_readline_keys() {
+        // "hllo" -> "hello" -> "hello!" -> "hello"
+        let keys = typed("hllo")
+            .chain([ctrl('a'), ctrl('f')])
+            .chain(typed("e"))
+            .chain([ctrl('e')])
+            .chain(typed("!"))
+            .chain([ctrl('b'), ctrl('d'), press(KeyCode::Enter)]);
+        let mut app = headless(60, 24, keys);
+        let result = app.run_ask("", None, None, false).unwrap();
+        assert!(
+            matches!(result, AppResult::Submit(ref t) if t == "hello"),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_headless_ask_picks_route() {
+        let alt = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::ALT);
+        let keys = [alt('a')]
+            .into_iter()
+            .chain(typed("plan"))
+            .chain([press(KeyCode::Enter), alt('a')])
+            .chain(typed("sonnet"))
+            .chain([press(KeyCode::Enter), press(KeyCode::Enter)]);
+        let mut app = headless(70, 24, keys)
+            .with_agent(Some("build".to_string()))
+            .with_choices(
+                vec!["build".to_string(), "plan".to_string()],
+                vec!["anthropic/claude-sonnet-4".to_string()],
+            );
+        let result = app.run_ask("fix it", None, None, false).unwrap();
+        assert!(
+            matches!(result, AppResult::Submit(ref t) if t == "fix it"),
+            "{:?}",
+            result
+        );
+        assert_eq!(
+            app.chosen(),
+            &Chosen {
+                agent: Some("plan".to_string()),
+                model: Some("anthropic/claude-sonnet-4".to_string()),
+            }
+        );
+
+        // The menu lists both, the active agent marked
+        let menu = app.backend().shown(1);
+        assert!(locate(menu, "@build").is_some());
+        assert!(locate(menu, "current").is_some());
+        assert!(locate(menu, "claude-sonnet-4").is_some());
+        let enter = app.backend().shown(14);
+        assert!(locate(enter, "@plan · claude-sonnet-4 fix it").is_some());
+
+        // With nothing to pick, a notice and no menu
+        let mut app = headless(70, 24, [alt('a'), press(KeyCode::Esc)]);
+        app.run_ask("", None, None, false).unwrap();
+        assert!(locate(app.backend().shown(1), "No agents or models").is_some());
+        assert_eq!(app.chosen(), &Chosen::default());
+    }
+
+    #[test]
+    fn test_headless_ask_pasted_images() {
+        let dir = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.png"), [0u8; 2048]).unwrap();
+        std::fs::write(dir.join("b c.gif"), [0u8; 10]).unwrap();
+        let text = "compare a.png with 'b c.gif' and notes.png";
+
+        // Ctrl+X drops the last
+        let keys = [ctrl('x'), press(KeyCode::Enter)];
+        let mut app = headless(80, 24, keys).with_attachments(Some(dir.clone()));
+        let result = app.run_ask(text, None, None, false).unwrap();
+        assert!(matches!(result, AppResult::Submit(ref t) if t == text));
+        let pasted: Vec<String> = app.pasted().iter().map(Pasted::file_name).collect();
+        assert_eq!(pasted, ["a.png"]);
+        let opened = app.backend().shown(0);
+        // On a line of their own, above the buttons
+        let (_, chips) = locate(opened, "📎 a.png (2.0 KB)").unwrap();
+        assert_eq!(
+            locate(opened, "📎 b c.gif (10 B)").map(|(_, y)| y),
+            Some(chips)
+        );
+        assert_eq!(locate(opened, "SEND").map(|(_, y)| y), Some(chips + 1));
+        let dropped = app.backend().shown(1);
+        assert!(locate(dropped, "📎 a.png (2.0 KB)").is_some());
+        assert!(locate(dropped, "📎 b c.gif").is_none());
+
+        // Off unless asked for
+        let mut app = headless(80, 24, [press(KeyCode::Enter)]);
+        app.run_ask(text, None, None, false).unwrap();
+        assert!(app.pasted().is_empty());
+        assert!(locate(app.backend().shown(0), "📎").is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_headless_choice_shows_diff() {
+        let message = "Apply these changes?\n\
+                       diff --git a/src/lib.rs b/src/lib.rs\n\
+                       --- a/src/lib.rs\n\
+                       +++ b/src/lib.rs\n\
+                       @@ -1,2 +1,2 @@\n\
+                       -fn old() {}\n\
+                       +fn new() {}\n \
+                       fn kept() {}";
+        let mut app = headless(80, 24, [press(KeyCode::Enter)]);
+        assert_eq!(app.run_choice(message, &["Apply"]).unwrap(), Some(0));
+        let screen = app.backend().shown(0);
+        let theme = Theme::default();
+        let fg = |needle| {
+            let (x, y) = locate(screen, needle).unwrap();
+            screen[(x, y)].fg
+        };
+        // Every line shows, none wrapped
+        assert!(locate(screen, "Apply these changes?").is_some());
+        assert_eq!(fg("@@ -1,2 +1,2 @@"), theme.secondary);
+        assert_eq!(fg("-fn old() {}"), theme.error);
+        assert_eq!(fg("+fn new() {}"), theme.success);
+        assert_eq!(fg(" fn kept() {}"), theme.text);
+        assert!(screen[locate(screen, "diff --git").unwrap()]
+            .modifier
+            .contains(Modifier::BOLD));
+    }
+
+    #[test]
+    fn test_route_chip() {
+        let theme = Theme::default();
+        let text = |agent, model| -> String {
+            route_chip(agent, model, &theme)
+                .iter()
+                .map(|span| span.content.to_string())
+                .collect()
+        };
+        assert_eq!(text(None, None), "");
+        assert_eq!(text(Some("plan"), None), "@plan ");
+        assert_eq!(
+            text(Some("plan"), Some("anthropic/claude-sonnet-4")),
+            "@plan · claude-sonnet-4 "
+        );
+        assert_eq!(text(None, Some("local-model")), "local-model ");
+        // A long name is cut to fit
+        let chip = text(
+            None,
+            Some("openrouter/some-very-long-model-name-with-a-date"),
+        );
+        assert_eq!(chip.trim_end().width(), MAX_MODEL_WIDTH);
+    }
+
+    #[test]
+    fn test_headless_ask_readline_edits() {
+        let alt = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::ALT);
+        // "été fix it" -> "ÉTÉ| fix it" -> "ÉTÉ Fix| it" -> "ÉTÉ Fix|"
+        // -> "ÉTÉ Fxi|" -> "ÉTÉ Fxi!"
+        let keys = [ctrl('a'), alt('u'), alt('c'), alt('d'), ctrl('t')]
+            .into_iter()
+            .chain(typed("!"))
+            .chain([press(KeyCode::Enter)]);
+        let mut app = headless(60, 24, keys);
+        let result = app.run_ask("été fix it", None, None, false).unwrap();
+        assert!(
+            matches!(result, AppResult::Submit(ref t) if t == "ÉTÉ Fxi!"),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_headless_ask_multibyte_edits() {
+        // Backspace over the family emoji, type "é", Delete the combined
+        // e + accent, then Up and Down through "日本" and back before typing
+        let keys = [ctrl('e'), press(KeyCode::Left), press(KeyCode::Backspace)]
+            .into_iter()
+            .chain(typed("é"))
+            .chain([ctrl('a'), press(KeyCode::Delete), press(KeyCode::Right)])
+            .chain([press(KeyCode::Up), press(KeyCode::Down)])
+            .chain(typed("!"))
+            .chain([press(KeyCode::Enter)]);
+        let mut app = headless(60, 24, keys);
+        let result = app
+            .run_ask("日本\ne\u{301}x👨\u{200d}👩\u{200d}👧y", None, None, false)
+            .unwrap();
+        assert!(
+            matches!(result, AppResult::Submit(ref t) if t == "日本\nx!éy"),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_headless_ask_unknown_words() {
+        // Underlined once typed past; "Keep editing" returns to the dialog
+        let keys = typed("fix @buffr ").chain([
+            press(KeyCode::Enter),
+            press(KeyCode::Char('k')),
+            press(KeyCode::Esc),
+        ]);
+        let mut app = headless(60, 24, keys);
+        let result = app.run_ask("", None, None, false).unwrap();
+        assert!(matches!(result, AppResult::Cancel), "{:?}", result);
+
+        let typing = app.backend().shown(9);
+        let (x, y) = locate(typing, "@buff").unwrap();
+        assert!(!typing[(x, y)].modifier.contains(Modifier::UNDERLINED));
+        let typed = app.backend().shown(11);
+        let (x, y) = locate(typed, "@buffr").unwrap();
+        for cell in [&typed[(x, y)], &typed[(x + 5, y)]] {
+            assert!(cell.modifier.contains(Modifier::UNDERLINED));
+            assert_eq!(cell.fg, app.theme.warning);
+        }
+        assert!(!typed[(x - 2, y)].modifier.contains(Modifier::UNDERLINED));
+        assert!(locate(app.backend().shown(12), "sent as typed: @buffr").is_some());
+        assert!(locate(app.backend().shown(13), "fix @buffr").is_some());
+
+        // Placeholders, agents, emails and decorators go without asking
+        let text = "@build: @buffer, a@b.com and `@Override`";
+        let mut app = headless(60, 24, [press(KeyCode::Enter)]).with_agents(vec!["build".into()]);
+        let result = app.run_ask(text, None, None, false).unwrap();
+        assert!(
+            matches!(result, AppResult::Submit(ref t) if t == text),
+            "{:?}",
+            result
+        );
+
+        // "Send" sends as typed
+        let keys = [press(KeyCode::Enter), press(KeyCode::Enter)];
+        let mut app = headless(60, 24, keys);
+        let result = app.run_ask("explain @slection", None, None, false).unwrap();
+        assert!(
+            matches!(result, AppResult::Submit(ref t) if t == "explain @slection"),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn test_headless_ctrl_d_cancels_when_empty() {
+        // Deletes what is typed, then cancels
+        let keys = typed("a").chain([ctrl('a'), ctrl('d'), ctrl('d')]);
+        let mut app = headless(60, 24, keys);
+        let result = app.run_ask("", None, None, false).unwrap();
+        assert!(matches!(result, AppResult::Cancel), "{:?}", result);
+        assert!(locate(app.backend().shown(3), "λ █").is_some());
+
+        let mut app = headless(60, 24, typed("re").chain([ctrl('d')]));
+        let result = app
+            .run_select(&mut select_items(), "", &mut BTreeSet::new(), None, false)
+            .unwrap();
+        assert!(matches!(result, AppResult::Cancel), "{:?}", result);
+    }
+
+    #[test]
+    fn test_headless_copy() {
+        let ctx = context();
+        let keys = typed("fix @this please").chain([ctrl('y'), press(KeyCode::Esc)]);
+        let mut app = headless(80, 48, keys);
+        let result = app.run_ask("", None, Some(&ctx), false).unwrap();
+        // Copying leaves the dialog open
+        assert!(matches!(result, AppResult::Cancel), "{:?}", result);
+        let expanded = ctx.expansion("fix @this please").text;
+        assert_ne!(expanded, "fix @this please");
+        assert_eq!(app.backend().copied(), [expanded.as_str()]);
+        let toast = format!("copied ({} B)", expanded.len());
+        assert!(locate(app.backend().shown(17), &toast).is_some());
+
+        let keys = [press(KeyCode::Down), ctrl('y'), press(KeyCode::Esc)];
+        let mut app = headless(60, 24, keys);
+        let result = app
+            .run_select(
+                &mut select_items(),
+                "",
+                &mut BTreeSet::new(),
+                Some(&ctx),
+                false,
+            )
+            .unwrap();
+        assert!(matches!(result, AppResult::Cancel), "{:?}", result);
+        assert_eq!(
+            app.backend().copied(),
+            [ctx.expansion("review @this").text.as_str()]
+        );
+        assert!(locate(app.backend().shown(2), "copied (").is_some());
+    }
+
+    #[test]
+    fn test_headless_japanese_strings() {
+        use unicode_width::UnicodeWidthStr;
+        let ctx = context();
+        for width in [80, 36] {
+            let alt_h = KeyEvent::new(KeyCode::Char('h'), KeyModifiers::ALT);
+            let keys = [alt_h, press(KeyCode::Esc), press(KeyCode::Esc)];
+            let mut app = headless(width, 48, keys).with_strings(strings::japanese());
+            let result = app.run_ask("", None, Some(&ctx), false).unwrap();
+            assert!(matches!(result, AppResult::Cancel), "{:?}", result);
+
+            // Every row of the dialog ends at its right border
+            let screen = rows(app.backend().shown(0));
+            let dialog: Vec<&String> = screen.iter().filter(|row| !row.is_empty()).collect();
+            for row in &dialog {
+                assert_eq!(row.width(), width as usize, "{}", row);
+                assert!(row.ends_with(['┃', '┓', '┛']), "{}", row);
+            }
+            let buttons = dialog
+                .iter()
+                .find(|row| row.contains("送信"))
+                .expect("buttons");
+            assert!(buttons.contains("送信      キャンセル"), "{}", buttons);
+            assert!(dialog.iter().any(|row| row.contains("プレースホルダー:")));
+            assert!(dialog
+                .iter()
+                .any(|row| row.contains("0 文字 · 約 0 トークン")));
+
+            let help = rows(app.backend().shown(1));
+            assert!(help.iter().any(|row| row.contains("キー操作")));
+            assert!(help.iter().any(|row| row.contains("┃全般")));
+        }
+
+        let mut items = select_items();
+        items.push(SelectItem::new("build", "", "@build", "AGENTS"));
+        let mut collapsed = BTreeSet::from(["PROMPTS".to_string()]);
+        let mut app = headless(50, 24, [press(KeyCode::Esc)]).with_strings(strings::japanese());
+        let result = app
+            .run_select(&mut items, "", &mut collapsed, None, false)
+            .unwrap();
+        assert!(matches!(result, AppResult::Cancel), "{:?}", result);
+        let screen = rows(app.backend().shown(0));
+        assert!(screen.iter().any(|row| row.contains("▓▒░  選択 ━")));
+        assert!(screen
+            .iter()
+            .any(|row| row.contains("PROMPTS (3 件を非表示)")));
+        assert!(screen.iter().any(|row| row.contains("[Enter] 実行")));
     }
 }
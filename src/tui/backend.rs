@@ -0,0 +1,444 @@
+//! Where the dialogs draw and read keys from
+//!
+//! [`App`](super::app::App) runs its dialogs against a [`Backend`]: the
+//! [`TtyBackend`] draws on `/dev/tty` (so it works under `:insert-output`,
+//! with stdout piped) and owns the terminal modes, the inline viewport and
+//! input decoding. Tests use a headless backend instead, drawing into
+//! ratatui's `TestBackend` and replaying a script of keys.
+
+use anyhow::{Context, Result};
+use crossterm::event::KeyEvent;
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+use ratatui::layout::{Rect, Size};
+use ratatui::prelude::CrosstermBackend;
+use ratatui::{Frame, Terminal, TerminalOptions, Viewport};
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
+
+use crate::config::CursorShape;
+use crate::tui::clipboard;
+use crate::tui::input::InputBuffer;
+use crate::tui::term::{self, Step, TerminalGuard};
+
+/// Why /dev/tty couldn't be opened, most likely
+const NO_TTY: &str = "cannot open /dev/tty — are you running inside a non-interactive context?";
+
+/// What a dialog loop needs from the terminal
+///
+/// Only drawing, keys and the size are required; the rest manages a real
+/// terminal and does nothing by default.
+pub trait Backend {
+    /// Draw a frame
+    fn draw(&mut self, render: impl FnOnce(&mut Frame)) -> Result<()>;
+
+    /// Next key, or None when none arrived within `timeout`
+    fn read_key(&mut self, timeout: Duration) -> Result<Option<KeyEvent>>;
+
+    /// Size of the screen
+    fn size(&mut self) -> Result<Size>;
+
+    /// Whether more input is already waiting, so drawing can wait until the
+    /// keys typed (or pasted) together are handled
+    fn key_waiting(&mut self) -> bool {
+        false
+    }
+
+    /// Whether the terminal gained (true) or lost (false) the focus since
+    /// the last call; None without a change, or focus reports
+    fn focus_change(&mut self) -> Option<bool> {
+        None
+    }
+
+    /// Whether the dialogs are drawn inline rather than on the alternate screen
+    fn inline(&self) -> bool {
+        false
+    }
+
+    /// Size the drawing area to `height` rows; true when it moved, so the
+    /// dialog must be redrawn
+    fn fit_viewport(&mut self, _height: u16) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// Set the terminal title and cursor shape
+    fn integrate(&mut self, _title: &str, _shape: Option<CursorShape>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Put the terminal back the way it was found
+    fn restore(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Run `f` (e.g. an external editor) with the terminal handed back
+    fn suspend<T>(&mut self, f: impl FnOnce() -> T) -> Result<T> {
+        Ok(f())
+    }
+
+    /// Put `text` on the system clipboard
+    fn copy(&mut self, _text: &str) -> Result<()> {
+        anyhow::bail!("no clipboard")
+    }
+}
+
+/// The controlling terminal, opened as `/dev/tty`
+pub struct TtyBackend {
+    /// Terminal backend - uses /dev/tty to work when stdout is piped
+    terminal: Terminal<CrosstermBackend<File>>,
+    /// TTY file for reading input
+    tty_reader: File,
+    /// Raw input not yet decoded into key events
+    input: InputBuffer,
+    /// Log the raw input bytes
+    debug: bool,
+    /// Title and cursor shape last set, set again after a suspend
+    integration: Option<(String, Option<CursorShape>)>,
+    /// Draw in the bottom rows of the screen instead of the alternate screen
+    inline: bool,
+    /// First screen row the inline dialog uses (it owns the rows below too)
+    inline_top: Option<u16>,
+    /// Rows the inline dialog is drawn in
+    viewport: Rect,
+}
+
+impl TtyBackend {
+    /// Open /dev/tty in raw mode, on the alternate screen unless `inline`
+    pub fn open(debug: bool, inline: bool) -> Result<Self> {
+        // Open /dev/tty directly - this works even when stdout is piped
+        let tty_write = File::options()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .context(NO_TTY)?;
+        let tty_reader = File::options()
+            .read(true)
+            .open("/dev/tty")
+            .context(NO_TTY)?;
+
+        // The inline viewport is sized by each dialog (see `fit_viewport`).
+        // Not `Viewport::Inline`: it asks for the cursor position on stdout,
+        // which is the `:insert-output` pipe.
+        let viewport = if inline {
+            Viewport::Fixed(Rect::default())
+        } else {
+            Viewport::Fullscreen
+        };
+        let terminal = Terminal::with_options(
+            CrosstermBackend::new(tty_write),
+            TerminalOptions { viewport },
+        )
+        .context("cannot read the terminal size — is /dev/tty a real terminal?")?;
+        let mut backend = Self {
+            terminal,
+            tty_reader,
+            input: InputBuffer::new(),
+            debug,
+            integration: None,
+            inline,
+            inline_top: None,
+            viewport: Rect::default(),
+        };
+        term::install_panic_hook();
+        backend.enter()?;
+        Ok(backend)
+    }
+
+    /// Put the terminal into raw mode on the alternate screen (or inline)
+    ///
+    /// On failure, whatever was already set is undone, so the shell isn't
+    /// left in raw mode or on the alternate screen.
+    fn enter(&mut self) -> Result<()> {
+        let mut guard = TerminalGuard::new(term::undo);
+        guard.apply(
+            Step::RawMode,
+            "failed to enable raw mode — another process may hold the terminal",
+            enable_raw_mode,
+        )?;
+        let out = self.terminal.backend_mut();
+        guard.apply(
+            Step::Screen,
+            "failed to write to the terminal — was it closed?",
+            || {
+                term::enter(out, !self.inline)?;
+                match &self.integration {
+                    Some((title, shape)) => term::integrate(out, title, *shape),
+                    None => Ok(()),
+                }
+            },
+        )?;
+        guard.disarm();
+        Ok(())
+    }
+
+    /// Ask the terminal for the 0-based cursor row, keeping keys typed meanwhile
+    ///
+    /// Not crossterm's query, which writes to stdout rather than the tty.
+    fn cursor_row(&mut self) -> Result<Option<u16>> {
+        use std::io::Write;
+        let out = self.terminal.backend_mut();
+        write!(out, "\x1b[6n")?;
+        Write::flush(out)?;
+        let deadline = Instant::now() + Duration::from_millis(200);
+        loop {
+            if let Some(row) = self.input.take_cursor_row() {
+                return Ok(Some(row.saturating_sub(1)));
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() || !self.fill_input(left)? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Wait up to `timeout` for input and append everything available to the buffer
+    /// Returns true if any bytes were read
+    fn fill_input(&mut self, timeout: Duration) -> Result<bool> {
+        let fd = self.tty_reader.as_raw_fd();
+
+        // Use poll to check if data is available
+        let mut pollfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        let timeout_ms = timeout.as_millis() as i32;
+        let ret = unsafe { libc::poll(&mut pollfd, 1, timeout_ms) };
+
+        if ret <= 0 {
+            return Ok(false);
+        }
+
+        let mut buf = [0u8; 1024];
+        let n = self.tty_reader.read(&mut buf)?;
+        if n == 0 {
+            return Ok(false);
+        }
+
+        // Debug log raw bytes
+        super::app::debug_log(self.debug, &format!("Input bytes: {:02x?}", &buf[..n]));
+
+        self.input.push(&buf[..n]);
+        Ok(true)
+    }
+}
+
+impl Backend for TtyBackend {
+    fn draw(&mut self, render: impl FnOnce(&mut Frame)) -> Result<()> {
+        self.terminal.draw(render)?;
+        Ok(())
+    }
+
+    /// Read a key event from /dev/tty with timeout
+    fn read_key(&mut self, timeout: Duration) -> Result<Option<KeyEvent>> {
+        // Keys left over from a previous read come first
+        if let Some(key) = self.input.next_key() {
+            return Ok(Some(key));
+        }
+
+        if !self.fill_input(timeout)? {
+            return Ok(None);
+        }
+
+        loop {
+            if let Some(key) = self.input.next_key() {
+                return Ok(Some(key));
+            }
+            if self.input.is_empty() {
+                return Ok(None);
+            }
+            // Incomplete sequence - wait briefly for the rest of it
+            if !self.fill_input(Duration::from_millis(50))? {
+                return Ok(self.input.flush());
+            }
+        }
+    }
+
+    fn size(&mut self) -> Result<Size> {
+        Ok(self.terminal.size()?)
+    }
+
+    fn key_waiting(&mut self) -> bool {
+        !self.input.is_empty() || self.fill_input(Duration::ZERO).unwrap_or(false)
+    }
+
+    fn focus_change(&mut self) -> Option<bool> {
+        self.input.take_focus()
+    }
+
+    fn inline(&self) -> bool {
+        self.inline
+    }
+
+    /// Size the inline viewport to `height` rows from the cursor row down
+    ///
+    /// Called before each draw, so a dialog that grows, or a resized screen,
+    /// moves the viewport along: the screen is scrolled up when the rows
+    /// don't fit, and the old rows are cleared before redrawing. Does nothing
+    /// on the alternate screen, where ratatui follows resizes itself.
+    fn fit_viewport(&mut self, height: u16) -> Result<bool> {
+        if !self.inline {
+            return Ok(false);
+        }
+        let screen = self.terminal.size()?;
+        let top = match self.inline_top {
+            Some(top) => top,
+            // Unknown: at the bottom
+            None => self.cursor_row()?.unwrap_or(u16::MAX),
+        };
+        let (area, scroll) = term::inline_area(screen, top, height);
+        if self.inline_top.is_some() && area == self.viewport {
+            return Ok(false);
+        }
+        let backend = self.terminal.backend_mut();
+        if self.inline_top.is_some() {
+            term::clear_rows(backend, screen, top)?;
+        }
+        term::scroll_up(backend, screen, scroll)?;
+        self.inline_top = Some(area.y);
+        self.viewport = area;
+        self.terminal.resize(area)?;
+        Ok(true)
+    }
+
+    fn integrate(&mut self, title: &str, shape: Option<CursorShape>) -> Result<()> {
+        term::integrate(self.terminal.backend_mut(), title, shape)?;
+        self.integration = Some((title.to_string(), shape));
+        Ok(())
+    }
+
+    fn restore(&mut self) -> Result<()> {
+        if let Some(top) = self.inline_top.take() {
+            let screen = self.terminal.size()?;
+            term::clear_rows(self.terminal.backend_mut(), screen, top)?;
+            self.viewport = Rect::default();
+        }
+        disable_raw_mode()?;
+        term::leave(self.terminal.backend_mut())?;
+        self.terminal.show_cursor()?;
+        Ok(())
+    }
+
+    /// The terminal is restored to cooked mode on the main screen for the
+    /// duration, and input that arrived before suspending is discarded.
+    fn suspend<T>(&mut self, f: impl FnOnce() -> T) -> Result<T> {
+        self.restore()?;
+        let result = f();
+        self.enter()?;
+        self.input = InputBuffer::new();
+        self.terminal.clear()?;
+        Ok(result)
+    }
+
+    /// With OSC 52 on the terminal, or a copy command when the text is too
+    /// long for that
+    fn copy(&mut self, text: &str) -> Result<()> {
+        use std::io::Write;
+        let Some(sequence) = clipboard::osc52(text) else {
+            return clipboard::copy_with_command(text);
+        };
+        let out = self.terminal.backend_mut();
+        out.write_all(sequence.as_bytes())?;
+        Write::flush(out)?;
+        Ok(())
+    }
+}
+
+impl Drop for TtyBackend {
+    fn drop(&mut self) {
+        let _ = self.restore();
+    }
+}
+
+/// A screen in memory and a script of keys, for driving dialogs in tests
+#[cfg(test)]
+pub struct Headless {
+    terminal: Terminal<ratatui::backend::TestBackend>,
+    keys: std::collections::VecDeque<KeyEvent>,
+    /// The screen as each key was read
+    shown: Vec<ratatui::buffer::Buffer>,
+    /// Where the terminal cursor was as each key was read
+    cursors: Vec<ratatui::layout::Position>,
+    /// Text put on the clipboard
+    copied: Vec<String>,
+}
+
+#[cfg(test)]
+impl Headless {
+    /// A `width` x `height` screen that replays `keys`
+    pub fn new(width: u16, height: u16, keys: impl IntoIterator<Item = KeyEvent>) -> Self {
+        Self {
+            terminal: Terminal::new(ratatui::backend::TestBackend::new(width, height))
+                .expect("test terminal"),
+            keys: keys.into_iter().collect(),
+            shown: Vec::new(),
+            cursors: Vec::new(),
+            copied: Vec::new(),
+        }
+    }
+
+    /// The screen when the `n`th scripted key was read (from 0)
+    pub fn shown(&self, n: usize) -> &ratatui::buffer::Buffer {
+        &self.shown[n]
+    }
+
+    /// The terminal cursor when the `n`th scripted key was read
+    pub fn cursor(&self, n: usize) -> ratatui::layout::Position {
+        self.cursors[n]
+    }
+
+    /// Everything copied, oldest first
+    pub fn copied(&self) -> &[String] {
+        &self.copied
+    }
+}
+
+/// Rows of `buffer` as text, without trailing spaces
+///
+/// The cells a wide character covers are skipped, as on a terminal.
+#[cfg(test)]
+pub fn rows(buffer: &ratatui::buffer::Buffer) -> Vec<String> {
+    use unicode_width::UnicodeWidthStr;
+    (0..buffer.area.height)
+        .map(|y| {
+            let mut row = String::new();
+            let mut x = 0;
+            while x < buffer.area.width {
+                let symbol = buffer[(x, y)].symbol();
+                row.push_str(symbol);
+                x += symbol.width().max(1) as u16;
+            }
+            row.trim_end().to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+impl Backend for Headless {
+    fn draw(&mut self, render: impl FnOnce(&mut Frame)) -> Result<()> {
+        self.terminal.draw(render)?;
+        Ok(())
+    }
+
+    /// The next scripted key at once; running out is an error, so a dialog
+    /// that didn't return when expected fails the test instead of hanging
+    fn read_key(&mut self, _timeout: Duration) -> Result<Option<KeyEvent>> {
+        self.shown.push(self.terminal.backend().buffer().clone());
+        self.cursors.push(self.terminal.get_cursor_position()?);
+        match self.keys.pop_front() {
+            Some(key) => Ok(Some(key)),
+            None => anyhow::bail!("the dialog is still open after the last scripted key"),
+        }
+    }
+
+    fn size(&mut self) -> Result<Size> {
+        Ok(self.terminal.size()?)
+    }
+
+    fn copy(&mut self, text: &str) -> Result<()> {
+        self.copied.push(text.to_string());
+        Ok(())
+    }
+}
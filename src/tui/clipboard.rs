@@ -0,0 +1,118 @@
+//! Copying the expanded prompt to the system clipboard (Ctrl+Y)
+//!
+//! The text goes to the terminal as an OSC 52 sequence, so it lands on the
+//! clipboard of the machine the terminal runs on, over SSH too. Terminals
+//! drop sequences past a size limit, so text whose base64 would be longer
+//! than [`OSC52_MAX`] is piped to `wl-copy` or `pbcopy` instead. A [`Toast`]
+//! then says how much was copied for a moment, without closing the dialog.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+
+/// Longest base64 payload sent in an OSC 52 sequence (hterm's and tmux's
+/// limit, which xterm's default also allows)
+pub const OSC52_MAX: usize = 100_000;
+
+/// How long the toast stays
+pub const TOAST_TIME: Duration = Duration::from_millis(1500);
+
+/// The OSC 52 sequence setting the clipboard to `text`, None when it is
+/// too long for terminals to accept
+pub fn osc52(text: &str) -> Option<String> {
+    let payload = base64::engine::general_purpose::STANDARD.encode(text);
+    (payload.len() <= OSC52_MAX).then(|| format!("\x1b]52;c;{}\x07", payload))
+}
+
+/// Put `text` on the clipboard with `wl-copy` (on Wayland) or `pbcopy`
+pub fn copy_with_command(text: &str) -> Result<()> {
+    let program = if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        "wl-copy"
+    } else if cfg!(target_os = "macos") {
+        "pbcopy"
+    } else {
+        bail!("too long for the terminal clipboard, and no wl-copy or pbcopy");
+    };
+    let mut child = Command::new(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("can't run {}", program))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(text.as_bytes())
+            .with_context(|| format!("can't write to {}", program))?;
+    }
+    if !child.wait()?.success() {
+        bail!("{} failed", program);
+    }
+    Ok(())
+}
+
+/// A message shown in place of a dialog's help line for [`TOAST_TIME`]
+#[derive(Debug, Default)]
+pub struct Toast {
+    shown: Option<(String, Instant)>,
+}
+
+impl Toast {
+    /// Show `text` from `now`
+    pub fn show(&mut self, text: String, now: Instant) {
+        self.shown = Some((text, now + TOAST_TIME));
+    }
+
+    /// The message, while shown
+    pub fn text(&self) -> Option<&str> {
+        self.shown.as_ref().map(|(text, _)| text.as_str())
+    }
+
+    /// Hide the message once its time is up; true when it needs drawing
+    pub fn tick(&mut self, now: Instant) -> bool {
+        if self.shown.as_ref().is_some_and(|(_, until)| *until <= now) {
+            self.shown = None;
+            return true;
+        }
+        false
+    }
+
+    /// When the message goes, while shown
+    pub fn deadline(&self) -> Option<Instant> {
+        self.shown.as_ref().map(|(_, until)| *until)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_osc52() {
+        assert_eq!(osc52("hi").unwrap(), "\x1b]52;c;aGk=\x07");
+        assert_eq!(
+            osc52("レビュー\n").unwrap(),
+            "\x1b]52;c;44Os44OT44Ol44O8Cg==\x07"
+        );
+        // 3 bytes encode to 4, so this is the most that fits
+        let most = "x".repeat(OSC52_MAX / 4 * 3);
+        assert_eq!(osc52(&most).unwrap().len(), OSC52_MAX + 8);
+        assert_eq!(osc52(&format!("{}x", most)), None);
+    }
+
+    #[test]
+    fn test_toast_expires() {
+        let start = Instant::now();
+        let mut toast = Toast::default();
+        assert!(!toast.tick(start));
+        toast.show("copied".to_string(), start);
+        assert_eq!(toast.deadline(), Some(start + TOAST_TIME));
+        assert!(!toast.tick(start + TOAST_TIME / 2));
+        assert_eq!(toast.text(), Some("copied"));
+        assert!(toast.tick(start + TOAST_TIME));
+        assert_eq!(toast.text(), None);
+        assert_eq!(toast.deadline(), None);
+    }
+}
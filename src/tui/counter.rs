@@ -0,0 +1,156 @@
+//! Character and token counter of the ask dialog
+//!
+//! The character count follows every keystroke. The token estimate is taken
+//! from the expanded text, so a large `@diff` shows up in it; expanding can
+//! run git, so the estimate is only redone once the input has been still for
+//! [`DEBOUNCE`].
+
+use std::time::{Duration, Instant};
+
+use crate::config::TokenBudget;
+use crate::tui::strings;
+use crate::util::estimate_tokens;
+
+/// How long the input must be unchanged before the expansion is re-estimated
+pub const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Where the estimate stands against the budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Normal,
+    /// Over the budget: the counter is drawn as a warning
+    OverBudget,
+    /// Over the hard limit: sending needs confirmation
+    OverLimit,
+}
+
+/// Counts for the ask dialog's input
+#[derive(Debug)]
+pub struct PromptCounter {
+    /// Input as of the last update, and when it last changed
+    latest: String,
+    changed_at: Instant,
+    /// Input the token estimate was made for
+    counted: Option<String>,
+    tokens: usize,
+}
+
+impl PromptCounter {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            latest: String::new(),
+            changed_at: now,
+            counted: None,
+            tokens: 0,
+        }
+    }
+
+    /// Track the input, re-estimating its expansion once it has settled
+    /// Returns true when the estimate was redone.
+    pub fn update(
+        &mut self,
+        input: &str,
+        now: Instant,
+        expand: impl FnOnce(&str) -> String,
+    ) -> bool {
+        if input != self.latest {
+            self.latest = input.to_string();
+            self.changed_at = now;
+        }
+        let stale = self.counted.as_deref() != Some(self.latest.as_str());
+        // The first estimate isn't held back
+        let due =
+            stale && (self.counted.is_none() || now.duration_since(self.changed_at) >= DEBOUNCE);
+        if due {
+            self.refresh(input, expand);
+        }
+        due
+    }
+
+    /// When the pending re-estimate is due, if the input changed since the last one
+    pub fn settles_at(&self) -> Option<Instant> {
+        (self.counted.as_deref() != Some(self.latest.as_str())).then(|| self.changed_at + DEBOUNCE)
+    }
+
+    /// Estimate the input's expansion now (e.g. before sending it)
+    pub fn refresh(&mut self, input: &str, expand: impl FnOnce(&str) -> String) {
+        self.latest = input.to_string();
+        self.tokens = estimate_tokens(&expand(input));
+        self.counted = Some(self.latest.clone());
+    }
+
+    /// Characters typed (before expansion)
+    pub fn chars(&self) -> usize {
+        self.latest.chars().count()
+    }
+
+    /// Estimated tokens of the expanded input
+    pub fn tokens(&self) -> usize {
+        self.tokens
+    }
+
+    pub fn level(&self, budget: TokenBudget) -> Level {
+        if self.tokens > budget.limit {
+            Level::OverLimit
+        } else if self.tokens > budget.warn {
+            Level::OverBudget
+        } else {
+            Level::Normal
+        }
+    }
+
+    /// `format` filled in, e.g. "42 chars · ~1,200 tokens"
+    pub fn label(&self, format: &str) -> String {
+        strings::fill(
+            format,
+            &[
+                ("chars", &self.chars().to_string()),
+                ("tokens", &crate::util::thousands(self.tokens)),
+            ],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tui::strings::Strings;
+
+    #[test]
+    fn test_estimate_is_debounced() {
+        let start = Instant::now();
+        let expand = |text: &str| text.replace("@diff", &"x".repeat(400));
+        let mut counter = PromptCounter::new(start);
+
+        counter.update("hi", start, expand);
+        assert_eq!((counter.chars(), counter.tokens()), (2, 1));
+
+        // Characters follow each keystroke; tokens wait for the input to settle
+        counter.update("hi @diff", start + Duration::from_millis(100), expand);
+        assert_eq!((counter.chars(), counter.tokens()), (8, 1));
+        counter.update("hi @diff", start + Duration::from_millis(400), expand);
+        assert_eq!(counter.tokens(), 1);
+        assert_eq!(
+            counter.settles_at(),
+            Some(start + Duration::from_millis(100) + DEBOUNCE)
+        );
+        assert!(counter.update("hi @diff", start + Duration::from_millis(600), expand));
+        assert_eq!(counter.tokens(), 101);
+        assert_eq!(counter.settles_at(), None);
+        assert!(!counter.update("hi @diff", start + Duration::from_millis(700), expand));
+        assert_eq!(
+            counter.label(&Strings::default().counter),
+            "8 chars · ~101 tokens"
+        );
+
+        let budget = TokenBudget {
+            warn: 50,
+            limit: 100,
+        };
+        assert_eq!(counter.level(budget), Level::OverLimit);
+        counter.refresh("@diff", |_| "y".repeat(300));
+        assert_eq!(counter.level(budget), Level::OverBudget);
+        counter.refresh("", |_| String::new());
+        assert_eq!(counter.level(budget), Level::Normal);
+    }
+}
@@ -0,0 +1,216 @@
+//! Unified diffs, such as an `@diff` expansion, drawn in color
+//!
+//! A text is only drawn as a diff when it [looks like one](looks_like_diff).
+//! Each line is then classified ([`classify`]) and styled from the theme:
+//! added lines in `success`, removed ones in `error`, hunk headers in
+//! `secondary` and `diff --git` headers bold. The line counts of each hunk
+//! header say where the hunk ends, so a removed `-- comment` line isn't
+//! taken for a `---` file header. Classifying doesn't draw anything, so
+//! other output (such as `watch`) can color diffs its own way.
+
+use ratatui::prelude::*;
+
+use super::markdown::truncate_spans;
+use super::theme::Theme;
+
+/// What a line of a unified diff is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    /// `diff --git a/… b/…`
+    Header,
+    /// `--- a/…` or `+++ b/…` before a hunk
+    File,
+    /// Extended header lines: `index`, modes, renames, copies, similarity,
+    /// and `Binary files … differ`
+    Meta,
+    /// `@@ -1,4 +1,5 @@`
+    Hunk,
+    Added,
+    Removed,
+    /// Unchanged lines, `\ No newline at end of file`, and anything else
+    Context,
+}
+
+/// Extended header lines git writes between `diff --git` and the hunks
+const META_PREFIXES: [&str; 14] = [
+    "index ",
+    "old mode ",
+    "new mode ",
+    "new file mode ",
+    "deleted file mode ",
+    "similarity index ",
+    "dissimilarity index ",
+    "rename from ",
+    "rename to ",
+    "copy from ",
+    "copy to ",
+    "Binary files ",
+    "GIT binary patch",
+    "literal ",
+];
+
+/// Whether `text` is (or holds) a unified diff: a `diff --git` line, or a
+/// `---` line followed by a `+++` line
+pub fn looks_like_diff(text: &str) -> bool {
+    let mut previous = "";
+    for line in text.lines() {
+        if line.starts_with("diff --git ")
+            || (previous.starts_with("--- ") && line.starts_with("+++ "))
+        {
+            return true;
+        }
+        previous = line;
+    }
+    false
+}
+
+/// The kind of each line of `text`, in order
+pub fn classify(text: &str) -> Vec<(LineKind, &str)> {
+    // Lines left in the current hunk, old and new side
+    let mut old = 0;
+    let mut new = 0;
+    text.lines()
+        .map(|line| {
+            if old > 0 || new > 0 {
+                let kind = match line.chars().next() {
+                    Some('+') if new > 0 => {
+                        new -= 1;
+                        Some(LineKind::Added)
+                    }
+                    Some('-') if old > 0 => {
+                        old -= 1;
+                        Some(LineKind::Removed)
+                    }
+                    Some(' ') | None => {
+                        old -= usize::from(old > 0);
+                        new -= usize::from(new > 0);
+                        Some(LineKind::Context)
+                    }
+                    Some('\\') => Some(LineKind::Context),
+                    // The hunk was cut short
+                    _ => {
+                        (old, new) = (0, 0);
+                        None
+                    }
+                };
+                if let Some(kind) = kind {
+                    return (kind, line);
+                }
+            }
+            let kind = if line.starts_with("diff --git ") {
+                LineKind::Header
+            } else if line.starts_with("--- ") || line.starts_with("+++ ") {
+                LineKind::File
+            } else if let Some(counts) = hunk_counts(line) {
+                (old, new) = counts;
+                LineKind::Hunk
+            } else if META_PREFIXES.iter().any(|prefix| line.starts_with(prefix)) {
+                LineKind::Meta
+            } else {
+                LineKind::Context
+            };
+            (kind, line)
+        })
+        .collect()
+}
+
+/// Old and new line counts of a hunk header, `@@ -a[,b] +c[,d] @@`
+fn hunk_counts(line: &str) -> Option<(usize, usize)> {
+    let ranges = line.strip_prefix("@@ -")?;
+    let (ranges, _) = ranges.split_once(" @@")?;
+    let (old, new) = ranges.split_once(" +")?;
+    let count = |range: &str| match range.split_once(',') {
+        Some((_, count)) => count.parse().ok(),
+        None => range.parse::<usize>().ok().map(|_| 1),
+    };
+    Some((count(old)?, count(new)?))
+}
+
+/// Style of a line of `kind`, from `theme`
+pub fn style(kind: LineKind, theme: &Theme) -> Style {
+    match kind {
+        LineKind::Header | LineKind::File => {
+            Style::default().fg(theme.text).add_modifier(Modifier::BOLD)
+        }
+        LineKind::Meta => Style::default().fg(theme.dim),
+        LineKind::Hunk => Style::default().fg(theme.secondary),
+        LineKind::Added => Style::default().fg(theme.success),
+        LineKind::Removed => Style::default().fg(theme.error),
+        LineKind::Context => Style::default().fg(theme.text),
+    }
+}
+
+/// `text` as diff-styled lines, each cut to `width` columns
+pub fn render(text: &str, width: usize, theme: &Theme) -> Vec<Line<'static>> {
+    classify(text)
+        .into_iter()
+        .map(|(kind, line)| {
+            let span = Span::styled(line.to_string(), style(kind, theme));
+            Line::from(truncate_spans(&[span], width))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIXED: &str = include_str!("../../tests/fixtures/diffs/mixed.diff");
+
+    #[test]
+    fn test_classify() {
+        use LineKind::*;
+        let kinds: Vec<LineKind> = classify(MIXED).into_iter().map(|(kind, _)| kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                // A rename with changes
+                Header, Meta, Meta, Meta, Meta, File, File, Hunk,
+                // Removed and added lines that look like file headers
+                Context, Removed, Added, Context, Removed, Context, Added,
+                // A new binary file
+                Header, Meta, Meta, Meta, // A deleted file
+                Header, Meta, File, File, Hunk, Removed,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_looks_like_diff() {
+        assert!(looks_like_diff(MIXED));
+        assert!(looks_like_diff(
+            "```diff\n--- a/x\n+++ b/x\n@@ -1 +1 @@\n-a\n+b\n```"
+        ));
+        for text in [
+            "fn main() {}",
+            "- a list\n+ not a diff",
+            "--- a heading\ntext\n+++ more",
+            "",
+        ] {
+            assert!(!looks_like_diff(text), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_hunk_counts() {
+        assert_eq!(hunk_counts("@@ -1,4 +1,5 @@ fn main() {"), Some((4, 5)));
+        assert_eq!(hunk_counts("@@ -3 +3,0 @@"), Some((1, 0)));
+        assert_eq!(hunk_counts("@@ -a +b @@"), None);
+        assert_eq!(hunk_counts("@@@ -1 -1 +1 @@@"), None);
+    }
+
+    #[test]
+    fn test_render() {
+        let theme = Theme::default();
+        let lines = render(
+            "@@ -1 +1 @@\n-old line\n+a much longer new line",
+            12,
+            &theme,
+        );
+        let text: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        assert_eq!(text, ["@@ -1 +1 @@", "-old line", "+a much lon…"]);
+        assert_eq!(lines[0].spans[0].style.fg, Some(theme.secondary));
+        assert_eq!(lines[1].spans[0].style.fg, Some(theme.error));
+        assert_eq!(lines[2].spans[0].style.fg, Some(theme.success));
+    }
+}
@@ -0,0 +1,132 @@
+//! Compose a prompt in an external editor ($VISUAL / $EDITOR)
+//!
+//! The ask dialog suspends itself, writes the current input to a temporary
+//! markdown file, runs the editor on the terminal, and reads the file back.
+
+use std::fs::File;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+use sysinfo::System;
+
+/// Editor command from $VISUAL or $EDITOR (falls back to vi)
+pub fn editor_command() -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+/// Check if an editor command launches Helix
+fn is_helix(command: &str) -> bool {
+    command
+        .split_whitespace()
+        .next()
+        .and_then(|program| Path::new(program).file_name())
+        .is_some_and(|name| name == "hx" || name == "helix")
+}
+
+/// Check if we are running inside Helix ($HELIX_PID set, or a Helix ancestor process)
+pub fn inside_helix() -> bool {
+    if std::env::var_os("HELIX_PID").is_some() {
+        return true;
+    }
+
+    let system = System::new_all();
+    let mut pid = sysinfo::get_current_pid().ok();
+    // Bounded walk in case of a parent cycle
+    for _ in 0..64 {
+        let Some(process) = pid.and_then(|p| system.process(p)) else {
+            break;
+        };
+        if is_helix(&process.name().to_string_lossy()) {
+            return true;
+        }
+        pid = process.parent();
+    }
+    false
+}
+
+/// Reason to refuse launching `editor`, if any
+/// Helix can't be nested inside the Helix instance that launched us
+pub fn refusal(editor: &str, inside_helix: bool, allow_nested: bool) -> Option<String> {
+    (is_helix(editor) && inside_helix && !allow_nested).then(|| {
+        format!(
+            "Refusing to run {} inside Helix (set allow_nested_editor = true to override)",
+            editor
+        )
+    })
+}
+
+/// Write `text` to a temporary .md file, run `editor` on it, and return the edited text
+///
+/// Fails (leaving the caller's text alone) if the editor exits nonzero or the
+/// file is gone afterwards.
+pub fn edit_text(editor: &str, text: &str) -> Result<String> {
+    let path = std::env::temp_dir().join(format!("opencode-helix-{}.md", uuid::Uuid::new_v4()));
+    std::fs::write(&path, text).context("Failed to write temp file")?;
+
+    let result = run_editor(editor, &path).and_then(|()| {
+        std::fs::read_to_string(&path).context("Edited file was deleted or unreadable")
+    });
+    let _ = std::fs::remove_file(&path);
+
+    Ok(result?.trim_end_matches('\n').to_string())
+}
+
+/// Run the editor on `path`, attached to the terminal
+fn run_editor(editor: &str, path: &Path) -> Result<()> {
+    // Run through the shell so commands like "code --wait" work
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(path);
+
+    // stdout may be captured by Helix, so talk to the terminal directly
+    if let Ok(tty) = File::options().read(true).write(true).open("/dev/tty") {
+        cmd.stdin(Stdio::from(tty.try_clone()?))
+            .stdout(Stdio::from(tty.try_clone()?))
+            .stderr(Stdio::from(tty));
+    }
+
+    let status = cmd
+        .status()
+        .with_context(|| format!("Failed to run {}", editor))?;
+    if !status.success() {
+        bail!("{} exited with {}", editor, status);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_helix() {
+        assert!(is_helix("hx"));
+        assert!(is_helix("/usr/bin/helix --vsplit"));
+        assert!(!is_helix("vim"));
+        assert!(!is_helix("code --wait"));
+    }
+
+    #[test]
+    fn test_refusal() {
+        assert!(refusal("hx", true, false).is_some());
+        assert!(refusal("hx", true, true).is_none());
+        assert!(refusal("hx", false, false).is_none());
+        assert!(refusal("nvim", true, false).is_none());
+    }
+
+    #[test]
+    fn test_edit_text() {
+        let edited = edit_text("sed -i 's/hello/goodbye/'", "hello world\n").unwrap();
+        assert_eq!(edited, "goodbye world");
+
+        assert!(edit_text("false", "keep").is_err());
+        assert!(edit_text("rm", "gone").is_err());
+    }
+}
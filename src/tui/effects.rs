@@ -1 +1,278 @@
-//! Visual effects for the TUI (placeholder - animations removed)
+//! Dialog open animation and the button focus flash
+//!
+//! A dialog's border grows from the center to its full size over
+//! [`OPEN_FRAMES`] frames (about 120ms), and a button flashes in the accent
+//! color for two frames when it gets the focus. Both are off with
+//! `--no-anim` and on terminals that look slow (`dialog_effects` in the
+//! config); the dialog then shows its final layout in the first frame.
+//!
+//! Neither holds up input: the loops keep polling for keys in between
+//! frames, and a key ends the open animation at once.
+
+use std::time::{Duration, Instant};
+
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Borders, Clear};
+
+use crate::tui::theme::Theme;
+
+/// Frames of the open animation after the first
+pub const OPEN_FRAMES: u8 = 5;
+
+/// Time between animation frames
+pub const FRAME_INTERVAL: Duration = Duration::from_millis(24);
+
+/// Frames a focused button flashes for
+const FLASH_FRAMES: u8 = 2;
+
+/// Ease-out cubic: quick at first, settling at the end (`t` in 0..=1)
+pub fn ease(t: f32) -> f32 {
+    let t = t.clamp(0.0, 1.0);
+    1.0 - (1.0 - t).powi(3)
+}
+
+/// Whether the terminal looks too slow for animations
+///
+/// Over SSH every frame crosses the network, and a dumb terminal can't
+/// redraw in place. `var` looks up an environment variable.
+pub fn slow_terminal(var: impl Fn(&str) -> Option<String>) -> bool {
+    var("SSH_CONNECTION").is_some()
+        || var("SSH_TTY").is_some()
+        || var("TERM").as_deref() == Some("dumb")
+}
+
+/// Frames left to show, one every [`FRAME_INTERVAL`]
+#[derive(Debug)]
+struct Frames {
+    left: u8,
+    next_at: Instant,
+}
+
+impl Frames {
+    fn new(now: Instant, count: u8) -> Self {
+        Self {
+            left: count,
+            next_at: now + FRAME_INTERVAL,
+        }
+    }
+
+    /// Step to the next frame if it is due
+    fn tick(&mut self, now: Instant) -> bool {
+        if self.left == 0 || now < self.next_at {
+            return false;
+        }
+        self.left -= 1;
+        self.next_at = now + FRAME_INTERVAL;
+        true
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        (self.left > 0).then_some(self.next_at)
+    }
+}
+
+/// The border of a dialog growing to its size as it opens
+#[derive(Debug)]
+pub struct OpenAnimation {
+    frames: Frames,
+}
+
+impl OpenAnimation {
+    /// Starts at the smallest size; already finished when not `enabled`
+    pub fn new(now: Instant, enabled: bool) -> Self {
+        let count = if enabled { OPEN_FRAMES } else { 0 };
+        Self {
+            frames: Frames::new(now, count),
+        }
+    }
+
+    /// Whether a frame before the final layout is showing
+    pub fn running(&self) -> bool {
+        self.frames.left > 0
+    }
+
+    /// Advance to the frame due at `now`; true when it needs drawing
+    pub fn tick(&mut self, now: Instant) -> bool {
+        self.frames.tick(now)
+    }
+
+    /// Jump to the final layout (on a key)
+    pub fn finish(&mut self) {
+        self.frames.left = 0;
+    }
+
+    /// When the next frame is due, while running
+    pub fn deadline(&self) -> Option<Instant> {
+        self.frames.deadline()
+    }
+
+    /// The border's rect in this frame, centered on `target`
+    pub fn current_rect(&self, target: Rect) -> Rect {
+        let frame = OPEN_FRAMES - self.frames.left;
+        let t = ease(f32::from(frame) / f32::from(OPEN_FRAMES));
+        let grow = |full: u16| {
+            let start = full.min(2);
+            start + (f32::from(full - start) * t).round() as u16
+        };
+        let (width, height) = (grow(target.width), grow(target.height));
+        Rect {
+            x: target.x + (target.width - width) / 2,
+            y: target.y + (target.height - height) / 2,
+            width,
+            height,
+        }
+    }
+}
+
+/// A button flashing as it gets the focus
+#[derive(Debug)]
+pub struct Flash {
+    enabled: bool,
+    frames: Frames,
+}
+
+impl Flash {
+    pub fn new(now: Instant, enabled: bool) -> Self {
+        Self {
+            enabled,
+            frames: Frames::new(now, 0),
+        }
+    }
+
+    /// Start flashing (when enabled)
+    pub fn start(&mut self, now: Instant) {
+        if self.enabled {
+            self.frames = Frames::new(now, FLASH_FRAMES);
+        }
+    }
+
+    /// Whether the button is drawn in the flash color
+    pub fn active(&self) -> bool {
+        self.frames.left > 0
+    }
+
+    /// Advance to the frame due at `now`; true when it needs drawing
+    pub fn tick(&mut self, now: Instant) -> bool {
+        self.frames.tick(now)
+    }
+
+    /// Stop flashing (when the terminal loses the focus)
+    pub fn finish(&mut self) {
+        self.frames.left = 0;
+    }
+
+    /// When the next frame is due, while flashing
+    pub fn deadline(&self) -> Option<Instant> {
+        self.frames.deadline()
+    }
+}
+
+/// Draw the empty dialog border of an opening frame
+pub fn render_opening(frame: &mut Frame, rect: Rect, theme: &Theme, border: Color) {
+    frame.render_widget(Clear, rect);
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(theme.border_type())
+        .border_style(Style::default().fg(border));
+    frame.render_widget(block, rect);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ease() {
+        assert_eq!(ease(0.0), 0.0);
+        assert_eq!(ease(1.0), 1.0);
+        assert_eq!(ease(2.0), 1.0);
+        // Past halfway by the middle of the animation
+        assert!(ease(0.5) > 0.8);
+        let steps: Vec<f32> = (0..=4).map(|i| ease(i as f32 / 4.0)).collect();
+        assert!(steps.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_open_animation_grows_to_target() {
+        let start = Instant::now();
+        let target = Rect::new(10, 5, 60, 20);
+        let mut open = OpenAnimation::new(start, true);
+        assert!(open.running());
+        assert_eq!(open.current_rect(target), Rect::new(39, 14, 2, 2));
+
+        // Nothing is due before the interval
+        assert!(!open.tick(start + FRAME_INTERVAL / 2));
+        assert_eq!(open.deadline(), Some(start + FRAME_INTERVAL));
+
+        let mut now = start;
+        let mut last = open.current_rect(target);
+        let mut frames = 1;
+        while open.running() {
+            now += FRAME_INTERVAL;
+            assert!(open.tick(now));
+            let rect = open.current_rect(target);
+            assert!(rect.width >= last.width && rect.height >= last.height);
+            // Stays centered on the target
+            assert!(rect.x >= target.x && rect.right() <= target.right());
+            last = rect;
+            frames += 1;
+        }
+        assert_eq!(frames, 6);
+        assert_eq!(now - start, Duration::from_millis(120));
+        assert_eq!(last, target);
+        assert_eq!(open.deadline(), None);
+        assert!(!open.tick(now + FRAME_INTERVAL));
+    }
+
+    #[test]
+    fn test_open_animation_skipped() {
+        let start = Instant::now();
+        let target = Rect::new(0, 0, 1, 1);
+        let disabled = OpenAnimation::new(start, false);
+        assert!(!disabled.running());
+        assert_eq!(disabled.current_rect(target), target);
+        assert_eq!(disabled.deadline(), None);
+
+        // A key shows the final layout right away
+        let mut open = OpenAnimation::new(start, true);
+        open.finish();
+        assert!(!open.running());
+        assert_eq!(open.current_rect(target), target);
+    }
+
+    #[test]
+    fn test_flash_lasts_two_frames() {
+        let start = Instant::now();
+        let mut flash = Flash::new(start, true);
+        assert!(!flash.active());
+        flash.start(start);
+        assert!(flash.active());
+        assert!(flash.tick(start + FRAME_INTERVAL));
+        assert!(flash.active());
+        assert!(flash.tick(start + FRAME_INTERVAL * 2));
+        assert!(!flash.active());
+        assert_eq!(flash.deadline(), None);
+
+        let mut off = Flash::new(start, false);
+        off.start(start);
+        assert!(!off.active());
+    }
+
+    #[test]
+    fn test_slow_terminal() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        assert!(!slow_terminal(env(&[("TERM", "xterm-256color")])));
+        assert!(slow_terminal(env(&[(
+            "SSH_CONNECTION",
+            "10.0.0.2 50000 10.0.0.1 22"
+        )])));
+        assert!(slow_terminal(env(&[("SSH_TTY", "/dev/pts/3")])));
+        assert!(slow_terminal(env(&[("TERM", "dumb")])));
+    }
+}
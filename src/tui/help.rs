@@ -0,0 +1,116 @@
+//! Keybinding help overlay for the TUI dialogs
+//!
+//! The overlay lists a dialog's keymap (see [`crate::tui::keymap`]), the
+//! table its keys are dispatched on, grouped by category.
+
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+
+use unicode_width::UnicodeWidthStr;
+
+use crate::tui::keymap::Binding;
+use crate::tui::strings::Strings;
+use crate::tui::theme::Theme;
+
+/// Overlay lines: a heading per category followed by its bindings, in the
+/// language of `strings`
+pub fn help_lines(bindings: &[Binding], theme: &Theme, strings: &Strings) -> Vec<Line<'static>> {
+    let labels: Vec<String> = bindings.iter().map(Binding::label).collect();
+    let keys_width = labels.iter().map(|l| l.width()).max().unwrap_or(0);
+
+    let mut lines = Vec::new();
+    let mut category = None;
+    for (b, keys) in bindings.iter().zip(&labels) {
+        if category != Some(b.category) {
+            if category.is_some() {
+                lines.push(Line::default());
+            }
+            lines.push(Line::from(Span::styled(
+                strings.help(b.category).to_string(),
+                Style::default()
+                    .fg(theme.warning)
+                    .add_modifier(Modifier::BOLD),
+            )));
+            category = Some(b.category);
+        }
+        lines.push(Line::from(vec![
+            Span::styled(
+                format!("  {}{}  ", keys, " ".repeat(keys_width - keys.width())),
+                Style::default().fg(theme.secondary),
+            ),
+            Span::styled(
+                strings.help(b.action).to_string(),
+                Style::default().fg(theme.text),
+            ),
+        ]));
+    }
+    lines
+}
+
+/// Largest useful scroll offset for the overlay in a terminal of `area_height` rows
+pub fn max_scroll(bindings: &[Binding], area_height: u16) -> usize {
+    let categories = bindings
+        .iter()
+        .enumerate()
+        .filter(|(i, b)| *i == 0 || bindings[i - 1].category != b.category)
+        .count();
+    // Headings plus blank separators, plus borders
+    let total = bindings.len() + categories * 2 - 1;
+    total.saturating_sub(area_height.saturating_sub(2) as usize)
+}
+
+/// Render the overlay centered in `area`
+pub fn render_help(
+    frame: &mut Frame,
+    area: Rect,
+    theme: &Theme,
+    strings: &Strings,
+    bindings: &[Binding],
+    scroll: usize,
+) {
+    let lines = help_lines(bindings, theme, strings);
+    let width = area.width.min(60);
+    let height = area.height.min(lines.len() as u16 + 2);
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+
+    frame.render_widget(Clear, popup);
+    let block = Block::default()
+        .title(format!(" {} ", strings.keys_title))
+        .title_style(
+            Style::default()
+                .fg(theme.primary)
+                .add_modifier(Modifier::BOLD),
+        )
+        .title_bottom(Line::from(format!(" {} ", strings.keys_help)).centered())
+        .borders(Borders::ALL)
+        .border_type(theme.border_type())
+        .border_style(Style::default().fg(theme.primary));
+    let para = Paragraph::new(lines)
+        .block(block)
+        .scroll((scroll as u16, 0));
+    frame.render_widget(para, popup);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tui::keymap::SELECT_BINDINGS;
+
+    #[test]
+    fn test_help_lines_grouped() {
+        let theme = Theme::default();
+        let lines = help_lines(SELECT_BINDINGS, &theme, &Strings::default());
+        // 3 categories: headings + 2 separators + bindings
+        assert_eq!(lines.len(), SELECT_BINDINGS.len() + 3 + 2);
+        assert_eq!(lines[0].spans[0].content, "General");
+        assert_eq!(max_scroll(SELECT_BINDINGS, 100), 0);
+        assert_eq!(max_scroll(SELECT_BINDINGS, 7), 13);
+    }
+}
@@ -0,0 +1,99 @@
+//! Syntax-highlighted previews for the placeholders panel
+//!
+//! With the `syntax` feature, previews are highlighted with syntect based on
+//! the file language. Without it (or for unknown languages) lines are plain.
+
+use ratatui::prelude::*;
+
+/// Highlight the first `max_lines` lines of `text`
+/// Falls back to plain lines styled with `fallback` when highlighting is unavailable
+pub fn highlight_preview(
+    text: &str,
+    language: Option<&str>,
+    max_lines: usize,
+    fallback: Color,
+) -> Vec<Line<'static>> {
+    #[cfg(feature = "syntax")]
+    if let Some(lines) = language.and_then(|lang| syntax::highlight(text, lang, max_lines)) {
+        return lines;
+    }
+    #[cfg(not(feature = "syntax"))]
+    let _ = language;
+
+    text.lines()
+        .take(max_lines)
+        .map(|l| Line::from(Span::styled(l.to_string(), Style::default().fg(fallback))))
+        .collect()
+}
+
+#[cfg(feature = "syntax")]
+mod syntax {
+    use std::sync::OnceLock;
+
+    use ratatui::prelude::*;
+    use syntect::easy::HighlightLines;
+    use syntect::highlighting::ThemeSet;
+    use syntect::parsing::SyntaxSet;
+    use syntect::util::LinesWithEndings;
+
+    /// Syntax theme used for previews
+    const SYNTAX_THEME: &str = "base16-ocean.dark";
+
+    /// Loaded syntax and theme sets, cached for the life of the process
+    fn sets() -> &'static (SyntaxSet, ThemeSet) {
+        static SETS: OnceLock<(SyntaxSet, ThemeSet)> = OnceLock::new();
+        SETS.get_or_init(|| {
+            (
+                SyntaxSet::load_defaults_newlines(),
+                ThemeSet::load_defaults(),
+            )
+        })
+    }
+
+    /// Highlight text for a language; None if the language is unknown or highlighting fails
+    pub fn highlight(text: &str, language: &str, max_lines: usize) -> Option<Vec<Line<'static>>> {
+        let (syntax_set, theme_set) = sets();
+        let syntax = syntax_set.find_syntax_by_token(language)?;
+        let theme = theme_set.themes.get(SYNTAX_THEME)?;
+        let mut highlighter = HighlightLines::new(syntax, theme);
+
+        let mut lines = Vec::new();
+        for line in LinesWithEndings::from(text).take(max_lines) {
+            let ranges = highlighter.highlight_line(line, syntax_set).ok()?;
+            let spans: Vec<Span<'static>> = ranges
+                .into_iter()
+                .map(|(style, piece)| {
+                    let fg = style.foreground;
+                    Span::styled(
+                        piece.trim_end_matches(['\r', '\n']).to_string(),
+                        Style::default().fg(Color::Rgb(fg.r, fg.g, fg.b)),
+                    )
+                })
+                .filter(|span| !span.content.is_empty())
+                .collect();
+            lines.push(Line::from(spans));
+        }
+        Some(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_preview_fallback() {
+        let lines = highlight_preview("a\nb\nc\nd", None, 3, Color::DarkGray);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].spans[0].content, "a");
+        assert_eq!(lines[0].spans[0].style.fg, Some(Color::DarkGray));
+    }
+
+    #[cfg(feature = "syntax")]
+    #[test]
+    fn test_highlight_known_language() {
+        let lines = highlight_preview("fn main() {}\n", Some("rust"), 3, Color::DarkGray);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].spans.len() > 1);
+    }
+}
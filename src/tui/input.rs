@@ -0,0 +1,501 @@
+//! Raw terminal input decoding
+//!
+//! Bytes read from /dev/tty are buffered and split into key events. A single
+//! read may contain several keys (key repeat, paste), and an escape sequence
+//! may be split across reads, so incomplete trailing bytes are kept until the
+//! next read.
+//!
+//! Focus reports (`ESC [ I` / `ESC [ O`, sent after `ESC [ ? 1004 h`) are
+//! never keys: they are taken out of the input and remembered for
+//! [`take_focus`](InputBuffer::take_focus).
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Buffered raw input from the terminal
+#[derive(Debug, Default)]
+pub struct InputBuffer {
+    bytes: Vec<u8>,
+    /// The last focus report not yet taken: true for focus in
+    focus: Option<bool>,
+}
+
+impl InputBuffer {
+    /// Create an empty input buffer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append bytes read from the terminal
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    /// Check if no bytes are buffered
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Pop the next complete key event off the front of the buffer
+    /// Returns None if the buffer is empty or only holds an incomplete sequence
+    pub fn next_key(&mut self) -> Option<KeyEvent> {
+        while !self.bytes.is_empty() {
+            let len = sequence_len(&self.bytes)?;
+            let seq: Vec<u8> = self.bytes.drain(..len).collect();
+            if let Some(focused) = focus_report(&seq) {
+                self.focus = Some(focused);
+            } else if let Some(key) = parse_key(&seq) {
+                return Some(key);
+            }
+        }
+        None
+    }
+
+    /// Remove the first cursor position report (`ESC [ row ; col R`, the
+    /// reply to `ESC [ 6 n`) from the buffer, returning its 1-based row
+    ///
+    /// Keys before and after it stay buffered.
+    pub fn take_cursor_row(&mut self) -> Option<u16> {
+        let mut start = 0;
+        while start < self.bytes.len() {
+            let len = sequence_len(&self.bytes[start..])?;
+            if let Some(row) = cursor_report_row(&self.bytes[start..start + len]) {
+                self.bytes.drain(start..start + len);
+                return Some(row);
+            }
+            start += len;
+        }
+        None
+    }
+
+    /// The focus change reported since the last call, if any: true when the
+    /// terminal gained the focus, false when it lost it
+    pub fn take_focus(&mut self) -> Option<bool> {
+        self.focus.take()
+    }
+
+    /// Give up on an incomplete trailing sequence (no more bytes arrived)
+    /// A lone or truncated escape sequence becomes a bare Escape key
+    pub fn flush(&mut self) -> Option<KeyEvent> {
+        if let Some(key) = self.next_key() {
+            return Some(key);
+        }
+        let pending = std::mem::take(&mut self.bytes);
+        match pending.as_slice() {
+            // ESC O with nothing after it was Alt+O, not the start of an SS3 sequence
+            [0x1b, 0x4f] => Some(KeyEvent::new(KeyCode::Char('O'), KeyModifiers::ALT)),
+            [0x1b, ..] => Some(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)),
+            _ => None,
+        }
+    }
+}
+
+/// Length of the complete sequence at the front of `buf`
+/// Returns None if more bytes are needed
+fn sequence_len(buf: &[u8]) -> Option<usize> {
+    match buf {
+        [] => None,
+        // Lone escape: could be the start of a sequence
+        [0x1b] => None,
+        // Escape followed by escape: the first is a bare Escape key
+        [0x1b, 0x1b, ..] => Some(1),
+        // X10 mouse report: ESC [ M + 3 bytes
+        [0x1b, 0x5b, 0x4d, rest @ ..] => (rest.len() >= 3).then_some(6),
+        // Linux console F1-F5: ESC [ [ A-E
+        [0x1b, 0x5b, 0x5b, rest @ ..] => (!rest.is_empty()).then_some(4),
+        // CSI: ESC [ params* intermediates* final
+        [0x1b, 0x5b, rest @ ..] => rest
+            .iter()
+            .position(|b| (0x40..=0x7e).contains(b))
+            .map(|i| i + 3),
+        // SS3: ESC O + final byte (application-mode arrows, Home/End, F1-F4)
+        [0x1b, 0x4f, rest @ ..] => (!rest.is_empty()).then_some(3),
+        // Alt + key (or an unknown two-byte sequence)
+        [0x1b, _, ..] => Some(2),
+        // UTF-8 multi-byte character
+        [b, ..] if *b >= 0xc0 => {
+            let len = match *b {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                _ => 4,
+            };
+            (buf.len() >= len).then_some(len)
+        }
+        _ => Some(1),
+    }
+}
+
+/// Row of a cursor position report (`ESC [ row ; col R`)
+fn cursor_report_row(seq: &[u8]) -> Option<u16> {
+    let params = seq.strip_prefix(b"\x1b[")?.strip_suffix(b"R")?;
+    let (row, col) = std::str::from_utf8(params).ok()?.split_once(';')?;
+    col.parse::<u16>().ok()?;
+    row.parse().ok()
+}
+
+/// Whether a focus report (`ESC [ I` or `ESC [ O`) is focus in
+fn focus_report(seq: &[u8]) -> Option<bool> {
+    match seq {
+        b"\x1b[I" => Some(true),
+        b"\x1b[O" => Some(false),
+        _ => None,
+    }
+}
+
+/// Function key for the number in `ESC [ <n> ~` (F1-F12)
+fn function_key_number(n: u8) -> Option<u8> {
+    match n {
+        11..=15 => Some(n - 10),
+        17..=21 => Some(n - 11),
+        23 | 24 => Some(n - 12),
+        _ => None,
+    }
+}
+
+/// Modifiers encoded in the second CSI parameter (`ESC [ 1 ; <m> H`)
+/// The value is 1 plus a bit mask: 1 Shift, 2 Alt, 4 Ctrl.
+fn modifiers_param(param: &str) -> Option<KeyModifiers> {
+    let bits = param.parse::<u8>().ok()?.checked_sub(1)?;
+    let mut modifiers = KeyModifiers::NONE;
+    for (bit, modifier) in [
+        (1, KeyModifiers::SHIFT),
+        (2, KeyModifiers::ALT),
+        (4, KeyModifiers::CONTROL),
+    ] {
+        if bits & bit != 0 {
+            modifiers |= modifier;
+        }
+    }
+    Some(modifiers)
+}
+
+/// Decode a CSI sequence from its parameters and final byte
+/// Covers the xterm, rxvt, tmux and Linux console variants of the navigation
+/// and function keys; returns None for anything else.
+fn parse_csi(params: &[u8], final_byte: u8) -> Option<KeyEvent> {
+    let params = std::str::from_utf8(params).ok()?;
+    let mut fields = params.split(';');
+    let first = fields.next().unwrap_or_default();
+    let modifiers = match fields.next() {
+        Some(param) => modifiers_param(param)?,
+        None => KeyModifiers::NONE,
+    };
+    if fields.next().is_some() {
+        return None;
+    }
+
+    let code = match final_byte {
+        b'~' => match first.parse::<u8>().ok()? {
+            1 | 7 => KeyCode::Home,
+            2 => KeyCode::Insert,
+            3 => KeyCode::Delete,
+            4 | 8 => KeyCode::End,
+            5 => KeyCode::PageUp,
+            6 => KeyCode::PageDown,
+            n => KeyCode::F(function_key_number(n)?),
+        },
+        // Letter finals only take "1" as the first parameter (before the modifiers)
+        _ if !first.is_empty() && first != "1" => return None,
+        b'A' => KeyCode::Up,
+        b'B' => KeyCode::Down,
+        b'C' => KeyCode::Right,
+        b'D' => KeyCode::Left,
+        b'H' => KeyCode::Home,
+        b'F' => KeyCode::End,
+        // Modified F1-F4: ESC [ 1 ; <m> P
+        b'P'..=b'S' => KeyCode::F(final_byte - b'P' + 1),
+        b'Z' => return Some(KeyEvent::new(KeyCode::BackTab, KeyModifiers::SHIFT)),
+        _ => return None,
+    };
+    Some(KeyEvent::new(code, modifiers))
+}
+
+/// Parse raw bytes into a KeyEvent
+fn parse_key(bytes: &[u8]) -> Option<KeyEvent> {
+    if bytes.is_empty() {
+        return None;
+    }
+
+    let key = match bytes {
+        // Escape
+        [0x1b] => KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE),
+        // Enter
+        [0x0d] | [0x0a] => KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE),
+        // Backspace, which some terminals send as 0x08 (Ctrl+H)
+        [0x7f] | [0x08] => KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE),
+        // Tab
+        [0x09] => KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE),
+        // Ctrl+C
+        [0x03] => KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL),
+        // Ctrl+D
+        [0x04] => KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL),
+        // Explicit Ctrl+N and Ctrl+P
+        [0x0e] => KeyEvent::new(KeyCode::Char('n'), KeyModifiers::CONTROL),
+        [0x10] => KeyEvent::new(KeyCode::Char('p'), KeyModifiers::CONTROL),
+        // Generic Control characters (Ctrl+A to Ctrl+Z)
+        // 0x01 (A) to 0x1A (Z), excluding those handled above
+        [c] if *c >= 0x01 && *c <= 0x1A => {
+            let char_code = c + 0x60; // 1 -> 'a'
+            KeyEvent::new(KeyCode::Char(char_code as char), KeyModifiers::CONTROL)
+        }
+        // Mouse report (mouse capture is on) - ignored
+        [0x1b, 0x5b, 0x4d, _, _, _] => return None,
+        // Linux console F1-F5: ESC [ [ A-E
+        [0x1b, 0x5b, 0x5b, c @ 0x41..=0x45] => {
+            KeyEvent::new(KeyCode::F(c - 0x41 + 1), KeyModifiers::NONE)
+        }
+        // Arrows, navigation and function keys; unknown sequences are ignored
+        // rather than cancelling the dialog as a bare Escape would
+        [0x1b, 0x5b, params @ .., final_byte] => return parse_csi(params, *final_byte),
+        // SS3 (application mode): arrows, Home/End and F1-F4
+        [0x1b, 0x4f, c] => {
+            let code = match c {
+                0x41 => KeyCode::Up,
+                0x42 => KeyCode::Down,
+                0x43 => KeyCode::Right,
+                0x44 => KeyCode::Left,
+                0x48 => KeyCode::Home,
+                0x46 => KeyCode::End,
+                0x50..=0x53 => KeyCode::F(c - 0x50 + 1),
+                _ => return None,
+            };
+            KeyEvent::new(code, KeyModifiers::NONE)
+        }
+        // Alt + Char
+        [0x1b, c] if *c >= 0x20 && *c < 0x7f => {
+            KeyEvent::new(KeyCode::Char(*c as char), KeyModifiers::ALT)
+        }
+        // Any other escape sequence - treat as Escape key
+        [0x1b, ..] => KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE),
+        // Regular ASCII character
+        [c] if *c >= 0x20 && *c < 0x7f => {
+            KeyEvent::new(KeyCode::Char(*c as char), KeyModifiers::NONE)
+        }
+        // UTF-8 character (2-4 bytes)
+        _ => {
+            if let Ok(s) = std::str::from_utf8(bytes) {
+                if let Some(c) = s.chars().next() {
+                    return Some(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE));
+                }
+            }
+            return None;
+        }
+    };
+
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(buf: &mut InputBuffer) -> Vec<KeyEvent> {
+        std::iter::from_fn(|| buf.next_key()).collect()
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_multiple_chars_in_one_read() {
+        let mut buf = InputBuffer::new();
+        buf.push(b"abc");
+        assert_eq!(
+            keys(&mut buf),
+            vec![
+                key(KeyCode::Char('a')),
+                key(KeyCode::Char('b')),
+                key(KeyCode::Char('c'))
+            ]
+        );
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_repeated_arrows_in_one_read() {
+        let mut buf = InputBuffer::new();
+        buf.push(b"\x1b[A\x1b[A\x1b[B");
+        assert_eq!(
+            keys(&mut buf),
+            vec![key(KeyCode::Up), key(KeyCode::Up), key(KeyCode::Down)]
+        );
+    }
+
+    #[test]
+    fn test_escape_sequence_split_across_reads() {
+        let mut buf = InputBuffer::new();
+        buf.push(b"\x1b");
+        assert_eq!(buf.next_key(), None);
+        assert!(!buf.is_empty());
+        buf.push(b"[A");
+        assert_eq!(buf.next_key(), Some(key(KeyCode::Up)));
+
+        buf.push(b"x\x1b[3");
+        assert_eq!(buf.next_key(), Some(key(KeyCode::Char('x'))));
+        assert_eq!(buf.next_key(), None);
+        buf.push(b"~");
+        assert_eq!(buf.next_key(), Some(key(KeyCode::Delete)));
+    }
+
+    #[test]
+    fn test_flush_lone_escape() {
+        let mut buf = InputBuffer::new();
+        buf.push(b"\x1b");
+        assert_eq!(buf.next_key(), None);
+        assert_eq!(buf.flush(), Some(key(KeyCode::Esc)));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_utf8_split_across_reads() {
+        let mut buf = InputBuffer::new();
+        let bytes = "é€".as_bytes();
+        buf.push(&bytes[..1]);
+        assert_eq!(buf.next_key(), None);
+        buf.push(&bytes[1..3]);
+        assert_eq!(buf.next_key(), Some(key(KeyCode::Char('é'))));
+        buf.push(&bytes[3..]);
+        assert_eq!(buf.next_key(), Some(key(KeyCode::Char('€'))));
+    }
+
+    #[test]
+    fn test_backspace_both_forms() {
+        let mut buf = InputBuffer::new();
+        buf.push(b"\x7f\x08");
+        assert_eq!(buf.next_key(), Some(key(KeyCode::Backspace)));
+        assert_eq!(buf.next_key(), Some(key(KeyCode::Backspace)));
+    }
+
+    #[test]
+    fn test_function_keys() {
+        let mut buf = InputBuffer::new();
+        buf.push(b"\x1bOP\x1bOS\x1b[15~\x1b[17~\x1b[21~\x1b[24~");
+        let codes: Vec<KeyCode> = keys(&mut buf).into_iter().map(|k| k.code).collect();
+        assert_eq!(codes, [1, 4, 5, 6, 10, 12].map(KeyCode::F).to_vec());
+
+        // ESC O alone is Alt+O once no more bytes arrive
+        buf.push(b"\x1bO");
+        assert_eq!(buf.next_key(), None);
+        assert_eq!(
+            buf.flush(),
+            Some(KeyEvent::new(KeyCode::Char('O'), KeyModifiers::ALT))
+        );
+    }
+
+    #[test]
+    fn test_navigation_sequences() {
+        let ctrl = KeyModifiers::CONTROL;
+        let table: &[(&[u8], KeyEvent)] = &[
+            // xterm
+            (b"\x1b[H", key(KeyCode::Home)),
+            (b"\x1b[F", key(KeyCode::End)),
+            (b"\x1b[5~", key(KeyCode::PageUp)),
+            (b"\x1b[6~", key(KeyCode::PageDown)),
+            (b"\x1b[2~", key(KeyCode::Insert)),
+            (b"\x1b[3~", key(KeyCode::Delete)),
+            (
+                b"\x1b[Z",
+                KeyEvent::new(KeyCode::BackTab, KeyModifiers::SHIFT),
+            ),
+            // tmux, Linux console
+            (b"\x1b[1~", key(KeyCode::Home)),
+            (b"\x1b[4~", key(KeyCode::End)),
+            // rxvt
+            (b"\x1b[7~", key(KeyCode::Home)),
+            (b"\x1b[8~", key(KeyCode::End)),
+            // Application cursor mode
+            (b"\x1bOH", key(KeyCode::Home)),
+            (b"\x1bOF", key(KeyCode::End)),
+            (b"\x1bOA", key(KeyCode::Up)),
+            (b"\x1bOD", key(KeyCode::Left)),
+            // Modifier-encoded forms
+            (b"\x1b[1;5H", KeyEvent::new(KeyCode::Home, ctrl)),
+            (
+                b"\x1b[1;2F",
+                KeyEvent::new(KeyCode::End, KeyModifiers::SHIFT),
+            ),
+            (b"\x1b[1;3A", KeyEvent::new(KeyCode::Up, KeyModifiers::ALT)),
+            (b"\x1b[5;5~", KeyEvent::new(KeyCode::PageUp, ctrl)),
+            (
+                b"\x1b[1;6C",
+                KeyEvent::new(KeyCode::Right, ctrl | KeyModifiers::SHIFT),
+            ),
+            (
+                b"\x1b[15;2~",
+                KeyEvent::new(KeyCode::F(5), KeyModifiers::SHIFT),
+            ),
+            (b"\x1b[1;5P", KeyEvent::new(KeyCode::F(1), ctrl)),
+            // Linux console F1-F5
+            (b"\x1b[[A", key(KeyCode::F(1))),
+            (b"\x1b[[E", key(KeyCode::F(5))),
+        ];
+        for (bytes, expected) in table {
+            let mut buf = InputBuffer::new();
+            buf.push(bytes);
+            assert_eq!(buf.next_key(), Some(*expected), "{:?}", bytes);
+            assert!(buf.is_empty(), "{:?}", bytes);
+        }
+    }
+
+    #[test]
+    fn test_unknown_sequences_are_ignored() {
+        // Focus events, SGR mouse, unknown ~ codes and private replies aren't Escape
+        let mut buf = InputBuffer::new();
+        buf.push(b"\x1b[I\x1b[<0;3;4M\x1b[99~\x1b[?1;2c\x1b[2A\x1bOxa");
+        assert_eq!(keys(&mut buf), [key(KeyCode::Char('a'))]);
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_focus_reports() {
+        let mut buf = InputBuffer::new();
+        assert_eq!(buf.take_focus(), None);
+        buf.push(b"a\x1b[Ob\x1b[");
+        assert_eq!(
+            keys(&mut buf),
+            [key(KeyCode::Char('a')), key(KeyCode::Char('b'))]
+        );
+        assert_eq!(buf.take_focus(), Some(false));
+        assert_eq!(buf.take_focus(), None);
+
+        // Split across reads; only the latest counts
+        buf.push(b"I\x1b[O\x1b[I");
+        assert_eq!(keys(&mut buf), []);
+        assert_eq!(buf.take_focus(), Some(true));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_cursor_report() {
+        let mut buf = InputBuffer::new();
+        buf.push(b"a\x1b[A\x1b[12;40Rb");
+        assert_eq!(buf.take_cursor_row(), Some(12));
+        assert_eq!(
+            keys(&mut buf),
+            [
+                key(KeyCode::Char('a')),
+                key(KeyCode::Up),
+                key(KeyCode::Char('b'))
+            ]
+        );
+
+        // Not there yet, or cut off
+        buf.push(b"x\x1b[12;4");
+        assert_eq!(buf.take_cursor_row(), None);
+        buf.push(b"0R");
+        assert_eq!(buf.take_cursor_row(), Some(12));
+        assert_eq!(keys(&mut buf), [key(KeyCode::Char('x'))]);
+    }
+
+    #[test]
+    fn test_alt_and_mouse() {
+        let mut buf = InputBuffer::new();
+        buf.push(b"\x1bx\x1b[M !!q");
+        assert_eq!(
+            buf.next_key(),
+            Some(KeyEvent::new(KeyCode::Char('x'), KeyModifiers::ALT))
+        );
+        // Mouse report is consumed without producing a key
+        assert_eq!(buf.next_key(), Some(key(KeyCode::Char('q'))));
+    }
+}
@@ -0,0 +1,729 @@
+//! Keybindings of the ask and select dialogs
+//!
+//! The tables here are what the dialogs dispatch on: a key is looked up to
+//! the [`Command`] it runs, and the help overlay lists the same rows, so what
+//! the overlay says is what the keys do.
+//!
+//! A row's keys come in groups, each bound under a condition ([`When`]). A
+//! key whose condition holds wins over the same key bound unconditionally,
+//! as Ctrl+D cancels on an empty input and deletes otherwise; among
+//! conditional bindings the first row wins.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use Command as C;
+use KeyCode::{
+    BackTab, Backspace, Char, Delete, Down, End, Enter, Esc, Home, Left, PageDown, PageUp, Right,
+    Tab, Up,
+};
+
+/// What a key does in a dialog
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Send the prompt, or run the selected item
+    Send,
+    Cancel,
+    FocusNext,
+    FocusPrev,
+    /// Compose in `$VISUAL` / `$EDITOR`
+    Compose,
+    Copy,
+    TogglePreview,
+    Help,
+    /// A configured quick action, if the key has one
+    Quick,
+    PickRoute,
+    DropImage,
+    Left,
+    Right,
+    Home,
+    End,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    /// Scroll the input without moving the cursor
+    ScrollUp,
+    ScrollDown,
+    /// First or last line of the input
+    First,
+    Last,
+    Backspace,
+    Delete,
+    DeleteWord,
+    Transpose,
+    Upcase,
+    Downcase,
+    Capitalize,
+    AcceptSuggestion,
+    /// Type the character
+    Insert,
+    CompletionUp,
+    CompletionDown,
+    Complete,
+    CloseCompletion,
+    PlaceholderUp,
+    PlaceholderDown,
+    InsertPlaceholder,
+    ViewPlaceholder,
+    ViewUp,
+    ViewDown,
+    ViewPageUp,
+    ViewPageDown,
+    CloseView,
+    /// Open the selected item in the ask dialog
+    Edit,
+    Collapse,
+    Expand,
+    /// Collapse or expand the category under the selection
+    Toggle,
+}
+
+/// When a group of keys is bound
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum When {
+    Always,
+    /// Nothing typed in the ask input
+    EmptyText,
+    /// Nothing typed in the select filter
+    EmptyFilter,
+    /// The last prompt is suggested in the empty input
+    Suggestion,
+    /// There is a selection to preview
+    WithSelection,
+    /// A pasted image is attached
+    WithImage,
+    /// The autocomplete popup lists completions
+    Completing,
+    /// The placeholders panel has the focus
+    OnPlaceholders,
+    /// A placeholder's expansion is open
+    InView,
+    /// The selection is on a category header
+    OnHeader,
+}
+
+impl When {
+    /// Shown after the keys in the overlay
+    fn note(self) -> Option<&'static str> {
+        match self {
+            Self::EmptyText | Self::Suggestion => Some("(empty input)"),
+            Self::EmptyFilter => Some("(empty filter)"),
+            Self::WithSelection => Some("(with a selection)"),
+            Self::WithImage => Some("(with an image)"),
+            Self::OnHeader => Some("(on a header)"),
+            Self::Always | Self::Completing | Self::OnPlaceholders | Self::InView => None,
+        }
+    }
+}
+
+/// A key, or a kind of key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// The key with exactly these of Ctrl and Alt held
+    Code(KeyCode, KeyModifiers),
+    /// Any function key
+    Function,
+    /// Alt and a digit
+    AltDigit,
+    /// Any character typed without Ctrl or Alt, shown as the text given
+    Text(&'static str),
+}
+
+impl Key {
+    fn matches(self, key: &KeyEvent) -> bool {
+        let held = key
+            .modifiers
+            .intersection(KeyModifiers::CONTROL | KeyModifiers::ALT);
+        match self {
+            Self::Code(code, modifiers) => key.code == code && held == modifiers,
+            Self::Function => matches!(key.code, KeyCode::F(_)) && key.modifiers.is_empty(),
+            Self::AltDigit => {
+                matches!(key.code, KeyCode::Char('0'..='9')) && key.modifiers == KeyModifiers::ALT
+            }
+            Self::Text(_) => matches!(key.code, KeyCode::Char(_)) && held.is_empty(),
+        }
+    }
+
+    fn label(self) -> String {
+        let (code, modifiers) = match self {
+            Self::Code(code, modifiers) => (code, modifiers),
+            Self::Function => return "F1-F12".to_string(),
+            Self::AltDigit => return "Alt+0-9".to_string(),
+            Self::Text(text) => return text.to_string(),
+        };
+        let name = match code {
+            KeyCode::Char(c) if modifiers.is_empty() => c.to_string(),
+            KeyCode::Char(c) => c.to_uppercase().to_string(),
+            KeyCode::Enter => "Enter".to_string(),
+            KeyCode::Esc => "Esc".to_string(),
+            KeyCode::Tab => "Tab".to_string(),
+            KeyCode::BackTab => "Shift+Tab".to_string(),
+            KeyCode::Backspace => "Backspace".to_string(),
+            KeyCode::Delete => "Delete".to_string(),
+            KeyCode::Left => "←".to_string(),
+            KeyCode::Right => "→".to_string(),
+            KeyCode::Up => "↑".to_string(),
+            KeyCode::Down => "↓".to_string(),
+            KeyCode::Home => "Home".to_string(),
+            KeyCode::End => "End".to_string(),
+            KeyCode::PageUp => "PgUp".to_string(),
+            KeyCode::PageDown => "PgDn".to_string(),
+            other => format!("{:?}", other),
+        };
+        let mut label = String::new();
+        if modifiers.contains(KeyModifiers::CONTROL) {
+            label.push_str("Ctrl+");
+        }
+        if modifiers.contains(KeyModifiers::ALT) {
+            label.push_str("Alt+");
+        }
+        label + &name
+    }
+}
+
+/// Keys bound under one condition, each to its command
+#[derive(Debug, Clone, Copy)]
+pub struct Keys {
+    pub keys: &'static [(Key, Command)],
+    pub when: When,
+}
+
+/// A row of the keymap
+#[derive(Debug, Clone, Copy)]
+pub struct Binding {
+    /// Group heading in the overlay
+    pub category: &'static str,
+    pub keys: &'static [Keys],
+    /// What the keys do
+    pub action: &'static str,
+}
+
+impl Binding {
+    /// The keys as the overlay shows them (e.g. "Tab / e (empty filter)"):
+    /// groups apart with " / ", each condition's note after its last group
+    pub fn label(&self) -> String {
+        let mut label = String::new();
+        for (i, group) in self.keys.iter().enumerate() {
+            if i > 0 {
+                label.push_str(" / ");
+            }
+            let keys: Vec<String> = group.keys.iter().map(|(key, _)| key.label()).collect();
+            label.push_str(&keys.join(" "));
+            let last = self
+                .keys
+                .get(i + 1)
+                .is_none_or(|next| next.when != group.when);
+            if let Some(note) = group.when.note().filter(|_| last) {
+                label.push(' ');
+                label.push_str(note);
+            }
+        }
+        label
+    }
+}
+
+/// The command `key` runs, given which conditions `holds`
+pub fn lookup(
+    bindings: &[Binding],
+    key: &KeyEvent,
+    holds: impl Fn(When) -> bool,
+) -> Option<Command> {
+    let mut unconditional = None;
+    for group in bindings.iter().flat_map(|b| b.keys) {
+        let Some((_, command)) = group.keys.iter().find(|(k, _)| k.matches(key)) else {
+            continue;
+        };
+        if !holds(group.when) {
+            continue;
+        }
+        if group.when != When::Always {
+            return Some(*command);
+        }
+        unconditional = unconditional.or(Some(*command));
+    }
+    unconditional
+}
+
+const fn key(code: KeyCode) -> Key {
+    Key::Code(code, KeyModifiers::NONE)
+}
+
+const fn ctrl(code: KeyCode) -> Key {
+    Key::Code(code, KeyModifiers::CONTROL)
+}
+
+const fn alt(code: KeyCode) -> Key {
+    Key::Code(code, KeyModifiers::ALT)
+}
+
+const fn keys(keys: &'static [(Key, Command)]) -> Keys {
+    Keys {
+        keys,
+        when: When::Always,
+    }
+}
+
+const fn keys_when(when: When, keys: &'static [(Key, Command)]) -> Keys {
+    Keys { keys, when }
+}
+
+const fn binding(category: &'static str, keys: &'static [Keys], action: &'static str) -> Binding {
+    Binding {
+        category,
+        keys,
+        action,
+    }
+}
+
+const HELP: &[(Key, Command)] = &[(alt(Char('h')), C::Help)];
+
+/// Keymap of the ask dialog
+pub const ASK_BINDINGS: &[Binding] = &[
+    binding(
+        "General",
+        &[keys(&[(key(Enter), C::Send)])],
+        "Send the prompt",
+    ),
+    binding(
+        "General",
+        &[
+            keys(&[(key(Esc), C::Cancel)]),
+            keys(&[(ctrl(Char('c')), C::Cancel)]),
+        ],
+        "Cancel",
+    ),
+    binding(
+        "General",
+        &[keys_when(When::EmptyText, &[(ctrl(Char('d')), C::Cancel)])],
+        "Cancel",
+    ),
+    binding(
+        "General",
+        &[
+            keys(&[(key(Tab), C::FocusNext)]),
+            keys(&[(key(BackTab), C::FocusPrev)]),
+        ],
+        "Cycle focus",
+    ),
+    binding(
+        "General",
+        &[keys(&[(ctrl(Char('g')), C::Compose)])],
+        "Compose in $VISUAL / $EDITOR",
+    ),
+    binding(
+        "General",
+        &[keys(&[(ctrl(Char('y')), C::Copy)])],
+        "Copy the expanded prompt",
+    ),
+    binding(
+        "General",
+        &[keys_when(
+            When::WithSelection,
+            &[(ctrl(Char('e')), C::TogglePreview)],
+        )],
+        "Show/hide the selection preview",
+    ),
+    binding(
+        "General",
+        &[
+            keys_when(When::EmptyText, &[(key(Char('?')), C::Help)]),
+            keys(HELP),
+        ],
+        "This help",
+    ),
+    binding(
+        "General",
+        &[
+            keys(&[(Key::Function, C::Quick)]),
+            keys(&[(Key::AltDigit, C::Quick)]),
+        ],
+        "Quick actions from the config",
+    ),
+    binding(
+        "General",
+        &[keys(&[(alt(Char('a')), C::PickRoute)])],
+        "Choose the agent and model",
+    ),
+    binding(
+        "General",
+        &[keys_when(
+            When::WithImage,
+            &[(ctrl(Char('x')), C::DropImage)],
+        )],
+        "Drop the last pasted image",
+    ),
+    binding(
+        "Input",
+        &[
+            keys(&[(key(Left), C::Left), (key(Right), C::Right)]),
+            keys(&[(key(Home), C::Home), (key(End), C::End)]),
+        ],
+        "Move the cursor",
+    ),
+    binding(
+        "Input",
+        &[keys(&[
+            (ctrl(Char('b')), C::Left),
+            (ctrl(Char('f')), C::Right),
+        ])],
+        "Same as ← →",
+    ),
+    binding(
+        "Input",
+        &[keys(&[
+            (ctrl(Char('a')), C::Home),
+            (ctrl(Char('e')), C::End),
+        ])],
+        "Same as Home End",
+    ),
+    binding(
+        "Input",
+        &[keys(&[(key(Up), C::Up), (key(Down), C::Down)])],
+        "Move between lines",
+    ),
+    binding(
+        "Input",
+        &[keys(&[
+            (key(PageUp), C::PageUp),
+            (key(PageDown), C::PageDown),
+        ])],
+        "Move a page up or down",
+    ),
+    binding(
+        "Input",
+        &[keys(&[
+            (ctrl(Up), C::ScrollUp),
+            (ctrl(Down), C::ScrollDown),
+        ])],
+        "Scroll without moving the cursor",
+    ),
+    binding(
+        "Input",
+        &[keys(&[(alt(Up), C::First), (alt(Down), C::Last)])],
+        "Jump to the first or last line",
+    ),
+    binding(
+        "Input",
+        &[
+            keys(&[(key(Backspace), C::Backspace)]),
+            keys(&[(key(Delete), C::Delete), (ctrl(Char('d')), C::Delete)]),
+        ],
+        "Delete a character",
+    ),
+    binding(
+        "Input",
+        &[keys(&[(alt(Char('d')), C::DeleteWord)])],
+        "Delete the next word",
+    ),
+    binding(
+        "Input",
+        &[keys(&[(ctrl(Char('t')), C::Transpose)])],
+        "Swap the characters around the cursor",
+    ),
+    binding(
+        "Input",
+        &[keys(&[
+            (alt(Char('u')), C::Upcase),
+            (alt(Char('l')), C::Downcase),
+            (alt(Char('c')), C::Capitalize),
+        ])],
+        "Upper/lower/capitalize the next word",
+    ),
+    binding(
+        "Input",
+        &[
+            keys_when(When::Suggestion, &[(key(Right), C::AcceptSuggestion)]),
+            keys_when(When::Suggestion, &[(key(End), C::AcceptSuggestion)]),
+        ],
+        "Accept the suggested last prompt",
+    ),
+    // Typing, of which `@` opens the popup
+    binding(
+        "Autocomplete",
+        &[keys(&[(Key::Text("@"), C::Insert)])],
+        "Complete a placeholder",
+    ),
+    binding(
+        "Autocomplete",
+        &[
+            keys_when(
+                When::Completing,
+                &[(key(Up), C::CompletionUp), (key(Down), C::CompletionDown)],
+            ),
+            keys_when(
+                When::Completing,
+                &[
+                    (ctrl(Char('n')), C::CompletionDown),
+                    (ctrl(Char('p')), C::CompletionUp),
+                ],
+            ),
+        ],
+        "Choose a completion",
+    ),
+    binding(
+        "Autocomplete",
+        &[
+            keys_when(When::Completing, &[(key(Tab), C::Complete)]),
+            keys_when(When::Completing, &[(key(Enter), C::Complete)]),
+        ],
+        "Accept the completion",
+    ),
+    binding(
+        "Autocomplete",
+        &[keys_when(
+            When::Completing,
+            &[(key(Esc), C::CloseCompletion)],
+        )],
+        "Close the popup",
+    ),
+    binding(
+        "Placeholders",
+        &[
+            keys_when(
+                When::OnPlaceholders,
+                &[(key(Up), C::PlaceholderUp), (key(Down), C::PlaceholderDown)],
+            ),
+            keys_when(
+                When::OnPlaceholders,
+                &[
+                    (key(Char('j')), C::PlaceholderDown),
+                    (key(Char('k')), C::PlaceholderUp),
+                ],
+            ),
+        ],
+        "Choose a placeholder",
+    ),
+    binding(
+        "Placeholders",
+        &[keys_when(
+            When::OnPlaceholders,
+            &[(key(Enter), C::InsertPlaceholder)],
+        )],
+        "Insert it at the cursor",
+    ),
+    binding(
+        "Placeholders",
+        &[keys_when(
+            When::OnPlaceholders,
+            &[(key(Char('v')), C::ViewPlaceholder)],
+        )],
+        "View its full expansion",
+    ),
+    binding(
+        "Expansion view",
+        &[
+            keys_when(
+                When::InView,
+                &[(key(Up), C::ViewUp), (key(Down), C::ViewDown)],
+            ),
+            keys_when(
+                When::InView,
+                &[(key(Char('j')), C::ViewDown), (key(Char('k')), C::ViewUp)],
+            ),
+            keys_when(
+                When::InView,
+                &[
+                    (key(PageUp), C::ViewPageUp),
+                    (key(PageDown), C::ViewPageDown),
+                ],
+            ),
+        ],
+        "Scroll",
+    ),
+    binding(
+        "Expansion view",
+        &[
+            keys_when(When::InView, &[(key(Esc), C::CloseView)]),
+            keys_when(When::InView, &[(key(Char('q')), C::CloseView)]),
+            keys_when(When::InView, &[(key(Char('v')), C::CloseView)]),
+        ],
+        "Close",
+    ),
+];
+
+/// Keymap of the select dialog
+pub const SELECT_BINDINGS: &[Binding] = &[
+    binding(
+        "General",
+        &[keys(&[(key(Enter), C::Send)])],
+        "Execute the selected item",
+    ),
+    binding(
+        "General",
+        &[
+            keys(&[(key(Esc), C::Cancel)]),
+            keys(&[(ctrl(Char('c')), C::Cancel)]),
+            keys(&[(ctrl(Char('d')), C::Cancel)]),
+        ],
+        "Cancel",
+    ),
+    binding(
+        "General",
+        &[
+            keys(&[(key(Tab), C::Edit)]),
+            keys_when(When::EmptyFilter, &[(key(Char('e')), C::Edit)]),
+        ],
+        "Edit the prompt before sending",
+    ),
+    binding(
+        "General",
+        &[keys(&[(ctrl(Char('y')), C::Copy)])],
+        "Copy the selected prompt, expanded",
+    ),
+    binding(
+        "General",
+        &[
+            keys_when(When::EmptyFilter, &[(key(Char('?')), C::Help)]),
+            keys(HELP),
+        ],
+        "This help",
+    ),
+    binding(
+        "Navigation",
+        &[
+            keys(&[(key(Up), C::Up), (key(Down), C::Down)]),
+            keys(&[(key(Char('j')), C::Down), (key(Char('k')), C::Up)]),
+        ],
+        "Move the selection",
+    ),
+    binding(
+        "Navigation",
+        &[
+            keys(&[(ctrl(Char('n')), C::Down)]),
+            keys(&[(ctrl(Char('p')), C::Up)]),
+        ],
+        "Move the selection",
+    ),
+    binding(
+        "Navigation",
+        &[keys(&[
+            (key(PageUp), C::PageUp),
+            (key(PageDown), C::PageDown),
+        ])],
+        "Move a page up or down",
+    ),
+    binding(
+        "Navigation",
+        &[
+            keys(&[(key(Left), C::Collapse), (key(Right), C::Expand)]),
+            keys_when(
+                When::EmptyFilter,
+                &[(key(Char('h')), C::Collapse), (key(Char('l')), C::Expand)],
+            ),
+        ],
+        "Collapse or expand a category",
+    ),
+    binding(
+        "Navigation",
+        &[keys_when(When::OnHeader, &[(key(Enter), C::Toggle)])],
+        "Collapse or expand it",
+    ),
+    binding(
+        "Filter",
+        &[keys(&[(Key::Text("Typing"), C::Insert)])],
+        "Filter by name, description or category",
+    ),
+    binding(
+        "Filter",
+        &[keys(&[(Key::Text(">category"), C::Insert)])],
+        "Only list matching categories (>ag)",
+    ),
+    binding(
+        "Filter",
+        &[keys(&[(key(Backspace), C::Backspace)])],
+        "Delete a filter character",
+    ),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+        KeyEvent::new(code, modifiers)
+    }
+
+    #[test]
+    fn test_labels() {
+        let labels: Vec<String> = SELECT_BINDINGS.iter().map(Binding::label).collect();
+        assert_eq!(labels[1], "Esc / Ctrl+C / Ctrl+D");
+        assert_eq!(labels[2], "Tab / e (empty filter)");
+        assert_eq!(labels[4], "? (empty filter) / Alt+H");
+        assert_eq!(labels[8], "← → / h l (empty filter)");
+        assert_eq!(labels[9], "Enter (on a header)");
+        let labels: Vec<String> = ASK_BINDINGS.iter().map(Binding::label).collect();
+        assert!(labels.contains(&"Tab / Shift+Tab".to_string()));
+        assert!(labels.contains(&"F1-F12 / Alt+0-9".to_string()));
+        assert!(labels.contains(&"Ctrl+↑ Ctrl+↓".to_string()));
+        assert!(labels.contains(&"→ / End (empty input)".to_string()));
+    }
+
+    #[test]
+    fn test_lookup() {
+        let none = KeyModifiers::NONE;
+        let ctrl_d = event(Char('d'), KeyModifiers::CONTROL);
+        // A condition that holds wins; otherwise the unconditional binding
+        let ask = |key: &KeyEvent, when: When| {
+            lookup(ASK_BINDINGS, key, |w| w == When::Always || w == when)
+        };
+        assert_eq!(ask(&ctrl_d, When::EmptyText), Some(C::Cancel));
+        assert_eq!(ask(&ctrl_d, When::Always), Some(C::Delete));
+        assert_eq!(
+            ask(&event(Down, none), When::Completing),
+            Some(C::CompletionDown)
+        );
+        assert_eq!(ask(&event(Down, none), When::Always), Some(C::Down));
+        assert_eq!(ask(&event(Char('?'), none), When::EmptyText), Some(C::Help));
+        assert_eq!(ask(&event(Char('?'), none), When::Always), Some(C::Insert));
+        // Shift aside, modifiers must match exactly
+        let back_tab = event(BackTab, KeyModifiers::SHIFT);
+        assert_eq!(ask(&back_tab, When::Always), Some(C::FocusPrev));
+        assert_eq!(
+            ask(&event(Char('X'), KeyModifiers::SHIFT), When::Always),
+            Some(C::Insert)
+        );
+        let ctrl_alt_a = event(Char('a'), KeyModifiers::CONTROL | KeyModifiers::ALT);
+        assert_eq!(ask(&ctrl_alt_a, When::Always), None);
+        assert_eq!(
+            ask(&event(KeyCode::F(5), none), When::Always),
+            Some(C::Quick)
+        );
+        // Readline movement, with Ctrl+E the preview toggle while there is one
+        let ctrl = |c| event(Char(c), KeyModifiers::CONTROL);
+        assert_eq!(ask(&ctrl('a'), When::Always), Some(C::Home));
+        assert_eq!(ask(&ctrl('f'), When::Always), Some(C::Right));
+        assert_eq!(ask(&ctrl('e'), When::Always), Some(C::End));
+        assert_eq!(ask(&ctrl('e'), When::WithSelection), Some(C::TogglePreview));
+
+        let select = |key: &KeyEvent, when: When| {
+            lookup(SELECT_BINDINGS, key, |w| w == When::Always || w == when)
+        };
+        assert_eq!(
+            select(&event(Char('e'), none), When::EmptyFilter),
+            Some(C::Edit)
+        );
+        assert_eq!(
+            select(&event(Char('e'), none), When::Always),
+            Some(C::Insert)
+        );
+        assert_eq!(select(&event(Char('j'), none), When::Always), Some(C::Down));
+        assert_eq!(select(&event(Enter, none), When::OnHeader), Some(C::Toggle));
+        assert_eq!(select(&event(Enter, none), When::Always), Some(C::Send));
+        assert_eq!(
+            select(&event(Tab, KeyModifiers::CONTROL), When::Always),
+            None
+        );
+    }
+
+    #[test]
+    fn test_help_is_not_backspace() {
+        // 0x08 arrives as Backspace, so help can't be Ctrl+H
+        let backspace = event(Backspace, KeyModifiers::NONE);
+        for bindings in [ASK_BINDINGS, SELECT_BINDINGS] {
+            assert_eq!(lookup(bindings, &backspace, |_| true), Some(C::Backspace));
+            let alt_h = event(Char('h'), KeyModifiers::ALT);
+            assert_eq!(lookup(bindings, &alt_h, |_| true), Some(C::Help));
+        }
+    }
+}
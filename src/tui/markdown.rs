@@ -0,0 +1,335 @@
+//! Inline markdown in one-line texts, such as the select menu's descriptions
+//!
+//! Server command descriptions are written for a markdown renderer, so they
+//! carry `code spans`, `*emphasis*` and `**strong**` text. Only those inline
+//! forms are rendered: code spans on the theme's accent, emphasis italic and
+//! strong text bold. There are no block elements, and anything that doesn't
+//! parse as markdown (an unclosed marker, `snake_case_names`) stays as it was
+//! written.
+
+use ratatui::prelude::*;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use super::theme::Theme;
+
+/// `text` as styled spans; styles only add to what the line is drawn with
+pub fn render_inline_md(text: &str, theme: &Theme) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+    inline(text, Style::default(), theme, &mut spans);
+    spans
+}
+
+/// Cut `spans` to at most `max_cols` terminal columns, ending with "…" when
+/// anything was dropped
+///
+/// Cuts between grapheme clusters and keeps each span's style, the ellipsis
+/// taking the style of the span it cut.
+pub fn truncate_spans(spans: &[Span<'static>], max_cols: usize) -> Vec<Span<'static>> {
+    let total: usize = spans.iter().map(|span| span.content.width()).sum();
+    if total <= max_cols {
+        return spans.to_vec();
+    }
+
+    // Leave a column for the ellipsis
+    let mut room = max_cols.saturating_sub(1);
+    let mut out = Vec::new();
+    for span in spans {
+        let mut cut = String::new();
+        for grapheme in span.content.graphemes(true) {
+            let width = grapheme.width();
+            if width > room {
+                room = 0;
+                break;
+            }
+            cut.push_str(grapheme);
+            room -= width;
+        }
+        let full = cut.len() == span.content.len();
+        if !cut.is_empty() {
+            out.push(Span::styled(cut, span.style));
+        }
+        if !full {
+            if max_cols > 0 {
+                out.push(Span::styled("…", span.style));
+            }
+            break;
+        }
+    }
+    out
+}
+
+/// Render `text` in `style` into `out`, merging runs of plain text
+fn inline(text: &str, style: Style, theme: &Theme, out: &mut Vec<Span<'static>>) {
+    let mut plain = String::new();
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+        match c {
+            '\\' if text[i + 1..].starts_with(|c: char| c.is_ascii_punctuation()) => {
+                plain.push(text.as_bytes()[i + 1] as char);
+                i += 2;
+            }
+            '`' => {
+                let run = run_len(text, i);
+                match code_end(text, i + run, run) {
+                    Some(end) => {
+                        flush(&mut plain, style, out);
+                        out.push(Span::styled(
+                            text[i + run..end].to_string(),
+                            style.fg(Color::Black).bg(theme.accent),
+                        ));
+                        i = end + run;
+                    }
+                    None => {
+                        plain.push_str(&text[i..i + run]);
+                        i += run;
+                    }
+                }
+            }
+            '*' | '_' => {
+                let run = run_len(text, i);
+                let delim = run.min(3);
+                match emphasis_end(text, i, delim) {
+                    Some(end) => {
+                        flush(&mut plain, style, out);
+                        // Extra markers before the delimiter stay literal
+                        plain.push_str(&text[i..i + run - delim]);
+                        flush(&mut plain, style, out);
+                        let modifier = match delim {
+                            1 => Modifier::ITALIC,
+                            2 => Modifier::BOLD,
+                            _ => Modifier::BOLD | Modifier::ITALIC,
+                        };
+                        inline(
+                            &text[i + run..end],
+                            style.add_modifier(modifier),
+                            theme,
+                            out,
+                        );
+                        i = end + delim;
+                    }
+                    None => {
+                        plain.push_str(&text[i..i + run]);
+                        i += run;
+                    }
+                }
+            }
+            _ => {
+                plain.push(c);
+                i += c.len_utf8();
+            }
+        }
+    }
+    flush(&mut plain, style, out);
+}
+
+/// Push `plain` as a span in `style`, if there is any
+fn flush(plain: &mut String, style: Style, out: &mut Vec<Span<'static>>) {
+    if !plain.is_empty() {
+        out.push(Span::styled(std::mem::take(plain), style));
+    }
+}
+
+/// Length of the run of the ASCII marker at `text[i]`
+fn run_len(text: &str, i: usize) -> usize {
+    let marker = text.as_bytes()[i];
+    text.as_bytes()[i..]
+        .iter()
+        .take_while(|&&b| b == marker)
+        .count()
+}
+
+/// Start of the backtick run of exactly `run` closing a code span opened
+/// just before `from`
+fn code_end(text: &str, from: usize, run: usize) -> Option<usize> {
+    let mut i = from;
+    while let Some(offset) = text[i..].find('`') {
+        let start = i + offset;
+        let len = run_len(text, start);
+        if len == run {
+            return Some(start);
+        }
+        i = start + len;
+    }
+    None
+}
+
+/// Where the `delim` markers closing the emphasis opened at `open` start
+///
+/// An opener is followed by a non-space, a closer follows one, and an
+/// underscore only counts outside a word. The closer is the last `delim`
+/// markers of its run, so `**bold *and italic***` nests. Code spans and
+/// escapes in between are skipped.
+fn emphasis_end(text: &str, open: usize, delim: usize) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let marker = bytes[open];
+    let run = run_len(text, open);
+    let after = text[open + run..].chars().next()?;
+    let before = text[..open].chars().next_back();
+    if after.is_whitespace() || (marker == b'_' && before.is_some_and(char::is_alphanumeric)) {
+        return None;
+    }
+
+    let mut i = open + run;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'`' => {
+                let len = run_len(text, i);
+                i = code_end(text, i + len, len).map_or(i + len, |end| end + len);
+            }
+            b if b == marker => {
+                let len = run_len(text, i);
+                let prev = text[..i].chars().next_back();
+                let next = text[i + len..].chars().next();
+                let closes = len >= delim
+                    && prev.is_some_and(|c| !c.is_whitespace())
+                    && !(marker == b'_' && next.is_some_and(char::is_alphanumeric));
+                if closes {
+                    return Some(i + len - delim);
+                }
+                i += len;
+            }
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Spans of `text` as (content, modifier, whether it is code)
+    fn render(text: &str) -> Vec<(String, Modifier, bool)> {
+        let theme = Theme::default();
+        render_inline_md(text, &theme)
+            .into_iter()
+            .map(|span| {
+                (
+                    span.content.to_string(),
+                    span.style.add_modifier,
+                    span.style.bg == Some(theme.accent),
+                )
+            })
+            .collect()
+    }
+
+    fn plain(text: &str) -> (String, Modifier, bool) {
+        (text.to_string(), Modifier::empty(), false)
+    }
+
+    #[test]
+    fn test_inline_forms() {
+        assert_eq!(
+            render("Run `cargo test` *now*"),
+            [
+                plain("Run "),
+                ("cargo test".to_string(), Modifier::empty(), true),
+                plain(" "),
+                ("now".to_string(), Modifier::ITALIC, false),
+            ]
+        );
+        assert_eq!(
+            render("__strong__ and ***both***"),
+            [
+                ("strong".to_string(), Modifier::BOLD, false),
+                plain(" and "),
+                ("both".to_string(), Modifier::BOLD | Modifier::ITALIC, false),
+            ]
+        );
+        // A longer fence can hold a backtick
+        assert_eq!(
+            render("``a ` b``"),
+            [("a ` b".to_string(), Modifier::empty(), true)]
+        );
+    }
+
+    #[test]
+    fn test_nesting() {
+        assert_eq!(
+            render("**bold *and italic***"),
+            [
+                ("bold ".to_string(), Modifier::BOLD, false),
+                (
+                    "and italic".to_string(),
+                    Modifier::BOLD | Modifier::ITALIC,
+                    false
+                ),
+            ]
+        );
+        assert_eq!(
+            render("*see `a*b`*"),
+            [
+                ("see ".to_string(), Modifier::ITALIC, false),
+                ("a*b".to_string(), Modifier::ITALIC, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_malformed_stays_plain() {
+        for text in [
+            "unclosed *emphasis",
+            "unclosed `code",
+            "snake_case_name",
+            "2 * 3 * 4",
+            "**",
+            "`",
+            "a\\",
+            "* list item",
+            "``mismatched`",
+        ] {
+            let joined: String = render(text).into_iter().map(|(s, ..)| s).collect();
+            assert_eq!(joined, text);
+            assert!(render(text)
+                .iter()
+                .all(|(_, m, code)| m.is_empty() && !code));
+        }
+        assert_eq!(render(r"\*not emphasis\*"), [plain("*not emphasis*")]);
+        assert!(render("").is_empty());
+
+        // Never a panic, whatever the markers
+        let alphabet = ['*', '_', '`', '\\', 'a', ' ', 'é'];
+        for n in 0..alphabet.len().pow(4) {
+            let text: String = (0..4)
+                .map(|k| alphabet[n / alphabet.len().pow(k) % alphabet.len()])
+                .collect();
+            render(&text);
+        }
+    }
+
+    #[test]
+    fn test_multibyte() {
+        assert_eq!(
+            render("Ünïcode *日本語* `é`"),
+            [
+                plain("Ünïcode "),
+                ("日本語".to_string(), Modifier::ITALIC, false),
+                plain(" "),
+                ("é".to_string(), Modifier::empty(), true),
+            ]
+        );
+        assert_eq!(render("_é_"), [("é".to_string(), Modifier::ITALIC, false)]);
+    }
+
+    #[test]
+    fn test_truncate_spans() {
+        let theme = Theme::default();
+        let spans = render_inline_md("Fix `日本語` now", &theme);
+        assert_eq!(truncate_spans(&spans, 20), spans);
+
+        // "Fix " and two wide chars, then the ellipsis in the code style
+        let cut = truncate_spans(&spans, 9);
+        let text: Vec<&str> = cut.iter().map(|span| span.content.as_ref()).collect();
+        assert_eq!(text, ["Fix ", "日本", "…"]);
+        assert_eq!(cut[2].style, spans[1].style);
+
+        // A wide char that doesn't fit isn't split
+        let cut = truncate_spans(&spans, 8);
+        let text: Vec<&str> = cut.iter().map(|span| span.content.as_ref()).collect();
+        assert_eq!(text, ["Fix ", "日", "…"]);
+
+        assert!(truncate_spans(&spans, 0).is_empty());
+    }
+}
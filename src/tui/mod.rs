@@ -2,6 +2,24 @@
 
 pub mod app;
 pub mod ask;
+pub mod backend;
+pub mod clipboard;
+pub mod counter;
+pub mod diff;
+pub mod editor;
 pub mod effects;
+pub mod help;
+pub mod highlight;
+pub mod input;
+pub mod keymap;
+pub mod markdown;
+pub mod previews;
+pub mod readline;
+pub mod redraw;
 pub mod select;
+pub mod strings;
+pub mod target;
+pub mod term;
 pub mod theme;
+pub mod typos;
+pub mod wrap;
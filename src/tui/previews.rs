@@ -0,0 +1,200 @@
+//! The placeholders panel's previews, computed behind the ask dialog
+//!
+//! Some values take a while: `@diff` runs git over the whole repository,
+//! `@clipboard` waits on whoever owns the clipboard. So the dialog opens
+//! with every name listed and "…" for the values, while a blocking tokio
+//! task per placeholder computes its preview. The results come back over a
+//! channel the draw loop drains ([`Previews::poll`]), each into its own row,
+//! so the rows never move.
+//!
+//! The values are kept ([`Previews::known`]): expanding the prompt in the
+//! dialog (the counter, submitting, Ctrl+Y) doesn't compute them again.
+//! Closing the dialog drops the [`Previews`], aborting the tasks that
+//! haven't started; one already running finishes, unheard.
+//!
+//! Without a runtime to spawn on (the dialog tests), the previews are
+//! computed up front instead.
+
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+
+use tokio::task::JoinHandle;
+
+use crate::context::{Context, Known, PlaceholderKind};
+
+/// A placeholder's preview and value, as [`PlaceholderKind::panel_entry`] finds them
+pub type Compute = fn(PlaceholderKind, &Context) -> (String, Option<String>);
+
+/// A row of the placeholders panel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    /// Token as typed, e.g. `@diff`
+    pub name: String,
+    /// Summary of the value, None until it is computed
+    pub preview: Option<String>,
+    /// Whether the placeholder has a value (false until computed)
+    pub available: bool,
+}
+
+/// What a task found for the placeholder in row `index`
+type Computed = (usize, PlaceholderKind, String, Option<String>);
+
+/// The panel's rows, filled in as their previews arrive
+pub struct Previews {
+    rows: Vec<Row>,
+    known: Known,
+    results: Receiver<Computed>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Previews {
+    /// List `ctx`'s placeholders and start computing their previews with `compute`
+    pub fn start(ctx: &Context, compute: Compute) -> Self {
+        let kinds = ctx.panel_kinds();
+        let mut rows: Vec<Row> = kinds
+            .iter()
+            .map(|kind| Row {
+                name: kind.name().to_string(),
+                preview: None,
+                available: false,
+            })
+            .collect();
+        // Registers are given on the command line, so they are ready at once
+        rows.extend(
+            ctx.register_previews()
+                .into_iter()
+                .map(|(name, preview)| Row {
+                    name,
+                    preview: Some(preview),
+                    available: true,
+                }),
+        );
+
+        let (send, results) = mpsc::channel();
+        let mut tasks = Vec::new();
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                let shared = Arc::new(ctx.clone());
+                for (index, kind) in kinds.into_iter().enumerate() {
+                    let (ctx, send) = (Arc::clone(&shared), send.clone());
+                    tasks.push(runtime.spawn_blocking(move || {
+                        let (preview, value) = compute(kind, &ctx);
+                        // The dialog may have closed
+                        let _ = send.send((index, kind, preview, value));
+                    }));
+                }
+            }
+            Err(_) => {
+                for (index, kind) in kinds.into_iter().enumerate() {
+                    let (preview, value) = compute(kind, ctx);
+                    let _ = send.send((index, kind, preview, value));
+                }
+            }
+        }
+
+        let mut previews = Self {
+            rows,
+            known: Known::new(),
+            results,
+            tasks,
+        };
+        previews.poll();
+        previews
+    }
+
+    /// Take in the previews computed since the last call; returns whether
+    /// any arrived
+    pub fn poll(&mut self) -> bool {
+        let mut arrived = false;
+        while let Ok((index, kind, preview, value)) = self.results.try_recv() {
+            let row = &mut self.rows[index];
+            row.preview = Some(preview);
+            row.available = value.is_some();
+            self.known.insert(kind, value);
+            arrived = true;
+        }
+        arrived
+    }
+
+    /// Rows of the panel, in a fixed order
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+
+    /// Values computed so far
+    pub fn known(&self) -> &Known {
+        &self.known
+    }
+
+    /// Whether some previews are still being computed
+    pub fn pending(&self) -> bool {
+        self.rows.iter().any(|row| row.preview.is_none())
+    }
+}
+
+impl Drop for Previews {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    /// `@diff` takes a while; every other placeholder is "fast"
+    fn slow_diff(kind: PlaceholderKind, _: &Context) -> (String, Option<String>) {
+        if kind == PlaceholderKind::Diff {
+            std::thread::sleep(Duration::from_millis(300));
+        }
+        (format!("{} preview", kind.name()), Some(kind.name().into()))
+    }
+
+    fn context() -> Context {
+        let mut ctx = Context::default();
+        ctx.file = Some("src/main.rs".to_string());
+        ctx.registers.insert("a", "copied text".to_string());
+        ctx
+    }
+
+    #[test]
+    fn test_without_runtime() {
+        let previews = Previews::start(&context(), slow_diff);
+        assert!(!previews.pending());
+        let names: Vec<&str> = previews.rows().iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names.first(), Some(&"@this"));
+        assert_eq!(names.last(), Some(&"@reg:a"));
+        assert_eq!(
+            previews.known().get(&PlaceholderKind::Diff),
+            Some(&Some("@diff".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rows_fill_in_place() {
+        let ctx = context();
+        let started = Instant::now();
+        let mut previews = Previews::start(&ctx, slow_diff);
+        assert!(started.elapsed() < Duration::from_millis(200));
+        let names: Vec<String> = previews.rows().iter().map(|r| r.name.clone()).collect();
+        // The registers don't wait
+        assert_eq!(
+            previews.rows().last().unwrap().preview.as_deref(),
+            Some("1 lines: copied text")
+        );
+
+        while previews.pending() {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            previews.poll();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let after: Vec<String> = previews.rows().iter().map(|r| r.name.clone()).collect();
+        assert_eq!(after, names);
+        let diff = previews.rows().iter().find(|r| r.name == "@diff").unwrap();
+        assert_eq!(diff.preview.as_deref(), Some("@diff preview"));
+        assert!(diff.available);
+    }
+}
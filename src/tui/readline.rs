@@ -0,0 +1,276 @@
+//! Readline-style edits of the ask input
+//!
+//! Each takes the input and the cursor (a byte offset) and changes both in
+//! place: Backspace [`backspace`], Delete and Ctrl+D [`delete`], Ctrl+T
+//! [`transpose`], Alt+U/L/C [`change_case`] of the next word, and Alt+D
+//! [`delete_word`]. They work on grapheme clusters, so an accented letter or
+//! an emoji sequence moves, goes and changes case as one.
+//!
+//! A word is a run of clusters starting with a letter or digit, as in
+//! readline; the next word is found after any other characters, newlines
+//! included. Transposing stays within the cursor's line.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// How [`change_case`] changes a word
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Case {
+    Upper,
+    Lower,
+    /// First letter uppercase, the rest lowercase
+    Capitalize,
+}
+
+/// `cursor` moved back to a character boundary
+fn floor_boundary(text: &str, cursor: usize) -> usize {
+    let mut cursor = cursor.min(text.len());
+    while !text.is_char_boundary(cursor) {
+        cursor -= 1;
+    }
+    cursor
+}
+
+/// Start of the cluster before `cursor` (0 at the start)
+pub fn prev_boundary(text: &str, cursor: usize) -> usize {
+    let at = floor_boundary(text, cursor);
+    text[..at]
+        .grapheme_indices(true)
+        .next_back()
+        .map_or(0, |(i, _)| i)
+}
+
+/// End of the cluster at `cursor` (the text's length at the end)
+pub fn next_boundary(text: &str, cursor: usize) -> usize {
+    let at = floor_boundary(text, cursor);
+    text[at..]
+        .graphemes(true)
+        .next()
+        .map_or(text.len(), |g| at + g.len())
+}
+
+/// Delete the cluster before the cursor, moving the cursor back over it
+///
+/// Returns whether anything changed.
+pub fn backspace(text: &mut String, cursor: &mut usize) -> bool {
+    let end = floor_boundary(text, *cursor);
+    let start = prev_boundary(text, end);
+    text.replace_range(start..end, "");
+    *cursor = start;
+    start < end
+}
+
+/// Delete the cluster under the cursor, leaving the cursor
+///
+/// Returns whether anything changed.
+pub fn delete(text: &mut String, cursor: &mut usize) -> bool {
+    let start = floor_boundary(text, *cursor);
+    let end = next_boundary(text, start);
+    text.replace_range(start..end, "");
+    *cursor = start;
+    start < end
+}
+
+/// Whether a cluster is part of a word
+fn is_word(grapheme: &str) -> bool {
+    grapheme.chars().next().is_some_and(char::is_alphanumeric)
+}
+
+/// Swap the cluster before the cursor with the one under it, moving the
+/// cursor past both; at the end of a line, swap the two before the cursor
+///
+/// Nothing changes at the start of a line or with fewer than two clusters
+/// on it. Returns whether anything changed.
+pub fn transpose(text: &mut String, cursor: &mut usize) -> bool {
+    let at = floor_boundary(text, *cursor);
+    let line_start = text[..at].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = text[at..].find('\n').map_or(text.len(), |i| at + i);
+    let line = &text[line_start..line_end];
+
+    // Cluster boundaries of the line, as offsets into `text`
+    let bounds: Vec<usize> = line
+        .grapheme_indices(true)
+        .map(|(i, _)| line_start + i)
+        .chain([line_end])
+        .collect();
+    // The cluster the cursor is in or before
+    let Some(index) = bounds.iter().rposition(|&bound| bound <= at) else {
+        return false;
+    };
+    // At the end of the line, the two before it; otherwise around the cursor
+    let index = if index + 1 == bounds.len() {
+        index.saturating_sub(1)
+    } else {
+        index
+    };
+    if index == 0 || index + 1 >= bounds.len() {
+        return false;
+    }
+    let (start, middle, end) = (bounds[index - 1], bounds[index], bounds[index + 1]);
+    let swapped = format!("{}{}", &text[middle..end], &text[start..middle]);
+    text.replace_range(start..end, &swapped);
+    *cursor = end;
+    true
+}
+
+/// Where the next word after `cursor` starts and ends, if there is one
+fn next_word(text: &str, cursor: usize) -> Option<(usize, usize)> {
+    let at = floor_boundary(text, cursor);
+    let mut clusters = text[at..]
+        .grapheme_indices(true)
+        .map(|(i, g)| (at + i, g))
+        .skip_while(|(_, g)| !is_word(g))
+        .peekable();
+    let start = clusters.peek()?.0;
+    let end = clusters
+        .find(|(_, g)| !is_word(g))
+        .map_or(text.len(), |(i, _)| i);
+    Some((start, end))
+}
+
+/// Change the case of the next word and move the cursor past it
+///
+/// Returns whether there was a word.
+pub fn change_case(text: &mut String, cursor: &mut usize, case: Case) -> bool {
+    let Some((start, end)) = next_word(text, *cursor) else {
+        return false;
+    };
+    let word = &text[start..end];
+    let changed = match case {
+        Case::Upper => word.to_uppercase(),
+        Case::Lower => word.to_lowercase(),
+        Case::Capitalize => {
+            let mut graphemes = word.graphemes(true);
+            let first = graphemes.next().unwrap_or_default().to_uppercase();
+            first + &graphemes.as_str().to_lowercase()
+        }
+    };
+    // Case changes can change the length (ß is SS uppercase)
+    *cursor = start + changed.len();
+    text.replace_range(start..end, &changed);
+    true
+}
+
+/// Delete from the cursor to the end of the next word, leaving the cursor
+///
+/// Returns whether there was a word.
+pub fn delete_word(text: &mut String, cursor: &mut usize) -> bool {
+    let at = floor_boundary(text, *cursor);
+    let Some((_, end)) = next_word(text, at) else {
+        return false;
+    };
+    text.replace_range(at..end, "");
+    *cursor = at;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Apply `edit` to `text` with the cursor at `|`, giving the result the same way
+    fn edited(text: &str, edit: impl Fn(&mut String, &mut usize) -> bool) -> String {
+        let mut cursor = text.find('|').unwrap();
+        let mut text = text.replacen('|', "", 1);
+        edit(&mut text, &mut cursor);
+        text.insert(cursor, '|');
+        text
+    }
+
+    #[test]
+    fn test_backspace() {
+        let b = |text| edited(text, backspace);
+        assert_eq!(b("ab|c"), "a|c");
+        assert_eq!(b("|abc"), "|abc");
+        assert_eq!(b("|"), "|");
+        // An emoji ZWJ sequence (family) and a flag go whole
+        assert_eq!(b("hi 👨\u{200d}👩\u{200d}👧|!"), "hi |!");
+        assert_eq!(b("🇯🇵|"), "|");
+        // e + combining acute, and a precomposed é
+        assert_eq!(b("cafe\u{301}|"), "caf|");
+        assert_eq!(b("café|"), "caf|");
+        // A CJK character at the start of the buffer
+        assert_eq!(b("日|本"), "|本");
+        // A line break is a cluster of its own, \r\n included
+        assert_eq!(b("a\r\n|b"), "a|b");
+    }
+
+    #[test]
+    fn test_delete() {
+        let d = |text| edited(text, delete);
+        assert_eq!(d("a|bc"), "a|c");
+        assert_eq!(d("abc|"), "abc|");
+        assert_eq!(d("|👨\u{200d}👩\u{200d}👧 hi"), "| hi");
+        assert_eq!(d("caf|e\u{301}!"), "caf|!");
+        assert_eq!(d("|日本"), "|本");
+    }
+
+    #[test]
+    fn test_boundaries() {
+        let text = "ae\u{301}日";
+        assert_eq!(next_boundary(text, 0), 1);
+        assert_eq!(next_boundary(text, 1), 4);
+        assert_eq!(next_boundary(text, 4), 7);
+        assert_eq!(next_boundary(text, 7), 7);
+        assert_eq!(prev_boundary(text, 7), 4);
+        assert_eq!(prev_boundary(text, 4), 1);
+        assert_eq!(prev_boundary(text, 0), 0);
+        // From inside a character: its own start
+        assert_eq!(prev_boundary(text, 5), 1);
+    }
+
+    #[test]
+    fn test_transpose() {
+        let t = |text| edited(text, transpose);
+        assert_eq!(t("ab|cd"), "acb|d");
+        // At the end of the line: the two before the cursor
+        assert_eq!(t("abcd|"), "abdc|");
+        assert_eq!(t("ab|\ncd"), "ba|\ncd");
+        // At the start of a line, or with one character: nothing
+        assert_eq!(t("|abc"), "|abc");
+        assert_eq!(t("ab\n|cd"), "ab\n|cd");
+        assert_eq!(t("a|"), "a|");
+        assert_eq!(t("ab\nc|"), "ab\nc|");
+        assert_eq!(t("|"), "|");
+
+        // Clusters, not bytes: é as e + combining acute, and a flag
+        assert_eq!(t("xe\u{301}|"), "e\u{301}x|");
+        assert_eq!(t("日本|語"), "日語本|");
+        assert_eq!(t("🇯🇵|a"), "a🇯🇵|");
+    }
+
+    #[test]
+    fn test_change_case() {
+        let upper = |text| edited(text, |text, cursor| change_case(text, cursor, Case::Upper));
+        let lower = |text| edited(text, |text, cursor| change_case(text, cursor, Case::Lower));
+        let capitalize = |text| {
+            edited(text, |text, cursor| {
+                change_case(text, cursor, Case::Capitalize)
+            })
+        };
+
+        assert_eq!(upper("fix |this bug"), "fix THIS| bug");
+        assert_eq!(lower("|FIX this"), "fix| this");
+        assert_eq!(capitalize("fix |tHIS bug"), "fix This| bug");
+        // From inside a word, its rest; past the punctuation and newlines before one
+        assert_eq!(upper("th|is"), "thIS|");
+        assert_eq!(capitalize("a|, \n\nb2c"), "a, \n\nB2c|");
+        assert_eq!(upper("done.|  "), "done.|  ");
+
+        // Multi-byte letters, and a case change that changes the length
+        assert_eq!(capitalize("|élan vital"), "Élan| vital");
+        assert_eq!(upper("|straße"), "STRASSE|");
+        assert_eq!(lower("|ÉTÉ"), "été|");
+        assert_eq!(capitalize("|e\u{301}t\u{301}"), "E\u{301}t\u{301}|");
+    }
+
+    #[test]
+    fn test_delete_word() {
+        let d = |text| edited(text, delete_word);
+        assert_eq!(d("fix |this bug"), "fix | bug");
+        assert_eq!(d("fix| this bug"), "fix| bug");
+        assert_eq!(d("a|, \nnext line"), "a| line");
+        assert_eq!(d("wo|rd"), "wo|");
+        assert_eq!(d("end|  "), "end|  ");
+        assert_eq!(d("|日本語 text"), "| text");
+    }
+}
@@ -0,0 +1,287 @@
+//! When the dialogs redraw, and how long they wait for input in between
+//!
+//! A dialog is only drawn after something changed: a key, a cursor blink,
+//! a resize or a debounced update. While idle the loop sleeps until the
+//! next blink (or [`IDLE_POLL`]), so an open dialog costs next to no CPU
+//! and a slow link like mosh isn't fed identical frames. While the terminal
+//! doesn't have the focus (as it reports with focus events), the cursor
+//! stops blinking and the loop wakes only every [`UNFOCUSED_POLL`].
+
+use std::time::{Duration, Instant};
+
+use ratatui::layout::Size;
+
+/// Cursor blink half-period
+pub const BLINK_INTERVAL: Duration = Duration::from_millis(530);
+
+/// Poll timeout while keys are arriving
+pub const ACTIVE_POLL: Duration = Duration::from_millis(16);
+
+/// Poll timeout once the keyboard has gone quiet
+pub const IDLE_POLL: Duration = Duration::from_millis(250);
+
+/// Poll timeout while the terminal doesn't have the focus
+pub const UNFOCUSED_POLL: Duration = Duration::from_secs(1);
+
+/// How long after a key the loop keeps polling at [`ACTIVE_POLL`]
+const ACTIVE_WINDOW: Duration = Duration::from_millis(500);
+
+/// Dirty flag and wake-up deadlines of a dialog loop
+#[derive(Debug)]
+pub struct Redraw {
+    dirty: bool,
+    /// Next cursor blink toggle, None without a cursor or the focus
+    next_blink: Option<Instant>,
+    /// The dialog has a blinking cursor
+    blink: bool,
+    /// The terminal has the focus (assumed until it says otherwise)
+    focused: bool,
+    last_key: Option<Instant>,
+    /// Terminal size at the last check
+    size: Option<Size>,
+}
+
+impl Redraw {
+    /// Starts dirty, so the first frame is drawn; `blink` for a dialog with
+    /// a text cursor
+    pub fn new(now: Instant, blink: bool) -> Self {
+        Self {
+            dirty: true,
+            next_blink: blink.then(|| now + BLINK_INTERVAL),
+            blink,
+            focused: true,
+            last_key: None,
+            size: None,
+        }
+    }
+
+    /// Something on screen changed
+    pub fn mark(&mut self) {
+        self.dirty = true;
+    }
+
+    /// A key was read; its handling may change anything
+    pub fn key(&mut self, now: Instant) {
+        self.dirty = true;
+        self.last_key = Some(now);
+    }
+
+    /// Whether the cursor blink is due, scheduling the next one if so
+    pub fn blink(&mut self, now: Instant) -> bool {
+        match self.next_blink {
+            Some(at) if at <= now => {
+                self.next_blink = Some(now + BLINK_INTERVAL);
+                self.dirty = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The terminal gained or lost the focus: the blink stops while it's
+    /// away and starts over when it's back
+    pub fn focus(&mut self, focused: bool, now: Instant) {
+        if focused == self.focused {
+            return;
+        }
+        self.focused = focused;
+        self.next_blink = (focused && self.blink).then(|| now + BLINK_INTERVAL);
+        self.dirty = true;
+    }
+
+    /// Track the terminal size; a change needs a full redraw
+    pub fn resize(&mut self, size: Size) {
+        if self.size.is_some_and(|old| old != size) {
+            self.dirty = true;
+        }
+        self.size = Some(size);
+    }
+
+    /// Whether to draw now, clearing the flag
+    pub fn take(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    /// How long to wait for input before looping again
+    ///
+    /// [`ACTIVE_POLL`] right after a key, else [`IDLE_POLL`] ([`UNFOCUSED_POLL`]
+    /// without the focus); never past the next blink or `deadline` (e.g. a
+    /// debounced update).
+    pub fn poll_timeout(&self, now: Instant, deadline: Option<Instant>) -> Duration {
+        let typing = self
+            .last_key
+            .is_some_and(|at| now.duration_since(at) < ACTIVE_WINDOW);
+        let base = match (self.focused, typing) {
+            (false, _) => UNFOCUSED_POLL,
+            (true, true) => ACTIVE_POLL,
+            (true, false) => IDLE_POLL,
+        };
+        [self.next_blink, deadline]
+            .into_iter()
+            .flatten()
+            .map(|at| at.saturating_duration_since(now))
+            .fold(base, Duration::min)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_draws_only_when_dirty() {
+        let start = Instant::now();
+        let mut redraw = Redraw::new(start, false);
+        assert!(redraw.take());
+        assert!(!redraw.take());
+
+        redraw.key(start);
+        assert!(redraw.take());
+        assert!(!redraw.blink(start + Duration::from_secs(5)));
+        assert!(!redraw.take());
+
+        redraw.resize(Size::new(80, 24));
+        assert!(!redraw.take());
+        redraw.resize(Size::new(80, 24));
+        assert!(!redraw.take());
+        redraw.resize(Size::new(100, 30));
+        assert!(redraw.take());
+
+        redraw.mark();
+        assert!(redraw.take());
+    }
+
+    #[test]
+    fn test_blink_deadline() {
+        let start = Instant::now();
+        let mut redraw = Redraw::new(start, true);
+        redraw.take();
+
+        assert!(!redraw.blink(start + Duration::from_millis(500)));
+        assert!(!redraw.take());
+        // Woken in time for the blink, not a full idle poll later
+        assert_eq!(
+            redraw.poll_timeout(start + Duration::from_millis(400), None),
+            Duration::from_millis(130)
+        );
+        let at = start + BLINK_INTERVAL;
+        assert!(redraw.blink(at));
+        assert!(redraw.take());
+        assert!(!redraw.blink(at + Duration::from_millis(100)));
+        assert_eq!(redraw.poll_timeout(at, None), IDLE_POLL);
+    }
+
+    #[test]
+    fn test_poll_timeout_follows_typing() {
+        let start = Instant::now();
+        let mut redraw = Redraw::new(start, false);
+        assert_eq!(redraw.poll_timeout(start, None), IDLE_POLL);
+
+        // Keys arriving keep input latency at a frame
+        for ms in [0, 30, 60, 90] {
+            let now = start + Duration::from_millis(ms);
+            redraw.key(now);
+            assert_eq!(redraw.poll_timeout(now, None), ACTIVE_POLL);
+        }
+        let quiet = start + Duration::from_millis(90) + ACTIVE_WINDOW;
+        assert_eq!(redraw.poll_timeout(quiet, None), IDLE_POLL);
+
+        // A pending deadline wakes the loop early, and a passed one at once
+        let deadline = quiet + Duration::from_millis(100);
+        assert_eq!(
+            redraw.poll_timeout(quiet, Some(deadline)),
+            Duration::from_millis(100)
+        );
+        assert_eq!(redraw.poll_timeout(deadline, Some(quiet)), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_unfocused_pauses_blink() {
+        let start = Instant::now();
+        let mut redraw = Redraw::new(start, true);
+        redraw.take();
+
+        redraw.focus(false, start);
+        assert!(redraw.take());
+        // No blink while away, and the loop sleeps longer
+        assert!(!redraw.blink(start + BLINK_INTERVAL * 4));
+        assert_eq!(redraw.poll_timeout(start, None), UNFOCUSED_POLL);
+        redraw.focus(false, start);
+        assert!(!redraw.take());
+
+        // Back: the blink starts over from now
+        let back = start + Duration::from_secs(10);
+        redraw.focus(true, back);
+        assert!(redraw.take());
+        assert_eq!(redraw.poll_timeout(back, None), IDLE_POLL);
+        assert!(!redraw.blink(back + BLINK_INTERVAL / 2));
+        assert!(redraw.blink(back + BLINK_INTERVAL));
+    }
+
+    /// CPU time of the calling thread
+    fn thread_cpu_time() -> Duration {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut ts) };
+        Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+    }
+
+    /// Idle dialog for `duration`: drawing every frame, then with the dirty flag
+    ///
+    /// `cargo test --release bench_idle_cpu -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_idle_cpu() {
+        use ratatui::backend::TestBackend;
+        use ratatui::widgets::{Block, Borders, Paragraph};
+        use ratatui::Terminal;
+
+        let duration = Duration::from_secs(3);
+        let text = "λ explain @this\n".repeat(20);
+        let run = |dirty_flag: bool| {
+            let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+            let start = Instant::now();
+            let cpu = thread_cpu_time();
+            let mut redraw = Redraw::new(start, true);
+            let mut frames = 0;
+            let mut cursor_visible = true;
+            while start.elapsed() < duration {
+                let now = Instant::now();
+                if redraw.blink(now) {
+                    cursor_visible = !cursor_visible;
+                }
+                if redraw.take() || !dirty_flag {
+                    terminal
+                        .draw(|frame| {
+                            let block = Block::default().borders(Borders::ALL);
+                            let cursor = if cursor_visible { "█" } else { " " };
+                            frame.render_widget(
+                                Paragraph::new(format!("{}{}", text, cursor)).block(block),
+                                frame.area(),
+                            );
+                        })
+                        .unwrap();
+                    frames += 1;
+                }
+                let timeout = if dirty_flag {
+                    redraw.poll_timeout(Instant::now(), None)
+                } else {
+                    ACTIVE_POLL
+                };
+                std::thread::sleep(timeout);
+            }
+            (frames, thread_cpu_time() - cpu)
+        };
+
+        let (every_frames, every_cpu) = run(false);
+        let (dirty_frames, dirty_cpu) = run(true);
+        println!(
+            "every frame: {} draws, {:?} CPU; dirty flag: {} draws, {:?} CPU",
+            every_frames, every_cpu, dirty_frames, dirty_cpu
+        );
+        assert!(dirty_frames * 10 < every_frames);
+        assert!(dirty_cpu < every_cpu);
+    }
+}
@@ -1,7 +1,716 @@
-//! Select mode implementation (placeholder - main logic is in app.rs)
+//! Select mode state (rendering lives in app.rs)
+//!
+//! Tracks the filter string and the selected item by identity, so the
+//! highlight stays on the same item while the filter changes.
+//!
+//! Every word of the filter must match an item's name, description or
+//! category. A leading `>word` restricts the menu to matching categories
+//! (`>ag` lists the agents); the words after it filter within them.
+//!
+//! When the items span several categories, each gets a header row that can
+//! be collapsed. Collapsed items are skipped by navigation; a non-empty
+//! filter shows every match regardless.
+//!
+//! Servers with MCP tools can list hundreds of commands, so filtering is
+//! kept cheap: the lowercased text each item is matched against is built
+//! once, a longer filter only rechecks the items that matched the shorter
+//! one, and the menu may queue edits with [`SelectState::edit_filter`] to
+//! [`refilter`](SelectState::refilter) once for keys typed together.
 
-// The select mode is implemented directly in app.rs as run_select()
-// This file is reserved for future enhancements like:
-// - Grouped sections (PROMPTS, COMMANDS, AGENTS)
-// - Preview pane
-// - Fuzzy matching
+use std::collections::BTreeSet;
+
+use crate::menu::SelectItem;
+
+/// Split a filter into its `>category` restriction and the remaining words
+fn split_filter(filter: &str) -> (Option<&str>, &str) {
+    match filter.strip_prefix('>') {
+        Some(rest) => {
+            let (category, words) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            (Some(category), words)
+        }
+        None => (None, filter),
+    }
+}
+
+/// Check whether the letters of `query_lower` appear in order in `category`
+/// so abbreviations work ("cmd" matches COMMANDS, "ag" matches AGENTS)
+fn category_matches(category: &str, query_lower: &str) -> bool {
+    let mut letters = category.chars().flat_map(char::to_lowercase);
+    query_lower.chars().all(|q| letters.any(|c| c == q))
+}
+
+/// The lowercased text an item's filter words are matched against
+fn haystack(item: &SelectItem) -> String {
+    format!(
+        "{}\n{}",
+        item.name.to_lowercase(),
+        item.description.to_lowercase()
+    )
+}
+
+/// [`SelectState::matches`] for an item's [`haystack`] and a lowercased filter
+///
+/// A filter word has no whitespace, so it can't match across the line break
+/// between the name and the description.
+fn matches_lowered(haystack: &str, category: &str, filter: &str) -> bool {
+    let (restriction, words) = split_filter(filter);
+    if restriction.is_some_and(|restriction| !category_matches(category, restriction)) {
+        return false;
+    }
+    words.split_whitespace().all(|word| {
+        haystack.contains(word)
+            // Under a `>` restriction, words only filter within the categories
+            || (restriction.is_none() && category_matches(category, word))
+    })
+}
+
+/// A row of the menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Row<'a> {
+    /// Category header, with the number of matching items collapsed under it
+    Header { category: &'a str, hidden: usize },
+    /// Index into the items
+    Item(usize),
+}
+
+/// What the selection was before queued filter edits, to keep it stable
+#[derive(Debug, Clone)]
+struct Pending<'a> {
+    filter: String,
+    previous: Option<usize>,
+    header: Option<&'a str>,
+}
+
+/// Filter, collapse and selection state for the select menu
+#[derive(Debug, Clone)]
+pub struct SelectState<'a> {
+    /// All menu items
+    items: &'a [SelectItem],
+    /// Each item's lowercased name and description, on two lines
+    haystacks: Vec<String>,
+    /// Categories in order of first appearance; headers are shown if there are several
+    categories: Vec<&'a str>,
+    /// Current filter text
+    filter: String,
+    /// Indices into `items` that match the filter
+    filtered: Vec<usize>,
+    /// Collapsed categories (ignored while filtering)
+    collapsed: BTreeSet<String>,
+    /// Headers and visible items, grouped by category
+    rows: Vec<Row<'a>>,
+    /// Position of the selection within `rows`
+    selected: usize,
+    /// Filter edits not yet applied
+    pending: Option<Pending<'a>>,
+}
+
+impl<'a> SelectState<'a> {
+    /// Create a new state with an empty filter and the first item selected
+    pub fn new(items: &'a [SelectItem]) -> Self {
+        let mut categories: Vec<&str> = Vec::new();
+        for item in items {
+            if !categories.contains(&item.category.as_str()) {
+                categories.push(&item.category);
+            }
+        }
+        let mut state = Self {
+            items,
+            haystacks: items.iter().map(haystack).collect(),
+            categories,
+            filter: String::new(),
+            filtered: (0..items.len()).collect(),
+            collapsed: BTreeSet::new(),
+            rows: Vec::new(),
+            selected: 0,
+            pending: None,
+        };
+        state.rebuild_rows();
+        state.selected = state.first_item_row();
+        state
+    }
+
+    /// Create a state with a pre-filled filter
+    pub fn with_filter(items: &'a [SelectItem], filter: &str) -> Self {
+        let mut state = Self::new(items);
+        state.set_filter(filter);
+        state
+    }
+
+    /// Start with these categories collapsed
+    pub fn with_collapsed(mut self, collapsed: BTreeSet<String>) -> Self {
+        let previous = self.selected_index();
+        self.collapsed = collapsed;
+        self.rebuild_rows();
+        self.selected = previous
+            .and_then(|prev| self.row_of(prev))
+            .unwrap_or_else(|| self.first_item_row());
+        self
+    }
+
+    /// Check whether an item matches a filter (case-insensitive)
+    pub fn matches(item: &SelectItem, filter: &str) -> bool {
+        matches_lowered(&haystack(item), &item.category, &filter.to_lowercase())
+    }
+
+    /// Current filter text
+    pub fn filter(&self) -> &str {
+        &self.filter
+    }
+
+    /// The `>category` part of the filter, if it restricts the categories
+    pub fn category_restriction(&self) -> Option<&str> {
+        let (category, _) = split_filter(&self.filter);
+        category.map(|category| &self.filter[..category.len() + 1])
+    }
+
+    /// Items matching the filter, paired with their original index
+    #[cfg(test)]
+    pub fn filtered(&self) -> impl Iterator<Item = (usize, &'a SelectItem)> + '_ {
+        self.filtered.iter().map(|&i| (i, &self.items[i]))
+    }
+
+    /// Headers and visible items, in display order
+    pub fn rows(&self) -> &[Row<'a>] {
+        &self.rows
+    }
+
+    /// Rows needed to show every item (and the headers), for sizing the dialog
+    pub fn row_capacity(&self) -> usize {
+        let headers = if self.grouped() {
+            self.categories.len()
+        } else {
+            0
+        };
+        self.items.len() + headers
+    }
+
+    /// Position of the selection within the rows
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Original index of the selected item, if an item (not a header) is selected
+    pub fn selected_index(&self) -> Option<usize> {
+        match self.rows.get(self.selected) {
+            Some(Row::Item(i)) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// The selected item, if an item (not a header) is selected
+    pub fn selected_item(&self) -> Option<&'a SelectItem> {
+        self.selected_index().map(|i| &self.items[i])
+    }
+
+    /// The category of the selected header, if a header is selected
+    pub fn selected_header(&self) -> Option<&'a str> {
+        match self.rows.get(self.selected) {
+            Some(Row::Header { category, .. }) => Some(category),
+            _ => None,
+        }
+    }
+
+    /// All menu items
+    pub fn items(&self) -> &'a [SelectItem] {
+        self.items
+    }
+
+    /// Select the first shown item with `value`, if there is one
+    pub fn select_value(&mut self, value: &str) {
+        if let Some(row) = self
+            .rows
+            .iter()
+            .position(|row| matches!(row, Row::Item(i) if self.items[*i].value == value))
+        {
+            self.selected = row;
+        }
+    }
+
+    /// Collapsed categories
+    pub fn collapsed(&self) -> &BTreeSet<String> {
+        &self.collapsed
+    }
+
+    /// Collapse the selected category (from its header or one of its items)
+    /// and select its header. Does nothing while filtering.
+    pub fn collapse(&mut self) {
+        let category = match self.rows.get(self.selected) {
+            Some(Row::Header { category, .. }) => *category,
+            Some(Row::Item(i)) => self.items[*i].category.as_str(),
+            None => return,
+        };
+        if !self.filter.is_empty() || !self.grouped() {
+            return;
+        }
+        self.collapsed.insert(category.to_string());
+        self.rebuild_rows();
+        self.selected = self.header_row(category).unwrap_or(0);
+    }
+
+    /// Expand the category under the selected header. Does nothing while filtering.
+    pub fn expand(&mut self) {
+        let Some(category) = self.selected_header() else {
+            return;
+        };
+        if !self.filter.is_empty() || !self.collapsed.remove(category) {
+            return;
+        }
+        self.rebuild_rows();
+        self.selected = self.header_row(category).unwrap_or(0);
+    }
+
+    /// Collapse or expand the category under the selected header
+    pub fn toggle(&mut self) {
+        match self.selected_header() {
+            Some(category) if self.collapsed.contains(category) => self.expand(),
+            Some(_) => self.collapse(),
+            None => {}
+        }
+    }
+
+    /// Move the selection up one row
+    pub fn move_up(&mut self) {
+        self.move_up_by(1);
+    }
+
+    /// Move the selection down one row
+    pub fn move_down(&mut self) {
+        self.move_down_by(1);
+    }
+
+    /// Move the selection up `n` rows (PageUp), stopping at the first
+    pub fn move_up_by(&mut self, n: usize) {
+        self.selected = self.selected.saturating_sub(n);
+    }
+
+    /// Move the selection down `n` rows (PageDown), stopping at the last
+    pub fn move_down_by(&mut self, n: usize) {
+        self.selected = (self.selected + n).min(self.rows.len().saturating_sub(1));
+    }
+
+    /// Append a character to the filter
+    #[cfg(test)]
+    pub fn push_char(&mut self, c: char) {
+        self.edit_filter(|filter| filter.push(c));
+        self.refilter();
+    }
+
+    /// Remove the last character from the filter
+    #[cfg(test)]
+    pub fn pop_char(&mut self) {
+        self.edit_filter(|filter| {
+            filter.pop();
+        });
+        self.refilter();
+    }
+
+    /// Replace the filter text and refilter, keeping the selection stable
+    pub fn set_filter(&mut self, filter: &str) {
+        self.edit_filter(|text| filter.clone_into(text));
+        self.refilter();
+    }
+
+    /// Change the filter text without refiltering yet
+    ///
+    /// The rows and selection are those of the old filter until
+    /// [`refilter`](Self::refilter), so several keys typed together cost one
+    /// pass over the items.
+    pub fn edit_filter(&mut self, edit: impl FnOnce(&mut String)) {
+        if self.pending.is_none() {
+            self.pending = Some(Pending {
+                filter: self.filter.clone(),
+                previous: self.selected_index(),
+                header: self.selected_header(),
+            });
+        }
+        edit(&mut self.filter);
+    }
+
+    /// Whether filter edits are waiting for [`refilter`](Self::refilter)
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Apply the queued filter edits, keeping the selection stable
+    pub fn refilter(&mut self) {
+        let Some(Pending {
+            filter: old,
+            previous,
+            header,
+        }) = self.pending.take()
+        else {
+            return;
+        };
+        let narrowed = self.filter.len() > old.len() && self.filter.starts_with(&old);
+
+        // A longer filter only drops items, so only the matches are rechecked
+        let filter = self.filter.to_lowercase();
+        let matching =
+            |&i: &usize| matches_lowered(&self.haystacks[i], &self.items[i].category, &filter);
+        self.filtered = if narrowed {
+            self.filtered.iter().copied().filter(matching).collect()
+        } else {
+            (0..self.items.len()).filter(matching).collect()
+        };
+        self.rebuild_rows();
+
+        self.selected = match previous {
+            // Same item still visible - keep it
+            Some(prev) if self.row_of(prev).is_some() => self.row_of(prev).unwrap_or(0),
+            // Filter got longer and dropped the item - start from the top
+            Some(_) if narrowed => self.first_item_row(),
+            // Otherwise move to the nearest surviving neighbor
+            Some(prev) => self
+                .rows
+                .iter()
+                .position(|row| matches!(row, Row::Item(i) if self.rank(*i) > self.rank(prev)))
+                .unwrap_or(self.rows.len())
+                .min(self.rows.len().saturating_sub(1)),
+            None => header
+                .and_then(|category| self.header_row(category))
+                .unwrap_or_else(|| self.first_item_row()),
+        };
+    }
+
+    /// Whether the items span several categories, so headers are shown
+    fn grouped(&self) -> bool {
+        self.categories.len() > 1
+    }
+
+    /// Display order of an item: by category, then as listed
+    fn rank(&self, index: usize) -> (usize, usize) {
+        let category = self.items[index].category.as_str();
+        let group = self.categories.iter().position(|c| *c == category);
+        (group.unwrap_or(0), index)
+    }
+
+    /// Row showing an item, unless it is filtered out or collapsed
+    fn row_of(&self, index: usize) -> Option<usize> {
+        self.rows.iter().position(|row| *row == Row::Item(index))
+    }
+
+    /// Row of a category's header
+    fn header_row(&self, category: &str) -> Option<usize> {
+        self.rows
+            .iter()
+            .position(|row| matches!(row, Row::Header { category: c, .. } if *c == category))
+    }
+
+    /// First item row, or the first row if every category is collapsed
+    fn first_item_row(&self) -> usize {
+        self.rows
+            .iter()
+            .position(|row| matches!(row, Row::Item(_)))
+            .unwrap_or(0)
+    }
+
+    /// Group the matching items under their headers, hiding collapsed ones
+    fn rebuild_rows(&mut self) {
+        self.rows.clear();
+        // A filter expands everything so no match is hidden
+        let expand_all = !self.filter.is_empty() || !self.grouped();
+        for &category in &self.categories {
+            let mut matching = self
+                .filtered
+                .iter()
+                .copied()
+                .filter(|&i| self.items[i].category == category)
+                .peekable();
+            if matching.peek().is_none() {
+                continue;
+            }
+            if expand_all || !self.collapsed.contains(category) {
+                if self.grouped() {
+                    self.rows.push(Row::Header {
+                        category,
+                        hidden: 0,
+                    });
+                }
+                self.rows.extend(matching.map(Row::Item));
+            } else {
+                self.rows.push(Row::Header {
+                    category,
+                    hidden: matching.count(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items() -> Vec<SelectItem> {
+        ["explain", "review", "refactor", "tests", "docs"]
+            .iter()
+            .map(|name| SelectItem::new(name, "", name, "PROMPTS"))
+            .collect()
+    }
+
+    #[test]
+    fn test_selection_kept_when_filtering() {
+        let items = items();
+        let mut state = SelectState::new(&items);
+        state.move_down();
+        state.move_down();
+        assert_eq!(state.selected_item().unwrap().name, "refactor");
+
+        state.push_char('r');
+        assert_eq!(state.selected_item().unwrap().name, "refactor");
+        state.push_char('e');
+        assert_eq!(state.selected_item().unwrap().name, "refactor");
+        state.pop_char();
+        state.pop_char();
+        assert_eq!(state.selected_item().unwrap().name, "refactor");
+    }
+
+    #[test]
+    fn test_selection_resets_when_narrowed_out() {
+        let items = items();
+        let mut state = SelectState::new(&items);
+        for _ in 0..4 {
+            state.move_down();
+        }
+        assert_eq!(state.selected_item().unwrap().name, "docs");
+
+        // "docs" is filtered out by a longer filter - back to the top
+        state.push_char('e');
+        assert_eq!(state.selected_item().unwrap().name, "explain");
+
+        state.push_char('x');
+        state.push_char('x');
+        assert!(state.selected_item().is_none());
+        state.pop_char();
+        assert_eq!(state.selected(), 0);
+    }
+
+    #[test]
+    fn test_selection_moves_to_neighbor() {
+        let items: Vec<SelectItem> = ["a1", "b", "c1", "d1"]
+            .iter()
+            .map(|name| SelectItem::new(name, "", name, "PROMPTS"))
+            .collect();
+        let mut state = SelectState::new(&items);
+        state.set_filter("b");
+        assert_eq!(state.selected_item().unwrap().name, "b");
+
+        // Replacing the filter (not narrowing) keeps the nearest neighbor
+        state.set_filter("1");
+        assert_eq!(state.selected_item().unwrap().name, "c1");
+    }
+
+    #[test]
+    fn test_with_filter() {
+        let items = items();
+        let state = SelectState::with_filter(&items, "RE");
+        assert_eq!(state.filter(), "RE");
+        let names: Vec<&str> = state
+            .filtered()
+            .map(|(_, item)| item.name.as_str())
+            .collect();
+        assert_eq!(names, ["review", "refactor"]);
+        assert_eq!(state.selected_item().unwrap().name, "review");
+    }
+
+    #[test]
+    fn test_select_value() {
+        let items = items();
+        let mut state = SelectState::with_filter(&items, "RE");
+        state.select_value("refactor");
+        assert_eq!(state.selected_item().unwrap().name, "refactor");
+        // Filtered out: the selection stays
+        state.select_value("docs");
+        assert_eq!(state.selected_item().unwrap().name, "refactor");
+    }
+
+    #[test]
+    fn test_category_filter() {
+        let items = vec![
+            SelectItem::new("review", "Code review", "review", "PROMPTS"),
+            SelectItem::new("test", "Run the tests", "/test", "COMMANDS"),
+            SelectItem::new("reviewer", "Reviews code", "@reviewer", "AGENTS"),
+            SelectItem::new("build", "Builds things", "@build", "AGENTS"),
+        ];
+        let names = |filter: &str| -> Vec<String> {
+            let state = SelectState::with_filter(&items, filter);
+            state
+                .filtered()
+                .map(|(_, item)| item.name.clone())
+                .collect()
+        };
+
+        // Words match name, description or (abbreviated) category
+        assert_eq!(names("cmd test"), ["test"]);
+        assert_eq!(names("review"), ["review", "reviewer"]);
+        assert_eq!(names("agents"), ["reviewer", "build"]);
+        assert_eq!(names("code review"), ["review", "reviewer"]);
+
+        // `>` restricts to categories; later words filter within them
+        assert_eq!(names(">ag"), ["reviewer", "build"]);
+        assert_eq!(names(">AG rev"), ["reviewer"]);
+        assert_eq!(names(">pr agents"), Vec::<String>::new());
+        assert_eq!(names(">"), ["review", "test", "reviewer", "build"]);
+        assert_eq!(names(">xyz"), Vec::<String>::new());
+
+        let state = SelectState::with_filter(&items, ">ag rev");
+        assert_eq!(state.category_restriction(), Some(">ag"));
+        let state = SelectState::with_filter(&items, "ag");
+        assert_eq!(state.category_restriction(), None);
+    }
+
+    #[test]
+    fn test_collapse_categories() {
+        let items = vec![
+            SelectItem::new("p1", "", "p1", "PROMPTS"),
+            SelectItem::new("ch1", "", "ch1", "CHAINS"),
+            SelectItem::new("p2", "", "p2", "PROMPTS"),
+            SelectItem::new("c1", "", "/c1", "COMMANDS"),
+            SelectItem::new("c2", "", "/c2", "COMMANDS"),
+            SelectItem::new("c3", "", "/c3", "COMMANDS"),
+        ];
+        let mut state = SelectState::new(&items);
+        // Grouped by category in order of first appearance, starting on an item
+        assert_eq!(state.rows().len(), 9);
+        assert_eq!(state.row_capacity(), 9);
+        assert_eq!(state.selected_item().unwrap().name, "p1");
+        assert_eq!(
+            state.rows()[3],
+            Row::Header {
+                category: "CHAINS",
+                hidden: 0
+            }
+        );
+
+        // Collapsing from an item selects its header
+        state.move_down_by(5);
+        assert_eq!(state.selected_item().unwrap().name, "c1");
+        state.collapse();
+        assert_eq!(state.selected_header(), Some("COMMANDS"));
+        assert_eq!(
+            state.rows().last(),
+            Some(&Row::Header {
+                category: "COMMANDS",
+                hidden: 3
+            })
+        );
+        // Navigation skips the hidden items
+        state.move_down();
+        assert_eq!(state.selected_header(), Some("COMMANDS"));
+        state.move_up();
+        assert_eq!(state.selected_item().unwrap().name, "ch1");
+
+        // A filter shows every match; clearing it collapses again
+        state.set_filter("c");
+        assert!(state.rows().contains(&Row::Item(5)));
+        state.collapse();
+        assert_eq!(state.collapsed().len(), 1);
+        state.set_filter("");
+        assert_eq!(state.rows().len(), 6);
+
+        // Restored collapse state, then expanded from the header
+        let mut state = SelectState::new(&items).with_collapsed(state.collapsed().clone());
+        assert_eq!(state.rows().len(), 6);
+        assert_eq!(state.selected_item().unwrap().name, "p1");
+        state.move_down_by(10);
+        state.expand();
+        assert_eq!(state.rows().len(), 9);
+        assert!(state.collapsed().is_empty());
+        state.toggle();
+        assert_eq!(state.collapsed().len(), 1);
+
+        // A single category has no headers and can't be collapsed
+        let items = self::items();
+        let mut state = SelectState::new(&items);
+        state.collapse();
+        assert!(state.collapsed().is_empty());
+        assert_eq!(state.rows().len(), items.len());
+    }
+
+    #[test]
+    fn test_queued_filter_edits() {
+        let items = items();
+        let mut state = SelectState::new(&items);
+        state.move_down_by(2);
+        // Rows stay those of the old filter until refiltered
+        state.edit_filter(|filter| filter.push_str("re"));
+        state.edit_filter(|filter| filter.push('x'));
+        state.edit_filter(|filter| {
+            filter.pop();
+        });
+        assert!(state.is_pending());
+        assert_eq!(state.filter(), "re");
+        assert_eq!(state.rows().len(), items.len());
+
+        // One pass, keeping the selection as if typed key by key
+        state.refilter();
+        assert!(!state.is_pending());
+        let names: Vec<&str> = state.filtered().map(|(_, i)| i.name.as_str()).collect();
+        assert_eq!(names, ["review", "refactor"]);
+        assert_eq!(state.selected_item().unwrap().name, "refactor");
+        state.refilter();
+        assert_eq!(state.selected_item().unwrap().name, "refactor");
+
+        // Narrowed from "re": only the earlier matches are rechecked
+        state.edit_filter(|filter| filter.push('v'));
+        state.refilter();
+        assert_eq!(state.selected_item().unwrap().name, "review");
+        state.set_filter("tests");
+        assert_eq!(state.selected_item().unwrap().name, "tests");
+    }
+
+    /// A menu with hundreds of MCP commands stays responsive: filtering as
+    /// each key is typed, and laying out a screenful, takes milliseconds
+    #[test]
+    fn test_filter_and_layout_of_many_items() {
+        let items: Vec<SelectItem> = (0..1000)
+            .map(|i| {
+                let category = ["PROMPTS", "COMMANDS", "AGENTS"][i % 3];
+                let description = format!("Runs tool number {} of the Example MCP server", i);
+                SelectItem::new(&format!("mcp-tool-{}", i), &description, "/tool", category)
+            })
+            .collect();
+
+        let started = std::time::Instant::now();
+        let mut state = SelectState::new(&items);
+        let mut shown = 0;
+        for c in "tool 99".chars() {
+            state.edit_filter(|filter| filter.push(c));
+            state.refilter();
+            // What the menu draws: the rows in a 30-row viewport
+            shown = state.rows().iter().take(30).count();
+        }
+        let elapsed = started.elapsed();
+
+        // 99, 199, ..., 899 and 990..=999, under three headers
+        assert_eq!(state.filtered().count(), 19);
+        assert_eq!(shown, 22);
+        // Generous, for debug builds on a busy machine
+        assert!(
+            elapsed < std::time::Duration::from_millis(200),
+            "{:?}",
+            elapsed
+        );
+    }
+
+    #[test]
+    fn test_move_bounds() {
+        let items = items();
+        let mut state = SelectState::new(&items);
+        state.move_up();
+        assert_eq!(state.selected(), 0);
+        for _ in 0..10 {
+            state.move_down();
+        }
+        assert_eq!(state.selected(), items.len() - 1);
+
+        state.move_up_by(3);
+        assert_eq!(state.selected(), 1);
+        state.move_up_by(3);
+        assert_eq!(state.selected(), 0);
+        state.move_down_by(3);
+        assert_eq!(state.selected(), 3);
+        state.move_down_by(3);
+        assert_eq!(state.selected(), items.len() - 1);
+    }
+}
@@ -0,0 +1,317 @@
+//! Text of the dialogs, translatable
+//!
+//! Every label, help line and message the dialogs draw comes from
+//! [`Strings`]. English is built in; a locale file picked by the locale
+//! variables, `$XDG_CONFIG_HOME/opencode-helix/strings/<locale>.toml` (e.g.
+//! `ja_JP.toml`, else `ja.toml`), replaces any of it, and the config's
+//! `[strings]` section replaces that in turn. Both use the field names of
+//! [`Strings`] as keys; the help overlay is translated through the `help`
+//! table, keyed by the English text.
+//!
+//! Translations change widths, so layout goes by display width
+//! (`unicode-width`), never by bytes or chars.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::context::ContextWarning;
+use crate::paths;
+
+/// The dialogs' text; `{name}` marks a value filled in when drawn
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Strings {
+    /// Ask dialog send button
+    pub send: String,
+    /// Ask dialog cancel button
+    pub cancel: String,
+    /// Heading of the placeholders panel
+    pub placeholders: String,
+    /// Ask dialog title tag in `ask --loop`: `{round}`
+    pub round: String,
+    /// Ask dialog help line
+    pub ask_help: String,
+    /// Ask dialog help line when a selection preview can be shown
+    pub ask_help_context: String,
+    /// Help line while a placeholder is focused
+    pub placeholders_help: String,
+    /// Help line of the placeholder expansion view
+    pub view_help: String,
+    /// Expansion view of a placeholder with no value
+    pub not_available: String,
+    /// Marker next to ▲/▼ when input rows are scrolled out of view
+    pub more: String,
+    /// Size of the expanded prompt: `{chars}`, `{tokens}`
+    pub counter: String,
+    /// Banner when placeholders would read a file with unsaved changes
+    pub stale_file: String,
+    /// Toast after Ctrl+Y: `{size}`
+    pub copied: String,
+    /// After the pasted images' chips
+    pub attachments_help: String,
+    /// Select dialog title, after the theme's
+    pub select_title: String,
+    /// Select dialog help line
+    pub select_help: String,
+    /// Select dialog help line when items can be edited before sending
+    pub select_help_edit: String,
+    /// Collapsed category: `{count}`
+    pub hidden: String,
+    /// Select dialog tag while its lists are from the cache
+    pub cached: String,
+    /// Question when a prompt is over the token limit: `{tokens}`, `{limit}`
+    pub over_limit: String,
+    /// Question when a prompt has `@words` nothing expands: `{count}`, `{words}`
+    pub unknown_words: String,
+    /// Answers to the over-limit and unknown-words questions
+    pub send_anyway: String,
+    pub keep_editing: String,
+    /// Dismissing a question
+    pub choice_cancel: String,
+    /// Notice when Alt+A has no agents or models to offer
+    pub no_choices: String,
+    /// Description of the agent or model in use, in the Alt+A menu
+    pub current: String,
+    /// Help overlay title
+    pub keys_title: String,
+    /// Help overlay bottom line
+    pub keys_help: String,
+    /// Help overlay headings and descriptions, keyed by the English text
+    pub help: BTreeMap<String, String>,
+}
+
+impl Default for Strings {
+    fn default() -> Self {
+        Self {
+            send: "SEND".to_string(),
+            cancel: "CANCEL".to_string(),
+            placeholders: "Placeholders:".to_string(),
+            round: "round {round}".to_string(),
+            ask_help: "[Tab] Focus  [^G] Editor  [M-h] Keys  [Enter] Send  [Esc] Abort".to_string(),
+            ask_help_context:
+                "[Tab] Focus  [^E] Context  [^G] Editor  [M-h] Keys  [Enter] Send  [Esc] Abort"
+                    .to_string(),
+            placeholders_help: "[↑↓] Select  [Enter] Insert  [v] View  [Tab] Next".to_string(),
+            view_help: "[↑↓] Scroll  [Esc] Close".to_string(),
+            not_available: "(not available)".to_string(),
+            more: "more".to_string(),
+            counter: "{chars} chars · ~{tokens} tokens".to_string(),
+            stale_file: ContextWarning::StaleFile.to_string(),
+            copied: "copied ({size})".to_string(),
+            attachments_help: "[^X] Remove".to_string(),
+            select_title: "SELECT".to_string(),
+            select_help: "[↑↓] Navigate  [Enter] Execute  [M-h] Keys  [Esc] Abort".to_string(),
+            select_help_edit: "[↑↓] Navigate  [Enter] Execute  [Tab] Edit  [M-h] Keys  [Esc] Abort"
+                .to_string(),
+            hidden: "{count} hidden".to_string(),
+            cached: "cached".to_string(),
+            over_limit: "This prompt is about {tokens} tokens after expansion, over the limit \
+                         of {limit}. Send it anyway?"
+                .to_string(),
+            unknown_words: "Not placeholders or agents, so sent as typed: {words}. Send anyway?"
+                .to_string(),
+            send_anyway: "Send".to_string(),
+            keep_editing: "Keep editing".to_string(),
+            choice_cancel: "Cancel".to_string(),
+            no_choices: "No agents or models to choose from".to_string(),
+            current: "current".to_string(),
+            keys_title: "Keys".to_string(),
+            keys_help: "[↑↓ PgUp PgDn] Scroll  [any key] Close".to_string(),
+            help: BTreeMap::new(),
+        }
+    }
+}
+
+impl Strings {
+    /// English, overridden by the locale file and then by `config` (`[strings]`)
+    ///
+    /// Like a broken default config, a locale file that can't be read or
+    /// parsed is skipped, noting why in the debug log.
+    pub fn load(
+        config: &toml::Table,
+        env: impl Fn(&str) -> Option<String>,
+        debug: bool,
+    ) -> Result<Self> {
+        let locale = locale_file(env).and_then(|path| {
+            let table = read_table(&path).and_then(|table| Self::layered(&[&table]).map(|_| table));
+            match table {
+                Ok(table) => Some(table),
+                Err(e) => {
+                    super::app::debug_log(debug, &format!("Ignoring strings: {:#}", e));
+                    None
+                }
+            }
+        });
+        match locale {
+            Some(locale) => Self::layered(&[&locale, config]),
+            None => Self::layered(&[config]),
+        }
+    }
+
+    /// English with each table's entries on top of the last; `help`
+    /// entries are merged one by one
+    pub fn layered(tables: &[&toml::Table]) -> Result<Self> {
+        let mut merged = toml::Table::new();
+        for table in tables {
+            for (key, value) in table.iter() {
+                match (merged.get_mut(key), value) {
+                    (Some(toml::Value::Table(into)), toml::Value::Table(from)) => {
+                        into.extend(from.clone());
+                    }
+                    _ => {
+                        merged.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        Ok(merged.try_into()?)
+    }
+
+    /// The help overlay's `text`, translated if there is a translation
+    pub fn help<'a>(&'a self, text: &'a str) -> &'a str {
+        self.help.get(text).map_or(text, String::as_str)
+    }
+}
+
+/// `template` with each `{name}` replaced by its value
+pub fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{}}}", name), value)
+        })
+}
+
+/// Locale names to look for, most specific first: `ja_JP.UTF-8` gives
+/// `ja_JP` then `ja`
+///
+/// The first of `$LC_ALL`, `$LC_MESSAGES` and `$LANG` that is set decides, as
+/// for messages in other programs; `C` and `POSIX` mean the built-in English.
+fn locale_names(env: impl Fn(&str) -> Option<String>) -> Vec<String> {
+    let Some(locale) = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .filter_map(&env)
+        .find(|value| !value.is_empty())
+    else {
+        return Vec::new();
+    };
+    let name = locale
+        .split(['.', '@'])
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    if name.is_empty() || name == "C" || name == "POSIX" {
+        return Vec::new();
+    }
+    let language = name.split('_').next().unwrap_or_default().to_string();
+    if language == name {
+        vec![name]
+    } else {
+        vec![name, language]
+    }
+}
+
+/// The locale file for the current locale, if one exists
+fn locale_file(env: impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    let dir = paths::strings_dir()?;
+    locale_names(env)
+        .into_iter()
+        .map(|name| dir.join(format!("{}.toml", name)))
+        .find(|path| path.exists())
+}
+
+fn read_table(path: &std::path::Path) -> Result<toml::Table> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    toml::from_str(&text).with_context(|| format!("Invalid strings {}", path.display()))
+}
+
+/// A complete Japanese translation, for rendering the dialogs in tests
+#[cfg(test)]
+const JAPANESE: &str = include_str!("../../tests/fixtures/strings/ja.toml");
+
+/// [`JAPANESE`] loaded
+#[cfg(test)]
+pub fn japanese() -> Strings {
+    Strings::layered(&[&toml::from_str(JAPANESE).expect("ja.toml")]).expect("ja.toml")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tui::keymap;
+
+    #[test]
+    fn test_japanese_is_complete() {
+        let table: toml::Table = toml::from_str(JAPANESE).unwrap();
+        let english = Strings::default();
+        let keys: Vec<&String> = table.keys().collect();
+        let all = toml::Table::try_from(&english).unwrap();
+        assert_eq!(keys, all.keys().collect::<Vec<_>>());
+
+        let strings = japanese();
+        for binding in keymap::ASK_BINDINGS.iter().chain(keymap::SELECT_BINDINGS) {
+            for text in [binding.category, binding.action] {
+                assert!(strings.help.contains_key(text), "{}", text);
+            }
+        }
+        assert_eq!(strings.help("Cancel"), "キャンセル");
+        assert_eq!(english.help("Cancel"), "Cancel");
+    }
+
+    #[test]
+    fn test_layered() {
+        let locale: toml::Table =
+            toml::from_str("send = \"送信\"\ncancel = \"取消\"\n[help]\nCancel = \"取消\"\n")
+                .unwrap();
+        let config: toml::Table =
+            toml::from_str("cancel = \"やめる\"\n[help]\nScroll = \"スクロール\"\n").unwrap();
+        let strings = Strings::layered(&[&locale, &config]).unwrap();
+        assert_eq!(strings.send, "送信");
+        assert_eq!(strings.cancel, "やめる");
+        assert_eq!(strings.help("Cancel"), "取消");
+        assert_eq!(strings.help("Scroll"), "スクロール");
+        assert_eq!(strings.placeholders, "Placeholders:");
+
+        let typo: toml::Table = toml::from_str("sned = \"送信\"").unwrap();
+        assert!(Strings::layered(&[&typo]).is_err());
+    }
+
+    #[test]
+    fn test_fill() {
+        let text = fill(
+            &Strings::default().counter,
+            &[("chars", "42"), ("tokens", "11")],
+        );
+        assert_eq!(text, "42 chars · ~11 tokens");
+        assert_eq!(fill("{a} {b} {a}", &[("a", "x")]), "x {b} x");
+    }
+
+    #[test]
+    fn test_locale_names() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |var: &str| {
+                vars.iter()
+                    .find(|(name, _)| *name == var)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        assert_eq!(
+            locale_names(env(&[("LANG", "ja_JP.UTF-8")])),
+            ["ja_JP", "ja"]
+        );
+        assert_eq!(
+            locale_names(env(&[("LANG", "ja_JP.UTF-8"), ("LC_ALL", "de_DE@euro")])),
+            ["de_DE", "de"]
+        );
+        assert_eq!(
+            locale_names(env(&[("LC_MESSAGES", "ja"), ("LC_ALL", "")])),
+            ["ja"]
+        );
+        assert!(locale_names(env(&[("LANG", "C.UTF-8")])).is_empty());
+        assert!(locale_names(env(&[])).is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+//! Identity of the server a dialog sends to
+//!
+//! Shown in the dialog title ("→ :39127 · ~/code/myproj") so that with several
+//! opencode instances running it is clear which one receives the prompt.
+
+use std::path::{Path, PathBuf};
+
+use unicode_width::UnicodeWidthChar;
+use unicode_width::UnicodeWidthStr;
+
+/// Widest session title shown before it is shortened
+const MAX_SESSION_WIDTH: usize = 24;
+
+/// Server (and session) the dialog sends to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    /// HTTP server port
+    pub port: u16,
+    /// Working directory of the server
+    pub cwd: PathBuf,
+    /// Title (or id) of the target session, if one was chosen
+    pub session: Option<String>,
+    /// The current file is outside the server's working directory
+    pub mismatch: bool,
+}
+
+impl Target {
+    /// Describe a server, flagging a mismatch if `file` (absolute) lies outside its cwd
+    pub fn new(port: u16, cwd: &Path, session: Option<String>, file: Option<&Path>) -> Self {
+        let mismatch = file.is_some_and(|file| !within(file, cwd));
+        Self {
+            port,
+            cwd: cwd.to_path_buf(),
+            session,
+            mismatch,
+        }
+    }
+
+    /// Title label fitted to `max_width` display columns
+    /// The path is shortened first; the port is always kept.
+    pub fn label(&self, max_width: usize) -> String {
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        let path = tilde_path(&self.cwd, home.as_deref());
+        let session = self
+            .session
+            .as_deref()
+            .map(|s| format!(" · {}", middle_ellipsis(s, MAX_SESSION_WIDTH)))
+            .unwrap_or_default();
+
+        let head = format!("→ :{} · ", self.port);
+        let room = max_width.saturating_sub(head.width() + session.width());
+        format!("{}{}{}", head, middle_ellipsis(&path, room), session)
+    }
+}
+
+/// Check if `file` is inside `dir`, comparing canonical paths when they exist
+fn within(file: &Path, dir: &Path) -> bool {
+    let file = file.canonicalize().unwrap_or_else(|_| file.to_path_buf());
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    file.starts_with(dir)
+}
+
+/// Display a path with the home directory replaced by `~`
+fn tilde_path(path: &Path, home: Option<&Path>) -> String {
+    match home.and_then(|home| path.strip_prefix(home).ok()) {
+        Some(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Some(rest) => format!("~/{}", rest.display()),
+        None => path.display().to_string(),
+    }
+}
+
+/// Shorten `text` to `max_width` display columns by replacing its middle with "…"
+fn middle_ellipsis(text: &str, max_width: usize) -> String {
+    if text.width() <= max_width {
+        return text.to_string();
+    }
+    if max_width == 0 {
+        return String::new();
+    }
+
+    // Keep slightly more of the end, which names the project
+    let budget = max_width - 1;
+    let head_width = budget / 2;
+    let tail_width = budget - head_width;
+
+    let mut head = String::new();
+    let mut width = 0;
+    for c in text.chars() {
+        let w = c.width().unwrap_or(0);
+        if width + w > head_width {
+            break;
+        }
+        width += w;
+        head.push(c);
+    }
+
+    let mut tail = Vec::new();
+    let mut width = 0;
+    for c in text.chars().rev() {
+        let w = c.width().unwrap_or(0);
+        if width + w > tail_width {
+            break;
+        }
+        width += w;
+        tail.push(c);
+    }
+
+    format!("{}…{}", head, tail.into_iter().rev().collect::<String>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tilde_path() {
+        let home = Path::new("/home/me");
+        assert_eq!(
+            tilde_path(Path::new("/home/me/code/x"), Some(home)),
+            "~/code/x"
+        );
+        assert_eq!(tilde_path(home, Some(home)), "~");
+        assert_eq!(tilde_path(Path::new("/srv/x"), Some(home)), "/srv/x");
+    }
+
+    #[test]
+    fn test_middle_ellipsis_uses_display_width() {
+        assert_eq!(middle_ellipsis("short", 10), "short");
+        assert_eq!(middle_ellipsis("/very/long/path/name", 9), "/ver…name");
+        // Wide characters count as two columns
+        let shortened = middle_ellipsis("~/項目/コード/リポジトリ", 12);
+        assert!(shortened.width() <= 12);
+        assert!(shortened.contains('…'));
+    }
+
+    #[test]
+    fn test_label_and_mismatch() {
+        let target = Target::new(
+            39127,
+            Path::new("/srv/myproj"),
+            Some("Fix the parser".to_string()),
+            Some(Path::new("/srv/myproj/src/main.rs")),
+        );
+        assert!(!target.mismatch);
+        assert_eq!(target.label(80), "→ :39127 · /srv/myproj · Fix the parser");
+        assert_eq!(target.label(30), "→ :39127 · …j · Fix the parser");
+
+        let other = Target::new(
+            1,
+            Path::new("/srv/myproj"),
+            None,
+            Some(Path::new("/srv/other/a.rs")),
+        );
+        assert!(other.mismatch);
+        assert!(!Target::new(1, Path::new("/srv/myproj"), None, None).mismatch);
+    }
+}
@@ -0,0 +1,290 @@
+//! Terminal modes while a dialog is open
+//!
+//! The dialogs use the alternate screen with mouse capture, or with
+//! `--inline` the rows from the cursor down on the current screen, scrolled
+//! up as needed to make room (so earlier output moves into the scrollback
+//! rather than being overwritten). Only those rows are cleared on exit.
+//!
+//! Focus reporting (`CSI ? 1004 h`) is on while a dialog is open, so the
+//! cursor can stop blinking while the terminal is in the background;
+//! terminals without it ignore the request and send no reports.
+//!
+//! Besides that, the dialogs set the window
+//! title (OSC 0) and the cursor shape (DECSCUSR). The old title is saved on
+//! the terminal's title stack (`CSI 22;0 t` / `CSI 23;0 t`); terminals
+//! without one ignore the push and pop. The previous cursor shape can't be
+//! queried, so it is reset to the terminal's default.
+//!
+//! A panic hook restores all of it, so a crash doesn't leave the terminal in
+//! raw mode on the alternate screen with a stale title. While the modes are
+//! being set up, a [`TerminalGuard`] undoes the ones already set if a later
+//! one fails.
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Once;
+
+use crossterm::terminal::disable_raw_mode;
+use ratatui::layout::{Rect, Size};
+
+use crate::config::CursorShape;
+
+/// The alternate screen is up (and raw mode on)
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// The dialogs are on the alternate screen rather than inline
+static ALTERNATE: AtomicBool = AtomicBool::new(false);
+
+/// The title was pushed and the cursor shape set
+static INTEGRATED: AtomicBool = AtomicBool::new(false);
+
+/// Switch to the alternate screen with mouse capture, or stay on the
+/// current screen for an inline dialog
+pub fn enter(out: &mut impl Write, alternate: bool) -> io::Result<()> {
+    ACTIVE.store(true, Ordering::SeqCst);
+    ALTERNATE.store(alternate, Ordering::SeqCst);
+    if alternate {
+        write!(out, "\x1b[?1049h")?; // Enter alternate screen
+        write!(out, "\x1b[?1000h")?; // Enable mouse capture
+    }
+    write!(out, "\x1b[?1004h")?; // Enable focus reporting
+    out.flush()
+}
+
+/// Set the window title and cursor shape, saving the old title the first time
+pub fn integrate(out: &mut impl Write, title: &str, shape: Option<CursorShape>) -> io::Result<()> {
+    if !INTEGRATED.swap(true, Ordering::SeqCst) {
+        write!(out, "\x1b[22;0t")?; // Push title
+    }
+    // Control characters would end the sequence early
+    let title: String = title.chars().filter(|c| !c.is_control()).collect();
+    write!(out, "\x1b]0;{}\x07", title)?;
+    if let Some(shape) = shape {
+        write!(out, "\x1b[{} q", shape.decscusr())?;
+    }
+    out.flush()
+}
+
+/// Undo [`integrate`] and [`enter`]; safe to call more than once
+pub fn leave(out: &mut impl Write) -> io::Result<()> {
+    if INTEGRATED.swap(false, Ordering::SeqCst) {
+        write!(out, "\x1b[0 q")?; // Default cursor shape
+        write!(out, "\x1b[23;0t")?; // Pop title
+    }
+    if ACTIVE.swap(false, Ordering::SeqCst) {
+        write!(out, "\x1b[?1004l")?; // Disable focus reporting
+    }
+    if ALTERNATE.load(Ordering::SeqCst) {
+        write!(out, "\x1b[?1000l")?; // Disable mouse capture
+        write!(out, "\x1b[?1049l")?; // Leave alternate screen
+    }
+    out.flush()
+}
+
+/// Area of an inline dialog `height` rows tall starting at row `top`, and
+/// the lines the screen must scroll up first to fit it
+///
+/// The dialog is cut to the screen height; `top` past the screen (an unknown
+/// cursor row, or a screen that shrank) puts it at the bottom.
+pub fn inline_area(screen: Size, top: u16, height: u16) -> (Rect, u16) {
+    let height = height.min(screen.height);
+    let top = top.min(screen.height);
+    let scroll = (top + height).saturating_sub(screen.height);
+    let area = Rect {
+        x: 0,
+        y: top - scroll,
+        width: screen.width,
+        height,
+    };
+    (area, scroll)
+}
+
+/// Scroll the screen up `lines` lines, moving its top lines into the scrollback
+pub fn scroll_up(out: &mut impl Write, screen: Size, lines: u16) -> io::Result<()> {
+    if lines == 0 {
+        return Ok(());
+    }
+    // From the last row; in raw mode a line feed moves down without returning
+    write!(out, "\x1b[{};1H", screen.height)?;
+    write!(out, "{}", "\n".repeat(lines as usize))?;
+    out.flush()
+}
+
+/// Clear the rows from `top` to the bottom and leave the cursor at `top`
+pub fn clear_rows(out: &mut impl Write, screen: Size, top: u16) -> io::Result<()> {
+    let top = top.min(screen.height.saturating_sub(1));
+    for row in top..screen.height {
+        write!(out, "\x1b[{};1H\x1b[2K", row + 1)?;
+    }
+    write!(out, "\x1b[{};1H", top + 1)?;
+    out.flush()
+}
+
+/// A terminal mode set while opening the dialogs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// Raw mode
+    RawMode,
+    /// The alternate screen, mouse capture, title and cursor shape ([`enter`]
+    /// and [`integrate`])
+    Screen,
+}
+
+/// Undo `step` on the controlling terminal
+pub fn undo(step: Step) {
+    match step {
+        Step::RawMode => {
+            let _ = disable_raw_mode();
+        }
+        Step::Screen => {
+            if let Ok(mut tty) = std::fs::OpenOptions::new().write(true).open("/dev/tty") {
+                let _ = leave(&mut tty);
+            }
+        }
+    }
+}
+
+/// The steps of setting up the terminal taken so far, undone newest first
+/// when it is dropped unless [`disarm`](Self::disarm)ed
+///
+/// A step counts as taken once it is started, so one that fails halfway
+/// (the alternate screen entered, mouse capture not) is undone too.
+pub struct TerminalGuard<F: FnMut(Step)> {
+    taken: Vec<Step>,
+    undo: F,
+}
+
+impl<F: FnMut(Step)> TerminalGuard<F> {
+    /// A guard undoing with `undo` ([`undo`] for the real terminal)
+    pub fn new(undo: F) -> Self {
+        Self {
+            taken: Vec::new(),
+            undo,
+        }
+    }
+
+    /// Take `step` by running `apply`, failing with `hint` as the context
+    pub fn apply<E>(
+        &mut self,
+        step: Step,
+        hint: &'static str,
+        apply: impl FnOnce() -> Result<(), E>,
+    ) -> anyhow::Result<()>
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        self.taken.push(step);
+        anyhow::Context::context(apply(), hint)
+    }
+
+    /// Keep the steps taken: setting up succeeded
+    pub fn disarm(mut self) {
+        self.taken.clear();
+    }
+}
+
+impl<F: FnMut(Step)> Drop for TerminalGuard<F> {
+    fn drop(&mut self) {
+        while let Some(step) = self.taken.pop() {
+            (self.undo)(step);
+        }
+    }
+}
+
+/// Restore the terminal before the panic message is printed
+pub fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            if ACTIVE.load(Ordering::SeqCst) {
+                let _ = disable_raw_mode();
+                if let Ok(mut tty) = std::fs::OpenOptions::new().write(true).open("/dev/tty") {
+                    let _ = leave(&mut tty);
+                    let _ = write!(tty, "\x1b[?25h"); // Show cursor
+                }
+            }
+            previous(info);
+        }));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_title_and_cursor_restored_once() {
+        let mut out = Vec::new();
+        enter(&mut out, true).unwrap();
+        integrate(&mut out, "opencode — ask", Some(CursorShape::Bar)).unwrap();
+        // A second dialog (or re-entering after the editor) keeps the saved title
+        integrate(&mut out, "opencode — confirm\x07", None).unwrap();
+        leave(&mut out).unwrap();
+        leave(&mut out).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\x1b[?1049h\x1b[?1000h\x1b[?1004h\
+             \x1b[22;0t\x1b]0;opencode — ask\x07\x1b[6 q\
+             \x1b]0;opencode — confirm\x07\
+             \x1b[0 q\x1b[23;0t\x1b[?1004l\x1b[?1000l\x1b[?1049l\
+             \x1b[?1000l\x1b[?1049l"
+        );
+    }
+
+    #[test]
+    fn test_guard_undoes_steps_taken() {
+        let undone = std::cell::RefCell::new(Vec::new());
+        let fail = || Err(io::Error::other("no terminal"));
+        let result = {
+            let mut guard = TerminalGuard::new(|step| undone.borrow_mut().push(step));
+            guard
+                .apply(Step::RawMode, "raw mode", || Ok::<_, io::Error>(()))
+                .and_then(|()| guard.apply(Step::Screen, "screen", fail))
+        };
+        assert_eq!(format!("{:#}", result.unwrap_err()), "screen: no terminal");
+        assert_eq!(*undone.borrow(), [Step::Screen, Step::RawMode]);
+
+        // Nothing is undone once set up, nor when the first step fails
+        undone.borrow_mut().clear();
+        let mut guard = TerminalGuard::new(|step| undone.borrow_mut().push(step));
+        guard
+            .apply(Step::RawMode, "raw mode", || Ok::<_, io::Error>(()))
+            .unwrap();
+        guard.disarm();
+        assert!(undone.borrow().is_empty());
+        let mut guard = TerminalGuard::new(|step| undone.borrow_mut().push(step));
+        assert!(guard.apply(Step::RawMode, "raw mode", fail).is_err());
+        drop(guard);
+        assert_eq!(*undone.borrow(), [Step::RawMode]);
+    }
+
+    #[test]
+    fn test_inline_rows() {
+        let screen = Size::new(80, 24);
+        // Room below the cursor
+        assert_eq!(inline_area(screen, 3, 6), (Rect::new(0, 3, 80, 6), 0));
+        assert_eq!(inline_area(screen, 18, 6), (Rect::new(0, 18, 80, 6), 0));
+        // Scrolled up to fit, or at the bottom when the row is unknown
+        assert_eq!(inline_area(screen, 20, 6), (Rect::new(0, 18, 80, 6), 2));
+        assert_eq!(
+            inline_area(screen, u16::MAX, 6),
+            (Rect::new(0, 18, 80, 6), 6)
+        );
+        // Never taller than the screen
+        assert_eq!(inline_area(screen, 5, 40), (Rect::new(0, 0, 80, 24), 5));
+
+        let mut out = Vec::new();
+        scroll_up(&mut out, screen, 3).unwrap();
+        scroll_up(&mut out, screen, 0).unwrap();
+        clear_rows(&mut out, screen, 22).unwrap();
+        clear_rows(&mut out, screen, 30).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\x1b[24;1H\n\n\n\
+             \x1b[23;1H\x1b[2K\x1b[24;1H\x1b[2K\x1b[23;1H\
+             \x1b[24;1H\x1b[2K\x1b[24;1H"
+        );
+    }
+}
@@ -1,8 +1,11 @@
 //! Visual themes for the TUI
 
-use ratatui::style::Color;
+use ratatui::buffer::Buffer;
+use ratatui::style::{Color, Modifier};
 use ratatui::widgets::BorderType;
 
+use crate::config::CursorShape;
+
 /// Available UI themes
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum ThemeKind {
@@ -15,6 +18,8 @@ pub enum ThemeKind {
     Matrix,
     /// Retro CRT amber theme
     Crt,
+    /// Bold white on black, for low vision and screen magnifiers
+    HighContrast,
 }
 
 impl ThemeKind {
@@ -25,6 +30,7 @@ impl ThemeKind {
             "hacker" | "hack" | "cyber" => Self::Hacker,
             "matrix" | "neo" => Self::Matrix,
             "crt" | "retro" | "amber" => Self::Crt,
+            "high-contrast" | "highcontrast" | "contrast" | "hc" => Self::HighContrast,
             _ => Self::default(),
         }
     }
@@ -36,6 +42,7 @@ impl ThemeKind {
             Self::Hacker => Theme::hacker(),
             Self::Matrix => Theme::matrix(),
             Self::Crt => Theme::crt(),
+            Self::HighContrast => Theme::high_contrast(),
         }
     }
 }
@@ -53,6 +60,8 @@ pub struct Theme {
     pub warning: Color,
     /// Error/cancel color
     pub error: Color,
+    /// Success color (added lines of a diff)
+    pub success: Color,
     /// Dimmed text color
     pub dim: Color,
     /// Normal text color
@@ -71,6 +80,14 @@ pub struct Theme {
     pub unselected_prefix: String,
     /// Border style: "rounded", "double", "thick", "plain"
     pub border_style: &'static str,
+    /// Background for cells that don't set one (None: the terminal's own)
+    pub background: Option<Color>,
+    /// Draw all text bold
+    pub bold: bool,
+    /// Terminal window title, followed by the dialog name ("opencode — ask")
+    pub title_text: String,
+    /// Terminal cursor shape (None: leave it alone)
+    pub cursor_shape: Option<CursorShape>,
 }
 
 impl Default for Theme {
@@ -88,6 +105,7 @@ impl Theme {
             accent: Color::Magenta,
             warning: Color::Yellow,
             error: Color::Red,
+            success: Color::Green,
             dim: Color::DarkGray,
             text: Color::White,
             input: Color::White,
@@ -97,6 +115,10 @@ impl Theme {
             selected_prefix: "> ".to_string(),
             unselected_prefix: "  ".to_string(),
             border_style: "rounded",
+            background: None,
+            bold: false,
+            title_text: "opencode".to_string(),
+            cursor_shape: Some(CursorShape::Bar),
         }
     }
 
@@ -108,6 +130,7 @@ impl Theme {
             accent: Color::Rgb(255, 0, 255),    // Magenta
             warning: Color::Rgb(255, 170, 0),   // Amber
             error: Color::Rgb(255, 50, 50),     // Red
+            success: Color::Rgb(120, 255, 120), // Pale green
             dim: Color::Rgb(0, 140, 0),         // Dark green
             text: Color::Rgb(0, 230, 0),        // Light green
             input: Color::Rgb(0, 255, 0),       // Bright green
@@ -117,6 +140,10 @@ impl Theme {
             selected_prefix: "▸ ".to_string(),
             unselected_prefix: "  ".to_string(),
             border_style: "thick",
+            background: None,
+            bold: false,
+            title_text: "opencode".to_string(),
+            cursor_shape: Some(CursorShape::Bar),
         }
     }
 
@@ -128,6 +155,7 @@ impl Theme {
             accent: Color::Rgb(150, 255, 150), // Light green
             warning: Color::Rgb(200, 255, 0),  // Yellow-green
             error: Color::Rgb(255, 100, 100),  // Soft red
+            success: Color::Rgb(90, 255, 90),  // Light green
             dim: Color::Rgb(0, 80, 0),         // Very dark green
             text: Color::Rgb(0, 180, 0),       // Medium green
             input: Color::Rgb(0, 255, 0),      // Bright green
@@ -137,6 +165,10 @@ impl Theme {
             selected_prefix: "█ ".to_string(),
             unselected_prefix: "░ ".to_string(),
             border_style: "thick",
+            background: None,
+            bold: false,
+            title_text: "opencode".to_string(),
+            cursor_shape: Some(CursorShape::Bar),
         }
     }
 
@@ -148,6 +180,7 @@ impl Theme {
             accent: Color::Rgb(255, 220, 100),   // Pale amber
             warning: Color::Rgb(255, 255, 0),    // Yellow
             error: Color::Rgb(255, 100, 0),      // Orange-red
+            success: Color::Rgb(190, 220, 60),   // Yellow-green
             dim: Color::Rgb(140, 90, 0),         // Dark amber
             text: Color::Rgb(255, 170, 0),       // Amber
             input: Color::Rgb(255, 200, 50),     // Light amber
@@ -157,6 +190,53 @@ impl Theme {
             selected_prefix: "=> ".to_string(),
             unselected_prefix: "   ".to_string(),
             border_style: "double",
+            background: None,
+            bold: false,
+            title_text: "opencode".to_string(),
+            cursor_shape: Some(CursorShape::Bar),
+        }
+    }
+
+    /// Bold white on black; warnings stay yellow so they remain distinguishable
+    pub fn high_contrast() -> Self {
+        Self {
+            primary: Color::White,
+            secondary: Color::White,
+            accent: Color::White,
+            warning: Color::Yellow,
+            error: Color::White,
+            success: Color::White,
+            dim: Color::White,
+            text: Color::White,
+            input: Color::White,
+            title: " OPENCODE ".to_string(),
+            prompt: "> ".to_string(),
+            filter_prompt: "/ ".to_string(),
+            selected_prefix: "> ".to_string(),
+            unselected_prefix: "  ".to_string(),
+            border_style: "thick",
+            background: Some(Color::Black),
+            bold: true,
+            title_text: "opencode".to_string(),
+            cursor_shape: Some(CursorShape::Block),
+        }
+    }
+
+    /// Apply the theme's background and bold text to a drawn frame
+    /// Cells with their own background (e.g. the selected row) keep it.
+    pub fn apply_emphasis(&self, buffer: &mut Buffer) {
+        if self.background.is_none() && !self.bold {
+            return;
+        }
+        for cell in buffer.content.iter_mut() {
+            if let Some(background) = self.background {
+                if cell.bg == Color::Reset {
+                    cell.set_bg(background);
+                }
+            }
+            if self.bold {
+                cell.modifier.insert(Modifier::BOLD);
+            }
         }
     }
 
@@ -194,5 +274,29 @@ mod tests {
         let minimal = ThemeKind::Minimal.config();
         assert_eq!(minimal.border_style, "rounded");
         assert_eq!(minimal.prompt, "> ");
+
+        assert_eq!(
+            ThemeKind::from_str("high-contrast"),
+            ThemeKind::HighContrast
+        );
+        let high_contrast = ThemeKind::HighContrast.config();
+        assert_eq!(high_contrast.background, Some(Color::Black));
+        assert!(high_contrast.bold);
+    }
+
+    #[test]
+    fn test_apply_emphasis() {
+        use ratatui::layout::Rect;
+
+        let mut buffer = Buffer::empty(Rect::new(0, 0, 2, 1));
+        buffer[(1, 0)].set_bg(Color::White);
+        ThemeKind::HighContrast.config().apply_emphasis(&mut buffer);
+        assert_eq!(buffer[(0, 0)].bg, Color::Black);
+        assert_eq!(buffer[(1, 0)].bg, Color::White);
+        assert!(buffer[(1, 0)].modifier.contains(Modifier::BOLD));
+
+        let mut plain = Buffer::empty(Rect::new(0, 0, 2, 1));
+        Theme::hacker().apply_emphasis(&mut plain);
+        assert_eq!(plain, Buffer::empty(Rect::new(0, 0, 2, 1)));
     }
 }
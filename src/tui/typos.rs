@@ -0,0 +1,145 @@
+//! `@words` in the ask input that nothing will expand
+//!
+//! A typo like `@buffr` or `@slection` is sent to opencode as typed, so the
+//! dialog underlines such words and asks before sending them. A word is as
+//! [`context::mention_at`] finds it, so `a@b.com`, `@Override` and a file
+//! reference like `@src/main.rs` are left alone, and so is anything in a
+//! code span or fenced block and an `@cmd:` command.
+
+use std::ops::Range;
+
+use crate::cmd;
+use crate::context::{self, PlaceholderKind};
+
+/// Whether `word` (with its `@`) is a placeholder of [`PlaceholderKind::ALL`],
+/// any `@diff:N` included
+pub fn is_placeholder(word: &str) -> bool {
+    if let Some(limit) = word.strip_prefix("@diff:") {
+        return !limit.is_empty() && limit.bytes().all(|b| b.is_ascii_digit());
+    }
+    PlaceholderKind::ALL.iter().any(|kind| kind.name() == word)
+}
+
+/// Byte ranges of the `@words` of `text` that `is_known` doesn't know
+pub fn unknown_words(text: &str, is_known: impl Fn(&str) -> bool) -> Vec<Range<usize>> {
+    let mut unknown = Vec::new();
+    let mut fenced = false;
+    for piece in cmd::split(text) {
+        let cmd::Piece::Text(piece) = piece else {
+            continue;
+        };
+        // Pieces are slices of `text`
+        let mut offset = piece.as_ptr() as usize - text.as_ptr() as usize;
+        for line in piece.split_inclusive('\n') {
+            let trimmed = line.trim_start();
+            if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                fenced = !fenced;
+            } else if !fenced {
+                unknown.extend(
+                    line_words(line)
+                        .filter(|range| !is_known(&line[range.clone()]))
+                        .map(|range| offset + range.start..offset + range.end),
+                );
+            }
+            offset += line.len();
+        }
+    }
+    unknown
+}
+
+/// Ranges of the candidate `@words` of one line, outside code spans
+fn line_words(line: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+    let mut in_code = false;
+    line.char_indices().filter_map(move |(i, c)| {
+        if c == '`' {
+            in_code = !in_code;
+            return None;
+        }
+        if in_code || c != '@' {
+            return None;
+        }
+        let word = context::mention_at(line, i)?;
+        Some(i..i + word.len())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unknown(text: &str) -> Vec<&str> {
+        let agents = ["build", "code-review"];
+        unknown_words(text, |word| {
+            is_placeholder(word) || word == "@reg:a" || agents.contains(&&word[1..])
+        })
+        .into_iter()
+        .map(|range| &text[range])
+        .collect()
+    }
+
+    #[test]
+    fn test_is_placeholder() {
+        for word in ["@this", "@selection:full", "@diff", "@diff:50", "@diffstat"] {
+            assert!(is_placeholder(word), "{}", word);
+        }
+        for word in [
+            "@buffr",
+            "@diff:",
+            "@diff:5x",
+            "@diff:200x",
+            "@reg:a",
+            "buffer",
+        ] {
+            assert!(!is_placeholder(word), "{}", word);
+        }
+    }
+
+    #[test]
+    fn test_unknown_words() {
+        assert_eq!(unknown("fix @buffr and @slection"), ["@buffr", "@slection"]);
+        assert_eq!(unknown("explain @this, then @buffer."), Vec::<&str>::new());
+        // Agents, registers given, and limits
+        assert_eq!(
+            unknown("@build @code-review @reg:a @reg:b @diff:30 @diff:x"),
+            ["@reg:b", "@diff:x"]
+        );
+        // `@this:` isn't expanded, so it is flagged
+        assert_eq!(unknown("(@this) [@thsi] \"@this:\""), ["@thsi", "@this:"]);
+        assert_eq!(unknown("at line end @selectoin\nnext"), ["@selectoin"]);
+    }
+
+    #[test]
+    fn test_unknown_words_leaves_alone() {
+        for text in [
+            // Emails and handles inside words
+            "mail me at dev@example.com or x@buffr",
+            // Decorators and annotations start uppercase
+            "why does @Override fail here? @Test too",
+            // Not words: @ alone, @@, digits
+            "a @ b @@buffer @1",
+            // Code spans and fenced blocks
+            "in `@property def x` and ``@decorator``",
+            "look:\n```python\n@dataclass\n@functools.cache\n```\n",
+            "~~~\n@lru_cache\n~~~",
+            // File references
+            "see @src/main.rs and @README.md or @docs/",
+            // A command runs as typed, @words and all
+            "@cmd:grep -n @foo src",
+        ] {
+            assert_eq!(unknown(text), Vec::<&str>::new(), "{}", text);
+        }
+        // Flagged again after the block and the command
+        assert_eq!(unknown("```\n@x\n```\n@buffr"), ["@buffr"]);
+        assert_eq!(unknown("@cmd:ls @a\n@buffr"), ["@buffr"]);
+        assert_eq!(unknown("`@a` @buffr `@b"), ["@buffr"]);
+    }
+
+    #[test]
+    fn test_unknown_word_offsets() {
+        let text = "日本 @buffr\n```\n```\n@x";
+        let ranges = unknown_words(text, is_placeholder);
+        assert_eq!(ranges.len(), 2);
+        assert_eq!(&text[ranges[0].clone()], "@buffr");
+        assert_eq!(&text[ranges[1].clone()], "@x");
+    }
+}
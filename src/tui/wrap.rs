@@ -0,0 +1,467 @@
+//! Soft wrapping of the ask dialog's input
+//!
+//! The layout is computed once per frame and shared by drawing, cursor
+//! placement and scrolling. Visual lines are byte ranges into the input, so
+//! rewrapping into a reused buffer doesn't allocate.
+//!
+//! Columns are display columns from the start of a visual line. A tab
+//! reaches the next tab stop; it is drawn as that many spaces
+//! ([`expand_tabs`]) while the input keeps the tab, so wrapping, drawing and
+//! the cursor agree on where it ends. A tab (or wide character) that would
+//! cross the end of a visual line starts the next one.
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+use unicode_width::UnicodeWidthChar;
+
+/// A visual line after soft wrapping
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WrappedLine {
+    /// Byte range of this visual line in the input (without the newline)
+    pub range: Range<usize>,
+    /// The logical line index this belongs to
+    pub logical_line: usize,
+    /// Whether this is the first visual line of the logical line
+    pub is_first: bool,
+}
+
+impl WrappedLine {
+    /// The text of this visual line
+    pub fn text<'a>(&self, input: &'a str) -> &'a str {
+        &input[self.range.clone()]
+    }
+}
+
+/// Columns `c` takes when drawn at column `col`
+fn char_width(c: char, col: usize, tab_width: usize) -> usize {
+    match c {
+        '\t' => tab_width - col % tab_width,
+        c => c.width().unwrap_or(0),
+    }
+}
+
+/// Columns `text` takes from the start of a line
+pub fn columns(text: &str, tab_width: usize) -> usize {
+    text.chars()
+        .fold(0, |col, c| col + char_width(c, col, tab_width))
+}
+
+/// `text` as drawn from column `start`, its tabs replaced by spaces to the
+/// next tab stop (a newline goes back to column 0)
+pub fn expand_tabs(text: &str, start: usize, tab_width: usize) -> Cow<'_, str> {
+    if !text.contains('\t') {
+        return Cow::Borrowed(text);
+    }
+    let mut expanded = String::with_capacity(text.len() + tab_width);
+    let mut col = start;
+    for c in text.chars() {
+        match c {
+            '\t' => {
+                let width = char_width(c, col, tab_width);
+                expanded.extend(std::iter::repeat_n(' ', width));
+                col += width;
+            }
+            '\n' => {
+                expanded.push(c);
+                col = 0;
+            }
+            c => {
+                expanded.push(c);
+                col += char_width(c, col, tab_width);
+            }
+        }
+    }
+    Cow::Owned(expanded)
+}
+
+/// Wrap text to fit within a given width, respecting logical line breaks
+#[cfg(test)]
+pub fn wrap_text(
+    text: &str,
+    width: usize,
+    prefix_width: usize,
+    tab_width: usize,
+) -> Vec<WrappedLine> {
+    let mut wrapped = Vec::new();
+    wrap_into(text, width, prefix_width, tab_width, &mut wrapped);
+    wrapped
+}
+
+/// Like [`wrap_text`], but reusing `wrapped`'s allocation
+pub fn wrap_into(
+    text: &str,
+    width: usize,
+    prefix_width: usize,
+    tab_width: usize,
+    wrapped: &mut Vec<WrappedLine>,
+) {
+    wrapped.clear();
+    // First line has the prompt, others an indent of the same width
+    let effective_width = width.saturating_sub(prefix_width);
+    let mut byte_offset = 0;
+
+    for (logical_line, line) in text.split('\n').enumerate() {
+        let end = byte_offset + line.len();
+        if line.is_empty() || effective_width == 0 {
+            // Empty line, or too narrow to wrap
+            wrapped.push(WrappedLine {
+                range: byte_offset..end,
+                logical_line,
+                is_first: true,
+            });
+        } else {
+            let mut start = byte_offset;
+            let mut col = 0;
+            for (i, c) in line.char_indices() {
+                if col > 0 && col + char_width(c, col, tab_width) > effective_width {
+                    wrapped.push(WrappedLine {
+                        range: start..byte_offset + i,
+                        logical_line,
+                        is_first: start == byte_offset,
+                    });
+                    start = byte_offset + i;
+                    col = 0;
+                }
+                col += char_width(c, col, tab_width);
+            }
+            wrapped.push(WrappedLine {
+                range: start..end,
+                logical_line,
+                is_first: start == byte_offset,
+            });
+        }
+        byte_offset = end + 1; // +1 for newline
+    }
+}
+
+/// Visual row of `row`'s `pos`, with its column
+fn visual_pos(
+    wrapped: &[WrappedLine],
+    input: &str,
+    row: usize,
+    pos: usize,
+    tab_width: usize,
+) -> (usize, usize) {
+    let start = wrapped[row].range.start;
+    (row, columns(&input[start..pos], tab_width))
+}
+
+/// Find the visual row and column for a cursor position (bytes into `input`)
+pub fn cursor_to_visual_pos(
+    wrapped: &[WrappedLine],
+    input: &str,
+    cursor_pos: usize,
+    tab_width: usize,
+) -> (usize, usize) {
+    match wrapped
+        .iter()
+        .position(|line| line.range.contains(&cursor_pos) || line.range.end == cursor_pos)
+    {
+        Some(row) => visual_pos(wrapped, input, row, cursor_pos, tab_width),
+        // Cursor is at the end
+        None => match wrapped.last() {
+            Some(last) => visual_pos(wrapped, input, wrapped.len() - 1, last.range.end, tab_width),
+            None => (0, 0),
+        },
+    }
+}
+
+/// Visual row and column of the character at `pos`
+///
+/// Unlike the cursor, which stays at the end of a row at a wrap point, a
+/// character there is at the start of the next row.
+pub fn char_to_visual_pos(
+    wrapped: &[WrappedLine],
+    input: &str,
+    pos: usize,
+    tab_width: usize,
+) -> (usize, usize) {
+    match wrapped.iter().position(|line| line.range.contains(&pos)) {
+        Some(row) => visual_pos(wrapped, input, row, pos, tab_width),
+        None => cursor_to_visual_pos(wrapped, input, pos, tab_width),
+    }
+}
+
+/// Cursor position at a visual row and column
+///
+/// The row is clamped to the layout; a column past the end of the row is
+/// its end, and one inside a tab (or wide character) is before it.
+pub fn visual_pos_to_cursor(
+    wrapped: &[WrappedLine],
+    input: &str,
+    row: usize,
+    col: usize,
+    tab_width: usize,
+) -> usize {
+    let Some(line) = wrapped.get(row).or(wrapped.last()) else {
+        return 0;
+    };
+    let mut at = 0;
+    for (i, c) in line.text(input).char_indices() {
+        at += char_width(c, at, tab_width);
+        if at > col {
+            return line.range.start + i;
+        }
+    }
+    line.range.end
+}
+
+/// Update scroll offset to keep the cursor's visual row visible
+pub fn scroll_to_row(visual_row: usize, scroll_offset: &mut usize, visible_lines: usize) {
+    if visual_row < *scroll_offset {
+        *scroll_offset = visual_row;
+    } else if visual_row >= *scroll_offset + visible_lines {
+        *scroll_offset = visual_row + 1 - visible_lines;
+    }
+}
+
+/// The visible rows of the input: following the cursor, or scrolled
+/// freely with Ctrl+Up/Down until the next edit
+#[derive(Debug, Default)]
+pub struct InputScroll {
+    /// First visible row
+    pub offset: usize,
+    /// Scrolled by hand, so the cursor may be out of view
+    free: bool,
+}
+
+impl InputScroll {
+    /// Place the view for this frame
+    ///
+    /// A free view only moves to keep the cursor within a page of it, and
+    /// never past the last row.
+    pub fn update(&mut self, cursor_row: usize, total_rows: usize, visible: usize) {
+        if self.free {
+            let nearest = (cursor_row + 1).saturating_sub(visible * 2);
+            let furthest = (cursor_row + visible).min(total_rows.saturating_sub(visible));
+            self.offset = self.offset.min(furthest).max(nearest);
+        } else {
+            scroll_to_row(cursor_row, &mut self.offset, visible);
+        }
+    }
+
+    /// Scroll the view by `rows` without moving the cursor
+    pub fn scroll_by(&mut self, rows: isize, cursor_row: usize, total_rows: usize, visible: usize) {
+        self.free = true;
+        self.offset = self.offset.saturating_add_signed(rows);
+        self.update(cursor_row, total_rows, visible);
+    }
+
+    /// Go back to following the cursor (on an edit or cursor move)
+    pub fn follow(&mut self) {
+        self.free = false;
+    }
+
+    /// Whether `row` is on screen
+    pub fn shows(&self, row: usize, visible: usize) -> bool {
+        (self.offset..self.offset + visible).contains(&row)
+    }
+
+    /// Whether rows are hidden above and below the view
+    pub fn more(&self, total_rows: usize, visible: usize) -> (bool, bool) {
+        (self.offset > 0, self.offset + visible < total_rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_text_no_wrap_needed() {
+        let text = "hello";
+        let wrapped = wrap_text(text, 20, 2, 4);
+        assert_eq!(wrapped.len(), 1);
+        assert_eq!(wrapped[0].text(text), "hello");
+        assert_eq!(wrapped[0].logical_line, 0);
+        assert!(wrapped[0].is_first);
+    }
+
+    #[test]
+    fn test_wrap_text_single_wrap() {
+        let text = "hello world foo bar";
+        let wrapped = wrap_text(text, 12, 2, 4); // effective width = 10
+        assert_eq!(wrapped.len(), 2);
+        assert_eq!(wrapped[0].text(text), "hello worl");
+        assert!(wrapped[0].is_first);
+        assert_eq!(wrapped[1].text(text), "d foo bar");
+        assert!(!wrapped[1].is_first);
+    }
+
+    #[test]
+    fn test_wrap_text_with_newlines() {
+        let text = "hello\nworld";
+        let wrapped = wrap_text(text, 20, 2, 4);
+        assert_eq!(wrapped.len(), 2);
+        assert_eq!(wrapped[0].text(text), "hello");
+        assert_eq!(wrapped[0].logical_line, 0);
+        assert_eq!(wrapped[1].text(text), "world");
+        assert_eq!(wrapped[1].logical_line, 1);
+    }
+
+    #[test]
+    fn test_wrap_text_edge_cases() {
+        assert_eq!(
+            wrap_text("", 20, 2, 4),
+            [WrappedLine {
+                range: 0..0,
+                logical_line: 0,
+                is_first: true
+            }]
+        );
+
+        // Exact fit doesn't leave an empty continuation; multi-byte chars split on boundaries
+        let text = "ééé\n\nab";
+        let wrapped = wrap_text(text, 5, 2, 4);
+        let texts: Vec<&str> = wrapped.iter().map(|l| l.text(text)).collect();
+        assert_eq!(texts, ["ééé", "", "ab"]);
+
+        let mut reused = wrap_text("one\ntwo\nthree", 20, 2, 4);
+        wrap_into(text, 4, 2, 4, &mut reused);
+        let texts: Vec<&str> = reused.iter().map(|l| l.text(text)).collect();
+        assert_eq!(texts, ["éé", "é", "", "ab"]);
+    }
+
+    #[test]
+    fn test_cursor_to_visual_pos_no_wrap() {
+        let text = "hello";
+        let (row, col) = cursor_to_visual_pos(&wrap_text(text, 20, 2, 4), text, 3, 4);
+        assert_eq!(row, 0);
+        assert_eq!(col, 3);
+    }
+
+    #[test]
+    fn test_cursor_to_visual_pos_with_wrap() {
+        let text = "hello world foo bar";
+        // With width=12, prefix=2, effective=10, wraps to:
+        // Line 0: "hello worl" (pos 0-10)
+        // Line 1: "d foo bar" (pos 10-19)
+        let (row, col) = cursor_to_visual_pos(&wrap_text(text, 12, 2, 4), text, 12, 4);
+        assert_eq!(row, 1);
+        assert_eq!(col, 2); // "d " = 2 chars into the wrapped line
+    }
+
+    #[test]
+    fn test_char_to_visual_pos() {
+        let text = "hello worl@ foo\n@x";
+        // "hello worl" / "@ foo" / "@x"
+        let wrapped = wrap_text(text, 12, 2, 4);
+        assert_eq!(cursor_to_visual_pos(&wrapped, text, 10, 4), (0, 10));
+        assert_eq!(char_to_visual_pos(&wrapped, text, 10, 4), (1, 0));
+        assert_eq!(char_to_visual_pos(&wrapped, text, 16, 4), (2, 0));
+        assert_eq!(char_to_visual_pos(&wrapped, text, 3, 4), (0, 3));
+        // Past the end, like the cursor
+        assert_eq!(char_to_visual_pos(&wrapped, text, text.len(), 4), (2, 2));
+    }
+
+    #[test]
+    fn test_visual_pos_to_cursor() {
+        let text = "hello world foo bar\nab";
+        let wrapped = wrap_text(text, 12, 2, 4);
+        // "hello worl" / "d foo bar" / "ab"
+        assert_eq!(visual_pos_to_cursor(&wrapped, text, 1, 2, 4), 12);
+        assert_eq!(visual_pos_to_cursor(&wrapped, text, 2, 5, 4), text.len());
+        assert_eq!(visual_pos_to_cursor(&wrapped, text, 9, 0, 4), 20);
+
+        // Columns, not bytes
+        let text = "éa";
+        let wrapped = wrap_text(text, 20, 2, 4);
+        assert_eq!(visual_pos_to_cursor(&wrapped, text, 0, 1, 4), 2);
+        assert_eq!(cursor_to_visual_pos(&wrapped, text, 2, 4), (0, 1));
+        assert_eq!(visual_pos_to_cursor(&[], "", 3, 3, 4), 0);
+    }
+
+    #[test]
+    fn test_tabs() {
+        // At the start of a line, and mid-line to the next stop
+        let text = "\tif x {\n\treturn\ta;";
+        assert_eq!(expand_tabs(text, 0, 4), "    if x {\n    return  a;");
+        assert_eq!(expand_tabs("ab\tc", 1, 4), "ab c");
+        assert!(matches!(expand_tabs("no tabs", 0, 4), Cow::Borrowed(_)));
+        assert_eq!(columns("ab\tc", 4), 5);
+        assert_eq!(columns("ab\tc", 8), 9);
+
+        let wrapped = wrap_text(text, 40, 2, 4);
+        assert_eq!(cursor_to_visual_pos(&wrapped, text, 1, 4), (0, 4));
+        // After "\treturn" (column 10), the tab reaches 12
+        let a = text.rfind('a').unwrap();
+        assert_eq!(cursor_to_visual_pos(&wrapped, text, a, 4), (1, 12));
+        // Inside the tab is before it
+        assert_eq!(visual_pos_to_cursor(&wrapped, text, 1, 11, 4), a - 1);
+        assert_eq!(visual_pos_to_cursor(&wrapped, text, 1, 12, 4), a);
+        assert_eq!(char_to_visual_pos(&wrapped, text, a - 1, 4), (1, 10));
+    }
+
+    #[test]
+    fn test_tab_at_wrap() {
+        // Effective width 10: "abcdefgh" reaches column 8, and the tab
+        // would end at 12, so it starts the next row
+        let text = "abcdefgh\tij";
+        let wrapped = wrap_text(text, 12, 2, 4);
+        let texts: Vec<&str> = wrapped.iter().map(|l| l.text(text)).collect();
+        assert_eq!(texts, ["abcdefgh", "\tij"]);
+        assert_eq!(cursor_to_visual_pos(&wrapped, text, 8, 4), (0, 8));
+        assert_eq!(char_to_visual_pos(&wrapped, text, 8, 4), (1, 0));
+        assert_eq!(cursor_to_visual_pos(&wrapped, text, 9, 4), (1, 4));
+        assert_eq!(expand_tabs(wrapped[1].text(text), 0, 4), "    ij");
+
+        // One within the row counts its expanded width
+        let text = "abcdef\tghij";
+        let texts: Vec<String> = wrap_text(text, 12, 2, 4)
+            .iter()
+            .map(|l| l.text(text).to_string())
+            .collect();
+        assert_eq!(texts, ["abcdef\tgh", "ij"]);
+        // Wider than a row: alone on it
+        let texts: Vec<String> = wrap_text("a\tb", 5, 2, 8)
+            .iter()
+            .map(|l| l.text("a\tb").to_string())
+            .collect();
+        assert_eq!(texts, ["a", "\t", "b"]);
+    }
+
+    #[test]
+    fn test_scroll_to_row() {
+        let mut scroll = 0;
+        scroll_to_row(6, &mut scroll, 5);
+        assert_eq!(scroll, 2);
+        scroll_to_row(1, &mut scroll, 5);
+        assert_eq!(scroll, 1);
+    }
+
+    #[test]
+    fn test_input_scroll_free() {
+        let mut scroll = InputScroll::default();
+        // Following: the cursor on row 6 of 20 shows rows 2-6
+        scroll.update(6, 20, 5);
+        assert_eq!(scroll.offset, 2);
+        assert_eq!(scroll.more(20, 5), (true, true));
+
+        // Scrolled down, at most a page past the cursor
+        scroll.scroll_by(3, 6, 20, 5);
+        assert_eq!(scroll.offset, 5);
+        assert!(scroll.shows(6, 5));
+        scroll.scroll_by(10, 6, 20, 5);
+        assert_eq!(scroll.offset, 11);
+        assert!(!scroll.shows(6, 5));
+        // ... and a page before it
+        scroll.scroll_by(-20, 6, 20, 5);
+        assert_eq!(scroll.offset, 0);
+        scroll.update(12, 20, 5);
+        assert_eq!(scroll.offset, 3);
+
+        // Never past the end
+        scroll.scroll_by(20, 19, 20, 5);
+        assert_eq!(scroll.offset, 15);
+        assert_eq!(scroll.more(20, 5), (true, false));
+
+        // An edit brings the cursor back into view
+        scroll.update(2, 20, 5);
+        assert_eq!(scroll.offset, 7);
+        scroll.follow();
+        scroll.update(2, 20, 5);
+        assert_eq!(scroll.offset, 2);
+        assert!(scroll.shows(2, 5));
+    }
+}
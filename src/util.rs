@@ -0,0 +1,202 @@
+//! Small helpers shared across modules
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Shorten `s` to at most `max_cols` terminal columns for single-line display
+///
+/// Cuts between grapheme clusters (so an emoji sequence or accented letter is
+/// never split) and ends with "…" when anything was dropped. Newlines, tabs
+/// and other control characters are shown as visible symbols, since they
+/// would otherwise break the line or move the cursor.
+pub fn display_truncate(s: &str, max_cols: usize) -> String {
+    let visible: String = s.chars().map(visible_char).collect();
+    if visible.width() <= max_cols {
+        return visible;
+    }
+
+    // Leave a column for the ellipsis
+    let room = max_cols.saturating_sub(1);
+    let mut out = String::new();
+    let mut width = 0;
+    for grapheme in visible.graphemes(true) {
+        let w = grapheme.width();
+        if width + w > room {
+            break;
+        }
+        out.push_str(grapheme);
+        width += w;
+    }
+    if max_cols > 0 {
+        out.push('…');
+    }
+    out
+}
+
+/// Format a count with thousands separators (7200 -> "7,200")
+pub fn thousands(n: usize) -> String {
+    let digits = n.to_string();
+    let mut result = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            result.push(',');
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// `bytes` for people: `512 B`, `1.2 KB`, `3.4 MB`
+pub fn size_label(bytes: u64) -> String {
+    const KB: f64 = 1024.0;
+    match bytes as f64 {
+        n if n < KB => format!("{} B", bytes),
+        n if n < KB * KB => format!("{:.1} KB", n / KB),
+        n => format!("{:.1} MB", n / (KB * KB)),
+    }
+}
+
+/// Rough token count of `text`: about four characters per token
+///
+/// Good enough to keep prompts within a budget; the real count depends on
+/// the model's tokenizer.
+#[cfg(feature = "tui")]
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Split `text` into pieces of at most `max_bytes` bytes that join back into it
+///
+/// Pieces end after a newline where possible, so lines stay whole; a line
+/// longer than `max_bytes` is cut between characters, never inside one. A
+/// single character wider than `max_bytes` still gets a piece of its own.
+pub fn split_chunks(text: &str, max_bytes: usize) -> Vec<&str> {
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while rest.len() > max_bytes {
+        let mut end = max_bytes;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let end = match rest[..end].rfind('\n') {
+            Some(newline) => newline + 1,
+            None if end > 0 => end,
+            None => rest.chars().next().map_or(1, char::len_utf8),
+        };
+        let (chunk, tail) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = tail;
+    }
+    if !rest.is_empty() || chunks.is_empty() {
+        chunks.push(rest);
+    }
+    chunks
+}
+
+/// A printable stand-in for a control character
+fn visible_char(c: char) -> char {
+    match c {
+        '\n' => '↵',
+        '\t' => '→',
+        // Control Pictures block: ␀ ... ␟
+        '\0'..='\x1f' => char::from_u32(0x2400 + c as u32).unwrap_or('�'),
+        '\x7f' => '␡',
+        c if c.is_control() => '�',
+        c => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_truncate() {
+        // Exactly at the limit is left alone; one over loses a column to "…"
+        assert_eq!(display_truncate("hello", 5), "hello");
+        assert_eq!(display_truncate("hello!", 5), "hell…");
+        assert_eq!(display_truncate("", 5), "");
+        assert_eq!(display_truncate("hello", 0), "");
+
+        // CJK is two columns wide and never cut in half
+        assert_eq!(display_truncate("日本語", 6), "日本語");
+        assert_eq!(display_truncate("日本語テキスト", 6), "日本…");
+        assert_eq!(display_truncate("日本語テキスト", 5), "日本…");
+        assert!(display_truncate("日本語テキスト", 5).width() <= 5);
+
+        // A multi-codepoint emoji stays whole or goes entirely
+        let family = "👨‍👩‍👧";
+        assert_eq!(
+            display_truncate(&format!("a{}b", family), 4),
+            format!("a{}b", family)
+        );
+        assert_eq!(display_truncate(&format!("ab{}cd", family), 4), "ab…");
+        assert_eq!(display_truncate("éééé", 3), "éé…");
+        // ...as does a letter with a combining accent
+        assert_eq!(
+            display_truncate("e\u{301}e\u{301}e\u{301}x", 3),
+            "e\u{301}e\u{301}…"
+        );
+
+        // Control characters become visible and count as one column
+        assert_eq!(display_truncate("a\nb\tc\r", 10), "a↵b→c␍");
+        assert_eq!(display_truncate("line one\nline two", 10), "line one↵…");
+        assert_eq!(display_truncate("\x1b[31m", 3), "␛[…");
+    }
+
+    #[test]
+    fn test_size_label() {
+        assert_eq!(size_label(0), "0 B");
+        assert_eq!(size_label(1023), "1023 B");
+        assert_eq!(size_label(1229), "1.2 KB");
+        assert_eq!(size_label(3 * 1024 * 1024 + 400_000), "3.4 MB");
+    }
+
+    #[test]
+    fn test_thousands() {
+        assert_eq!(thousands(7), "7");
+        assert_eq!(thousands(7200), "7,200");
+        assert_eq!(thousands(1234567), "1,234,567");
+    }
+
+    #[test]
+    fn test_split_chunks() {
+        // Short text is one piece, even when empty
+        assert_eq!(split_chunks("short", 64), ["short"]);
+        assert_eq!(split_chunks("", 64), [""]);
+
+        // Cut after the last newline that fits
+        assert_eq!(split_chunks("aaa\nbbb\ncc", 8), ["aaa\nbbb\n", "cc"]);
+        assert_eq!(split_chunks("aaa\nbbb\n", 4), ["aaa\n", "bbb\n"]);
+        // A line that doesn't fit is cut mid-line
+        assert_eq!(
+            split_chunks("abcdefgh\nij", 3),
+            ["abc", "def", "gh\n", "ij"]
+        );
+
+        // Never inside a character: "é" is 2 bytes, "日" is 3
+        assert_eq!(split_chunks("éééé", 3), ["é", "é", "é", "é"]);
+        assert_eq!(split_chunks("日本語", 4), ["日", "本", "語"]);
+        // Even when a character is wider than a piece
+        assert_eq!(split_chunks("日本", 2), ["日", "本"]);
+
+        let text = "line one\nline two 日本語\n".repeat(500);
+        for max in [7, 64, 1000] {
+            let chunks = split_chunks(&text, max);
+            assert_eq!(chunks.concat(), text);
+            assert!(chunks.iter().all(|c| c.len() <= max && !c.is_empty()));
+        }
+    }
+
+    #[cfg(feature = "tui")]
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abc"), 1);
+        assert_eq!(estimate_tokens("abcd"), 1);
+        assert_eq!(estimate_tokens("abcde"), 2);
+        // Characters, not bytes
+        assert_eq!(estimate_tokens("日本語テ"), 1);
+        assert_eq!(estimate_tokens(&"x".repeat(4000)), 1000);
+    }
+}
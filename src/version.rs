@@ -0,0 +1,102 @@
+//! What this binary is: version and build details for `--version`
+//!
+//! The commit, build date and target come from `build.rs`. `--version
+//! --json` prints them with the enabled cargo features, for packagers and
+//! bug reports.
+
+use serde::Serialize;
+
+/// Crate version
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Short hash of the commit built, if built from a git checkout
+pub const GIT_HASH: Option<&str> = option_env!("OPENCODE_HELIX_GIT_HASH");
+
+/// Build date (UTC, `YYYY-MM-DD`)
+pub const BUILD_DATE: &str = env!("OPENCODE_HELIX_BUILD_DATE");
+
+/// Target triple built for
+pub const TARGET: &str = env!("OPENCODE_HELIX_TARGET");
+
+/// Version and build details
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_hash: Option<&'static str>,
+    pub build_date: &'static str,
+    /// Enabled optional cargo features
+    pub features: Vec<&'static str>,
+    pub target: &'static str,
+}
+
+impl BuildInfo {
+    /// Details of this binary
+    pub fn current() -> Self {
+        Self {
+            version: VERSION,
+            git_hash: GIT_HASH,
+            build_date: BUILD_DATE,
+            features: features(),
+            target: TARGET,
+        }
+    }
+
+    /// One line: `0.1.0 (1a2b3c4d5e6f 2026-01-02)`, without the hash
+    /// outside a git checkout
+    pub fn line(&self) -> String {
+        match self.git_hash {
+            Some(hash) => format!("{} ({} {})", self.version, hash, self.build_date),
+            None => format!("{} ({})", self.version, self.build_date),
+        }
+    }
+}
+
+/// Optional cargo features this binary was built with
+pub fn features() -> Vec<&'static str> {
+    [
+        ("tui", cfg!(feature = "tui")),
+        ("syntax", cfg!(feature = "syntax")),
+        ("notifications", cfg!(feature = "notifications")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_info() {
+        let info = BuildInfo::current();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(info.features.contains(&"tui"), cfg!(feature = "tui"));
+        assert_eq!(info.build_date.len(), 10, "{}", info.build_date);
+        assert!(!info.target.is_empty());
+
+        let info = BuildInfo {
+            version: "0.1.0",
+            git_hash: Some("1a2b3c4d5e6f"),
+            build_date: "2026-01-02",
+            features: vec!["tui"],
+            target: "x86_64-unknown-linux-gnu",
+        };
+        assert_eq!(info.line(), "0.1.0 (1a2b3c4d5e6f 2026-01-02)");
+        assert_eq!(
+            serde_json::to_value(&info).unwrap(),
+            serde_json::json!({
+                "version": "0.1.0",
+                "git_hash": "1a2b3c4d5e6f",
+                "build_date": "2026-01-02",
+                "features": ["tui"],
+                "target": "x86_64-unknown-linux-gnu",
+            })
+        );
+        let info = BuildInfo {
+            git_hash: None,
+            ..info
+        };
+        assert_eq!(info.line(), "0.1.0 (2026-01-02)");
+    }
+}
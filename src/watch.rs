@@ -0,0 +1,286 @@
+//! `watch`: a read-only log of what opencode is doing, for a terminal split
+//!
+//! Follows the server's event stream and prints a line per step of the
+//! work: an assistant message starting, each tool call (with the file or
+//! command it targets) and the message completing, with the first line of
+//! its reply. `--json` prints the events as they arrive instead. A dropped
+//! stream is reconnected after [`Backoff`]; Ctrl+C stops.
+//!
+//! Colors are only used on a terminal, and never with `$NO_COLOR` set.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use crate::server::events::{Event, MessageInfo, Part};
+
+/// Longest reply summary, in chars
+const SUMMARY_MAX: usize = 80;
+
+/// Chars of a session id shown in front of each line
+const SESSION_CHARS: usize = 12;
+
+/// Whether to color the log: `stdout_tty` and no `$NO_COLOR` (see no-color.org)
+pub fn use_color(stdout_tty: bool, env: impl Fn(&str) -> Option<String>) -> bool {
+    stdout_tty && env("NO_COLOR").is_none_or(|value| value.is_empty())
+}
+
+/// Session a raw event belongs to, if it names one
+pub fn session_of<'a>(value: &'a serde_json::Value, event: &'a Event) -> Option<&'a str> {
+    event
+        .session_id()
+        .or_else(|| value["properties"]["sessionID"].as_str())
+}
+
+/// Turns events into log lines, remembering what was already printed
+#[derive(Debug, Default)]
+pub struct Log {
+    /// Only this session, if set
+    session: Option<String>,
+    color: bool,
+    /// Assistant messages started, and the session of each
+    started: HashMap<String, String>,
+    completed: HashSet<String>,
+    /// Tool parts already printed
+    tools: HashSet<String>,
+    /// Latest reply text of each message
+    replies: HashMap<String, String>,
+}
+
+impl Log {
+    /// A log of `session` (every session when None)
+    pub fn new(session: Option<String>, color: bool) -> Self {
+        Self {
+            session,
+            color,
+            ..Default::default()
+        }
+    }
+
+    /// Lines to print for `event`, often none
+    pub fn lines(&mut self, event: &Event) -> Vec<String> {
+        match event {
+            Event::MessageUpdated { info } => self.message(info),
+            Event::PartUpdated { part } => self.part(part).into_iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn message(&mut self, info: &MessageInfo) -> Vec<String> {
+        if info.role != "assistant"
+            || self
+                .session
+                .as_ref()
+                .is_some_and(|session| *session != info.session_id)
+        {
+            return Vec::new();
+        }
+        let mut lines = Vec::new();
+        if !self.started.contains_key(&info.id) {
+            self.started
+                .insert(info.id.clone(), info.session_id.clone());
+            lines.push(self.line(&info.session_id, "36", "▶", "message started"));
+        }
+        if info.time.completed.is_some() && self.completed.insert(info.id.clone()) {
+            let text = match self.replies.get(&info.id).and_then(|reply| summary(reply)) {
+                Some(summary) => format!("message completed: {}", summary),
+                None => "message completed".to_string(),
+            };
+            lines.push(self.line(&info.session_id, "32", "✓", &text));
+        }
+        lines
+    }
+
+    fn part(&mut self, part: &Part) -> Option<String> {
+        // Only parts of messages being watched
+        let session = self.started.get(&part.message_id)?.clone();
+        match part.kind.as_str() {
+            "text" => {
+                if let Some(text) = &part.text {
+                    self.replies.insert(part.message_id.clone(), text.clone());
+                }
+                None
+            }
+            "tool" => {
+                let state = part.state.as_ref()?;
+                if state.status == "pending" || !self.tools.insert(part.id.clone()) {
+                    return None;
+                }
+                let tool = part.tool.as_deref().unwrap_or("tool");
+                let text = match target(&state.input) {
+                    Some(target) => format!("{} {}", tool, target),
+                    None => tool.to_string(),
+                };
+                Some(self.line(&session, "33", "⚙", &text))
+            }
+            _ => None,
+        }
+    }
+
+    /// `session` (shortened), then `mark` and `text` in the color `sgr`
+    fn line(&self, session: &str, sgr: &str, mark: &str, text: &str) -> String {
+        let session: String = session.chars().take(SESSION_CHARS).collect();
+        if self.color {
+            format!(
+                "\x1b[2m{}\x1b[0m \x1b[{}m{}\x1b[0m {}",
+                session, sgr, mark, text
+            )
+        } else {
+            format!("{} {} {}", session, mark, text)
+        }
+    }
+}
+
+/// What a tool call works on: its file, path, pattern, command or URL
+fn target(input: &serde_json::Value) -> Option<String> {
+    ["filePath", "path", "pattern", "command", "url"]
+        .iter()
+        .find_map(|key| input[key].as_str())
+        .and_then(summary)
+}
+
+/// First non-empty line of `text`, cut to [`SUMMARY_MAX`] chars
+fn summary(text: &str) -> Option<String> {
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty())?;
+    if line.chars().count() <= SUMMARY_MAX {
+        return Some(line.to_string());
+    }
+    let cut: String = line.chars().take(SUMMARY_MAX - 1).collect();
+    Some(format!("{}…", cut))
+}
+
+/// Delay before reconnecting a dropped stream: 1s, doubling up to 30s, and
+/// back to 1s once a connection works
+#[derive(Debug)]
+pub struct Backoff {
+    next: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self { next: Self::FIRST }
+    }
+}
+
+impl Backoff {
+    const FIRST: Duration = Duration::from_secs(1);
+    const MAX: Duration = Duration::from_secs(30);
+
+    /// The delay before the next attempt
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(Self::MAX);
+        delay
+    }
+
+    /// A connection worked
+    pub fn reset(&mut self) {
+        self.next = Self::FIRST;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn message(id: &str, session: &str, completed: bool) -> Event {
+        let time = if completed {
+            json!({ "created": 1, "completed": 2 })
+        } else {
+            json!({ "created": 1 })
+        };
+        Event::parse(&json!({
+            "type": "message.updated",
+            "properties": { "info": {
+                "id": id, "sessionID": session, "role": "assistant", "time": time
+            } }
+        }))
+    }
+
+    fn part(id: &str, message: &str, part: serde_json::Value) -> Event {
+        let mut part = part;
+        part["id"] = json!(id);
+        part["messageID"] = json!(message);
+        Event::parse(&json!({ "type": "message.part.updated", "properties": { "part": part } }))
+    }
+
+    fn tool(id: &str, status: &str) -> Event {
+        part(
+            id,
+            "msg_1",
+            json!({
+                "type": "tool", "tool": "edit",
+                "state": { "status": status, "input": { "filePath": "src/main.rs" } }
+            }),
+        )
+    }
+
+    #[test]
+    fn test_log_lines() {
+        let mut log = Log::new(None, false);
+        let mut lines = Vec::new();
+        for event in [
+            message("msg_1", "ses_0123456789abcdef", false),
+            message("msg_1", "ses_0123456789abcdef", false),
+            tool("prt_1", "pending"),
+            tool("prt_1", "running"),
+            tool("prt_1", "completed"),
+            part(
+                "prt_2",
+                "msg_1",
+                json!({ "type": "text", "text": "\nDone: fixed the parser.\nDetails..." }),
+            ),
+            message("msg_1", "ses_0123456789abcdef", true),
+            message("msg_1", "ses_0123456789abcdef", true),
+        ] {
+            lines.extend(log.lines(&event));
+        }
+        assert_eq!(
+            lines,
+            [
+                "ses_01234567 ▶ message started",
+                "ses_01234567 ⚙ edit src/main.rs",
+                "ses_01234567 ✓ message completed: Done: fixed the parser.",
+            ]
+        );
+
+        // Colored, the mark is; the text isn't
+        let mut log = Log::new(None, true);
+        assert_eq!(
+            log.lines(&message("msg_1", "ses_a", false)),
+            ["\x1b[2mses_a\x1b[0m \x1b[36m▶\x1b[0m message started"]
+        );
+    }
+
+    #[test]
+    fn test_log_filters_by_session() {
+        let mut log = Log::new(Some("ses_b".to_string()), false);
+        assert!(log.lines(&message("msg_1", "ses_a", false)).is_empty());
+        assert!(log.lines(&tool("prt_1", "running")).is_empty());
+        assert_eq!(log.lines(&message("msg_2", "ses_b", true)).len(), 2);
+    }
+
+    #[test]
+    fn test_summary_and_color() {
+        assert_eq!(summary("  \n  one\ntwo").as_deref(), Some("one"));
+        assert_eq!(summary(" \n"), None);
+        let long = summary(&"x".repeat(100)).unwrap();
+        assert_eq!(long.chars().count(), SUMMARY_MAX);
+        assert!(long.ends_with('…'));
+
+        let env = |no_color: Option<&'static str>| move |_: &str| no_color.map(String::from);
+        assert!(use_color(true, env(None)));
+        assert!(use_color(true, env(Some(""))));
+        assert!(!use_color(true, env(Some("1"))));
+        assert!(!use_color(false, env(None)));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let mut backoff = Backoff::default();
+        let delays: Vec<u64> = (0..7).map(|_| backoff.next_delay().as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 30, 30]);
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+    }
+}
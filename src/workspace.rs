@@ -0,0 +1,130 @@
+//! The project a `--file` belongs to, when `--cwd` isn't given
+//!
+//! Helix started in `~` and editing `~/code/app/src/main.rs` passes
+//! `--file code/app/src/main.rs`, so the process cwd says nothing about the
+//! project. Without `--cwd`, the working directory is instead the nearest
+//! directory above the file holding one of the root markers (`root_markers`
+//! in the config, by default [`DEFAULT_MARKERS`]), or else the file's own
+//! directory. Discovery, git commands and the paths `@this` and `@alt`
+//! give all go by it.
+
+use std::path::{Path, PathBuf};
+
+/// Files or directories that mark a project's root
+pub const DEFAULT_MARKERS: [&str; 4] = [".git", "Cargo.toml", "package.json", ".opencode"];
+
+/// Working directory for `file` (absolute): the nearest directory above it
+/// holding one of `markers`, else the file's own directory
+///
+/// None when the file's directory doesn't exist, as for a path mistyped.
+pub fn root_of(file: &Path, markers: &[String]) -> Option<PathBuf> {
+    let dir = file.parent().filter(|dir| dir.is_dir())?;
+    let root = dir
+        .ancestors()
+        .find(|dir| markers.iter().any(|marker| dir.join(marker).exists()))
+        .unwrap_or(dir);
+    Some(root.to_path_buf())
+}
+
+/// `path` (absolute) relative to `root`, if it is inside it
+pub fn relative(path: &Path, root: &Path) -> Option<PathBuf> {
+    path.strip_prefix(root)
+        .ok()
+        .filter(|rest| !rest.as_os_str().is_empty())
+        .map(Path::to_path_buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn markers() -> Vec<String> {
+        DEFAULT_MARKERS.iter().map(|m| m.to_string()).collect()
+    }
+
+    /// A directory with `files` (empty) and `dirs` in it
+    fn tree(files: &[&str], dirs: &[&str]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("opencode-helix-{}", uuid::Uuid::new_v4()));
+        for dir in dirs {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in files {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn test_root_of_nested() {
+        let root = tree(
+            &[
+                "code/app/src/main.rs",
+                "code/app/vendor/lib/Cargo.toml",
+                "code/app/vendor/lib/src/lib.rs",
+                "code/web/package.json",
+                "code/web/src/deep/index.js",
+            ],
+            &["code/app/.git", "code/notes/.opencode"],
+        );
+        let at = |file: &str| root_of(&root.join(file), &markers()).unwrap();
+        assert_eq!(at("code/app/src/main.rs"), root.join("code/app"));
+        // The nearest marker wins over the repository around it
+        assert_eq!(
+            at("code/app/vendor/lib/src/lib.rs"),
+            root.join("code/app/vendor/lib")
+        );
+        assert_eq!(at("code/web/src/deep/index.js"), root.join("code/web"));
+        // A marker next to the file, and a file that doesn't exist yet
+        assert_eq!(
+            at("code/app/vendor/lib/Cargo.toml"),
+            root.join("code/app/vendor/lib")
+        );
+        assert_eq!(at("code/notes/new.md"), root.join("code/notes"));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_root_of_without_marker() {
+        let root = tree(&["code/app/.git/HEAD", "scratch/deep/note.txt"], &[]);
+        let markers = markers();
+        // Outside any marker (the temp dir has none): the file's directory
+        if !std::env::temp_dir()
+            .ancestors()
+            .any(|dir| markers.iter().any(|m| dir.join(m).exists()))
+        {
+            assert_eq!(
+                root_of(&root.join("scratch/deep/note.txt"), &markers),
+                Some(root.join("scratch/deep"))
+            );
+        }
+        // Other markers configured: .git no longer counts
+        std::fs::create_dir_all(root.join("code/app/src")).unwrap();
+        std::fs::write(root.join("code/Makefile"), "").unwrap();
+        let other = vec!["Makefile".to_string()];
+        assert_eq!(
+            root_of(&root.join("code/app/src/x.rs"), &other),
+            Some(root.join("code"))
+        );
+        // A directory that doesn't exist
+        assert_eq!(root_of(&root.join("code/gone/x.rs"), &markers), None);
+        // None configured: always the file's directory
+        assert_eq!(
+            root_of(&root.join("code/app/src/x.rs"), &[]),
+            Some(root.join("code/app/src"))
+        );
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_relative() {
+        let root = Path::new("/home/me/code/app");
+        assert_eq!(
+            relative(Path::new("/home/me/code/app/src/main.rs"), root),
+            Some(PathBuf::from("src/main.rs"))
+        );
+        assert_eq!(relative(Path::new("/home/me/code/other.rs"), root), None);
+        assert_eq!(relative(root, root), None);
+    }
+}
@@ -0,0 +1,54 @@
+//! `--agent` hands the prompt to an agent the server has
+
+mod common;
+
+use common::{mock_server, opencode_helix, state_dir};
+
+#[test]
+fn test_agent_prefix_and_validation() {
+    let port = mock_server(&[
+        ("/path", "200 OK", r#"{"directory":"/tmp"}"#),
+        ("/tui/publish", "200 OK", "true"),
+        (
+            "/agent",
+            "200 OK",
+            r#"[{"name":"build","mode":"primary"},{"name":"reviewer","mode":"subagent"}]"#,
+        ),
+    ]);
+    let state = state_dir();
+    let run = |args: &[&str]| {
+        opencode_helix(port, &state)
+            .args(["--output", "json"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let output = run(&["--agent", "reviewer", "prompt", "Review this"]);
+    assert_eq!(output.status.code(), Some(0));
+    let record: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(record["expanded"], "@reviewer Review this");
+
+    // Not added twice
+    let output = run(&["--agent", "@reviewer", "prompt", "Ask @reviewer to check"]);
+    let record: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(record["expanded"], "Ask @reviewer to check");
+
+    // An unknown agent is a usage error naming the ones there are
+    let output = run(&["--agent", "critic", "prompt", "Review this"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(5), "{}", stderr);
+    assert!(stderr.contains("available: build, reviewer"), "{}", stderr);
+
+    let output = run(&[
+        "--agent",
+        "critic",
+        "--no-validate-agent",
+        "prompt",
+        "Review this",
+    ]);
+    let _ = std::fs::remove_dir_all(&state);
+    assert_eq!(output.status.code(), Some(0));
+    let record: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(record["expanded"], "@critic Review this");
+}
@@ -0,0 +1,55 @@
+//! `--clear-prompt` never silently wipes text the user typed into opencode's prompt box
+
+mod common;
+
+use common::{mock_server, opencode_helix, state_dir};
+
+#[test]
+fn test_clear_checks_prompt_box() {
+    let port = mock_server(&[
+        ("/path", "200 OK", r#"{"directory":"/tmp"}"#),
+        ("/tui/prompt", "200 OK", r#"{"text":"half-typed"}"#),
+        ("/tui/publish", "200 OK", "true"),
+    ]);
+    let state = state_dir();
+    let run = |args: &[&str]| {
+        opencode_helix(port, &state)
+            .args(args)
+            .args(["prompt", "hello"])
+            .output()
+            .unwrap()
+    };
+
+    // Nobody to ask without a dialog: refuse and point at --force-clear
+    let output = run(&["--clear-prompt"]);
+    assert_eq!(output.status.code(), Some(5));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--force-clear"));
+
+    assert_eq!(run(&["--force-clear"]).status.code(), Some(0));
+    assert_eq!(run(&[]).status.code(), Some(0));
+    let _ = std::fs::remove_dir_all(&state);
+}
+
+#[test]
+fn test_clear_behavior_when_box_unknown() {
+    // No /tui/prompt endpoint: the config decides
+    let port = mock_server(&[
+        ("/path", "200 OK", r#"{"directory":"/tmp"}"#),
+        ("/tui/publish", "200 OK", "true"),
+    ]);
+    let state = state_dir();
+    let config = state.join("opencode-helix");
+    std::fs::create_dir_all(&config).unwrap();
+    let run = |behavior: &str| {
+        std::fs::write(config.join("config.toml"), behavior).unwrap();
+        opencode_helix(port, &state)
+            .args(["--clear-prompt", "prompt", "hello"])
+            .output()
+            .unwrap()
+    };
+
+    assert_eq!(run("").status.code(), Some(5));
+    assert_eq!(run("clear_behavior = \"append\"").status.code(), Some(0));
+    assert_eq!(run("clear_behavior = \"overwrite\"").status.code(), Some(0));
+    let _ = std::fs::remove_dir_all(&state);
+}
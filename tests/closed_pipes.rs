@@ -0,0 +1,62 @@
+//! Exit codes when stdout/stderr are closed before we write to them
+//!
+//! Helix can close the `:insert-output` pipe early; the exit code must still
+//! reflect what happened rather than a panic from writing the status message.
+
+mod common;
+
+use std::process::Stdio;
+
+use common::{mock_server, opencode_helix, state_dir};
+
+/// Run the binary with stdout and stderr closed, returning its exit code
+fn exit_code_with_closed_pipes(port: u16, args: &[&str]) -> Option<i32> {
+    let state = state_dir();
+    let mut child = opencode_helix(port, &state)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Close the read ends before anything is written
+    drop(child.stdout.take());
+    drop(child.stderr.take());
+    let code = child.wait().unwrap().code();
+    let _ = std::fs::remove_dir_all(&state);
+    code
+}
+
+#[test]
+fn test_status_with_closed_stdout() {
+    let port = mock_server(&[("/path", "200 OK", r#"{"directory":"/tmp"}"#)]);
+    assert_eq!(exit_code_with_closed_pipes(port, &["status"]), Some(0));
+}
+
+#[test]
+fn test_send_outcome_with_closed_pipes() {
+    // The "Sent: ..." message can't be written, but the send succeeded
+    let port = mock_server(&[
+        ("/path", "200 OK", r#"{"directory":"/tmp"}"#),
+        ("/tui/publish", "200 OK", "true"),
+    ]);
+    assert_eq!(
+        exit_code_with_closed_pipes(port, &["prompt", "hello"]),
+        Some(0)
+    );
+
+    // A failed send still exits with the server error code
+    let port = mock_server(&[
+        ("/path", "200 OK", r#"{"directory":"/tmp"}"#),
+        ("/session", "200 OK", "[]"),
+        (
+            "/session/ses_1/prompt_async",
+            "500 Internal Server Error",
+            "",
+        ),
+    ]);
+    assert_eq!(
+        exit_code_with_closed_pipes(port, &["--session", "ses_1", "prompt", "hello"]),
+        Some(4)
+    );
+}
@@ -0,0 +1,71 @@
+//! `@cmd:` runs only when the config allows it, and `--allow-cmd` says so
+//! outside the dialogs
+
+mod common;
+
+use common::{opencode_helix, publish_server, state_dir};
+
+#[test]
+fn test_cmd_placeholder() {
+    let state = state_dir();
+    let config = state.join("config.toml");
+    let (port, published) = publish_server(&[("/path", "200 OK", r#"{"directory":"/tmp"}"#)]);
+    let run = |args: &[&str]| {
+        let output = opencode_helix(port, &state)
+            .args(["--config", config.to_str().unwrap()])
+            .args(args)
+            .args(["prompt", "why: @cmd:echo one: two"])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(0), "{:?}", output);
+        let events: String = published.try_iter().collect();
+        (String::from_utf8_lossy(&output.stderr).into_owned(), events)
+    };
+
+    // Off in the config: sent as typed, whatever the flag
+    std::fs::write(&config, "").unwrap();
+    let (stderr, events) = run(&["--allow-cmd"]);
+    assert!(stderr.contains("allow_cmd_placeholder"), "{}", stderr);
+    assert!(events.contains("why: @cmd:echo one: two"), "{}", events);
+
+    // Allowed, but not confirmed
+    std::fs::write(&config, "allow_cmd_placeholder = true\n").unwrap();
+    let (stderr, events) = run(&[]);
+    assert!(stderr.contains("--allow-cmd"), "{}", stderr);
+    assert!(events.contains("@cmd:echo one: two"), "{}", events);
+
+    let (stderr, events) = run(&["--allow-cmd"]);
+    assert!(!stderr.contains("Warning"), "{}", stderr);
+    assert!(
+        events.contains(r"why: ```console\n$ echo one: two\none: two\n```"),
+        "{}",
+        events
+    );
+
+    // A project prompt, from whatever repository is checked out, never runs
+    // its command without asking
+    let project = state.join("project");
+    std::fs::create_dir_all(&project).unwrap();
+    std::fs::write(
+        project.join(".opencode-helix.toml"),
+        "[[prompts]]\nname = \"review\"\nprompt = \"@cmd:echo owned\"\n",
+    )
+    .unwrap();
+    let output = opencode_helix(port, &state)
+        .args(["--config", config.to_str().unwrap(), "--allow-cmd", "--cwd"])
+        .arg(&project)
+        .args(["prompt", "review"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("@cmd:echo owned not run: it comes from .opencode-helix.toml"),
+        "{}",
+        stderr
+    );
+    let events: String = published.try_iter().collect();
+    assert!(events.contains("@cmd:echo owned"), "{}", events);
+    assert!(!events.contains("console"), "{}", events);
+    let _ = std::fs::remove_dir_all(&state);
+}
@@ -0,0 +1,162 @@
+//! Helpers shared by the integration tests
+//!
+//! Each test binary compiles this module but uses only some of it.
+#![allow(dead_code)]
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{mpsc, Arc};
+
+/// A fresh directory for `$XDG_STATE_HOME`, so runs don't touch the real history
+pub fn state_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("opencode-helix-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// The binary, talking to the server on `port` and keeping its state in `state`
+pub fn opencode_helix(port: u16, state: &Path) -> Command {
+    let mut command = discovering(state);
+    command.arg("--port").arg(port.to_string());
+    command
+}
+
+/// The binary without `--port`, discovering the server itself
+pub fn discovering(state: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_opencode-helix"));
+    command
+        // Config, cache and queue in `state` too, never the developer's own
+        .env("HOME", state)
+        .env("XDG_CONFIG_HOME", state)
+        .env("XDG_CACHE_HOME", state)
+        .env("XDG_RUNTIME_DIR", state)
+        .env("XDG_STATE_HOME", state)
+        .env_remove("OPENCODE_HELIX_CONFIG")
+        .env_remove("OPENCODE_HELIX_STATE_DIR")
+        .env_remove("OPENCODE_PORT");
+    command
+}
+
+/// A mock server's answer to a path: the path, status line and body
+pub type Route = (&'static str, &'static str, &'static str);
+
+/// Serve `routes` on a local port; other paths get 404
+pub fn mock_server(routes: &'static [Route]) -> u16 {
+    serve(move |path, _, mut stream| {
+        let (status, body) = route(routes, path);
+        respond(&mut stream, status, body);
+    })
+}
+
+/// Serve `routes` like [`mock_server`], taking the events published to the
+/// TUI and passing their bodies on to the receiver
+pub fn publish_server(routes: &'static [Route]) -> (u16, mpsc::Receiver<String>) {
+    let (send, published) = mpsc::channel();
+    let port = serve(move |path, body, mut stream| {
+        let (status, reply) = match path {
+            "/tui/publish" => {
+                let _ = send.send(body.to_string());
+                ("200 OK", "true")
+            }
+            _ => route(routes, path),
+        };
+        respond(&mut stream, status, reply);
+    });
+    (port, published)
+}
+
+/// Status line and body `routes` answer `path` with, 404 if none
+fn route(routes: &[Route], path: &str) -> (&'static str, &'static str) {
+    routes
+        .iter()
+        .find(|(p, _, _)| *p == path)
+        .map_or(("404 Not Found", ""), |(_, status, body)| (*status, *body))
+}
+
+/// A port nothing listens on
+pub fn dead_port() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.local_addr().unwrap().port()
+}
+
+/// Serve requests on a local port, each on its own thread: `handle` gets
+/// the path, the body and the connection to answer on
+pub fn serve(handle: impl Fn(&str, &str, TcpStream) + Send + Sync + 'static) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let handle = Arc::new(handle);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let handle = handle.clone();
+            std::thread::spawn(move || {
+                let (path, body) = read_request(&mut stream);
+                handle(&path, &body, stream);
+            });
+        }
+    });
+    port
+}
+
+/// Read a request, returning its path and body
+///
+/// The whole body is read so closing the socket doesn't reset the connection.
+pub fn read_request(stream: &mut TcpStream) -> (String, String) {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !String::from_utf8_lossy(&buf).contains("\r\n\r\n") {
+        match stream.read(&mut chunk) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+        }
+    }
+    let head_end = String::from_utf8_lossy(&buf)
+        .find("\r\n\r\n")
+        .map_or(buf.len(), |i| i + 4);
+    let head = String::from_utf8_lossy(&buf[..head_end]).to_string();
+    let length: usize = head
+        .lines()
+        .find_map(|l| {
+            let (name, value) = l.split_once(':')?;
+            name.eq_ignore_ascii_case("content-length")
+                .then(|| value.trim().parse().ok())?
+        })
+        .unwrap_or(0);
+    let mut body = buf[head_end..].to_vec();
+    while body.len() < length {
+        match stream.read(&mut chunk) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => body.extend_from_slice(&chunk[..n]),
+        }
+    }
+
+    let path = head
+        .split_whitespace()
+        .nth(1)
+        .unwrap_or_default()
+        .to_string();
+    (path, String::from_utf8_lossy(&body).to_string())
+}
+
+/// Write a JSON response and let the connection close
+pub fn respond(stream: &mut TcpStream, status: &str, body: &str) {
+    let response = format!(
+        "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes());
+}
+
+/// Answer with an event stream that stays open; events are written to
+/// `stream` after this
+pub fn event_stream(stream: &mut TcpStream) {
+    let _ = write!(
+        stream,
+        "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\n\r\n"
+    );
+    let _ = stream.flush();
+}
@@ -0,0 +1,42 @@
+//! `--config` and `OPENCODE_HELIX_CONFIG` name the config file to use
+
+mod common;
+
+use common::{mock_server, opencode_helix, state_dir};
+
+#[test]
+fn test_explicit_config() {
+    let port = mock_server(&[
+        ("/path", "200 OK", r#"{"directory":"/tmp"}"#),
+        ("/tui/publish", "200 OK", "true"),
+    ]);
+    let state = state_dir();
+    let config = state.join("elsewhere.toml");
+    std::fs::write(&config, "stats = true\n").unwrap();
+    let run = |args: &[&str]| opencode_helix(port, &state).args(args).output().unwrap();
+
+    let flag = run(&["--config", config.to_str().unwrap(), "prompt", "explain"]);
+    let env = opencode_helix(port, &state)
+        .env("OPENCODE_HELIX_CONFIG", &config)
+        .args(["prompt", "explain"])
+        .output()
+        .unwrap();
+    let missing = run(&["--config", "/nonexistent/config.toml", "prompt", "explain"]);
+    std::fs::write(&config, "stats = 3\n").unwrap();
+    let invalid = run(&["--config", config.to_str().unwrap(), "prompt", "explain"]);
+    let stats = std::fs::read_to_string(state.join("opencode-helix/stats.jsonl"));
+    let _ = std::fs::remove_dir_all(&state);
+
+    assert_eq!(flag.status.code(), Some(0));
+    assert_eq!(env.status.code(), Some(0));
+    // Both runs were recorded, so the file was read
+    assert_eq!(stats.unwrap().lines().count(), 2);
+
+    // A named config that can't be loaded is an error, not the defaults
+    assert_eq!(missing.status.code(), Some(5));
+    let stderr = String::from_utf8_lossy(&missing.stderr);
+    assert!(stderr.contains("/nonexistent/config.toml"), "{}", stderr);
+    assert_eq!(invalid.status.code(), Some(5));
+    let stderr = String::from_utf8_lossy(&invalid.stderr);
+    assert!(stderr.contains("Invalid config"), "{}", stderr);
+}
@@ -0,0 +1,34 @@
+//! `context` prints a prompt expanded without a server, and `--explain`
+//! what became of each placeholder
+
+mod common;
+
+use common::{discovering, state_dir};
+
+#[test]
+fn test_context_explain() {
+    let state = state_dir();
+    let run = |args: &[&str]| {
+        let output = discovering(&state)
+            .current_dir(&state)
+            .args(["-f", "src/lib.rs", "-l", "7", "context"])
+            .args(args)
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        assert_eq!(output.status.code(), Some(0), "{}", stderr);
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+
+    assert_eq!(
+        run(&["look at @this and @selection"]),
+        "look at @src/lib.rs L7 and @selection\n"
+    );
+    assert_eq!(
+        run(&["--explain", "@this, @buffr, @this\n@cmd:ls"]),
+        "@this at 0, 15: expanded, +18 bytes\n\
+         @buffr at 7: left as typed (unknown)\n\
+         @cmd:ls at 21: left as typed (disabled)\n"
+    );
+    let _ = std::fs::remove_dir_all(&state);
+}
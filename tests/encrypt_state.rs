@@ -0,0 +1,54 @@
+//! `encrypt_state = true` keeps the history sealed, and `state clear` removes it
+
+mod common;
+
+use common::{mock_server, opencode_helix, state_dir};
+
+#[test]
+fn test_encrypted_history() {
+    let port = mock_server(&[
+        ("/path", "200 OK", r#"{"directory":"/tmp"}"#),
+        ("/tui/publish", "200 OK", "true"),
+    ]);
+    let state = state_dir();
+    let config = state.join("opencode-helix");
+    std::fs::create_dir_all(&config).unwrap();
+    std::fs::write(config.join("config.toml"), "encrypt_state = true\n").unwrap();
+    let run = |key: &str, args: &[&str]| {
+        opencode_helix(port, &state)
+            .env("OPENCODE_HELIX_STATE_KEY", key)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    assert_eq!(
+        run("correct horse", &["prompt", "Review the parser"])
+            .status
+            .code(),
+        Some(0)
+    );
+    let history = state.join("opencode-helix/last");
+    for entry in std::fs::read_dir(&history).unwrap() {
+        let sealed = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+        assert!(!sealed.contains("parser"), "{}", sealed);
+    }
+    let output = run("correct horse", &["--output", "json", "resend"]);
+    assert_eq!(output.status.code(), Some(0));
+    let record: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(record["input"], "Review the parser");
+
+    // Another key can't read it
+    let output = run("battery staple", &["resend"]);
+    assert_ne!(output.status.code(), Some(0));
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    assert!(stderr.contains("state unavailable"), "{}", stderr);
+
+    let output = run("battery staple", &["state", "clear"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Removed"));
+    assert!(!history.exists());
+    let output = run("correct horse", &["resend"]);
+    let _ = std::fs::remove_dir_all(&state);
+    assert_eq!(output.status.code(), Some(5));
+}
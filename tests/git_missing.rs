@@ -0,0 +1,50 @@
+//! Diff placeholders that can't be expanded say why on stderr
+
+mod common;
+
+use common::{mock_server, opencode_helix, state_dir};
+
+#[test]
+fn test_diff_not_expanded_warning() {
+    let port = mock_server(&[
+        ("/path", "200 OK", r#"{"directory":"/tmp"}"#),
+        ("/tui/publish", "200 OK", "true"),
+    ]);
+    let state = state_dir();
+    let project = state.join("project");
+    std::fs::create_dir_all(&project).unwrap();
+    let run = |path: &str, args: &[&str]| {
+        opencode_helix(port, &state)
+            .env("PATH", path)
+            .arg("--cwd")
+            .arg(&project)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    // An empty directory as the PATH: no git to run
+    let empty = state.join("empty");
+    std::fs::create_dir_all(&empty).unwrap();
+    let no_git = run(empty.to_str().unwrap(), &["prompt", "Review @diff"]);
+    let path = std::env::var("PATH").unwrap_or_default();
+    let no_repo = run(&path, &["prompt", "Review @diff:20"]);
+    let quiet = run(&path, &["--quiet", "prompt", "Review @diff"]);
+    let _ = std::fs::remove_dir_all(&state);
+
+    // Sent as typed, with a warning saying why
+    assert_eq!(no_git.status.code(), Some(0));
+    let stderr = String::from_utf8_lossy(&no_git.stderr);
+    assert!(
+        stderr.contains("Warning: @diff not expanded: git is not installed"),
+        "{}",
+        stderr
+    );
+    let stderr = String::from_utf8_lossy(&no_repo.stderr);
+    assert!(
+        stderr.contains("Warning: @diff:20 not expanded: not a git repository"),
+        "{}",
+        stderr
+    );
+    assert!(!String::from_utf8_lossy(&quiet.stderr).contains("not expanded"));
+}
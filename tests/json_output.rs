@@ -0,0 +1,93 @@
+//! `--output json` writes one record per sent prompt to stdout
+
+mod common;
+
+use std::process::Output;
+
+use common::{dead_port, mock_server, opencode_helix, state_dir};
+
+/// Run the binary against a mock server on `port`
+fn run(port: u16, args: &[&str]) -> Output {
+    let state = state_dir();
+    let output = opencode_helix(port, &state).args(args).output().unwrap();
+    let _ = std::fs::remove_dir_all(&state);
+    output
+}
+
+#[test]
+fn test_prompt_json_record() {
+    let port = mock_server(&[
+        ("/path", "200 OK", r#"{"directory":"/tmp"}"#),
+        ("/tui/publish", "200 OK", "true"),
+    ]);
+    let file = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+    let output = run(
+        port,
+        &[
+            "--quiet",
+            "--output",
+            "json",
+            "--file",
+            file,
+            "prompt",
+            "Explain @path",
+        ],
+    );
+    assert_eq!(output.status.code(), Some(0));
+    // --quiet silences stderr but not the record
+    assert!(output.stderr.is_empty(), "{:?}", output.stderr);
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 1);
+    let record: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(record["version"], 1);
+    assert_eq!(record["port"], port);
+    assert_eq!(record["step"], "text");
+    assert_eq!(record["input"], "Explain @path");
+    let expanded = record["expanded"].as_str().unwrap();
+    assert!(expanded.ends_with("/Cargo.toml"), "{}", expanded);
+    assert_eq!(
+        record["placeholders"]["@path"],
+        expanded.len() - "Explain ".len()
+    );
+    assert!(record["timestamp"].as_str().unwrap().ends_with('Z'));
+}
+
+#[test]
+fn test_failed_send_has_no_record() {
+    let port = mock_server(&[
+        ("/path", "200 OK", r#"{"directory":"/tmp"}"#),
+        ("/session", "200 OK", "[]"),
+        (
+            "/session/ses_1/prompt_async",
+            "500 Internal Server Error",
+            "",
+        ),
+    ]);
+    let output = run(
+        port,
+        &["--output", "json", "--session", "ses_1", "prompt", "hello"],
+    );
+    assert_eq!(output.status.code(), Some(4));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Error"));
+}
+
+#[test]
+fn test_discovery_error_json() {
+    let port = dead_port();
+    let output = run(port, &["--output", "json", "prompt", "hello"]);
+    assert_eq!(output.status.code(), Some(7));
+    assert!(output.stdout.is_empty());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr.lines().count(), 1, "{}", stderr);
+    let error: serde_json::Value = serde_json::from_str(&stderr).unwrap();
+    assert_eq!(error["reason"], "all_probes_failed");
+    assert_eq!(error["exit_code"], 7);
+    assert_eq!(error["attempts"][0]["port"], port);
+    assert!(error["message"]
+        .as_str()
+        .unwrap()
+        .contains(&format!("No opencode server responding on port {}", port)));
+}
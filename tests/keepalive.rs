@@ -0,0 +1,56 @@
+//! `--wait` gives up when the server stops answering, even though the event
+//! stream stays open
+
+mod common;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use common::{event_stream, opencode_helix, respond, serve, state_dir};
+
+/// A server that takes the prompt, then stops answering while `/event`
+/// stays open without sending anything
+fn dying_server() -> u16 {
+    let dead = AtomicBool::new(false);
+    serve(move |path, body, mut stream| match path {
+        // Dropped without an answer once the server "died"
+        _ if dead.load(Ordering::SeqCst) => {}
+        "/path" => respond(&mut stream, "200 OK", r#"{"directory":"/tmp"}"#),
+        "/event" => {
+            event_stream(&mut stream);
+            // Half-open: never another byte, never closed
+            std::thread::sleep(Duration::from_secs(600));
+        }
+        "/tui/publish" => {
+            respond(&mut stream, "200 OK", "true");
+            // Gone once the prompt is submitted
+            if body.contains("prompt.submit") {
+                dead.store(true, Ordering::SeqCst);
+            }
+        }
+        _ => respond(&mut stream, "404 Not Found", ""),
+    })
+}
+
+#[test]
+fn test_wait_detects_server_death() {
+    let port = dying_server();
+    let state = state_dir();
+    let config = state.join("config.toml");
+    std::fs::write(&config, "keepalive_secs = 1\nkeepalive_failures = 2\n").unwrap();
+
+    let started = Instant::now();
+    let output = opencode_helix(port, &state)
+        .env("OPENCODE_HELIX_CONFIG", &config)
+        .args(["--wait", "prompt", "hello"])
+        .output()
+        .unwrap();
+    let elapsed = started.elapsed();
+    let _ = std::fs::remove_dir_all(&state);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(4), "{}", stderr);
+    assert!(stderr.contains("went away"), "{}", stderr);
+    // Two probes a second apart, not the 600s the stream stays open
+    assert!(elapsed < Duration::from_secs(20), "{:?}", elapsed);
+}
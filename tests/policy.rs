@@ -0,0 +1,56 @@
+//! The config's `[policy]` holds whatever the flags ask for
+
+mod common;
+
+use common::{opencode_helix, publish_server, state_dir, Route};
+
+/// A server with the agents build and plan
+const ROUTES: &[Route] = &[
+    ("/path", "200 OK", r#"{"directory":"/tmp"}"#),
+    ("/agent", "200 OK", r#"[{"name":"build"},{"name":"plan"}]"#),
+    ("/event", "200 OK", ""),
+];
+
+#[test]
+fn test_policy() {
+    let state = state_dir();
+    let config = state.join("opencode-helix");
+    std::fs::create_dir_all(&config).unwrap();
+    std::fs::write(
+        config.join("config.toml"),
+        "[policy]\nnever_submit = true\nallowed_agents = [\"plan\"]\n",
+    )
+    .unwrap();
+    let (port, published) = publish_server(ROUTES);
+    let run = |args: &[&str]| opencode_helix(port, &state).args(args).output().unwrap();
+
+    // Appended, with a notice, but never submitted
+    let output = run(&["prompt", "@plan fix it"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert!(stderr.contains("never_submit"), "{}", stderr);
+    let events: String = published.try_iter().collect();
+    assert!(events.contains("@plan fix it"), "{}", events);
+    assert!(!events.contains("prompt.submit"), "{}", events);
+
+    // Nothing to wait for once appended
+    let output = run(&["--wait", "prompt", "@plan fix it"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert!(stderr.contains("never_submit"), "{}", stderr);
+    let events: String = published.try_iter().collect();
+    assert!(!events.contains("prompt.submit"), "{}", events);
+
+    // Other agents aren't sent to, by flag or mention
+    for args in [
+        &["--agent", "build", "prompt", "fix it"][..],
+        &["prompt", "@build fix it"],
+    ] {
+        let output = run(args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(5), "{}", stderr);
+        assert!(stderr.contains("agent \"build\""), "{}", stderr);
+    }
+    let _ = std::fs::remove_dir_all(&state);
+    assert_eq!(published.try_iter().count(), 0);
+}
@@ -0,0 +1,81 @@
+//! `prompt --print` waits for the reply and prints it to stdout, and
+//! `--max-wait` gives up on a session that stays busy
+
+mod common;
+
+use std::io::Write;
+use std::sync::{mpsc, Mutex};
+use std::time::Duration;
+
+use common::{event_stream, opencode_helix, respond, serve, state_dir};
+
+/// A server whose event stream answers a submitted prompt with `events`
+/// (one JSON object each), or with nothing at all if there are none
+fn replying_server(events: &'static [&'static str]) -> u16 {
+    let (submitted, on_submit) = mpsc::channel::<()>();
+    let on_submit = Mutex::new(Some(on_submit));
+    serve(move |path, body, mut stream| match path {
+        "/path" => respond(&mut stream, "200 OK", r#"{"directory":"/tmp"}"#),
+        "/event" => {
+            let on_submit = on_submit.lock().unwrap().take().unwrap();
+            event_stream(&mut stream);
+            let _ = on_submit.recv();
+            for event in events {
+                let _ = write!(stream, "data: {}\n\n", event);
+                let _ = stream.flush();
+            }
+            std::thread::sleep(Duration::from_secs(600));
+        }
+        "/tui/publish" => {
+            respond(&mut stream, "200 OK", "true");
+            if body.contains("prompt.submit") {
+                let _ = submitted.send(());
+            }
+        }
+        _ => respond(&mut stream, "404 Not Found", ""),
+    })
+}
+
+const REPLY: &[&str] = &[
+    r#"{"type":"message.updated","properties":{"info":{"id":"msg_1","sessionID":"ses_a","role":"assistant"}}}"#,
+    r#"{"type":"message.part.updated","properties":{"part":{"id":"prt_1","messageID":"msg_1","type":"text","text":"Here you go:\n\n```text\nfix: parser\n```\n"}}}"#,
+    r#"{"type":"session.idle","properties":{"sessionID":"ses_a"}}"#,
+];
+
+#[test]
+fn test_print_reply() {
+    let state = state_dir();
+    let output = opencode_helix(replying_server(REPLY), &state)
+        .args(["prompt", "--print", "write a commit message"])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "Here you go:\n\n```text\nfix: parser\n```\n"
+    );
+
+    let output = opencode_helix(replying_server(REPLY), &state)
+        .args(["prompt", "--print", "--first-code-block", "write one"])
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_dir_all(&state);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "fix: parser\n");
+}
+
+#[test]
+fn test_max_wait_gives_up() {
+    let state = state_dir();
+    let output = opencode_helix(replying_server(&[]), &state)
+        .args(["prompt", "--print", "--max-wait", "1", "hello"])
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_dir_all(&state);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(9), "{}", stderr);
+    assert!(stderr.contains("still busy after 1s"), "{}", stderr);
+    assert!(output.stdout.is_empty());
+}
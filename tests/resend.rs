@@ -0,0 +1,48 @@
+//! `resend` sends the last prompt from the same project again
+
+mod common;
+
+use common::{mock_server, opencode_helix, state_dir};
+
+#[test]
+fn test_resend_last_prompt() {
+    let port = mock_server(&[
+        ("/path", "200 OK", r#"{"directory":"/tmp"}"#),
+        ("/tui/publish", "200 OK", "true"),
+    ]);
+    let state = state_dir();
+    let project = state.join("project");
+    std::fs::create_dir_all(&project).unwrap();
+    let run = |args: &[&str]| {
+        opencode_helix(port, &state)
+            .arg("--cwd")
+            .arg(&project)
+            .args(["--output", "json"])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    // Nothing sent yet: a usage error that says so
+    let output = run(&["resend"]);
+    assert_eq!(output.status.code(), Some(5));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Nothing has been sent"));
+
+    assert_eq!(run(&["prompt", "Review @this"]).status.code(), Some(0));
+    let output = run(&["resend"]);
+    assert_eq!(output.status.code(), Some(0));
+    let record: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(record["input"], "Review @this");
+
+    // Another project has its own history
+    let other = state.join("other");
+    std::fs::create_dir_all(&other).unwrap();
+    let output = opencode_helix(port, &state)
+        .arg("--cwd")
+        .arg(&other)
+        .arg("resend")
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_dir_all(&state);
+    assert_eq!(output.status.code(), Some(5));
+}
@@ -0,0 +1,41 @@
+//! `--selection-file -` reads the selection from stdin, so a keybinding can
+//! pipe it in without a temp file
+
+mod common;
+
+use std::io::Write;
+use std::process::Stdio;
+
+use common::{opencode_helix, publish_server, state_dir};
+
+#[test]
+fn test_selection_from_stdin() {
+    let state = state_dir();
+    // A file named `-` is not what is meant, and is left alone
+    std::fs::write(state.join("-"), "not the selection").unwrap();
+    let (port, published) = publish_server(&[("/path", "200 OK", r#"{"directory":"/tmp"}"#)]);
+    let mut child = opencode_helix(port, &state)
+        .current_dir(&state)
+        .args(["--selection-file", "-", "--selection-start", "3"])
+        .args(["--selection-end", "3", "-f", "src/lib.rs"])
+        .args(["prompt", "look: @selection"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"fn piped() {}\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    let events: String = published.try_iter().collect();
+    assert!(events.contains("fn piped() {}"), "{}", events);
+    assert!(!events.contains("not the selection"), "{}", events);
+    assert!(state.join("-").exists());
+    let _ = std::fs::remove_dir_all(&state);
+}
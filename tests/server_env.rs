@@ -0,0 +1,61 @@
+//! `$OPENCODE_PORT` is tried before scanning processes, and
+//! `status --print-server-env` prints it for nested invocations
+
+mod common;
+
+use common::{dead_port, discovering, mock_server, opencode_helix, state_dir};
+
+#[test]
+fn test_env_port_is_used_and_printed() {
+    let port = mock_server(&[("/path", "200 OK", r#"{"directory":"/tmp/it's"}"#)]);
+    let state = state_dir();
+    let output = discovering(&state)
+        .env("OPENCODE_PORT", port.to_string())
+        .args(["status", "--print-server-env"])
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_dir_all(&state);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!(
+            "export OPENCODE_PORT={}\nexport OPENCODE_CWD='/tmp/it'\\''s'\n",
+            port
+        )
+    );
+}
+
+#[test]
+fn test_dead_env_port_falls_back() {
+    let dead = dead_port();
+    let state = state_dir();
+    let cwd = state.join("project");
+    std::fs::create_dir_all(&cwd).unwrap();
+
+    // Discovery goes on to scan processes, so however that ends, it isn't
+    // with the port from the environment
+    let output = discovering(&state)
+        .env("OPENCODE_PORT", dead.to_string())
+        .arg("--cwd")
+        .arg(&cwd)
+        .args(["status", "--print-server-env"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stdout.contains(&dead.to_string()), "{}", stdout);
+    assert!(!stderr.contains(&dead.to_string()), "{}", stderr);
+
+    // --port comes first
+    let port = mock_server(&[("/path", "200 OK", r#"{"directory":"/tmp"}"#)]);
+    let output = opencode_helix(port, &state)
+        .env("OPENCODE_PORT", dead.to_string())
+        .args(["status", "--print-server-env"])
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_dir_all(&state);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).contains(&format!("OPENCODE_PORT={}", port)));
+}
@@ -0,0 +1,72 @@
+//! The first prompt sent to an untitled `--session` names it
+
+mod common;
+
+use std::sync::mpsc;
+
+use common::{opencode_helix, respond, serve, state_dir};
+
+/// A server with the untitled session `ses_new` and the titled `ses_old`,
+/// answering a rename with `rename_status`; returns its port and the bodies
+/// of the renames
+fn session_server(rename_status: &'static str) -> (u16, mpsc::Receiver<(String, String)>) {
+    let (send, renames) = mpsc::channel();
+    let port = serve(move |path, body, mut stream| {
+        let (status, reply) = match path {
+            "/path" => ("200 OK", r#"{"directory":"/tmp"}"#),
+            "/session" => (
+                "200 OK",
+                r#"[{"id":"ses_new","title":"New session - 2026-01-01T00:00:00.000Z"},
+                    {"id":"ses_old","title":"Refactor the parser"}]"#,
+            ),
+            "/session/ses_new/prompt_async" | "/session/ses_old/prompt_async" => {
+                ("204 No Content", "")
+            }
+            "/session/ses_new" | "/session/ses_old" => {
+                let _ = send.send((path.to_string(), body.to_string()));
+                (rename_status, "true")
+            }
+            _ => ("404 Not Found", ""),
+        };
+        respond(&mut stream, status, reply);
+    });
+    (port, renames)
+}
+
+#[test]
+fn test_auto_title() {
+    let state = state_dir();
+    let (port, renames) = session_server("200 OK");
+    let run = |args: &[&str]| opencode_helix(port, &state).args(args).output().unwrap();
+
+    let output = run(&["--session", "ses_new", "prompt", "Explain @this in detail"]);
+    assert_eq!(output.status.code(), Some(0));
+    let (path, body) = renames.try_recv().unwrap();
+    assert_eq!(path, "/session/ses_new");
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["title"], "Explain in detail");
+
+    // A titled session keeps its title, unless --title says otherwise
+    run(&["--session", "ses_old", "prompt", "Explain this"]);
+    run(&["--session", "ses_new", "--no-auto-title", "prompt", "hi"]);
+    assert!(renames.try_recv().is_err());
+    run(&["--session", "ses_old", "--title", "Parser", "prompt", "hi"]);
+    let (_, body) = renames.try_recv().unwrap();
+    assert!(body.contains(r#""title":"Parser""#), "{}", body);
+
+    // A rejected title doesn't fail the send
+    let (port, renames) = session_server("500 Internal Server Error");
+    let output = opencode_helix(port, &state)
+        .args(["--session", "ses_new", "prompt", "Explain this"])
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_dir_all(&state);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert!(renames.try_recv().is_ok());
+    assert!(
+        stderr.contains("Warning: session not renamed"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,49 @@
+//! `stats = true` records each invocation locally and `stats` summarizes them
+
+mod common;
+
+use common::{mock_server, opencode_helix, state_dir};
+
+#[test]
+fn test_stats_recorded_and_cleared() {
+    let port = mock_server(&[
+        ("/path", "200 OK", r#"{"directory":"/tmp"}"#),
+        ("/tui/publish", "200 OK", "true"),
+    ]);
+    let state = state_dir();
+    let run = |args: &[&str]| opencode_helix(port, &state).args(args).output().unwrap();
+
+    // Nothing is recorded until enabled
+    assert_eq!(run(&["prompt", "explain"]).status.code(), Some(0));
+    let output = run(&["stats"]);
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("No stats recorded yet"));
+
+    let config = state.join("opencode-helix");
+    std::fs::create_dir_all(&config).unwrap();
+    std::fs::write(config.join("config.toml"), "stats = true\n").unwrap();
+    assert_eq!(run(&["prompt", "explain"]).status.code(), Some(0));
+    assert_eq!(run(&["prompt", "just text"]).status.code(), Some(0));
+    assert_eq!(run(&["prompt", "--name", "nope"]).status.code(), Some(5));
+
+    let output = run(&["stats"]);
+    assert_eq!(output.status.code(), Some(0));
+    let report = String::from_utf8_lossy(&output.stdout).to_string();
+    assert!(report.contains("3 runs since"), "{}", report);
+    assert!(
+        report.contains("66% succeeded (1 failed, 0 cancelled)"),
+        "{}",
+        report
+    );
+    assert!(report.contains("  explain  1"), "{}", report);
+
+    let line = std::fs::read_to_string(state.join("opencode-helix/stats.jsonl")).unwrap();
+    let record: serde_json::Value = serde_json::from_str(line.lines().next().unwrap()).unwrap();
+    assert_eq!(record["command"], "prompt");
+    assert_eq!(record["status"], "ok");
+    assert!(record["expanded"].as_u64().unwrap() > 0);
+
+    assert_eq!(run(&["stats", "--clear"]).status.code(), Some(0));
+    let output = run(&["stats"]);
+    let _ = std::fs::remove_dir_all(&state);
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("No stats recorded yet"));
+}
@@ -0,0 +1,52 @@
+//! `--strict-placeholders` (or `strict_placeholders` in the config) fails a
+//! `prompt` with placeholders left as typed instead of sending it
+
+mod common;
+
+use common::{opencode_helix, publish_server, state_dir};
+
+#[test]
+fn test_strict_placeholders() {
+    let state = state_dir();
+    let config = state.join("config.toml");
+    std::fs::write(&config, "").unwrap();
+    let (port, published) = publish_server(&[("/path", "200 OK", r#"{"directory":"/tmp"}"#)]);
+    let run = |args: &[&str], prompt: &str| {
+        let output = opencode_helix(port, &state)
+            .args(["--config", config.to_str().unwrap(), "-f", "src/lib.rs"])
+            .args(args)
+            .args(["prompt", prompt])
+            .output()
+            .unwrap();
+        let events: String = published.try_iter().collect();
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        (output.status.code(), stderr, events)
+    };
+
+    // Expanded and unknown words are fine
+    let (code, stderr, events) = run(&["--strict-placeholders"], "fix @this, not @buffr");
+    assert_eq!(code, Some(0), "{}", stderr);
+    assert!(events.contains("fix @src/lib.rs, not @buffr"), "{}", events);
+
+    // One without a value fails, naming it and why, and nothing is sent
+    let prompt = "fix @this in @selection, @reg:x and @buffr";
+    let (code, stderr, events) = run(&["--strict-placeholders"], prompt);
+    assert_eq!(code, Some(5), "{}", stderr);
+    assert!(
+        stderr.contains("@selection (no selection given), @reg:x (register not given)"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("@buffr"), "{}", stderr);
+    assert_eq!(events, "");
+
+    // Sent as typed without the flag, and refused with the config's default
+    let (code, _, events) = run(&[], prompt);
+    assert_eq!(code, Some(0));
+    assert!(events.contains("@selection"), "{}", events);
+    std::fs::write(&config, "strict_placeholders = true\n").unwrap();
+    let (code, stderr, events) = run(&[], prompt);
+    assert_eq!(code, Some(5), "{}", stderr);
+    assert_eq!(events, "");
+    let _ = std::fs::remove_dir_all(&state);
+}
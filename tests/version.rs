@@ -0,0 +1,48 @@
+//! `--version --json` prints the build details, and `status` warns about a
+//! server outside the opencode versions known to work
+
+mod common;
+
+use common::{discovering, mock_server, opencode_helix, state_dir};
+
+#[test]
+fn test_version_json() {
+    let state = state_dir();
+    let output = discovering(&state)
+        .args(["--version", "--json"])
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_dir_all(&state);
+
+    assert_eq!(output.status.code(), Some(0));
+    let info: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+    assert!(info["build_date"].is_string(), "{}", info);
+    assert!(info["target"].is_string(), "{}", info);
+    assert!(info["features"].is_array(), "{}", info);
+    assert!(info.get("git_hash").is_some(), "{}", info);
+}
+
+#[test]
+fn test_status_warns_about_old_server() {
+    let port = mock_server(&[
+        ("/path", "200 OK", r#"{"directory":"/tmp"}"#),
+        ("/global/health", "200 OK", r#"{"version":"0.2.1"}"#),
+    ]);
+    let state = state_dir();
+    let output = opencode_helix(port, &state)
+        .args(["status", "--json"])
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_dir_all(&state);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr);
+    assert!(
+        stderr.contains("Warning: opencode 0.2.1 is older than 0.3.0"),
+        "{}",
+        stderr
+    );
+    let status: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(status["compat"], "too_old");
+}
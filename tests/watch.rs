@@ -0,0 +1,81 @@
+//! `watch` prints the session activity and reconnects when the stream drops
+
+mod common;
+
+use std::io::{BufRead, BufReader};
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use common::{mock_server, opencode_helix, state_dir};
+
+/// Events of two sessions, after which the stream closes
+const EVENTS: &str = concat!(
+    "data: {\"type\":\"server.connected\",\"properties\":{}}\n\n",
+    "data: {\"type\":\"message.updated\",\"properties\":{\"info\":",
+    "{\"id\":\"msg_1\",\"sessionID\":\"ses_a\",\"role\":\"assistant\",\"time\":{\"created\":1}}}}\n\n",
+    "data: {\"type\":\"message.part.updated\",\"properties\":{\"part\":{\"id\":\"prt_1\",",
+    "\"messageID\":\"msg_1\",\"sessionID\":\"ses_a\",\"type\":\"tool\",\"tool\":\"read\",",
+    "\"state\":{\"status\":\"running\",\"input\":{\"filePath\":\"src/lib.rs\"}}}}}\n\n",
+    "data: {\"type\":\"message.updated\",\"properties\":{\"info\":",
+    "{\"id\":\"msg_2\",\"sessionID\":\"ses_b\",\"role\":\"assistant\",\"time\":{\"created\":1}}}}\n\n",
+);
+
+const ROUTES: &[(&str, &str, &str)] = &[
+    ("/path", "200 OK", r#"{"directory":"/tmp"}"#),
+    ("/session", "200 OK", "[]"),
+    ("/event", "200 OK", EVENTS),
+];
+
+/// The first `count` lines `watch` prints with `args`, then stop it
+fn watch_lines(args: &[&str], count: usize) -> Vec<String> {
+    let port = mock_server(ROUTES);
+    let state = state_dir();
+    let mut child = opencode_helix(port, &state)
+        .env("NO_COLOR", "1")
+        .arg("watch")
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let (send, lines) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            let _ = send.send(line);
+        }
+    });
+    let got: Vec<String> = (0..count)
+        .map_while(|_| lines.recv_timeout(Duration::from_secs(15)).ok())
+        .collect();
+    let _ = child.kill();
+    let _ = child.wait();
+    let _ = std::fs::remove_dir_all(&state);
+    got
+}
+
+#[test]
+fn test_watch_log() {
+    assert_eq!(
+        watch_lines(&[], 3),
+        [
+            "ses_a ▶ message started",
+            "ses_a ⚙ read src/lib.rs",
+            "ses_b ▶ message started",
+        ]
+    );
+}
+
+#[test]
+fn test_watch_json_reconnects() {
+    // Two events of ses_a per connection; the second pair comes after the
+    // first stream closed
+    let lines = watch_lines(&["--json", "--session", "ses_a"], 4);
+    assert_eq!(lines.len(), 4, "{:?}", lines);
+    for line in &lines {
+        let event: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert!(!event.to_string().contains("ses_b"), "{}", line);
+    }
+    assert_eq!(lines[..2], lines[2..]);
+}
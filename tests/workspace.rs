@@ -0,0 +1,64 @@
+//! Without --cwd, the working directory is the project around --file
+
+mod common;
+
+use common::{discovering, state_dir};
+
+#[test]
+fn test_working_directory_from_file() {
+    let state = state_dir();
+    // Helix runs in `home`, which holds projects but is none itself
+    let home = state.join("home");
+    for dir in ["code/app/.git", "code/app/src", "code/app/vendor/lib/src"] {
+        std::fs::create_dir_all(home.join(dir)).unwrap();
+    }
+    std::fs::create_dir_all(home.join("scratch")).unwrap();
+    std::fs::write(home.join("code/app/vendor/lib/Cargo.toml"), "").unwrap();
+    let run = |args: &[&str]| {
+        let output = discovering(&state)
+            .current_dir(&home)
+            .arg("--debug")
+            .args(args)
+            .args(["context", "@this"])
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        assert_eq!(output.status.code(), Some(0), "{}", stderr);
+        let log = std::fs::read_to_string(state.join("opencode-helix/debug.log")).unwrap();
+        let cwd = log
+            .lines()
+            .find_map(|line| line.split_once("CWD: ").map(|(_, cwd)| cwd.to_string()))
+            .unwrap();
+        (String::from_utf8_lossy(&output.stdout).into_owned(), cwd)
+    };
+    let quoted = |dir: &str| format!("{:?}", home.join(dir));
+
+    // The repository, and the nearest marker within it
+    assert_eq!(
+        run(&["-f", "code/app/src/main.rs"]),
+        ("@src/main.rs\n".to_string(), quoted("code/app"))
+    );
+    assert_eq!(
+        run(&["-f", "code/app/vendor/lib/src/lib.rs"]),
+        ("@src/lib.rs\n".to_string(), quoted("code/app/vendor/lib"))
+    );
+    // An absolute path, and a file outside any project
+    let absolute = home.join("code/app/src/main.rs");
+    assert_eq!(run(&["-f", absolute.to_str().unwrap()]).0, "@src/main.rs\n");
+    if !state
+        .ancestors()
+        .any(|dir| dir.join(".git").exists() || dir.join("Cargo.toml").exists())
+    {
+        assert_eq!(
+            run(&["-f", "scratch/notes.txt"]),
+            ("@notes.txt\n".to_string(), quoted("scratch"))
+        );
+    }
+    // --cwd wins
+    let home_str = home.to_str().unwrap();
+    assert_eq!(
+        run(&["--cwd", home_str, "-f", "code/app/src/main.rs"]),
+        ("@code/app/src/main.rs\n".to_string(), format!("{:?}", home))
+    );
+    let _ = std::fs::remove_dir_all(&state);
+}